@@ -73,6 +73,10 @@ constnamedbitmap! {
 
         /// Realm.Allocate allows viewing, creating, modifying and deleting realms
         PRIV_REALM_ALLOCATE("Realm.Allocate");
+
+        /// Permissions.Delegate allows granting and removing the own privileges to other users
+        /// and API tokens, limited to the subtree the privilege is assigned on
+        PRIV_PERMISSIONS_DELEGATE("Permissions.Delegate");
     }
 }
 
@@ -112,6 +116,14 @@ pub const ROLE_DATASTORE_ADMIN: u64 = 0
     | PRIV_DATASTORE_BACKUP
     | PRIV_DATASTORE_PRUNE;
 
+#[rustfmt::skip]
+#[allow(clippy::identity_op)]
+/// Datastore.TenantAdmin can do anything on the datastore or namespace and delegate those
+/// privileges to other users below it.
+pub const ROLE_DATASTORE_TENANT_ADMIN: u64 = 0
+    | ROLE_DATASTORE_ADMIN
+    | PRIV_PERMISSIONS_DELEGATE;
+
 #[rustfmt::skip]
 #[allow(clippy::identity_op)]
 /// Datastore.Reader can read/verify datastore content and do restore
@@ -211,6 +223,8 @@ pub enum Role {
     NoAccess = ROLE_NO_ACCESS,
     /// Datastore Administrator
     DatastoreAdmin = ROLE_DATASTORE_ADMIN,
+    /// Datastore Tenant Administrator (datastore admin with permission delegation)
+    DatastoreTenantAdmin = ROLE_DATASTORE_TENANT_ADMIN,
     /// Datastore Reader (inspect datastore content and do restores)
     DatastoreReader = ROLE_DATASTORE_READER,
     /// Datastore Backup (backup and restore owned backups)
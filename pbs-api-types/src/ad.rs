@@ -3,8 +3,9 @@ use serde::{Deserialize, Serialize};
 use proxmox_schema::{api, Updater};
 
 use super::{
-    LdapMode, LDAP_DOMAIN_SCHEMA, REALM_ID_SCHEMA, SINGLE_LINE_COMMENT_SCHEMA,
-    SYNC_ATTRIBUTES_SCHEMA, SYNC_DEFAULTS_STRING_SCHEMA, USER_CLASSES_SCHEMA,
+    LdapMode, LoginNotify, LDAP_DOMAIN_SCHEMA, NS_PROVISION_STRING_SCHEMA, REALM_ID_SCHEMA,
+    SINGLE_LINE_COMMENT_SCHEMA, SYNC_ATTRIBUTES_SCHEMA, SYNC_DEFAULTS_STRING_SCHEMA,
+    USER_CLASSES_SCHEMA,
 };
 
 #[api(
@@ -39,7 +40,11 @@ use super::{
         "bind-dn" : {
             schema: LDAP_DOMAIN_SCHEMA,
             optional: true,
-        }
+        },
+        "namespace-provision": {
+            schema: NS_PROVISION_STRING_SCHEMA,
+            optional: true,
+        },
     },
 )]
 #[derive(Serialize, Deserialize, Updater, Clone)]
@@ -95,4 +100,10 @@ pub struct AdRealmConfig {
     /// User ``objectClass`` classes to sync
     #[serde(skip_serializing_if = "Option::is_none")]
     pub user_classes: Option<String>,
+    /// Notify about logins from previously unseen addresses
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub login_notify: Option<LoginNotify>,
+    /// Namespace created for users of the realm on their first login
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace_provision: Option<String>,
 }
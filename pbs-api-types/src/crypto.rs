@@ -18,6 +18,8 @@ pub enum CryptMode {
     SignOnly,
 }
 
+serde_plain::derive_display_from_serialize!(CryptMode);
+
 #[derive(Debug, Eq, PartialEq, Hash, Clone, Deserialize, Serialize)]
 #[serde(transparent)]
 /// 32-byte fingerprint, usually calculated with SHA256.
@@ -3,6 +3,7 @@ use std::path::PathBuf;
 
 use anyhow::{bail, format_err, Error};
 use const_format::concatcp;
+use hex::FromHex;
 use serde::{Deserialize, Serialize};
 
 use proxmox_schema::{
@@ -12,10 +13,11 @@ use proxmox_schema::{
 
 use crate::{
     Authid, CryptMode, Fingerprint, GroupFilter, MaintenanceMode, MaintenanceType, Userid,
-    BACKUP_ID_RE, BACKUP_NS_RE, BACKUP_TIME_RE, BACKUP_TYPE_RE, DATASTORE_NOTIFY_STRING_SCHEMA,
-    GC_SCHEDULE_SCHEMA, GROUP_OR_SNAPSHOT_PATH_REGEX_STR, PROXMOX_SAFE_ID_FORMAT,
-    PROXMOX_SAFE_ID_REGEX_STR, PRUNE_SCHEDULE_SCHEMA, SHA256_HEX_REGEX, SINGLE_LINE_COMMENT_SCHEMA,
-    SNAPSHOT_PATH_REGEX_STR, UPID,
+    ARCHIVE_SCHEDULE_SCHEMA, BACKUP_ID_RE, BACKUP_NS_RE, BACKUP_TIME_RE, BACKUP_TYPE_RE,
+    DAILY_DURATION_FORMAT, DATASTORE_NOTIFY_STRING_SCHEMA, GC_SCHEDULE_SCHEMA,
+    GROUP_OR_SNAPSHOT_PATH_REGEX_STR, JOB_ID_SCHEMA, PROXMOX_SAFE_ID_FORMAT,
+    PROXMOX_SAFE_ID_REGEX_STR, PRUNE_SCHEDULE_SCHEMA, REMOTE_ID_SCHEMA, SHA256_HEX_REGEX,
+    SINGLE_LINE_COMMENT_FORMAT, SINGLE_LINE_COMMENT_SCHEMA, SNAPSHOT_PATH_REGEX_STR, UPID,
 };
 
 const_regex! {
@@ -203,12 +205,47 @@ pub enum DatastoreFSyncLevel {
     Filesystem,
 }
 
+/// Default maximum size of uploaded chunks, in MiB.
+pub const DEFAULT_MAX_CHUNK_SIZE: u64 = 16;
+/// Upper bound for the configurable maximum size of uploaded chunks, in MiB.
+pub const MAX_CHUNK_SIZE_LIMIT: u64 = 64;
+/// Default maximum size of uploaded blobs and manifests, in MiB.
+pub const DEFAULT_MAX_BLOB_SIZE: u64 = 16;
+/// Upper bound for the configurable maximum size of uploaded blobs and manifests, in MiB.
+pub const MAX_BLOB_SIZE_LIMIT: u64 = 128;
+
 #[api(
     properties: {
         "chunk-order": {
             type: ChunkOrder,
             optional: true,
         },
+        "max-chunk-size": {
+            type: Integer,
+            optional: true,
+            minimum: 1,
+            maximum: MAX_CHUNK_SIZE_LIMIT as isize,
+            default: DEFAULT_MAX_CHUNK_SIZE as isize,
+        },
+        "max-blob-size": {
+            type: Integer,
+            optional: true,
+            minimum: 1,
+            maximum: MAX_BLOB_SIZE_LIMIT as isize,
+            default: DEFAULT_MAX_BLOB_SIZE as isize,
+        },
+        "max-manifest-size": {
+            type: Integer,
+            optional: true,
+            minimum: 1,
+            maximum: MAX_BLOB_SIZE_LIMIT as isize,
+            default: DEFAULT_MAX_BLOB_SIZE as isize,
+        },
+        "blob-dedup-min-size": {
+            type: Integer,
+            optional: true,
+            minimum: 0,
+        },
     },
 )]
 #[derive(Serialize, Deserialize, Default)]
@@ -220,6 +257,18 @@ pub struct DatastoreTuning {
     pub chunk_order: Option<ChunkOrder>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub sync_level: Option<DatastoreFSyncLevel>,
+    /// Maximum size of uploaded chunks, in MiB
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_chunk_size: Option<u64>,
+    /// Maximum size of uploaded blobs, in MiB
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_blob_size: Option<u64>,
+    /// Maximum size of uploaded manifests, in MiB
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_manifest_size: Option<u64>,
+    /// Store uploaded blobs of at least this size (in bytes) deduplicated (default: never)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blob_dedup_min_size: Option<u64>,
 }
 
 pub const DATASTORE_TUNING_STRING_SCHEMA: Schema = StringSchema::new("Datastore tuning options")
@@ -228,6 +277,210 @@ pub const DATASTORE_TUNING_STRING_SCHEMA: Schema = StringSchema::new("Datastore
     ))
     .schema();
 
+pub const GROUP_TEMPLATE_NAME_SCHEMA: Schema = StringSchema::new("Backup group template name.")
+    .format(&PROXMOX_SAFE_ID_FORMAT)
+    .min_length(2)
+    .max_length(32)
+    .schema();
+
+pub const GROUP_TEMPLATE_TAGS_SCHEMA: Schema =
+    StringSchema::new("Tags of the backup group, separated by ';'.")
+        .format(&SINGLE_LINE_COMMENT_FORMAT)
+        .schema();
+
+#[api(
+    properties: {
+        name: {
+            schema: GROUP_TEMPLATE_NAME_SCHEMA,
+        },
+        owner: {
+            type: Authid,
+            optional: true,
+        },
+        notes: {
+            optional: true,
+            schema: SINGLE_LINE_COMMENT_SCHEMA,
+        },
+        tags: {
+            optional: true,
+            schema: GROUP_TEMPLATE_TAGS_SCHEMA,
+        },
+        "keep-last": {
+            schema: PRUNE_SCHEMA_KEEP_LAST,
+            optional: true,
+        },
+        "keep-hourly": {
+            schema: PRUNE_SCHEMA_KEEP_HOURLY,
+            optional: true,
+        },
+        "keep-daily": {
+            schema: PRUNE_SCHEMA_KEEP_DAILY,
+            optional: true,
+        },
+        "keep-weekly": {
+            schema: PRUNE_SCHEMA_KEEP_WEEKLY,
+            optional: true,
+        },
+        "keep-monthly": {
+            schema: PRUNE_SCHEMA_KEEP_MONTHLY,
+            optional: true,
+        },
+        "keep-yearly": {
+            schema: PRUNE_SCHEMA_KEEP_YEARLY,
+            optional: true,
+        },
+    },
+)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Settings applied to backup groups created from a template before their first backup. The
+/// keep options are only a hint for external tools, they are not used for pruning.
+pub struct GroupTemplate {
+    pub name: String,
+    /// Owner of the created groups, defaults to the user creating them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<Authid>,
+    /// Notes of the created groups
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_last: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_hourly: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_daily: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_weekly: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_monthly: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_yearly: Option<u64>,
+}
+
+pub const GROUP_TEMPLATE_STRING_SCHEMA: Schema = StringSchema::new("Backup group template.")
+    .format(&ApiStringFormat::PropertyString(&GroupTemplate::API_SCHEMA))
+    .schema();
+
+pub const GROUP_TEMPLATE_LIST_SCHEMA: Schema = ArraySchema::new(
+    "List of backup group templates.",
+    &GROUP_TEMPLATE_STRING_SCHEMA,
+)
+.schema();
+
+pub const DATASTORE_WINDOW_SCHEMA: Schema =
+    StringSchema::new("Timeframe in which the datastore accepts this kind of access.")
+        .format(&DAILY_DURATION_FORMAT)
+        .type_text("<daily-duration>")
+        .schema();
+
+pub const DATASTORE_WINDOW_LIST_SCHEMA: Schema = ArraySchema::new(
+    "List of timeframes in which the datastore accepts this kind of access.",
+    &DATASTORE_WINDOW_SCHEMA,
+)
+.schema();
+
+pub const DATASTORE_MAX_SESSIONS_SCHEMA: Schema =
+    IntegerSchema::new("Maximum number of concurrent sessions on the datastore.")
+        .minimum(1)
+        .schema();
+
+pub const DATASTORE_RESTORE_PRIORITY_RATIO_SCHEMA: Schema = IntegerSchema::new(
+    "While restores are running, limit backup uploads to this percentage of the restore rate.",
+)
+.minimum(1)
+.maximum(100)
+.schema();
+
+pub const DATASTORE_SPACE_WATERMARK_SCHEMA: Schema =
+    IntegerSchema::new("Percentage of used space on the datastore's file system.")
+        .minimum(1)
+        .maximum(100)
+        .schema();
+
+pub const DATASTORE_MIN_RETENTION_DAYS_SCHEMA: Schema =
+    IntegerSchema::new("New snapshots cannot be removed until they are this many days old.")
+        .minimum(1)
+        .schema();
+
+pub const DATASTORE_COLD_TIER_PATH_SCHEMA: Schema =
+    StringSchema::new("Directory on slower storage rarely used chunks are offloaded to.")
+        .min_length(1)
+        .max_length(4096)
+        .schema();
+
+pub const DATASTORE_COLD_TIER_AFTER_SCHEMA: Schema = IntegerSchema::new(
+    "Offload chunks only referenced by snapshots older than this number of days.",
+)
+.minimum(1)
+.default(30)
+.schema();
+
+pub const DATASTORE_READ_THROUGH_CACHE_SIZE_SCHEMA: Schema = IntegerSchema::new(
+    "Size of the snapshots cached from the read-through remote, in GiB. The least recently read \
+    snapshots are removed first when it is exceeded.",
+)
+.minimum(1)
+.default(100)
+.schema();
+
+pub const DATASTORE_DIGEST_SALT_SCHEMA: Schema = StringSchema::new(
+    "Secret salt mixed into the digests of unencrypted chunks (64 hex digits). Can only be set \
+    on creation, datastores sharing chunks through sync or tape need the same salt.",
+)
+.format(&CHUNK_DIGEST_FORMAT)
+.schema();
+
+pub const DATASTORE_ARCHIVE_AFTER_SCHEMA: Schema =
+    IntegerSchema::new("Move snapshots older than this number of days to the archive datastore.")
+        .minimum(1)
+        .schema();
+
+#[api]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// What to do with client sessions started outside of their allowed window.
+pub enum DatastoreWindowAction {
+    /// Refuse the session, telling the client when the window opens again.
+    #[default]
+    Refuse,
+    /// Keep the connection open and start the session once the window opens.
+    Queue,
+}
+
+#[api]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// Priority of a backup session, requested by the client.
+pub enum BackupPriority {
+    /// Bulk job, keeps a session slot free for other backups and waits longer when busy.
+    Low,
+    /// Regular backup.
+    #[default]
+    Normal,
+    /// Interactive backup, may use a reserved session slot and is not slowed down by restores.
+    /// Requires the Datastore.Modify privilege.
+    High,
+}
+serde_plain::derive_display_from_serialize!(BackupPriority);
+
+#[api]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// Which kind of archives new backups may contain.
+pub enum DatastoreCryptPolicy {
+    /// Accept encrypted, signed and plain archives.
+    #[default]
+    Any,
+    /// Only accept client-side encrypted archives.
+    Encrypted,
+    /// Only accept archives the server can read, signed or plain.
+    Unencrypted,
+}
+
+serde_plain::derive_display_from_serialize!(DatastoreCryptPolicy);
+
 #[api(
     properties: {
         name: {
@@ -273,6 +526,109 @@ pub const DATASTORE_TUNING_STRING_SCHEMA: Schema = StringSchema::new("Datastore
             format: &ApiStringFormat::PropertyString(&MaintenanceMode::API_SCHEMA),
             type: String,
         },
+        "backup-window": {
+            optional: true,
+            schema: DATASTORE_WINDOW_LIST_SCHEMA,
+        },
+        "restore-window": {
+            optional: true,
+            schema: DATASTORE_WINDOW_LIST_SCHEMA,
+        },
+        "maintenance-window": {
+            optional: true,
+            schema: DATASTORE_WINDOW_LIST_SCHEMA,
+        },
+        "backup-window-action": {
+            optional: true,
+            type: DatastoreWindowAction,
+        },
+        "restore-window-action": {
+            optional: true,
+            type: DatastoreWindowAction,
+        },
+        "max-backup-sessions": {
+            optional: true,
+            schema: DATASTORE_MAX_SESSIONS_SCHEMA,
+        },
+        "max-reader-sessions": {
+            optional: true,
+            schema: DATASTORE_MAX_SESSIONS_SCHEMA,
+        },
+        "archive-store": {
+            optional: true,
+            schema: DATASTORE_SCHEMA,
+        },
+        "archive-after": {
+            optional: true,
+            schema: DATASTORE_ARCHIVE_AFTER_SCHEMA,
+        },
+        "archive-schedule": {
+            optional: true,
+            schema: ARCHIVE_SCHEDULE_SCHEMA,
+        },
+        "cold-tier-path": {
+            optional: true,
+            schema: DATASTORE_COLD_TIER_PATH_SCHEMA,
+        },
+        "cold-tier-after": {
+            optional: true,
+            schema: DATASTORE_COLD_TIER_AFTER_SCHEMA,
+        },
+        "restore-priority-ratio": {
+            optional: true,
+            schema: DATASTORE_RESTORE_PRIORITY_RATIO_SCHEMA,
+        },
+        "low-space-watermark": {
+            optional: true,
+            schema: DATASTORE_SPACE_WATERMARK_SCHEMA,
+        },
+        "critical-space-watermark": {
+            optional: true,
+            schema: DATASTORE_SPACE_WATERMARK_SCHEMA,
+        },
+        "critical-space-deny-backup": {
+            optional: true,
+            type: bool,
+            default: false,
+        },
+        "crypt-policy": {
+            optional: true,
+            type: DatastoreCryptPolicy,
+        },
+        "min-retention-days": {
+            optional: true,
+            schema: DATASTORE_MIN_RETENTION_DAYS_SCHEMA,
+        },
+        "protect-new-backups": {
+            optional: true,
+            type: bool,
+            default: false,
+        },
+        "group-template": {
+            optional: true,
+            schema: GROUP_TEMPLATE_LIST_SCHEMA,
+        },
+        "read-through-remote": {
+            optional: true,
+            schema: REMOTE_ID_SCHEMA,
+        },
+        "read-through-store": {
+            optional: true,
+            schema: DATASTORE_SCHEMA,
+        },
+        "read-through-cache-size": {
+            optional: true,
+            schema: DATASTORE_READ_THROUGH_CACHE_SIZE_SCHEMA,
+        },
+        "warm-up": {
+            optional: true,
+            type: bool,
+            default: false,
+        },
+        "digest-salt": {
+            optional: true,
+            schema: DATASTORE_DIGEST_SALT_SCHEMA,
+        },
     }
 )]
 #[derive(Serialize, Deserialize, Updater, Clone, PartialEq)]
@@ -320,6 +676,104 @@ pub struct DataStoreConfig {
     /// Maintenance mode, type is either 'offline' or 'read-only', message should be enclosed in "
     #[serde(skip_serializing_if = "Option::is_none")]
     pub maintenance_mode: Option<String>,
+
+    /// Timeframes in which new backup sessions are accepted (default: always)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backup_window: Option<Vec<String>>,
+
+    /// Timeframes in which new restore sessions are accepted (default: always)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub restore_window: Option<Vec<String>>,
+
+    /// Timeframes in which scheduled garbage collection, prune and verify jobs may start
+    /// (default: always)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub maintenance_window: Option<Vec<String>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backup_window_action: Option<DatastoreWindowAction>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub restore_window_action: Option<DatastoreWindowAction>,
+
+    /// Maximum number of concurrent backup sessions, further clients are asked to retry later
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_backup_sessions: Option<u64>,
+
+    /// Maximum number of concurrent reader sessions, further clients are asked to retry later
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_reader_sessions: Option<u64>,
+
+    /// Datastore old snapshots are moved to, leaving a stub behind
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive_store: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive_after: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive_schedule: Option<String>,
+
+    /// Rarely used chunks are moved there by garbage collection, reads are served from it
+    /// transparently
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cold_tier_path: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cold_tier_after: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub restore_priority_ratio: Option<u64>,
+
+    /// Run the prune jobs of the datastore and garbage collection when usage reaches this level
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub low_space_watermark: Option<u64>,
+
+    /// Like the low watermark, but prune and garbage collection are run more often and
+    /// regardless of the maintenance window
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub critical_space_watermark: Option<u64>,
+
+    /// Refuse new backups while the usage is above the critical watermark
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub critical_space_deny_backup: Option<bool>,
+
+    /// Reject new backups containing archives not matching the policy (default: any)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crypt_policy: Option<DatastoreCryptPolicy>,
+
+    /// New snapshots cannot be removed until this many days after they were finished
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_retention_days: Option<u64>,
+
+    /// Mark every new snapshot as protected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protect_new_backups: Option<bool>,
+
+    /// Templates for pre-creating backup groups
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group_template: Option<Vec<String>>,
+
+    /// Remote to fetch snapshots not present locally from, when they are read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_through_remote: Option<String>,
+
+    /// Datastore on the read-through remote
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_through_store: Option<String>,
+
+    /// Size limit for the snapshots cached from the read-through remote, in GiB
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_through_cache_size: Option<u64>,
+
+    /// Preload the metadata of the datastore into the page cache when it comes online
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warm_up: Option<bool>,
+
+    /// Secret salt mixed into the digests of unencrypted chunks (hex), only set on creation.
+    #[updater(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub digest_salt: Option<String>,
 }
 
 #[api]
@@ -354,9 +808,74 @@ impl DataStoreConfig {
             notification_mode: None,
             tuning: None,
             maintenance_mode: None,
+            backup_window: None,
+            restore_window: None,
+            maintenance_window: None,
+            backup_window_action: None,
+            restore_window_action: None,
+            max_backup_sessions: None,
+            max_reader_sessions: None,
+            archive_store: None,
+            archive_after: None,
+            archive_schedule: None,
+            cold_tier_path: None,
+            cold_tier_after: None,
+            restore_priority_ratio: None,
+            low_space_watermark: None,
+            critical_space_watermark: None,
+            critical_space_deny_backup: None,
+            crypt_policy: None,
+            min_retention_days: None,
+            protect_new_backups: None,
+            group_template: None,
+            read_through_remote: None,
+            read_through_store: None,
+            read_through_cache_size: None,
+            warm_up: None,
+            digest_salt: None,
         }
     }
 
+    /// Returns the salt mixed into the digests of unencrypted chunks, if any.
+    pub fn digest_salt(&self) -> Result<Option<[u8; 32]>, Error> {
+        self.digest_salt
+            .as_deref()
+            .map(|salt| {
+                <[u8; 32]>::from_hex(salt).map_err(|err| {
+                    format_err!("invalid digest salt of datastore '{}' - {err}", self.name)
+                })
+            })
+            .transpose()
+    }
+
+    /// Returns the parsed backup group templates.
+    pub fn group_templates(&self) -> Result<Vec<GroupTemplate>, Error> {
+        self.group_template
+            .iter()
+            .flatten()
+            .map(|template| {
+                GroupTemplate::deserialize(proxmox_schema::de::SchemaDeserializer::new(
+                    template,
+                    &GroupTemplate::API_SCHEMA,
+                ))
+                .map_err(Error::from)
+            })
+            .collect()
+    }
+
+    /// Returns the backup group template called `name`.
+    pub fn lookup_group_template(&self, name: &str) -> Result<GroupTemplate, Error> {
+        self.group_templates()?
+            .into_iter()
+            .find(|template| template.name == name)
+            .ok_or_else(|| {
+                format_err!(
+                    "no backup group template '{name}' on datastore '{}'",
+                    self.name
+                )
+            })
+    }
+
     pub fn get_maintenance_mode(&self) -> Option<MaintenanceMode> {
         self.maintenance_mode.as_ref().and_then(|str| {
             MaintenanceMode::deserialize(proxmox_schema::de::SchemaDeserializer::new(
@@ -480,6 +999,175 @@ pub struct SnapshotVerifyState {
     pub state: VerifyState,
 }
 
+#[api(
+    properties: {
+        ns: {
+            type: BackupNamespace,
+            optional: true,
+        },
+        backup: {
+            type: BackupDir,
+        },
+        state: {
+            type: VerifyState,
+        },
+    },
+)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Verification state of a single snapshot, as contained in a verify report.
+pub struct VerifyReportEntry {
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub ns: Option<BackupNamespace>,
+    #[serde(flatten)]
+    pub backup: BackupDir,
+    /// Digest over the archives listed in the snapshot's manifest (hex).
+    pub content_digest: String,
+    pub state: VerifyState,
+    /// Start time of the last verification.
+    pub verified: i64,
+}
+
+#[api(
+    properties: {
+        store: {
+            schema: DATASTORE_SCHEMA,
+        },
+        snapshots: {
+            type: Array,
+            items: { type: VerifyReportEntry },
+        },
+    },
+)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Verification states of the snapshots of a datastore, signed by the reporting node.
+pub struct VerifyReport {
+    /// Name of the reporting node.
+    pub node: String,
+    pub store: String,
+    /// Generation time of the report.
+    pub generated: i64,
+    pub snapshots: Vec<VerifyReportEntry>,
+    /// Certificate of the reporting node (PEM).
+    pub certificate: String,
+    /// Signature over the canonical JSON of the report without this property (hex).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+#[api(
+    properties: {
+        store: {
+            schema: DATASTORE_SCHEMA,
+        },
+        job: {
+            schema: JOB_ID_SCHEMA,
+        },
+        upid: {
+            schema: UPID::API_SCHEMA,
+        },
+        snapshots: {
+            type: Array,
+            items: { type: VerifyReportEntry },
+        },
+    },
+)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Signed record of the snapshots verified by a single verification job run.
+pub struct VerifyAttestation {
+    /// Name of the node which ran the verification.
+    pub node: String,
+    pub store: String,
+    pub job: String,
+    /// Task which verified the snapshots.
+    pub upid: String,
+    /// Generation time of the attestation.
+    pub generated: i64,
+    pub snapshots: Vec<VerifyReportEntry>,
+    /// Certificate of the verifying node (PEM).
+    pub certificate: String,
+    /// Signature over the canonical JSON of the attestation without this property (hex).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+#[api()]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// Outcome of a single snapshot in a verification run.
+pub enum SnapshotVerifyOutcome {
+    /// All archives were verified successfully
+    Ok,
+    /// One or more archives failed verification
+    Failed,
+    /// The snapshot was not verified
+    Skipped,
+}
+
+#[api(
+    properties: {
+        outcome: {
+            type: SnapshotVerifyOutcome,
+        },
+        errors: {
+            type: Array,
+            items: {
+                type: String,
+                description: "Verification error.",
+            },
+        },
+    },
+)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Result of a single snapshot in a verification run.
+pub struct SnapshotVerifyResult {
+    /// Namespace and path of the snapshot.
+    pub snapshot: String,
+    pub outcome: SnapshotVerifyOutcome,
+    /// Why the snapshot was skipped or confirmed without reading it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+    /// Time spent on the snapshot, in seconds.
+    pub duration: f64,
+}
+
+#[api(
+    properties: {
+        store: {
+            schema: DATASTORE_SCHEMA,
+        },
+        job: {
+            schema: JOB_ID_SCHEMA,
+        },
+        upid: {
+            schema: UPID::API_SCHEMA,
+        },
+        snapshots: {
+            type: Array,
+            items: { type: SnapshotVerifyResult },
+        },
+    },
+)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Per-snapshot results of a verification job run.
+pub struct VerifyJobResult {
+    pub store: String,
+    pub job: String,
+    /// Task which verified the snapshots.
+    pub upid: String,
+    /// Start time of the run.
+    pub starttime: i64,
+    /// End time of the run.
+    pub endtime: i64,
+    pub snapshots: Vec<SnapshotVerifyResult>,
+}
+
 /// A namespace provides a logical separation between backup groups from different domains
 /// (cluster, sites, ...) where uniqueness cannot be guaranteed anymore. It allows users to share a
 /// datastore (i.e., one deduplication domain (chunk store)) with multiple (trusted) sites and
@@ -902,8 +1590,12 @@ impl BackupGroup {
     }
 
     pub fn matches(&self, filter: &crate::GroupFilter) -> bool {
+        self.matches_filter_type(&filter.filter_type)
+    }
+
+    pub fn matches_filter_type(&self, filter_type: &crate::FilterType) -> bool {
         use crate::FilterType;
-        match &filter.filter_type {
+        match filter_type {
             FilterType::Group(backup_group) => {
                 match backup_group.parse::<BackupGroup>() {
                     Ok(group) => *self == group,
@@ -1179,6 +1871,12 @@ pub struct SnapshotListItem {
     /// Protection from prunes
     #[serde(default)]
     pub protected: bool,
+    /// End of the minimum retention period, the snapshot cannot be removed before
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retained_until: Option<i64>,
+    /// Datastore the snapshot was moved to, only a stub is left on this datastore
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archived: Option<String>,
 }
 
 #[api(
@@ -1327,6 +2025,12 @@ pub struct GarbageCollectionStatus {
     pub removed_bad: usize,
     /// Number of chunks still marked as .bad after garbage collection.
     pub still_bad: usize,
+    /// Number of chunks offloaded to the cold tier.
+    #[serde(default)]
+    pub cold_chunks: usize,
+    /// Bytes used by chunks in the cold tier.
+    #[serde(default)]
+    pub cold_bytes: u64,
 }
 
 #[api(
@@ -1441,6 +2145,9 @@ pub struct DataStoreStatusListItem {
     /// Status of last GC
     #[serde(skip_serializing_if = "Option::is_none")]
     pub gc_status: Option<GarbageCollectionStatus>,
+    /// Projection of the usage, missing if not enough data points are available yet.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage_trend: Option<DataStoreUsageTrend>,
 }
 
 impl DataStoreStatusListItem {
@@ -1456,10 +2163,222 @@ impl DataStoreStatusListItem {
             estimated_full_date: None,
             error: err,
             gc_status: None,
+            usage_trend: None,
         }
     }
 }
 
+#[api]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Projection of the datastore usage.
+///
+/// Calculated via a simple Linear Regression (Least Squares) over the RRD usage data. The
+/// confidence interval is the 95% interval of the growth rate.
+pub struct DataStoreUsageTrend {
+    /// Current usage (between 0.0 and 1.0), according to the regression.
+    pub usage: f64,
+    /// Usage growth per day, negative if the usage is declining.
+    pub growth_per_day: f64,
+    /// Projected days until the storage is full. Missing if the usage is not growing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub days_until_full: Option<f64>,
+    /// Lower bound of the confidence interval of 'days-until-full'.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub days_until_full_min: Option<f64>,
+    /// Upper bound of the confidence interval of 'days-until-full'. Missing if the usage might
+    /// not be growing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub days_until_full_max: Option<f64>,
+    /// Number of data points the projection is based on.
+    pub data_points: u64,
+}
+
+#[api]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// A stored chunk cross-reference report.
+pub struct ChunkXrefReportInfo {
+    /// File name of the report.
+    pub name: String,
+    /// Time the report was generated (epoch).
+    pub time: i64,
+    /// Size of the compressed report in bytes.
+    pub size: u64,
+}
+
+#[api]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+/// Direction of a datastore seed transfer.
+pub enum SeedAction {
+    /// The seed was written from this datastore.
+    Export,
+    /// The seed was read into this datastore.
+    Import,
+}
+
+#[api(
+    properties: {
+        action: {
+            type: SeedAction,
+        },
+        "auth-id": {
+            type: Authid,
+        },
+    },
+)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// A recorded seed export or import of a datastore.
+pub struct SeedHistoryEntry {
+    pub action: SeedAction,
+    /// Identifier of the seed, the same for its export and import.
+    pub seed_id: String,
+    /// Time of the export or import (epoch).
+    pub time: i64,
+    /// Directory the seed was written to or read from.
+    pub path: String,
+    /// Datastore the seed was exported from.
+    pub source_store: String,
+    pub auth_id: Authid,
+    /// Number of snapshots exported or imported.
+    pub snapshots: u64,
+    /// Number of snapshots skipped on import, because they already existed.
+    pub skipped: u64,
+    /// Number of chunks in the seed.
+    pub chunks: u64,
+}
+
+#[api]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Expiring link for downloading a file or directory of a snapshot without logging in.
+pub struct RestoreLink {
+    /// Path and query of the download, relative to the address of the server.
+    pub url: String,
+    /// Expiry of the link (epoch).
+    pub expire: i64,
+    /// The link can only be used for a single download.
+    pub one_time: bool,
+}
+
+#[api]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+/// Type of an accounted restore transfer.
+pub enum RestoreAccountingKind {
+    /// A reader session of the backup reader protocol.
+    Reader,
+    /// A file downloaded via the datastore API.
+    Download,
+}
+
+#[api(
+    properties: {
+        "auth-id": {
+            type: Authid,
+        },
+        store: {
+            schema: DATASTORE_SCHEMA,
+        },
+        kind: {
+            type: RestoreAccountingKind,
+        },
+    },
+)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// An entry of the restore accounting log, written once a transfer finished.
+pub struct RestoreAccountingEntry {
+    /// Time the transfer finished (epoch).
+    pub time: i64,
+    pub auth_id: Authid,
+    pub store: String,
+    pub kind: RestoreAccountingKind,
+    /// The snapshot or file that was transferred.
+    pub name: String,
+    /// Bytes sent to the client.
+    pub bytes: u64,
+    /// Duration of the transfer in seconds.
+    pub duration: i64,
+}
+
+#[api(
+    properties: {
+        "auth-id": {
+            type: Authid,
+        },
+    },
+)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Restore traffic of one auth-id.
+pub struct RestoreAccountingSummary {
+    pub auth_id: Authid,
+    /// Total bytes sent to the auth-id.
+    pub bytes: u64,
+    /// Number of reader sessions.
+    pub reader_sessions: u64,
+    /// Number of file downloads.
+    pub downloads: u64,
+}
+
+pub const WARM_READER_TTL_SCHEMA: Schema =
+    IntegerSchema::new("Seconds a warm reader session is kept after its creation or last use.")
+        .minimum(10)
+        .maximum(86400)
+        .default(600)
+        .schema();
+
+#[api(
+    properties: {
+        store: {
+            schema: DATASTORE_SCHEMA,
+        },
+        ns: {
+            type: BackupNamespace,
+        },
+        backup: {
+            type: BackupDir,
+        },
+        "auth-id": {
+            type: Authid,
+        },
+        ttl: {
+            schema: WARM_READER_TTL_SCHEMA,
+        },
+    },
+)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// A warm standby reader session and its statistics.
+pub struct WarmReaderSessionInfo {
+    /// Identifier of the session.
+    pub id: String,
+    pub store: String,
+    pub ns: BackupNamespace,
+    #[serde(flatten)]
+    pub backup: BackupDir,
+    /// The auth-id that created the session.
+    pub auth_id: Authid,
+    /// Creation time (epoch).
+    pub ctime: i64,
+    /// Time the session expires, unless used again (epoch).
+    pub expire: i64,
+    pub ttl: i64,
+    /// Number of preloaded files.
+    pub files: u64,
+    /// Size of the preloaded files in bytes.
+    pub preloaded_bytes: u64,
+    /// Number of reader sessions that used it.
+    pub readers: u64,
+    /// Number of file downloads served from memory.
+    pub downloads: u64,
+    /// Bytes served from memory.
+    pub bytes: u64,
+}
+
 pub const ADMIN_DATASTORE_LIST_SNAPSHOTS_RETURN_TYPE: ReturnType = ReturnType {
     optional: false,
     schema: &ArraySchema::new(
@@ -1514,6 +2433,14 @@ pub const ADMIN_DATASTORE_PRUNE_RETURN_TYPE: ReturnType = ReturnType {
             schema: NS_MAX_DEPTH_SCHEMA,
             optional: true,
         },
+        "target-store": {
+            schema: DATASTORE_SCHEMA,
+            optional: true,
+        },
+        owner: {
+            type: Authid,
+            optional: true,
+        },
      },
 )]
 #[derive(Serialize, Deserialize)]
@@ -1530,6 +2457,12 @@ pub struct TapeRestoreNamespace {
     /// The (optional) recursion depth
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_depth: Option<usize>,
+    /// The target datastore of the source datastore, instead of the one of the store mapping
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_store: Option<String>,
+    /// The owner of the restored groups, instead of the owner of the restore
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<Authid>,
 }
 
 pub const TAPE_RESTORE_NAMESPACE_SCHEMA: Schema = StringSchema::new("A namespace mapping")
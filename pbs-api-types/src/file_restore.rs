@@ -12,6 +12,9 @@ pub struct RestoreDaemonStatus {
     /// time left until auto-shutdown, keep in mind that this is useless when 'keep-timeout' is
     /// not set, as then the status call will have reset the timer before returning the value
     pub timeout: i64,
+    /// time left until the maximum lifetime of the VM is reached, if limited
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lifetime: Option<i64>,
 }
 
 #[api]
@@ -0,0 +1,141 @@
+use std::str::FromStr;
+
+use anyhow::{bail, format_err};
+use serde::{Deserialize, Serialize};
+
+use proxmox_schema::*;
+
+use crate::{PROXMOX_SAFE_ID_FORMAT, SINGLE_LINE_COMMENT_SCHEMA};
+
+const_regex! {
+    /// Regex for a date in 'YYYY-MM-DD' notation.
+    pub SCHEDULE_EXCLUSION_DATE_REGEX = r"^\d{4}-\d{2}-\d{2}$";
+}
+
+pub const HOLIDAY_CALENDAR_ID_SCHEMA: Schema = StringSchema::new("Holiday calendar ID.")
+    .format(&PROXMOX_SAFE_ID_FORMAT)
+    .min_length(3)
+    .max_length(32)
+    .schema();
+
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// An exclusion of days from a job schedule.
+///
+/// Exclusions always cover whole days in local time.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScheduleExclusion {
+    /// All days excluded by the referenced holiday calendar ('@NAME').
+    Calendar(String),
+    /// Inclusive date range ('YYYY-MM-DD' or 'YYYY-MM-DD..YYYY-MM-DD').
+    Dates(String, String),
+    /// The last given weekday of every month ('last-fri'), with 0 being sunday.
+    LastWeekday(u8),
+    /// All days on which the calendar event triggers at least once.
+    Event(String),
+}
+
+fn verify_exclusion_date(date: &str) -> Result<(), anyhow::Error> {
+    if !SCHEDULE_EXCLUSION_DATE_REGEX.is_match(date) {
+        bail!("invalid date '{date}', expected 'YYYY-MM-DD'");
+    }
+    // round-trip to reject days beyond the end of the month, like '2023-02-29'
+    let valid = proxmox_time::parse_rfc3339(&format!("{date}T00:00:00Z"))
+        .and_then(proxmox_time::epoch_to_rfc3339_utc)
+        .map(|time| time.starts_with(date))
+        .unwrap_or(false);
+    if !valid {
+        bail!("invalid date '{date}'");
+    }
+    Ok(())
+}
+
+impl FromStr for ScheduleExclusion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(name) = s.strip_prefix('@') {
+            HOLIDAY_CALENDAR_ID_SCHEMA.parse_simple_value(name)?;
+            return Ok(ScheduleExclusion::Calendar(name.to_string()));
+        }
+
+        if let Some(weekday) = s.strip_prefix("last-") {
+            let weekday = weekday.to_lowercase();
+            let index = WEEKDAYS
+                .iter()
+                .position(|day| *day == weekday)
+                .ok_or_else(|| format_err!("invalid weekday '{weekday}'"))?;
+            return Ok(ScheduleExclusion::LastWeekday(index as u8));
+        }
+
+        let (from, to) = s.split_once("..").unwrap_or((s, s));
+        if SCHEDULE_EXCLUSION_DATE_REGEX.is_match(from) {
+            verify_exclusion_date(from)?;
+            verify_exclusion_date(to)?;
+            if from > to {
+                bail!("invalid date range '{s}', start is after end");
+            }
+            return Ok(ScheduleExclusion::Dates(from.to_string(), to.to_string()));
+        }
+
+        proxmox_time::verify_calendar_event(s)?;
+        Ok(ScheduleExclusion::Event(s.to_string()))
+    }
+}
+
+// used for serializing below, caution!
+impl std::fmt::Display for ScheduleExclusion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScheduleExclusion::Calendar(name) => write!(f, "@{name}"),
+            ScheduleExclusion::Dates(from, to) if from == to => f.write_str(from),
+            ScheduleExclusion::Dates(from, to) => write!(f, "{from}..{to}"),
+            ScheduleExclusion::LastWeekday(day) => write!(f, "last-{}", WEEKDAYS[*day as usize]),
+            ScheduleExclusion::Event(event) => f.write_str(event),
+        }
+    }
+}
+
+proxmox_serde::forward_deserialize_to_from_str!(ScheduleExclusion);
+proxmox_serde::forward_serialize_to_display!(ScheduleExclusion);
+
+fn verify_schedule_exclusion(input: &str) -> Result<(), anyhow::Error> {
+    ScheduleExclusion::from_str(input).map(|_| ())
+}
+
+pub const SCHEDULE_EXCLUSION_SCHEMA: Schema = StringSchema::new(
+    "Days to skip scheduled runs on: a date ('YYYY-MM-DD'), a date range \
+    ('YYYY-MM-DD..YYYY-MM-DD'), the last weekday of a month ('last-fri'), a calendar event \
+    matching the days to skip, or a reference to a holiday calendar ('@NAME').",
+)
+.format(&ApiStringFormat::VerifyFn(verify_schedule_exclusion))
+.type_text("<date[..date]|last-<weekday>|calendar-event|@calendar>")
+.schema();
+
+pub const SCHEDULE_EXCLUSION_LIST_SCHEMA: Schema =
+    ArraySchema::new("List of schedule exclusions.", &SCHEDULE_EXCLUSION_SCHEMA).schema();
+
+#[api(
+    properties: {
+        name: {
+            schema: HOLIDAY_CALENDAR_ID_SCHEMA,
+        },
+        comment: {
+            optional: true,
+            schema: SINGLE_LINE_COMMENT_SCHEMA,
+        },
+        exclude: {
+            schema: SCHEDULE_EXCLUSION_LIST_SCHEMA,
+        },
+    },
+)]
+#[derive(Serialize, Deserialize, Updater, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// A named set of days on which scheduled jobs referencing it do not run.
+pub struct HolidayCalendarConfig {
+    #[updater(skip)]
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    pub exclude: Vec<ScheduleExclusion>,
+}
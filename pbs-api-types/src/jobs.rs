@@ -8,10 +8,11 @@ use serde::{Deserialize, Serialize};
 use proxmox_schema::*;
 
 use crate::{
-    Authid, BackupNamespace, BackupType, NotificationMode, RateLimitConfig, Userid,
-    BACKUP_GROUP_SCHEMA, BACKUP_NAMESPACE_SCHEMA, BACKUP_NS_RE, DATASTORE_SCHEMA,
-    DRIVE_NAME_SCHEMA, MEDIA_POOL_NAME_SCHEMA, NS_MAX_DEPTH_REDUCED_SCHEMA, PROXMOX_SAFE_ID_FORMAT,
-    PROXMOX_SAFE_ID_REGEX_STR, REMOTE_ID_SCHEMA, SINGLE_LINE_COMMENT_SCHEMA,
+    Authid, BackupNamespace, BackupType, NotificationMode, RateLimitConfig, ScheduleExclusion,
+    TaskStateType, Userid, BACKUP_GROUP_SCHEMA, BACKUP_NAMESPACE_SCHEMA, BACKUP_NS_RE,
+    DATASTORE_SCHEMA, DRIVE_NAME_SCHEMA, MEDIA_POOL_NAME_SCHEMA, NS_MAX_DEPTH_REDUCED_SCHEMA,
+    PROXMOX_SAFE_ID_FORMAT, PROXMOX_SAFE_ID_REGEX_STR, REMOTE_ID_SCHEMA,
+    SCHEDULE_EXCLUSION_LIST_SCHEMA, SINGLE_LINE_COMMENT_SCHEMA,
 };
 
 const_regex! {
@@ -43,6 +44,14 @@ pub const GC_SCHEDULE_SCHEMA: Schema =
         .type_text("<calendar-event>")
         .schema();
 
+pub const ARCHIVE_SCHEDULE_SCHEMA: Schema =
+    StringSchema::new("Move old snapshots to the archive datastore at specified schedule.")
+        .format(&ApiStringFormat::VerifyFn(
+            proxmox_time::verify_calendar_event,
+        ))
+        .type_text("<calendar-event>")
+        .schema();
+
 pub const PRUNE_SCHEDULE_SCHEMA: Schema = StringSchema::new("Run prune job at specified schedule.")
     .format(&ApiStringFormat::VerifyFn(
         proxmox_time::verify_calendar_event,
@@ -86,6 +95,10 @@ pub const REMOVE_VANISHED_BACKUPS_SCHEMA: Schema = BooleanSchema::new(
             optional: true,
             type: Integer,
         },
+        "last-run-error-class": {
+            type: JobErrorClass,
+            optional: true,
+        },
     }
 )]
 #[derive(Serialize, Deserialize, Default, Clone, PartialEq)]
@@ -100,6 +113,263 @@ pub struct JobScheduleStatus {
     pub last_run_upid: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub last_run_endtime: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_run_error_class: Option<JobErrorClass>,
+}
+
+#[api()]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Classification of the error a job run failed with.
+pub enum JobErrorClass {
+    /// Temporary network or server problem, worth retrying.
+    TransientNetwork,
+    /// Missing privileges or failed authentication.
+    Permission,
+    /// No space left on the target storage.
+    StorageFull,
+    /// Corrupt or inconsistent backup data.
+    DataCorruption,
+    /// Any other error.
+    Other,
+}
+serde_plain::derive_display_from_serialize!(JobErrorClass);
+
+#[api(
+    properties: {
+        count: {
+            type: Integer,
+            minimum: 0,
+            maximum: 10,
+            optional: true,
+            default: 0,
+        },
+        delay: {
+            type: Integer,
+            minimum: 1,
+            maximum: 3600,
+            optional: true,
+            default: 60,
+        },
+    },
+)]
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Automatic retries of job runs which failed with a transient error.
+pub struct JobRetryPolicy {
+    /// Number of retries.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count: Option<u64>,
+    /// Delay before the first retry in seconds, doubled for each further one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delay: Option<u64>,
+}
+
+pub const JOB_RETRY_POLICY_SCHEMA: Schema = StringSchema::new(
+    "Retry job runs which failed with a transient error, like a network interruption.",
+)
+.format(&ApiStringFormat::PropertyString(
+    &JobRetryPolicy::API_SCHEMA,
+))
+.schema();
+
+#[api()]
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Statistics of a garbage collection run, as recorded in the job history.
+pub struct GcRunStatistics {
+    /// Sum of removed bytes.
+    pub removed_bytes: u64,
+    /// Number of removed chunks.
+    pub removed_chunks: usize,
+    /// Sum of pending bytes (pending removal - kept for safety).
+    pub pending_bytes: u64,
+    /// Number of pending chunks (pending removal - kept for safety).
+    pub pending_chunks: usize,
+    /// Bytes used by chunks on disk after the run.
+    pub disk_bytes: u64,
+    /// Used space of the datastore file system before the run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub used_before: Option<u64>,
+    /// Used space of the datastore file system after the run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub used_after: Option<u64>,
+}
+
+#[api()]
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Statistics of a verification run, as recorded in the job history.
+pub struct VerifyRunStatistics {
+    /// Number of snapshots verified successfully.
+    pub ok: u64,
+    /// Number of snapshots which failed verification.
+    pub failed: u64,
+    /// Number of snapshots skipped, e.g. because they were verified recently.
+    pub skipped: u64,
+}
+
+#[api(
+    properties: {
+        status: {
+            type: TaskStateType,
+        },
+        store: {
+            schema: DATASTORE_SCHEMA,
+            optional: true,
+        },
+        gc: {
+            type: GcRunStatistics,
+            optional: true,
+        },
+        verify: {
+            type: VerifyRunStatistics,
+            optional: true,
+        },
+        "error-class": {
+            type: JobErrorClass,
+            optional: true,
+        },
+    }
+)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// A single run of a job, as recorded in the job history.
+pub struct JobHistoryEntry {
+    /// Job type, e.g. 'syncjob'.
+    pub job_type: String,
+    /// Job ID.
+    pub job_id: String,
+    /// Task UPID of the run.
+    pub upid: String,
+    /// Start time (UNIX epoch).
+    pub starttime: i64,
+    /// End time (UNIX epoch).
+    pub endtime: i64,
+    pub status: TaskStateType,
+    /// Task end status message.
+    pub state: String,
+    /// Amount of transferred bytes, if applicable for the job type.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+    /// Number of processed snapshots, if applicable for the job type.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapshots: Option<u64>,
+    /// Datastore the job ran on, if applicable for the job type.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub store: Option<String>,
+    /// Statistics of successful garbage collection runs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gc: Option<GcRunStatistics>,
+    /// Statistics of verification runs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verify: Option<VerifyRunStatistics>,
+    /// Error class of a failed run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_class: Option<JobErrorClass>,
+}
+
+#[api()]
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Aggregated statistics over the recorded runs of a job.
+pub struct JobHistoryStatistics {
+    /// Job type, e.g. 'syncjob'.
+    pub job_type: String,
+    /// Job ID.
+    pub job_id: String,
+    /// Number of recorded runs.
+    pub runs: u64,
+    /// Number of runs which finished successfully.
+    pub ok: u64,
+    /// Number of runs which finished with warnings.
+    pub warnings: u64,
+    /// Number of failed runs (including runs with unknown status).
+    pub errors: u64,
+    /// Average run duration in seconds.
+    pub average_duration: f64,
+    /// Longest run duration in seconds.
+    pub max_duration: i64,
+    /// Sum of transferred bytes over all runs.
+    pub bytes: u64,
+}
+
+#[api(
+    properties: {
+        store: {
+            schema: DATASTORE_SCHEMA,
+        },
+        "next-runs": {
+            description: "Computed start times of the next runs (UNIX epoch).",
+            type: Array,
+            items: {
+                description: "Start time (UNIX epoch).",
+                type: Integer,
+            },
+        },
+    }
+)]
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Upcoming runs of a scheduled job.
+pub struct JobSchedulePreview {
+    /// Job type, e.g. 'syncjob'.
+    pub job_type: String,
+    /// Job ID.
+    pub job_id: String,
+    pub store: String,
+    /// The schedule of the job.
+    pub schedule: String,
+    /// Whether the job puts a heavy load on its datastore.
+    pub heavy: bool,
+    pub next_runs: Vec<i64>,
+    /// Expected duration of a run in seconds, based on the job history.
+    pub estimated_duration: i64,
+    /// Why the runs of the job could not be computed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[api(
+    properties: {
+        store: {
+            schema: DATASTORE_SCHEMA,
+        },
+    }
+)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Two heavy jobs on the same datastore expected to run at the same time.
+pub struct JobScheduleConflict {
+    pub store: String,
+    /// The job starting first, as '<job-type>/<job-id>'.
+    pub first: String,
+    /// Start time of the first job (UNIX epoch).
+    pub first_start: i64,
+    /// The job starting while the first one is still expected to run.
+    pub second: String,
+    /// Start time of the second job (UNIX epoch).
+    pub second_start: i64,
+}
+
+#[api(
+    properties: {
+        jobs: {
+            type: Array,
+            items: { type: JobSchedulePreview },
+        },
+        conflicts: {
+            type: Array,
+            items: { type: JobScheduleConflict },
+        },
+    }
+)]
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Upcoming runs of all scheduled jobs and the conflicts between them.
+pub struct SchedulePreview {
+    pub jobs: Vec<JobSchedulePreview>,
+    pub conflicts: Vec<JobScheduleConflict>,
 }
 
 #[api()]
@@ -195,6 +465,10 @@ pub const VERIFICATION_OUTDATED_AFTER_SCHEMA: Schema =
             optional: true,
             schema: VERIFICATION_SCHEDULE_SCHEMA,
         },
+        "schedule-exclude": {
+            optional: true,
+            schema: SCHEDULE_EXCLUSION_LIST_SCHEMA,
+        },
         ns: {
             optional: true,
             schema: BACKUP_NAMESPACE_SCHEMA,
@@ -203,6 +477,38 @@ pub const VERIFICATION_OUTDATED_AFTER_SCHEMA: Schema =
             optional: true,
             schema: crate::NS_MAX_DEPTH_SCHEMA,
         },
+        replica: {
+            optional: true,
+            schema: REMOTE_ID_SCHEMA,
+        },
+        "replica-store": {
+            optional: true,
+            schema: DATASTORE_SCHEMA,
+        },
+        "replica-ns": {
+            optional: true,
+            schema: BACKUP_NAMESPACE_SCHEMA,
+        },
+        attestation: {
+            optional: true,
+            default: false,
+        },
+        "attestation-webhook": {
+            optional: true,
+            schema: crate::HTTP_URL_SCHEMA,
+        },
+        "junit-report": {
+            optional: true,
+            default: false,
+        },
+        exclude: {
+            optional: true,
+            schema: VERIFY_EXCLUDE_LIST_SCHEMA,
+        },
+        priority: {
+            optional: true,
+            schema: VERIFY_PRIORITY_LIST_SCHEMA,
+        },
     }
 )]
 #[derive(Serialize, Deserialize, Updater, Clone, PartialEq)]
@@ -226,6 +532,9 @@ pub struct VerificationJobConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     /// when to schedule this job in calendar event notation
     pub schedule: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// days on which scheduled runs are skipped
+    pub schedule_exclude: Option<Vec<ScheduleExclusion>>,
     #[serde(skip_serializing_if = "Option::is_none", default)]
     /// on which backup namespace to run the verification recursively
     pub ns: Option<BackupNamespace>,
@@ -233,6 +542,31 @@ pub struct VerificationJobConfig {
     /// how deep the verify should go from the `ns` level downwards. Passing 0 verifies only the
     /// snapshots on the same level as the passed `ns`, or the datastore root if none.
     pub max_depth: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// trusted remote holding a synced copy of the datastore, snapshots it verified successfully
+    /// are not read locally again
+    pub replica: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// datastore on the replica remote, defaults to the local datastore name
+    pub replica_store: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    /// namespace on the replica corresponding to `ns`, defaults to the same namespace
+    pub replica_ns: Option<BackupNamespace>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// store a signed attestation listing the snapshots verified by each run
+    pub attestation: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// URL the attestation is posted to after each run, implies 'attestation'
+    pub attestation_webhook: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// additionally write the result of each run as JUnit XML file
+    pub junit_report: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// backup groups which are never verified by the job
+    pub exclude: Option<Vec<VerifyGroupSelector>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// backup groups which are verified first, in the order of the list
+    pub priority: Option<Vec<VerifyGroupSelector>>,
 }
 
 impl VerificationJobConfig {
@@ -350,6 +684,14 @@ pub struct TapeBackupJobSetup {
             optional: true,
             schema: SYNC_SCHEDULE_SCHEMA,
         },
+        "schedule-exclude": {
+            optional: true,
+            schema: SCHEDULE_EXCLUSION_LIST_SCHEMA,
+        },
+        retry: {
+            optional: true,
+            schema: JOB_RETRY_POLICY_SCHEMA,
+        },
     }
 )]
 #[derive(Serialize, Deserialize, Clone, Updater, PartialEq)]
@@ -364,6 +706,12 @@ pub struct TapeBackupJobConfig {
     pub comment: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub schedule: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// days on which scheduled runs are skipped
+    pub schedule_exclude: Option<Vec<ScheduleExclusion>>,
+    /// Retry policy for runs failing with a transient error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry: Option<String>,
 }
 
 #[api(
@@ -493,6 +841,71 @@ pub const GROUP_FILTER_SCHEMA: Schema = StringSchema::new(
 pub const GROUP_FILTER_LIST_SCHEMA: Schema =
     ArraySchema::new("List of group filters.", &GROUP_FILTER_SCHEMA).schema();
 
+#[derive(Clone, Debug, PartialEq)]
+/// Selects backup groups of a verification job, by namespace or like a group filter.
+pub enum VerifyGroupSelector {
+    /// All groups in the namespace and the namespaces below it
+    Namespace(BackupNamespace),
+    /// All groups matching the filter, in any namespace
+    Group(FilterType),
+}
+
+impl VerifyGroupSelector {
+    /// Check whether the group `group` in namespace `ns` is selected.
+    pub fn matches(&self, ns: &BackupNamespace, group: &crate::BackupGroup) -> bool {
+        match self {
+            Self::Namespace(selected) => selected.contains(ns).is_some(),
+            Self::Group(filter_type) => group.matches_filter_type(filter_type),
+        }
+    }
+}
+
+impl std::str::FromStr for VerifyGroupSelector {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("ns", value)) => Ok(Self::Namespace(BackupNamespace::new(value)?)),
+            _ => Ok(Self::Group(s.parse()?)),
+        }
+    }
+}
+
+// used for serializing below, caution!
+impl std::fmt::Display for VerifyGroupSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Namespace(ns) => write!(f, "ns:{}", ns),
+            Self::Group(filter_type) => std::fmt::Display::fmt(filter_type, f),
+        }
+    }
+}
+
+proxmox_serde::forward_deserialize_to_from_str!(VerifyGroupSelector);
+proxmox_serde::forward_serialize_to_display!(VerifyGroupSelector);
+
+fn verify_verify_group_selector(input: &str) -> Result<(), anyhow::Error> {
+    VerifyGroupSelector::from_str(input).map(|_| ())
+}
+
+pub const VERIFY_GROUP_SELECTOR_SCHEMA: Schema = StringSchema::new(
+    "Backup groups selected by namespace including sub-namespaces ('ns:NAMESPACE'), group identifier ('group:GROUP'), group type ('type:<vm|ct|host>'), or regex ('regex:RE').")
+    .format(&ApiStringFormat::VerifyFn(verify_verify_group_selector))
+    .type_text("<ns:NAMESPACE|type:<vm|ct|host>|group:GROUP|regex:RE>")
+    .schema();
+
+pub const VERIFY_EXCLUDE_LIST_SCHEMA: Schema = ArraySchema::new(
+    "Backup groups which are never verified by the job.",
+    &VERIFY_GROUP_SELECTOR_SCHEMA,
+)
+.schema();
+
+pub const VERIFY_PRIORITY_LIST_SCHEMA: Schema = ArraySchema::new(
+    "Backup groups which are verified first, in the order of the list.",
+    &VERIFY_GROUP_SELECTOR_SCHEMA,
+)
+.schema();
+
 pub const TRANSFER_LAST_SCHEMA: Schema =
     IntegerSchema::new("Limit transfer to last N snapshots (per group), skipping others")
         .minimum(1)
@@ -544,6 +957,10 @@ pub const TRANSFER_LAST_SCHEMA: Schema =
             optional: true,
             schema: SYNC_SCHEDULE_SCHEMA,
         },
+        "schedule-exclude": {
+            optional: true,
+            schema: SCHEDULE_EXCLUSION_LIST_SCHEMA,
+        },
         "group-filter": {
             schema: GROUP_FILTER_LIST_SCHEMA,
             optional: true,
@@ -552,6 +969,15 @@ pub const TRANSFER_LAST_SCHEMA: Schema =
             schema: TRANSFER_LAST_SCHEMA,
             optional: true,
         },
+        "verify-new": {
+            type: bool,
+            optional: true,
+            default: false,
+        },
+        retry: {
+            optional: true,
+            schema: JOB_RETRY_POLICY_SCHEMA,
+        },
     }
 )]
 #[derive(Serialize, Deserialize, Clone, Updater, PartialEq)]
@@ -580,11 +1006,20 @@ pub struct SyncJobConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub schedule: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    /// days on which scheduled runs are skipped
+    pub schedule_exclude: Option<Vec<ScheduleExclusion>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub group_filter: Option<Vec<GroupFilter>>,
     #[serde(flatten)]
     pub limit: RateLimitConfig,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub transfer_last: Option<usize>,
+    /// Verify newly synced snapshots once the sync finished
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verify_new: Option<bool>,
+    /// Retry policy for runs failing with a transient error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry: Option<String>,
 }
 
 impl SyncJobConfig {
@@ -735,6 +1170,10 @@ impl PruneJobOptions {
         schedule: {
             schema: PRUNE_SCHEDULE_SCHEMA,
         },
+        "schedule-exclude": {
+            optional: true,
+            schema: SCHEDULE_EXCLUSION_LIST_SCHEMA,
+        },
         comment: {
             optional: true,
             schema: SINGLE_LINE_COMMENT_SCHEMA,
@@ -742,6 +1181,10 @@ impl PruneJobOptions {
         options: {
             type: PruneJobOptions,
         },
+        retry: {
+            optional: true,
+            schema: JOB_RETRY_POLICY_SCHEMA,
+        },
     },
 )]
 #[derive(Deserialize, Serialize, Updater, Clone, PartialEq)]
@@ -761,11 +1204,19 @@ pub struct PruneJobConfig {
 
     pub schedule: String,
 
+    /// Days on which scheduled runs are skipped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schedule_exclude: Option<Vec<ScheduleExclusion>>,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     pub comment: Option<String>,
 
     #[serde(flatten)]
     pub options: PruneJobOptions,
+
+    /// Retry policy for runs failing with a transient error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry: Option<String>,
 }
 
 impl PruneJobConfig {
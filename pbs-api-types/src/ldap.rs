@@ -2,7 +2,7 @@ use serde::{Deserialize, Serialize};
 
 use proxmox_schema::{api, ApiStringFormat, ApiType, ArraySchema, Schema, StringSchema, Updater};
 
-use super::{REALM_ID_SCHEMA, SINGLE_LINE_COMMENT_SCHEMA};
+use super::{LoginNotify, NS_PROVISION_STRING_SCHEMA, REALM_ID_SCHEMA, SINGLE_LINE_COMMENT_SCHEMA};
 
 #[api()]
 #[derive(Copy, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
@@ -51,7 +51,11 @@ pub enum LdapMode {
         "bind-dn" : {
             schema: LDAP_DOMAIN_SCHEMA,
             optional: true,
-        }
+        },
+        "namespace-provision": {
+            schema: NS_PROVISION_STRING_SCHEMA,
+            optional: true,
+        },
     },
 )]
 #[derive(Serialize, Deserialize, Updater, Clone)]
@@ -106,6 +110,12 @@ pub struct LdapRealmConfig {
     /// User ``objectClass`` classes to sync
     #[serde(skip_serializing_if = "Option::is_none")]
     pub user_classes: Option<String>,
+    /// Notify about logins from previously unseen addresses
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub login_notify: Option<LoginNotify>,
+    /// Namespace created for users of the realm on their first login
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace_provision: Option<String>,
 }
 
 #[api(
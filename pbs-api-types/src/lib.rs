@@ -89,6 +89,9 @@ pub use acl::*;
 mod datastore;
 pub use datastore::*;
 
+mod holiday;
+pub use holiday::*;
+
 mod jobs;
 pub use jobs::*;
 
@@ -156,7 +159,9 @@ const_regex! {
         r"^^(?:(?:(",
         USER_ID_REGEX_STR, "|", APITOKEN_ID_REGEX_STR,
         ")@)?(",
-        DNS_NAME_STR, "|",  IPRE_BRACKET_STR,
+        r"_pbs\._tcp\.", DNS_NAME_STR, "|",
+        "(?:", DNS_NAME_STR, "|",  IPRE_BRACKET_STR, ")",
+        "(?:,(?:", DNS_NAME_STR, "|",  IPRE_BRACKET_STR, "))*",
         "):)?(?:([0-9]{1,5}):)?(", PROXMOX_SAFE_ID_REGEX_STR, r")$"
     );
 
@@ -289,7 +294,7 @@ pub enum NodePowerCommand {
 }
 
 #[api()]
-#[derive(Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum TaskStateType {
     /// Ok
@@ -415,3 +420,67 @@ pub struct BasicRealmInfo {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub comment: Option<String>,
 }
+
+#[api]
+#[derive(Deserialize, Serialize, Copy, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+/// Who to notify when a user of a realm logs in from a previously unseen address
+pub enum LoginNotify {
+    /// Send a mail to the email address of the user
+    User,
+    /// Send a notification of type `login` through the notification system
+    Admin,
+    /// Notify both the user and the notification system
+    All,
+}
+
+serde_plain::derive_display_from_serialize!(LoginNotify);
+serde_plain::derive_fromstr_from_deserialize!(LoginNotify);
+
+const_regex! {
+    pub NS_PROVISION_TEMPLATE_REGEX = concatcp!(
+        r"^(?:[A-Za-z0-9_.\-]|\{user\}|\{realm\})+",
+        r"(?:/(?:[A-Za-z0-9_.\-]|\{user\}|\{realm\})+){0,7}$"
+    );
+}
+
+pub const NS_PROVISION_TEMPLATE_SCHEMA: Schema = StringSchema::new(
+    "Namespace path template, '{user}' and '{realm}' are replaced by the name and realm of the user.",
+)
+.format(&ApiStringFormat::Pattern(&NS_PROVISION_TEMPLATE_REGEX))
+.max_length(256)
+.schema();
+
+#[api(
+    properties: {
+        store: {
+            schema: DATASTORE_SCHEMA,
+        },
+        ns: {
+            schema: NS_PROVISION_TEMPLATE_SCHEMA,
+        },
+        role: {
+            type: Role,
+            optional: true,
+        },
+    },
+)]
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+/// Personal namespace created on the first login of a user of a realm
+pub struct NamespaceProvision {
+    /// Datastore the namespaces are created on
+    pub store: String,
+    /// Namespace path, '{user}' and '{realm}' are replaced by the name and realm of the user
+    pub ns: String,
+    /// Role granted to the user on the namespace, defaults to 'DatastoreBackup'
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+}
+
+pub const NS_PROVISION_STRING_SCHEMA: Schema =
+    StringSchema::new("Namespace to provision for users of the realm.")
+        .format(&ApiStringFormat::PropertyString(
+            &NamespaceProvision::API_SCHEMA,
+        ))
+        .schema();
@@ -187,3 +187,57 @@ pub struct MetricServerInfo {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub comment: Option<String>,
 }
+
+/// Upper bounds of the latency histogram buckets of API endpoints, in milliseconds. The last
+/// bucket counts all slower calls.
+pub const API_LATENCY_BUCKETS_MS: &[u64] = &[5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+#[api]
+#[derive(Copy, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+/// Order of the API endpoint statistics
+pub enum ApiStatsSortBy {
+    /// Slowest average latency first
+    Average,
+    /// Slowest 95th percentile latency first
+    P95,
+    /// Slowest single call first
+    Max,
+    /// Most calls first
+    Count,
+}
+
+#[api(
+    properties: {
+        histogram: {
+            type: Array,
+            items: {
+                type: Integer,
+                description: "Calls in this latency bucket.",
+            },
+        },
+    },
+)]
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Latency and throughput of an API endpoint since the start of the proxy
+pub struct ApiEndpointStats {
+    /// HTTP method and path template, for example `GET /admin/datastore/{store}/status`
+    pub endpoint: String,
+    /// Number of calls
+    pub count: u64,
+    /// Number of calls failing with a server error (5xx)
+    pub errors: u64,
+    /// Average latency until the response started, in milliseconds
+    pub average: f64,
+    /// Estimated 95th percentile latency (upper bound of its histogram bucket), in milliseconds
+    pub p95: u64,
+    /// Latency of the slowest call, in milliseconds
+    pub max: u64,
+    /// Total size of the request bodies with known length, in bytes
+    pub bytes_in: u64,
+    /// Total size of the response bodies with known length, in bytes
+    pub bytes_out: u64,
+    /// Number of calls per latency bucket, see `API_LATENCY_BUCKETS_MS`
+    pub histogram: Vec<u64>,
+}
@@ -160,3 +160,23 @@ pub struct NodeStatus {
     /// Current boot mode
     pub boot_info: BootModeInformation,
 }
+
+#[api(
+    properties: {
+        loaded: {
+            optional: true,
+        },
+    },
+)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Generation of a configuration file, increased on every change
+pub struct ConfigGeneration {
+    /// The configuration file name.
+    pub config: String,
+    /// The current generation.
+    pub generation: u64,
+    /// The generation applied by the proxy, for configurations it keeps loaded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loaded: Option<u64>,
+}
@@ -1,9 +1,11 @@
+use anyhow::{bail, Error};
 use serde::{Deserialize, Serialize};
 
 use proxmox_schema::{api, ApiStringFormat, ArraySchema, Schema, StringSchema, Updater};
 
 use super::{
-    GENERIC_URI_REGEX, PROXMOX_SAFE_ID_FORMAT, PROXMOX_SAFE_ID_REGEX, REALM_ID_SCHEMA,
+    LoginNotify, Role, ACL_PATH_REGEX, GENERIC_URI_REGEX, NS_PROVISION_STRING_SCHEMA,
+    PROXMOX_SAFE_ID_FORMAT, PROXMOX_SAFE_ID_REGEX, REALM_ID_SCHEMA, SINGLE_LINE_COMMENT_FORMAT,
     SINGLE_LINE_COMMENT_SCHEMA,
 };
 
@@ -54,6 +56,74 @@ pub const OPENID_USERNAME_CLAIM_SCHEMA: Schema = StringSchema::new(
 .format(&PROXMOX_SAFE_ID_FORMAT)
 .schema();
 
+pub const OPENID_ROLES_CLAIM_SCHEMA: Schema = StringSchema::new(
+    "Claim containing the group or role names matched by the role mapping, 'groups' by default.",
+)
+.min_length(1)
+.max_length(256)
+.format(&SINGLE_LINE_COMMENT_FORMAT)
+.schema();
+
+/// A single entry of the role mapping of an OpenID realm.
+pub struct OpenIdRoleMapping {
+    /// Value of the roles claim this entry applies to
+    pub value: String,
+    /// Role granted if the claim contains the value
+    pub role: String,
+    /// ACL path the role is granted on
+    pub path: String,
+}
+
+impl std::str::FromStr for OpenIdRoleMapping {
+    type Err = Error;
+
+    /// Parses `<value>:<role>:<path>`, the value itself may contain colons.
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let mut parts = s.rsplitn(3, ':');
+        let (path, role, value) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(path), Some(role), Some(value)) if !value.is_empty() => (path, role, value),
+            _ => bail!("invalid role mapping '{s}', expected '<value>:<role>:<path>'"),
+        };
+
+        if role.parse::<Role>().is_err() {
+            bail!("invalid role mapping '{s}' - unknown role '{role}'");
+        }
+        if !ACL_PATH_REGEX.is_match(path) {
+            bail!("invalid role mapping '{s}' - invalid ACL path '{path}'");
+        }
+
+        Ok(Self {
+            value: value.to_string(),
+            role: role.to_string(),
+            path: path.to_string(),
+        })
+    }
+}
+
+/// Separator of the role mapping entries. Group names may contain commas or whitespace, for
+/// example LDAP distinguished names, so neither can be used.
+pub const OPENID_ROLE_MAPPING_SEPARATOR: char = ';';
+
+/// Parse a role mapping list, entries are separated by [`OPENID_ROLE_MAPPING_SEPARATOR`].
+pub fn parse_openid_role_mapping_list(list: &str) -> Result<Vec<OpenIdRoleMapping>, Error> {
+    list.split(OPENID_ROLE_MAPPING_SEPARATOR)
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+fn verify_openid_role_mapping_list(s: &str) -> Result<(), Error> {
+    parse_openid_role_mapping_list(s).map(drop)
+}
+
+pub const OPENID_ROLE_MAPPING_LIST_SCHEMA: Schema = StringSchema::new(
+    "Grant a role on an ACL path if the roles claim contains a value, \
+    as ';' separated list of '<value>:<role>:<path>' entries.",
+)
+.format(&ApiStringFormat::VerifyFn(verify_openid_role_mapping_list))
+.schema();
+
 #[api(
     properties: {
         realm: {
@@ -88,6 +158,18 @@ pub const OPENID_USERNAME_CLAIM_SCHEMA: Schema = StringSchema::new(
             schema: OPENID_USERNAME_CLAIM_SCHEMA,
             optional: true,
         },
+        "namespace-provision": {
+            schema: NS_PROVISION_STRING_SCHEMA,
+            optional: true,
+        },
+        "roles-claim": {
+            schema: OPENID_ROLES_CLAIM_SCHEMA,
+            optional: true,
+        },
+        "role-mapping": {
+            schema: OPENID_ROLE_MAPPING_LIST_SCHEMA,
+            optional: true,
+        },
     },
 )]
 #[derive(Serialize, Deserialize, Updater)]
@@ -117,4 +199,16 @@ pub struct OpenIdRealmConfig {
     #[updater(skip)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub username_claim: Option<String>,
+    /// Notify about logins from previously unseen addresses
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub login_notify: Option<LoginNotify>,
+    /// Namespace created for users of the realm on their first login
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace_provision: Option<String>,
+    /// Claim holding the group or role names matched by the role mapping
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roles_claim: Option<String>,
+    /// Roles granted to the users of the realm depending on the roles claim
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role_mapping: Option<String>,
 }
@@ -4,7 +4,10 @@ use serde::{Deserialize, Serialize};
 
 use proxmox_schema::{api, IntegerSchema, Schema, StringSchema, Updater};
 
-use crate::{OptionalDeviceIdentification, CHANGER_NAME_SCHEMA, PROXMOX_SAFE_ID_FORMAT};
+use crate::{
+    OptionalDeviceIdentification, CHANGER_NAME_SCHEMA, MEDIA_LABEL_SCHEMA, PROXMOX_SAFE_ID_FORMAT,
+    UPID,
+};
 
 pub const DRIVE_NAME_SCHEMA: Schema = StringSchema::new("Drive Identifier.")
     .format(&PROXMOX_SAFE_ID_FORMAT)
@@ -98,6 +101,45 @@ pub struct DriveListEntry {
     pub activity: Option<DeviceActivity>,
 }
 
+#[api(
+    properties: {
+        upid: {
+            schema: UPID::API_SCHEMA,
+        },
+        media: {
+            schema: MEDIA_LABEL_SCHEMA,
+            optional: true,
+        },
+    },
+)]
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+/// Progress of the task using a drive, updated by the task while it writes to or reads from the
+/// media
+pub struct DriveOperationProgress {
+    /// The task using the drive
+    pub upid: String,
+    /// Label of the loaded media
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub media: Option<String>,
+    /// Current file number
+    pub file_number: u64,
+    /// Current block number
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_number: Option<u64>,
+    /// Bytes written by the task
+    #[serde(default)]
+    pub bytes_written: u64,
+    /// Bytes read by the task
+    #[serde(default)]
+    pub bytes_read: u64,
+    /// Write data compression ratio since the media was loaded (percent)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression_ratio: Option<u64>,
+    /// Time of the last update (epoch)
+    pub updated: i64,
+}
+
 #[api()]
 #[derive(Serialize, Deserialize)]
 /// Medium auxiliary memory attributes (MAM)
@@ -98,6 +98,10 @@ impl RateLimitConfig {
             },
             optional: true,
         },
+        parent: {
+            schema: TRAFFIC_CONTROL_ID_SCHEMA,
+            optional: true,
+        },
     },
 )]
 #[derive(Clone, Serialize, Deserialize, PartialEq, Updater)]
@@ -119,6 +123,10 @@ pub struct TrafficControlRule {
     /// Enable the rule at specific times
     #[serde(skip_serializing_if = "Option::is_none")]
     pub timeframe: Option<Vec<String>>,
+    /// Rule whose limits apply to the traffic of this rule as well, shared with all of its
+    /// other children
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
 }
 
 #[api(
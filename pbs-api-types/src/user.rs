@@ -1,9 +1,14 @@
 use serde::{Deserialize, Serialize};
 
-use proxmox_schema::{api, BooleanSchema, IntegerSchema, Schema, StringSchema, Updater};
+use proxmox_human_byte::HumanByte;
+use proxmox_schema::{
+    api, ApiStringFormat, ApiType, BooleanSchema, IntegerSchema, Schema, StringSchema, Updater,
+};
 
 use super::userid::{Authid, Userid, PROXMOX_TOKEN_ID_SCHEMA};
-use super::{SINGLE_LINE_COMMENT_FORMAT, SINGLE_LINE_COMMENT_SCHEMA};
+use super::{
+    BackupNamespace, ACL_PATH_SCHEMA, SINGLE_LINE_COMMENT_FORMAT, SINGLE_LINE_COMMENT_SCHEMA,
+};
 
 pub const ENABLE_USER_SCHEMA: Schema = BooleanSchema::new(
     "Enable the account (default). You can set this to '0' to disable the account.",
@@ -70,7 +75,7 @@ pub const EMAIL_SCHEMA: Schema = StringSchema::new("E-Mail Address.")
             optional: true,
             description: "List of user's API tokens.",
             items: {
-                type: ApiToken
+                type: ApiTokenWithLastUse
             },
         },
         "totp-locked": {
@@ -83,6 +88,14 @@ pub const EMAIL_SCHEMA: Schema = StringSchema::new("E-Mail Address.")
             optional: true,
             description: "Contains a timestamp until when a user is locked out of 2nd factors",
         },
+        "last-used": {
+            optional: true,
+            description: "Time of the last successful authentication (epoch).",
+        },
+        "last-used-ip": {
+            optional: true,
+            description: "Source address of the last login or backup/reader session.",
+        },
     }
 )]
 #[derive(Serialize, Deserialize, Clone, PartialEq)]
@@ -103,17 +116,60 @@ pub struct UserWithTokens {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub email: Option<String>,
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
-    pub tokens: Vec<ApiToken>,
+    pub tokens: Vec<ApiTokenWithLastUse>,
     #[serde(skip_serializing_if = "bool_is_false", default)]
     pub totp_locked: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tfa_locked_until: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_used: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_used_ip: Option<String>,
 }
 
 fn bool_is_false(b: &bool) -> bool {
     !b
 }
 
+#[api(
+    properties: {
+        ns: {
+            type: BackupNamespace,
+            optional: true,
+        },
+        rate: {
+            type: HumanByte,
+            optional: true,
+        },
+        burst: {
+            type: HumanByte,
+            optional: true,
+        },
+    },
+)]
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+/// Settings suggested to clients using an API token, which have not been configured locally.
+pub struct TokenClientProfile {
+    /// Default namespace for backups
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ns: Option<BackupNamespace>,
+    /// Rate limit for backups and restores
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate: Option<HumanByte>,
+    /// Burst size for the rate limit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub burst: Option<HumanByte>,
+}
+
+pub const TOKEN_CLIENT_PROFILE_FORMAT: ApiStringFormat =
+    ApiStringFormat::PropertyString(&TokenClientProfile::API_SCHEMA);
+
+pub const TOKEN_CLIENT_PROFILE_SCHEMA: Schema =
+    StringSchema::new("Client profile suggested to clients using the API token.")
+        .format(&TOKEN_CLIENT_PROFILE_FORMAT)
+        .schema();
+
 #[api(
     properties: {
         tokenid: {
@@ -131,9 +187,18 @@ fn bool_is_false(b: &bool) -> bool {
             optional: true,
             schema: EXPIRE_USER_SCHEMA,
         },
+        "client-profile": {
+            optional: true,
+            schema: TOKEN_CLIENT_PROFILE_SCHEMA,
+        },
+        subtree: {
+            optional: true,
+            schema: ACL_PATH_SCHEMA,
+        },
     }
 )]
 #[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
 /// ApiToken properties.
 pub struct ApiToken {
     pub tokenid: Authid,
@@ -143,6 +208,12 @@ pub struct ApiToken {
     pub enable: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub expire: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_profile: Option<String>,
+    /// The token has no privileges outside of this ACL path and the paths below it. Can only be
+    /// set when creating the token.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subtree: Option<String>,
 }
 
 impl ApiToken {
@@ -160,6 +231,33 @@ impl ApiToken {
     }
 }
 
+#[api(
+    properties: {
+        token: {
+            type: ApiToken,
+        },
+        "last-used": {
+            optional: true,
+            description: "Time of the last successful authentication (epoch).",
+        },
+        "last-used-ip": {
+            optional: true,
+            description: "Source address of the last backup/reader session.",
+        },
+    }
+)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// ApiToken properties with the last use of the token, which is not part of the configuration.
+pub struct ApiTokenWithLastUse {
+    #[serde(flatten)]
+    pub token: ApiToken,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_used: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_used_ip: Option<String>,
+}
+
 #[api(
     properties: {
         userid: {
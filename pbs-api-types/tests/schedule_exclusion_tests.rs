@@ -0,0 +1,25 @@
+use pbs_api_types::ScheduleExclusion;
+use std::str::FromStr;
+
+#[test]
+fn test_exclusion_dates() {
+    for input in ["2024-02-29", "2023-12-31", "2024-01-01..2024-01-06"] {
+        assert!(matches!(
+            ScheduleExclusion::from_str(input).unwrap(),
+            ScheduleExclusion::Dates(_, _)
+        ));
+    }
+
+    for input in [
+        "2023-02-29",
+        "2024-02-30",
+        "2024-04-31",
+        "2024-13-01",
+        "2024-00-10",
+        "2024-01-00",
+        "2024-01-01..2024-02-31",
+        "2024-01-06..2024-01-01",
+    ] {
+        assert!(ScheduleExclusion::from_str(input).is_err(), "{input}");
+    }
+}
@@ -0,0 +1,75 @@
+use pbs_api_types::{BackupGroup, BackupNamespace, BackupType, VerifyGroupSelector};
+use std::str::FromStr;
+
+#[test]
+fn test_parse_display_roundtrip() {
+    for input in [
+        "ns:",
+        "ns:tenant-a",
+        "ns:tenant-a/sub",
+        "group:vm/100",
+        "type:ct",
+        "regex:^host/.*",
+    ] {
+        let selector = VerifyGroupSelector::from_str(input).unwrap();
+        assert_eq!(selector.to_string(), input);
+        assert_eq!(
+            VerifyGroupSelector::from_str(&selector.to_string()).unwrap(),
+            selector
+        );
+    }
+
+    assert!(matches!(
+        VerifyGroupSelector::from_str("ns:tenant-a").unwrap(),
+        VerifyGroupSelector::Namespace(_)
+    ));
+    assert!(matches!(
+        VerifyGroupSelector::from_str("type:vm").unwrap(),
+        VerifyGroupSelector::Group(_)
+    ));
+}
+
+#[test]
+fn test_parse_invalid() {
+    for input in [
+        "",
+        "vm/100",
+        "ns:a//b",
+        "namespace:a",
+        "type:foo",
+        "group:invalid",
+    ] {
+        assert!(VerifyGroupSelector::from_str(input).is_err(), "{input}");
+    }
+}
+
+#[test]
+fn test_matches() {
+    let root = BackupNamespace::root();
+    let tenant = BackupNamespace::new("tenant-a").unwrap();
+    let sub = BackupNamespace::new("tenant-a/sub").unwrap();
+    let other = BackupNamespace::new("tenant-b").unwrap();
+    let vm = BackupGroup::new(BackupType::Vm, "100");
+    let ct = BackupGroup::new(BackupType::Ct, "200");
+
+    // namespaces include their sub-namespaces
+    let selector = VerifyGroupSelector::from_str("ns:tenant-a").unwrap();
+    assert!(selector.matches(&tenant, &vm));
+    assert!(selector.matches(&sub, &ct));
+    assert!(!selector.matches(&root, &vm));
+    assert!(!selector.matches(&other, &vm));
+
+    let selector = VerifyGroupSelector::from_str("ns:").unwrap();
+    assert!(selector.matches(&root, &vm));
+    assert!(selector.matches(&other, &vm));
+
+    // group filters apply in any namespace
+    let selector = VerifyGroupSelector::from_str("type:vm").unwrap();
+    assert!(selector.matches(&root, &vm));
+    assert!(selector.matches(&sub, &vm));
+    assert!(!selector.matches(&root, &ct));
+
+    let selector = VerifyGroupSelector::from_str("group:ct/200").unwrap();
+    assert!(selector.matches(&other, &ct));
+    assert!(!selector.matches(&other, &vm));
+}
@@ -75,12 +75,21 @@ pub const API_ACCESS_LOG_FN: &str = concat!(PROXMOX_BACKUP_LOG_DIR_M!(), "/api/a
 /// creations. This file can be useful for fail2ban.
 pub const API_AUTH_LOG_FN: &str = concat!(PROXMOX_BACKUP_LOG_DIR_M!(), "/api/auth.log");
 
+/// logfile for finished restore transfers (reader sessions and downloads), one JSON object per
+/// line, used for accounting restore traffic per auth-id.
+pub const RESTORE_ACCOUNTING_LOG_FN: &str =
+    concat!(PROXMOX_BACKUP_LOG_DIR_M!(), "/api/restore-accounting.log");
+
 /// the PID filename for the unprivileged proxy daemon
 pub const PROXMOX_BACKUP_PROXY_PID_FN: &str = concat!(PROXMOX_BACKUP_RUN_DIR_M!(), "/proxy.pid");
 
 /// the PID filename for the privileged api daemon
 pub const PROXMOX_BACKUP_API_PID_FN: &str = concat!(PROXMOX_BACKUP_RUN_DIR_M!(), "/api.pid");
 
+/// the unix socket the proxy serves the API on for local users, see `local-api-users`
+pub const PROXMOX_BACKUP_LOCAL_API_SOCKET_FN: &str =
+    concat!(PROXMOX_BACKUP_RUN_DIR_M!(), "/api.sock");
+
 /// filename of the cached initramfs to use for booting single file restore VMs, this file is
 /// automatically created by APT hooks
 pub const PROXMOX_BACKUP_INITRAMFS_FN: &str =
@@ -25,6 +25,7 @@ pub struct BackupReader {
     h2: H2Client,
     abort: AbortHandle,
     crypt_config: Option<Arc<CryptConfig>>,
+    digest_salt: Option<[u8; 32]>,
 }
 
 impl Drop for BackupReader {
@@ -34,11 +35,17 @@ impl Drop for BackupReader {
 }
 
 impl BackupReader {
-    fn new(h2: H2Client, abort: AbortHandle, crypt_config: Option<Arc<CryptConfig>>) -> Arc<Self> {
+    fn new(
+        h2: H2Client,
+        abort: AbortHandle,
+        crypt_config: Option<Arc<CryptConfig>>,
+        digest_salt: Option<[u8; 32]>,
+    ) -> Arc<Self> {
         Arc::new(Self {
             h2,
             abort,
             crypt_config,
+            digest_salt,
         })
     }
 
@@ -63,20 +70,29 @@ impl BackupReader {
             param["ns"] = serde_json::to_value(ns)?;
         }
 
-        let req = HttpClient::request_builder(
-            client.server(),
-            client.port(),
-            "GET",
-            "/api2/json/reader",
-            Some(param),
-        )
-        .unwrap();
-
         let (h2, abort) = client
-            .start_h2_connection(req, String::from(PROXMOX_BACKUP_READER_PROTOCOL_ID_V1!()))
+            .start_h2_connection_with_retry(
+                || {
+                    HttpClient::request_builder(
+                        client.server(),
+                        client.port(),
+                        "GET",
+                        "/api2/json/reader",
+                        Some(param.clone()),
+                    )
+                },
+                PROXMOX_BACKUP_READER_PROTOCOL_ID_V1!(),
+            )
             .await?;
 
-        Ok(BackupReader::new(h2, abort, crypt_config))
+        let digest_salt = h2.digest_salt().await?;
+
+        Ok(BackupReader::new(h2, abort, crypt_config, digest_salt))
+    }
+
+    /// Digest salt of the datastore, needed to verify the digests of unencrypted chunks
+    pub fn digest_salt(&self) -> Option<&[u8; 32]> {
+        self.digest_salt.as_ref()
     }
 
     /// Execute a GET request
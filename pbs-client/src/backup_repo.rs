@@ -4,19 +4,25 @@ use anyhow::{format_err, Error};
 
 use pbs_api_types::{Authid, Userid, BACKUP_REPO_URL_REGEX, IP_V6_REGEX};
 
+use crate::service_discovery::{self, SERVICE_PREFIX};
+
 /// Reference remote backup locations
 ///
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct BackupRepository {
     /// The user name used for Authentication
     auth_id: Option<Authid>,
     /// The host name or IP address
     host: Option<String>,
+    /// Further hosts serving the same datastore, read sessions fail over to them
+    failover_hosts: Vec<String>,
     /// The port
     port: Option<u16>,
     /// The name of the datastore
     store: String,
+    /// Servers found via service discovery, replacing `host` and `port` for connections
+    discovered: Vec<(String, u16)>,
 }
 
 impl BackupRepository {
@@ -33,8 +39,10 @@ impl BackupRepository {
         Self {
             auth_id,
             host,
+            failover_hosts: Vec::new(),
             port,
             store,
+            discovered: Vec::new(),
         }
     }
 
@@ -55,13 +63,43 @@ impl BackupRepository {
     }
 
     pub fn host(&self) -> &str {
+        if let Some((host, _)) = self.discovered.first() {
+            return host;
+        }
         if let Some(ref host) = self.host {
             return host;
         }
         "localhost"
     }
 
+    /// All hosts of the repository with their port, starting with the primary one.
+    pub fn hosts(&self) -> Vec<(&str, u16)> {
+        if !self.discovered.is_empty() {
+            return self
+                .discovered
+                .iter()
+                .map(|(host, port)| (host.as_str(), *port))
+                .collect();
+        }
+        let mut hosts = vec![(self.host(), self.port())];
+        hosts.extend(
+            self.failover_hosts
+                .iter()
+                .map(|host| (host.as_str(), self.port())),
+        );
+        hosts
+    }
+
+    fn host_list(&self) -> String {
+        let mut hosts = vec![self.host.as_deref().unwrap_or("localhost")];
+        hosts.extend(self.failover_hosts.iter().map(String::as_str));
+        hosts.join(",")
+    }
+
     pub fn port(&self) -> u16 {
+        if let Some((_, port)) = self.discovered.first() {
+            return *port;
+        }
         if let Some(port) = self.port {
             return port;
         }
@@ -71,6 +109,39 @@ impl BackupRepository {
     pub fn store(&self) -> &str {
         &self.store
     }
+
+    /// Returns whether the servers of this repository were found via service discovery.
+    pub fn is_discovered(&self) -> bool {
+        !self.discovered.is_empty()
+    }
+
+    /// Resolve a `_pbs._tcp.<domain>` host to the discovered servers, ordered by preference.
+    ///
+    /// Does nothing for other repositories. The repository is still displayed with its original
+    /// host, so it can be resolved again later.
+    pub fn resolve(&mut self) -> Result<(), Error> {
+        let name = match &self.host {
+            Some(host) if host.starts_with(SERVICE_PREFIX) => host,
+            _ => return Ok(()),
+        };
+        if !self.discovered.is_empty() {
+            return Ok(());
+        }
+
+        let default_port = self.port.unwrap_or(8007);
+        self.discovered = service_discovery::discover(name)?
+            .into_iter()
+            .map(|candidate| {
+                let host = match candidate.host.parse::<std::net::Ipv6Addr>() {
+                    Ok(_) => format!("[{}]", candidate.host),
+                    Err(_) => candidate.host,
+                };
+                (host, candidate.port.unwrap_or(default_port))
+            })
+            .collect();
+
+        Ok(())
+    }
 }
 
 impl fmt::Display for BackupRepository {
@@ -80,12 +151,12 @@ impl fmt::Display for BackupRepository {
                 f,
                 "{}@{}:{}:{}",
                 auth_id,
-                self.host(),
+                self.host_list(),
                 self.port(),
                 self.store
             ),
-            (None, Some(host), None) => write!(f, "{}:{}", host, self.store),
-            (None, _, Some(port)) => write!(f, "{}:{}:{}", self.host(), port, self.store),
+            (None, Some(_), None) => write!(f, "{}:{}", self.host_list(), self.store),
+            (None, _, Some(port)) => write!(f, "{}:{}:{}", self.host_list(), port, self.store),
             (None, None, None) => write!(f, "{}", self.store),
         }
     }
@@ -98,20 +169,31 @@ impl std::str::FromStr for BackupRepository {
     ///
     /// This parses strings like `user@host:datastore`. The `user` and
     /// `host` parts are optional, where `host` defaults to the local
-    /// host, and `user` defaults to `root@pam`.
+    /// host, and `user` defaults to `root@pam`. `host` can be a comma
+    /// separated list, the further hosts are used as failover for read
+    /// sessions. A `host` of the form `_pbs._tcp.<domain>` is looked up
+    /// via service discovery, see [BackupRepository::resolve].
     fn from_str(url: &str) -> Result<Self, Self::Err> {
         let cap = (BACKUP_REPO_URL_REGEX.regex_obj)()
             .captures(url)
             .ok_or_else(|| format_err!("unable to parse repository url '{}'", url))?;
 
+        let hosts: Vec<String> = match cap.get(2) {
+            Some(m) => m.as_str().split(',').map(String::from).collect(),
+            None => Vec::new(),
+        };
+        let mut hosts = hosts.into_iter();
+
         Ok(Self {
             auth_id: cap
                 .get(1)
                 .map(|m| Authid::try_from(m.as_str().to_owned()))
                 .transpose()?,
-            host: cap.get(2).map(|m| m.as_str().to_owned()),
+            host: hosts.next(),
+            failover_hosts: hosts.collect(),
             port: cap.get(3).map(|m| m.as_str().parse::<u16>()).transpose()?,
             store: cap[4].to_owned(),
+            discovered: Vec::new(),
         })
     }
 }
@@ -3,7 +3,7 @@ use anyhow::{bail, Error};
 use proxmox_schema::*;
 
 const_regex! {
-    BACKUPSPEC_REGEX = r"^([a-zA-Z0-9_-]+\.(pxar|img|conf|log)):(.+)$";
+    BACKUPSPEC_REGEX = r"^([a-zA-Z0-9_-]+\.(pxar|img|conf|log|raw)):(.+)$";
 }
 
 pub const BACKUP_SOURCE_SCHEMA: Schema =
@@ -16,8 +16,13 @@ pub enum BackupSpecificationType {
     IMAGE,
     CONFIG,
     LOGFILE,
+    /// Plain data stream, stored with dynamic chunking
+    RAW,
 }
 
+/// Path of backup sources read from standard input
+pub const BACKUP_SOURCE_STDIN: &str = "-";
+
 pub struct BackupSpecification {
     pub archive_name: String,  // left part
     pub config_string: String, // right part
@@ -34,6 +39,7 @@ pub fn parse_backup_specification(value: &str) -> Result<BackupSpecification, Er
             "img" => BackupSpecificationType::IMAGE,
             "conf" => BackupSpecificationType::CONFIG,
             "log" => BackupSpecificationType::LOGFILE,
+            "raw" => BackupSpecificationType::RAW,
             _ => bail!("unknown backup source type '{}'", extension),
         };
         return Ok(BackupSpecification {
@@ -12,8 +12,8 @@ use tokio::io::AsyncReadExt;
 use tokio::sync::{mpsc, oneshot};
 use tokio_stream::wrappers::ReceiverStream;
 
-use pbs_api_types::{BackupDir, BackupNamespace};
-use pbs_datastore::data_blob::{ChunkInfo, DataBlob, DataChunkBuilder};
+use pbs_api_types::{BackupDir, BackupNamespace, BackupPriority};
+use pbs_datastore::data_blob::{ChunkInfo, DataBlob, DataChunkBuilder, DEFAULT_COMPRESSION_LEVEL};
 use pbs_datastore::dynamic_index::DynamicIndexReader;
 use pbs_datastore::fixed_index::FixedIndexReader;
 use pbs_datastore::index::IndexFile;
@@ -31,6 +31,7 @@ pub struct BackupWriter {
     h2: H2Client,
     abort: AbortHandle,
     crypt_config: Option<Arc<CryptConfig>>,
+    digest_salt: Option<[u8; 32]>,
 }
 
 impl Drop for BackupWriter {
@@ -49,10 +50,19 @@ pub struct BackupStats {
 pub struct UploadOptions {
     pub previous_manifest: Option<Arc<BackupManifest>>,
     pub compress: bool,
+    /// zstd level used if `compress` is set, defaults to [`DEFAULT_COMPRESSION_LEVEL`]
+    pub compression_level: Option<i32>,
     pub encrypt: bool,
     pub fixed_size: Option<u64>,
 }
 
+impl UploadOptions {
+    fn compression_level(&self) -> Option<i32> {
+        self.compress
+            .then(|| self.compression_level.unwrap_or(DEFAULT_COMPRESSION_LEVEL))
+    }
+}
+
 struct UploadStats {
     chunk_count: usize,
     chunk_reused: usize,
@@ -67,11 +77,17 @@ type UploadQueueSender = mpsc::Sender<(MergedChunkInfo, Option<h2::client::Respo
 type UploadResultReceiver = oneshot::Receiver<Result<(), Error>>;
 
 impl BackupWriter {
-    fn new(h2: H2Client, abort: AbortHandle, crypt_config: Option<Arc<CryptConfig>>) -> Arc<Self> {
+    fn new(
+        h2: H2Client,
+        abort: AbortHandle,
+        crypt_config: Option<Arc<CryptConfig>>,
+        digest_salt: Option<[u8; 32]>,
+    ) -> Arc<Self> {
         Arc::new(Self {
             h2,
             abort,
             crypt_config,
+            digest_salt,
         })
     }
 
@@ -85,6 +101,7 @@ impl BackupWriter {
         backup: &BackupDir,
         debug: bool,
         benchmark: bool,
+        priority: Option<BackupPriority>,
     ) -> Result<Arc<BackupWriter>, Error> {
         let mut param = json!({
             "backup-type": backup.ty(),
@@ -98,21 +115,28 @@ impl BackupWriter {
         if !ns.is_root() {
             param["ns"] = serde_json::to_value(ns)?;
         }
-
-        let req = HttpClient::request_builder(
-            client.server(),
-            client.port(),
-            "GET",
-            "/api2/json/backup",
-            Some(param),
-        )
-        .unwrap();
+        if let Some(priority) = priority {
+            param["priority"] = serde_json::to_value(priority)?;
+        }
 
         let (h2, abort) = client
-            .start_h2_connection(req, String::from(PROXMOX_BACKUP_PROTOCOL_ID_V1!()))
+            .start_h2_connection_with_retry(
+                || {
+                    HttpClient::request_builder(
+                        client.server(),
+                        client.port(),
+                        "GET",
+                        "/api2/json/backup",
+                        Some(param.clone()),
+                    )
+                },
+                PROXMOX_BACKUP_PROTOCOL_ID_V1!(),
+            )
             .await?;
 
-        Ok(BackupWriter::new(h2, abort, crypt_config))
+        let digest_salt = h2.digest_salt().await?;
+
+        Ok(BackupWriter::new(h2, abort, crypt_config, digest_salt))
     }
 
     pub async fn get(&self, path: &str, param: Option<Value>) -> Result<Value, Error> {
@@ -213,10 +237,10 @@ impl BackupWriter {
         options: UploadOptions,
     ) -> Result<BackupStats, Error> {
         let blob = match (options.encrypt, &self.crypt_config) {
-            (false, _) => DataBlob::encode(&data, None, options.compress)?,
+            (false, _) => DataBlob::encode_with_level(&data, None, options.compression_level())?,
             (true, None) => bail!("requested encryption without a crypt config"),
             (true, Some(crypt_config)) => {
-                DataBlob::encode(&data, Some(crypt_config), options.compress)?
+                DataBlob::encode_with_level(&data, Some(crypt_config), options.compression_level())?
             }
         };
 
@@ -340,7 +364,8 @@ impl BackupWriter {
             } else {
                 None
             },
-            options.compress,
+            self.digest_salt,
+            options.compression_level(),
         )
         .await?;
 
@@ -635,7 +660,8 @@ impl BackupWriter {
         prefix: &str,
         known_chunks: Arc<Mutex<HashSet<[u8; 32]>>>,
         crypt_config: Option<Arc<CryptConfig>>,
-        compress: bool,
+        digest_salt: Option<[u8; 32]>,
+        compression_level: Option<i32>,
     ) -> impl Future<Output = Result<UploadStats, Error>> {
         let total_chunks = Arc::new(AtomicUsize::new(0));
         let total_chunks2 = total_chunks.clone();
@@ -668,10 +694,16 @@ impl BackupWriter {
                 total_chunks.fetch_add(1, Ordering::SeqCst);
                 let offset = stream_len.fetch_add(chunk_len, Ordering::SeqCst) as u64;
 
-                let mut chunk_builder = DataChunkBuilder::new(data.as_ref()).compress(compress);
+                let mut chunk_builder =
+                    DataChunkBuilder::new(data.as_ref()).compress(compression_level.is_some());
+                if let Some(level) = compression_level {
+                    chunk_builder = chunk_builder.compression_level(level);
+                }
 
                 if let Some(ref crypt_config) = crypt_config {
                     chunk_builder = chunk_builder.crypt_config(crypt_config);
+                } else {
+                    chunk_builder = chunk_builder.digest_salt(digest_salt.as_ref());
                 }
 
                 let mut known_chunks = known_chunks.lock().unwrap();
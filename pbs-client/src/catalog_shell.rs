@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ffi::{CStr, CString, OsStr, OsString};
 use std::future::Future;
 use std::io::Write;
@@ -318,6 +318,9 @@ pub struct Shell {
     /// List of selected paths for restore
     selected: HashMap<OsString, MatchEntry>,
 
+    /// Entries of `selected` added by path with `select`, as opposed to patterns added by `find`
+    selected_paths: HashSet<OsString>,
+
     /// pxar accessor instance for the current pxar archive
     accessor: Accessor,
 
@@ -366,6 +369,7 @@ impl Shell {
             prompt: String::new(),
             catalog,
             selected: HashMap::new(),
+            selected_paths: HashSet::new(),
             accessor: archive,
             position,
         };
@@ -758,6 +762,7 @@ impl Shell {
 
         let path = Self::format_path_stack(&stack);
         let entry = MatchEntry::include(MatchPattern::Literal(path.as_bytes().to_vec()));
+        self.selected_paths.insert(path.clone());
         if self.selected.insert(path.clone(), entry).is_some() {
             println!("path already selected: {:?}", path);
         } else {
@@ -779,6 +784,7 @@ impl Shell {
 
         let path = Self::format_path_stack(&stack);
 
+        self.selected_paths.remove(&path);
         if self.selected.remove(&path).is_some() {
             println!("removed path from selection: {:?}", path);
         } else {
@@ -790,6 +796,7 @@ impl Shell {
 
     async fn deselect_all(&mut self) -> Result<(), Error> {
         self.selected.clear();
+        self.selected_paths.clear();
         println!("cleared selection");
         Ok(())
     }
@@ -855,6 +862,7 @@ impl Shell {
         )?;
 
         if found_some && select {
+            self.selected_paths.remove(&pattern_os);
             self.selected.insert(pattern_os, pattern_entry);
         }
 
@@ -868,7 +876,21 @@ impl Shell {
 
         let match_list = self.build_match_list();
 
-        self.restore_with_match_list(destination, &match_list).await
+        // if only paths are selected, directories not leading to one of them can be skipped
+        // instead of walking through the whole archive
+        let sub_tree = if self.selected_paths.len() == self.selected.len() {
+            Some(
+                self.selected_paths
+                    .iter()
+                    .map(|path| path.as_bytes().to_vec())
+                    .collect(),
+            )
+        } else {
+            None
+        };
+
+        self.restore_with_match_list(destination, &match_list, sub_tree)
+            .await
     }
 
     async fn restore(
@@ -889,13 +911,18 @@ impl Shell {
             }
         };
 
-        self.restore_with_match_list(destination, match_list).await
+        self.restore_with_match_list(destination, match_list, None)
+            .await
     }
 
+    /// Restore the entries matching `match_list` in a single pass over the catalog, which is in
+    /// archive order, so the archive is read front to back. With `selected_paths` set, only the
+    /// directories leading to or contained in one of these paths are visited.
     async fn restore_with_match_list(
         &mut self,
         destination: PathBuf,
         match_list: &[MatchEntry],
+        selected_paths: Option<Vec<Vec<u8>>>,
     ) -> Result<(), Error> {
         create_path(
             &destination,
@@ -938,6 +965,7 @@ impl Shell {
             dir_stack,
             extractor,
             match_list,
+            selected_paths,
             &self.accessor,
         )?;
 
@@ -962,6 +990,7 @@ struct ExtractorState<'a> {
 
     catalog: &'a mut CatalogReader,
     match_list: &'a [MatchEntry],
+    selected_paths: Option<Vec<Vec<u8>>>,
     accessor: &'a Accessor,
 }
 
@@ -971,6 +1000,7 @@ impl<'a> ExtractorState<'a> {
         dir_stack: Vec<PathStackEntry>,
         extractor: crate::pxar::extract::Extractor,
         match_list: &'a [MatchEntry],
+        selected_paths: Option<Vec<Vec<u8>>>,
         accessor: &'a Accessor,
     ) -> Result<Self, Error> {
         let read_dir = catalog
@@ -993,10 +1023,30 @@ impl<'a> ExtractorState<'a> {
 
             catalog,
             match_list,
+            selected_paths,
             accessor,
         })
     }
 
+    /// Check whether the directory at the current path has to be visited, which is always the
+    /// case unless only paths were selected.
+    fn visit_directory(&self) -> bool {
+        let selected_paths = match &self.selected_paths {
+            Some(selected_paths) => selected_paths,
+            None => return true,
+        };
+
+        let is_below = |parent: &[u8], path: &[u8]| {
+            path.len() > parent.len() && path.starts_with(parent) && path[parent.len()] == b'/'
+        };
+
+        selected_paths.iter().any(|selected| {
+            *selected == self.path
+                || is_below(&self.path, selected)
+                || is_below(selected, &self.path)
+        })
+    }
+
     pub async fn extract(&mut self) -> Result<(), Error> {
         loop {
             let entry = match self.read_dir.next() {
@@ -1083,7 +1133,9 @@ impl<'a> ExtractorState<'a> {
 
         match (did_match, &entry.attr) {
             (_, DirEntryAttribute::Directory { .. }) => {
-                self.handle_new_directory(entry, match_result?).await?;
+                if did_match || self.visit_directory() {
+                    self.handle_new_directory(entry, match_result?).await?;
+                }
             }
             (true, DirEntryAttribute::File { .. }) => {
                 self.dir_stack.push(PathStackEntry::new(entry));
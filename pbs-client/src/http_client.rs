@@ -1,9 +1,11 @@
 use std::io::{IsTerminal, Write};
+use std::net::IpAddr;
 use std::sync::{Arc, Mutex, RwLock};
 use std::time::Duration;
 
 use anyhow::{bail, format_err, Error};
 use futures::*;
+use hex::FromHex;
 use http::header::HeaderValue;
 use http::Uri;
 use http::{Request, Response};
@@ -36,6 +38,26 @@ use super::PROXMOX_BACKUP_TCP_KEEPALIVE_TIME;
 /// certain error conditions. Keep it generous, to avoid false-positive under high load.
 const HTTP_TIMEOUT: Duration = Duration::from_secs(2 * 60);
 
+/// Upper limit for the total time spent waiting on a busy server when starting a session.
+const MAX_BUSY_WAIT: Duration = Duration::from_secs(60 * 60);
+
+/// The server refused a session for now and asked to retry after some time.
+#[derive(Debug)]
+pub struct ServerBusyError {
+    /// Time the server asked us to wait before retrying.
+    pub retry_after: Duration,
+    /// Message sent by the server.
+    pub message: String,
+}
+
+impl std::fmt::Display for ServerBusyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ServerBusyError {}
+
 #[derive(Clone)]
 pub struct AuthInfo {
     pub auth_id: Authid,
@@ -294,6 +316,126 @@ fn load_ticket_info(prefix: &str, server: &str, userid: &Userid) -> Option<(Stri
     }
 }
 
+/// Proxy settings from `~/.config/<prefix>/proxy`
+#[derive(Default)]
+struct ProxySettings {
+    proxy: Option<String>,
+    no_proxy: Option<String>,
+}
+
+fn load_proxy_settings(prefix: &str) -> Result<ProxySettings, Error> {
+    let base = BaseDirectories::with_prefix(prefix)?;
+
+    // usually ~/.config/<prefix>/proxy
+    let path = match base.find_config_file("proxy") {
+        Some(path) => path,
+        None => return Ok(ProxySettings::default()),
+    };
+
+    let raw = std::fs::read_to_string(&path)
+        .map_err(|err| format_err!("unable to read proxy settings from {path:?} - {err}"))?;
+
+    let mut settings = ProxySettings::default();
+    for line in raw.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.split_once(char::is_whitespace) {
+            Some(("proxy", value)) => settings.proxy = Some(value.trim().to_string()),
+            Some(("no-proxy", value)) => settings.no_proxy = Some(value.trim().to_string()),
+            _ => bail!("unable to parse proxy settings {path:?} - invalid line '{line}'"),
+        }
+    }
+
+    Ok(settings)
+}
+
+fn first_env_var(names: &[&str]) -> Option<String> {
+    names
+        .iter()
+        .find_map(|name| std::env::var(name).ok().filter(|value| !value.is_empty()))
+}
+
+fn ip_in_network(ip: IpAddr, network: IpAddr, prefix_len: u32) -> bool {
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) if prefix_len <= 32 => {
+            let mask = u32::MAX.checked_shl(32 - prefix_len).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) if prefix_len <= 128 => {
+            let mask = u128::MAX.checked_shl(128 - prefix_len).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+/// Check if `server` matches a comma separated `no_proxy` list.
+///
+/// Entries are `*`, domain names matching the domain and its subdomains (optionally prefixed
+/// with `.` or `*.`), IP addresses and networks in CIDR notation.
+fn no_proxy_match(no_proxy: &str, server: &str) -> bool {
+    let host = server
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_ascii_lowercase();
+    let ip: Option<IpAddr> = host.parse().ok();
+
+    for entry in no_proxy.split(',') {
+        let entry = entry.trim().to_ascii_lowercase();
+        if entry.is_empty() {
+            continue;
+        }
+        if entry == "*" {
+            return true;
+        }
+
+        if let Some((network, prefix_len)) = entry.split_once('/') {
+            if let (Some(ip), Ok(network), Ok(prefix_len)) =
+                (ip, network.parse(), prefix_len.parse())
+            {
+                if ip_in_network(ip, network, prefix_len) {
+                    return true;
+                }
+            }
+            continue;
+        }
+
+        let domain = entry.trim_start_matches("*.").trim_start_matches('.');
+        if host == domain || host.ends_with(&format!(".{domain}")) {
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Returns the proxy to use for connections to `server`.
+///
+/// The `HTTPS_PROXY`, `ALL_PROXY` and `NO_PROXY` environment variables (also in lower case)
+/// take precedence over the settings in `~/.config/<prefix>/proxy`.
+fn lookup_proxy_config(server: &str, prefix: Option<&str>) -> Result<Option<ProxyConfig>, Error> {
+    let settings = match prefix {
+        Some(prefix) => load_proxy_settings(prefix)?,
+        None => ProxySettings::default(),
+    };
+
+    let no_proxy = first_env_var(&["NO_PROXY", "no_proxy"]).or(settings.no_proxy);
+    if let Some(no_proxy) = no_proxy {
+        if no_proxy_match(&no_proxy, server) {
+            return Ok(None);
+        }
+    }
+
+    let proxy =
+        first_env_var(&["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"]).or(settings.proxy);
+    match proxy {
+        Some(proxy) => Ok(Some(ProxyConfig::parse_proxy_url(&proxy)?)),
+        None => Ok(None),
+    }
+}
+
 fn build_uri(server: &str, port: u16, path: &str, query: Option<String>) -> Result<Uri, Error> {
     Uri::builder()
         .scheme("https")
@@ -391,7 +533,9 @@ impl HttpClient {
             )))));
         }
 
-        let proxy_config = ProxyConfig::from_proxy_env()?;
+        // backup and reader sessions are upgraded from a request over the same connector, so the
+        // proxy is used for them as well, via a CONNECT tunnel
+        let proxy_config = lookup_proxy_config(server, options.prefix.as_deref())?;
         if let Some(config) = proxy_config {
             log::info!("Using proxy connection: {}:{}", config.host, config.port);
             https.set_proxy(config);
@@ -633,7 +777,15 @@ impl HttpClient {
         bail!("Certificate fingerprint was not confirmed.");
     }
 
-    pub async fn request(&self, mut req: Request<Body>) -> Result<Value, Error> {
+    pub async fn request(&self, req: Request<Body>) -> Result<Value, Error> {
+        self.request_with_timeout(req, Some(HTTP_TIMEOUT)).await
+    }
+
+    async fn request_with_timeout(
+        &self,
+        mut req: Request<Body>,
+        timeout: Option<Duration>,
+    ) -> Result<Value, Error> {
         let client = self.client.clone();
 
         let auth = self.login().await?;
@@ -660,7 +812,7 @@ impl HttpClient {
             );
         }
 
-        Self::api_request(client, req).await
+        Self::api_request(client, req, timeout).await
     }
 
     pub async fn get(&self, path: &str, data: Option<Value>) -> Result<Value, Error> {
@@ -724,6 +876,30 @@ impl HttpClient {
         path: &str,
         data: Option<Value>,
     ) -> Result<Value, Error> {
+        let req = self.upload_request(content_type, body, path, data)?;
+        self.request(req).await
+    }
+
+    /// Like `upload`, but without a timeout, for large streamed uploads the server only responds
+    /// to once it processed all of the data.
+    pub async fn upload_stream(
+        &self,
+        content_type: &str,
+        body: Body,
+        path: &str,
+        data: Option<Value>,
+    ) -> Result<Value, Error> {
+        let req = self.upload_request(content_type, body, path, data)?;
+        self.request_with_timeout(req, None).await
+    }
+
+    fn upload_request(
+        &self,
+        content_type: &str,
+        body: Body,
+        path: &str,
+        data: Option<Value>,
+    ) -> Result<Request<Body>, Error> {
         let query = match data {
             Some(data) => Some(json_object_to_query(data)?),
             None => None,
@@ -738,7 +914,7 @@ impl HttpClient {
             .body(body)
             .unwrap();
 
-        self.request(req).await
+        Ok(req)
     }
 
     pub async fn start_h2_connection(
@@ -782,11 +958,38 @@ impl HttpClient {
             .map_err(|_| format_err!("http upgrade request timed out"))??;
         let status = resp.status();
 
+        if status == http::StatusCode::SERVICE_UNAVAILABLE {
+            let retry_after = resp
+                .headers()
+                .get(http::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<u64>().ok());
+            if let Some(retry_after) = retry_after {
+                let data = hyper::body::to_bytes(resp.into_body()).await?;
+                return Err(ServerBusyError {
+                    retry_after: Duration::from_secs(retry_after),
+                    message: String::from_utf8_lossy(&data).into_owned(),
+                }
+                .into());
+            }
+        }
+
         if status != http::StatusCode::SWITCHING_PROTOCOLS {
             Self::api_response(resp).await?;
             bail!("unknown error");
         }
 
+        let headers = resp.headers();
+        if let Some(days) = headers
+            .get("proxmox-backup-min-retention-days")
+            .and_then(|value| value.to_str().ok())
+        {
+            log::info!("server enforces a minimum retention of {days} days for this snapshot");
+        }
+        if headers.contains_key("proxmox-backup-protected") {
+            log::info!("server marks this snapshot as protected");
+        }
+
         let upgraded = hyper::upgrade::on(resp).await?;
 
         let max_window_size = (1 << 31) - 2;
@@ -814,6 +1017,50 @@ impl HttpClient {
         Ok((H2Client::new(c), abort))
     }
 
+    /// Like [`start_h2_connection`](Self::start_h2_connection), but politely retries while the
+    /// server reports to be busy.
+    ///
+    /// The request is rebuilt via `build_request` for every attempt. The delay assigned by the
+    /// server is extended with a random jitter, so that clients refused at the same time do not
+    /// all come back at once.
+    pub async fn start_h2_connection_with_retry<F>(
+        &self,
+        build_request: F,
+        protocol_name: &str,
+    ) -> Result<(H2Client, futures::future::AbortHandle), Error>
+    where
+        F: Fn() -> Result<Request<Body>, Error>,
+    {
+        let mut waited = Duration::ZERO;
+        loop {
+            let req = build_request()?;
+            let err = match self
+                .start_h2_connection(req, protocol_name.to_string())
+                .await
+            {
+                Ok(res) => return Ok(res),
+                Err(err) => err,
+            };
+
+            let retry_after = match err.downcast_ref::<ServerBusyError>() {
+                Some(busy) => busy.retry_after,
+                None => return Err(err),
+            };
+
+            let mut rand = [0u8; 1];
+            openssl::rand::rand_bytes(&mut rand)?;
+            let delay = retry_after + retry_after * rand[0] as u32 / 1024;
+
+            if waited + delay > MAX_BUSY_WAIT {
+                return Err(err);
+            }
+
+            log::info!("{err} - retrying in {}s", delay.as_secs());
+            tokio::time::sleep(delay).await;
+            waited += delay;
+        }
+    }
+
     async fn credentials(
         client: Client<HttpsConnector>,
         server: String,
@@ -829,7 +1076,7 @@ impl HttpClient {
             "/api2/json/access/ticket",
             Some(data),
         )?;
-        let cred = Self::api_request(client, req).await?;
+        let cred = Self::api_request(client, req, Some(HTTP_TIMEOUT)).await?;
         let auth = AuthInfo {
             auth_id: cred["data"]["username"].as_str().unwrap().parse()?,
             ticket: cred["data"]["ticket"].as_str().unwrap().to_owned(),
@@ -862,13 +1109,15 @@ impl HttpClient {
     async fn api_request(
         client: Client<HttpsConnector>,
         req: Request<Body>,
+        timeout: Option<Duration>,
     ) -> Result<Value, Error> {
-        Self::api_response(
-            tokio::time::timeout(HTTP_TIMEOUT, client.request(req))
+        let response = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, client.request(req))
                 .await
                 .map_err(|_| format_err!("http request timed out"))??,
-        )
-        .await
+            None => client.request(req).await?,
+        };
+        Self::api_response(response).await
     }
 
     // Read-only access to server property
@@ -949,6 +1198,27 @@ impl H2Client {
         self.request(req).await
     }
 
+    /// Query the digest salt of the datastore of a backup or reader session.
+    ///
+    /// Servers which do not support digest salts yet never use one.
+    pub async fn digest_salt(&self) -> Result<Option<[u8; 32]>, Error> {
+        let data = match self.get("digest_salt", None).await {
+            Ok(data) => data,
+            Err(err) => match err.downcast_ref::<HttpError>() {
+                Some(HttpError { code, .. }) if *code == http::StatusCode::NOT_FOUND => {
+                    return Ok(None)
+                }
+                _ => return Err(err),
+            },
+        };
+        let salt: Option<String> = serde_json::from_value(data)?;
+        salt.map(|salt| {
+            <[u8; 32]>::from_hex(salt)
+                .map_err(|err| format_err!("invalid digest salt returned by server - {err}"))
+        })
+        .transpose()
+    }
+
     pub async fn put(&self, path: &str, param: Option<Value>) -> Result<Value, Error> {
         let req = Self::request_builder("localhost", "PUT", path, param, None).unwrap();
         self.request(req).await
@@ -1107,3 +1377,30 @@ impl H2Client {
         Ok(request)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::no_proxy_match;
+
+    #[test]
+    fn test_no_proxy_match() {
+        let no_proxy = "localhost, .example.com,*.lan,10.0.0.0/8,fd00::/8,192.168.1.5";
+
+        assert!(no_proxy_match(no_proxy, "localhost"));
+        assert!(no_proxy_match(no_proxy, "example.com"));
+        assert!(no_proxy_match(no_proxy, "pbs.EXAMPLE.com"));
+        assert!(no_proxy_match(no_proxy, "pbs.lan"));
+        assert!(no_proxy_match(no_proxy, "10.1.2.3"));
+        assert!(no_proxy_match(no_proxy, "[fd00::1]"));
+        assert!(no_proxy_match(no_proxy, "192.168.1.5"));
+
+        assert!(!no_proxy_match(no_proxy, "notexample.com"));
+        assert!(!no_proxy_match(no_proxy, "pbs.example.org"));
+        assert!(!no_proxy_match(no_proxy, "11.1.2.3"));
+        assert!(!no_proxy_match(no_proxy, "192.168.1.6"));
+        assert!(!no_proxy_match(no_proxy, "[fe80::1]"));
+
+        assert!(no_proxy_match("*", "anything"));
+        assert!(!no_proxy_match("", "anything"));
+    }
+}
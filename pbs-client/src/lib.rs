@@ -9,6 +9,7 @@ pub mod tools;
 
 mod merge_known_chunks;
 pub mod pipe_to_stream;
+pub mod retry;
 
 mod http_client;
 pub use http_client::*;
@@ -34,6 +35,8 @@ pub use pxar_backup_stream::*;
 mod backup_repo;
 pub use backup_repo::*;
 
+mod service_discovery;
+
 mod backup_specification;
 pub use backup_specification::*;
 
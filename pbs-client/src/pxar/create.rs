@@ -1,4 +1,4 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::ffi::{CStr, CString, OsStr};
 use std::fmt;
 use std::io::{self, Read};
@@ -19,6 +19,7 @@ use pathpatterns::{MatchEntry, MatchFlag, MatchList, MatchType, PatternFlag};
 use proxmox_sys::error::SysError;
 use pxar::encoder::{LinkOffset, SeqWrite};
 use pxar::Metadata;
+use serde::Serialize;
 
 use proxmox_io::vec;
 use proxmox_lang::c_str;
@@ -27,7 +28,7 @@ use proxmox_sys::fs::{self, acl, xattr};
 use pbs_datastore::catalog::BackupCatalogWriter;
 
 use crate::pxar::metadata::errno_is_unsupported;
-use crate::pxar::tools::assert_single_path_component;
+use crate::pxar::tools::{assert_single_path_component, XattrFilter};
 use crate::pxar::Flags;
 
 /// Pxar options for creating a pxar archive/stream
@@ -41,8 +42,86 @@ pub struct PxarCreateOptions {
     pub entries_max: usize,
     /// Skip lost+found directory
     pub skip_lost_and_found: bool,
+    /// Skip entries with the `nodump` file attribute (`chattr +d`)
+    pub skip_nodump: bool,
     /// Skip xattrs of files that return E2BIG error
     pub skip_e2big_xattr: bool,
+    /// Extended attributes not to store in the archive
+    pub xattr_exclude: XattrFilter,
+    /// Skip the contents of directories tagged with a `CACHEDIR.TAG` file
+    pub skip_cache_dirs: bool,
+    /// Skip well-known cache locations, see [KNOWN_CACHE_PATTERNS]
+    pub skip_known_caches: bool,
+    /// Statistics about the archived entries, filled in once the archive is complete
+    pub stats: Option<Arc<Mutex<PxarCreateStats>>>,
+}
+
+/// Statistics about the contents of a created pxar archive
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PxarCreateStats {
+    /// Number of entries stored as hardlink to a previous entry
+    pub hardlinks: u64,
+    /// Bytes of regular files not allocated on disk (holes of sparse files)
+    pub sparse_bytes: u64,
+    /// Number of entries skipped by exclusion patterns or their `nodump` attribute
+    pub excluded: u64,
+    /// Number of cache directories whose contents were skipped
+    pub cache_dirs: u64,
+    /// Number of files skipped in well-known cache locations
+    pub cache_files: u64,
+    /// Size of the regular files below each top-level directory, files directly in the archive
+    /// root are accounted to "."
+    pub top_level_sizes: BTreeMap<String, u64>,
+}
+
+impl PxarCreateStats {
+    fn add_file(&mut self, path: &Path, size: u64) {
+        let mut components = path.components();
+        let top_level = match (components.next(), components.next()) {
+            (Some(top_level), Some(_)) => top_level.as_os_str().to_string_lossy().into_owned(),
+            _ => ".".to_string(),
+        };
+        *self.top_level_sizes.entry(top_level).or_default() += size;
+    }
+}
+
+/// Cache locations skipped with [PxarCreateOptions::skip_known_caches], relative to the root of
+/// the file system, also when archiving a directory below it. They only hold data their
+/// applications download or generate again when missing.
+pub const KNOWN_CACHE_PATTERNS: &[&str] = &[
+    "/root/.cache",
+    "/home/*/.cache",
+    "/var/cache/apt/archives/*.deb",
+    "/var/cache/apt/*.bin",
+    "/var/cache/man",
+    "/var/lib/apt/lists",
+];
+
+/// The start of a `CACHEDIR.TAG` file, see <https://bford.info/cachedir/>
+const CACHEDIR_TAG_SIGNATURE: &[u8] = b"Signature: 8a477f597d28d172789f06886806bc55";
+
+fn known_cache_patterns() -> Result<Vec<MatchEntry>, Error> {
+    KNOWN_CACHE_PATTERNS
+        .iter()
+        .map(|pattern| {
+            Ok(MatchEntry::parse_pattern(
+                *pattern,
+                PatternFlag::PATH_NAME,
+                MatchType::Exclude,
+            )?)
+        })
+        .collect()
+}
+
+/// Check whether the contents of a `CACHEDIR.TAG` file start with the signature of the standard.
+fn has_cachedir_signature<R: Read>(mut reader: R) -> io::Result<bool> {
+    let mut signature = [0u8; CACHEDIR_TAG_SIGNATURE.len()];
+    match reader.read_exact(&mut signature) {
+        Ok(()) => Ok(signature == CACHEDIR_TAG_SIGNATURE),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(err) => Err(err),
+    }
 }
 
 fn detect_fs_type(fd: RawFd) -> Result<i64, Error> {
@@ -131,6 +210,12 @@ struct Archiver {
     hardlinks: HashMap<HardLinkInfo, (PathBuf, LinkOffset)>,
     file_copy_buffer: Vec<u8>,
     skip_e2big_xattr: bool,
+    xattr_exclude: XattrFilter,
+    skip_cache_dirs: bool,
+    cache_patterns: Vec<MatchEntry>,
+    /// Absolute path of the archive root, the known cache locations are matched against
+    root_path: PathBuf,
+    stats: PxarCreateStats,
 }
 
 type Encoder<'a, T> = pxar::encoder::aio::Encoder<'a, T>;
@@ -162,6 +247,7 @@ where
         fs_magic,
         &mut fs_feature_flags,
         options.skip_e2big_xattr,
+        &options.xattr_exclude,
     )
     .context("failed to get metadata for source directory")?;
 
@@ -174,6 +260,11 @@ where
 
     let mut patterns = options.patterns;
 
+    let mut feature_flags = feature_flags;
+    if options.skip_nodump {
+        feature_flags.insert(Flags::EXCLUDE_NODUMP);
+    }
+
     if options.skip_lost_and_found {
         patterns.push(MatchEntry::parse_pattern(
             "lost+found",
@@ -182,6 +273,20 @@ where
         )?);
     }
 
+    let mut cache_patterns = Vec::new();
+    let mut root_path = PathBuf::from("/");
+    if options.skip_known_caches {
+        match std::fs::read_link(format!("/proc/self/fd/{}", source_dir.as_raw_fd())) {
+            Ok(path) => {
+                root_path = path;
+                cache_patterns = known_cache_patterns()?;
+            }
+            Err(err) => {
+                log::warn!("not skipping known cache locations, unknown source path - {err}")
+            }
+        }
+    }
+
     let mut archiver = Archiver {
         feature_flags,
         fs_feature_flags,
@@ -197,12 +302,22 @@ where
         hardlinks: HashMap::new(),
         file_copy_buffer: vec::undefined(4 * 1024 * 1024),
         skip_e2big_xattr: options.skip_e2big_xattr,
+        xattr_exclude: options.xattr_exclude,
+        skip_cache_dirs: options.skip_cache_dirs,
+        cache_patterns,
+        root_path,
+        stats: PxarCreateStats::default(),
     };
 
     archiver
         .archive_dir_contents(&mut encoder, source_dir, true)
         .await?;
     encoder.finish().await?;
+
+    if let Some(stats) = options.stats {
+        *stats.lock().unwrap() = archiver.stats;
+    }
+
     Ok(())
 }
 
@@ -320,6 +435,48 @@ impl Archiver {
         }
     }
 
+    /// Check for a `CACHEDIR.TAG` file starting with the signature of the standard.
+    fn is_cache_dir(&mut self, dir_fd: RawFd) -> Result<bool, Error> {
+        let fd = match self.open_file(dir_fd, c_str!("CACHEDIR.TAG"), OFlag::O_RDONLY, false)? {
+            Some(fd) => fd,
+            None => return Ok(false),
+        };
+
+        match has_cachedir_signature(std::fs::File::from(fd)) {
+            Ok(is_cache_dir) => Ok(is_cache_dir),
+            Err(err) => {
+                log::warn!("failed to read CACHEDIR.TAG in {:?}: {}", self.path, err);
+                Ok(false)
+            }
+        }
+    }
+
+    /// Archive only the `CACHEDIR.TAG` file of a cache directory.
+    fn add_cachedir_tag<'a, T: SeqWrite + Send>(
+        &'a mut self,
+        encoder: &'a mut Encoder<'_, T>,
+        dir_fd: RawFd,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        async move {
+            let file_name = c_str!("CACHEDIR.TAG");
+            let stat = match nix::sys::stat::fstatat(
+                dir_fd,
+                file_name,
+                nix::fcntl::AtFlags::AT_SYMLINK_NOFOLLOW,
+            ) {
+                Ok(stat) => stat,
+                Err(Errno::ENOENT) => return Ok(()),
+                Err(err) => return Err(err.into()),
+            };
+
+            let old_path = std::mem::replace(&mut self.path, self.path.join("CACHEDIR.TAG"));
+            let result = self.add_entry(encoder, dir_fd, file_name, &stat).await;
+            self.path = old_path;
+            result
+        }
+        .boxed()
+    }
+
     fn read_pxar_excludes(&mut self, parent: RawFd) -> Result<(), Error> {
         let fd = match self.open_file(parent, c_str!(".pxarexclude"), OFlag::O_RDONLY, false)? {
             Some(fd) => fd,
@@ -457,7 +614,10 @@ impl Archiver {
                 });
 
             match match_result {
-                Ok(Some(MatchType::Exclude)) => continue,
+                Ok(Some(MatchType::Exclude)) => {
+                    self.stats.excluded += 1;
+                    continue;
+                }
                 Ok(_) => (),
                 Err(err) if err.not_found() => continue,
                 Err(err) => {
@@ -545,6 +705,22 @@ impl Archiver {
             .matches(match_path.as_os_str().as_bytes(), stat.st_mode)?
             == Some(MatchType::Exclude)
         {
+            self.stats.excluded += 1;
+            return Ok(());
+        }
+
+        if !self.cache_patterns.is_empty()
+            && self.cache_patterns.matches(
+                self.root_path.join(&self.path).as_os_str().as_bytes(),
+                stat.st_mode,
+            )? == Some(MatchType::Exclude)
+        {
+            log::info!("skipping known cache location: {:?}", self.path);
+            if (stat.st_mode & libc::S_IFMT) == libc::S_IFDIR {
+                self.stats.cache_dirs += 1;
+            } else {
+                self.stats.cache_files += 1;
+            }
             return Ok(());
         }
 
@@ -555,8 +731,17 @@ impl Archiver {
             self.fs_magic,
             &mut self.fs_feature_flags,
             self.skip_e2big_xattr,
+            &self.xattr_exclude,
         )?;
 
+        if self.feature_flags.contains(Flags::EXCLUDE_NODUMP)
+            && metadata.stat.flags & Flags::WITH_FLAG_NODUMP.bits() != 0
+        {
+            log::debug!("skipping entry with nodump attribute: {:?}", self.path);
+            self.stats.excluded += 1;
+            return Ok(());
+        }
+
         let file_name: &Path = OsStr::from_bytes(c_file_name.to_bytes()).as_ref();
         match metadata.file_type() {
             mode::IFREG => {
@@ -572,12 +757,16 @@ impl Archiver {
                         }
 
                         encoder.add_hardlink(file_name, path, *offset).await?;
+                        self.stats.hardlinks += 1;
 
                         return Ok(());
                     }
                 }
 
                 let file_size = stat.st_size as u64;
+                self.stats.add_file(&self.path, file_size);
+                self.stats.sparse_bytes += file_size.saturating_sub(stat.st_blocks as u64 * 512);
+
                 if let Some(ref catalog) = self.catalog {
                     catalog
                         .lock()
@@ -685,6 +874,11 @@ impl Archiver {
         let result = if skip_contents {
             log::info!("skipping mount point: {:?}", self.path);
             Ok(())
+        } else if self.skip_cache_dirs && self.is_cache_dir(dir.as_raw_fd())? {
+            // like tar's --exclude-caches, the tag itself is kept for restores to stay tagged
+            log::info!("skipping cache directory: {:?}", self.path);
+            self.stats.cache_dirs += 1;
+            self.add_cachedir_tag(&mut encoder, dir.as_raw_fd()).await
         } else {
             self.archive_dir_contents(&mut encoder, dir, false).await
         };
@@ -772,6 +966,7 @@ fn get_metadata(
     fs_magic: i64,
     fs_feature_flags: &mut Flags,
     skip_e2big_xattr: bool,
+    xattr_exclude: &XattrFilter,
 ) -> Result<Metadata, Error> {
     // required for some of these
     let proc_path = Path::new("/proc/self/fd/").join(fd.to_string());
@@ -794,6 +989,7 @@ fn get_metadata(
         flags,
         fs_feature_flags,
         skip_e2big_xattr,
+        xattr_exclude,
     )?;
     get_chattr(&mut meta, fd)?;
     get_fat_attr(&mut meta, fd, fs_magic)?;
@@ -833,6 +1029,7 @@ fn get_xattr_fcaps_acl(
     flags: Flags,
     fs_feature_flags: &mut Flags,
     skip_e2big_xattr: bool,
+    xattr_exclude: &XattrFilter,
 ) -> Result<(), Error> {
     if !flags.contains(Flags::WITH_XATTRS) {
         return Ok(());
@@ -857,13 +1054,17 @@ fn get_xattr_fcaps_acl(
     };
 
     for attr in &xattrs {
+        if xattr_exclude.matches(attr.to_bytes()) {
+            continue;
+        }
+
         if xattr::is_security_capability(attr) {
             get_fcaps(meta, fd, flags, fs_feature_flags)?;
             continue;
         }
 
         if xattr::is_acl(attr) {
-            get_acl(meta, proc_path, flags, fs_feature_flags)?;
+            get_acl(meta, proc_path, flags, fs_feature_flags, xattr_exclude)?;
             continue;
         }
 
@@ -980,6 +1181,7 @@ fn get_acl(
     proc_path: &Path,
     flags: Flags,
     fs_feature_flags: &mut Flags,
+    xattr_exclude: &XattrFilter,
 ) -> Result<(), Error> {
     if !flags.contains(Flags::WITH_ACL) {
         return Ok(());
@@ -989,9 +1191,11 @@ fn get_acl(
         return Ok(());
     }
 
-    get_acl_do(metadata, proc_path, acl::ACL_TYPE_ACCESS, fs_feature_flags)?;
+    if !xattr_exclude.matches(b"system.posix_acl_access") {
+        get_acl_do(metadata, proc_path, acl::ACL_TYPE_ACCESS, fs_feature_flags)?;
+    }
 
-    if metadata.is_dir() {
+    if metadata.is_dir() && !xattr_exclude.matches(b"system.posix_acl_default") {
         get_acl_do(metadata, proc_path, acl::ACL_TYPE_DEFAULT, fs_feature_flags)?;
     }
 
@@ -1136,3 +1340,55 @@ fn generate_pxar_excludes_cli(patterns: &[MatchEntry]) -> Vec<u8> {
 
     content
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cachedir_signature() {
+        let tag = b"Signature: 8a477f597d28d172789f06886806bc55\n# created by some application\n";
+        assert!(has_cachedir_signature(&tag[..]).unwrap());
+        assert!(has_cachedir_signature(CACHEDIR_TAG_SIGNATURE).unwrap());
+
+        assert!(!has_cachedir_signature(&b""[..]).unwrap());
+        assert!(!has_cachedir_signature(&b"Signature: 8a477f59"[..]).unwrap());
+        assert!(
+            !has_cachedir_signature(&b"signature: 8a477f597d28d172789f06886806bc55"[..]).unwrap()
+        );
+        assert!(
+            !has_cachedir_signature(&b" Signature: 8a477f597d28d172789f06886806bc55"[..]).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_known_cache_patterns() -> Result<(), Error> {
+        let patterns = known_cache_patterns()?;
+        let is_cache = |root: &str, path: &str, mode: u32| -> Result<bool, Error> {
+            let path = Path::new(root).join(path);
+            Ok(patterns.matches(path.as_os_str().as_bytes(), mode)? == Some(MatchType::Exclude))
+        };
+
+        assert!(is_cache("/", "root/.cache", libc::S_IFDIR)?);
+        assert!(is_cache("/", "home/user/.cache", libc::S_IFDIR)?);
+        assert!(is_cache(
+            "/",
+            "var/cache/apt/archives/foo_1.0_amd64.deb",
+            libc::S_IFREG
+        )?);
+
+        // locations are relative to the file system root, not to the archive root
+        assert!(is_cache("/home", "user/.cache", libc::S_IFDIR)?);
+        assert!(is_cache("/var/cache", "apt/pkgcache.bin", libc::S_IFREG)?);
+        assert!(!is_cache("/srv/data", "root/.cache", libc::S_IFDIR)?);
+        assert!(!is_cache("/srv/data", "home/user/.cache", libc::S_IFDIR)?);
+
+        assert!(!is_cache("/", "home/user/.config", libc::S_IFDIR)?);
+        assert!(!is_cache(
+            "/",
+            "var/cache/apt/archives/partial",
+            libc::S_IFDIR
+        )?);
+        Ok(())
+    }
+}
@@ -1,9 +1,9 @@
 //! Code for extraction of pxar contents onto the file system.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ffi::{CStr, CString, OsStr, OsString};
-use std::io;
-use std::os::unix::ffi::OsStrExt;
+use std::io::{self, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
@@ -27,7 +27,7 @@ use proxmox_sys::fs::{create_path, CreateOptions};
 use proxmox_compression::zip::{ZipEncoder, ZipEntry};
 
 use crate::pxar::dir_stack::PxarDirStack;
-use crate::pxar::metadata;
+use crate::pxar::metadata::{self, AclPolicy, AclReport, MetadataMapping};
 use crate::pxar::Flags;
 
 pub struct PxarExtractOptions<'a> {
@@ -36,6 +36,88 @@ pub struct PxarExtractOptions<'a> {
     pub allow_existing_dirs: bool,
     pub overwrite_flags: OverwriteFlags,
     pub on_error: Option<ErrorHandler>,
+    /// Record extracted entries, and skip the entries already recorded
+    pub journal: Option<&'a mut ExtractJournal>,
+    /// Changes to the ownership and permissions of the extracted entries
+    pub metadata_mapping: MetadataMapping,
+    /// Handling of ACLs that cannot be restored
+    pub acl_policy: AclPolicy,
+    /// The entries whose ACLs were degraded, filled in during the extraction
+    pub acl_report: Option<Arc<Mutex<AclReport>>>,
+}
+
+/// Journal of the entries written by an extraction, so that an interrupted extraction can be
+/// resumed without extracting them again.
+///
+/// The journal starts with an identifier of the extracted archive, followed by the paths of the
+/// extracted non-directory entries, each terminated by a NUL byte.
+pub struct ExtractJournal {
+    file: std::fs::File,
+    done: HashSet<OsString>,
+}
+
+impl ExtractJournal {
+    /// Create a new journal for the archive identified by `id`, replacing an existing one.
+    pub fn create(path: &Path, id: &str) -> Result<Self, Error> {
+        let mut file = std::fs::File::create(path)
+            .with_context(|| format!("unable to create restore journal {path:?}"))?;
+        file.write_all(id.as_bytes())?;
+        file.write_all(b"\0")?;
+
+        Ok(Self {
+            file,
+            done: HashSet::new(),
+        })
+    }
+
+    /// Open the journal of an interrupted extraction of the archive identified by `id`.
+    pub fn resume(path: &Path, id: &str) -> Result<Self, Error> {
+        let data = std::fs::read(path)
+            .with_context(|| format!("unable to read restore journal {path:?}"))?;
+
+        // drop a partially written last entry
+        let len = data.iter().rposition(|b| *b == 0).map_or(0, |pos| pos + 1);
+        let mut entries = data[..len].split(|b| *b == 0);
+
+        if entries.next() != Some(id.as_bytes()) {
+            bail!("restore journal {path:?} belongs to a different archive");
+        }
+
+        let done = entries
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| OsString::from_vec(entry.to_vec()))
+            .collect();
+
+        let file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(path)
+            .with_context(|| format!("unable to open restore journal {path:?}"))?;
+        file.set_len(len as u64)?;
+
+        Ok(Self { file, done })
+    }
+
+    /// Number of entries recorded as extracted.
+    pub fn len(&self) -> usize {
+        self.done.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.done.is_empty()
+    }
+
+    fn contains(&self, path: &OsStr) -> bool {
+        self.done.contains(path)
+    }
+
+    fn record(&mut self, path: &OsStr) -> Result<(), Error> {
+        let mut entry = Vec::with_capacity(path.len() + 1);
+        entry.extend_from_slice(path.as_bytes());
+        entry.push(0);
+        self.file
+            .write_all(&entry)
+            .context("unable to write restore journal")
+    }
 }
 
 bitflags! {
@@ -88,6 +170,7 @@ where
     callback: F,
     extractor: Extractor,
     match_list: &'a [MatchEntry],
+    journal: Option<&'a mut ExtractJournal>,
     state: ExtractorIterState,
 }
 
@@ -161,12 +244,15 @@ where
         if let Some(on_error) = options.on_error {
             extractor.on_error(on_error);
         }
+        extractor.set_metadata_mapping(options.metadata_mapping);
+        extractor.set_acl_policy(options.acl_policy, options.acl_report);
 
         Ok(Self {
             decoder,
             callback,
             extractor,
             match_list: options.match_list,
+            journal: options.journal,
             state,
         })
     }
@@ -266,6 +352,21 @@ where
             None => self.state.current_match,
         };
 
+        let journaled = did_match
+            && !matches!(entry.kind(), EntryKind::Directory | EntryKind::GoodbyeTable)
+            && self.journal.is_some();
+
+        if journaled
+            && self
+                .journal
+                .as_ref()
+                .unwrap()
+                .contains(entry.path().as_os_str())
+            && self.extractor.is_extracted(&file_name, &entry)
+        {
+            return Some(Ok(())); // already extracted by the interrupted run
+        }
+
         let extract_res = match (did_match, entry.kind()) {
             (_, EntryKind::Directory) => {
                 self.callback(entry.path());
@@ -376,6 +477,11 @@ where
             (false, _) => Ok(()), // skip this
         };
 
+        let extract_res = match (extract_res, self.journal.as_mut()) {
+            (Ok(()), Some(journal)) if journaled => journal.record(entry.path().as_os_str()),
+            (res, _) => res,
+        };
+
         Some(
             extract_res
                 .with_context(|| format!("error at entry {file_name_os:?}"))
@@ -466,6 +572,12 @@ pub struct Extractor {
     /// Error callback. Includes `current_path` in the reformatted error, should return `Ok` to
     /// continue extracting or the passed error as `Err` to bail out.
     on_error: ErrorHandler,
+
+    /// Changes to the ownership and permissions from the archive.
+    metadata_mapping: MetadataMapping,
+
+    acl_policy: AclPolicy,
+    acl_report: Arc<Mutex<AclReport>>,
 }
 
 impl Extractor {
@@ -484,6 +596,23 @@ impl Extractor {
             feature_flags,
             current_path: Arc::new(Mutex::new(OsString::new())),
             on_error: Box::new(Err),
+            metadata_mapping: MetadataMapping::default(),
+            acl_policy: AclPolicy::default(),
+            acl_report: Arc::new(Mutex::new(AclReport::default())),
+        }
+    }
+
+    /// Change the ownership and permissions of extracted entries according to `mapping`.
+    pub fn set_metadata_mapping(&mut self, mapping: MetadataMapping) {
+        self.metadata_mapping = mapping;
+    }
+
+    /// Handle ACLs that cannot be restored according to `policy`, recording degraded entries in
+    /// `report` if given.
+    pub fn set_acl_policy(&mut self, policy: AclPolicy, report: Option<Arc<Mutex<AclReport>>>) {
+        self.acl_policy = policy;
+        if let Some(report) = report {
+            self.acl_report = report;
         }
     }
 
@@ -537,9 +666,11 @@ impl Extractor {
         if let Some(fd) = dir.try_as_borrowed_fd() {
             metadata::apply(
                 self.feature_flags,
-                dir.metadata(),
+                &self.metadata_mapping.map(dir.metadata()),
                 fd.as_raw_fd(),
                 &path_info,
+                self.acl_policy,
+                &self.acl_report,
                 &mut self.on_error,
             )
             .context("failed to apply directory metadata")?;
@@ -552,6 +683,30 @@ impl Extractor {
         self.feature_flags.contains(flag)
     }
 
+    /// Quick check whether `entry` was completely extracted before: it has to exist, and regular
+    /// files need the size and modification time from the archive, which are set last.
+    fn is_extracted(&mut self, file_name: &CStr, entry: &Entry) -> bool {
+        let parent = match self.parent_fd() {
+            Ok(parent) => parent,
+            Err(_) => return false,
+        };
+        let stat = match nix::sys::stat::fstatat(
+            parent,
+            file_name,
+            nix::fcntl::AtFlags::AT_SYMLINK_NOFOLLOW,
+        ) {
+            Ok(stat) => stat,
+            Err(_) => return false,
+        };
+
+        match entry.kind() {
+            EntryKind::File { size, .. } => {
+                stat.st_size as u64 == *size && stat.st_mtime == entry.metadata().stat.mtime.secs
+            }
+            _ => true,
+        }
+    }
+
     fn parent_fd(&mut self) -> Result<RawFd, Error> {
         self.dir_stack
             .last_dir_fd(self.allow_existing_dirs)
@@ -582,10 +737,12 @@ impl Extractor {
 
         metadata::apply_at(
             self.feature_flags,
-            metadata,
+            &self.metadata_mapping.map(metadata),
             parent,
             file_name,
             self.dir_stack.path(),
+            self.acl_policy,
+            &self.acl_report,
             &mut self.on_error,
         )
     }
@@ -647,10 +804,12 @@ impl Extractor {
 
         metadata::apply_at(
             self.feature_flags,
-            metadata,
+            &self.metadata_mapping.map(metadata),
             parent,
             file_name,
             self.dir_stack.path(),
+            self.acl_policy,
+            &self.acl_report,
             &mut self.on_error,
         )
     }
@@ -706,9 +865,11 @@ impl Extractor {
 
         metadata::apply(
             self.feature_flags,
-            metadata,
+            &self.metadata_mapping.map(metadata),
             file.as_raw_fd(),
             self.dir_stack.path(),
+            self.acl_policy,
+            &self.acl_report,
             &mut self.on_error,
         )
     }
@@ -765,9 +926,11 @@ impl Extractor {
 
         metadata::apply(
             self.feature_flags,
-            metadata,
+            &self.metadata_mapping.map(metadata),
             file.as_raw_fd(),
             self.dir_stack.path(),
+            self.acl_policy,
+            &self.acl_report,
             &mut self.on_error,
         )
     }
@@ -1300,3 +1463,44 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_extract_journal() -> Result<(), Error> {
+        let mut testdir = PathBuf::from("./target/testout");
+        testdir.push(std::module_path!());
+        let _ = std::fs::remove_dir_all(&testdir);
+        std::fs::create_dir_all(&testdir)?;
+        let path = testdir.join("journal");
+
+        let mut journal = ExtractJournal::create(&path, "root.pxar:1234")?;
+        assert!(journal.is_empty());
+        journal.record(OsStr::new("etc/hostname"))?;
+        journal.record(OsStr::new("etc/hosts"))?;
+        drop(journal);
+
+        // an interrupted write leaves a partial last entry
+        let mut file = std::fs::OpenOptions::new().append(true).open(&path)?;
+        file.write_all(b"etc/pas")?;
+        drop(file);
+
+        assert!(ExtractJournal::resume(&path, "root.pxar:5678").is_err());
+
+        let mut journal = ExtractJournal::resume(&path, "root.pxar:1234")?;
+        assert_eq!(journal.len(), 2);
+        assert!(journal.contains(OsStr::new("etc/hostname")));
+        assert!(!journal.contains(OsStr::new("etc/pas")));
+
+        journal.record(OsStr::new("etc/passwd"))?;
+        drop(journal);
+
+        let journal = ExtractJournal::resume(&path, "root.pxar:1234")?;
+        assert_eq!(journal.len(), 3);
+        assert!(journal.contains(OsStr::new("etc/passwd")));
+
+        Ok(())
+    }
+}
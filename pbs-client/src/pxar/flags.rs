@@ -131,7 +131,7 @@ bitflags! {
             Flags::WITH_FLAG_NOATIME.bits() |
             Flags::WITH_FLAG_COMPR.bits() |
             Flags::WITH_FLAG_NOCOW.bits() |
-            //WITH_FLAG_NODUMP.bits() |
+            Flags::WITH_FLAG_NODUMP.bits() |
             Flags::WITH_FLAG_DIRSYNC.bits() |
             Flags::WITH_FLAG_IMMUTABLE.bits() |
             Flags::WITH_FLAG_SYNC.bits() |
@@ -146,7 +146,7 @@ bitflags! {
             Flags::WITH_SELINUX.bits() |
             Flags::WITH_FCAPS.bits() |
             Flags::WITH_QUOTA_PROJID.bits() |
-            Flags::EXCLUDE_NODUMP.bits() |
+            // skipping NODUMP entries must be requested explicitly
             Flags::EXCLUDE_FILE.bits();
     }
 }
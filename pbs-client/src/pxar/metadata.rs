@@ -1,6 +1,9 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::ffi::{CStr, CString};
 use std::os::unix::io::{AsRawFd, RawFd};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
 use anyhow::{anyhow, bail, Context, Error};
 use nix::errno::Errno;
@@ -8,12 +11,14 @@ use nix::fcntl::OFlag;
 use nix::sys::stat::Mode;
 
 use pxar::Metadata;
+use serde::{Deserialize, Serialize};
 
+use proxmox_schema::api;
 use proxmox_sys::c_result;
 use proxmox_sys::error::SysError;
 use proxmox_sys::fs::{self, acl, xattr};
 
-use crate::pxar::tools::perms_from_metadata;
+use crate::pxar::tools::{perms_from_metadata, XattrFilter};
 use crate::pxar::Flags;
 
 //
@@ -53,16 +58,237 @@ fn timestamp_to_update_timespec(mtime: &pxar::format::StatxTimestamp) -> [libc::
     ]
 }
 
+//
+// ownership and permission mapping:
+//
+
+/// Changes to the ownership and permissions of extracted entries, for restoring to a host with a
+/// different user database.
+#[derive(Clone, Debug, Default)]
+pub struct MetadataMapping {
+    uid_map: HashMap<u32, u32>,
+    gid_map: HashMap<u32, u32>,
+    squash: Option<(u32, u32)>,
+    umask: u32,
+    skip_xattrs: XattrFilter,
+}
+
+impl MetadataMapping {
+    /// Parse a map file, consisting of `uid <archive-id> <target-id>` and
+    /// `gid <archive-id> <target-id>` lines. Empty lines and lines starting with `#` are ignored.
+    pub fn parse_map(data: &str) -> Result<Self, Error> {
+        let mut mapping = Self::default();
+
+        for (lineno, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            let (map, from, to) = match parts[..] {
+                ["uid", from, to] => (&mut mapping.uid_map, from, to),
+                ["gid", from, to] => (&mut mapping.gid_map, from, to),
+                _ => bail!("invalid map entry in line {} - {line:?}", lineno + 1),
+            };
+            let parse_id = |id: &str| {
+                id.parse::<u32>()
+                    .with_context(|| format!("invalid id in line {} - {line:?}", lineno + 1))
+            };
+            map.insert(parse_id(from)?, parse_id(to)?);
+        }
+
+        Ok(mapping)
+    }
+
+    /// Read and parse a map file, see [`parse_map`](Self::parse_map).
+    pub fn from_map_file(path: &Path) -> Result<Self, Error> {
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("unable to read map file {path:?}"))?;
+        Self::parse_map(&data).with_context(|| format!("unable to parse map file {path:?}"))
+    }
+
+    /// Give all entries to `uid` and `gid` instead of their original or mapped owners. Named
+    /// users and groups are dropped from ACLs, since their IDs are meaningless on the target.
+    pub fn squash(mut self, uid: u32, gid: u32) -> Self {
+        self.squash = Some((uid, gid));
+        self
+    }
+
+    /// Remove the permission bits set in `umask` from all entries, including their ACLs.
+    pub fn umask(mut self, umask: u32) -> Self {
+        self.umask = umask & 0o7777;
+        self
+    }
+
+    /// Do not restore the extended attributes matching `filter`, including file capabilities
+    /// (`security.capability`) and ACLs (`system.posix_acl_access` and
+    /// `system.posix_acl_default`).
+    pub fn skip_xattrs(mut self, filter: XattrFilter) -> Self {
+        self.skip_xattrs = filter;
+        self
+    }
+
+    /// Returns true if extracted entries keep the ownership, permissions and extended attributes
+    /// of the archive.
+    pub fn is_identity(&self) -> bool {
+        self.uid_map.is_empty()
+            && self.gid_map.is_empty()
+            && self.squash.is_none()
+            && self.umask == 0
+            && self.skip_xattrs.is_empty()
+    }
+
+    fn map_uid(&self, uid: u32) -> u32 {
+        self.uid_map.get(&uid).copied().unwrap_or(uid)
+    }
+
+    fn map_gid(&self, gid: u32) -> u32 {
+        self.gid_map.get(&gid).copied().unwrap_or(gid)
+    }
+
+    // With an extended ACL, the group bits of the mode are the ACL mask, so the umask applies
+    // to the mask already. The entries themselves are masked too, so that they do not grant more
+    // than the umask allows once the mask gets changed, and the default ACL is masked like a
+    // mode, since it is what new files below an extracted directory get.
+    fn mask_acl(&self, acl: &mut pxar::Acl) {
+        use pxar::format::acl::Permissions;
+
+        let mask = |permissions: &mut Permissions, bits: u32| {
+            // unset permissions of the default ACL are stored as `NO_MASK`
+            if *permissions != Permissions::NO_MASK {
+                permissions.0 &= !u64::from(bits & 0o7);
+            }
+        };
+        let user_bits = self.umask >> 6;
+        let group_bits = self.umask >> 3;
+        let other_bits = self.umask;
+
+        if let Some(group_obj) = acl.group_obj.as_mut() {
+            mask(&mut group_obj.permissions, group_bits);
+        }
+        for user in acl.users.iter_mut().chain(acl.default_users.iter_mut()) {
+            mask(&mut user.permissions, group_bits);
+        }
+        for group in acl.groups.iter_mut().chain(acl.default_groups.iter_mut()) {
+            mask(&mut group.permissions, group_bits);
+        }
+        if let Some(default) = acl.default.as_mut() {
+            mask(&mut default.user_obj_permissions, user_bits);
+            mask(&mut default.group_obj_permissions, group_bits);
+            mask(&mut default.other_permissions, other_bits);
+            mask(&mut default.mask_permissions, group_bits);
+        }
+    }
+
+    /// Returns the metadata to apply to an entry with the metadata `metadata` from the archive.
+    pub fn map<'m>(&self, metadata: &'m Metadata) -> Cow<'m, Metadata> {
+        if self.is_identity() {
+            return Cow::Borrowed(metadata);
+        }
+
+        let mut metadata = metadata.clone();
+        metadata.stat.mode &= !u64::from(self.umask);
+
+        if !self.skip_xattrs.is_empty() {
+            let filter = &self.skip_xattrs;
+            metadata
+                .xattrs
+                .retain(|xattr| !filter.matches(xattr.name().to_bytes()));
+            if filter.matches(b"security.capability") {
+                metadata.fcaps = None;
+            }
+            if filter.matches(b"system.posix_acl_access") {
+                metadata.acl.users.clear();
+                metadata.acl.groups.clear();
+                metadata.acl.group_obj = None;
+            }
+            if filter.matches(b"system.posix_acl_default") {
+                metadata.acl.default = None;
+                metadata.acl.default_users.clear();
+                metadata.acl.default_groups.clear();
+            }
+        }
+
+        self.mask_acl(&mut metadata.acl);
+
+        let acl = &mut metadata.acl;
+        match self.squash {
+            Some((uid, gid)) => {
+                metadata.stat.uid = uid;
+                metadata.stat.gid = gid;
+                acl.users.clear();
+                acl.groups.clear();
+                acl.default_users.clear();
+                acl.default_groups.clear();
+                // without named entries, the group bits of the mode are the permissions of the
+                // group object, limited by the mask they held so far
+                if let Some(group_obj) = acl.group_obj.take() {
+                    let mask = (metadata.stat.mode >> 3) & 0o7;
+                    metadata.stat.mode &= !0o070;
+                    metadata.stat.mode |= (group_obj.permissions.0 & mask) << 3;
+                }
+            }
+            None => {
+                metadata.stat.uid = self.map_uid(metadata.stat.uid);
+                metadata.stat.gid = self.map_gid(metadata.stat.gid);
+                for user in acl.users.iter_mut().chain(acl.default_users.iter_mut()) {
+                    if let Ok(uid) = u32::try_from(user.uid) {
+                        user.uid = u64::from(self.map_uid(uid));
+                    }
+                }
+                for group in acl.groups.iter_mut().chain(acl.default_groups.iter_mut()) {
+                    if let Ok(gid) = u32::try_from(group.gid) {
+                        group.gid = u64::from(self.map_gid(gid));
+                    }
+                }
+            }
+        }
+
+        Cow::Owned(metadata)
+    }
+}
+
+#[api]
+/// What to do when the ACL of an entry cannot be restored, for example because the target file
+/// system does not support ACLs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AclPolicy {
+    /// Treat it as an error of the entry.
+    #[default]
+    Strict,
+    /// Warn, restore only the permission bits, with the group permissions limited to the ones
+    /// of the owning group, and record the entry in the [AclReport].
+    Degrade,
+}
+
+/// An entry whose ACL could not be restored.
+#[derive(Clone, Debug)]
+pub struct AclReportEntry {
+    pub path: PathBuf,
+    pub error: String,
+}
+
+/// The entries whose ACLs were degraded during an extraction.
+#[derive(Clone, Debug, Default)]
+pub struct AclReport {
+    pub entries: Vec<AclReportEntry>,
+}
+
 //
 // metadata application:
 //
 
+#[allow(clippy::too_many_arguments)]
 pub fn apply_at(
     flags: Flags,
     metadata: &Metadata,
     parent: RawFd,
     file_name: &CStr,
     path_info: &Path,
+    acl_policy: AclPolicy,
+    acl_report: &Mutex<AclReport>,
     on_error: &mut (dyn FnMut(Error) -> Result<(), Error> + Send),
 ) -> Result<(), Error> {
     let fd = proxmox_sys::fd::openat(
@@ -72,7 +298,15 @@ pub fn apply_at(
         Mode::empty(),
     )?;
 
-    apply(flags, metadata, fd.as_raw_fd(), path_info, on_error)
+    apply(
+        flags,
+        metadata,
+        fd.as_raw_fd(),
+        path_info,
+        acl_policy,
+        acl_report,
+        on_error,
+    )
 }
 
 pub fn apply_initial_flags(
@@ -96,6 +330,8 @@ pub fn apply(
     metadata: &Metadata,
     fd: RawFd,
     path_info: &Path,
+    acl_policy: AclPolicy,
+    acl_report: &Mutex<AclReport>,
     on_error: &mut (dyn FnMut(Error) -> Result<(), Error> + Send),
 ) -> Result<(), Error> {
     let c_proc_path = CString::new(format!("/proc/self/fd/{}", fd)).unwrap();
@@ -105,21 +341,35 @@ pub fn apply(
     apply_xattrs(flags, c_proc_path.as_ptr(), metadata, &mut skip_xattrs)
         .or_else(&mut *on_error)?;
     add_fcaps(flags, c_proc_path.as_ptr(), metadata, &mut skip_xattrs).or_else(&mut *on_error)?;
-    apply_acls(flags, &c_proc_path, metadata, path_info)
-        .context("failed to apply acls")
-        .or_else(&mut *on_error)?;
+    let mut acl_degraded = false;
+    match apply_acls(flags, &c_proc_path, metadata, path_info, acl_policy) {
+        Ok(()) => (),
+        Err(err) if acl_policy == AclPolicy::Degrade => {
+            log::warn!("unable to restore ACL of {path_info:?}, restoring the mode only - {err}");
+            acl_report.lock().unwrap().entries.push(AclReportEntry {
+                path: path_info.to_owned(),
+                error: err.to_string(),
+            });
+            acl_degraded = true;
+        }
+        Err(err) => Err(err)
+            .context("failed to apply acls")
+            .or_else(&mut *on_error)?,
+    }
     apply_quota_project_id(flags, fd, metadata).or_else(&mut *on_error)?;
 
     // Finally mode and time. We may lose access with mode, but the changing the mode also
     // affects times.
     if !metadata.is_symlink() && flags.contains(Flags::WITH_PERMISSIONS) {
-        c_result!(unsafe {
-            libc::chmod(c_proc_path.as_ptr(), perms_from_metadata(metadata)?.bits())
-        })
-        .map(drop)
-        .or_else(allow_notsupp)
-        .context("failed to change file mode")
-        .or_else(&mut *on_error)?;
+        let mut perms = perms_from_metadata(metadata)?;
+        if acl_degraded {
+            perms = degraded_acl_perms(perms, metadata);
+        }
+        c_result!(unsafe { libc::chmod(c_proc_path.as_ptr(), perms.bits()) })
+            .map(drop)
+            .or_else(allow_notsupp)
+            .context("failed to change file mode")
+            .or_else(&mut *on_error)?;
     }
 
     let res = c_result!(unsafe {
@@ -236,11 +486,24 @@ fn apply_xattrs(
     Ok(())
 }
 
+/// With an extended ACL the group permission bits are its mask, which may grant the owning group
+/// more than its own ACL entry. Without the ACL, only grant what both allow.
+fn degraded_acl_perms(perms: Mode, metadata: &Metadata) -> Mode {
+    match metadata.acl.group_obj.as_ref() {
+        Some(group_obj) => {
+            let group = (group_obj.permissions.0 as u32 & 0o7) << 3;
+            Mode::from_bits_truncate((perms.bits() & !0o070) | (perms.bits() & group))
+        }
+        None => perms,
+    }
+}
+
 fn apply_acls(
     flags: Flags,
     c_proc_path: &CStr,
     metadata: &Metadata,
     path_info: &Path,
+    acl_policy: AclPolicy,
 ) -> Result<(), Error> {
     if !flags.contains(Flags::WITH_ACL) || metadata.acl.is_empty() {
         return Ok(());
@@ -297,7 +560,15 @@ fn apply_acls(
         bail!("Error while restoring ACL - ACL invalid");
     }
 
-    acl.set_file(c_proc_path, acl::ACL_TYPE_ACCESS)?;
+    // when degrading, directories with only a default ACL have an access ACL equivalent to
+    // their mode, which is restored anyway on file systems without ACL support
+    let minimal = metadata.acl.group_obj.is_none()
+        && metadata.acl.users.is_empty()
+        && metadata.acl.groups.is_empty();
+    match acl.set_file(c_proc_path, acl::ACL_TYPE_ACCESS) {
+        Err(Errno::EOPNOTSUPP) if minimal && acl_policy == AclPolicy::Degrade => (),
+        res => res?,
+    }
     drop(acl);
 
     // acl type default:
@@ -406,3 +677,126 @@ fn apply_flags(flags: Flags, fd: RawFd, entry_flags: u64) -> Result<(), Error> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use pxar::format::acl::{self as pxar_acl, Group, GroupObject, Permissions, User};
+
+    fn acl_metadata() -> Metadata {
+        let mut metadata = Metadata::default();
+        metadata.stat.mode = pxar::format::mode::IFDIR | 0o775;
+        metadata.stat.uid = 1000;
+        metadata.stat.gid = 1000;
+        metadata.acl.users.push(User {
+            uid: 1001,
+            permissions: Permissions(0o7),
+        });
+        metadata.acl.groups.push(Group {
+            gid: 1002,
+            permissions: Permissions(0o6),
+        });
+        metadata.acl.group_obj = Some(GroupObject {
+            permissions: Permissions(0o5),
+        });
+        metadata.acl.default = Some(pxar_acl::Default {
+            user_obj_permissions: Permissions(0o7),
+            group_obj_permissions: Permissions(0o7),
+            other_permissions: Permissions(0o7),
+            mask_permissions: Permissions::NO_MASK,
+        });
+        metadata.acl.default_users.push(User {
+            uid: 1001,
+            permissions: Permissions(0o7),
+        });
+        metadata
+    }
+
+    #[test]
+    fn test_parse_map() -> Result<(), Error> {
+        let mapping = MetadataMapping::parse_map("# comment\n\nuid 1000 2000\ngid 100 200\n")?;
+        assert_eq!(mapping.map_uid(1000), 2000);
+        assert_eq!(mapping.map_uid(1001), 1001);
+        assert_eq!(mapping.map_gid(100), 200);
+
+        assert!(MetadataMapping::parse_map("uid 1000").is_err());
+        assert!(MetadataMapping::parse_map("user 1000 2000").is_err());
+        assert!(MetadataMapping::parse_map("uid 1000 -1").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_map_umask() {
+        let metadata = acl_metadata();
+        let mapping = MetadataMapping::default().umask(0o027);
+        let mapped = mapping.map(&metadata);
+
+        assert_eq!(mapped.stat.mode, pxar::format::mode::IFDIR | 0o750);
+        let acl = &mapped.acl;
+        assert_eq!(acl.users[0].permissions, Permissions(0o5));
+        assert_eq!(acl.groups[0].permissions, Permissions(0o4));
+        assert_eq!(
+            acl.group_obj.as_ref().unwrap().permissions,
+            Permissions(0o5)
+        );
+        assert_eq!(acl.default_users[0].permissions, Permissions(0o5));
+
+        let default = acl.default.as_ref().unwrap();
+        assert_eq!(default.user_obj_permissions, Permissions(0o7));
+        assert_eq!(default.group_obj_permissions, Permissions(0o5));
+        assert_eq!(default.other_permissions, Permissions(0o0));
+        assert_eq!(default.mask_permissions, Permissions::NO_MASK);
+    }
+
+    #[test]
+    fn test_map_ownership() -> Result<(), Error> {
+        let metadata = acl_metadata();
+
+        let mapping = MetadataMapping::parse_map("uid 1000 2000\nuid 1001 2001\ngid 1002 2002")?;
+        let mapped = mapping.map(&metadata);
+        assert_eq!((mapped.stat.uid, mapped.stat.gid), (2000, 1000));
+        assert_eq!(mapped.acl.users[0].uid, 2001);
+        assert_eq!(mapped.acl.groups[0].gid, 2002);
+        assert_eq!(mapped.acl.default_users[0].uid, 2001);
+
+        let mapped = MetadataMapping::default().squash(0, 0).map(&metadata);
+        assert_eq!((mapped.stat.uid, mapped.stat.gid), (0, 0));
+        assert!(mapped.acl.users.is_empty() && mapped.acl.groups.is_empty());
+        assert!(mapped.acl.default_users.is_empty());
+        // the group object permissions, limited by the mask, end up in the mode
+        assert!(mapped.acl.group_obj.is_none());
+        assert_eq!(mapped.stat.mode, pxar::format::mode::IFDIR | 0o755);
+
+        assert!(matches!(
+            MetadataMapping::default().map(&metadata),
+            Cow::Borrowed(_)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_degraded_acl_perms() {
+        let mut metadata = acl_metadata();
+        let perms = Mode::from_bits_truncate;
+
+        // the group bits are the mask of the ACL, limit them to the owning group
+        assert_eq!(degraded_acl_perms(perms(0o775), &metadata), perms(0o755));
+        assert_eq!(degraded_acl_perms(perms(0o2770), &metadata), perms(0o2750));
+        assert_eq!(degraded_acl_perms(perms(0o705), &metadata), perms(0o705));
+
+        metadata.acl.group_obj = Some(GroupObject {
+            permissions: Permissions(0o7),
+        });
+        assert_eq!(degraded_acl_perms(perms(0o750), &metadata), perms(0o750));
+
+        metadata.acl.group_obj = Some(GroupObject {
+            permissions: Permissions(0o0),
+        });
+        assert_eq!(degraded_acl_perms(perms(0o775), &metadata), perms(0o705));
+
+        // without an extended ACL the group bits are those of the owning group already
+        metadata.acl.group_obj = None;
+        assert_eq!(degraded_acl_perms(perms(0o775), &metadata), perms(0o775));
+    }
+}
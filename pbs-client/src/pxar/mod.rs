@@ -56,10 +56,10 @@ pub(crate) mod tools;
 mod flags;
 pub use flags::Flags;
 
-pub use create::{create_archive, PxarCreateOptions};
+pub use create::{create_archive, PxarCreateOptions, PxarCreateStats, KNOWN_CACHE_PATTERNS};
 pub use extract::{
     create_tar, create_zip, extract_archive, extract_sub_dir, extract_sub_dir_seq, ErrorHandler,
-    OverwriteFlags, PxarExtractContext, PxarExtractOptions,
+    ExtractJournal, OverwriteFlags, PxarExtractContext, PxarExtractOptions,
 };
 
 /// The format requires to build sorted directory lookup tables in
@@ -67,4 +67,6 @@ pub use extract::{
 /// maximum memory usage.
 pub const ENCODER_MAX_ENTRIES: usize = 1024 * 1024;
 
-pub use tools::{format_multi_line_entry, format_single_line_entry};
+pub use metadata::{AclPolicy, AclReport, AclReportEntry, MetadataMapping};
+
+pub use tools::{format_multi_line_entry, format_single_line_entry, XattrFilter};
@@ -21,6 +21,62 @@ pub fn perms_from_metadata(meta: &Metadata) -> Result<Mode, Error> {
         })
 }
 
+/// A set of extended attribute names and namespaces, to skip when creating or extracting an
+/// archive.
+///
+/// Patterns are either full names like `security.selinux`, or namespaces like `user.*`, which
+/// match all names starting with `user.`.
+#[derive(Clone, Debug, Default)]
+pub struct XattrFilter {
+    names: Vec<Vec<u8>>,
+    namespaces: Vec<Vec<u8>>,
+}
+
+impl XattrFilter {
+    /// Add a name or namespace pattern.
+    pub fn add(&mut self, pattern: &str) -> Result<(), Error> {
+        match pattern.strip_suffix('*') {
+            Some(prefix) if prefix.ends_with('.') && prefix.len() > 1 => {
+                self.namespaces.push(prefix.as_bytes().to_vec());
+            }
+            _ if pattern.is_empty() || pattern.contains('*') || pattern.starts_with('.') => {
+                bail!("invalid xattr pattern '{pattern}', expected a name or '<namespace>.*'");
+            }
+            _ => self.names.push(pattern.as_bytes().to_vec()),
+        }
+        Ok(())
+    }
+
+    /// Build a filter from a list of patterns.
+    pub fn from_patterns<'a, I: IntoIterator<Item = &'a str>>(patterns: I) -> Result<Self, Error> {
+        let mut filter = Self::default();
+        for pattern in patterns {
+            filter.add(pattern)?;
+        }
+        Ok(filter)
+    }
+
+    /// Add all patterns of another filter.
+    pub fn extend(&mut self, other: &XattrFilter) {
+        self.names.extend(other.names.iter().cloned());
+        self.namespaces.extend(other.namespaces.iter().cloned());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty() && self.namespaces.is_empty()
+    }
+
+    /// Returns true if the xattr `name` (without a trailing NUL byte) matches the filter.
+    pub fn matches(&self, name: &[u8]) -> bool {
+        let name = name.strip_suffix(b"\0").unwrap_or(name);
+        self.names.iter().any(|pattern| pattern == name)
+            || self
+                .namespaces
+                .iter()
+                .any(|prefix| name.starts_with(prefix))
+    }
+}
+
 /// Make sure path is relative and not '.' or '..'.
 pub fn assert_relative_path<S: AsRef<OsStr> + ?Sized>(path: &S) -> Result<(), Error> {
     assert_relative_path_do(Path::new(path))
@@ -204,3 +260,46 @@ pub fn format_multi_line_entry(entry: &Entry) -> String {
         format_mtime(&meta.stat.mtime),
     )
 }
+
+#[cfg(test)]
+mod test {
+    use super::XattrFilter;
+
+    #[test]
+    fn test_xattr_filter_add() {
+        let mut filter = XattrFilter::default();
+        assert!(filter.is_empty());
+
+        for pattern in ["security.selinux", "user.*", "trusted.overlay.*", "system"] {
+            filter.add(pattern).unwrap();
+        }
+        assert!(!filter.is_empty());
+
+        for pattern in ["", "*", ".*", ".user", "user*", "user.*.foo", "*.selinux"] {
+            assert!(filter.add(pattern).is_err(), "pattern '{pattern}' accepted");
+        }
+    }
+
+    #[test]
+    fn test_xattr_filter_matches() {
+        let filter = XattrFilter::from_patterns(["security.selinux", "user.*"]).unwrap();
+
+        assert!(filter.matches(b"security.selinux"));
+        assert!(filter.matches(b"security.selinux\0"));
+        assert!(filter.matches(b"user.foo"));
+        assert!(filter.matches(b"user.foo.bar\0"));
+
+        assert!(!filter.matches(b"security.selinux.foo"));
+        assert!(!filter.matches(b"security.capability"));
+        assert!(!filter.matches(b"user"));
+        assert!(!filter.matches(b"trusted.user.foo"));
+
+        let mut other = XattrFilter::from_patterns(["trusted.*"]).unwrap();
+        assert!(!other.matches(b"user.foo"));
+        other.extend(&filter);
+        assert!(other.matches(b"user.foo"));
+        assert!(other.matches(b"trusted.foo"));
+
+        assert!(!XattrFilter::default().matches(b"user.foo"));
+    }
+}
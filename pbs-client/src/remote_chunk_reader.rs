@@ -1,11 +1,12 @@
 use std::collections::HashMap;
 use std::future::Future;
 use std::pin::Pin;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 
 use anyhow::{bail, format_err, Error};
-
 use proxmox_async::runtime::block_on;
+use proxmox_router::HttpError;
 
 use pbs_api_types::CryptMode;
 use pbs_datastore::data_blob::DataBlob;
@@ -13,12 +14,72 @@ use pbs_datastore::read_chunk::AsyncReadChunk;
 use pbs_datastore::read_chunk::ReadChunk;
 use pbs_tools::crypt_config::CryptConfig;
 
+use super::retry::{backoff_delay, is_transient_status};
 use super::BackupReader;
 
+const ENV_VAR_PBS_CHUNK_RETRIES: &str = "PBS_CHUNK_RETRIES";
+const ENV_VAR_PBS_CHUNK_RETRY_DELAY: &str = "PBS_CHUNK_RETRY_DELAY";
+
+/// How often and how fast failed chunk downloads are retried
+#[derive(Clone, Copy, Debug)]
+pub struct ChunkRetryPolicy {
+    /// Number of retries per chunk
+    pub retries: usize,
+    /// Delay before the first retry, doubled for each further one
+    pub delay: Duration,
+    /// Upper limit for the delay
+    pub max_delay: Duration,
+}
+
+impl Default for ChunkRetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 5,
+            delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+        }
+    }
+}
+
+impl ChunkRetryPolicy {
+    /// Default policy, adapted by the `PBS_CHUNK_RETRIES` and `PBS_CHUNK_RETRY_DELAY` (in
+    /// seconds) environment variables.
+    pub fn from_env() -> Self {
+        let mut policy = Self::default();
+
+        if let Ok(retries) = std::env::var(ENV_VAR_PBS_CHUNK_RETRIES) {
+            match retries.parse() {
+                Ok(retries) => policy.retries = retries,
+                Err(err) => log::warn!("ignoring invalid {ENV_VAR_PBS_CHUNK_RETRIES} - {err}"),
+            }
+        }
+        if let Ok(delay) = std::env::var(ENV_VAR_PBS_CHUNK_RETRY_DELAY) {
+            match delay.parse() {
+                Ok(delay) => policy.delay = Duration::from_secs(delay),
+                Err(err) => log::warn!("ignoring invalid {ENV_VAR_PBS_CHUNK_RETRY_DELAY} - {err}"),
+            }
+        }
+
+        policy
+    }
+}
+
+/// Opens a new reader session for the same snapshot, possibly on another server.
+///
+/// The new session must already have downloaded the indices of the chunks to be read, as the
+/// server only allows reading chunks of downloaded indices.
+pub type ReconnectFn = Arc<dyn Fn() -> ReconnectFuture + Send + Sync>;
+
+pub type ReconnectFuture = Pin<Box<dyn Future<Output = Result<Arc<BackupReader>, Error>> + Send>>;
+
 /// Read chunks from remote host using ``BackupReader``
 #[derive(Clone)]
 pub struct RemoteChunkReader {
-    client: Arc<BackupReader>,
+    /// current session, with a generation counter to detect reconnects
+    client: Arc<RwLock<(usize, Arc<BackupReader>)>>,
+    reconnect: Option<ReconnectFn>,
+    reconnect_lock: Arc<tokio::sync::Mutex<()>>,
+    retry: ChunkRetryPolicy,
     crypt_config: Option<Arc<CryptConfig>>,
     crypt_mode: CryptMode,
     cache_hint: Arc<HashMap<[u8; 32], usize>>,
@@ -36,7 +97,10 @@ impl RemoteChunkReader {
         cache_hint: HashMap<[u8; 32], usize>,
     ) -> Self {
         Self {
-            client,
+            client: Arc::new(RwLock::new((0, client))),
+            reconnect: None,
+            reconnect_lock: Arc::new(tokio::sync::Mutex::new(())),
+            retry: ChunkRetryPolicy::from_env(),
             crypt_config,
             crypt_mode,
             cache_hint: Arc::new(cache_hint),
@@ -44,12 +108,93 @@ impl RemoteChunkReader {
         }
     }
 
+    /// Use `policy` instead of the default retry policy.
+    pub fn with_retry_policy(mut self, policy: ChunkRetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Open a new session with `reconnect` if the connection to the server breaks.
+    pub fn with_reconnect(mut self, reconnect: ReconnectFn) -> Self {
+        self.reconnect = Some(reconnect);
+        self
+    }
+
+    fn current_client(&self) -> (usize, Arc<BackupReader>) {
+        let current = self.client.read().unwrap();
+        (current.0, Arc::clone(&current.1))
+    }
+
+    fn decode_chunk(&self, chunk: &DataBlob, digest: &[u8; 32]) -> Result<Vec<u8>, Error> {
+        let (_generation, client) = self.current_client();
+        chunk.decode_salted(
+            self.crypt_config.as_ref().map(Arc::as_ref),
+            Some(digest),
+            client.digest_salt(),
+        )
+    }
+
+    async fn reconnect(&self, generation: usize) {
+        let reconnect = match &self.reconnect {
+            Some(reconnect) => reconnect,
+            None => return,
+        };
+
+        let _guard = self.reconnect_lock.lock().await;
+        if self.client.read().unwrap().0 != generation {
+            return; // another download already reconnected
+        }
+
+        match reconnect().await {
+            Ok(client) => *self.client.write().unwrap() = (generation + 1, client),
+            Err(err) => log::warn!("opening new reader session failed - {err}"),
+        }
+    }
+
+    async fn download_chunk(&self, digest: &[u8; 32]) -> Result<Vec<u8>, Error> {
+        let mut attempt = 0;
+
+        loop {
+            let (generation, client) = self.current_client();
+
+            let mut chunk_data = Vec::with_capacity(4 * 1024 * 1024);
+            let err = match client.download_chunk(digest, &mut chunk_data).await {
+                Ok(()) => return Ok(chunk_data),
+                Err(err) => err,
+            };
+
+            let connection_broken = match err.downcast_ref::<HttpError>() {
+                Some(http_err) if !is_transient_status(http_err.code) => return Err(err),
+                Some(_) => false,
+                None => true,
+            };
+
+            if attempt >= self.retry.retries {
+                return Err(err);
+            }
+            attempt += 1;
+
+            let delay = backoff_delay(self.retry.delay, self.retry.max_delay, attempt as u32);
+            log::warn!(
+                "downloading chunk {} failed, retry {attempt}/{} in {}s - {err}",
+                hex::encode(digest),
+                self.retry.retries,
+                delay.as_secs_f64(),
+            );
+            tokio::time::sleep(delay).await;
+
+            if connection_broken {
+                self.reconnect(generation).await;
+            }
+        }
+    }
+
     /// Downloads raw chunk. This only verifies the (untrusted) CRC32, use
     /// DataBlob::verify_unencrypted or DataBlob::decode before storing/processing further.
+    ///
+    /// Failed downloads are retried according to the retry policy.
     pub async fn read_raw_chunk(&self, digest: &[u8; 32]) -> Result<DataBlob, Error> {
-        let mut chunk_data = Vec::with_capacity(4 * 1024 * 1024);
-
-        self.client.download_chunk(digest, &mut chunk_data).await?;
+        let chunk_data = self.download_chunk(digest).await?;
 
         let chunk = DataBlob::load_from_reader(&mut &chunk_data[..])
             .map_err(|err| format_err!("Failed to parse chunk {} - {err}", hex::encode(digest)))?;
@@ -81,7 +226,7 @@ impl ReadChunk for RemoteChunkReader {
 
         let chunk = ReadChunk::read_raw_chunk(self, digest)?;
 
-        let raw_data = chunk.decode(self.crypt_config.as_ref().map(Arc::as_ref), Some(digest))?;
+        let raw_data = self.decode_chunk(&chunk, digest)?;
 
         let use_cache = self.cache_hint.contains_key(digest);
         if use_cache {
@@ -111,8 +256,7 @@ impl AsyncReadChunk for RemoteChunkReader {
 
             let chunk = Self::read_raw_chunk(self, digest).await?;
 
-            let raw_data =
-                chunk.decode(self.crypt_config.as_ref().map(Arc::as_ref), Some(digest))?;
+            let raw_data = self.decode_chunk(&chunk, digest)?;
 
             let use_cache = self.cache_hint.contains_key(digest);
             if use_cache {
@@ -0,0 +1,99 @@
+//! Helpers shared by everything retrying failed requests
+//!
+//! Used for the per-chunk retries of the [`RemoteChunkReader`](crate::RemoteChunkReader) as well
+//! as for the automatic retries of failed jobs on the server.
+
+use std::time::Duration;
+
+use http::StatusCode;
+
+use proxmox_router::HttpError;
+
+/// Request timeouts, rate limiting and server errors are worth retrying, other errors are final.
+pub fn is_transient_status(code: StatusCode) -> bool {
+    code == StatusCode::REQUEST_TIMEOUT
+        || code == StatusCode::TOO_MANY_REQUESTS
+        || code.is_server_error()
+}
+
+/// Whether an error is a temporary network or server problem.
+///
+/// Only network specific errors count, a generic timeout (e.g. of a tape drive) is not
+/// transient.
+pub fn is_transient_error(err: &(dyn std::error::Error + 'static)) -> bool {
+    if let Some(HttpError { code, .. }) = err.downcast_ref::<HttpError>() {
+        is_transient_status(*code)
+    } else if let Some(err) = err.downcast_ref::<std::io::Error>() {
+        is_transient_io_error(err)
+    } else if let Some(errno) = err.downcast_ref::<nix::errno::Errno>() {
+        is_transient_io_error(&std::io::Error::from(*errno))
+    } else {
+        err.is::<hyper::Error>() || err.is::<h2::Error>()
+    }
+}
+
+fn is_transient_io_error(err: &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+
+    if let Some(nix::errno::Errno::EHOSTUNREACH | nix::errno::Errno::ENETUNREACH) =
+        err.raw_os_error().map(nix::errno::Errno::from_i32)
+    {
+        return true;
+    }
+
+    matches!(
+        err.kind(),
+        ErrorKind::ConnectionRefused
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::NotConnected
+            | ErrorKind::BrokenPipe
+    )
+}
+
+/// Exponential backoff, the delay before retry number `retry` (starting at 1).
+///
+/// Starts with `delay`, doubled for each further retry and capped at `max_delay`.
+pub fn backoff_delay(delay: Duration, max_delay: Duration, retry: u32) -> Duration {
+    delay
+        .saturating_mul(1 << retry.saturating_sub(1).min(16))
+        .min(max_delay)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_transient_error() {
+        let transient = |err: anyhow::Error| is_transient_error(&*err);
+
+        assert!(transient(
+            std::io::Error::from(std::io::ErrorKind::ConnectionReset).into()
+        ));
+        assert!(transient(nix::errno::Errno::ENETUNREACH.into()));
+        assert!(transient(
+            HttpError::new(StatusCode::SERVICE_UNAVAILABLE, "unavailable".into()).into()
+        ));
+
+        assert!(!transient(
+            HttpError::new(StatusCode::FORBIDDEN, "permission check failed".into()).into()
+        ));
+        assert!(!transient(
+            std::io::Error::from(std::io::ErrorKind::TimedOut).into()
+        ));
+        assert!(!transient(nix::errno::Errno::ETIMEDOUT.into()));
+        assert!(!transient(anyhow::format_err!("connection timed out")));
+    }
+
+    #[test]
+    fn test_backoff_delay() {
+        let delay = Duration::from_secs(10);
+        let max_delay = Duration::from_secs(60);
+
+        assert_eq!(backoff_delay(delay, max_delay, 1), Duration::from_secs(10));
+        assert_eq!(backoff_delay(delay, max_delay, 3), Duration::from_secs(40));
+        assert_eq!(backoff_delay(delay, max_delay, 4), max_delay);
+        assert_eq!(backoff_delay(delay, max_delay, u32::MAX), max_delay);
+    }
+}
@@ -0,0 +1,361 @@
+//! Service discovery for backup repositories.
+//!
+//! A repository host of the form `_pbs._tcp.<domain>` is resolved to a list of candidate
+//! servers. The candidates are looked up via DNS SRV records, or, if `PBS_DISCOVERY_URL` is set,
+//! fetched from that URL. Results are cached in the user's cache directory, so clients keep
+//! working with the last known servers if the lookup fails.
+
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::time::Duration;
+
+use anyhow::{bail, format_err, Error};
+use hyper::client::{Client, HttpConnector};
+use hyper::Body;
+use openssl::ssl::{SslConnector, SslMethod};
+use serde::{Deserialize, Serialize};
+
+use proxmox_http::client::HttpsConnector;
+use proxmox_sys::fs::{replace_file, CreateOptions};
+
+/// Prefix of repository hosts that are resolved via service discovery.
+pub const SERVICE_PREFIX: &str = "_pbs._tcp.";
+
+const ENV_VAR_PBS_DISCOVERY_URL: &str = "PBS_DISCOVERY_URL";
+
+/// Cache lifetime of discovery URL results, SRV results use the record TTL.
+const DISCOVERY_URL_TTL: i64 = 3600;
+const MIN_CACHE_TTL: i64 = 60;
+
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A server found via service discovery.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Candidate {
+    pub host: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    /// Lower values are preferred
+    #[serde(default)]
+    pub priority: u16,
+    /// Among candidates with the same priority, higher values are preferred
+    #[serde(default)]
+    pub weight: u16,
+}
+
+#[derive(Deserialize, Serialize)]
+struct CacheEntry {
+    expires: i64,
+    candidates: Vec<Candidate>,
+}
+
+/// Returns the candidate servers for `name`, ordered by preference.
+pub fn discover(name: &str) -> Result<Vec<Candidate>, Error> {
+    let now = proxmox_time::epoch_i64();
+
+    let cached = load_cache().remove(name);
+    if let Some(entry) = &cached {
+        if entry.expires > now && !entry.candidates.is_empty() {
+            return Ok(entry.candidates.clone());
+        }
+    }
+
+    let result = match std::env::var(ENV_VAR_PBS_DISCOVERY_URL) {
+        Ok(url) => query_discovery_url(&url, name).map(|list| (list, DISCOVERY_URL_TTL)),
+        Err(_) => query_srv(name),
+    }
+    .and_then(|(list, ttl)| {
+        if list.is_empty() {
+            bail!("no servers found");
+        }
+        Ok((list, ttl))
+    });
+
+    match result {
+        Ok((mut candidates, ttl)) => {
+            sort_candidates(&mut candidates);
+            let expires = now + ttl.max(MIN_CACHE_TTL);
+            if let Err(err) = store_cache(name, &candidates, expires) {
+                log::warn!("unable to cache discovered servers for {name} - {err}");
+            }
+            Ok(candidates)
+        }
+        Err(err) => match cached {
+            Some(entry) if !entry.candidates.is_empty() => {
+                log::warn!("service discovery for {name} failed, using cached servers - {err}");
+                Ok(entry.candidates)
+            }
+            _ => bail!("service discovery for {name} failed - {err}"),
+        },
+    }
+}
+
+fn sort_candidates(candidates: &mut [Candidate]) {
+    candidates.sort_by(|a, b| {
+        a.priority
+            .cmp(&b.priority)
+            .then_with(|| b.weight.cmp(&a.weight))
+    });
+}
+
+fn load_cache() -> HashMap<String, CacheEntry> {
+    let path = match crate::tools::base_directories().and_then(|base| {
+        base.find_cache_file("discovery.json")
+            .ok_or_else(|| format_err!("no cache file"))
+    }) {
+        Ok(path) => path,
+        Err(_) => return HashMap::new(),
+    };
+
+    std::fs::read(path)
+        .ok()
+        .and_then(|data| serde_json::from_slice(&data).ok())
+        .unwrap_or_default()
+}
+
+fn store_cache(name: &str, candidates: &[Candidate], expires: i64) -> Result<(), Error> {
+    let base = crate::tools::base_directories()?;
+    // usually ~/.cache/proxmox-backup/discovery.json
+    let path = base.place_cache_file("discovery.json")?;
+
+    let mut cache = load_cache();
+    cache.insert(
+        name.to_string(),
+        CacheEntry {
+            expires,
+            candidates: candidates.to_vec(),
+        },
+    );
+
+    let mode = nix::sys::stat::Mode::from_bits_truncate(0o0600);
+    replace_file(
+        path,
+        &serde_json::to_vec(&cache)?,
+        CreateOptions::new().perm(mode),
+        false,
+    )
+}
+
+/// Fetch the candidates from a discovery URL, which returns a JSON list of candidates.
+///
+/// The domain is passed as `domain` query parameter, so one URL can serve several domains.
+fn query_discovery_url(url: &str, name: &str) -> Result<Vec<Candidate>, Error> {
+    let domain = &name[SERVICE_PREFIX.len()..];
+    let separator = if url.contains('?') { '&' } else { '?' };
+    let uri: http::Uri = format!("{url}{separator}domain={domain}")
+        .parse()
+        .map_err(|err| format_err!("invalid discovery URL '{url}' - {err}"))?;
+
+    proxmox_async::runtime::block_on(async move {
+        let ssl_connector = SslConnector::builder(SslMethod::tls())?.build();
+        let mut httpc = HttpConnector::new();
+        httpc.enforce_http(false);
+        httpc.set_connect_timeout(Some(Duration::new(10, 0)));
+        let https = HttpsConnector::with_connector(
+            httpc,
+            ssl_connector,
+            crate::PROXMOX_BACKUP_TCP_KEEPALIVE_TIME,
+        );
+        let client = Client::builder().build::<_, Body>(https);
+
+        let resp = tokio::time::timeout(DISCOVERY_TIMEOUT, client.get(uri))
+            .await
+            .map_err(|_| format_err!("discovery URL request timed out"))??;
+        let status = resp.status();
+        let data = hyper::body::to_bytes(resp.into_body()).await?;
+        if !status.is_success() {
+            bail!("discovery URL request failed with status {status}");
+        }
+
+        serde_json::from_slice(&data)
+            .map_err(|err| format_err!("unable to parse discovery URL response - {err}"))
+    })
+}
+
+#[link(name = "resolv")]
+extern "C" {
+    fn res_query(
+        dname: *const libc::c_char,
+        class: libc::c_int,
+        type_: libc::c_int,
+        answer: *mut u8,
+        anslen: libc::c_int,
+    ) -> libc::c_int;
+}
+
+const DNS_CLASS_IN: u16 = 1;
+const DNS_TYPE_SRV: u16 = 33;
+
+/// Look up the SRV records of `name` with the system resolver.
+///
+/// Returns the candidates and the lowest record TTL.
+fn query_srv(name: &str) -> Result<(Vec<Candidate>, i64), Error> {
+    let dname = CString::new(name)?;
+    let mut answer = vec![0u8; 8192];
+
+    let len = unsafe {
+        res_query(
+            dname.as_ptr(),
+            DNS_CLASS_IN as libc::c_int,
+            DNS_TYPE_SRV as libc::c_int,
+            answer.as_mut_ptr(),
+            answer.len() as libc::c_int,
+        )
+    };
+    if len < 0 {
+        bail!("SRV lookup for {name} failed");
+    }
+    let len = (len as usize).min(answer.len());
+
+    parse_srv_response(&answer[..len])
+}
+
+fn read_u16(msg: &[u8], pos: usize) -> Result<u16, Error> {
+    match msg.get(pos..pos + 2) {
+        Some(data) => Ok(u16::from_be_bytes([data[0], data[1]])),
+        None => bail!("truncated DNS response"),
+    }
+}
+
+fn read_u32(msg: &[u8], pos: usize) -> Result<u32, Error> {
+    match msg.get(pos..pos + 4) {
+        Some(data) => Ok(u32::from_be_bytes([data[0], data[1], data[2], data[3]])),
+        None => bail!("truncated DNS response"),
+    }
+}
+
+/// Read a (possibly compressed) domain name, returns the name and the position after it.
+fn read_name(msg: &[u8], mut pos: usize) -> Result<(String, usize), Error> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    let mut jumps = 0;
+
+    loop {
+        let len = *msg
+            .get(pos)
+            .ok_or_else(|| format_err!("truncated DNS response"))? as usize;
+        match len & 0xc0 {
+            0xc0 => {
+                let offset = (read_u16(msg, pos)? & 0x3fff) as usize;
+                end.get_or_insert(pos + 2);
+                jumps += 1;
+                if jumps > 32 {
+                    bail!("DNS name compression loop");
+                }
+                pos = offset;
+            }
+            0 if len == 0 => {
+                let end = end.unwrap_or(pos + 1);
+                return Ok((labels.join("."), end));
+            }
+            0 => {
+                let label = msg
+                    .get(pos + 1..pos + 1 + len)
+                    .ok_or_else(|| format_err!("truncated DNS response"))?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                pos += 1 + len;
+            }
+            _ => bail!("invalid label in DNS response"),
+        }
+    }
+}
+
+/// Extract the SRV records from a DNS response message.
+fn parse_srv_response(msg: &[u8]) -> Result<(Vec<Candidate>, i64), Error> {
+    let question_count = read_u16(msg, 4)?;
+    let answer_count = read_u16(msg, 6)?;
+
+    let mut pos = 12;
+    for _ in 0..question_count {
+        pos = read_name(msg, pos)?.1 + 4; // type and class
+    }
+
+    let mut candidates = Vec::new();
+    let mut ttl = None;
+
+    for _ in 0..answer_count {
+        pos = read_name(msg, pos)?.1;
+        let record_type = read_u16(msg, pos)?;
+        let record_ttl = read_u32(msg, pos + 4)? as i64;
+        let data_len = read_u16(msg, pos + 8)? as usize;
+        pos += 10;
+
+        if record_type == DNS_TYPE_SRV {
+            let priority = read_u16(msg, pos)?;
+            let weight = read_u16(msg, pos + 2)?;
+            let port = read_u16(msg, pos + 4)?;
+            let (target, _) = read_name(msg, pos + 6)?;
+            // a target of "." means the service is not available in this domain
+            if !target.is_empty() {
+                candidates.push(Candidate {
+                    host: target,
+                    port: Some(port),
+                    priority,
+                    weight,
+                });
+                ttl = Some(ttl.map_or(record_ttl, |ttl: i64| ttl.min(record_ttl)));
+            }
+        }
+
+        pos += data_len;
+    }
+
+    Ok((candidates, ttl.unwrap_or(0)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn encode_name(msg: &mut Vec<u8>, name: &str) {
+        for label in name.split('.') {
+            msg.push(label.len() as u8);
+            msg.extend_from_slice(label.as_bytes());
+        }
+        msg.push(0);
+    }
+
+    fn srv_record(
+        msg: &mut Vec<u8>,
+        ttl: u32,
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: &str,
+    ) {
+        msg.extend_from_slice(&[0xc0, 12]); // pointer to the question name
+        msg.extend_from_slice(&DNS_TYPE_SRV.to_be_bytes());
+        msg.extend_from_slice(&DNS_CLASS_IN.to_be_bytes());
+        msg.extend_from_slice(&ttl.to_be_bytes());
+        let mut data = Vec::new();
+        data.extend_from_slice(&priority.to_be_bytes());
+        data.extend_from_slice(&weight.to_be_bytes());
+        data.extend_from_slice(&port.to_be_bytes());
+        encode_name(&mut data, target);
+        msg.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        msg.extend_from_slice(&data);
+    }
+
+    #[test]
+    fn test_parse_srv_response() -> Result<(), Error> {
+        let mut msg = vec![0x12, 0x34, 0x81, 0x80, 0, 1, 0, 2, 0, 0, 0, 0];
+        encode_name(&mut msg, "_pbs._tcp.example.com");
+        msg.extend_from_slice(&DNS_TYPE_SRV.to_be_bytes());
+        msg.extend_from_slice(&DNS_CLASS_IN.to_be_bytes());
+        srv_record(&mut msg, 300, 20, 0, 8007, "pbs-remote.example.com");
+        srv_record(&mut msg, 120, 10, 5, 8008, "pbs-local.example.com");
+
+        let (mut candidates, ttl) = parse_srv_response(&msg)?;
+        sort_candidates(&mut candidates);
+
+        assert_eq!(ttl, 120);
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].host, "pbs-local.example.com");
+        assert_eq!(candidates[0].port, Some(8008));
+        assert_eq!(candidates[1].host, "pbs-remote.example.com");
+
+        assert!(parse_srv_response(&msg[..msg.len() - 4]).is_err());
+
+        Ok(())
+    }
+}
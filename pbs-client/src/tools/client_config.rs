@@ -0,0 +1,119 @@
+//! Client configuration file with named profiles.
+//!
+//! Profiles are read from `client.cfg` in the system wide (usually `/etc/xdg/proxmox-backup/`)
+//! and in the per-user (usually `~/.config/proxmox-backup/`) config directory. A per-user profile
+//! replaces a system wide profile with the same name.
+
+use anyhow::{bail, format_err, Error};
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use serde_json::Value;
+
+use proxmox_human_byte::HumanByte;
+use proxmox_schema::{api, ApiType, Schema};
+use proxmox_section_config::{SectionConfig, SectionConfigPlugin};
+
+use pbs_api_types::BackupNamespace;
+
+use super::key_source::KEYFILE_SCHEMA;
+use super::{base_directories, PROFILE_SCHEMA, REPO_URL_SCHEMA};
+
+pub const CLIENT_CFG_FILENAME: &str = "client.cfg";
+
+const ENV_VAR_PBS_PROFILE: &str = "PBS_PROFILE";
+
+#[api(
+    properties: {
+        name: {
+            schema: PROFILE_SCHEMA,
+        },
+        repository: {
+            schema: REPO_URL_SCHEMA,
+            optional: true,
+        },
+        keyfile: {
+            schema: KEYFILE_SCHEMA,
+            optional: true,
+        },
+        rate: {
+            type: HumanByte,
+            optional: true,
+        },
+        burst: {
+            type: HumanByte,
+            optional: true,
+        },
+        ns: {
+            type: BackupNamespace,
+            optional: true,
+        },
+    },
+)]
+#[derive(Clone, Debug, Deserialize)]
+/// Client profile, provides defaults for the command line parameters.
+pub struct ClientProfile {
+    pub name: String,
+    pub repository: Option<String>,
+    pub keyfile: Option<String>,
+    pub rate: Option<HumanByte>,
+    pub burst: Option<HumanByte>,
+    pub ns: Option<BackupNamespace>,
+}
+
+lazy_static! {
+    static ref CONFIG: SectionConfig = init();
+}
+
+fn init() -> SectionConfig {
+    let obj_schema = match ClientProfile::API_SCHEMA {
+        Schema::Object(ref obj_schema) => obj_schema,
+        _ => unreachable!(),
+    };
+
+    let plugin =
+        SectionConfigPlugin::new("profile".to_string(), Some("name".to_string()), obj_schema);
+    let mut config = SectionConfig::new(&PROFILE_SCHEMA);
+    config.register_plugin(plugin);
+
+    config
+}
+
+/// Look up a profile in the client configuration files.
+pub fn lookup_profile(name: &str) -> Result<ClientProfile, Error> {
+    let mut profile = None;
+
+    // ordered from the lowest to the highest priority
+    for path in base_directories()?.find_config_files(CLIENT_CFG_FILENAME) {
+        let content = std::fs::read_to_string(&path)
+            .map_err(|err| format_err!("unable to read {path:?} - {err}"))?;
+        let data = CONFIG
+            .parse(&path.to_string_lossy(), &content)
+            .map_err(|err| format_err!("unable to parse {path:?} - {err}"))?;
+        if data.sections.contains_key(name) {
+            profile = Some(data.lookup("profile", name)?);
+        }
+    }
+
+    profile.ok_or_else(|| format_err!("client profile '{name}' not found"))
+}
+
+/// Returns the profile selected by name, or by the `PBS_PROFILE` environment variable.
+pub fn selected_profile(name: Option<&str>) -> Result<Option<ClientProfile>, Error> {
+    match name {
+        Some(name) => lookup_profile(name).map(Some),
+        None => match std::env::var(ENV_VAR_PBS_PROFILE) {
+            Ok(name) => lookup_profile(&name).map(Some),
+            Err(_) => Ok(None),
+        },
+    }
+}
+
+/// Returns the profile selected by the `profile` parameter or the `PBS_PROFILE` environment
+/// variable.
+pub fn profile_from_param(param: &Value) -> Result<Option<ClientProfile>, Error> {
+    match param.get("profile") {
+        Some(Value::String(name)) => selected_profile(Some(name)),
+        Some(_) => bail!("invalid profile parameter"),
+        None => selected_profile(None),
+    }
+}
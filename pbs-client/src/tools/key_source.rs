@@ -136,6 +136,15 @@ fn do_crypto_parameters(param: &Value, keep_keyfd_open: bool) -> Result<CryptoPa
         None => None,
     };
 
+    // like the default key, a profile key is not used if encryption is disabled explicitly
+    let profile_keyfile = match (keyfile, key_fd, &mode) {
+        (None, None, Some(CryptMode::None)) | (Some(_), _, _) | (_, Some(_), _) => None,
+        (None, None, _) => {
+            super::client_config::profile_from_param(param)?.and_then(|profile| profile.keyfile)
+        }
+    };
+    let keyfile = keyfile.or(profile_keyfile.as_ref());
+
     let key = match (keyfile, key_fd) {
         (None, None) => None,
         (Some(_), Some(_)) => bail!("--keyfile and --keyfd are mutually exclusive"),
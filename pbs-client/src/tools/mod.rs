@@ -5,6 +5,7 @@ use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::os::unix::io::FromRawFd;
 use std::process::Command;
+use std::sync::{Arc, Mutex};
 
 use anyhow::{bail, format_err, Context, Error};
 use serde_json::{json, Value};
@@ -12,13 +13,22 @@ use xdg::BaseDirectories;
 
 use proxmox_http::uri::json_object_to_query;
 use proxmox_router::cli::{complete_file_name, shellword_split};
+use proxmox_router::HttpError;
 use proxmox_schema::*;
 use proxmox_sys::fs::file_get_json;
 
-use pbs_api_types::{Authid, BackupNamespace, RateLimitConfig, UserWithTokens, BACKUP_REPO_URL};
+use pbs_api_types::{
+    Authid, BackupDir, BackupNamespace, RateLimitConfig, TokenClientProfile, UserWithTokens,
+    BACKUP_REPO_URL, PROXMOX_SAFE_ID_FORMAT,
+};
+use pbs_datastore::manifest::{archive_type, ArchiveType};
+use pbs_tools::crypt_config::CryptConfig;
 
-use crate::{BackupRepository, HttpClient, HttpClientOptions};
+use crate::{
+    BackupReader, BackupRepository, HttpClient, HttpClientOptions, ReconnectFn, ReconnectFuture,
+};
 
+pub mod client_config;
 pub mod key_source;
 
 const ENV_VAR_PBS_FINGERPRINT: &str = "PBS_FINGERPRINT";
@@ -29,6 +39,13 @@ pub const REPO_URL_SCHEMA: Schema = StringSchema::new("Repository URL.")
     .max_length(256)
     .schema();
 
+pub const PROFILE_SCHEMA: Schema =
+    StringSchema::new("Client profile name, see the client configuration file.")
+        .format(&PROXMOX_SAFE_ID_FORMAT)
+        .min_length(2)
+        .max_length(32)
+        .schema();
+
 pub const CHUNK_SIZE_SCHEMA: Schema = IntegerSchema::new("Chunk size in KB. Must be a power of 2.")
     .minimum(64)
     .maximum(4096)
@@ -117,14 +134,18 @@ pub fn get_default_repository() -> Option<String> {
     std::env::var("PBS_REPOSITORY").ok()
 }
 
+/// Get the repository from the parameters, the selected profile or the environment, in this
+/// order.
 pub fn extract_repository_from_value(param: &Value) -> Result<BackupRepository, Error> {
-    let repo_url = param["repository"]
-        .as_str()
-        .map(String::from)
-        .or_else(get_default_repository)
-        .ok_or_else(|| format_err!("unable to get (default) repository"))?;
+    let repo_url = match param["repository"].as_str() {
+        Some(repo_url) => Some(repo_url.to_string()),
+        None => client_config::profile_from_param(param)?.and_then(|profile| profile.repository),
+    }
+    .or_else(get_default_repository)
+    .ok_or_else(|| format_err!("unable to get (default) repository"))?;
 
-    let repo: BackupRepository = repo_url.parse()?;
+    let mut repo: BackupRepository = repo_url.parse()?;
+    repo.resolve()?;
 
     Ok(repo)
 }
@@ -133,8 +154,16 @@ pub fn extract_repository_from_map(param: &HashMap<String, String>) -> Option<Ba
     param
         .get("repository")
         .map(String::from)
+        .or_else(|| {
+            let profile = client_config::selected_profile(param.get("profile").map(String::as_str));
+            profile
+                .ok()
+                .flatten()
+                .and_then(|profile| profile.repository)
+        })
         .or_else(get_default_repository)
         .and_then(|repo_url| repo_url.parse::<BackupRepository>().ok())
+        .and_then(|mut repo| repo.resolve().ok().map(|_| repo))
 }
 
 pub fn connect(repo: &BackupRepository) -> Result<HttpClient, Error> {
@@ -151,6 +180,116 @@ pub fn connect_rate_limited(
         .map_err(|err| format_err!("error building client for repository {} - {}", repo, err))
 }
 
+/// Connect to the first reachable host of `repo`.
+///
+/// Returns the client and the index of its host in [BackupRepository::hosts].
+pub async fn connect_first_reachable(
+    repo: &BackupRepository,
+    rate_limit: RateLimitConfig,
+) -> Result<(HttpClient, usize), Error> {
+    let hosts = repo.hosts();
+    let mut last_err = None;
+
+    for (index, (host, port)) in hosts.iter().enumerate() {
+        let client = connect_do(host, *port, repo.auth_id(), rate_limit.clone())
+            .map_err(|err| format_err!("error building client for host {host} - {err}"))?;
+        match client.login().await {
+            Ok(_) => return Ok((client, index)),
+            Err(err) => {
+                if hosts.len() > 1 {
+                    log::warn!("connecting to {host} failed - {err}");
+                }
+                last_err = Some(err);
+            }
+        }
+    }
+
+    Err(last_err.unwrap_or_else(|| format_err!("repository {repo} has no hosts")))
+}
+
+/// Returns a [ReconnectFn] opening reader sessions for `snapshot` on the hosts of `repo`.
+///
+/// Each reconnect tries all hosts in turn, starting after the one currently used, which is
+/// `current` initially. The new session downloads the `indices`, given as file name and checksum
+/// from the original manifest, so that their chunks can be read. Hosts with a different version
+/// of the snapshot are skipped.
+pub fn reader_reconnect(
+    repo: &BackupRepository,
+    rate_limit: RateLimitConfig,
+    current: usize,
+    crypt_config: Option<Arc<CryptConfig>>,
+    ns: BackupNamespace,
+    snapshot: BackupDir,
+    indices: Vec<(String, [u8; 32])>,
+) -> ReconnectFn {
+    let repo = repo.clone();
+    let current = Arc::new(Mutex::new(current));
+
+    Arc::new(move || -> ReconnectFuture {
+        let repo = repo.clone();
+        let rate_limit = rate_limit.clone();
+        let current = Arc::clone(&current);
+        let crypt_config = crypt_config.clone();
+        let ns = ns.clone();
+        let snapshot = snapshot.clone();
+        let indices = indices.clone();
+
+        Box::pin(async move {
+            let hosts = repo.hosts();
+            let start = *current.lock().unwrap();
+
+            for offset in 1..=hosts.len() {
+                let index = (start + offset) % hosts.len();
+                let (host, port) = hosts[index];
+
+                let result: Result<Arc<BackupReader>, Error> = async {
+                    let client = connect_do(host, port, repo.auth_id(), rate_limit.clone())?;
+                    let reader = BackupReader::start(
+                        &client,
+                        crypt_config.clone(),
+                        repo.store(),
+                        &ns,
+                        &snapshot,
+                        false,
+                    )
+                    .await?;
+
+                    let (manifest, _) = reader.download_manifest().await?;
+                    for (name, csum) in indices.iter() {
+                        if manifest.lookup_file_info(name)?.csum != *csum {
+                            bail!("snapshot differs from the original one");
+                        }
+                        // downloading the index allows reading its chunks in this session
+                        match archive_type(name)? {
+                            ArchiveType::DynamicIndex => {
+                                reader.download_dynamic_index(&manifest, name).await?;
+                            }
+                            ArchiveType::FixedIndex => {
+                                reader.download_fixed_index(&manifest, name).await?;
+                            }
+                            ArchiveType::Blob => (),
+                        }
+                    }
+
+                    Ok(reader)
+                }
+                .await;
+
+                match result {
+                    Ok(reader) => {
+                        log::info!("continuing with new reader session on {host}");
+                        *current.lock().unwrap() = index;
+                        return Ok(reader);
+                    }
+                    Err(err) => log::warn!("opening reader session on {host} failed - {err}"),
+                }
+            }
+
+            bail!("no host of repository {repo} is reachable");
+        })
+    })
+}
+
 fn connect_do(
     server: &str,
     port: u16,
@@ -165,6 +304,21 @@ fn connect_do(
     HttpClient::new(server, port, auth_id, options)
 }
 
+/// Fetch the client profile the server suggests for the API token used by `client`.
+///
+/// Returns an empty profile if the server does not support client profiles.
+pub async fn fetch_token_profile(client: &HttpClient) -> Result<TokenClientProfile, Error> {
+    match client.get("api2/json/access/client-profile", None).await {
+        Ok(mut result) => Ok(serde_json::from_value(result["data"].take())?),
+        Err(err) => match err.downcast_ref::<HttpError>() {
+            Some(HttpError { code, .. }) if *code == http::StatusCode::NOT_FOUND => {
+                Ok(TokenClientProfile::default())
+            }
+            _ => Err(err),
+        },
+    }
+}
+
 /// like get, but simply ignore errors and return Null instead
 pub async fn try_get(repo: &BackupRepository, url: &str) -> Value {
     let fingerprint = std::env::var(ENV_VAR_PBS_FINGERPRINT).ok();
@@ -392,7 +546,7 @@ pub async fn complete_auth_id_do(param: &HashMap<String, String>) -> Vec<String>
         for user in parsed {
             result.push(user.userid.to_string());
             for token in user.tokens {
-                result.push(token.tokenid.to_string());
+                result.push(token.token.tokenid.to_string());
             }
         }
     };
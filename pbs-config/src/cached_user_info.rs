@@ -67,6 +67,11 @@ impl CachedUserInfo {
         Ok(config)
     }
 
+    /// Returns the configuration generation of the cached instance.
+    pub fn loaded_generation() -> usize {
+        CACHED_CONFIG.read().unwrap().last_user_cache_generation
+    }
+
     /// Only exposed for testing
     #[doc(hidden)]
     pub fn test_new(user_cfg: SectionConfigData, acl_tree: AclTree) -> Self {
@@ -168,6 +173,10 @@ impl CachedUserInfo {
         }
 
         if auth_id.is_token() {
+            if !self.token_subtree_contains(auth_id, path) {
+                return (0, 0);
+            }
+
             // limit privs to that of owning user
             let user_auth_id = Authid::from(auth_id.user().clone());
             let (owner_privs, owner_propagated_privs) =
@@ -179,6 +188,25 @@ impl CachedUserInfo {
         (privs, propagated_privs)
     }
 
+    // whether `path` is within the subtree the token is restricted to, if any
+    fn token_subtree_contains(&self, auth_id: &Authid, path: &[&str]) -> bool {
+        let token = match self
+            .user_cfg
+            .lookup::<ApiToken>("token", &auth_id.to_string())
+        {
+            Ok(token) => token,
+            Err(_) => return true,
+        };
+
+        match token.subtree {
+            Some(subtree) => {
+                let subtree = crate::acl::split_acl_path(&subtree);
+                path.starts_with(&subtree)
+            }
+            None => true,
+        }
+    }
+
     /// Checks whether the `auth_id` has any of the privilegs `privs` on any object below `path`.
     pub fn any_privs_below(
         &self,
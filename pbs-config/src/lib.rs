@@ -4,6 +4,7 @@ pub use cached_user_info::CachedUserInfo;
 pub mod datastore;
 pub mod domains;
 pub mod drive;
+pub mod holiday;
 pub mod media_pool;
 pub mod metrics;
 pub mod network;
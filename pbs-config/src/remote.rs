@@ -47,7 +47,13 @@ pub fn config() -> Result<(SectionConfigData, [u8; 32]), Error> {
 
 pub fn save_config(config: &SectionConfigData) -> Result<(), Error> {
     let raw = CONFIG.write(REMOTE_CFG_FILENAME, config)?;
-    crate::replace_backup_config(REMOTE_CFG_FILENAME, raw.as_bytes())
+    crate::replace_backup_config(REMOTE_CFG_FILENAME, raw.as_bytes())?;
+
+    // remotes are read on every use, the version only tells about changes
+    let version_cache = crate::ConfigVersionCache::new()?;
+    version_cache.increase_remote_generation();
+
+    Ok(())
 }
 
 // shell completion helper
@@ -131,11 +131,13 @@ impl BackupGroup {
                 let files = list_backup_files(l2_fd, backup_time)?;
 
                 let protected = backup_dir.is_protected();
+                let retained_until = backup_dir.retention_locked_until();
 
                 list.push(BackupInfo {
                     backup_dir,
                     files,
                     protected,
+                    retained_until,
                 });
 
                 Ok(())
@@ -238,7 +240,7 @@ impl BackupGroup {
         let mut delete_stats = BackupGroupDeleteStats::default();
         for snap in self.iter_snapshots()? {
             let snap = snap?;
-            if snap.is_protected() {
+            if snap.is_protected() || snap.is_retention_locked() {
                 delete_stats.increment_protected_snapshots();
                 continue;
             }
@@ -429,6 +431,41 @@ impl BackupDir {
         path.exists()
     }
 
+    pub fn retention_file(&self) -> PathBuf {
+        let mut path = self.full_path();
+        path.push(".retain-until");
+        path
+    }
+
+    /// Returns the end of the minimum retention period the snapshot was created with, if any.
+    pub fn retained_until(&self) -> Option<i64> {
+        std::fs::read_to_string(self.retention_file())
+            .ok()
+            .and_then(|content| content.trim().parse().ok())
+    }
+
+    /// Returns the end of the minimum retention period while the snapshot is within it.
+    pub fn retention_locked_until(&self) -> Option<i64> {
+        self.retained_until()
+            .filter(|until| *until > proxmox_time::epoch_i64())
+    }
+
+    /// Returns true while the snapshot is within its minimum retention period.
+    pub fn is_retention_locked(&self) -> bool {
+        self.retention_locked_until().is_some()
+    }
+
+    /// Set the end of the minimum retention period, the snapshot cannot be removed before.
+    pub fn set_retained_until(&self, until: i64) -> Result<(), Error> {
+        replace_file(
+            self.retention_file(),
+            until.to_string().as_bytes(),
+            CreateOptions::new(),
+            false,
+        )
+        .map_err(|err| format_err!("could not create retention file - {err}"))
+    }
+
     pub fn backup_time_to_string(backup_time: i64) -> Result<String, Error> {
         // fixme: can this fail? (avoid unwrap)
         proxmox_time::epoch_to_rfc3339_utc(backup_time)
@@ -485,6 +522,14 @@ impl BackupDir {
         if self.is_protected() {
             bail!("cannot remove protected snapshot"); // use special error type?
         }
+        if let Some(until) = self.retained_until() {
+            if until > proxmox_time::epoch_i64() {
+                bail!(
+                    "cannot remove snapshot before the end of its minimum retention period ({})",
+                    proxmox_time::epoch_to_rfc3339_utc(until)?,
+                );
+            }
+        }
 
         log::info!("removing backup snapshot {:?}", full_path);
         std::fs::remove_dir_all(&full_path).map_err(|err| {
@@ -548,6 +593,38 @@ impl BackupDir {
         Ok(())
     }
 
+    /// Turn the snapshot into a stub pointing to its copy on the datastore `archive_store`.
+    ///
+    /// Marks the manifest as archived and removes all archives listed in it, the manifest itself
+    /// and the client log are kept. The caller must hold the snapshot lock and ensure that the
+    /// archive datastore contains a complete copy of the snapshot.
+    pub fn convert_to_archive_stub(&self, archive_store: &str) -> Result<(), Error> {
+        let (manifest, _) = self.load_manifest()?;
+        if manifest.archived_to().is_some() {
+            bail!("snapshot {self:?} is already archived");
+        }
+
+        self.update_manifest(|manifest| {
+            manifest.unprotected["archived"] = serde_json::json!({
+                "store": archive_store,
+                "time": proxmox_time::epoch_i64(),
+            });
+        })?;
+
+        for item in manifest.files() {
+            let mut path = self.full_path();
+            path.push(&item.filename);
+            match std::fs::remove_file(&path) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                    bail!("removing archived file {path:?} failed - {err}");
+                }
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
     /// Cleans up the backup directory by removing any file not mentioned in the manifest.
     pub fn cleanup_unreferenced_files(&self, manifest: &BackupManifest) -> Result<(), Error> {
         let full_path = self.full_path();
@@ -634,6 +711,8 @@ pub struct BackupInfo {
     pub files: Vec<String>,
     /// Protection Status
     pub protected: bool,
+    /// End of the minimum retention period, while the snapshot is within it
+    pub retained_until: Option<i64>,
 }
 
 impl BackupInfo {
@@ -642,11 +721,13 @@ impl BackupInfo {
 
         let files = list_backup_files(libc::AT_FDCWD, &path)?;
         let protected = backup_dir.is_protected();
+        let retained_until = backup_dir.retention_locked_until();
 
         Ok(BackupInfo {
             backup_dir,
             files,
             protected,
+            retained_until,
         })
     }
 
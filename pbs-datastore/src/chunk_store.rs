@@ -13,6 +13,7 @@ use proxmox_sys::process_locker::{
 use proxmox_sys::task_log;
 use proxmox_sys::WorkerTaskContext;
 
+use crate::fault_injection::{self, FaultPoint};
 use crate::file_formats::{
     COMPRESSED_BLOB_MAGIC_1_0, ENCRYPTED_BLOB_MAGIC_1_0, UNCOMPRESSED_BLOB_MAGIC_1_0,
 };
@@ -439,6 +440,81 @@ impl ChunkStore {
         Ok(())
     }
 
+    /// Directory of the deduplicated blobs, named by the checksum of their raw data.
+    pub fn blob_dir(&self) -> PathBuf {
+        self.base.join(".blobs")
+    }
+
+    /// Store the raw blob `data` with checksum `csum` at `target`.
+    ///
+    /// The target is a hard link to the copy in the blob directory, which is reused by all
+    /// snapshots containing the same blob, so they share one inode and its data. Returns true
+    /// if an existing copy was reused.
+    pub fn insert_dedup_blob(
+        &self,
+        data: &[u8],
+        csum: &[u8; 32],
+        target: &Path,
+    ) -> Result<bool, Error> {
+        let blob_dir = self.blob_dir();
+        let blob_path = blob_dir.join(hex::encode(csum));
+
+        match std::fs::hard_link(&blob_path, target) {
+            Ok(()) => return Ok(true),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => bail!("linking blob {blob_path:?} to {target:?} failed - {err}"),
+        }
+
+        proxmox_sys::fs::replace_file(
+            target,
+            data,
+            CreateOptions::new(),
+            self.sync_level == DatastoreFSyncLevel::File,
+        )?;
+
+        create_path(&blob_dir, None, None)?;
+        match std::fs::hard_link(target, &blob_path) {
+            // a concurrent backup stored the same blob, this copy just stays separate
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(err) => bail!("linking {target:?} to blob {blob_path:?} failed - {err}"),
+        }
+
+        Ok(false)
+    }
+
+    /// Remove the deduplicated blobs which are not linked by any snapshot anymore.
+    ///
+    /// Returns the number and size of the removed blobs.
+    pub fn sweep_unused_blobs(&self, worker: &dyn WorkerTaskContext) -> Result<(u64, u64), Error> {
+        use std::os::unix::fs::MetadataExt;
+
+        let blob_dir = self.blob_dir();
+        let entries = match std::fs::read_dir(&blob_dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok((0, 0)),
+            Err(err) => bail!("unable to read blob dir {blob_dir:?} - {err}"),
+        };
+
+        let mut removed = 0;
+        let mut removed_bytes = 0;
+        for entry in entries {
+            worker.check_abort()?;
+            worker.fail_on_shutdown()?;
+
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            // the copy in the blob directory is the only link left
+            if metadata.is_file() && metadata.nlink() == 1 {
+                std::fs::remove_file(entry.path())?;
+                removed += 1;
+                removed_bytes += metadata.len();
+            }
+        }
+
+        Ok((removed, removed_bytes))
+    }
+
     pub fn insert_chunk(&self, chunk: &DataBlob, digest: &[u8; 32]) -> Result<(bool, u64), Error> {
         // unwrap: only `None` in unit tests
         assert!(self.locker.is_some());
@@ -500,6 +576,10 @@ impl ChunkStore {
             .parent()
             .ok_or_else(|| format_err!("unable to get chunk dir"))?;
 
+        if let Some(fault) = fault_injection::check(FaultPoint::ChunkInsert, &chunk_path) {
+            return Err(fault_injection::inject(fault, &chunk_path, raw_data));
+        }
+
         proxmox_sys::fs::replace_file(
             &chunk_path,
             raw_data,
@@ -554,6 +634,11 @@ impl ChunkStore {
         self.base.clone()
     }
 
+    /// The mutex serializing modifications of chunk files.
+    pub(crate) fn mutex(&self) -> &Mutex<()> {
+        &self.mutex
+    }
+
     pub fn try_shared_lock(&self) -> Result<ProcessLockSharedGuard, Error> {
         // unwrap: only `None` in unit tests
         ProcessLocker::try_shared_lock(self.locker.clone().unwrap())
@@ -0,0 +1,325 @@
+//! Secondary storage for rarely used chunks.
+//!
+//! Offloaded chunks are moved to the cold tier directory, using the same `.chunks/<prefix>/`
+//! layout as the chunk store. An empty stub file stays at the original location in the chunk
+//! store, so existence checks, garbage collection marking and deduplication keep working
+//! unchanged. Reads of a stub are transparently served from the cold tier.
+
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, format_err, Error};
+
+use proxmox_sys::{task_log, WorkerTaskContext};
+
+use pbs_api_types::GarbageCollectionStatus;
+
+use crate::ChunkStore;
+
+/// Chunks referenced by hot snapshots, and chunks only referenced by cold ones, collected while
+/// marking the used chunks of a datastore.
+#[derive(Default)]
+pub struct ChunkUsage {
+    hot: HashSet<[u8; 32]>,
+    cold: HashSet<[u8; 32]>,
+}
+
+impl ChunkUsage {
+    /// Record a chunk referenced by a hot or a cold snapshot.
+    pub fn mark(&mut self, digest: &[u8; 32], hot: bool) {
+        if hot {
+            if self.hot.insert(*digest) {
+                self.cold.remove(digest);
+            }
+        } else if !self.hot.contains(digest) {
+            self.cold.insert(*digest);
+        }
+    }
+
+    /// Returns whether the chunk is only referenced by cold snapshots.
+    pub fn is_cold(&self, digest: &[u8; 32]) -> bool {
+        self.cold.contains(digest)
+    }
+}
+
+// only chunks which exist, are not a stub yet and were not accessed recently are offloaded
+fn is_offload_candidate(hot_path: &Path, touched_after: i64) -> Result<bool, Error> {
+    match std::fs::metadata(hot_path) {
+        Ok(metadata) => Ok(metadata.len() > 0 && metadata.atime() < touched_after),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(err) => bail!("unable to stat chunk {hot_path:?} - {err}"),
+    }
+}
+
+// reading the chunk must not update its access time, which marks chunks in use
+fn read_noatime(path: &Path) -> Result<Vec<u8>, Error> {
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NOATIME)
+        .open(path);
+    let mut file = match file {
+        Ok(file) => file,
+        // O_NOATIME is only allowed for the owner of the file
+        Err(err) if err.raw_os_error() == Some(libc::EPERM) => std::fs::File::open(path)?,
+        Err(err) => return Err(err.into()),
+    };
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+pub struct ColdTier {
+    base: PathBuf,
+    /// Chunks only referenced by snapshots older than this many seconds are offloaded
+    after: i64,
+}
+
+impl ColdTier {
+    pub fn new<P: Into<PathBuf>>(base: P, after_days: u64) -> Self {
+        Self {
+            base: base.into(),
+            after: (after_days as i64) * 86400,
+        }
+    }
+
+    /// Snapshots started before the returned time do not keep their chunks in the hot tier.
+    pub fn cutoff(&self, now: i64) -> i64 {
+        now - self.after
+    }
+
+    fn chunk_dir(&self, digest_str: &str) -> PathBuf {
+        let mut path = self.base.clone();
+        path.push(".chunks");
+        path.push(&digest_str[0..4]);
+        path
+    }
+
+    /// Path of a chunk in the cold tier, whether it exists or not.
+    pub fn chunk_path(&self, digest: &[u8; 32]) -> PathBuf {
+        let digest_str = hex::encode(digest);
+        let mut path = self.chunk_dir(&digest_str);
+        path.push(digest_str);
+        path
+    }
+
+    /// Returns whether the chunk file at `hot_path` is a stub for an offloaded chunk.
+    pub fn is_stub(&self, hot_path: &Path, digest: &[u8; 32]) -> bool {
+        match std::fs::metadata(hot_path) {
+            Ok(metadata) if metadata.is_file() && metadata.len() == 0 => {
+                self.chunk_path(digest).exists()
+            }
+            _ => false,
+        }
+    }
+
+    /// Move a chunk to the cold tier and replace it with a stub, returns the offloaded size.
+    ///
+    /// Chunks accessed at or after `touched_after` are in use, for example by a running backup,
+    /// and stay in the hot tier, as do chunks which vanished or already are a stub. The stub
+    /// replaces the chunk while holding the chunk store mutex, so concurrent inserts of the same
+    /// chunk see either the chunk or the stub. Readers holding the chunk open are not affected.
+    pub fn offload_chunk(
+        &self,
+        chunk_store: &ChunkStore,
+        digest: &[u8; 32],
+        touched_after: i64,
+    ) -> Result<Option<u64>, Error> {
+        let (hot_path, digest_str) = chunk_store.chunk_path(digest);
+        if !is_offload_candidate(&hot_path, touched_after)? {
+            return Ok(None);
+        }
+
+        let cold_dir = self.chunk_dir(&digest_str);
+        std::fs::create_dir_all(&cold_dir)?;
+        let mut cold_path = cold_dir;
+        cold_path.push(&digest_str);
+
+        let data = read_noatime(&hot_path)?;
+        let mut tmp_path = cold_path.clone();
+        tmp_path.set_extension("tmp");
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o644)
+            .open(&tmp_path)?;
+        file.write_all(&data)?;
+        file.sync_all()?;
+        drop(file);
+        std::fs::rename(&tmp_path, &cold_path)
+            .map_err(|err| format_err!("moving chunk {digest_str} to cold tier failed - {err}"))?;
+
+        let _lock = chunk_store.mutex().lock().unwrap();
+
+        // a backup might have used the chunk while it was copied, the copy in the cold tier is
+        // then removed by the next sweep
+        if !is_offload_candidate(&hot_path, touched_after)? {
+            return Ok(None);
+        }
+
+        let mut stub_path = hot_path.clone();
+        stub_path.set_extension("stub");
+        std::fs::File::create(&stub_path)?;
+        std::fs::rename(&stub_path, &hot_path)
+            .map_err(|err| format_err!("replacing chunk {digest_str} with stub failed - {err}"))?;
+
+        Ok(Some(data.len() as u64))
+    }
+
+    /// Offload the chunks only referenced by cold snapshots, see [`ColdTier::offload_chunk`].
+    ///
+    /// Chunks not referenced by any snapshot are never offloaded, they either belong to a running
+    /// backup or are removed by garbage collection.
+    pub fn offload_chunks(
+        &self,
+        chunk_store: &ChunkStore,
+        usage: &ChunkUsage,
+        touched_after: i64,
+        worker: &dyn WorkerTaskContext,
+    ) -> Result<(usize, u64), Error> {
+        let mut count = 0;
+        let mut bytes = 0;
+
+        for digest in usage.cold.iter() {
+            worker.check_abort()?;
+            worker.fail_on_shutdown()?;
+
+            if let Some(size) = self.offload_chunk(chunk_store, digest, touched_after)? {
+                bytes += size;
+                count += 1;
+            }
+        }
+
+        Ok((count, bytes))
+    }
+
+    /// Remove cold chunks whose stub vanished or was replaced by a re-uploaded chunk.
+    ///
+    /// Updates the cold tier statistics of `status`.
+    pub fn sweep(
+        &self,
+        chunk_store: &ChunkStore,
+        status: &mut GarbageCollectionStatus,
+        worker: &dyn WorkerTaskContext,
+    ) -> Result<(), Error> {
+        let mut chunk_dir = self.base.clone();
+        chunk_dir.push(".chunks");
+
+        let prefixes = match std::fs::read_dir(&chunk_dir) {
+            Ok(prefixes) => prefixes,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => bail!("unable to read cold tier {chunk_dir:?} - {err}"),
+        };
+
+        let mut removed = 0;
+        for prefix in prefixes {
+            for entry in std::fs::read_dir(prefix?.path())? {
+                worker.check_abort()?;
+                worker.fail_on_shutdown()?;
+
+                let entry = entry?;
+                let path = entry.path();
+                let mut digest = [0u8; 32];
+                let is_chunk = entry
+                    .file_name()
+                    .to_str()
+                    .map(|name| hex::decode_to_slice(name, &mut digest).is_ok())
+                    .unwrap_or(false);
+                if !is_chunk {
+                    continue; // leftover temporary file
+                }
+
+                let (hot_path, _) = chunk_store.chunk_path(&digest);
+                match std::fs::metadata(&hot_path) {
+                    Ok(metadata) if metadata.len() == 0 => {
+                        status.cold_chunks += 1;
+                        status.cold_bytes += entry.metadata()?.len();
+                    }
+                    _ => {
+                        std::fs::remove_file(&path)
+                            .map_err(|err| format_err!("removing {path:?} failed - {err}"))?;
+                        removed += 1;
+                    }
+                }
+            }
+        }
+
+        task_log!(worker, "Removed cold tier chunks: {removed}");
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use pbs_api_types::DatastoreFSyncLevel;
+
+    use crate::data_blob::DataChunkBuilder;
+
+    #[test]
+    fn test_chunk_usage() {
+        let mut usage = ChunkUsage::default();
+
+        usage.mark(&[1u8; 32], false);
+        usage.mark(&[2u8; 32], false);
+        usage.mark(&[2u8; 32], true);
+        usage.mark(&[3u8; 32], true);
+        usage.mark(&[3u8; 32], false);
+
+        assert!(usage.is_cold(&[1u8; 32]));
+        assert!(!usage.is_cold(&[2u8; 32]));
+        assert!(!usage.is_cold(&[3u8; 32]));
+        assert!(!usage.is_cold(&[4u8; 32]));
+    }
+
+    #[test]
+    fn test_offload_chunk() -> Result<(), Error> {
+        let base = std::env::temp_dir().join(format!("pbs-cold-tier-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+
+        let user = nix::unistd::User::from_uid(nix::unistd::Uid::current())?.unwrap();
+        let chunk_store = ChunkStore::create(
+            "test",
+            base.join("hot"),
+            user.uid,
+            user.gid,
+            None,
+            DatastoreFSyncLevel::None,
+        )?;
+        let cold_tier = ColdTier::new(base.join("cold"), 30);
+
+        let (chunk, digest) = DataChunkBuilder::new(b"cold data").build()?;
+        chunk_store.insert_chunk(&chunk, &digest)?;
+        let (hot_path, _) = chunk_store.chunk_path(&digest);
+
+        // just inserted, so the chunk counts as in use
+        let inserted = proxmox_time::epoch_i64();
+        assert_eq!(
+            cold_tier.offload_chunk(&chunk_store, &digest, inserted - 60)?,
+            None
+        );
+        assert!(!cold_tier.is_stub(&hot_path, &digest));
+
+        let size = cold_tier.offload_chunk(&chunk_store, &digest, inserted + 60)?;
+        assert_eq!(size, Some(chunk.raw_data().len() as u64));
+        assert!(cold_tier.is_stub(&hot_path, &digest));
+        assert_eq!(
+            std::fs::read(cold_tier.chunk_path(&digest))?,
+            chunk.raw_data()
+        );
+
+        // a stub is not offloaded again
+        assert_eq!(
+            cold_tier.offload_chunk(&chunk_store, &digest, inserted + 60)?,
+            None
+        );
+
+        let _ = std::fs::remove_dir_all(&base);
+
+        Ok(())
+    }
+}
@@ -12,6 +12,40 @@ use super::file_formats::*;
 
 const MAX_BLOB_SIZE: usize = 128 * 1024 * 1024;
 
+/// zstd level used for chunks and blobs.
+pub const DEFAULT_COMPRESSION_LEVEL: i32 = 1;
+
+/// zstd level used for the manifest and the catalog.
+///
+/// Those are small compared to the backed up data, but read on every listing and file restore, so
+/// trading some compression speed for a smaller size pays off. The level is not recorded in the
+/// blob, zstd frames can be decoded regardless of the level used.
+pub const METADATA_COMPRESSION_LEVEL: i32 = 9;
+
+/// Compute the digest of unencrypted chunk data.
+///
+/// Datastores with a digest salt mix it into the digest, like encrypted chunks use a secret
+/// derived from the key, so that digests of known data cannot be computed without it.
+pub fn compute_unencrypted_digest(data: &[u8], salt: Option<&[u8; 32]>) -> [u8; 32] {
+    match salt {
+        Some(salt) => {
+            let mut hasher = openssl::sha::Sha256::new();
+            hasher.update(data);
+            hasher.update(salt); // at the end, to avoid length extensions attacks
+            hasher.finish()
+        }
+        None => openssl::sha::sha256(data),
+    }
+}
+
+/// Fingerprint of a digest salt.
+///
+/// Stored along with exported chunks, to check whether they can be imported into a datastore
+/// without revealing the salt itself.
+pub fn digest_salt_fingerprint(salt: &[u8; 32]) -> [u8; 32] {
+    openssl::sha::sha256(salt)
+}
+
 /// Encoded data chunk with digest and positional information
 pub struct ChunkInfo {
     pub chunk: DataBlob,
@@ -88,6 +122,16 @@ impl DataBlob {
         data: &[u8],
         config: Option<&CryptConfig>,
         compress: bool,
+    ) -> Result<Self, Error> {
+        let level = compress.then_some(DEFAULT_COMPRESSION_LEVEL);
+        Self::encode_with_level(data, config, level)
+    }
+
+    /// Create a DataBlob, compressed with the zstd `level` if set and optionally encrypted
+    pub fn encode_with_level(
+        data: &[u8],
+        config: Option<&CryptConfig>,
+        level: Option<i32>,
     ) -> Result<Self, Error> {
         if data.len() > MAX_BLOB_SIZE {
             bail!("data blob too large ({} bytes).", data.len());
@@ -95,8 +139,8 @@ impl DataBlob {
 
         let mut blob = if let Some(config) = config {
             let compr_data;
-            let (_compress, data, magic) = if compress {
-                compr_data = zstd::bulk::compress(data, 1)?;
+            let (_compress, data, magic) = if let Some(level) = level {
+                compr_data = zstd::bulk::compress(data, level)?;
                 // Note: We only use compression if result is shorter
                 if compr_data.len() < data.len() {
                     (true, &compr_data[..], ENCR_COMPR_BLOB_MAGIC_1_0)
@@ -137,7 +181,7 @@ impl DataBlob {
             DataBlob { raw_data }
         } else {
             let max_data_len = data.len() + std::mem::size_of::<DataBlobHeader>();
-            if compress {
+            if let Some(level) = level {
                 let mut comp_data = Vec::with_capacity(max_data_len);
 
                 let head = DataBlobHeader {
@@ -148,7 +192,7 @@ impl DataBlob {
                     comp_data.write_le_value(head)?;
                 }
 
-                zstd::stream::copy_encode(data, &mut comp_data, 1)?;
+                zstd::stream::copy_encode(data, &mut comp_data, level)?;
 
                 if comp_data.len() < max_data_len {
                     let mut blob = DataBlob {
@@ -198,6 +242,16 @@ impl DataBlob {
         &self,
         config: Option<&CryptConfig>,
         digest: Option<&[u8; 32]>,
+    ) -> Result<Vec<u8>, Error> {
+        self.decode_salted(config, digest, None)
+    }
+
+    /// Decode blob data, verifying the digest of unencrypted data with a datastore digest salt
+    pub fn decode_salted(
+        &self,
+        config: Option<&CryptConfig>,
+        digest: Option<&[u8; 32]>,
+        salt: Option<&[u8; 32]>,
     ) -> Result<Vec<u8>, Error> {
         let magic = self.magic();
 
@@ -205,7 +259,7 @@ impl DataBlob {
             let data_start = std::mem::size_of::<DataBlobHeader>();
             let data = self.raw_data[data_start..].to_vec();
             if let Some(digest) = digest {
-                Self::verify_digest(&data, None, digest)?;
+                Self::verify_digest(&data, None, salt, digest)?;
             }
             Ok(data)
         } else if magic == &COMPRESSED_BLOB_MAGIC_1_0 {
@@ -215,7 +269,7 @@ impl DataBlob {
             // zstd::block::decompress is abou 10% slower
             // let data = zstd::block::decompress(&self.raw_data[data_start..], MAX_BLOB_SIZE)?;
             if let Some(digest) = digest {
-                Self::verify_digest(&data, None, digest)?;
+                Self::verify_digest(&data, None, salt, digest)?;
             }
             Ok(data)
         } else if magic == &ENCR_COMPR_BLOB_MAGIC_1_0 || magic == &ENCRYPTED_BLOB_MAGIC_1_0 {
@@ -241,7 +295,7 @@ impl DataBlob {
                     )?
                 };
                 if let Some(digest) = digest {
-                    Self::verify_digest(&data, Some(config), digest)?;
+                    Self::verify_digest(&data, Some(config), None, digest)?;
                 }
                 Ok(data)
             } else {
@@ -311,6 +365,16 @@ impl DataBlob {
         &self,
         expected_chunk_size: usize,
         expected_digest: &[u8; 32],
+    ) -> Result<(), Error> {
+        self.verify_unencrypted_salted(expected_chunk_size, expected_digest, None)
+    }
+
+    /// Like [`verify_unencrypted`](Self::verify_unencrypted), for datastores with a digest salt.
+    pub fn verify_unencrypted_salted(
+        &self,
+        expected_chunk_size: usize,
+        expected_digest: &[u8; 32],
+        salt: Option<&[u8; 32]>,
     ) -> Result<(), Error> {
         let magic = self.magic();
 
@@ -319,7 +383,7 @@ impl DataBlob {
         }
 
         // verifies digest!
-        let data = self.decode(None, Some(expected_digest))?;
+        let data = self.decode_salted(None, Some(expected_digest), salt)?;
 
         if expected_chunk_size != data.len() {
             bail!(
@@ -335,11 +399,12 @@ impl DataBlob {
     fn verify_digest(
         data: &[u8],
         config: Option<&CryptConfig>,
+        salt: Option<&[u8; 32]>,
         expected_digest: &[u8; 32],
     ) -> Result<(), Error> {
         let digest = match config {
             Some(config) => config.compute_digest(data),
-            None => openssl::sha::sha256(data),
+            None => compute_unencrypted_digest(data, salt),
         };
         if &digest != expected_digest {
             bail!("detected chunk with wrong digest.");
@@ -476,10 +541,12 @@ impl DataBlob {
 /// we always compute the correct one.
 pub struct DataChunkBuilder<'a, 'b> {
     config: Option<&'b CryptConfig>,
+    digest_salt: Option<&'b [u8; 32]>,
     orig_data: &'a [u8],
     digest_computed: bool,
     digest: [u8; 32],
     compress: bool,
+    compression_level: i32,
 }
 
 impl<'a, 'b> DataChunkBuilder<'a, 'b> {
@@ -488,20 +555,28 @@ impl<'a, 'b> DataChunkBuilder<'a, 'b> {
         Self {
             orig_data,
             config: None,
+            digest_salt: None,
             digest_computed: false,
             digest: [0u8; 32],
             compress: true,
+            compression_level: DEFAULT_COMPRESSION_LEVEL,
         }
     }
 
     /// Set compression flag.
     ///
-    /// If true, chunk data is compressed using zstd (level 1).
+    /// If true, chunk data is compressed using zstd (level 1 by default).
     pub fn compress(mut self, value: bool) -> Self {
         self.compress = value;
         self
     }
 
+    /// Set the zstd compression level.
+    pub fn compression_level(mut self, level: i32) -> Self {
+        self.compression_level = level;
+        self
+    }
+
     /// Set encryption Configuration
     ///
     /// If set, chunks are encrypted
@@ -513,12 +588,23 @@ impl<'a, 'b> DataChunkBuilder<'a, 'b> {
         self
     }
 
+    /// Set the digest salt of the target datastore
+    ///
+    /// Only used for unencrypted chunks, encrypted ones already use a secret digest.
+    pub fn digest_salt(mut self, value: Option<&'b [u8; 32]>) -> Self {
+        if self.digest_computed {
+            panic!("unable to set digest_salt after compute_digest().");
+        }
+        self.digest_salt = value;
+        self
+    }
+
     fn compute_digest(&mut self) {
         if !self.digest_computed {
             if let Some(config) = self.config {
                 self.digest = config.compute_digest(self.orig_data);
             } else {
-                self.digest = openssl::sha::sha256(self.orig_data);
+                self.digest = compute_unencrypted_digest(self.orig_data, self.digest_salt);
             }
             self.digest_computed = true;
         }
@@ -543,7 +629,8 @@ impl<'a, 'b> DataChunkBuilder<'a, 'b> {
             self.compute_digest();
         }
 
-        let chunk = DataBlob::encode(self.orig_data, self.config, self.compress)?;
+        let level = self.compress.then_some(self.compression_level);
+        let chunk = DataBlob::encode_with_level(self.orig_data, self.config, level)?;
         Ok((chunk, self.digest))
     }
 
@@ -19,17 +19,21 @@ use proxmox_sys::WorkerTaskContext;
 use proxmox_sys::{task_log, task_warn};
 
 use pbs_api_types::{
-    Authid, BackupNamespace, BackupType, ChunkOrder, DataStoreConfig, DatastoreFSyncLevel,
-    DatastoreTuning, GarbageCollectionStatus, MaintenanceMode, MaintenanceType, Operation, UPID,
+    Authid, BackupNamespace, BackupType, ChunkOrder, CryptMode, DataStoreConfig,
+    DatastoreCryptPolicy, DatastoreFSyncLevel, DatastoreTuning, GarbageCollectionStatus,
+    MaintenanceMode, MaintenanceType, Operation, DEFAULT_MAX_BLOB_SIZE, DEFAULT_MAX_CHUNK_SIZE,
+    UPID,
 };
 
 use crate::backup_info::{BackupDir, BackupGroup, BackupGroupDeleteStats};
 use crate::chunk_store::ChunkStore;
+use crate::cold_tier::{ChunkUsage, ColdTier};
+use crate::data_blob::digest_salt_fingerprint;
 use crate::dynamic_index::{DynamicIndexReader, DynamicIndexWriter};
 use crate::fixed_index::{FixedIndexReader, FixedIndexWriter};
 use crate::hierarchy::{ListGroups, ListGroupsType, ListNamespaces, ListNamespacesRecursive};
 use crate::index::IndexFile;
-use crate::manifest::{archive_type, ArchiveType};
+use crate::manifest::{archive_type, ArchiveType, BackupManifest};
 use crate::task_tracking::{self, update_active_operations};
 use crate::DataBlob;
 
@@ -58,9 +62,16 @@ pub struct DataStoreImpl {
     gc_mutex: Mutex<()>,
     last_gc_status: Mutex<GarbageCollectionStatus>,
     verify_new: bool,
+    crypt_policy: DatastoreCryptPolicy,
     chunk_order: ChunkOrder,
     last_digest: Option<[u8; 32]>,
     sync_level: DatastoreFSyncLevel,
+    cold_tier: Option<ColdTier>,
+    max_chunk_size: usize,
+    max_blob_size: usize,
+    max_manifest_size: usize,
+    blob_dedup_min_size: Option<u64>,
+    digest_salt: Option<[u8; 32]>,
 }
 
 impl DataStoreImpl {
@@ -72,9 +83,16 @@ impl DataStoreImpl {
             gc_mutex: Mutex::new(()),
             last_gc_status: Mutex::new(GarbageCollectionStatus::default()),
             verify_new: false,
+            crypt_policy: Default::default(),
             chunk_order: Default::default(),
             last_digest: None,
             sync_level: Default::default(),
+            cold_tier: None,
+            max_chunk_size: (DEFAULT_MAX_CHUNK_SIZE * 1024 * 1024) as usize,
+            max_blob_size: (DEFAULT_MAX_BLOB_SIZE * 1024 * 1024) as usize,
+            max_manifest_size: (DEFAULT_MAX_BLOB_SIZE * 1024 * 1024) as usize,
+            blob_dedup_min_size: None,
+            digest_salt: None,
         })
     }
 }
@@ -305,14 +323,32 @@ impl DataStore {
                 .parse_property_string(config.tuning.as_deref().unwrap_or(""))?,
         )?;
 
+        let digest_salt = config.digest_salt()?;
+
+        let cold_tier = config
+            .cold_tier_path
+            .as_ref()
+            .map(|path| ColdTier::new(path, config.cold_tier_after.unwrap_or(30)));
+
         Ok(DataStoreImpl {
             chunk_store,
             gc_mutex: Mutex::new(()),
             last_gc_status: Mutex::new(gc_status),
             verify_new: config.verify_new.unwrap_or(false),
+            crypt_policy: config.crypt_policy.unwrap_or_default(),
             chunk_order: tuning.chunk_order.unwrap_or_default(),
             last_digest,
             sync_level: tuning.sync_level.unwrap_or_default(),
+            cold_tier,
+            max_chunk_size: (tuning.max_chunk_size.unwrap_or(DEFAULT_MAX_CHUNK_SIZE) * 1024 * 1024)
+                as usize,
+            max_blob_size: (tuning.max_blob_size.unwrap_or(DEFAULT_MAX_BLOB_SIZE) * 1024 * 1024)
+                as usize,
+            max_manifest_size: (tuning.max_manifest_size.unwrap_or(DEFAULT_MAX_BLOB_SIZE)
+                * 1024
+                * 1024) as usize,
+            blob_dedup_min_size: tuning.blob_dedup_min_size,
+            digest_salt,
         })
     }
 
@@ -480,6 +516,25 @@ impl DataStore {
         Ok(ns)
     }
 
+    /// Create a backup namespace including all of its missing parents.
+    ///
+    /// Returns the namespaces which were created, parents first.
+    pub fn create_namespace_recursive(
+        self: &Arc<Self>,
+        ns: &BackupNamespace,
+    ) -> Result<Vec<BackupNamespace>, Error> {
+        let mut created = Vec::new();
+        let mut parent = BackupNamespace::root();
+        for component in ns.components() {
+            let current = BackupNamespace::from_parent_ns(&parent, component.to_string())?;
+            if !self.namespace_exists(&current) {
+                created.push(self.create_namespace(&parent, component.to_string())?);
+            }
+            parent = current;
+        }
+        Ok(created)
+    }
+
     /// Returns if the given namespace exists on the datastore
     pub fn namespace_exists(&self, ns: &BackupNamespace) -> bool {
         let mut path = self.base_path();
@@ -954,6 +1009,7 @@ impl DataStore {
         index: I,
         file_name: &Path, // only used for error reporting
         status: &mut GarbageCollectionStatus,
+        mut usage: Option<(&mut ChunkUsage, bool)>,
         worker: &dyn WorkerTaskContext,
     ) -> Result<(), Error> {
         status.index_file_count += 1;
@@ -963,6 +1019,9 @@ impl DataStore {
             worker.check_abort()?;
             worker.fail_on_shutdown()?;
             let digest = index.index_digest(pos).unwrap();
+            if let Some((usage, is_hot)) = usage.as_mut() {
+                usage.mark(digest, *is_hot);
+            }
             if !self.inner.chunk_store.cond_touch_chunk(digest, false)? {
                 let hex = hex::encode(digest);
                 task_warn!(
@@ -985,9 +1044,15 @@ impl DataStore {
         Ok(())
     }
 
+    /// Mark all chunks used by index files.
+    ///
+    /// If `usage` is given, the chunks are recorded there as hot if referenced by a snapshot newer
+    /// than `hot_cutoff`, and as cold otherwise.
     fn mark_used_chunks(
         &self,
         status: &mut GarbageCollectionStatus,
+        mut usage: Option<&mut ChunkUsage>,
+        hot_cutoff: i64,
         worker: &dyn WorkerTaskContext,
     ) -> Result<(), Error> {
         let image_list = self.list_images()?;
@@ -1001,14 +1066,18 @@ impl DataStore {
             worker.check_abort()?;
             worker.fail_on_shutdown()?;
 
+            // index files outside of the expected scheme are treated as hot
+            let mut is_hot = true;
             if let Some(backup_dir_path) = img.parent() {
                 let backup_dir_path = backup_dir_path.strip_prefix(self.base_path())?;
                 if let Some(backup_dir_str) = backup_dir_path.to_str() {
-                    if pbs_api_types::parse_ns_and_snapshot(backup_dir_str).is_err() {
-                        strange_paths_count += 1;
+                    match pbs_api_types::parse_ns_and_snapshot(backup_dir_str) {
+                        Ok((_ns, dir)) => is_hot = dir.time >= hot_cutoff,
+                        Err(_) => strange_paths_count += 1,
                     }
                 }
             }
+            let hot = usage.as_deref_mut().map(|usage| (usage, is_hot));
 
             match std::fs::File::open(&img) {
                 Ok(file) => {
@@ -1017,12 +1086,12 @@ impl DataStore {
                             let index = FixedIndexReader::new(file).map_err(|e| {
                                 format_err!("can't read index '{}' - {}", img.to_string_lossy(), e)
                             })?;
-                            self.index_mark_used_chunks(index, &img, status, worker)?;
+                            self.index_mark_used_chunks(index, &img, status, hot, worker)?;
                         } else if archive_type == ArchiveType::DynamicIndex {
                             let index = DynamicIndexReader::new(file).map_err(|e| {
                                 format_err!("can't read index '{}' - {}", img.to_string_lossy(), e)
                             })?;
-                            self.index_mark_used_chunks(index, &img, status, worker)?;
+                            self.index_mark_used_chunks(index, &img, status, hot, worker)?;
                         }
                     }
                 }
@@ -1087,7 +1156,20 @@ impl DataStore {
 
             task_log!(worker, "Start GC phase1 (mark used chunks)");
 
-            self.mark_used_chunks(&mut gc_status, worker)?;
+            let mut usage = ChunkUsage::default();
+            let cold_tier = self.inner.cold_tier.as_ref();
+            let hot_cutoff = match cold_tier {
+                Some(cold_tier) => cold_tier.cutoff(phase1_start_time),
+                None => i64::MIN,
+            };
+            self.mark_used_chunks(
+                &mut gc_status,
+                cold_tier.map(|_| &mut usage),
+                hot_cutoff,
+                worker,
+            )?;
+            // chunks accessed after marking are in use by a running backup or reader
+            let marked_time = proxmox_time::epoch_i64();
 
             task_log!(worker, "Start GC phase2 (sweep unused chunks)");
             self.inner.chunk_store.sweep_unused_chunks(
@@ -1103,6 +1185,16 @@ impl DataStore {
                 HumanByte::from(gc_status.removed_bytes),
             );
             task_log!(worker, "Removed chunks: {}", gc_status.removed_chunks);
+
+            let (removed_blobs, removed_blob_bytes) =
+                self.inner.chunk_store.sweep_unused_blobs(worker)?;
+            if removed_blobs > 0 {
+                task_log!(
+                    worker,
+                    "Removed unused deduplicated blobs: {removed_blobs} ({})",
+                    HumanByte::from(removed_blob_bytes),
+                );
+            }
             if gc_status.pending_bytes > 0 {
                 task_log!(
                     worker,
@@ -1119,6 +1211,32 @@ impl DataStore {
                 task_log!(worker, "Leftover bad chunks: {}", gc_status.still_bad);
             }
 
+            if let Some(cold_tier) = cold_tier {
+                task_log!(worker, "Start GC phase3 (offload cold chunks)");
+                cold_tier.sweep(&self.inner.chunk_store, &mut gc_status, worker)?;
+                let (count, bytes) = cold_tier.offload_chunks(
+                    &self.inner.chunk_store,
+                    &usage,
+                    marked_time,
+                    worker,
+                )?;
+                task_log!(
+                    worker,
+                    "Offloaded chunks: {count} ({})",
+                    HumanByte::from(bytes)
+                );
+
+                gc_status.cold_chunks += count;
+                gc_status.cold_bytes += bytes;
+                gc_status.disk_bytes = gc_status.disk_bytes.saturating_sub(bytes);
+                task_log!(
+                    worker,
+                    "Cold tier chunks: {} ({})",
+                    gc_status.cold_chunks,
+                    HumanByte::from(gc_status.cold_bytes),
+                );
+            }
+
             task_log!(
                 worker,
                 "Original data usage: {}",
@@ -1191,16 +1309,36 @@ impl DataStore {
     }
 
     pub fn insert_chunk(&self, chunk: &DataBlob, digest: &[u8; 32]) -> Result<(bool, u64), Error> {
+        if let Some(cold_tier) = &self.inner.cold_tier {
+            let (chunk_path, _digest_str) = self.inner.chunk_store.chunk_path(digest);
+            if cold_tier.is_stub(&chunk_path, digest) {
+                self.inner.chunk_store.cond_touch_chunk(digest, true)?;
+                let size = std::fs::metadata(cold_tier.chunk_path(digest))?.len();
+                return Ok((true, size));
+            }
+        }
         self.inner.chunk_store.insert_chunk(chunk, digest)
     }
 
+    /// Path to read a chunk from, which is in the cold tier if the chunk was offloaded.
+    pub fn chunk_read_path(&self, digest: &[u8; 32]) -> PathBuf {
+        let (chunk_path, _digest_str) = self.inner.chunk_store.chunk_path(digest);
+        match &self.inner.cold_tier {
+            Some(cold_tier) if cold_tier.is_stub(&chunk_path, digest) => {
+                cold_tier.chunk_path(digest)
+            }
+            _ => chunk_path,
+        }
+    }
+
     pub fn stat_chunk(&self, digest: &[u8; 32]) -> Result<std::fs::Metadata, Error> {
         let (chunk_path, _digest_str) = self.inner.chunk_store.chunk_path(digest);
         std::fs::metadata(chunk_path).map_err(Error::from)
     }
 
     pub fn load_chunk(&self, digest: &[u8; 32]) -> Result<DataBlob, Error> {
-        let (chunk_path, digest_str) = self.inner.chunk_store.chunk_path(digest);
+        let chunk_path = self.chunk_read_path(digest);
+        let digest_str = hex::encode(digest);
 
         proxmox_lang::try_block!({
             let mut file = std::fs::File::open(&chunk_path)?;
@@ -1244,6 +1382,91 @@ impl DataStore {
         self.inner.verify_new
     }
 
+    pub fn crypt_policy(&self) -> DatastoreCryptPolicy {
+        self.inner.crypt_policy
+    }
+
+    /// Salt mixed into the digests of unencrypted chunks, set on creation of the datastore.
+    pub fn digest_salt(&self) -> Option<&[u8; 32]> {
+        self.inner.digest_salt.as_ref()
+    }
+
+    /// Fingerprint of the digest salt, see [`digest_salt_fingerprint`].
+    pub fn digest_salt_fingerprint(&self) -> Option<[u8; 32]> {
+        self.digest_salt().map(digest_salt_fingerprint)
+    }
+
+    /// Check that unencrypted chunks from `source`, whose digest salt has the fingerprint
+    /// `source_fingerprint`, can be inserted into this datastore.
+    ///
+    /// Their digests only match if both use the same salt, or none. Checking this up front
+    /// avoids failing on every single chunk.
+    pub fn check_digest_salt(
+        &self,
+        source: &str,
+        source_fingerprint: Option<[u8; 32]>,
+    ) -> Result<(), Error> {
+        if source_fingerprint != self.digest_salt_fingerprint() {
+            bail!(
+                "{source} and datastore '{}' use different digest salts, chunks cannot be shared \
+                between them",
+                self.name(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Minimum size of blobs stored deduplicated, from the `blob-dedup-min-size` tuning option.
+    pub fn blob_dedup_min_size(&self) -> Option<u64> {
+        self.inner.blob_dedup_min_size
+    }
+
+    /// Store the raw blob `data` at `target`, sharing the data with identical blobs of other
+    /// snapshots, see [ChunkStore::insert_dedup_blob].
+    pub fn insert_dedup_blob(&self, data: &[u8], target: &Path) -> Result<bool, Error> {
+        let csum = openssl::sha::sha256(data);
+        self.inner
+            .chunk_store
+            .insert_dedup_blob(data, &csum, target)
+    }
+
+    /// Maximum size of uploaded chunks in bytes, from the `max-chunk-size` tuning option.
+    pub fn max_chunk_size(&self) -> usize {
+        self.inner.max_chunk_size
+    }
+
+    /// Maximum size of uploaded blobs in bytes, from the `max-blob-size` tuning option.
+    pub fn max_blob_size(&self) -> usize {
+        self.inner.max_blob_size
+    }
+
+    /// Maximum size of uploaded manifests in bytes, from the `max-manifest-size` tuning option.
+    pub fn max_manifest_size(&self) -> usize {
+        self.inner.max_manifest_size
+    }
+
+    /// Check if all archives of a new snapshot's manifest are allowed by the crypt policy.
+    pub fn check_crypt_policy(&self, manifest: &BackupManifest) -> Result<(), Error> {
+        let policy = self.crypt_policy();
+        for file in manifest.files() {
+            let allowed = match policy {
+                DatastoreCryptPolicy::Any => true,
+                DatastoreCryptPolicy::Encrypted => file.crypt_mode == CryptMode::Encrypt,
+                DatastoreCryptPolicy::Unencrypted => file.crypt_mode != CryptMode::Encrypt,
+            };
+            if !allowed {
+                bail!(
+                    "datastore '{}' only accepts {policy} backups, but archive '{}' has crypt mode \
+                    '{}'",
+                    self.name(),
+                    file.filename,
+                    file.crypt_mode,
+                );
+            }
+        }
+        Ok(())
+    }
+
     /// returns a list of chunks sorted by their inode number on disk chunks that couldn't get
     /// stat'ed are placed at the end of the list
     pub fn get_chunks_in_order<F, A>(
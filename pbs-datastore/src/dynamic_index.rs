@@ -20,6 +20,7 @@ use pbs_tools::lru_cache::LruCache;
 use crate::chunk_stat::ChunkStat;
 use crate::chunk_store::ChunkStore;
 use crate::data_blob::{DataBlob, DataChunkBuilder};
+use crate::fault_injection::{self, FaultPoint};
 use crate::file_formats;
 use crate::index::{ChunkReadInfo, IndexFile};
 use crate::read_chunk::ReadChunk;
@@ -362,6 +363,11 @@ impl DynamicIndexWriter {
         self.writer.write_all(&index_csum)?;
         self.writer.flush()?;
 
+        if let Some(fault) = fault_injection::check(FaultPoint::IndexClose, &self.filename) {
+            let data = std::fs::read(&self.tmp_filename)?;
+            return Err(fault_injection::inject(fault, &self.filename, &data));
+        }
+
         if let Err(err) = std::fs::rename(&self.tmp_filename, &self.filename) {
             bail!("Atomic rename file {:?} failed - {}", self.filename, err);
         }
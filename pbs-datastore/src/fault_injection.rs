@@ -0,0 +1,184 @@
+//! Fault injection into the datastore IO, for testing
+//!
+//! With the `fault-injection` feature, the chunk store and the index writers check the scenario
+//! file named by the `PBS_FAULT_SCENARIO` environment variable before they persist data, and
+//! simulate a failure instead if a rule matches. Without the feature, [check] always returns
+//! `None` and compiles to nothing.
+//!
+//! The scenario is a JSON list of rules, like
+//!
+//! ```json
+//! [
+//!   { "point": "chunk-insert", "fault": "no-space", "skip": 100, "count": 5 },
+//!   { "point": "index-close", "fault": "torn-rename", "path": ".fidx" }
+//! ]
+//! ```
+//!
+//! A rule lets `skip` matching operations pass and then injects its fault into the next `count`
+//! ones (default 1, 0 for all), so a scenario fails the same operations on every run.
+
+use std::path::Path;
+
+use anyhow::Error;
+use serde::Deserialize;
+
+/// Operations faults can be injected into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FaultPoint {
+    /// Writing a chunk file into the chunk store.
+    ChunkInsert,
+    /// Renaming a finished fixed or dynamic index into place.
+    IndexClose,
+}
+
+/// The simulated failures.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Fault {
+    /// The operation fails with `ENOSPC`, nothing is written.
+    NoSpace,
+    /// Only the first half of the data ends up in the target file, as after a crash on a
+    /// non-atomic write.
+    ShortWrite,
+    /// The target file exists but is empty, as after a crash where the rename was persisted but
+    /// the data was not.
+    TornRename,
+}
+
+#[cfg(feature = "fault-injection")]
+mod scenario {
+    use std::path::Path;
+    use std::sync::Mutex;
+
+    use lazy_static::lazy_static;
+    use serde::Deserialize;
+
+    use super::{Fault, FaultPoint};
+
+    fn default_count() -> u64 {
+        1
+    }
+
+    #[derive(Deserialize)]
+    pub(super) struct FaultRule {
+        point: FaultPoint,
+        fault: Fault,
+        /// Only operations on paths containing this.
+        path: Option<String>,
+        #[serde(default)]
+        skip: u64,
+        #[serde(default = "default_count")]
+        count: u64,
+        #[serde(skip)]
+        hits: u64,
+    }
+
+    impl FaultRule {
+        pub(super) fn hit(&mut self, point: FaultPoint, path: &str) -> Option<Fault> {
+            if self.point != point {
+                return None;
+            }
+            if let Some(filter) = &self.path {
+                if !path.contains(filter.as_str()) {
+                    return None;
+                }
+            }
+            self.hits += 1;
+            if self.hits <= self.skip || (self.count != 0 && self.hits > self.skip + self.count) {
+                return None;
+            }
+            Some(self.fault)
+        }
+    }
+
+    fn load_scenario() -> Vec<FaultRule> {
+        let path = match std::env::var("PBS_FAULT_SCENARIO") {
+            Ok(path) => path,
+            Err(_) => return Vec::new(),
+        };
+        let rules = std::fs::read_to_string(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|data| Ok(serde_json::from_str(&data)?));
+        match rules {
+            Ok(rules) => rules,
+            Err(err) => {
+                log::error!("unable to load fault injection scenario {path:?} - {err}");
+                Vec::new()
+            }
+        }
+    }
+
+    lazy_static! {
+        static ref SCENARIO: Mutex<Vec<FaultRule>> = Mutex::new(load_scenario());
+    }
+
+    pub(super) fn check(point: FaultPoint, path: &Path) -> Option<Fault> {
+        let path = path.to_string_lossy();
+        let mut rules = SCENARIO.lock().unwrap();
+        // every matching rule counts the operation, the first one firing wins
+        let mut result = None;
+        for rule in rules.iter_mut() {
+            if let Some(fault) = rule.hit(point, &path) {
+                result = result.or(Some(fault));
+            }
+        }
+        result
+    }
+}
+
+/// Returns the fault to inject into the operation at `point` on `path`, if any.
+#[cfg(feature = "fault-injection")]
+pub fn check(point: FaultPoint, path: &Path) -> Option<Fault> {
+    scenario::check(point, path)
+}
+
+/// Returns the fault to inject into the operation at `point` on `path`, if any.
+#[cfg(not(feature = "fault-injection"))]
+#[inline(always)]
+pub fn check(_point: FaultPoint, _path: &Path) -> Option<Fault> {
+    None
+}
+
+/// Leave `path` as the failed write of `data` would, and return the error of the operation.
+pub fn inject(fault: Fault, path: &Path, data: &[u8]) -> Error {
+    log::warn!("injecting fault {fault:?} at {path:?}");
+    // the partial data is written, then the operation fails as if interrupted
+    let write_partial = |data: &[u8]| match std::fs::write(path, data) {
+        Ok(()) => std::io::Error::from_raw_os_error(libc::EIO),
+        Err(err) => err,
+    };
+    let err = match fault {
+        Fault::NoSpace => std::io::Error::from_raw_os_error(libc::ENOSPC),
+        Fault::ShortWrite => write_partial(&data[..data.len() / 2]),
+        Fault::TornRename => write_partial(&[]),
+    };
+    Error::from(err).context(format!("injected fault {fault:?} at {path:?}"))
+}
+
+#[cfg(all(test, feature = "fault-injection"))]
+mod test {
+    use super::scenario::FaultRule;
+    use super::*;
+
+    #[test]
+    fn test_rule_counting() {
+        let mut rule: FaultRule = serde_json::from_str(
+            r#"{ "point": "chunk-insert", "fault": "no-space", "skip": 1, "count": 2, "path": "/a/" }"#,
+        )
+        .unwrap();
+
+        assert_eq!(rule.hit(FaultPoint::IndexClose, "/a/x"), None);
+        assert_eq!(rule.hit(FaultPoint::ChunkInsert, "/b/x"), None);
+        assert_eq!(rule.hit(FaultPoint::ChunkInsert, "/a/x"), None);
+        assert_eq!(
+            rule.hit(FaultPoint::ChunkInsert, "/a/x"),
+            Some(Fault::NoSpace)
+        );
+        assert_eq!(
+            rule.hit(FaultPoint::ChunkInsert, "/a/x"),
+            Some(Fault::NoSpace)
+        );
+        assert_eq!(rule.hit(FaultPoint::ChunkInsert, "/a/x"), None);
+    }
+}
@@ -23,6 +23,9 @@ pub const FIXED_SIZED_CHUNK_INDEX_1_0: [u8; 8] = [47, 127, 65, 237, 145, 253, 15
 // openssl::sha::sha256(b"Proxmox Backup dynamic sized chunk index v1.0")[0..8]
 pub const DYNAMIC_SIZED_CHUNK_INDEX_1_0: [u8; 8] = [28, 145, 78, 165, 25, 186, 179, 205];
 
+// openssl::sha::sha256(b"Proxmox Backup snapshot export v1.0")[0..8]
+pub const PROXMOX_BACKUP_SNAPSHOT_EXPORT_MAGIC_1_0: [u8; 8] = [124, 44, 158, 41, 161, 64, 255, 58];
+
 /// Data blob binary storage format
 ///
 /// The format start with a 8 byte magic number to identify the type,
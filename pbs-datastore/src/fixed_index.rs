@@ -14,6 +14,7 @@ use proxmox_uuid::Uuid;
 use crate::chunk_stat::ChunkStat;
 use crate::chunk_store::ChunkStore;
 use crate::data_blob::ChunkInfo;
+use crate::fault_injection::{self, FaultPoint};
 use crate::file_formats;
 use crate::index::{ChunkReadInfo, IndexFile};
 
@@ -354,6 +355,11 @@ impl FixedIndexWriter {
         self.file.write_all(&index_csum)?;
         self.file.flush()?;
 
+        if let Some(fault) = fault_injection::check(FaultPoint::IndexClose, &self.filename) {
+            let data = std::fs::read(&self.tmp_filename)?;
+            return Err(fault_injection::inject(fault, &self.filename, &data));
+        }
+
         if let Err(err) = std::fs::rename(&self.tmp_filename, &self.filename) {
             bail!("Atomic rename file {:?} failed - {}", self.filename, err);
         }
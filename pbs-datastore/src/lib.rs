@@ -175,17 +175,20 @@ pub mod checksum_writer;
 pub mod chunk_stat;
 pub mod chunk_store;
 pub mod chunker;
+pub mod cold_tier;
 pub mod crypt_reader;
 pub mod crypt_writer;
 pub mod data_blob;
 pub mod data_blob_reader;
 pub mod data_blob_writer;
+pub mod fault_injection;
 pub mod file_formats;
 pub mod index;
 pub mod manifest;
 pub mod paperkey;
 pub mod prune;
 pub mod read_chunk;
+pub mod snapshot_export;
 pub mod store_progress;
 pub mod task_tracking;
 
@@ -57,7 +57,11 @@ impl ReadChunk for LocalChunkReader {
     fn read_chunk(&self, digest: &[u8; 32]) -> Result<Vec<u8>, Error> {
         let chunk = ReadChunk::read_raw_chunk(self, digest)?;
 
-        let raw_data = chunk.decode(self.crypt_config.as_ref().map(Arc::as_ref), Some(digest))?;
+        let raw_data = chunk.decode_salted(
+            self.crypt_config.as_ref().map(Arc::as_ref),
+            Some(digest),
+            self.store.digest_salt(),
+        )?;
 
         Ok(raw_data)
     }
@@ -69,7 +73,7 @@ impl AsyncReadChunk for LocalChunkReader {
         digest: &'a [u8; 32],
     ) -> Pin<Box<dyn Future<Output = Result<DataBlob, Error>> + Send + 'a>> {
         Box::pin(async move {
-            let (path, _) = self.store.chunk_path(digest);
+            let path = self.store.chunk_read_path(digest);
 
             let raw_data = tokio::fs::read(&path).await?;
 
@@ -87,8 +91,11 @@ impl AsyncReadChunk for LocalChunkReader {
         Box::pin(async move {
             let chunk = AsyncReadChunk::read_raw_chunk(self, digest).await?;
 
-            let raw_data =
-                chunk.decode(self.crypt_config.as_ref().map(Arc::as_ref), Some(digest))?;
+            let raw_data = chunk.decode_salted(
+                self.crypt_config.as_ref().map(Arc::as_ref),
+                Some(digest),
+                self.store.digest_salt(),
+            )?;
 
             // fixme: verify digest?
 
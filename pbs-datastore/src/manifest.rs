@@ -114,6 +114,31 @@ impl BackupManifest {
         &self.files[..]
     }
 
+    /// The snapshot this manifest belongs to.
+    pub fn snapshot(&self) -> pbs_api_types::BackupDir {
+        (self.backup_type, self.backup_id.clone(), self.backup_time).into()
+    }
+
+    /// Digest over name, size and checksum of all archives.
+    ///
+    /// Unlike the raw manifest blob, this is independent of the unprotected part, so it is the
+    /// same for a snapshot and all of its synced copies.
+    pub fn content_digest(&self) -> [u8; 32] {
+        let mut hasher = openssl::sha::Sha256::new();
+        for info in self.files.iter() {
+            hasher.update(info.filename.as_bytes());
+            hasher.update(&[0]);
+            hasher.update(&info.size.to_le_bytes());
+            hasher.update(&info.csum);
+        }
+        hasher.finish()
+    }
+
+    /// Name of the datastore the snapshot was moved to, if this manifest is an archive stub.
+    pub fn archived_to(&self) -> Option<&str> {
+        self.unprotected["archived"]["store"].as_str()
+    }
+
     pub fn lookup_file_info(&self, name: &str) -> Result<&FileInfo, Error> {
         let info = self.files.iter().find(|item| item.filename == name);
 
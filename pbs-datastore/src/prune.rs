@@ -10,6 +10,8 @@ use super::BackupInfo;
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum PruneMark {
     Protected,
+    /// Within the minimum retention period of the datastore
+    Retained,
     Keep,
     KeepPartial,
     Remove,
@@ -29,6 +31,7 @@ impl std::fmt::Display for PruneMark {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(match self {
             PruneMark::Protected => "protected",
+            PruneMark::Retained => "retained",
             PruneMark::Keep => "keep",
             PruneMark::KeepPartial => "keep-partial",
             PruneMark::Remove => "remove",
@@ -62,6 +65,10 @@ fn mark_selections<F: Fn(&BackupInfo) -> Result<String, Error>>(
             mark.insert(backup_id, PruneMark::Protected);
             continue;
         }
+        if info.retained_until.is_some() {
+            mark.insert(backup_id, PruneMark::Retained);
+            continue;
+        }
         let sel_id: String = select_id(info)?;
 
         if already_included.contains(&sel_id) {
@@ -160,6 +167,8 @@ pub fn compute_prune_info(
             let backup_id = info.backup_dir.relative_path();
             let mark = if info.protected {
                 PruneMark::Protected
+            } else if info.retained_until.is_some() {
+                PruneMark::Retained
             } else {
                 mark.get(&backup_id).copied().unwrap_or(PruneMark::Remove)
             };
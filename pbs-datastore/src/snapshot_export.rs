@@ -0,0 +1,565 @@
+//! Snapshot export archives (`.pbsx`)
+//!
+//! An export archive contains everything needed to restore a single snapshot without access to
+//! its datastore: the manifest, all blobs and index files, and every chunk referenced by the
+//! indexes. It is written and read as a stream, so it can be produced and consumed on the fly.
+//!
+//! Format (all integers little endian):
+//!
+//! ```text
+//! MAGIC || SALT? || ENTRY* || END || SHA256
+//!
+//! SALT:  0x03 || FINGERPRINT ([u8; 32])                         (digest salt of the datastore)
+//! ENTRY: 0x01 || NAME_LEN (u16) || NAME || SIZE (u64) || DATA   (file of the snapshot)
+//!      | 0x02 || DIGEST ([u8; 32]) || SIZE (u64) || DATA        (chunk, as stored on disk)
+//! END:   0x00
+//! ```
+//!
+//! Archives of datastores with a digest salt start with its fingerprint, so importing them into
+//! a datastore with another salt fails right away instead of on every chunk. The first entry is
+//! the manifest (`index.json.blob`). Files and chunks are stored exactly as in the datastore, so
+//! encrypted snapshots stay encrypted. The archive ends with the SHA-256 digest over all
+//! preceding bytes.
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+use anyhow::{bail, format_err, Error};
+
+use pbs_api_types::{Authid, BackupNamespace, CryptMode, MAX_CHUNK_SIZE_LIMIT};
+
+use crate::dynamic_index::DynamicIndexReader;
+use crate::file_formats::{EncryptedDataBlobHeader, PROXMOX_BACKUP_SNAPSHOT_EXPORT_MAGIC_1_0};
+use crate::fixed_index::FixedIndexReader;
+use crate::index::IndexFile;
+use crate::manifest::{archive_type, ArchiveType, CLIENT_LOG_BLOB_NAME, MANIFEST_BLOB_NAME};
+use crate::{check_backup_owner, BackupDir, BackupManifest, DataBlob, DataStore, SnapshotReader};
+
+const ENTRY_END: u8 = 0;
+const ENTRY_FILE: u8 = 1;
+const ENTRY_CHUNK: u8 = 2;
+const ENTRY_SALT: u8 = 3;
+
+/// Upper limit for files in an export, index files of very large images stay well below
+const MAX_FILE_SIZE: u64 = 256 * 1024 * 1024;
+/// Upper limit for chunks in an export, the largest configurable chunk size plus blob header
+const MAX_CHUNK_SIZE: u64 =
+    MAX_CHUNK_SIZE_LIMIT * 1024 * 1024 + std::mem::size_of::<EncryptedDataBlobHeader>() as u64;
+
+/// Writes a snapshot export archive.
+pub struct SnapshotExportWriter<W: Write> {
+    writer: W,
+    hasher: openssl::sha::Sha256,
+}
+
+impl<W: Write> SnapshotExportWriter<W> {
+    pub fn new(writer: W) -> Result<Self, Error> {
+        let mut this = Self {
+            writer,
+            hasher: openssl::sha::Sha256::new(),
+        };
+        this.write(&PROXMOX_BACKUP_SNAPSHOT_EXPORT_MAGIC_1_0)?;
+        Ok(this)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.hasher.update(data);
+        self.writer.write_all(data)?;
+        Ok(())
+    }
+
+    /// Add a file of the snapshot.
+    pub fn add_file(&mut self, name: &str, data: &[u8]) -> Result<(), Error> {
+        let name_len: u16 = name
+            .len()
+            .try_into()
+            .map_err(|_| format_err!("file name '{name}' too long"))?;
+        self.write(&[ENTRY_FILE])?;
+        self.write(&name_len.to_le_bytes())?;
+        self.write(name.as_bytes())?;
+        self.write(&(data.len() as u64).to_le_bytes())?;
+        self.write(data)
+    }
+
+    /// Add the fingerprint of the digest salt of the datastore, before any other entry.
+    pub fn add_digest_salt(&mut self, fingerprint: &[u8; 32]) -> Result<(), Error> {
+        self.write(&[ENTRY_SALT])?;
+        self.write(fingerprint)
+    }
+
+    /// Add a chunk, `data` is the raw chunk blob.
+    pub fn add_chunk(&mut self, digest: &[u8; 32], data: &[u8]) -> Result<(), Error> {
+        self.write(&[ENTRY_CHUNK])?;
+        self.write(digest)?;
+        self.write(&(data.len() as u64).to_le_bytes())?;
+        self.write(data)
+    }
+
+    /// Write the end marker and checksum, returns the inner writer.
+    pub fn finish(mut self) -> Result<W, Error> {
+        self.write(&[ENTRY_END])?;
+        let digest = self.hasher.finish();
+        self.writer.write_all(&digest)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// An entry of a snapshot export archive.
+pub enum SnapshotExportEntry {
+    File { name: String, data: Vec<u8> },
+    Chunk { digest: [u8; 32], data: Vec<u8> },
+    DigestSalt { fingerprint: [u8; 32] },
+}
+
+/// Reads a snapshot export archive.
+pub struct SnapshotExportReader<R: Read> {
+    reader: R,
+    hasher: openssl::sha::Sha256,
+    finished: bool,
+}
+
+impl<R: Read> SnapshotExportReader<R> {
+    pub fn new(reader: R) -> Result<Self, Error> {
+        let mut this = Self {
+            reader,
+            hasher: openssl::sha::Sha256::new(),
+            finished: false,
+        };
+        let mut magic = [0u8; 8];
+        this.read(&mut magic)?;
+        if magic != PROXMOX_BACKUP_SNAPSHOT_EXPORT_MAGIC_1_0 {
+            bail!("not a snapshot export archive (wrong magic)");
+        }
+        Ok(this)
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        self.reader
+            .read_exact(buf)
+            .map_err(|err| format_err!("unable to read snapshot export - {err}"))?;
+        self.hasher.update(buf);
+        Ok(())
+    }
+
+    fn read_u64(&mut self) -> Result<u64, Error> {
+        let mut buf = [0u8; 8];
+        self.read(&mut buf)?;
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    fn read_data(&mut self, size: u64, limit: u64) -> Result<Vec<u8>, Error> {
+        if size > limit {
+            bail!("entry in snapshot export too large ({size} bytes)");
+        }
+        let mut data = vec![0u8; size as usize];
+        self.read(&mut data)?;
+        Ok(data)
+    }
+
+    /// Returns the next entry, or `None` after the checksum at the end was verified.
+    pub fn next_entry(&mut self) -> Result<Option<SnapshotExportEntry>, Error> {
+        if self.finished {
+            return Ok(None);
+        }
+
+        let mut kind = [0u8; 1];
+        self.read(&mut kind)?;
+        match kind[0] {
+            ENTRY_END => {
+                let expected = std::mem::replace(&mut self.hasher, openssl::sha::Sha256::new());
+                let expected = expected.finish();
+                let mut digest = [0u8; 32];
+                self.reader.read_exact(&mut digest)?;
+                if digest != expected {
+                    bail!("snapshot export checksum mismatch");
+                }
+                self.finished = true;
+                Ok(None)
+            }
+            ENTRY_FILE => {
+                let mut name_len = [0u8; 2];
+                self.read(&mut name_len)?;
+                let mut name = vec![0u8; u16::from_le_bytes(name_len) as usize];
+                self.read(&mut name)?;
+                let name = String::from_utf8(name)
+                    .map_err(|_| format_err!("invalid file name in snapshot export"))?;
+                let size = self.read_u64()?;
+                let data = self.read_data(size, MAX_FILE_SIZE)?;
+                Ok(Some(SnapshotExportEntry::File { name, data }))
+            }
+            ENTRY_CHUNK => {
+                let mut digest = [0u8; 32];
+                self.read(&mut digest)?;
+                let size = self.read_u64()?;
+                let data = self.read_data(size, MAX_CHUNK_SIZE)?;
+                Ok(Some(SnapshotExportEntry::Chunk { digest, data }))
+            }
+            ENTRY_SALT => {
+                let mut fingerprint = [0u8; 32];
+                self.read(&mut fingerprint)?;
+                Ok(Some(SnapshotExportEntry::DigestSalt { fingerprint }))
+            }
+            other => bail!("unknown entry type {other} in snapshot export"),
+        }
+    }
+}
+
+/// Export a snapshot, holding a shared lock on it while writing.
+///
+/// Returns the number of exported chunks.
+pub fn export_snapshot<W: Write>(
+    datastore: Arc<DataStore>,
+    ns: BackupNamespace,
+    snapshot: pbs_api_types::BackupDir,
+    writer: W,
+) -> Result<u64, Error> {
+    export_snapshot_incremental(datastore, ns, snapshot, writer, &mut HashSet::new())
+}
+
+/// Export a snapshot like [`export_snapshot`], but leave out the chunks in `exported` and add
+/// the newly exported ones to it.
+///
+/// Used to write a series of archives without duplicating shared chunks, only importing them in
+/// the same order succeeds.
+pub fn export_snapshot_incremental<W: Write>(
+    datastore: Arc<DataStore>,
+    ns: BackupNamespace,
+    snapshot: pbs_api_types::BackupDir,
+    writer: W,
+    exported: &mut HashSet<[u8; 32]>,
+) -> Result<u64, Error> {
+    let snapshot_reader = SnapshotReader::new(datastore.clone(), ns, snapshot)?;
+
+    let (manifest, _) = snapshot_reader.snapshot().load_manifest()?;
+    if let Some(archive) = manifest.archived_to() {
+        bail!("snapshot was moved to datastore '{archive}', export it from there");
+    }
+
+    let mut writer = SnapshotExportWriter::new(writer)?;
+
+    if let Some(fingerprint) = datastore.digest_salt_fingerprint() {
+        writer.add_digest_salt(&fingerprint)?;
+    }
+
+    for name in snapshot_reader.file_list() {
+        let mut data = Vec::new();
+        snapshot_reader.open_file(name)?.read_to_end(&mut data)?;
+        writer.add_file(name, &data)?;
+    }
+
+    let exported = RefCell::new(exported);
+    let skip_fn = |digest: &[u8; 32]| exported.borrow().contains(digest);
+    let mut count = 0;
+    for digest in snapshot_reader.chunk_iterator(skip_fn)? {
+        let digest = digest?;
+        if !exported.borrow_mut().insert(digest) {
+            continue; // referenced more than once in the same index
+        }
+        let chunk = datastore.load_chunk(&digest)?;
+        writer.add_chunk(&digest, chunk.raw_data())?;
+        count += 1;
+    }
+
+    writer.finish()?;
+
+    Ok(count)
+}
+
+/// Check a chunk from an untrusted source.
+fn check_chunk(
+    digest: &[u8; 32],
+    data: Vec<u8>,
+    salt: Option<&[u8; 32]>,
+) -> Result<DataBlob, Error> {
+    let chunk = DataBlob::from_raw(data)?;
+    chunk.verify_crc()?;
+    if chunk.crypt_mode()? == CryptMode::None {
+        chunk.decode_salted(None, Some(digest), salt)?; // verifies the digest
+    }
+    Ok(chunk)
+}
+
+/// Write a file of an imported snapshot to a temporary file and check it against the manifest.
+fn import_file(
+    snapshot: &BackupDir,
+    manifest: &BackupManifest,
+    name: &str,
+    data: &[u8],
+) -> Result<(), Error> {
+    if name.contains('/') {
+        bail!("invalid file name '{name}' in snapshot export");
+    }
+    let mut path = snapshot.full_path();
+    path.push(name);
+    let mut tmp_path = path.clone();
+    tmp_path.set_extension("tmp");
+    std::fs::write(&tmp_path, data)?;
+
+    if name != CLIENT_LOG_BLOB_NAME {
+        let (csum, size) = match archive_type(name)? {
+            ArchiveType::DynamicIndex => {
+                DynamicIndexReader::new(std::fs::File::open(&tmp_path)?)?.compute_csum()
+            }
+            ArchiveType::FixedIndex => {
+                FixedIndexReader::new(std::fs::File::open(&tmp_path)?)?.compute_csum()
+            }
+            ArchiveType::Blob => (openssl::sha::sha256(data), data.len() as u64),
+        };
+        manifest.verify_file(name, &csum, size)?;
+    }
+
+    std::fs::rename(&tmp_path, &path)
+        .map_err(|err| format_err!("Atomic rename file {path:?} failed - {err}"))?;
+
+    Ok(())
+}
+
+/// Import a snapshot from an export archive into `ns`, owned by `auth_id`.
+///
+/// All files are checked against the manifest and all chunks against their digest. The manifest
+/// is written last, after all referenced chunks are known to exist, so an incomplete import
+/// never shows up as a finished snapshot.
+pub fn import_snapshot<R: Read>(
+    datastore: &Arc<DataStore>,
+    ns: &BackupNamespace,
+    auth_id: &Authid,
+    reader: R,
+) -> Result<pbs_api_types::BackupDir, Error> {
+    let mut reader = SnapshotExportReader::new(reader)?;
+
+    let mut entry = reader.next_entry()?;
+    let source_fingerprint = match entry {
+        Some(SnapshotExportEntry::DigestSalt { fingerprint }) => {
+            entry = reader.next_entry()?;
+            Some(fingerprint)
+        }
+        _ => None,
+    };
+    datastore.check_digest_salt("snapshot export", source_fingerprint)?;
+
+    let manifest_data = match entry {
+        Some(SnapshotExportEntry::File { name, data }) if name == MANIFEST_BLOB_NAME => data,
+        _ => bail!("snapshot export does not start with a manifest"),
+    };
+    let manifest = BackupManifest::try_from(DataBlob::from_raw(manifest_data.clone())?)?;
+    datastore.check_crypt_policy(&manifest)?;
+    let snapshot = manifest.snapshot();
+
+    let (owner, _group_guard) =
+        datastore.create_locked_backup_group(ns, &snapshot.group, auth_id)?;
+    check_backup_owner(&owner, auth_id)?;
+    let (_relative_path, is_new, _snapshot_guard) =
+        datastore.create_locked_backup_dir(ns, &snapshot)?;
+    if !is_new {
+        bail!("snapshot {snapshot} already exists");
+    }
+    let backup_dir = datastore.backup_dir(ns.clone(), snapshot.clone())?;
+
+    let result = proxmox_lang::try_block!({
+        let mut files = Vec::new();
+
+        while let Some(entry) = reader.next_entry()? {
+            match entry {
+                SnapshotExportEntry::File { name, data } => {
+                    import_file(&backup_dir, &manifest, &name, &data)?;
+                    files.push(name);
+                }
+                SnapshotExportEntry::Chunk { digest, data } => {
+                    let chunk = check_chunk(&digest, data, datastore.digest_salt())
+                        .map_err(|err| format_err!("chunk {} - {err}", hex::encode(digest)))?;
+                    datastore.insert_chunk(&chunk, &digest)?;
+                }
+                SnapshotExportEntry::DigestSalt { .. } => {
+                    bail!("unexpected digest salt entry in snapshot export");
+                }
+            }
+        }
+
+        for info in manifest.files() {
+            if !files.contains(&info.filename) {
+                bail!("file '{}' missing in snapshot export", info.filename);
+            }
+        }
+
+        for name in &files {
+            if archive_type(name)? == ArchiveType::Blob {
+                continue;
+            }
+            let index = datastore.open_index(backup_dir.full_path().join(name))?;
+            for pos in 0..index.index_count() {
+                let digest = index.index_digest(pos).unwrap();
+                if !datastore.cond_touch_chunk(digest, false)? {
+                    bail!(
+                        "chunk {} of '{name}' missing in snapshot export",
+                        hex::encode(digest)
+                    );
+                }
+            }
+        }
+
+        let mut path = backup_dir.full_path();
+        path.push(MANIFEST_BLOB_NAME);
+        let mut tmp_path = path.clone();
+        tmp_path.set_extension("tmp");
+        std::fs::write(&tmp_path, &manifest_data)?;
+        std::fs::rename(&tmp_path, &path)
+            .map_err(|err| format_err!("Atomic rename file {path:?} failed - {err}"))?;
+
+        Ok(())
+    });
+
+    if let Err(err) = result {
+        if let Err(cleanup_err) = std::fs::remove_dir_all(backup_dir.full_path()) {
+            log::warn!("unable to remove incomplete snapshot {snapshot} - {cleanup_err}");
+        }
+        return Err(err);
+    }
+
+    Ok(snapshot)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use pbs_api_types::DatastoreFSyncLevel;
+
+    use crate::data_blob::DataChunkBuilder;
+    use crate::ChunkStore;
+
+    fn test_datastore(name: &str) -> Result<(Arc<DataStore>, std::path::PathBuf), Error> {
+        let path =
+            std::env::temp_dir().join(format!("pbs-export-test-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        let user = nix::unistd::User::from_uid(nix::unistd::Uid::current())?.unwrap();
+        ChunkStore::create(
+            name,
+            &path,
+            user.uid,
+            user.gid,
+            None,
+            DatastoreFSyncLevel::None,
+        )?;
+        // only this test uses the datastore, so there are no other process lockers on it
+        let datastore = unsafe { DataStore::open_path(name, &path, None)? };
+        Ok((datastore, path))
+    }
+
+    // create a snapshot with a blob and a dynamic index of two chunks, returns the manifest
+    fn create_snapshot(
+        datastore: &Arc<DataStore>,
+        dir: &pbs_api_types::BackupDir,
+        owner: &Authid,
+    ) -> Result<BackupManifest, Error> {
+        let ns = BackupNamespace::root();
+        datastore.create_locked_backup_group(&ns, dir.as_ref(), owner)?;
+        datastore.create_locked_backup_dir(&ns, dir)?;
+        let snapshot = datastore.backup_dir(ns, dir.clone())?;
+        let mut manifest = BackupManifest::new(dir.clone());
+
+        let blob = DataBlob::encode(b"config data", None, true)?;
+        std::fs::write(snapshot.full_path().join("config.blob"), blob.raw_data())?;
+        manifest.add_file(
+            "config.blob".into(),
+            blob.raw_data().len() as u64,
+            openssl::sha::sha256(blob.raw_data()),
+            CryptMode::None,
+        )?;
+
+        let mut writer = datastore.create_dynamic_writer(snapshot.full_path().join("data.didx"))?;
+        let mut offset = 0;
+        for data in [&b"first chunk"[..], &b"second chunk"[..]] {
+            let (chunk, digest) = DataChunkBuilder::new(data).build()?;
+            datastore.insert_chunk(&chunk, &digest)?;
+            offset += data.len() as u64;
+            writer.add_chunk(offset, &digest)?;
+        }
+        let csum = writer.close()?;
+        manifest.add_file("data.didx".into(), offset, csum, CryptMode::None)?;
+
+        let manifest_blob = DataBlob::encode(manifest.to_string(None)?.as_bytes(), None, true)?;
+        std::fs::write(
+            snapshot.full_path().join(MANIFEST_BLOB_NAME),
+            manifest_blob.raw_data(),
+        )?;
+
+        Ok(manifest)
+    }
+
+    #[test]
+    fn test_export_import_roundtrip() -> Result<(), Error> {
+        let (source, source_path) = test_datastore("source")?;
+        let (target, target_path) = test_datastore("target")?;
+
+        let dir: pbs_api_types::BackupDir = "host/test/2024-01-01T00:00:00Z".parse()?;
+        let owner: Authid = "test@pbs".parse()?;
+        let manifest = create_snapshot(&source, &dir, &owner)?;
+
+        let mut archive = Vec::new();
+        let count = export_snapshot(
+            source.clone(),
+            BackupNamespace::root(),
+            dir.clone(),
+            &mut archive,
+        )?;
+        assert_eq!(count, 2);
+
+        let ns = BackupNamespace::root();
+        let imported = import_snapshot(&target, &ns, &owner, &archive[..])?;
+        assert_eq!(imported, dir);
+        assert_eq!(target.get_owner(&ns, dir.as_ref())?, owner);
+
+        let snapshot = target.backup_dir(ns.clone(), dir.clone())?;
+        let (imported_manifest, _) = snapshot.load_manifest()?;
+        assert_eq!(
+            imported_manifest.content_digest(),
+            manifest.content_digest()
+        );
+
+        let index = target.open_index(snapshot.full_path().join("data.didx"))?;
+        for pos in 0..index.index_count() {
+            let digest = index.index_digest(pos).unwrap();
+            check_chunk(digest, target.load_chunk(digest)?.raw_data().to_vec(), None)?;
+        }
+
+        // importing the same snapshot again is refused
+        assert!(import_snapshot(&target, &ns, &owner, &archive[..]).is_err());
+
+        // a damaged archive is rejected, and leaves no incomplete snapshot behind
+        let other: pbs_api_types::BackupDir = "host/test/2024-01-02T00:00:00Z".parse()?;
+        create_snapshot(&source, &other, &owner)?;
+        let mut archive = Vec::new();
+        export_snapshot(source.clone(), ns.clone(), other.clone(), &mut archive)?;
+        let last = archive.len() - 1;
+        archive[last] ^= 0xff;
+        assert!(import_snapshot(&target, &ns, &owner, &archive[..]).is_err());
+        assert!(!target.snapshot_path(&ns, &other).exists());
+
+        let _ = std::fs::remove_dir_all(source_path);
+        let _ = std::fs::remove_dir_all(target_path);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_digest_salt_mismatch() -> Result<(), Error> {
+        let (target, target_path) = test_datastore("unsalted")?;
+        let owner: Authid = "test@pbs".parse()?;
+
+        let mut writer = SnapshotExportWriter::new(Vec::new())?;
+        writer.add_digest_salt(&crate::data_blob::digest_salt_fingerprint(&[1u8; 32]))?;
+        let archive = writer.finish()?;
+
+        // rejected before even looking at the manifest
+        let err =
+            import_snapshot(&target, &BackupNamespace::root(), &owner, &archive[..]).unwrap_err();
+        assert!(err.to_string().contains("different digest salts"));
+
+        let _ = std::fs::remove_dir_all(target_path);
+
+        Ok(())
+    }
+}
@@ -1,4 +1,6 @@
 use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::sync::OnceLock;
 use std::time::Duration;
 
 use anyhow::{Context, Error};
@@ -7,6 +9,150 @@ use serde_json::Value;
 use proxmox_human_byte::HumanByte;
 use proxmox_time::TimeSpan;
 
+/// Translations are JSON objects mapping the english text to the translated one, named after the
+/// language, like `de.json`.
+const TRANSLATION_DIR: &str = "/usr/share/proxmox-backup/i18n";
+
+// languages using a comma as decimal separator
+const DECIMAL_COMMA_LANGUAGES: &[&str] = &[
+    "bg", "ca", "cs", "da", "de", "el", "es", "eu", "fi", "fr", "gl", "hr", "hu", "id", "it", "lt",
+    "lv", "nb", "nl", "nn", "pl", "pt", "ro", "ru", "sk", "sl", "sr", "sv", "tr", "uk", "vi",
+];
+
+/// Locale of the human readable CLI output.
+///
+/// Only the text output of the render helpers is localized, the JSON output formats stay the same
+/// for all locales. Use `LC_ALL=C` for the untranslated text output.
+pub struct Locale {
+    /// Language code, like `de`, or `C` for the default locale.
+    pub language: String,
+    /// Separator of the fractional part of numbers.
+    pub decimal_separator: char,
+    translations: HashMap<String, String>,
+}
+
+static LOCALE: OnceLock<Locale> = OnceLock::new();
+
+/// Returns the value of the first set locale variable, in the order of precedence of POSIX.
+fn locale_env(category: &str) -> Option<String> {
+    ["LC_ALL", category, "LANG"]
+        .into_iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+}
+
+/// Returns the language of a locale name like `de_AT.UTF-8@euro`.
+fn locale_language(name: &str) -> Option<&str> {
+    let language = name.split(['_', '.', '@']).next()?;
+    match language {
+        "" | "C" | "POSIX" => None,
+        language => Some(language),
+    }
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self {
+            language: "C".to_string(),
+            decimal_separator: '.',
+            translations: HashMap::new(),
+        }
+    }
+}
+
+impl Locale {
+    /// Detect the locale from the `LC_ALL`, `LC_MESSAGES`, `LC_NUMERIC` and `LANG` variables.
+    pub fn from_env() -> Self {
+        let mut locale = Self::default();
+
+        let numeric = locale_env("LC_NUMERIC");
+        if let Some(language) = numeric.as_deref().and_then(locale_language) {
+            if DECIMAL_COMMA_LANGUAGES.contains(&language) {
+                locale.decimal_separator = ',';
+            }
+        }
+
+        let messages = locale_env("LC_MESSAGES");
+        if let Some(language) = messages.as_deref().and_then(locale_language) {
+            locale.language = language.to_string();
+            let path = format!("{TRANSLATION_DIR}/{language}.json");
+            match std::fs::read_to_string(&path) {
+                Ok(data) => match serde_json::from_str(&data) {
+                    Ok(translations) => locale.translations = translations,
+                    Err(err) => log::warn!("unable to parse translations {path:?} - {err}"),
+                },
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+                Err(err) => log::warn!("unable to read translations {path:?} - {err}"),
+            }
+        }
+
+        locale
+    }
+
+    /// Returns the locale set up by [setup_locale], the default locale if it was not called.
+    pub fn current() -> &'static Self {
+        LOCALE.get_or_init(Self::default)
+    }
+
+    /// Returns the translation of `text`, or `text` itself if there is none.
+    pub fn translate<'a>(&'a self, text: &'a str) -> &'a str {
+        self.translations
+            .get(text)
+            .map(String::as_str)
+            .unwrap_or(text)
+    }
+
+    /// Format a number for humans, with the decimal separator of the locale.
+    pub fn format_number(&self, number: &str) -> String {
+        if self.decimal_separator == '.' {
+            number.to_string()
+        } else {
+            number.replace('.', &self.decimal_separator.to_string())
+        }
+    }
+}
+
+/// Set up the locale of the human readable output from the environment.
+///
+/// Meant to be called at the start of CLI tools, before any output is rendered. This also sets
+/// the libc `LC_TIME` locale, so dates are formatted in the local format. As `setlocale` is not
+/// thread safe, this has to be called before any threads are started, like those of the async
+/// runtime.
+pub fn setup_locale() {
+    LOCALE.get_or_init(|| {
+        unsafe {
+            libc::setlocale(libc::LC_TIME, b"\0".as_ptr() as *const libc::c_char);
+        }
+        Locale::from_env()
+    });
+}
+
+/// Translate a CLI message into the language of the current locale.
+pub fn tr(text: &str) -> &str {
+    Locale::current().translate(text)
+}
+
+/// Translate a CLI message with `{}` placeholders, which are replaced by `args` in order.
+///
+/// The placeholders are replaced after the translation, so the translation is looked up by the
+/// message with the placeholders, like `"Path: {}"`.
+pub fn tr_format(text: &str, args: &[&dyn std::fmt::Display]) -> String {
+    fill_placeholders(tr(text), args)
+}
+
+fn fill_placeholders(text: &str, args: &[&dyn std::fmt::Display]) -> String {
+    let mut args = args.iter();
+    let mut parts = text.split("{}");
+    let mut result = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        if let Some(arg) = args.next() {
+            result.push_str(&arg.to_string());
+        }
+        result.push_str(part);
+    }
+    result
+}
+
 pub fn strip_server_file_extension(name: &str) -> &str {
     if name.ends_with(".didx") || name.ends_with(".fidx") || name.ends_with(".blob") {
         &name[..name.len() - 5]
@@ -45,9 +191,9 @@ pub fn render_epoch(value: &Value, _record: &Value) -> Result<String, Error> {
 
 pub fn render_task_status(value: &Value, record: &Value) -> Result<String, Error> {
     if record["endtime"].is_null() {
-        Ok(value.as_str().unwrap_or("running").to_string())
+        Ok(value.as_str().unwrap_or(tr("running")).to_string())
     } else {
-        Ok(value.as_str().unwrap_or("unknown").to_string())
+        Ok(value.as_str().unwrap_or(tr("unknown")).to_string())
     }
 }
 
@@ -61,7 +207,7 @@ pub fn render_bytes_human_readable(value: &Value, _record: &Value) -> Result<Str
         return Ok(String::new());
     }
     let text = match value.as_u64() {
-        Some(bytes) => HumanByte::from(bytes).to_string(),
+        Some(bytes) => Locale::current().format_number(&HumanByte::from(bytes).to_string()),
         None => value.to_string(),
     };
     Ok(text)
@@ -76,3 +222,55 @@ pub fn render_duration(val: &Value, _record: &Value) -> Result<String, Error> {
 
     Ok(format!("{time_span}"))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_locale_language() {
+        assert_eq!(locale_language("de_AT.UTF-8@euro"), Some("de"));
+        assert_eq!(locale_language("fr_FR.UTF-8"), Some("fr"));
+        assert_eq!(locale_language("en"), Some("en"));
+        assert_eq!(locale_language("C.UTF-8"), None);
+        assert_eq!(locale_language("C"), None);
+        assert_eq!(locale_language("POSIX"), None);
+        assert_eq!(locale_language(""), None);
+    }
+
+    #[test]
+    fn test_locale_formatting() {
+        let locale = Locale {
+            language: "de".to_string(),
+            decimal_separator: ',',
+            translations: HashMap::from([("Path: {}".to_string(), "Pfad: {}".to_string())]),
+        };
+        assert_eq!(locale.format_number("1.5 GiB"), "1,5 GiB");
+        assert_eq!(locale.translate("Path: {}"), "Pfad: {}");
+        assert_eq!(locale.translate("Issuer: {}"), "Issuer: {}");
+        assert_eq!(Locale::default().format_number("1.5 GiB"), "1.5 GiB");
+
+        assert_eq!(
+            fill_placeholders(locale.translate("Path: {}"), &[&"/datastore"]),
+            "Pfad: /datastore"
+        );
+        assert_eq!(fill_placeholders("{} of {}", &[&1, &2]), "1 of 2");
+        assert_eq!(fill_placeholders("{} of {}", &[&1]), "1 of ");
+    }
+
+    #[test]
+    fn test_shipped_translations() {
+        let placeholders = |text: &str| text.matches("{}").count();
+        for (language, data) in [("de", include_str!("../../i18n/de.json"))] {
+            let translations: HashMap<String, String> = serde_json::from_str(data)
+                .unwrap_or_else(|err| panic!("unable to parse {language}.json - {err}"));
+            for (text, translation) in translations {
+                assert_eq!(
+                    placeholders(&text),
+                    placeholders(&translation),
+                    "placeholders of {text:?} differ in {language}.json"
+                );
+            }
+        }
+    }
+}
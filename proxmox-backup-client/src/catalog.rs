@@ -19,7 +19,8 @@ use crate::{
     complete_pxar_archive_name, complete_repository, connect, crypto_parameters, decrypt_key,
     dir_or_last_from_group, extract_repository_from_value, format_key_source, optional_ns_param,
     record_repository, BackupDir, BufferedDynamicReadAt, BufferedDynamicReader, CatalogReader,
-    DynamicIndexReader, IndexFile, Shell, CATALOG_NAME, KEYFD_SCHEMA, REPO_URL_SCHEMA,
+    DynamicIndexReader, IndexFile, Shell, CATALOG_NAME, KEYFD_SCHEMA, PROFILE_SCHEMA,
+    REPO_URL_SCHEMA,
 };
 
 #[api(
@@ -29,6 +30,10 @@ use crate::{
                 schema: REPO_URL_SCHEMA,
                 optional: true,
             },
+            profile: {
+                schema: PROFILE_SCHEMA,
+                optional: true,
+            },
             ns: {
                 type: BackupNamespace,
                 optional: true,
@@ -143,6 +148,10 @@ async fn dump_catalog(param: Value) -> Result<Value, Error> {
                 optional: true,
                 schema: REPO_URL_SCHEMA,
             },
+            "profile": {
+                optional: true,
+                schema: PROFILE_SCHEMA,
+            },
             "keyfile": {
                 optional: true,
                 type: String,
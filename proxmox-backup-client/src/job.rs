@@ -0,0 +1,575 @@
+//! Scheduled backup jobs, run by `proxmox-backup-client daemon`.
+//!
+//! Jobs are read from `jobs.cfg` in the system wide (usually `/etc/xdg/proxmox-backup/`) and in
+//! the per-user (usually `~/.config/proxmox-backup/`) config directory:
+//!
+//! ```text
+//! job: nightly
+//!     schedule daily
+//!     backupspec root.pxar:/ etc.pxar:/etc
+//!     repository backup@pbs@pbs.example.com:store1
+//!     keyfile /root/backup-key.json
+//! ```
+//!
+//! Each job runs the `backup` command with its settings, failed runs are retried with an
+//! increasing delay. The result of the last run is kept in the data directory (usually
+//! `~/.local/share/proxmox-backup/jobs/`), and a lock file there prevents concurrent runs of the
+//! same job, also between a daemon and `job run`.
+//!
+//! If started by systemd socket activation, the daemon also accepts `run <job>` requests on the
+//! socket, which `job run` uses to let a running daemon start a job.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{bail, format_err, Error};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use proxmox_router::cli::*;
+use proxmox_schema::{api, ApiStringFormat, ApiType, Schema, StringSchema};
+use proxmox_section_config::{SectionConfig, SectionConfigData, SectionConfigPlugin};
+use proxmox_sys::fs::{file_read_optional_string, replace_file, CreateOptions};
+use proxmox_time::{epoch_i64, CalendarEvent};
+
+use pbs_api_types::{BackupNamespace, BACKUP_ID_SCHEMA, JOB_ID_SCHEMA, SINGLE_LINE_COMMENT_SCHEMA};
+use pbs_client::parse_backup_specification;
+use pbs_client::tools::key_source::KEYFILE_SCHEMA;
+use pbs_client::tools::{base_directories, REPO_URL_SCHEMA};
+
+pub const JOBS_CFG_FILENAME: &str = "jobs.cfg";
+
+const DEFAULT_RETRIES: u64 = 3;
+const DEFAULT_RETRY_DELAY: u64 = 60;
+// upper limit of the doubled retry delay
+const MAX_RETRY_DELAY: u64 = 3600;
+// jobs are re-read and checked at least this often
+const CHECK_INTERVAL: i64 = 60;
+
+pub const BACKUP_JOB_SCHEDULE_SCHEMA: Schema =
+    StringSchema::new("Run the backup job at specified schedule.")
+        .format(&ApiStringFormat::VerifyFn(
+            proxmox_time::verify_calendar_event,
+        ))
+        .type_text("<calendar-event>")
+        .schema();
+
+#[api(
+    properties: {
+        id: {
+            schema: JOB_ID_SCHEMA,
+        },
+        schedule: {
+            schema: BACKUP_JOB_SCHEDULE_SCHEMA,
+        },
+        backupspec: {
+            type: String,
+            description: "Space separated list of backup source specifications \
+                ([<label.ext>:<path>]), like for the 'backup' command.",
+        },
+        repository: {
+            schema: REPO_URL_SCHEMA,
+        },
+        keyfile: {
+            schema: KEYFILE_SCHEMA,
+            optional: true,
+        },
+        ns: {
+            type: BackupNamespace,
+            optional: true,
+        },
+        "backup-id": {
+            schema: BACKUP_ID_SCHEMA,
+            optional: true,
+        },
+        retries: {
+            type: Integer,
+            description: "Number of retries of a failed run.",
+            minimum: 0,
+            maximum: 100,
+            default: DEFAULT_RETRIES as isize,
+            optional: true,
+        },
+        "retry-delay": {
+            type: Integer,
+            description: "Seconds to wait before the first retry, doubled for every further one.",
+            minimum: 1,
+            maximum: MAX_RETRY_DELAY as isize,
+            default: DEFAULT_RETRY_DELAY as isize,
+            optional: true,
+        },
+        comment: {
+            schema: SINGLE_LINE_COMMENT_SCHEMA,
+            optional: true,
+        },
+    },
+)]
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// A scheduled backup job of the client daemon.
+pub struct BackupJobConfig {
+    pub id: String,
+    pub schedule: String,
+    pub backupspec: String,
+    pub repository: String,
+    pub keyfile: Option<String>,
+    pub ns: Option<BackupNamespace>,
+    pub backup_id: Option<String>,
+    pub retries: Option<u64>,
+    pub retry_delay: Option<u64>,
+    pub comment: Option<String>,
+}
+
+lazy_static! {
+    static ref CONFIG: SectionConfig = init();
+}
+
+fn init() -> SectionConfig {
+    let obj_schema = match BackupJobConfig::API_SCHEMA {
+        Schema::Object(ref obj_schema) => obj_schema,
+        _ => unreachable!(),
+    };
+
+    let plugin = SectionConfigPlugin::new("job".to_string(), Some("id".to_string()), obj_schema);
+    let mut config = SectionConfig::new(&JOB_ID_SCHEMA);
+    config.register_plugin(plugin);
+
+    config
+}
+
+/// Read the jobs from all configuration files, per-user jobs replace system wide ones.
+pub fn load_jobs() -> Result<Vec<BackupJobConfig>, Error> {
+    let mut jobs = HashMap::new();
+
+    // ordered from the lowest to the highest priority
+    for path in base_directories()?.find_config_files(JOBS_CFG_FILENAME) {
+        let content = std::fs::read_to_string(&path)
+            .map_err(|err| format_err!("unable to read {path:?} - {err}"))?;
+        let data: SectionConfigData = CONFIG
+            .parse(&path.to_string_lossy(), &content)
+            .map_err(|err| format_err!("unable to parse {path:?} - {err}"))?;
+        for job in data.convert_to_typed_array::<BackupJobConfig>("job")? {
+            jobs.insert(job.id.clone(), job);
+        }
+    }
+
+    let mut jobs: Vec<BackupJobConfig> = jobs.into_values().collect();
+    jobs.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(jobs)
+}
+
+fn lookup_job(id: &str) -> Result<BackupJobConfig, Error> {
+    load_jobs()?
+        .into_iter()
+        .find(|job| job.id == id)
+        .ok_or_else(|| format_err!("backup job '{id}' not found"))
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Result of the last run of a job.
+struct JobState {
+    /// Start of the last run (epoch).
+    last_run: Option<i64>,
+    /// End of the last successful run (epoch).
+    last_success: Option<i64>,
+    /// Error of the last run, if it failed.
+    last_error: Option<String>,
+    /// Number of attempts of the last run.
+    attempts: u64,
+}
+
+fn job_file(id: &str, ext: &str) -> Result<PathBuf, Error> {
+    // usually $HOME/.local/share/proxmox-backup/jobs/<id>.<ext>
+    Ok(base_directories()?.place_data_file(format!("jobs/{id}.{ext}"))?)
+}
+
+fn load_state(id: &str) -> Result<JobState, Error> {
+    match file_read_optional_string(job_file(id, "json")?)? {
+        Some(data) => Ok(serde_json::from_str(&data)?),
+        None => Ok(JobState::default()),
+    }
+}
+
+fn save_state(id: &str, state: &JobState) -> Result<(), Error> {
+    let data = serde_json::to_vec(state)?;
+    replace_file(job_file(id, "json")?, &data, CreateOptions::new(), false)
+}
+
+/// Take the lock of a job, fails if it is running already.
+fn lock_job(id: &str) -> Result<File, Error> {
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .open(job_file(id, "lck")?)?;
+    nix::fcntl::flock(
+        file.as_raw_fd(),
+        nix::fcntl::FlockArg::LockExclusiveNonblock,
+    )
+    .map_err(|_| format_err!("backup job '{id}' is already running"))?;
+    Ok(file)
+}
+
+/// Returns the time of the next run of a job, jobs which never ran are due right away.
+fn next_run(job: &BackupJobConfig, state: &JobState) -> Result<Option<i64>, Error> {
+    let event: CalendarEvent = job.schedule.parse()?;
+    match state.last_run {
+        Some(last) => event.compute_next_event(last),
+        None => Ok(Some(0)),
+    }
+}
+
+fn backup_args(job: &BackupJobConfig) -> Result<Vec<String>, Error> {
+    let mut args = vec!["backup".to_string()];
+    for spec in job.backupspec.split_whitespace() {
+        parse_backup_specification(spec)?;
+        args.push(spec.to_string());
+    }
+    if args.len() == 1 {
+        bail!("backup job '{}' has no backup specification", job.id);
+    }
+    args.extend(["--repository".to_string(), job.repository.clone()]);
+    if let Some(keyfile) = &job.keyfile {
+        args.extend(["--keyfile".to_string(), keyfile.clone()]);
+    }
+    if let Some(ns) = &job.ns {
+        args.extend(["--ns".to_string(), ns.to_string()]);
+    }
+    if let Some(backup_id) = &job.backup_id {
+        args.extend(["--backup-id".to_string(), backup_id.clone()]);
+    }
+    Ok(args)
+}
+
+/// Run the backup command of a job once.
+async fn run_backup(job: &BackupJobConfig) -> Result<(), Error> {
+    let args = backup_args(job)?;
+    let exe = std::env::current_exe()?;
+    let status =
+        tokio::task::spawn_blocking(move || std::process::Command::new(exe).args(args).status())
+            .await??;
+    if !status.success() {
+        bail!("backup command failed - {status}");
+    }
+    Ok(())
+}
+
+/// Run a job with its retries and record the result.
+pub async fn run_job(job: &BackupJobConfig) -> Result<(), Error> {
+    let _lock = lock_job(&job.id)?;
+
+    let retries = job.retries.unwrap_or(DEFAULT_RETRIES);
+    let mut delay = job.retry_delay.unwrap_or(DEFAULT_RETRY_DELAY);
+
+    let mut state = load_state(&job.id)?;
+    state.last_run = Some(epoch_i64());
+    state.attempts = 0;
+
+    let result = loop {
+        state.attempts += 1;
+        log::info!(
+            "backup job '{}': starting attempt {}",
+            job.id,
+            state.attempts
+        );
+        match run_backup(job).await {
+            Ok(()) => break Ok(()),
+            Err(err) if state.attempts > retries => break Err(err),
+            Err(err) => {
+                log::warn!(
+                    "backup job '{}' failed, retrying in {delay}s - {err}",
+                    job.id
+                );
+                tokio::time::sleep(Duration::from_secs(delay)).await;
+                delay = (delay * 2).min(MAX_RETRY_DELAY);
+            }
+        }
+    };
+
+    match &result {
+        Ok(()) => {
+            state.last_success = Some(epoch_i64());
+            state.last_error = None;
+            log::info!("backup job '{}' finished", job.id);
+        }
+        Err(err) => {
+            state.last_error = Some(err.to_string());
+            log::error!("backup job '{}' failed - {err}", job.id);
+        }
+    }
+    save_state(&job.id, &state)?;
+
+    result
+}
+
+/// The socket `job run` sends requests to, as created by the systemd socket unit.
+fn daemon_socket_path() -> PathBuf {
+    if nix::unistd::Uid::effective().is_root() {
+        return PathBuf::from("/run/proxmox-backup-client/daemon.sock");
+    }
+    let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(format!("/run/user/{}", nix::unistd::getuid())));
+    runtime_dir.join("proxmox-backup-client/daemon.sock")
+}
+
+/// Returns the listening socket passed by systemd socket activation, if any.
+fn activated_socket() -> Option<UnixListener> {
+    let pid: u32 = std::env::var("LISTEN_PID").ok()?.parse().ok()?;
+    let fds: u32 = std::env::var("LISTEN_FDS").ok()?.parse().ok()?;
+    if pid != std::process::id() || fds < 1 {
+        return None;
+    }
+    std::env::remove_var("LISTEN_PID");
+    std::env::remove_var("LISTEN_FDS");
+    std::env::remove_var("LISTEN_FDNAMES");
+    // SD_LISTEN_FDS_START, the fd is owned by us from now on
+    let listener = unsafe { UnixListener::from_raw_fd(3) };
+    // the backup commands run as children must not inherit it
+    let _ = nix::fcntl::fcntl(
+        3,
+        nix::fcntl::FcntlArg::F_SETFD(nix::fcntl::FdFlag::FD_CLOEXEC),
+    );
+    Some(listener)
+}
+
+type RunRequests = Arc<Mutex<HashSet<String>>>;
+
+fn handle_request(stream: UnixStream, requests: &RunRequests) -> Result<(), Error> {
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let reply = match line.trim().split_once(' ') {
+        Some(("run", id)) => match lookup_job(id) {
+            Ok(_) => {
+                requests.lock().unwrap().insert(id.to_string());
+                "OK".to_string()
+            }
+            Err(err) => format!("ERROR {err}"),
+        },
+        _ => "ERROR invalid request".to_string(),
+    };
+    (&stream).write_all(format!("{reply}\n").as_bytes())?;
+    Ok(())
+}
+
+fn start_request_listener(listener: UnixListener, requests: RunRequests) {
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let result = stream
+                .map_err(Error::from)
+                .and_then(|stream| handle_request(stream, &requests));
+            if let Err(err) = result {
+                log::error!("daemon request failed - {err}");
+            }
+        }
+    });
+}
+
+#[api(
+    input: {
+        properties: {
+            oneshot: {
+                type: Boolean,
+                description: "Run the due jobs once and exit, for activation by a timer.",
+                optional: true,
+                default: false,
+            },
+        },
+    },
+)]
+/// Run the scheduled backup jobs of the jobs configuration.
+pub async fn daemon(oneshot: bool) -> Result<(), Error> {
+    let requests = RunRequests::default();
+    if let Some(listener) = activated_socket() {
+        start_request_listener(listener, Arc::clone(&requests));
+    }
+
+    let running: Arc<Mutex<HashSet<String>>> = Default::default();
+    let mut oneshot_runs = Vec::new();
+
+    loop {
+        let now = epoch_i64();
+        let mut wakeup = now + CHECK_INTERVAL;
+
+        let jobs = match load_jobs() {
+            Ok(jobs) => jobs,
+            Err(err) => {
+                log::error!("unable to load backup jobs - {err}");
+                Vec::new()
+            }
+        };
+        let mut requested = std::mem::take(&mut *requests.lock().unwrap());
+
+        for job in jobs {
+            if running.lock().unwrap().contains(&job.id) {
+                continue;
+            }
+            let next = match load_state(&job.id).and_then(|state| next_run(&job, &state)) {
+                Ok(next) => next,
+                Err(err) => {
+                    log::error!("unable to schedule backup job '{}' - {err}", job.id);
+                    continue;
+                }
+            };
+            let due = next.map(|next| next <= now).unwrap_or(false);
+            if !due && !requested.remove(&job.id) {
+                if let Some(next) = next {
+                    wakeup = wakeup.min(next);
+                }
+                continue;
+            }
+
+            running.lock().unwrap().insert(job.id.clone());
+            let running = Arc::clone(&running);
+            let handle = tokio::spawn(async move {
+                // errors are logged and recorded in the job state
+                let _ = run_job(&job).await;
+                running.lock().unwrap().remove(&job.id);
+            });
+            if oneshot {
+                oneshot_runs.push(handle);
+            }
+        }
+
+        if oneshot {
+            futures::future::join_all(oneshot_runs).await;
+            return Ok(());
+        }
+
+        for id in requested {
+            log::warn!("requested backup job '{id}' is already running");
+        }
+
+        // wake up early to pick up run requests
+        let delay = (wakeup - epoch_i64()).clamp(1, 5);
+        tokio::time::sleep(Duration::from_secs(delay as u64)).await;
+    }
+}
+
+#[api(
+    input: {
+        properties: {
+            id: {
+                schema: JOB_ID_SCHEMA,
+            },
+            foreground: {
+                type: Boolean,
+                description: "Run the job in this process, even if a daemon is running.",
+                optional: true,
+                default: false,
+            },
+        },
+    },
+)]
+/// Run a backup job now.
+///
+/// If a daemon started by socket activation is running, it runs the job, else it runs in the
+/// foreground.
+async fn run(id: String, foreground: bool) -> Result<(), Error> {
+    if !foreground {
+        if let Ok(mut stream) = UnixStream::connect(daemon_socket_path()) {
+            stream.write_all(format!("run {id}\n").as_bytes())?;
+            let mut reply = String::new();
+            BufReader::new(&stream).read_line(&mut reply)?;
+            return match reply.trim().strip_prefix("ERROR ") {
+                Some(err) => bail!("{err}"),
+                None => {
+                    log::info!("backup job '{id}' started by the daemon");
+                    Ok(())
+                }
+            };
+        }
+    }
+
+    run_job(&lookup_job(&id)?).await
+}
+
+#[api(
+    input: {
+        properties: {
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        },
+    },
+)]
+/// Show the configured backup jobs and the result of their last run.
+fn status(param: Value) -> Result<(), Error> {
+    let output_format = get_output_format(&param);
+
+    let mut list = Vec::new();
+    for job in load_jobs()? {
+        let state = load_state(&job.id)?;
+        let running = lock_job(&job.id).is_err();
+        let status = if running {
+            "running".to_string()
+        } else if let Some(err) = &state.last_error {
+            format!("error: {err}")
+        } else if state.last_success.is_some() {
+            "ok".to_string()
+        } else {
+            "never run".to_string()
+        };
+        list.push(json!({
+            "id": job.id,
+            "schedule": job.schedule,
+            "last-run": state.last_run,
+            "last-success": state.last_success,
+            "next-run": next_run(&job, &state).ok().flatten().filter(|next| *next > 0),
+            "attempts": state.attempts,
+            "status": status,
+        }));
+    }
+
+    if output_format == "text" {
+        let render_time = |value: &Value| -> String {
+            value
+                .as_i64()
+                .and_then(|time| proxmox_time::strftime_local("%c", time).ok())
+                .unwrap_or_else(|| "-".to_string())
+        };
+        for item in list {
+            println!(
+                "{} ({}): {} - last run {}, next run {}",
+                item["id"].as_str().unwrap(),
+                item["schedule"].as_str().unwrap(),
+                item["status"].as_str().unwrap(),
+                render_time(&item["last-run"]),
+                render_time(&item["next-run"]),
+            );
+        }
+    } else {
+        format_and_print_result(&Value::from(list), &output_format);
+    }
+
+    Ok(())
+}
+
+pub fn daemon_cmd_def() -> CliCommand {
+    CliCommand::new(&API_METHOD_DAEMON)
+}
+
+pub fn job_mgmt_cli() -> CliCommandMap {
+    CliCommandMap::new()
+        .insert(
+            "run",
+            CliCommand::new(&API_METHOD_RUN)
+                .arg_param(&["id"])
+                .completion_cb("id", complete_job_id),
+        )
+        .insert("status", CliCommand::new(&API_METHOD_STATUS))
+}
+
+fn complete_job_id(_arg: &str, _param: &HashMap<String, String>) -> Vec<String> {
+    load_jobs()
+        .map(|jobs| jobs.into_iter().map(|job| job.id).collect())
+        .unwrap_or_default()
+}
@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
@@ -7,7 +7,7 @@ use std::task::Context;
 
 use anyhow::{bail, format_err, Error};
 use futures::stream::{StreamExt, TryStreamExt};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::ReceiverStream;
@@ -24,32 +24,35 @@ use proxmox_time::{epoch_i64, strftime_local};
 use pxar::accessor::{MaybeReady, ReadAt, ReadAtOperation};
 
 use pbs_api_types::{
-    Authid, BackupDir, BackupGroup, BackupNamespace, BackupPart, BackupType, CryptMode,
-    Fingerprint, GroupListItem, PruneJobOptions, PruneListItem, RateLimitConfig, SnapshotListItem,
-    StorageStatus, BACKUP_ID_SCHEMA, BACKUP_NAMESPACE_SCHEMA, BACKUP_TIME_SCHEMA,
+    Authid, BackupDir, BackupGroup, BackupNamespace, BackupPart, BackupPriority, BackupType,
+    CryptMode, Fingerprint, GroupListItem, PruneJobOptions, PruneListItem, RateLimitConfig,
+    SnapshotListItem, StorageStatus, BACKUP_ID_SCHEMA, BACKUP_NAMESPACE_SCHEMA, BACKUP_TIME_SCHEMA,
     BACKUP_TYPE_SCHEMA, TRAFFIC_CONTROL_BURST_SCHEMA, TRAFFIC_CONTROL_RATE_SCHEMA,
 };
 use pbs_client::catalog_shell::Shell;
-use pbs_client::pxar::ErrorHandler as PxarErrorHandler;
+use pbs_client::pxar::{ErrorHandler as PxarErrorHandler, XattrFilter};
 use pbs_client::tools::{
+    client_config::profile_from_param,
     complete_archive_name, complete_auth_id, complete_backup_group, complete_backup_snapshot,
     complete_backup_source, complete_chunk_size, complete_group_or_snapshot,
     complete_img_archive_name, complete_namespace, complete_pxar_archive_name, complete_repository,
-    connect, connect_rate_limited, extract_repository_from_value,
+    connect, connect_first_reachable, connect_rate_limited, extract_repository_from_value,
+    fetch_token_profile,
     key_source::{
         crypto_parameters, format_key_source, get_encryption_key_password, KEYFD_SCHEMA,
         KEYFILE_SCHEMA, MASTER_PUBKEY_FD_SCHEMA, MASTER_PUBKEY_FILE_SCHEMA,
     },
-    CHUNK_SIZE_SCHEMA, REPO_URL_SCHEMA,
+    reader_reconnect, CHUNK_SIZE_SCHEMA, PROFILE_SCHEMA, REPO_URL_SCHEMA,
 };
 use pbs_client::{
     delete_ticket_info, parse_backup_specification, view_task_result, BackupReader,
     BackupRepository, BackupSpecificationType, BackupStats, BackupWriter, ChunkStream,
-    FixedChunkStream, HttpClient, PxarBackupStream, RemoteChunkReader, UploadOptions,
-    BACKUP_SOURCE_SCHEMA,
+    FixedChunkStream, HttpClient, PxarBackupStream, ReconnectFn, RemoteChunkReader, UploadOptions,
+    BACKUP_SOURCE_SCHEMA, BACKUP_SOURCE_STDIN,
 };
 use pbs_datastore::catalog::{BackupCatalogWriter, CatalogReader, CatalogWriter};
 use pbs_datastore::chunk_store::verify_chunk_size;
+use pbs_datastore::data_blob::{compute_unencrypted_digest, METADATA_COMPRESSION_LEVEL};
 use pbs_datastore::dynamic_index::{BufferedDynamicReader, DynamicIndexReader};
 use pbs_datastore::fixed_index::FixedIndexReader;
 use pbs_datastore::index::IndexFile;
@@ -60,6 +63,7 @@ use pbs_datastore::read_chunk::AsyncReadChunk;
 use pbs_datastore::CATALOG_NAME;
 use pbs_key_config::{decrypt_key, rsa_encrypt_key_config, KeyConfig};
 use pbs_tools::crypt_config::CryptConfig;
+use pbs_tools::format::tr_format;
 use pbs_tools::json;
 
 mod benchmark;
@@ -72,6 +76,7 @@ mod catalog;
 pub use catalog::*;
 mod snapshot;
 pub use snapshot::*;
+pub mod job;
 pub mod key;
 pub mod namespace;
 
@@ -217,26 +222,121 @@ async fn backup_directory<P: AsRef<Path>>(
     Ok(stats)
 }
 
-async fn backup_image<P: AsRef<Path>>(
+fn log_pxar_stats(archive_name: &str, stats: &pbs_client::pxar::PxarCreateStats) {
+    log::info!(
+        "{archive_name}: {} hardlinks, {} in sparse files, {} excluded entries, {} skipped cache directories, {} skipped cache files",
+        stats.hardlinks,
+        HumanByte::from(stats.sparse_bytes),
+        stats.excluded,
+        stats.cache_dirs,
+        stats.cache_files,
+    );
+    for (top_level, size) in stats.top_level_sizes.iter() {
+        log::info!("{archive_name}: {top_level}: {}", HumanByte::from(*size));
+    }
+}
+
+/// Open a backup source for reading, `-` is standard input.
+async fn open_backup_source(
+    path: &str,
+) -> Result<Box<dyn tokio::io::AsyncRead + Send + Unpin>, Error> {
+    if path == BACKUP_SOURCE_STDIN {
+        Ok(Box::new(tokio::io::stdin()))
+    } else {
+        let file = tokio::fs::File::open(path)
+            .await
+            .map_err(|err| format_err!("unable to open '{path}' - {err}"))?;
+        Ok(Box::new(file))
+    }
+}
+
+/// Fail if the stream does not deliver exactly `size` bytes, if set.
+///
+/// The size of inputs like standard input is only announced, a mismatch would otherwise only be
+/// noticed by the server when closing the index.
+fn exact_size_stream<T: AsRef<[u8]>>(
+    stream: impl futures::Stream<Item = Result<T, Error>>,
+    size: Option<u64>,
+) -> impl futures::Stream<Item = Result<T, Error>> {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    let received = Arc::new(AtomicU64::new(0));
+    let received2 = Arc::clone(&received);
+
+    let stream = stream.map(move |data| {
+        let data = data?;
+        let len = data.as_ref().len() as u64;
+        let total = received.fetch_add(len, Ordering::SeqCst) + len;
+        match size {
+            Some(size) if total > size => {
+                bail!("input is larger than the announced size of {size} bytes")
+            }
+            _ => Ok(data),
+        }
+    });
+
+    let end = futures::stream::once(async move {
+        let total = received2.load(Ordering::SeqCst);
+        match size {
+            Some(size) if total != size => {
+                bail!("input ended after {total} of the announced {size} bytes")
+            }
+            _ => Ok(()),
+        }
+    })
+    .try_filter_map(|()| futures::future::ok(None));
+
+    stream.chain(end)
+}
+
+async fn backup_image(
     client: &BackupWriter,
-    image_path: P,
+    image_path: &str,
     archive_name: &str,
     chunk_size: Option<usize>,
     upload_options: UploadOptions,
 ) -> Result<BackupStats, Error> {
-    let path = image_path.as_ref().to_owned();
+    let size = match upload_options.fixed_size {
+        Some(size) => size,
+        None => bail!("cannot backup image with dynamic chunk size!"),
+    };
 
-    let file = tokio::fs::File::open(path).await?;
+    let input = open_backup_source(image_path).await?;
 
-    let stream = tokio_util::codec::FramedRead::new(file, tokio_util::codec::BytesCodec::new())
+    let stream = tokio_util::codec::FramedRead::new(input, tokio_util::codec::BytesCodec::new())
         .map_err(Error::from);
 
+    // files and block devices have a known size, standard input only the announced one
+    let announced_size = (image_path == BACKUP_SOURCE_STDIN).then_some(size);
+    let stream = Box::pin(exact_size_stream(stream, announced_size));
+
     let stream = FixedChunkStream::new(stream, chunk_size.unwrap_or(4 * 1024 * 1024));
 
-    if upload_options.fixed_size.is_none() {
-        bail!("cannot backup image with dynamic chunk size!");
+    let stats = client
+        .upload_stream(archive_name, stream, upload_options)
+        .await?;
+
+    Ok(stats)
+}
+
+async fn backup_raw_stream(
+    client: &BackupWriter,
+    path: &str,
+    archive_name: &str,
+    chunk_size: Option<usize>,
+    upload_options: UploadOptions,
+) -> Result<BackupStats, Error> {
+    if upload_options.fixed_size.is_some() {
+        bail!("cannot backup data stream with fixed chunk size!");
     }
 
+    let input = open_backup_source(path).await?;
+
+    let stream = tokio_util::codec::FramedRead::new(input, tokio_util::codec::BytesCodec::new())
+        .map_err(Error::from);
+
+    let stream = ChunkStream::new(stream, chunk_size);
+
     let stats = client
         .upload_stream(archive_name, stream, upload_options)
         .await?;
@@ -244,14 +344,92 @@ async fn backup_image<P: AsRef<Path>>(
     Ok(stats)
 }
 
+/// Read a blob source completely, `-` is standard input.
+async fn read_blob_source(path: &str) -> Result<Vec<u8>, Error> {
+    use tokio::io::AsyncReadExt;
+
+    let mut data = Vec::new();
+    open_backup_source(path)
+        .await?
+        .read_to_end(&mut data)
+        .await
+        .map_err(|err| format_err!("unable to read '{path}' - {err}"))?;
+    Ok(data)
+}
+
 pub fn optional_ns_param(param: &Value) -> Result<BackupNamespace, Error> {
     Ok(match param.get("ns") {
         Some(Value::String(ns)) => ns.parse()?,
         Some(_) => bail!("invalid namespace parameter"),
-        None => BackupNamespace::root(),
+        None => profile_from_param(param)?
+            .and_then(|profile| profile.ns)
+            .unwrap_or_else(BackupNamespace::root),
     })
 }
 
+/// Connect for a backup, discovered servers are independent of each other, so use the first
+/// reachable one.
+async fn connect_backup_client(
+    repo: &BackupRepository,
+    rate_limit: RateLimitConfig,
+) -> Result<HttpClient, Error> {
+    if repo.is_discovered() {
+        Ok(connect_first_reachable(repo, rate_limit).await?.0)
+    } else {
+        connect_rate_limited(repo, rate_limit)
+    }
+}
+
+/// Apply the client profile the server suggests for an API token, to the settings which were
+/// not configured locally.
+///
+/// Returns a new client if the rate limit changed, as it is part of the connection.
+async fn apply_token_profile(
+    client: HttpClient,
+    repo: &BackupRepository,
+    param: &Value,
+    backup_ns: &mut BackupNamespace,
+    rate_limit: &mut RateLimitConfig,
+) -> Result<HttpClient, Error> {
+    if !repo.auth_id().is_token() {
+        return Ok(client);
+    }
+    let token_profile = fetch_token_profile(&client).await?;
+
+    let local_ns = param.get("ns").is_some()
+        || profile_from_param(param)?.map_or(false, |profile| profile.ns.is_some());
+    if let (false, Some(ns)) = (local_ns, token_profile.ns) {
+        log::info!("Using namespace '{ns}' from the client profile of the token");
+        *backup_ns = ns;
+    }
+
+    if rate_limit.rate_in.is_none() && token_profile.rate.is_some() {
+        *rate_limit = RateLimitConfig::with_same_inout(token_profile.rate, token_profile.burst);
+        return connect_backup_client(repo, rate_limit.clone()).await;
+    }
+
+    Ok(client)
+}
+
+/// Get the rate limit from the parameters, falling back to the selected profile.
+fn rate_limit_param(param: &Value) -> Result<RateLimitConfig, Error> {
+    let profile = match (param["rate"].as_str(), param["burst"].as_str()) {
+        (None, None) => profile_from_param(param)?,
+        _ => None,
+    };
+
+    let rate = match param["rate"].as_str() {
+        Some(s) => Some(s.parse::<HumanByte>()?),
+        None => profile.as_ref().and_then(|profile| profile.rate),
+    };
+    let burst = match param["burst"].as_str() {
+        Some(s) => Some(s.parse::<HumanByte>()?),
+        None => profile.as_ref().and_then(|profile| profile.burst),
+    };
+
+    Ok(RateLimitConfig::with_same_inout(rate, burst))
+}
+
 #[api(
    input: {
         properties: {
@@ -259,6 +437,10 @@ pub fn optional_ns_param(param: &Value) -> Result<BackupNamespace, Error> {
                 schema: REPO_URL_SCHEMA,
                 optional: true,
             },
+            profile: {
+                schema: PROFILE_SCHEMA,
+                optional: true,
+            },
             "ns": {
                 type: BackupNamespace,
                 optional: true,
@@ -352,6 +534,10 @@ fn merge_group_into(to: &mut serde_json::Map<String, Value>, group: BackupGroup)
                 schema: REPO_URL_SCHEMA,
                 optional: true,
             },
+            profile: {
+                schema: PROFILE_SCHEMA,
+                optional: true,
+            },
             group: {
                 type: String,
                 description: "Backup group.",
@@ -374,6 +560,7 @@ async fn change_backup_owner(group: String, mut param: Value) -> Result<(), Erro
     let client = connect(&repo)?;
 
     param.as_object_mut().unwrap().remove("repository");
+    param.as_object_mut().unwrap().remove("profile");
 
     let group: BackupGroup = group.parse()?;
 
@@ -397,6 +584,10 @@ async fn change_backup_owner(group: String, mut param: Value) -> Result<(), Erro
                 schema: REPO_URL_SCHEMA,
                 optional: true,
             },
+            profile: {
+                schema: PROFILE_SCHEMA,
+                optional: true,
+            },
         }
    }
 )]
@@ -419,6 +610,10 @@ async fn api_login(param: Value) -> Result<Value, Error> {
                 schema: REPO_URL_SCHEMA,
                 optional: true,
             },
+            profile: {
+                schema: PROFILE_SCHEMA,
+                optional: true,
+            },
         }
    }
 )]
@@ -438,6 +633,10 @@ fn api_logout(param: Value) -> Result<Value, Error> {
                 schema: REPO_URL_SCHEMA,
                 optional: true,
             },
+            profile: {
+                schema: PROFILE_SCHEMA,
+                optional: true,
+            },
             "output-format": {
                 schema: OUTPUT_FORMAT,
                 optional: true,
@@ -467,15 +666,17 @@ async fn api_version(param: Value) -> Result<(), Error> {
         }
     }
     if output_format == "text" {
-        println!(
-            "client version: {}.{}",
+        let client_version = format!(
+            "{}.{}",
             pbs_buildcfg::PROXMOX_PKG_VERSION,
             pbs_buildcfg::PROXMOX_PKG_RELEASE,
         );
+        println!("{}", tr_format("client version: {}", &[&client_version]));
         if let Some(server) = version_info["server"].as_object() {
             let server_version = server["version"].as_str().unwrap();
             let server_release = server["release"].as_str().unwrap();
-            println!("server version: {}.{}", server_version, server_release);
+            let server_version = format!("{server_version}.{server_release}");
+            println!("{}", tr_format("server version: {}", &[&server_version]));
         }
     } else {
         format_and_print_result(&version_info, &output_format);
@@ -491,6 +692,10 @@ async fn api_version(param: Value) -> Result<(), Error> {
                 schema: REPO_URL_SCHEMA,
                 optional: true,
             },
+            profile: {
+                schema: PROFILE_SCHEMA,
+                optional: true,
+            },
             "output-format": {
                 schema: OUTPUT_FORMAT,
                 optional: true,
@@ -540,6 +745,7 @@ fn spawn_catalog_upload(
     let upload_options = UploadOptions {
         encrypt,
         compress: true,
+        compression_level: Some(METADATA_COMPRESSION_LEVEL),
         ..UploadOptions::default()
     };
 
@@ -576,6 +782,10 @@ fn spawn_catalog_upload(
                schema: REPO_URL_SCHEMA,
                optional: true,
            },
+           profile: {
+               schema: PROFILE_SCHEMA,
+               optional: true,
+           },
            "include-dev": {
                description: "Include mountpoints with same st_dev number (see ``man fstat``) as specified files.",
                optional: true,
@@ -616,6 +826,24 @@ fn spawn_catalog_upload(
                optional: true,
                default: false,
            },
+           "skip-nodump": {
+               type: Boolean,
+               description: "Skip files and directories with the 'nodump' file attribute (chattr +d).",
+               optional: true,
+               default: false,
+           },
+           "skip-cache-dirs": {
+               type: Boolean,
+               description: "Skip the contents of cache directories tagged with a CACHEDIR.TAG file.",
+               optional: true,
+               default: false,
+           },
+           "skip-known-caches": {
+               type: Boolean,
+               description: "Skip well-known cache locations like '/var/cache/apt/archives/*.deb' or '/home/*/.cache'.",
+               optional: true,
+               default: false,
+           },
            "ns": {
                schema: BACKUP_NAMESPACE_SCHEMA,
                optional: true,
@@ -653,6 +881,15 @@ fn spawn_catalog_upload(
                    description: "Path or match pattern.",
                 }
            },
+           "exclude-xattr": {
+               type: Array,
+               description: "List of extended attributes not to back up, for all or only one archive.",
+               optional: true,
+               items: {
+                   type: String,
+                   description: "'[<archive-name>:]<pattern>', with a name or a namespace like 'user.*' as pattern.",
+                }
+           },
            "entries-max": {
                type: Integer,
                description: "Max number of entries to hold in memory.",
@@ -671,6 +908,16 @@ fn spawn_catalog_upload(
                optional: true,
                default: false,
            },
+           priority: {
+               type: BackupPriority,
+               optional: true,
+           },
+           "stdin-size": {
+               type: Integer,
+               description: "Size in bytes of an image read from standard input ('<label>.img:-').",
+               optional: true,
+               minimum: 1,
+           },
        }
    }
 )]
@@ -679,6 +926,9 @@ async fn create_backup(
     param: Value,
     all_file_systems: bool,
     skip_lost_and_found: bool,
+    skip_nodump: bool,
+    skip_cache_dirs: bool,
+    skip_known_caches: bool,
     dry_run: bool,
     skip_e2big_xattr: bool,
     _info: &ApiMethod,
@@ -696,16 +946,7 @@ async fn create_backup(
         verify_chunk_size(size)?;
     }
 
-    let rate = match param["rate"].as_str() {
-        Some(s) => Some(s.parse::<HumanByte>()?),
-        None => None,
-    };
-    let burst = match param["burst"].as_str() {
-        Some(s) => Some(s.parse::<HumanByte>()?),
-        None => None,
-    };
-
-    let rate_limit = RateLimitConfig::with_same_inout(rate, burst);
+    let mut rate_limit = rate_limit_param(&param)?;
 
     let crypto = crypto_parameters(&param)?;
 
@@ -713,10 +954,15 @@ async fn create_backup(
         .as_str()
         .unwrap_or_else(|| proxmox_sys::nodename());
 
-    let backup_ns = optional_ns_param(&param)?;
+    let mut backup_ns = optional_ns_param(&param)?;
 
     let backup_type: BackupType = param["backup-type"].as_str().unwrap_or("host").parse()?;
 
+    let priority: Option<BackupPriority> = match param.get("priority") {
+        Some(priority) => Some(serde_json::from_value(priority.clone())?),
+        None => None,
+    };
+
     let include_dev = param["include-dev"].as_array();
 
     let entries_max = param["entries-max"]
@@ -737,6 +983,16 @@ async fn create_backup(
         );
     }
 
+    // xattr patterns for all archives are stored with an empty archive name
+    let mut xattr_exclude: HashMap<&str, XattrFilter> = HashMap::new();
+    for entry in param["exclude-xattr"].as_array().unwrap_or(&empty) {
+        let entry = entry
+            .as_str()
+            .ok_or_else(|| format_err!("Invalid pattern string slice"))?;
+        let (archive, pattern) = entry.split_once(':').unwrap_or(("", entry));
+        xattr_exclude.entry(archive).or_default().add(pattern)?;
+    }
+
     let mut devices = if all_file_systems {
         None
     } else {
@@ -760,6 +1016,7 @@ async fn create_backup(
 
     let mut upload_list = vec![];
     let mut target_set = HashSet::new();
+    let mut stdin_used = false;
 
     for backupspec in backupspec_list {
         let spec = parse_backup_specification(backupspec.as_str().unwrap())?;
@@ -771,6 +1028,41 @@ async fn create_backup(
         }
         target_set.insert(target.to_string());
 
+        if !matches!(spec.spec_type, BackupSpecificationType::PXAR)
+            && xattr_exclude.contains_key(target.as_str())
+        {
+            bail!("cannot exclude xattrs from non-directory archive '{target}'");
+        }
+
+        if filename == BACKUP_SOURCE_STDIN {
+            if stdin_used {
+                bail!("only one backup source can be read from standard input");
+            }
+            stdin_used = true;
+
+            let (extension, size) = match spec.spec_type {
+                BackupSpecificationType::PXAR => {
+                    bail!("cannot backup a directory from standard input");
+                }
+                BackupSpecificationType::IMAGE => match param["stdin-size"].as_u64() {
+                    Some(size) => ("fidx", size),
+                    None => {
+                        bail!("reading image '{target}' from standard input requires 'stdin-size'")
+                    }
+                },
+                BackupSpecificationType::CONFIG | BackupSpecificationType::LOGFILE => ("blob", 0),
+                BackupSpecificationType::RAW => ("didx", 0),
+            };
+            upload_list.push((
+                spec.spec_type,
+                filename.to_owned(),
+                target.to_owned(),
+                extension,
+                size,
+            ));
+            continue;
+        }
+
         use std::os::unix::fs::FileTypeExt;
 
         let metadata = std::fs::metadata(filename)
@@ -833,12 +1125,37 @@ async fn create_backup(
                     metadata.len(),
                 ));
             }
+            BackupSpecificationType::RAW => {
+                if !(file_type.is_file() || file_type.is_block_device() || file_type.is_fifo()) {
+                    bail!("got unexpected file type (expected file, block device or fifo)");
+                }
+                upload_list.push((
+                    BackupSpecificationType::RAW,
+                    filename.to_owned(),
+                    target.to_owned(),
+                    "didx",
+                    0,
+                ));
+            }
         }
     }
 
+    if param["stdin-size"].as_u64().is_some() && !stdin_used {
+        bail!("option 'stdin-size' requires an image read from standard input");
+    }
+
+    if let Some(archive) = xattr_exclude
+        .keys()
+        .find(|archive| !archive.is_empty() && !target_set.contains(**archive))
+    {
+        bail!("option 'exclude-xattr' refers to unknown archive '{archive}'");
+    }
+
     let backup_time = backup_time_opt.unwrap_or_else(epoch_i64);
 
-    let http_client = connect_rate_limited(&repo, rate_limit)?;
+    let http_client = connect_backup_client(&repo, rate_limit.clone()).await?;
+    let http_client =
+        apply_token_profile(http_client, &repo, &param, &mut backup_ns, &mut rate_limit).await?;
     record_repository(&repo);
 
     let snapshot = BackupDir::from((backup_type, backup_id.to_owned(), backup_time));
@@ -897,6 +1214,7 @@ async fn create_backup(
         &snapshot,
         true,
         false,
+        priority,
     )
     .await?;
 
@@ -956,6 +1274,7 @@ async fn create_backup(
             (BackupSpecificationType::LOGFILE, true) => log_file("log file", &filename, &target),
             (BackupSpecificationType::PXAR, true) => log_file("directory", &filename, &target),
             (BackupSpecificationType::IMAGE, true) => log_file("image", &filename, &target),
+            (BackupSpecificationType::RAW, true) => log_file("data stream", &filename, &target),
             // no dry-run
             (BackupSpecificationType::CONFIG, false) => {
                 let upload_options = UploadOptions {
@@ -965,8 +1284,9 @@ async fn create_backup(
                 };
 
                 log_file("config file", &filename, &target);
+                let data = read_blob_source(&filename).await?;
                 let stats = client
-                    .upload_blob_from_file(&filename, &target, upload_options)
+                    .upload_blob_from_data(data, &target, upload_options)
                     .await?;
                 manifest.add_file(target, stats.size, stats.csum, crypto.mode)?;
             }
@@ -979,8 +1299,9 @@ async fn create_backup(
                 };
 
                 log_file("log file", &filename, &target);
+                let data = read_blob_source(&filename).await?;
                 let stats = client
-                    .upload_blob_from_file(&filename, &target, upload_options)
+                    .upload_blob_from_data(data, &target, upload_options)
                     .await?;
                 manifest.add_file(target, stats.size, stats.csum, crypto.mode)?;
             }
@@ -995,17 +1316,28 @@ async fn create_backup(
                 let catalog = catalog.as_ref().unwrap();
 
                 log_file("directory", &filename, &target);
+                let pxar_stats = Arc::new(Mutex::new(pbs_client::pxar::PxarCreateStats::default()));
                 catalog
                     .lock()
                     .unwrap()
                     .start_directory(std::ffi::CString::new(target.as_str())?.as_c_str())?;
 
+                let mut xattr_filter = xattr_exclude.get("").cloned().unwrap_or_default();
+                if let Some(archive_filter) = xattr_exclude.get(target_base.as_str()) {
+                    xattr_filter.extend(archive_filter);
+                }
+
                 let pxar_options = pbs_client::pxar::PxarCreateOptions {
                     device_set: devices.clone(),
                     patterns: pattern_list.clone(),
                     entries_max: entries_max as usize,
                     skip_lost_and_found,
+                    skip_nodump,
                     skip_e2big_xattr,
+                    stats: Some(pxar_stats.clone()),
+                    xattr_exclude: xattr_filter,
+                    skip_cache_dirs,
+                    skip_known_caches,
                 };
 
                 let upload_options = UploadOptions {
@@ -1025,6 +1357,11 @@ async fn create_backup(
                     upload_options,
                 )
                 .await?;
+
+                let pxar_stats = pxar_stats.lock().unwrap().clone();
+                log_pxar_stats(&target, &pxar_stats);
+                manifest.unprotected["pxar-stats"][&target] = serde_json::to_value(pxar_stats)?;
+
                 manifest.add_file(target, stats.size, stats.csum, crypto.mode)?;
                 catalog.lock().unwrap().end_directory()?;
             }
@@ -1036,6 +1373,7 @@ async fn create_backup(
                     fixed_size: Some(size),
                     compress: true,
                     encrypt: crypto.mode == CryptMode::Encrypt,
+                    ..UploadOptions::default()
                 };
 
                 let stats =
@@ -1043,6 +1381,21 @@ async fn create_backup(
                         .await?;
                 manifest.add_file(target, stats.size, stats.csum, crypto.mode)?;
             }
+            (BackupSpecificationType::RAW, false) => {
+                log_file("data stream", &filename, &target);
+
+                let upload_options = UploadOptions {
+                    previous_manifest: previous_manifest.clone(),
+                    compress: true,
+                    encrypt: crypto.mode == CryptMode::Encrypt,
+                    ..UploadOptions::default()
+                };
+
+                let stats =
+                    backup_raw_stream(&client, &filename, &target, chunk_size_opt, upload_options)
+                        .await?;
+                manifest.add_file(target, stats.size, stats.csum, crypto.mode)?;
+            }
         }
     }
 
@@ -1090,6 +1443,7 @@ async fn create_backup(
 
     let options = UploadOptions {
         compress: true,
+        compression_level: Some(METADATA_COMPRESSION_LEVEL),
         encrypt: false,
         ..UploadOptions::default()
     };
@@ -1106,16 +1460,92 @@ async fn create_backup(
     Ok(Value::Null)
 }
 
-async fn dump_image<W: Write>(
+/// Number of chunks written to an image between two updates of its restore state.
+const IMAGE_RESTORE_CHECKPOINT_CHUNKS: usize = 256;
+
+#[derive(Deserialize, Serialize)]
+/// Progress of an image restore, to resume it after an interruption.
+struct ImageRestoreState {
+    /// Identifies the restored archive
+    archive: String,
+    /// Number of chunks written to the target
+    chunks: usize,
+}
+
+impl ImageRestoreState {
+    fn load(path: &Path, id: &str) -> Result<Self, Error> {
+        let state: Self = serde_json::from_value(file_get_json(path, None)?)
+            .map_err(|err| format_err!("unable to parse restore state {path:?} - {err}"))?;
+        if state.archive != id {
+            bail!("restore state {path:?} belongs to a different archive");
+        }
+        Ok(state)
+    }
+
+    fn save(&self, path: &Path) -> Result<(), Error> {
+        replace_file(path, &serde_json::to_vec(self)?, CreateOptions::new(), true)
+    }
+}
+
+/// Check the first `count` chunks already written to `file` against the digests of the index.
+///
+/// Returns the number of chunks which are intact, the restore continues with the next one.
+fn verify_restored_chunks(
+    file: &mut std::fs::File,
+    index: &FixedIndexReader,
+    count: usize,
+    crypt_config: Option<&CryptConfig>,
+    crypt_mode: CryptMode,
+    digest_salt: Option<&[u8; 32]>,
+) -> Result<usize, Error> {
+    let mut data = Vec::new();
+    for pos in 0..count.min(index.index_count()) {
+        let info = index.chunk_info(pos).unwrap();
+        data.resize(info.size() as usize, 0);
+
+        file.seek(SeekFrom::Start(info.range.start))?;
+        if file.read_exact(&mut data).is_err() {
+            return Ok(pos);
+        }
+
+        let digest = match (crypt_mode, crypt_config) {
+            (CryptMode::Encrypt, Some(config)) => config.compute_digest(&data),
+            _ => compute_unencrypted_digest(&data, digest_salt),
+        };
+        if digest != info.digest {
+            log::info!("restored chunk {pos} differs from the backup");
+            return Ok(pos);
+        }
+    }
+    Ok(count.min(index.index_count()))
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn dump_image<W: Write + Seek>(
     client: Arc<BackupReader>,
     crypt_config: Option<Arc<CryptConfig>>,
     crypt_mode: CryptMode,
     index: FixedIndexReader,
     mut writer: W,
+    reconnect: ReconnectFn,
+    start: usize,
+    state: Option<(&Path, &str)>,
 ) -> Result<(), Error> {
     let most_used = index.find_most_used_chunks(8);
 
-    let chunk_reader = RemoteChunkReader::new(client.clone(), crypt_config, crypt_mode, most_used);
+    let chunk_reader = RemoteChunkReader::new(client.clone(), crypt_config, crypt_mode, most_used)
+        .with_reconnect(reconnect);
+
+    if start > 0 {
+        writer.seek(SeekFrom::Start(start as u64 * index.chunk_size as u64))?;
+    }
+    if let Some((path, archive)) = state {
+        let state = ImageRestoreState {
+            archive: archive.to_string(),
+            chunks: start,
+        };
+        state.save(path)?;
+    }
 
     // Note: we avoid using BufferedFixedReader, because that add an additional buffer/copy
     // and thus slows down reading. Instead, directly use RemoteChunkReader
@@ -1123,11 +1553,21 @@ async fn dump_image<W: Write>(
     let mut bytes = 0;
     let start_time = std::time::Instant::now();
 
-    for pos in 0..index.index_count() {
+    for pos in start..index.index_count() {
         let digest = index.index_digest(pos).unwrap();
         let raw_data = chunk_reader.read_chunk(digest).await?;
         writer.write_all(&raw_data)?;
         bytes += raw_data.len();
+        if let Some((path, archive)) = state {
+            if (pos + 1) % IMAGE_RESTORE_CHECKPOINT_CHUNKS == 0 {
+                writer.flush()?;
+                let state = ImageRestoreState {
+                    archive: archive.to_string(),
+                    chunks: pos + 1,
+                };
+                state.save(path)?;
+            }
+        }
         let next_per = ((pos + 1) * 100) / index.index_count();
         if per != next_per {
             log::debug!(
@@ -1152,10 +1592,38 @@ async fn dump_image<W: Write>(
     Ok(())
 }
 
+/// Build the ownership and permission changes for a restore from the parameters.
+fn metadata_mapping_param(param: &Value) -> Result<pbs_client::pxar::MetadataMapping, Error> {
+    let mut mapping = match param["id-map"].as_str() {
+        Some(path) => pbs_client::pxar::MetadataMapping::from_map_file(Path::new(path))?,
+        None => Default::default(),
+    };
+
+    if param["squash-owner"].as_bool().unwrap_or(false) {
+        mapping = mapping.squash(
+            nix::unistd::getuid().as_raw(),
+            nix::unistd::getgid().as_raw(),
+        );
+    }
+
+    if let Some(umask) = param["umask"].as_str() {
+        let umask = u32::from_str_radix(umask, 8)
+            .map_err(|err| format_err!("invalid umask '{umask}' - {err}"))?;
+        mapping = mapping.umask(umask);
+    }
+
+    if let Some(patterns) = param["skip-xattr"].as_array() {
+        let patterns = patterns.iter().filter_map(Value::as_str);
+        mapping = mapping.skip_xattrs(XattrFilter::from_patterns(patterns)?);
+    }
+
+    Ok(mapping)
+}
+
 fn parse_archive_type(name: &str) -> (String, ArchiveType) {
     if name.ends_with(".didx") || name.ends_with(".fidx") || name.ends_with(".blob") {
         (name.into(), archive_type(name).unwrap())
-    } else if name.ends_with(".pxar") {
+    } else if name.ends_with(".pxar") || name.ends_with(".raw") {
         (format!("{}.didx", name), ArchiveType::DynamicIndex)
     } else if name.ends_with(".img") {
         (format!("{}.fidx", name), ArchiveType::FixedIndex)
@@ -1171,6 +1639,10 @@ fn parse_archive_type(name: &str) -> (String, ArchiveType) {
                 schema: REPO_URL_SCHEMA,
                 optional: true,
             },
+            profile: {
+                schema: PROFILE_SCHEMA,
+                optional: true,
+            },
             ns: {
                 type: BackupNamespace,
                 optional: true,
@@ -1229,6 +1701,16 @@ We do not extract '.pxar' archives when writing to standard output.
                 optional: true,
                 default: false,
             },
+            "acl-policy": {
+                type: pbs_client::pxar::AclPolicy,
+                optional: true,
+            },
+            "restore-chattr": {
+                type: Boolean,
+                description: "restore file attributes like immutable, append-only or nodump (chattr)",
+                optional: true,
+                default: false,
+            },
             "ignore-ownership": {
                 type: Boolean,
                 description: "ignore owner settings (no chown)",
@@ -1267,7 +1749,40 @@ We do not extract '.pxar' archives when writing to standard output.
                 description: "ignore errors that occur during device node extraction",
                 optional: true,
                 default: false,
-            }
+            },
+            "resume": {
+                type: Boolean,
+                description: "record the progress in a state file next to the target, \
+                    and resume an interrupted restore recorded there",
+                optional: true,
+                default: false,
+            },
+            "id-map": {
+                type: String,
+                description: "file mapping the UIDs and GIDs of the archive to the ones of the target, \
+                    with 'uid <archive-id> <target-id>' and 'gid <archive-id> <target-id>' lines",
+                optional: true,
+            },
+            "squash-owner": {
+                type: Boolean,
+                description: "give all restored files to the current user and group",
+                optional: true,
+                default: false,
+            },
+            umask: {
+                type: String,
+                description: "octal permission bits to remove from all restored files (e.g. '027')",
+                optional: true,
+            },
+            "skip-xattr": {
+                type: Array,
+                description: "List of extended attributes not to restore.",
+                optional: true,
+                items: {
+                    type: String,
+                    description: "Attribute name or namespace like 'user.*'.",
+                },
+            },
         }
     }
 )]
@@ -1277,6 +1792,7 @@ async fn restore(
     allow_existing_dirs: bool,
     ignore_acls: bool,
     ignore_xattrs: bool,
+    restore_chattr: bool,
     ignore_ownership: bool,
     ignore_permissions: bool,
     overwrite: bool,
@@ -1284,23 +1800,15 @@ async fn restore(
     overwrite_symlinks: bool,
     overwrite_hardlinks: bool,
     ignore_extract_device_errors: bool,
+    resume: bool,
 ) -> Result<Value, Error> {
     let repo = extract_repository_from_value(&param)?;
 
     let archive_name = json::required_string_param(&param, "archive-name")?;
 
-    let rate = match param["rate"].as_str() {
-        Some(s) => Some(s.parse::<HumanByte>()?),
-        None => None,
-    };
-    let burst = match param["burst"].as_str() {
-        Some(s) => Some(s.parse::<HumanByte>()?),
-        None => None,
-    };
-
-    let rate_limit = RateLimitConfig::with_same_inout(rate, burst);
+    let rate_limit = rate_limit_param(&param)?;
 
-    let client = connect_rate_limited(&repo, rate_limit)?;
+    let (client, host_index) = connect_first_reachable(&repo, rate_limit.clone()).await?;
     record_repository(&repo);
 
     let ns = optional_ns_param(&param)?;
@@ -1311,6 +1819,10 @@ async fn restore(
     let target = json::required_string_param(&param, "target")?;
     let target = if target == "-" { None } else { Some(target) };
 
+    if resume && target.is_none() {
+        bail!("cannot resume a restore to stdout");
+    }
+
     let crypto = crypto_parameters(&param)?;
 
     let crypt_config = match crypto.enc_key {
@@ -1369,9 +1881,68 @@ async fn restore(
 
     let file_info = manifest.lookup_file_info(&archive_name)?;
 
+    if resume && archive_type == ArchiveType::Blob {
+        bail!("cannot resume the restore of a blob");
+    }
+    if resume && archive_name.ends_with(".raw.didx") {
+        bail!("cannot resume the restore of a data stream");
+    }
+
+    // progress of a resumable restore is kept next to the target, an existing state file means
+    // that an interrupted restore is continued
+    let state_path = target
+        .filter(|_| resume)
+        .map(|target| PathBuf::from(format!("{target}.restore-state")));
+    let resuming = state_path.as_ref().map_or(false, |path| path.exists());
+    let state_id = format!("{archive_name}:{}", hex::encode(file_info.csum));
+
+    let reconnect = reader_reconnect(
+        &repo,
+        rate_limit,
+        host_index,
+        crypt_config.clone(),
+        ns.clone(),
+        backup_dir.clone(),
+        vec![(archive_name.clone(), file_info.csum)],
+    );
+
     if archive_type == ArchiveType::Blob {
         let mut reader = client.download_blob(&manifest, &archive_name).await?;
 
+        if let Some(target) = target {
+            let mut writer = std::fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .create_new(true)
+                .open(target)
+                .map_err(|err| {
+                    format_err!("unable to create target file {:?} - {}", target, err)
+                })?;
+            std::io::copy(&mut reader, &mut writer)?;
+        } else {
+            let stdout = std::io::stdout();
+            let mut writer = stdout.lock();
+            std::io::copy(&mut reader, &mut writer)
+                .map_err(|err| format_err!("unable to pipe data - {}", err))?;
+        }
+    } else if archive_type == ArchiveType::DynamicIndex && archive_name.ends_with(".raw.didx") {
+        // data streams are restored as they are, not extracted
+        let index = client
+            .download_dynamic_index(&manifest, &archive_name)
+            .await?;
+
+        let most_used = index.find_most_used_chunks(8);
+
+        let chunk_reader = RemoteChunkReader::new(
+            client.clone(),
+            crypt_config,
+            file_info.chunk_crypt_mode(),
+            most_used,
+        )
+        .with_reconnect(reconnect);
+
+        let mut reader = BufferedDynamicReader::new(index, chunk_reader);
+
         if let Some(target) = target {
             let mut writer = std::fs::OpenOptions::new()
                 .write(true)
@@ -1400,7 +1971,8 @@ async fn restore(
             crypt_config,
             file_info.chunk_crypt_mode(),
             most_used,
-        );
+        )
+        .with_reconnect(reconnect);
 
         let mut reader = BufferedDynamicReader::new(index, chunk_reader);
 
@@ -1433,12 +2005,42 @@ async fn restore(
             overwrite_flags.insert(pbs_client::pxar::OverwriteFlags::all());
         }
 
+        let mut journal = match &state_path {
+            Some(path) if resuming => {
+                let journal = pbs_client::pxar::ExtractJournal::resume(path, &state_id)?;
+                log::info!(
+                    "resuming restore, skipping {} restored entries",
+                    journal.len()
+                );
+                Some(journal)
+            }
+            Some(path) => Some(pbs_client::pxar::ExtractJournal::create(path, &state_id)?),
+            None => None,
+        };
+
+        let (allow_existing_dirs, overwrite_flags) = if resuming {
+            // the interrupted restore left created directories and possibly a partial file
+            (true, pbs_client::pxar::OverwriteFlags::all())
+        } else {
+            (allow_existing_dirs, overwrite_flags)
+        };
+
+        let acl_policy: pbs_client::pxar::AclPolicy = match param.get("acl-policy") {
+            Some(policy) => serde_json::from_value(policy.clone())?,
+            None => Default::default(),
+        };
+        let acl_report = Arc::new(Mutex::new(pbs_client::pxar::AclReport::default()));
+
         let options = pbs_client::pxar::PxarExtractOptions {
             match_list: &[],
             extract_match_default: true,
             allow_existing_dirs,
             overwrite_flags,
             on_error,
+            journal: journal.as_mut(),
+            metadata_mapping: metadata_mapping_param(&param)?,
+            acl_policy,
+            acl_report: Some(Arc::clone(&acl_report)),
         };
 
         let mut feature_flags = pbs_client::pxar::Flags::DEFAULT;
@@ -1449,6 +2051,9 @@ async fn restore(
         if ignore_xattrs {
             feature_flags.remove(pbs_client::pxar::Flags::WITH_XATTRS);
         }
+        if !restore_chattr {
+            feature_flags.remove(pbs_client::pxar::Flags::WITH_CHATTR);
+        }
         if ignore_ownership {
             feature_flags.remove(pbs_client::pxar::Flags::WITH_OWNER);
         }
@@ -1467,6 +2072,23 @@ async fn restore(
                 options,
             )
             .map_err(|err| format_err!("error extracting archive - {:#}", err))?;
+
+            let acl_report = acl_report.lock().unwrap();
+            if !acl_report.entries.is_empty() {
+                log::warn!(
+                    "the ACLs of {} entries could not be restored, only their mode was set:",
+                    acl_report.entries.len()
+                );
+                for entry in acl_report.entries.iter() {
+                    log::warn!("  {:?}: {}", entry.path, entry.error);
+                }
+            }
+
+            drop(journal);
+            if let Some(path) = &state_path {
+                std::fs::remove_file(path)
+                    .map_err(|err| format_err!("unable to remove {path:?} - {err}"))?;
+            }
         } else {
             let mut writer = std::fs::OpenOptions::new()
                 .write(true)
@@ -1481,13 +2103,40 @@ async fn restore(
             .download_fixed_index(&manifest, &archive_name)
             .await?;
 
+        let mut start = 0;
         let mut writer = if let Some(target) = target {
-            std::fs::OpenOptions::new()
-                .write(true)
-                .create(true)
-                .create_new(true)
-                .open(target)
-                .map_err(|err| format_err!("unable to create target file {:?} - {}", target, err))?
+            if resuming {
+                let mut file = std::fs::OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .open(target)
+                    .map_err(|err| {
+                        format_err!("unable to open target file {:?} - {}", target, err)
+                    })?;
+                let state = ImageRestoreState::load(state_path.as_ref().unwrap(), &state_id)?;
+                start = verify_restored_chunks(
+                    &mut file,
+                    &index,
+                    state.chunks,
+                    crypt_config.as_deref(),
+                    file_info.chunk_crypt_mode(),
+                    client.digest_salt(),
+                )?;
+                log::info!(
+                    "resuming restore, {start} of {} chunks already restored",
+                    index.index_count()
+                );
+                file
+            } else {
+                std::fs::OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .create_new(true)
+                    .open(target)
+                    .map_err(|err| {
+                        format_err!("unable to create target file {:?} - {}", target, err)
+                    })?
+            }
         } else {
             std::fs::OpenOptions::new()
                 .write(true)
@@ -1495,14 +2144,24 @@ async fn restore(
                 .map_err(|err| format_err!("unable to open /dev/stdout - {}", err))?
         };
 
+        let state = state_path.as_deref().map(|path| (path, state_id.as_str()));
+
         dump_image(
             client.clone(),
             crypt_config.clone(),
             file_info.chunk_crypt_mode(),
             index,
             &mut writer,
+            reconnect,
+            start,
+            state,
         )
         .await?;
+
+        if let Some(path) = &state_path {
+            std::fs::remove_file(path)
+                .map_err(|err| format_err!("unable to remove {path:?} - {err}"))?;
+        }
     }
 
     Ok(Value::Null)
@@ -1538,6 +2197,10 @@ async fn restore(
                 schema: REPO_URL_SCHEMA,
                 optional: true,
             },
+            profile: {
+                schema: PROFILE_SCHEMA,
+                optional: true,
+            },
         },
     },
 )]
@@ -1630,6 +2293,10 @@ async fn prune(
                schema: REPO_URL_SCHEMA,
                optional: true,
            },
+           profile: {
+               schema: PROFILE_SCHEMA,
+               optional: true,
+           },
            "output-format": {
                schema: OUTPUT_FORMAT,
                optional: true,
@@ -1723,6 +2390,7 @@ impl ReadAt for BufferedDynamicReadAt {
 fn main() {
     pbs_tools::setup_libc_malloc_opts();
     init_cli_logger("PBS_LOG", "info");
+    pbs_tools::format::setup_locale();
 
     let backup_cmd_def = CliCommand::new(&API_METHOD_CREATE_BACKUP)
         .arg_param(&["backupspec"])
@@ -1791,6 +2459,8 @@ fn main() {
         .insert("map", map_cmd_def())
         .insert("unmap", unmap_cmd_def())
         .insert("catalog", catalog_mgmt_cli())
+        .insert("daemon", job::daemon_cmd_def())
+        .insert("job", job::job_mgmt_cli())
         .insert("task", task_mgmt_cli())
         .insert("version", version_cmd_def)
         .insert("benchmark", benchmark_cmd_def)
@@ -31,7 +31,7 @@ use crate::{
     complete_group_or_snapshot, complete_img_archive_name, complete_namespace,
     complete_pxar_archive_name, complete_repository, connect, dir_or_last_from_group,
     extract_repository_from_value, optional_ns_param, record_repository, BufferedDynamicReadAt,
-    REPO_URL_SCHEMA,
+    PROFILE_SCHEMA, REPO_URL_SCHEMA,
 };
 
 #[sortable]
@@ -56,6 +56,7 @@ const API_METHOD_MOUNT: ApiMethod = ApiMethod::new(
                 false,
                 &StringSchema::new("Target directory path.").schema()
             ),
+            ("profile", true, &PROFILE_SCHEMA),
             ("repository", true, &REPO_URL_SCHEMA),
             (
                 "keyfile",
@@ -91,6 +92,7 @@ WARNING: Only do this with *trusted* backups!",
                 false,
                 &StringSchema::new("Backup archive name.").schema()
             ),
+            ("profile", true, &PROFILE_SCHEMA),
             ("repository", true, &REPO_URL_SCHEMA),
             (
                 "keyfile",
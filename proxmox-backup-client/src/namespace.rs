@@ -2,7 +2,7 @@ use anyhow::{bail, Error};
 use serde_json::{json, Value};
 
 use pbs_api_types::BackupNamespace;
-use pbs_client::tools::REPO_URL_SCHEMA;
+use pbs_client::tools::{PROFILE_SCHEMA, REPO_URL_SCHEMA};
 
 use proxmox_router::cli::{
     format_and_print_result, get_output_format, CliCommand, CliCommandMap, OUTPUT_FORMAT,
@@ -21,6 +21,10 @@ use crate::{
                 schema: REPO_URL_SCHEMA,
                 optional: true,
             },
+            profile: {
+                schema: PROFILE_SCHEMA,
+                optional: true,
+            },
             ns: {
                 type: BackupNamespace,
                 optional: true,
@@ -88,6 +92,10 @@ async fn list_namespaces(param: Value, max_depth: Option<usize>) -> Result<(), E
                 schema: REPO_URL_SCHEMA,
                 optional: true,
             },
+            profile: {
+                schema: PROFILE_SCHEMA,
+                optional: true,
+            },
             ns: {
                 type: BackupNamespace,
                 optional: true,
@@ -128,6 +136,10 @@ async fn create_namespace(param: Value) -> Result<(), Error> {
                 schema: REPO_URL_SCHEMA,
                 optional: true,
             },
+            profile: {
+                schema: PROFILE_SCHEMA,
+                optional: true,
+            },
             ns: {
                 type: BackupNamespace,
                 optional: true,
@@ -1,8 +1,9 @@
 use std::sync::Arc;
 
-use anyhow::Error;
+use anyhow::{bail, format_err, Error};
 use serde_json::{json, Value};
 
+use proxmox_async::blocking::WrappedReaderStream;
 use proxmox_router::cli::*;
 use proxmox_schema::api;
 use proxmox_sys::fs::file_get_contents;
@@ -18,7 +19,7 @@ use crate::{
     api_datastore_list_snapshots, complete_backup_group, complete_backup_snapshot,
     complete_namespace, complete_repository, connect, crypto_parameters,
     extract_repository_from_value, optional_ns_param, record_repository, BackupDir, KEYFD_SCHEMA,
-    KEYFILE_SCHEMA, REPO_URL_SCHEMA,
+    KEYFILE_SCHEMA, PROFILE_SCHEMA, REPO_URL_SCHEMA,
 };
 
 fn snapshot_args(ns: &BackupNamespace, snapshot: &BackupDir) -> Result<Value, Error> {
@@ -36,6 +37,10 @@ fn snapshot_args(ns: &BackupNamespace, snapshot: &BackupDir) -> Result<Value, Er
                 schema: REPO_URL_SCHEMA,
                 optional: true,
             },
+            profile: {
+                schema: PROFILE_SCHEMA,
+                optional: true,
+            },
             ns: {
                 type: BackupNamespace,
                 optional: true,
@@ -112,6 +117,10 @@ async fn list_snapshots(param: Value) -> Result<Value, Error> {
                 schema: REPO_URL_SCHEMA,
                 optional: true,
             },
+            profile: {
+                schema: PROFILE_SCHEMA,
+                optional: true,
+            },
             ns: {
                 type: BackupNamespace,
                 optional: true,
@@ -165,6 +174,10 @@ async fn list_snapshot_files(param: Value) -> Result<Value, Error> {
                 schema: REPO_URL_SCHEMA,
                 optional: true,
             },
+            profile: {
+                schema: PROFILE_SCHEMA,
+                optional: true,
+            },
             ns: {
                 type: BackupNamespace,
                 optional: true,
@@ -204,6 +217,10 @@ async fn forget_snapshots(param: Value) -> Result<(), Error> {
                 schema: REPO_URL_SCHEMA,
                 optional: true,
             },
+            profile: {
+                schema: PROFILE_SCHEMA,
+                optional: true,
+            },
             ns: {
                 type: BackupNamespace,
                 optional: true,
@@ -285,6 +302,128 @@ async fn upload_log(param: Value) -> Result<Value, Error> {
                 schema: REPO_URL_SCHEMA,
                 optional: true,
             },
+            profile: {
+                schema: PROFILE_SCHEMA,
+                optional: true,
+            },
+            ns: {
+                type: BackupNamespace,
+                optional: true,
+            },
+            snapshot: {
+                type: String,
+                description: "Snapshot path.",
+            },
+            file: {
+                type: String,
+                description: "The file to write the snapshot export archive to.",
+            },
+        }
+    }
+)]
+/// Export a snapshot including all its chunks into a single archive file.
+async fn export_snapshot(param: Value) -> Result<(), Error> {
+    let repo = extract_repository_from_value(&param)?;
+
+    let backup_ns = optional_ns_param(&param)?;
+    let snapshot: BackupDir = required_string_param(&param, "snapshot")?.parse()?;
+    let target = required_string_param(&param, "file")?;
+
+    let client = connect(&repo)?;
+
+    let mut query = format!(
+        "backup-type={}&backup-id={}&backup-time={}",
+        snapshot.group.ty, snapshot.group.id, snapshot.time,
+    );
+    if !backup_ns.is_root() {
+        query.push_str(&format!("&ns={backup_ns}"));
+    }
+    let path = format!(
+        "api2/json/admin/datastore/{}/snapshot-export?{query}",
+        repo.store()
+    );
+
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(target)
+        .map_err(|err| format_err!("unable to create {target:?} - {err}"))?;
+
+    if let Err(err) = client.download(&path, &mut file).await {
+        let _ = std::fs::remove_file(target);
+        bail!("snapshot export failed - {err}");
+    }
+
+    record_repository(&repo);
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            repository: {
+                schema: REPO_URL_SCHEMA,
+                optional: true,
+            },
+            profile: {
+                schema: PROFILE_SCHEMA,
+                optional: true,
+            },
+            ns: {
+                type: BackupNamespace,
+                optional: true,
+            },
+            file: {
+                type: String,
+                description: "The snapshot export archive to import.",
+            },
+        }
+    }
+)]
+/// Import a snapshot from a snapshot export archive.
+async fn import_snapshot(param: Value) -> Result<(), Error> {
+    let repo = extract_repository_from_value(&param)?;
+
+    let backup_ns = optional_ns_param(&param)?;
+    let source = required_string_param(&param, "file")?;
+
+    let file = std::fs::File::open(source)
+        .map_err(|err| format_err!("unable to open {source:?} - {err}"))?;
+    let body = hyper::Body::wrap_stream(WrappedReaderStream::new(file));
+
+    let client = connect(&repo)?;
+
+    let path = format!("api2/json/admin/datastore/{}/snapshot-import", repo.store());
+    let args = if backup_ns.is_root() {
+        None
+    } else {
+        Some(json!({ "ns": backup_ns }))
+    };
+
+    let mut result = client
+        .upload_stream("application/octet-stream", body, &path, args)
+        .await?;
+
+    let snapshot: BackupDir = serde_json::from_value(result["data"].take())?;
+    log::info!("imported snapshot {snapshot}");
+
+    record_repository(&repo);
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            repository: {
+                schema: REPO_URL_SCHEMA,
+                optional: true,
+            },
+            profile: {
+                schema: PROFILE_SCHEMA,
+                optional: true,
+            },
             ns: {
                 type: BackupNamespace,
                 optional: true,
@@ -342,6 +481,10 @@ async fn show_notes(param: Value) -> Result<Value, Error> {
                 schema: REPO_URL_SCHEMA,
                 optional: true,
             },
+            profile: {
+                schema: PROFILE_SCHEMA,
+                optional: true,
+            },
             ns: {
                 type: BackupNamespace,
                 optional: true,
@@ -384,6 +527,10 @@ async fn update_notes(param: Value) -> Result<Value, Error> {
                 schema: REPO_URL_SCHEMA,
                 optional: true,
             },
+            profile: {
+                schema: PROFILE_SCHEMA,
+                optional: true,
+            },
             ns: {
                 type: BackupNamespace,
                 optional: true,
@@ -441,6 +588,10 @@ async fn show_protection(param: Value) -> Result<(), Error> {
                 schema: REPO_URL_SCHEMA,
                 optional: true,
             },
+            profile: {
+                schema: PROFILE_SCHEMA,
+                optional: true,
+            },
             ns: {
                 type: BackupNamespace,
                 optional: true,
@@ -531,6 +682,23 @@ pub fn snapshot_mgtm_cli() -> CliCommandMap {
                 .completion_cb("repository", complete_repository)
                 .completion_cb("snapshot", complete_backup_snapshot),
         )
+        .insert(
+            "export",
+            CliCommand::new(&API_METHOD_EXPORT_SNAPSHOT)
+                .arg_param(&["snapshot", "file"])
+                .completion_cb("ns", complete_namespace)
+                .completion_cb("repository", complete_repository)
+                .completion_cb("snapshot", complete_backup_snapshot)
+                .completion_cb("file", complete_file_name),
+        )
+        .insert(
+            "import",
+            CliCommand::new(&API_METHOD_IMPORT_SNAPSHOT)
+                .arg_param(&["file"])
+                .completion_cb("ns", complete_namespace)
+                .completion_cb("repository", complete_repository)
+                .completion_cb("file", complete_file_name),
+        )
         .insert(
             "forget",
             CliCommand::new(&API_METHOD_FORGET_SNAPSHOTS)
@@ -10,7 +10,9 @@ use pbs_tools::json::required_string_param;
 
 use pbs_api_types::UPID;
 
-use crate::{complete_repository, connect, extract_repository_from_value, REPO_URL_SCHEMA};
+use crate::{
+    complete_repository, connect, extract_repository_from_value, PROFILE_SCHEMA, REPO_URL_SCHEMA,
+};
 
 #[api(
     input: {
@@ -19,6 +21,10 @@ use crate::{complete_repository, connect, extract_repository_from_value, REPO_UR
                 schema: REPO_URL_SCHEMA,
                 optional: true,
             },
+            profile: {
+                schema: PROFILE_SCHEMA,
+                optional: true,
+            },
             limit: {
                 description: "The maximal number of tasks to list.",
                 type: Integer,
@@ -91,6 +97,10 @@ async fn task_list(param: Value) -> Result<Value, Error> {
                 schema: REPO_URL_SCHEMA,
                 optional: true,
             },
+            profile: {
+                schema: PROFILE_SCHEMA,
+                optional: true,
+            },
             upid: {
                 type: UPID,
             },
@@ -116,6 +126,10 @@ async fn task_log(param: Value) -> Result<Value, Error> {
                 schema: REPO_URL_SCHEMA,
                 optional: true,
             },
+            profile: {
+                schema: PROFILE_SCHEMA,
+                optional: true,
+            },
             upid: {
                 type: UPID,
             },
@@ -184,13 +184,49 @@ pub async fn status(driver: Option<BlockDriverType>, param: Value) -> Result<(),
             "name": {
                 type: String,
                 description: "The name of the VM to stop.",
+                optional: true,
+            },
+            "all": {
+                type: bool,
+                description: "Stop all VMs of the current user, including orphaned ones.",
+                optional: true,
+                default: false,
             },
         },
    },
 )]
-/// Immediately stop/unmap a given image. Not typically necessary, as VMs will stop themselves
-/// after a timer anyway.
-pub async fn stop(name: String) -> Result<(), Error> {
+/// Immediately stop/unmap a given image, or all of them. Not typically necessary, as VMs will
+/// stop themselves after a timer anyway.
+pub async fn stop(name: Option<String>, all: bool) -> Result<(), Error> {
+    if all {
+        if name.is_some() {
+            bail!("'name' and 'all' cannot be used together");
+        }
+        let mut errors = 0;
+        for drv in ALL_DRIVERS.iter().map(BlockDriverType::resolve) {
+            // status() also stops orphaned VMs not known to the driver anymore
+            if let Err(err) = drv.status().await {
+                log::error!("{err}");
+                errors += 1;
+            }
+            for name in drv.list() {
+                if let Err(err) = drv.stop(name.clone()).await {
+                    log::error!("stopping '{name}' failed - {err}");
+                    errors += 1;
+                }
+            }
+        }
+        if errors > 0 {
+            bail!("stopping VMs failed");
+        }
+        return Ok(());
+    }
+
+    let name = match name {
+        Some(name) => name,
+        None => bail!("either 'name' or 'all' is required"),
+    };
+
     for drv in ALL_DRIVERS.iter().map(BlockDriverType::resolve) {
         if drv.list().contains(&name) {
             return drv.stop(name).await;
@@ -91,7 +91,30 @@ fn make_name(repo: &BackupRepository, ns: &BackupNamespace, snap: &BackupDir) ->
     proxmox_sys::systemd::escape_unit(full, false)
 }
 
-/// remove non-responsive VMs from given map, returns 'true' if map was modified
+/// Kill restore VMs of the current user which are not in the map, e.g. left behind by a crashed
+/// invocation before they could be recorded. Must only be called with the map locked, so no VM
+/// can be in the process of starting.
+fn kill_orphaned_vms(map: &HashMap<String, VMState>) {
+    let pids = match qemu_helper::list_vm_pids() {
+        Ok(pids) => pids,
+        Err(err) => {
+            log::warn!("unable to check for orphaned VMs - {err}");
+            return;
+        }
+    };
+    for pid in pids {
+        if map.values().any(|state| state.pid == pid) {
+            continue;
+        }
+        log::warn!("VM with pid {pid} is not in map, stopping orphaned VM");
+        if let Err(err) = qemu_helper::try_kill_vm(pid) {
+            log::error!("{err}");
+        }
+    }
+}
+
+/// remove non-responsive VMs from given map and stop orphaned VMs, returns 'true' if map was
+/// modified
 async fn cleanup_map(map: &mut HashMap<String, VMState>) -> bool {
     let mut to_remove = Vec::new();
     for (name, state) in map.iter() {
@@ -116,6 +139,8 @@ async fn cleanup_map(map: &mut HashMap<String, VMState>) -> bool {
         map.remove(tr);
     }
 
+    kill_orphaned_vms(map);
+
     !to_remove.is_empty()
 }
 
@@ -28,6 +28,8 @@ const PBS_VM_NAME: &str = "pbs-restore-vm";
 const MAX_CID_TRIES: u64 = 32;
 pub const MAX_MEMORY_DIMM_SIZE: usize = 512;
 const QMP_SOCKET_PREFIX: &str = "/run/proxmox-backup/file-restore-qmp-";
+/// Default maximum lifetime of a restore VM in seconds, even if it is kept busy
+const DEFAULT_MAX_LIFETIME: i64 = 24 * 3600;
 
 fn create_restore_log_dir() -> Result<String, Error> {
     let logpath = format!("{}/file-restore", pbs_buildcfg::PROXMOX_BACKUP_LOG_DIR);
@@ -64,6 +66,36 @@ fn validate_img_existance(debug: bool) -> Result<(), Error> {
     Ok(())
 }
 
+/// Returns the PIDs of all restore VMs running as the current user
+pub fn list_vm_pids() -> Result<Vec<i32>, Error> {
+    use std::os::unix::fs::MetadataExt;
+
+    let uid = nix::unistd::Uid::current().as_raw();
+    let mut pids = Vec::new();
+    for entry in std::fs::read_dir("/proc")? {
+        let entry = entry?;
+        let pid: i32 = match entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse().ok())
+        {
+            Some(pid) => pid,
+            None => continue,
+        };
+        // ignore errors, the process might have exited in between
+        match entry.metadata() {
+            Ok(metadata) if metadata.uid() == uid => (),
+            _ => continue,
+        }
+        if let Ok(cmdline) = file_read_string(format!("/proc/{pid}/cmdline")) {
+            if cmdline.split('\0').any(|a| a == PBS_VM_NAME) {
+                pids.push(pid);
+            }
+        }
+    }
+    Ok(pids)
+}
+
 pub fn try_kill_vm(pid: i32) -> Result<(), Error> {
     let pid = Pid::from_raw(pid);
     if kill(pid, None).is_ok() {
@@ -200,6 +232,18 @@ pub fn debug_mode() -> bool {
         .unwrap_or(false)
 }
 
+/// Maximum lifetime of a restore VM in seconds, 0 for unlimited
+fn max_lifetime() -> i64 {
+    match std::env::var("PBS_FILE_RESTORE_MAX_LIFETIME").map(|v| v.parse::<i64>()) {
+        Ok(Ok(lifetime)) if lifetime >= 0 => lifetime,
+        Ok(_) => {
+            log::warn!("invalid PBS_FILE_RESTORE_MAX_LIFETIME, using default");
+            DEFAULT_MAX_LIFETIME
+        }
+        Err(_) => DEFAULT_MAX_LIFETIME,
+    }
+}
+
 pub async fn start_vm(
     // u16 so we can do wrapping_add without going too high
     mut cid: u16,
@@ -262,8 +306,9 @@ pub async fn start_vm(
         // NOTE: ZFS requires that the ARC can at least grow to the max transaction size of 64MB
         // also: setting any of min/max to zero will rather do the opposite of what one wants here
         &format!(
-            "{} panic=1 zfs.zfs_arc_min=33554432 zfs.zfs_arc_max=67108864 memhp_default_state=online_kernel",
-            if debug { "debug" } else { "quiet" }
+            "{} panic=1 zfs.zfs_arc_min=33554432 zfs.zfs_arc_max=67108864 memhp_default_state=online_kernel pbs_max_lifetime={}",
+            if debug { "debug" } else { "quiet" },
+            max_lifetime(),
         ),
         "-daemonize",
         "-pidfile",
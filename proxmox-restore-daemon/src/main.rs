@@ -92,8 +92,16 @@ fn setup_system_env() -> Result<(), Error> {
     Ok(())
 }
 
+/// Read the maximum lifetime passed by proxmox-file-restore on the kernel command line
+fn read_max_lifetime() -> Option<i64> {
+    let cmdline = std::fs::read_to_string("/proc/cmdline").ok()?;
+    cmdline
+        .split_ascii_whitespace()
+        .find_map(|arg| arg.strip_prefix("pbs_max_lifetime=")?.parse().ok())
+}
+
 async fn run() -> Result<(), Error> {
-    watchdog_init();
+    watchdog_init(read_max_lifetime());
 
     let init_future = async move {
         match tokio::time::timeout(
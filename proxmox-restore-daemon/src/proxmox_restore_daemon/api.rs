@@ -29,7 +29,9 @@ use pbs_tools::json::required_string_param;
 
 use pxar::encoder::aio::TokioWriter;
 
-use super::{disk::ResolveResult, watchdog_inhibit, watchdog_ping, watchdog_remaining};
+use super::{
+    disk::ResolveResult, lifetime_remaining, watchdog_inhibit, watchdog_ping, watchdog_remaining,
+};
 
 // NOTE: All API endpoints must have Permission::Superuser, as the configs for authentication do
 // not exist within the restore VM. Safety is guaranteed by checking a ticket via a custom ApiAuth.
@@ -83,6 +85,7 @@ fn status(
     Ok(RestoreDaemonStatus {
         uptime: read_uptime()? as i64,
         timeout: watchdog_remaining(),
+        lifetime: lifetime_remaining(),
     })
 }
 
@@ -357,7 +360,12 @@ fn extract(
                         device_set: None,
                         patterns,
                         skip_lost_and_found: false,
+                        skip_nodump: false,
                         skip_e2big_xattr: false,
+                        stats: None,
+                        xattr_exclude: Default::default(),
+                        skip_cache_dirs: false,
+                        skip_known_caches: false,
                     };
 
                     let pxar_writer = TokioWriter::new(writer);
@@ -1,4 +1,5 @@
-//! Tokio-based watchdog that shuts down the VM if not pinged for TIMEOUT
+//! Tokio-based watchdog that shuts down the VM if not pinged for TIMEOUT, or once it reached its
+//! maximum lifetime
 use std::sync::atomic::{AtomicI64, Ordering};
 
 use proxmox_time::epoch_i64;
@@ -6,6 +7,8 @@ use proxmox_time::epoch_i64;
 const TIMEOUT: i64 = 600; // seconds
 static TRIGGERED: AtomicI64 = AtomicI64::new(0);
 static INHIBITORS: AtomicI64 = AtomicI64::new(0);
+// time at which the VM shuts down regardless of pings and inhibitors, 0 if unlimited
+static DEADLINE: AtomicI64 = AtomicI64::new(0);
 
 pub struct WatchdogInhibitor {}
 
@@ -28,8 +31,12 @@ async fn watchdog_loop() {
     }
 }
 
-/// Initialize watchdog
-pub fn watchdog_init() {
+/// Initialize watchdog, with an optional maximum lifetime of the VM in seconds
+pub fn watchdog_init(max_lifetime: Option<i64>) {
+    if let Some(max_lifetime) = max_lifetime.filter(|lifetime| *lifetime > 0) {
+        log::info!("maximum lifetime: {max_lifetime} seconds");
+        DEADLINE.store(epoch_i64() + max_lifetime, Ordering::Release);
+    }
     watchdog_ping();
     tokio::spawn(watchdog_loop());
 }
@@ -41,10 +48,22 @@ pub fn watchdog_ping() {
 
 /// Returns the remaining time before watchdog expiry in seconds
 pub fn watchdog_remaining() -> i64 {
-    if INHIBITORS.load(Ordering::Acquire) > 0 {
+    let remaining = if INHIBITORS.load(Ordering::Acquire) > 0 {
         TIMEOUT
     } else {
         TIMEOUT - (epoch_i64() - TRIGGERED.load(Ordering::Acquire))
+    };
+    match lifetime_remaining() {
+        Some(lifetime) => remaining.min(lifetime),
+        None => remaining,
+    }
+}
+
+/// Returns the remaining time before the maximum lifetime is reached in seconds, if limited
+pub fn lifetime_remaining() -> Option<i64> {
+    match DEADLINE.load(Ordering::Acquire) {
+        0 => None,
+        deadline => Some(deadline - epoch_i64()),
     }
 }
 
@@ -4,7 +4,7 @@ use std::fs::OpenOptions;
 use std::os::unix::fs::OpenOptionsExt;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use anyhow::{bail, format_err, Error};
 use futures::future::FutureExt;
@@ -13,7 +13,8 @@ use tokio::signal::unix::{signal, SignalKind};
 
 use pathpatterns::{MatchEntry, MatchType, PatternFlag};
 use pbs_client::pxar::{
-    format_single_line_entry, Flags, OverwriteFlags, PxarExtractOptions, ENCODER_MAX_ENTRIES,
+    format_single_line_entry, AclPolicy, AclReport, Flags, MetadataMapping, OverwriteFlags,
+    PxarExtractOptions, XattrFilter, ENCODER_MAX_ENTRIES,
 };
 
 use proxmox_router::cli::*;
@@ -70,6 +71,24 @@ fn extract_archive_from_reader<R: std::io::Read>(
                 optional: true,
                 default: false,
             },
+            "acl-policy": {
+                type: AclPolicy,
+                optional: true,
+            },
+            "restore-chattr": {
+                description: "Restore file attributes like immutable, append-only or nodump.",
+                optional: true,
+                default: false,
+            },
+            "skip-xattr": {
+                description: "List of extended attributes not to restore.",
+                optional: true,
+                type: Array,
+                items: {
+                    description: "Attribute name or namespace like 'user.*'.",
+                    type: String,
+                },
+            },
             "allow-existing-dirs": {
                 description: "Allows directories to already exist on restore.",
                 optional: true,
@@ -131,6 +150,9 @@ fn extract_archive(
     no_xattrs: bool,
     no_fcaps: bool,
     no_acls: bool,
+    acl_policy: Option<AclPolicy>,
+    restore_chattr: bool,
+    skip_xattr: Option<Vec<String>>,
     allow_existing_dirs: bool,
     overwrite: bool,
     overwrite_files: bool,
@@ -152,6 +174,9 @@ fn extract_archive(
     if no_acls {
         feature_flags.remove(Flags::WITH_ACL);
     }
+    if !restore_chattr {
+        feature_flags.remove(Flags::WITH_CHATTR);
+    }
     if no_device_nodes {
         feature_flags.remove(Flags::WITH_DEVICE_NODES);
     }
@@ -193,6 +218,8 @@ fn extract_archive(
 
     let extract_match_default = match_list.is_empty();
 
+    let acl_report = Arc::new(Mutex::new(AclReport::default()));
+
     let was_ok = Arc::new(AtomicBool::new(true));
     let on_error = if strict {
         // by default errors are propagated up
@@ -214,6 +241,12 @@ fn extract_archive(
         overwrite_flags,
         extract_match_default,
         on_error,
+        journal: None,
+        metadata_mapping: MetadataMapping::default().skip_xattrs(XattrFilter::from_patterns(
+            skip_xattr.iter().flatten().map(String::as_str),
+        )?),
+        acl_policy: acl_policy.unwrap_or_default(),
+        acl_report: Some(Arc::clone(&acl_report)),
     };
 
     if archive == "-" {
@@ -227,6 +260,17 @@ fn extract_archive(
         extract_archive_from_reader(&mut reader, target, feature_flags, options)?;
     }
 
+    let acl_report = acl_report.lock().unwrap();
+    if !acl_report.entries.is_empty() {
+        log::warn!(
+            "the ACLs of {} entries could not be restored, only their mode was set:",
+            acl_report.entries.len()
+        );
+        for entry in acl_report.entries.iter() {
+            log::warn!("  {:?}: {}", entry.path, entry.error);
+        }
+    }
+
     if !was_ok.load(Ordering::Acquire) {
         bail!("there were errors");
     }
@@ -278,6 +322,30 @@ fn extract_archive(
                 optional: true,
                 default: false,
             },
+            "skip-nodump": {
+                description: "Skip entries with the nodump file attribute.",
+                optional: true,
+                default: false,
+            },
+            "skip-cache-dirs": {
+                description: "Skip the contents of directories tagged with a CACHEDIR.TAG file.",
+                optional: true,
+                default: false,
+            },
+            "skip-known-caches": {
+                description: "Skip well-known cache locations.",
+                optional: true,
+                default: false,
+            },
+            "exclude-xattr": {
+                description: "List of extended attributes not to archive.",
+                optional: true,
+                type: Array,
+                items: {
+                    description: "Attribute name or namespace like 'user.*'.",
+                    type: String,
+                },
+            },
             exclude: {
                 description: "List of paths or pattern matching files to exclude.",
                 optional: true,
@@ -309,6 +377,10 @@ async fn create_archive(
     no_device_nodes: bool,
     no_fifos: bool,
     no_sockets: bool,
+    skip_nodump: bool,
+    skip_cache_dirs: bool,
+    skip_known_caches: bool,
+    exclude_xattr: Option<Vec<String>>,
     exclude: Option<Vec<String>>,
     entries_max: isize,
 ) -> Result<(), Error> {
@@ -335,7 +407,14 @@ async fn create_archive(
         device_set,
         patterns,
         skip_lost_and_found: false,
+        skip_nodump,
         skip_e2big_xattr: false,
+        stats: None,
+        xattr_exclude: XattrFilter::from_patterns(
+            exclude_xattr.iter().flatten().map(String::as_str),
+        )?,
+        skip_cache_dirs,
+        skip_known_caches,
     };
 
     let source = PathBuf::from(source);
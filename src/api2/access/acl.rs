@@ -1,5 +1,7 @@
 //! Manage Access Control Lists
 
+use std::str::FromStr;
+
 use anyhow::{bail, Error};
 use hex::FromHex;
 
@@ -7,8 +9,8 @@ use proxmox_router::{Permission, Router, RpcEnvironment};
 use proxmox_schema::api;
 
 use pbs_api_types::{
-    AclListItem, Authid, Role, ACL_PATH_SCHEMA, ACL_PROPAGATE_SCHEMA, PRIV_PERMISSIONS_MODIFY,
-    PRIV_SYS_AUDIT, PROXMOX_CONFIG_DIGEST_SCHEMA, PROXMOX_GROUP_ID_SCHEMA,
+    AclListItem, Authid, Role, ACL_PATH_SCHEMA, ACL_PROPAGATE_SCHEMA, PRIV_PERMISSIONS_DELEGATE,
+    PRIV_PERMISSIONS_MODIFY, PRIV_SYS_AUDIT, PROXMOX_CONFIG_DIGEST_SCHEMA, PROXMOX_GROUP_ID_SCHEMA,
 };
 
 use pbs_config::acl::AclTreeNode;
@@ -102,7 +104,7 @@ fn extract_acl_node_data(
     },
     access: {
         permission: &Permission::Anybody,
-        description: "Returns all ACLs if user has Sys.Audit on '/access/acl', or just the ACLs containing the user's API tokens.",
+        description: "Returns all ACLs if user has Sys.Audit on '/access/acl', all ACLs below 'path' if user has Permissions.Delegate on it, or just the ACLs containing the user's API tokens.",
     },
 )]
 /// Read Access Control List (ACLs).
@@ -116,7 +118,15 @@ pub fn read_acl(
     let user_info = CachedUserInfo::new()?;
 
     let top_level_privs = user_info.lookup_privs(&auth_id, &["access", "acl"]);
-    let auth_id_filter = if (top_level_privs & PRIV_SYS_AUDIT) == 0 {
+    let delegated = match &path {
+        Some(path) => {
+            let components = pbs_config::acl::split_acl_path(path);
+            is_delegation_path(&components)
+                && user_info.lookup_privs(&auth_id, &components) & PRIV_PERMISSIONS_DELEGATE != 0
+        }
+        None => false,
+    };
+    let auth_id_filter = if (top_level_privs & PRIV_SYS_AUDIT) == 0 && !delegated {
         Some(auth_id)
     } else {
         None
@@ -138,6 +148,54 @@ pub fn read_acl(
     Ok(list)
 }
 
+/// Permissions.Delegate is only honored on datastore and namespace paths.
+fn is_delegation_path(components: &[&str]) -> bool {
+    components.len() >= 2 && components[0] == "datastore"
+}
+
+/// Check if `auth_id` may set or remove `role` for `target` on `path` via Permissions.Delegate.
+///
+/// The delegated role must not contain any privilege `auth_id` does not hold on `path` itself and
+/// must not be `NoAccess`. The delegating user cannot change their own ACL items, nor those of
+/// users or API tokens which already hold privileges on `path` the delegating user lacks.
+fn check_delegated_acl_update(
+    user_info: &CachedUserInfo,
+    auth_id: &Authid,
+    target: &Authid,
+    path: &str,
+    role: &str,
+) -> Result<(), Error> {
+    let components = pbs_config::acl::split_acl_path(path);
+    let privs = user_info.lookup_privs(auth_id, &components);
+
+    if privs & PRIV_PERMISSIONS_DELEGATE == 0 || !is_delegation_path(&components) {
+        if !target.is_token() {
+            bail!("Unprivileged users can only set ACL items for API tokens.");
+        }
+        bail!("Unprivileged users can only set ACL items for their own API tokens.");
+    }
+
+    if target.user() == auth_id.user() && !target.is_token() {
+        bail!("Users can't change their own ACL items via delegation.");
+    }
+
+    let parsed_role = Role::from_str(role)?;
+    if matches!(parsed_role, Role::NoAccess) {
+        bail!("Role 'NoAccess' can't be set via delegation.");
+    }
+
+    if user_info.lookup_privs(target, &components) & !privs != 0 {
+        bail!("'{target}' holds privileges on '{path}' not held by '{auth_id}', refusing to update.");
+    }
+
+    let role_privs = parsed_role as u64;
+    if role_privs & !privs != 0 {
+        bail!("Role '{role}' contains privileges not held on '{path}', refusing to delegate.");
+    }
+
+    Ok(())
+}
+
 #[api(
     protected: true,
     input: {
@@ -173,7 +231,7 @@ pub fn read_acl(
     },
     access: {
         permission: &Permission::Anybody,
-        description: "Requires Permissions.Modify on '/access/acl', limited to updating ACLs of the user's API tokens otherwise."
+        description: "Requires Permissions.Modify on '/access/acl'. Otherwise limited to updating ACLs of the user's API tokens, or of other users and API tokens on datastore paths the user has Permissions.Delegate on, with roles not exceeding the user's own privileges there."
     },
 )]
 /// Update Access Control List (ACLs).
@@ -202,10 +260,14 @@ pub fn update_acl(
             Some(auth_id) => {
                 if current_auth_id.is_token() {
                     bail!("Unprivileged API tokens can't set ACL items.");
-                } else if !auth_id.is_token() {
-                    bail!("Unprivileged users can only set ACL items for API tokens.");
-                } else if auth_id.user() != current_auth_id.user() {
-                    bail!("Unprivileged users can only set ACL items for their own API tokens.");
+                } else if !auth_id.is_token() || auth_id.user() != current_auth_id.user() {
+                    check_delegated_acl_update(
+                        &user_info,
+                        &current_auth_id,
+                        auth_id,
+                        &path,
+                        &role,
+                    )?;
                 }
             }
             None => {
@@ -275,3 +337,81 @@ pub fn update_acl(
 pub const ROUTER: Router = Router::new()
     .get(&API_METHOD_READ_ACL)
     .put(&API_METHOD_UPDATE_ACL);
+
+#[test]
+fn delegated_acl_update_test() -> Result<(), Error> {
+    let (user_cfg, _) = pbs_config::user::test_cfg_from_str(
+        r###"
+user: tenant@pbs
+
+user: other@pbs
+
+user: admin@pbs
+
+user: fresh@pbs
+
+token: tenant@pbs!scoped
+	subtree /datastore/store1
+
+"###,
+    )
+    .expect("test user.cfg is not parsable");
+    let acl_tree = pbs_config::acl::AclTree::from_raw(
+        r###"
+acl:1:/datastore/store1:tenant@pbs:DatastoreTenantAdmin
+acl:1:/datastore/store2:tenant@pbs:DatastoreTenantAdmin
+acl:1:/datastore/store1:other@pbs:DatastoreBackup
+acl:1:/datastore:admin@pbs:Admin
+acl:1:/:tenant@pbs!scoped:DatastoreAudit
+"###,
+    )
+    .expect("test acl.cfg is not parsable");
+
+    let user_info = CachedUserInfo::test_new(user_cfg, acl_tree);
+
+    let tenant: Authid = "tenant@pbs".parse()?;
+    let other: Authid = "other@pbs".parse()?;
+    let admin: Authid = "admin@pbs".parse()?;
+    let fresh: Authid = "fresh@pbs".parse()?;
+    let path = "/datastore/store1";
+
+    // delegating a role within the own privileges to a less privileged user is fine
+    check_delegated_acl_update(&user_info, &tenant, &other, path, "DatastoreReader")?;
+    check_delegated_acl_update(&user_info, &tenant, &fresh, path, "DatastoreAdmin")?;
+
+    // NoAccess would allow masking the privileges of anybody on the datastore
+    assert!(check_delegated_acl_update(&user_info, &tenant, &fresh, path, "NoAccess").is_err());
+
+    // users holding more privileges than the delegator must not be touched
+    assert!(
+        check_delegated_acl_update(&user_info, &tenant, &admin, path, "DatastoreAudit").is_err()
+    );
+
+    // roles exceeding the own privileges can't be delegated
+    assert!(check_delegated_acl_update(&user_info, &tenant, &fresh, path, "Admin").is_err());
+
+    // no delegation outside of datastore paths, nor for the own user
+    assert!(check_delegated_acl_update(
+        &user_info,
+        &tenant,
+        &fresh,
+        "/datastore/store3",
+        "DatastoreAudit"
+    )
+    .is_err());
+    assert!(
+        check_delegated_acl_update(&user_info, &tenant, &tenant, path, "DatastoreAudit").is_err()
+    );
+
+    // API tokens with a subtree only hold privileges within it
+    let scoped: Authid = "tenant@pbs!scoped".parse()?;
+    assert_ne!(user_info.lookup_privs(&scoped, &["datastore", "store1"]), 0);
+    assert_ne!(
+        user_info.lookup_privs(&scoped, &["datastore", "store1", "ns"]),
+        0
+    );
+    assert_eq!(user_info.lookup_privs(&scoped, &["datastore", "store2"]), 0);
+    assert_eq!(user_info.lookup_privs(&scoped, &["datastore"]), 0);
+
+    Ok(())
+}
@@ -0,0 +1,295 @@
+//! Logins, running the login hooks once a user is fully authenticated
+//!
+//! The authenticator of a realm only checks the password, so it cannot know whether a second
+//! factor is still missing. The login methods are therefore wrapped as `AsyncHttp` handlers,
+//! which also gives access to the user agent of the client.
+
+use std::net::IpAddr;
+
+use anyhow::{bail, Error};
+use futures::*;
+use hyper::header;
+use hyper::http::request::Parts;
+use hyper::Body;
+use serde_json::Value;
+
+use proxmox_rest_server::formatter;
+use proxmox_router::{ApiHandler, ApiMethod, ApiResponseFuture, RpcEnvironment};
+
+use pbs_api_types::{Authid, Userid};
+
+use crate::auth::record_login_failure;
+use crate::server::auth_last_used::record_use;
+use crate::server::login_notify::check_login;
+use crate::server::namespace_provision::provision_user_namespace;
+use crate::tools::login_attempts::LoginAttempts;
+
+/// Limit for the size of login requests
+const MAX_REQUEST_SIZE: usize = 64 * 1024;
+
+/// Create or verify an authentication ticket, see [`proxmox_auth_api::api::create_ticket`].
+pub const API_METHOD_CREATE_TICKET: ApiMethod = ApiMethod {
+    handler: &ApiHandler::AsyncHttp(&create_ticket),
+    ..proxmox_auth_api::api::API_METHOD_CREATE_TICKET
+};
+
+/// Verify an OpenID authorization code and create a ticket, see
+/// [`super::openid::openid_login`].
+pub const API_METHOD_OPENID_LOGIN: ApiMethod = ApiMethod {
+    handler: &ApiHandler::AsyncHttp(&openid_login),
+    ..super::openid::API_METHOD_OPENID_LOGIN
+};
+
+// the REST server only passes the query parameters to `AsyncHttp` handlers
+async fn request_parameters(
+    parts: &Parts,
+    mut body: Body,
+    method: &ApiMethod,
+) -> Result<Value, Error> {
+    let mut data = Vec::new();
+    while let Some(chunk) = body.try_next().await? {
+        if data.len() + chunk.len() > MAX_REQUEST_SIZE {
+            bail!("request is too large");
+        }
+        data.extend_from_slice(&chunk);
+    }
+
+    let is_json = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map_or(false, |value| value.starts_with("application/json"));
+
+    if is_json && !data.is_empty() {
+        let params: Value = serde_json::from_slice(&data)?;
+        method.parameters.verify_json(&params)?;
+        return Ok(params);
+    }
+
+    let mut param_list: Vec<(String, String)> = Vec::new();
+    if let Some(query) = parts.uri.query() {
+        param_list.extend(url::form_urlencoded::parse(query.as_bytes()).into_owned());
+    }
+    param_list.extend(url::form_urlencoded::parse(&data).into_owned());
+
+    Ok(method
+        .parameters
+        .parse_parameter_strings(&param_list, true)?)
+}
+
+fn user_agent(parts: &Parts) -> Option<String> {
+    parts
+        .headers
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.chars().take(256).collect())
+}
+
+// call the wrapped login method with the parameters of the request
+async fn call_login_method(
+    param: Value,
+    method: &'static ApiMethod,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Value, Error> {
+    match method.handler {
+        ApiHandler::Sync(handler) => handler(param, method, rpcenv),
+        ApiHandler::Async(handler) => handler(param, method, rpcenv).await,
+        _ => bail!("internal error - unsupported login handler"),
+    }
+}
+
+/// Returns whether a successful ticket request was a completed login, and not a ticket renewal,
+/// a privilege check or the first step of a login with a second factor.
+fn is_completed_login(param: &Value, result: &Value) -> bool {
+    if param["path"].is_string() {
+        return false;
+    }
+    if param["tfa-challenge"].is_null()
+        && param["password"]
+            .as_str()
+            .map_or(false, |password| password.starts_with("PBS:"))
+    {
+        return false;
+    }
+    let need_tfa = &result["NeedTFA"];
+    if need_tfa.as_bool() == Some(true) || need_tfa.as_i64().map_or(false, |need| need != 0) {
+        return false;
+    }
+    result["ticket"]
+        .as_str()
+        .map_or(false, |ticket| !ticket.contains(":!tfa!"))
+}
+
+/// Hooks for a completed login of `userid`, including the second factor if there is one.
+fn login_hooks(userid: &Userid, rpcenv: &dyn RpcEnvironment, user_agent: Option<String>) {
+    let ip = rpcenv.get_client_ip().map(|addr| addr.ip());
+    record_use(&Authid::from(userid.clone()), ip);
+    check_login(userid, ip, user_agent);
+}
+
+// done before the ticket is handed out, so the namespace is there right away
+async fn provision_namespace(userid: Userid) {
+    let provision_userid = userid.clone();
+    match tokio::task::spawn_blocking(move || provision_user_namespace(&provision_userid)).await {
+        Ok(Ok(())) => (),
+        Ok(Err(err)) => log::error!("namespace provisioning for '{userid}' failed - {err}"),
+        Err(err) => log::error!("namespace provisioning task for '{userid}' failed - {err}"),
+    }
+}
+
+/// Returns the user of a request answering a second factor challenge.
+fn tfa_response_userid(param: &Value) -> Option<Userid> {
+    if !param["tfa-challenge"].is_string() {
+        return None;
+    }
+    param["username"].as_str()?.parse().ok()
+}
+
+// fails if logins of `userid` from `address` are currently refused
+fn login_attempts(
+    userid: &Userid,
+    address: Option<IpAddr>,
+) -> Result<Option<&'static LoginAttempts>, Error> {
+    let attempts = match LoginAttempts::new() {
+        Ok(attempts) => attempts,
+        Err(err) => {
+            log::error!("unable to access failed login counters - {err}");
+            return Ok(None);
+        }
+    };
+    if attempts.locked_until(userid, address).is_some() {
+        bail!("too many failed logins, try again later");
+    }
+    Ok(Some(attempts))
+}
+
+fn run_login(
+    parts: Parts,
+    req_body: Body,
+    method: &'static ApiMethod,
+    check_result: fn(&Value, &Value) -> bool,
+    mut rpcenv: Box<dyn RpcEnvironment>,
+) -> ApiResponseFuture {
+    async move {
+        let param = request_parameters(&parts, req_body, method).await?;
+
+        // second factors are not checked by the authenticator, so count their failures here
+        let tfa_userid = tfa_response_userid(&param);
+        let address = rpcenv.get_client_ip().map(|addr| addr.ip());
+        let attempts = match &tfa_userid {
+            Some(userid) => login_attempts(userid, address)?,
+            None => None,
+        };
+
+        let result = call_login_method(param.clone(), method, &mut *rpcenv).await;
+
+        if let (Some(userid), Some(attempts), Err(_)) = (&tfa_userid, attempts, &result) {
+            record_login_failure(attempts, userid, address);
+        }
+        let result = result?;
+
+        if check_result(&param, &result) {
+            match result["username"].as_str().map(str::parse::<Userid>) {
+                Some(Ok(userid)) => {
+                    if let Ok(attempts) = LoginAttempts::new() {
+                        attempts.record_success(&userid, address);
+                    }
+                    login_hooks(&userid, &*rpcenv, user_agent(&parts));
+                    provision_namespace(userid).await;
+                }
+                _ => log::error!("cannot run login hooks - login result without user"),
+            }
+        }
+
+        Ok(formatter::JSON_FORMATTER.format_data(result, &*rpcenv))
+    }
+    .boxed()
+}
+
+fn create_ticket(
+    parts: Parts,
+    req_body: Body,
+    _param: Value,
+    _info: &ApiMethod,
+    rpcenv: Box<dyn RpcEnvironment>,
+) -> ApiResponseFuture {
+    run_login(
+        parts,
+        req_body,
+        &proxmox_auth_api::api::API_METHOD_CREATE_TICKET,
+        is_completed_login,
+        rpcenv,
+    )
+}
+
+fn openid_login(
+    parts: Parts,
+    req_body: Body,
+    _param: Value,
+    _info: &ApiMethod,
+    rpcenv: Box<dyn RpcEnvironment>,
+) -> ApiResponseFuture {
+    // there is no second factor for OpenID logins
+    run_login(
+        parts,
+        req_body,
+        &super::openid::API_METHOD_OPENID_LOGIN,
+        |_param, _result| true,
+        rpcenv,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn test_is_completed_login() {
+        let full = json!({ "username": "test@pbs", "ticket": "PBS:test@pbs:65A0B0C0::sig" });
+        let partial = json!({
+            "username": "test@pbs",
+            "ticket": "PBS:!tfa!%7B%7D:65A0B0C0::sig",
+            "NeedTFA": 1,
+        });
+
+        let login = json!({ "username": "test@pbs", "password": "secret" });
+        assert!(is_completed_login(&login, &full));
+        assert!(!is_completed_login(&login, &partial));
+
+        let tfa = json!({
+            "username": "test@pbs",
+            "password": "totp:123456",
+            "tfa-challenge": "PBS:!tfa!%7B%7D:65A0B0C0::sig",
+        });
+        assert!(is_completed_login(&tfa, &full));
+
+        let renewal = json!({ "username": "test@pbs", "password": "PBS:test@pbs:65A0B0C0::sig" });
+        assert!(!is_completed_login(&renewal, &full));
+
+        let privilege_check = json!({
+            "username": "test@pbs",
+            "password": "PBSTERM:test@pbs:65A0B0C0::sig",
+            "path": "/system",
+            "privs": "Sys.Console",
+        });
+        assert!(!is_completed_login(
+            &privilege_check,
+            &json!({ "username": "test@pbs" })
+        ));
+    }
+
+    #[test]
+    fn test_tfa_response_userid() {
+        let tfa = json!({
+            "username": "test@pbs",
+            "password": "totp:123456",
+            "tfa-challenge": "PBS:!tfa!%7B%7D:65A0B0C0::sig",
+        });
+        assert_eq!(tfa_response_userid(&tfa), Some("test@pbs".parse().unwrap()));
+
+        let login = json!({ "username": "test@pbs", "password": "secret" });
+        assert_eq!(tfa_response_userid(&login), None);
+    }
+}
@@ -9,18 +9,19 @@ use std::collections::HashSet;
 use proxmox_router::{
     http_bail, http_err, list_subdirs_api_method, Permission, Router, RpcEnvironment, SubdirMap,
 };
-use proxmox_schema::api;
+use proxmox_schema::{api, ApiType};
 use proxmox_sortable_macro::sortable;
 
 use pbs_api_types::{
-    Authid, User, Userid, ACL_PATH_SCHEMA, PASSWORD_FORMAT, PASSWORD_SCHEMA, PRIVILEGES,
-    PRIV_PERMISSIONS_MODIFY, PRIV_SYS_AUDIT,
+    ApiToken, Authid, TokenClientProfile, User, Userid, ACL_PATH_SCHEMA, PASSWORD_FORMAT,
+    PASSWORD_SCHEMA, PRIVILEGES, PRIV_PERMISSIONS_MODIFY, PRIV_SYS_AUDIT,
 };
 use pbs_config::acl::AclTreeNode;
 use pbs_config::CachedUserInfo;
 
 pub mod acl;
 pub mod domain;
+pub mod login;
 pub mod openid;
 pub mod role;
 pub mod tfa;
@@ -258,9 +259,45 @@ pub fn list_permissions(
     Ok(map)
 }
 
+#[api(
+    returns: {
+        type: TokenClientProfile,
+    },
+    access: {
+        description: "Anybody can read the client profile of the API token they are authenticated with.",
+        permission: &Permission::Anybody,
+    },
+)]
+/// Get the client profile of the API token used for this request.
+///
+/// Returns an empty profile if the request is not authenticated with an API token, or if the
+/// token has no client profile.
+pub fn get_client_profile(rpcenv: &mut dyn RpcEnvironment) -> Result<TokenClientProfile, Error> {
+    let auth_id: Authid = rpcenv
+        .get_auth_id()
+        .ok_or_else(|| format_err!("no authid available"))?
+        .parse()?;
+
+    if !auth_id.is_token() {
+        return Ok(TokenClientProfile::default());
+    }
+
+    let (config, _digest) = pbs_config::user::config()?;
+    let token: ApiToken = config.lookup("token", &auth_id.to_string())?;
+
+    let profile = TokenClientProfile::API_SCHEMA
+        .parse_property_string(token.client_profile.as_deref().unwrap_or(""))?;
+
+    Ok(serde_json::from_value(profile)?)
+}
+
 #[sortable]
 const SUBDIRS: SubdirMap = &sorted!([
     ("acl", &acl::ROUTER),
+    (
+        "client-profile",
+        &Router::new().get(&API_METHOD_GET_CLIENT_PROFILE)
+    ),
     ("password", &Router::new().put(&API_METHOD_CHANGE_PASSWORD)),
     (
         "permissions",
@@ -268,15 +305,36 @@ const SUBDIRS: SubdirMap = &sorted!([
     ),
     (
         "ticket",
-        &Router::new().post(&proxmox_auth_api::api::API_METHOD_CREATE_TICKET)
+        &Router::new().post(&login::API_METHOD_CREATE_TICKET)
     ),
     ("openid", &openid::ROUTER),
     ("domains", &domain::ROUTER),
     ("roles", &role::ROUTER),
     ("users", &user::ROUTER),
     ("tfa", &tfa::ROUTER),
+    (
+        "unused-tokens",
+        &Router::new().get(&user::API_METHOD_LIST_UNUSED_TOKENS)
+    ),
 ]);
 
 pub const ROUTER: Router = Router::new()
     .get(&list_subdirs_api_method!(SUBDIRS))
     .subdirs(SUBDIRS);
+
+#[sortable]
+const RESTORE_SUBDIRS: SubdirMap = &sorted!([
+    (
+        "client-profile",
+        &Router::new().get(&API_METHOD_GET_CLIENT_PROFILE)
+    ),
+    (
+        "ticket",
+        &Router::new().post(&login::API_METHOD_CREATE_TICKET)
+    ),
+]);
+
+/// Access API subset for the restore-only listener of the proxy, only allows logging in.
+pub const RESTORE_ROUTER: Router = Router::new()
+    .get(&list_subdirs_api_method!(RESTORE_SUBDIRS))
+    .subdirs(RESTORE_SUBDIRS);
@@ -1,4 +1,6 @@
 //! OpenID redirect/login API
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
 use anyhow::{bail, format_err, Error};
 use serde_json::{json, Value};
 
@@ -9,14 +11,15 @@ use proxmox_router::{
 };
 use proxmox_schema::api;
 use proxmox_sortable_macro::sortable;
+use proxmox_sys::fs::{file_read_optional_string, replace_file, CreateOptions};
 
 use proxmox_openid::{OpenIdAuthenticator, OpenIdConfig};
 
 use pbs_api_types::{
-    OpenIdRealmConfig, User, Userid, EMAIL_SCHEMA, FIRST_NAME_SCHEMA, LAST_NAME_SCHEMA,
-    OPENID_DEFAILT_SCOPE_LIST, REALM_ID_SCHEMA,
+    parse_openid_role_mapping_list, Authid, OpenIdRealmConfig, OpenIdRoleMapping, User, Userid,
+    EMAIL_SCHEMA, FIRST_NAME_SCHEMA, LAST_NAME_SCHEMA, OPENID_DEFAILT_SCOPE_LIST, REALM_ID_SCHEMA,
 };
-use pbs_buildcfg::PROXMOX_BACKUP_RUN_DIR_M;
+use pbs_buildcfg::{PROXMOX_BACKUP_RUN_DIR_M, PROXMOX_BACKUP_STATE_DIR_M};
 
 use pbs_config::open_backup_lockfile;
 use pbs_config::CachedUserInfo;
@@ -58,6 +61,130 @@ fn openid_authenticator(
     OpenIdAuthenticator::discover(&config, redirect_url)
 }
 
+const ROLE_MAPPING_STATE_FN: &str =
+    concat!(PROXMOX_BACKUP_STATE_DIR_M!(), "/openid-role-mapping.json");
+
+/// A role granted on an ACL path, as `(path, role)`.
+type RoleGrant = (String, String);
+
+/// Computes the role mapping changes for the values of the roles claim.
+///
+/// Returns the roles to grant, the roles to revoke and the updated set of roles managed by the
+/// mapping. Only roles the mapping granted itself are ever revoked, so roles an administrator
+/// granted manually stay, even if the mapping lists them.
+fn role_mapping_changes(
+    mappings: &[OpenIdRoleMapping],
+    values: &HashSet<&str>,
+    managed: &BTreeSet<RoleGrant>,
+    is_granted: impl Fn(&str, &str) -> bool,
+) -> (Vec<RoleGrant>, Vec<RoleGrant>, BTreeSet<RoleGrant>) {
+    let mut wanted: BTreeMap<RoleGrant, bool> = BTreeMap::new();
+    for mapping in mappings.iter() {
+        *wanted
+            .entry((mapping.path.clone(), mapping.role.clone()))
+            .or_default() |= values.contains(mapping.value.as_str());
+    }
+
+    let mut grant = Vec::new();
+    let mut revoke = Vec::new();
+    let mut new_managed = BTreeSet::new();
+
+    for (entry, want) in wanted.iter() {
+        let granted = is_granted(&entry.0, &entry.1);
+        if *want && !granted {
+            grant.push(entry.clone());
+            new_managed.insert(entry.clone());
+        } else if *want && managed.contains(entry) {
+            new_managed.insert(entry.clone());
+        }
+    }
+
+    // also covers roles whose mapping entry was removed in the meantime
+    for entry in managed.iter() {
+        if wanted.get(entry) != Some(&true) && is_granted(&entry.0, &entry.1) {
+            revoke.push(entry.clone());
+        }
+    }
+
+    (grant, revoke, new_managed)
+}
+
+/// Grant and revoke the roles of the realm's role mapping according to the roles claim.
+///
+/// A role listed in the mapping is granted if the claim contains any of the values mapped to it.
+/// Roles granted this way are recorded and revoked again once the claim no longer contains the
+/// values, other ACL entries of the user are left alone.
+fn apply_role_mapping(
+    config: &OpenIdRealmConfig,
+    userid: &Userid,
+    claims: &Value,
+) -> Result<(), Error> {
+    let mappings = match config.role_mapping.as_deref() {
+        Some(list) => parse_openid_role_mapping_list(list)?,
+        None => Vec::new(),
+    };
+
+    let claim = config.roles_claim.as_deref().unwrap_or("groups");
+    let values: HashSet<&str> = match &claims[claim] {
+        Value::Array(list) => list.iter().filter_map(Value::as_str).collect(),
+        Value::String(value) => HashSet::from([value.as_str()]),
+        _ => HashSet::new(),
+    };
+
+    let auth_id = Authid::from(userid.clone());
+
+    // the state file is protected by the ACL config lock as well
+    let _lock = pbs_config::acl::lock_config()?;
+    let (mut tree, _digest) = pbs_config::acl::config()?;
+
+    let mut state: HashMap<String, BTreeSet<RoleGrant>> =
+        match file_read_optional_string(ROLE_MAPPING_STATE_FN)? {
+            Some(content) => serde_json::from_str(&content)
+                .map_err(|err| format_err!("unable to parse {ROLE_MAPPING_STATE_FN:?} - {err}"))?,
+            None => HashMap::new(),
+        };
+    let managed = state.remove(userid.as_str()).unwrap_or_default();
+
+    if mappings.is_empty() && managed.is_empty() {
+        return Ok(());
+    }
+
+    let (grant, revoke, managed) =
+        role_mapping_changes(&mappings, &values, &managed, |path, role| {
+            tree.find_node(path)
+                .and_then(|node| node.users.get(&auth_id))
+                .map_or(false, |roles| roles.contains_key(role))
+        });
+
+    for (path, role) in grant.iter() {
+        tree.insert_user_role(path, &auth_id, role, true);
+    }
+    for (path, role) in revoke.iter() {
+        tree.delete_user_role(path, &auth_id, role);
+    }
+    if !grant.is_empty() || !revoke.is_empty() {
+        pbs_config::acl::save_config(&tree)?;
+    }
+
+    if !managed.is_empty() {
+        state.insert(userid.to_string(), managed);
+    }
+    let backup_user = pbs_config::backup_user()?;
+    let options = CreateOptions::new()
+        .perm(nix::sys::stat::Mode::from_bits_truncate(0o0640))
+        .owner(backup_user.uid)
+        .group(backup_user.gid);
+    replace_file(
+        ROLE_MAPPING_STATE_FN,
+        serde_json::to_string(&state)?.as_bytes(),
+        options,
+        false,
+    )
+    .map_err(|err| format_err!("unable to write {ROLE_MAPPING_STATE_FN:?} - {err}"))?;
+
+    Ok(())
+}
+
 #[api(
     input: {
         properties: {
@@ -199,6 +326,9 @@ pub fn openid_login(
             }
         }
 
+        apply_role_mapping(&config, &user_id, &info)
+            .map_err(|err| format_err!("applying role mapping failed - {err}"))?;
+
         let api_ticket = ApiTicket::Full(user_id.clone());
         let ticket = Ticket::new("PBS", &api_ticket)?.sign(private_auth_keyring(), None)?;
         let token = assemble_csrf_prevention_token(csrf_secret(), &user_id);
@@ -261,10 +391,83 @@ fn openid_auth_url(
 
 #[sortable]
 const SUBDIRS: SubdirMap = &sorted!([
-    ("login", &Router::new().post(&API_METHOD_OPENID_LOGIN)),
+    (
+        "login",
+        &Router::new().post(&super::login::API_METHOD_OPENID_LOGIN)
+    ),
     ("auth-url", &Router::new().post(&API_METHOD_OPENID_AUTH_URL)),
 ]);
 
 pub const ROUTER: Router = Router::new()
     .get(&list_subdirs_api_method!(SUBDIRS))
     .subdirs(SUBDIRS);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn grant(path: &str, role: &str) -> RoleGrant {
+        (path.to_string(), role.to_string())
+    }
+
+    #[test]
+    fn test_parse_role_mapping_list() -> Result<(), Error> {
+        let mappings = parse_openid_role_mapping_list(
+            "Backup Admins:DatastoreAdmin:/datastore; cn=users,ou=groups:DatastoreBackup:/datastore/store1",
+        )?;
+        assert_eq!(mappings.len(), 2);
+        assert_eq!(mappings[0].value, "Backup Admins");
+        assert_eq!(mappings[0].role, "DatastoreAdmin");
+        assert_eq!(mappings[0].path, "/datastore");
+        assert_eq!(mappings[1].value, "cn=users,ou=groups");
+        assert_eq!(mappings[1].path, "/datastore/store1");
+
+        assert!(parse_openid_role_mapping_list("admins:NoSuchRole:/").is_err());
+        assert!(parse_openid_role_mapping_list("admins:Admin").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_role_mapping_changes() -> Result<(), Error> {
+        let mappings = parse_openid_role_mapping_list(
+            "admins:DatastoreAdmin:/datastore;users:DatastoreBackup:/datastore",
+        )?;
+
+        // first login as admin, the backup role was granted manually before
+        let values = HashSet::from(["admins", "users"]);
+        let (add, remove, managed) =
+            role_mapping_changes(&mappings, &values, &BTreeSet::new(), |_path, role| {
+                role == "DatastoreBackup"
+            });
+        assert_eq!(add, vec![grant("/datastore", "DatastoreAdmin")]);
+        assert!(remove.is_empty());
+        assert_eq!(
+            managed,
+            BTreeSet::from([grant("/datastore", "DatastoreAdmin")])
+        );
+
+        // no longer in either group, only the role granted by the mapping is revoked
+        let values = HashSet::new();
+        let (add, remove, new_managed) =
+            role_mapping_changes(&mappings, &values, &managed, |_path, _role| true);
+        assert!(add.is_empty());
+        assert_eq!(remove, vec![grant("/datastore", "DatastoreAdmin")]);
+        assert!(new_managed.is_empty());
+
+        // a granted role stays managed while the claim contains its value
+        let values = HashSet::from(["admins"]);
+        let (add, remove, new_managed) =
+            role_mapping_changes(&mappings, &values, &managed, |_path, _role| true);
+        assert!(add.is_empty());
+        assert!(remove.is_empty());
+        assert_eq!(new_managed, managed);
+
+        // removing the entry from the mapping revokes what it granted
+        let (add, remove, new_managed) =
+            role_mapping_changes(&[], &values, &managed, |_path, _role| true);
+        assert!(add.is_empty());
+        assert_eq!(remove, vec![grant("/datastore", "DatastoreAdmin")]);
+        assert!(new_managed.is_empty());
+        Ok(())
+    }
+}
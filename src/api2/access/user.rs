@@ -11,14 +11,17 @@ use proxmox_schema::api;
 use proxmox_tfa::api::TfaConfig;
 
 use pbs_api_types::{
-    ApiToken, Authid, Tokenname, User, UserUpdater, UserWithTokens, Userid, ENABLE_USER_SCHEMA,
-    EXPIRE_USER_SCHEMA, PBS_PASSWORD_SCHEMA, PRIV_PERMISSIONS_MODIFY, PRIV_SYS_AUDIT,
-    PROXMOX_CONFIG_DIGEST_SCHEMA, SINGLE_LINE_COMMENT_SCHEMA,
+    ApiToken, ApiTokenWithLastUse, Authid, Tokenname, User, UserUpdater, UserWithTokens, Userid,
+    ACL_PATH_SCHEMA, ENABLE_USER_SCHEMA, EXPIRE_USER_SCHEMA, PBS_PASSWORD_SCHEMA,
+    PRIV_PERMISSIONS_MODIFY, PRIV_SYS_AUDIT, PROXMOX_CONFIG_DIGEST_SCHEMA,
+    SINGLE_LINE_COMMENT_SCHEMA, TOKEN_CLIENT_PROFILE_SCHEMA,
 };
 use pbs_config::token_shadow;
 
 use pbs_config::CachedUserInfo;
 
+use crate::server::auth_last_used::{last_uses, LastUse};
+
 fn new_user_with_tokens(user: User, tfa: &TfaConfig) -> UserWithTokens {
     UserWithTokens {
         totp_locked: tfa
@@ -38,6 +41,17 @@ fn new_user_with_tokens(user: User, tfa: &TfaConfig) -> UserWithTokens {
         lastname: user.lastname,
         email: user.email,
         tokens: Vec::new(),
+        last_used: None,
+        last_used_ip: None,
+    }
+}
+
+fn with_last_use(token: ApiToken, last_uses: &HashMap<String, LastUse>) -> ApiTokenWithLastUse {
+    let last = last_uses.get(&token.tokenid.to_string());
+    ApiTokenWithLastUse {
+        last_used: last.map(|last| last.time),
+        last_used_ip: last.and_then(|last| last.ip.clone()),
+        token,
     }
 }
 
@@ -90,17 +104,18 @@ pub fn list_users(
     rpcenv["digest"] = hex::encode(digest).into();
 
     let tfa_data = crate::config::tfa::read()?;
+    let last_uses = last_uses()?;
 
     let iter = list.into_iter().filter(filter_by_privs);
-    let list = if include_tokens {
+    let mut list: Vec<UserWithTokens> = if include_tokens {
         let tokens: Vec<ApiToken> = config.convert_to_typed_array("token")?;
         let mut user_to_tokens = tokens.into_iter().fold(
             HashMap::new(),
-            |mut map: HashMap<Userid, Vec<ApiToken>>, token: ApiToken| {
+            |mut map: HashMap<Userid, Vec<ApiTokenWithLastUse>>, token: ApiToken| {
                 if token.tokenid.is_token() {
                     map.entry(token.tokenid.user().clone())
                         .or_default()
-                        .push(token);
+                        .push(with_last_use(token, &last_uses));
                 }
                 map
             },
@@ -116,6 +131,13 @@ pub fn list_users(
             .collect()
     };
 
+    for user in list.iter_mut() {
+        if let Some(last) = last_uses.get(user.userid.as_str()) {
+            user.last_used = Some(last.time);
+            user.last_used_ip = last.ip.clone();
+        }
+    }
+
     Ok(list)
 }
 
@@ -456,6 +478,14 @@ pub fn read_token(
                 schema: EXPIRE_USER_SCHEMA,
                 optional: true,
             },
+            "client-profile": {
+                schema: TOKEN_CLIENT_PROFILE_SCHEMA,
+                optional: true,
+            },
+            subtree: {
+                schema: ACL_PATH_SCHEMA,
+                optional: true,
+            },
             digest: {
                 optional: true,
                 schema: PROXMOX_CONFIG_DIGEST_SCHEMA,
@@ -489,6 +519,8 @@ pub fn generate_token(
     comment: Option<String>,
     enable: Option<bool>,
     expire: Option<i64>,
+    client_profile: Option<String>,
+    subtree: Option<String>,
     digest: Option<String>,
 ) -> Result<Value, Error> {
     let _lock = pbs_config::user::lock_config()?;
@@ -511,6 +543,10 @@ pub fn generate_token(
         );
     }
 
+    if let Some(subtree) = &subtree {
+        pbs_config::acl::check_acl_path(subtree)?;
+    }
+
     let secret = format!("{:x}", proxmox_uuid::Uuid::generate());
     token_shadow::set_secret(&tokenid, &secret)?;
 
@@ -519,6 +555,8 @@ pub fn generate_token(
         comment,
         enable,
         expire,
+        client_profile: client_profile.filter(|profile| !profile.is_empty()),
+        subtree,
     };
 
     config.set_data(&tokenid_string, "token", &token)?;
@@ -553,6 +591,10 @@ pub fn generate_token(
                 schema: EXPIRE_USER_SCHEMA,
                 optional: true,
             },
+            "client-profile": {
+                schema: TOKEN_CLIENT_PROFILE_SCHEMA,
+                optional: true,
+            },
             digest: {
                 optional: true,
                 schema: PROXMOX_CONFIG_DIGEST_SCHEMA,
@@ -573,6 +615,7 @@ pub fn update_token(
     comment: Option<String>,
     enable: Option<bool>,
     expire: Option<i64>,
+    client_profile: Option<String>,
     digest: Option<String>,
 ) -> Result<(), Error> {
     let _lock = pbs_config::user::lock_config()?;
@@ -606,6 +649,14 @@ pub fn update_token(
         data.expire = if expire > 0 { Some(expire) } else { None };
     }
 
+    if let Some(client_profile) = client_profile {
+        data.client_profile = if client_profile.is_empty() {
+            None
+        } else {
+            Some(client_profile)
+        };
+    }
+
     config.set_data(&tokenid_string, "token", &data)?;
 
     pbs_config::user::save_config(&config)?;
@@ -675,7 +726,7 @@ pub fn delete_token(
 #[api(
     properties: {
         "token-name": { type: Tokenname },
-        token: { type: ApiToken },
+        token: { type: ApiTokenWithLastUse },
     }
 )]
 #[derive(Serialize, Deserialize)]
@@ -685,7 +736,7 @@ pub struct TokenApiEntry {
     /// The Token name
     pub token_name: Tokenname,
     #[serde(flatten)]
-    pub token: ApiToken,
+    pub token: ApiTokenWithLastUse,
 }
 
 #[api(
@@ -720,9 +771,12 @@ pub fn list_tokens(
 
     rpcenv["digest"] = hex::encode(digest).into();
 
+    let last_uses = last_uses()?;
+
     let filter_by_owner = |token: ApiToken| {
         if token.tokenid.is_token() && token.tokenid.user() == &userid {
             let token_name = token.tokenid.tokenname().unwrap().to_owned();
+            let token = with_last_use(token, &last_uses);
             Some(TokenApiEntry { token_name, token })
         } else {
             None
@@ -734,6 +788,45 @@ pub fn list_tokens(
     Ok(res)
 }
 
+#[api(
+    input: {
+        properties: {
+            days: {
+                type: u64,
+                description: "Report tokens not used for this many days.",
+                optional: true,
+                default: 90,
+                minimum: 1,
+            },
+        },
+    },
+    returns: {
+        description: "API tokens not used within the given number of days, including tokens \
+            which were never used.",
+        type: Array,
+        items: { type: ApiTokenWithLastUse },
+    },
+    access: {
+        permission: &Permission::Privilege(&["access", "users"], PRIV_SYS_AUDIT, false),
+    },
+)]
+/// List API tokens of all users which were not used recently.
+pub fn list_unused_tokens(days: u64) -> Result<Vec<ApiTokenWithLastUse>, Error> {
+    let (config, _digest) = pbs_config::user::config()?;
+    let last_uses = last_uses()?;
+
+    let cutoff = proxmox_time::epoch_i64() - (days as i64) * 86400;
+
+    let list: Vec<ApiToken> = config.convert_to_typed_array("token")?;
+    let list = list
+        .into_iter()
+        .map(|token| with_last_use(token, &last_uses))
+        .filter(|token| token.last_used.map_or(true, |time| time < cutoff))
+        .collect();
+
+    Ok(list)
+}
+
 #[api(
     protected: true,
     input: {
@@ -768,6 +861,28 @@ pub fn unlock_tfa(userid: Userid) -> Result<bool, Error> {
     }
 }
 
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            userid: {
+                type: Userid,
+            },
+        },
+    },
+    returns: {
+        type: bool,
+        description: "Whether the user was previously locked out after failed logins.",
+    },
+    access: {
+        permission: &Permission::Privilege(&["access", "users"], PRIV_PERMISSIONS_MODIFY, false),
+    },
+)]
+/// Reset the failed login counters of a user, lifting a lockout.
+pub fn unlock_login(userid: Userid) -> Result<bool, Error> {
+    Ok(crate::tools::login_attempts::LoginAttempts::new()?.unlock(&userid))
+}
+
 const TOKEN_ITEM_ROUTER: Router = Router::new()
     .get(&API_METHOD_READ_TOKEN)
     .put(&API_METHOD_UPDATE_TOKEN)
@@ -780,7 +895,13 @@ const TOKEN_ROUTER: Router = Router::new()
 
 const UNLOCK_TFA_ROUTER: Router = Router::new().put(&API_METHOD_UNLOCK_TFA);
 
-const USER_SUBDIRS: SubdirMap = &[("token", &TOKEN_ROUTER), ("unlock-tfa", &UNLOCK_TFA_ROUTER)];
+const UNLOCK_LOGIN_ROUTER: Router = Router::new().put(&API_METHOD_UNLOCK_LOGIN);
+
+const USER_SUBDIRS: SubdirMap = &[
+    ("token", &TOKEN_ROUTER),
+    ("unlock-login", &UNLOCK_LOGIN_ROUTER),
+    ("unlock-tfa", &UNLOCK_TFA_ROUTER),
+];
 
 const USER_ROUTER: Router = Router::new()
     .get(&API_METHOD_READ_USER)
@@ -0,0 +1,162 @@
+//! Restore traffic accounting per auth-id
+
+use std::collections::BTreeMap;
+
+use anyhow::Error;
+use serde_json::Value;
+
+use proxmox_router::{list_subdirs_api_method, Permission, Router, RpcEnvironment, SubdirMap};
+use proxmox_schema::api;
+use proxmox_sortable_macro::sortable;
+
+use pbs_api_types::{
+    Authid, RRDMode, RRDTimeFrame, RestoreAccountingKind, RestoreAccountingSummary, PRIV_SYS_AUDIT,
+};
+use pbs_config::CachedUserInfo;
+
+use crate::api2::node::rrd::create_value_from_rrd;
+use crate::server::restore_accounting::read_restore_accounting_log;
+
+/// Users see their own traffic and that of their tokens, Sys.Audit on /system/log allows to see
+/// the traffic of everybody.
+fn accounting_visible(auth_id: &Authid, list_all: bool) -> impl Fn(&Authid) -> bool + '_ {
+    move |entry: &Authid| {
+        list_all || entry == auth_id || (!auth_id.is_token() && entry.user() == auth_id.user())
+    }
+}
+
+fn can_list_all(auth_id: &Authid) -> Result<bool, Error> {
+    let user_info = CachedUserInfo::new()?;
+    let privs = user_info.lookup_privs(auth_id, &["system", "log"]);
+    Ok(privs & PRIV_SYS_AUDIT != 0)
+}
+
+#[api(
+    input: {
+        properties: {
+            since: {
+                type: i64,
+                description: "Only account transfers finished since this time (epoch).",
+                optional: true,
+            },
+            until: {
+                type: i64,
+                description: "Only account transfers finished before this time (epoch).",
+                optional: true,
+            },
+            "auth-id": {
+                type: Authid,
+                optional: true,
+            },
+        },
+    },
+    returns: {
+        description: "Restore traffic per auth-id.",
+        type: Array,
+        items: { type: RestoreAccountingSummary },
+    },
+    access: {
+        description: "Users can see their own traffic and the traffic of their tokens, Sys.Audit \
+            on /system/log is required to see the traffic of others.",
+        permission: &Permission::Anybody,
+    },
+)]
+/// Summarize the restore accounting log per auth-id.
+pub fn list_restore_accounting(
+    since: Option<i64>,
+    until: Option<i64>,
+    auth_id: Option<Authid>,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Vec<RestoreAccountingSummary>, Error> {
+    let requester: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let list_all = can_list_all(&requester)?;
+    let visible = accounting_visible(&requester, list_all);
+
+    let mut summaries: BTreeMap<String, RestoreAccountingSummary> = BTreeMap::new();
+
+    read_restore_accounting_log(|entry| {
+        if since.map(|since| entry.time < since).unwrap_or(false) {
+            return false; // the log is read from the newest entry on
+        }
+        if until.map(|until| entry.time >= until).unwrap_or(false) {
+            return true;
+        }
+        if !visible(&entry.auth_id) || auth_id.as_ref().map_or(false, |id| *id != entry.auth_id) {
+            return true;
+        }
+
+        let summary = summaries
+            .entry(entry.auth_id.to_string())
+            .or_insert_with(|| RestoreAccountingSummary {
+                auth_id: entry.auth_id.clone(),
+                bytes: 0,
+                reader_sessions: 0,
+                downloads: 0,
+            });
+        summary.bytes += entry.bytes;
+        match entry.kind {
+            RestoreAccountingKind::Reader => summary.reader_sessions += 1,
+            RestoreAccountingKind::Download => summary.downloads += 1,
+        }
+
+        true
+    })?;
+
+    Ok(summaries.into_values().collect())
+}
+
+#[api(
+    input: {
+        properties: {
+            "auth-id": {
+                type: Authid,
+            },
+            timeframe: {
+                type: RRDTimeFrame,
+            },
+            cf: {
+                type: RRDMode,
+            },
+        },
+    },
+    access: {
+        description: "Users can see their own traffic and the traffic of their tokens, Sys.Audit \
+            on /system/log is required to see the traffic of others.",
+        permission: &Permission::Anybody,
+    },
+)]
+/// Read the restore traffic statistics of an auth-id.
+pub fn get_restore_accounting_rrd(
+    auth_id: Authid,
+    timeframe: RRDTimeFrame,
+    cf: RRDMode,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Value, Error> {
+    let requester: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    if !accounting_visible(&requester, can_list_all(&requester)?)(&auth_id) {
+        proxmox_router::http_bail!(FORBIDDEN, "permission check failed");
+    }
+
+    create_value_from_rrd(
+        &format!("accounting/{auth_id}"),
+        &["restore"],
+        timeframe,
+        cf,
+    )
+}
+
+#[sortable]
+const SUBDIRS: SubdirMap = &sorted!([
+    (
+        "restore",
+        &Router::new().get(&API_METHOD_LIST_RESTORE_ACCOUNTING)
+    ),
+    (
+        "rrd",
+        &Router::new().get(&API_METHOD_GET_RESTORE_ACCOUNTING_RRD)
+    ),
+]);
+
+pub const ROUTER: Router = Router::new()
+    .get(&list_subdirs_api_method!(SUBDIRS))
+    .subdirs(SUBDIRS);
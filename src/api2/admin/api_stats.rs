@@ -0,0 +1,47 @@
+//! Latency statistics of the API endpoints
+
+use anyhow::Error;
+
+use proxmox_router::{Permission, Router};
+use proxmox_schema::api;
+
+use pbs_api_types::{ApiEndpointStats, ApiStatsSortBy, PRIV_SYS_AUDIT};
+
+use crate::server::api_stats::api_stats;
+
+#[api(
+    input: {
+        properties: {
+            limit: {
+                type: Integer,
+                description: "Number of endpoints to return.",
+                optional: true,
+                minimum: 1,
+                default: 10,
+            },
+            "sort-by": {
+                type: ApiStatsSortBy,
+                optional: true,
+            },
+        },
+    },
+    returns: {
+        description: "The slowest API endpoints since the start of the proxy.",
+        type: Array,
+        items: { type: ApiEndpointStats },
+    },
+    access: {
+        permission: &Permission::Privilege(&["system", "status"], PRIV_SYS_AUDIT, false),
+    },
+)]
+/// List the slowest API endpoints, by average latency unless sorted otherwise.
+pub fn list_api_stats(
+    limit: u64,
+    sort_by: Option<ApiStatsSortBy>,
+) -> Result<Vec<ApiEndpointStats>, Error> {
+    let mut list = api_stats(sort_by.unwrap_or(ApiStatsSortBy::Average));
+    list.truncate(limit as usize);
+    Ok(list)
+}
+
+pub const ROUTER: Router = Router::new().get(&API_METHOD_LIST_API_STATS);
@@ -1,7 +1,7 @@
 //! Datastore Management
 
 use std::collections::HashSet;
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
 use std::os::unix::ffi::OsStrExt;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -18,8 +18,8 @@ use proxmox_async::blocking::WrappedReaderStream;
 use proxmox_async::{io::AsyncChannelWriter, stream::AsyncReaderStream};
 use proxmox_compression::zstd::ZstdEncoder;
 use proxmox_router::{
-    http_err, list_subdirs_api_method, ApiHandler, ApiMethod, ApiResponseFuture, Permission,
-    Router, RpcEnvironment, RpcEnvironmentType, SubdirMap,
+    http_bail, http_err, list_subdirs_api_method, ApiHandler, ApiMethod, ApiResponseFuture,
+    Permission, Router, RpcEnvironment, RpcEnvironmentType, SubdirMap,
 };
 use proxmox_schema::*;
 use proxmox_sortable_macro::sortable;
@@ -29,19 +29,20 @@ use proxmox_sys::fs::{
 use proxmox_sys::{task_log, task_warn};
 use proxmox_time::CalendarEvent;
 
-use pxar::accessor::aio::Accessor;
+use pxar::accessor::aio::{Accessor, FileEntry};
 use pxar::EntryKind;
 
 use pbs_api_types::{
     print_ns_and_snapshot, print_store_and_ns, Authid, BackupContent, BackupNamespace, BackupType,
-    Counts, CryptMode, DataStoreConfig, DataStoreListItem, DataStoreStatus,
-    GarbageCollectionJobStatus, GroupListItem, JobScheduleStatus, KeepOptions, Operation,
-    PruneJobOptions, RRDMode, RRDTimeFrame, SnapshotListItem, SnapshotVerifyState,
+    ChunkXrefReportInfo, Counts, CryptMode, DataStoreConfig, DataStoreListItem, DataStoreStatus,
+    DataStoreUsageTrend, GarbageCollectionJobStatus, GroupListItem, GroupTemplate, JobHistoryEntry,
+    JobScheduleStatus, KeepOptions, Operation, PruneJobOptions, RRDMode, RRDTimeFrame, RestoreLink,
+    SeedHistoryEntry, SnapshotListItem, SnapshotVerifyState, VerificationJobConfig, VerifyReport,
     BACKUP_ARCHIVE_NAME_SCHEMA, BACKUP_ID_SCHEMA, BACKUP_NAMESPACE_SCHEMA, BACKUP_TIME_SCHEMA,
-    BACKUP_TYPE_SCHEMA, DATASTORE_SCHEMA, IGNORE_VERIFIED_BACKUPS_SCHEMA, MAX_NAMESPACE_DEPTH,
-    NS_MAX_DEPTH_SCHEMA, PRIV_DATASTORE_AUDIT, PRIV_DATASTORE_BACKUP, PRIV_DATASTORE_MODIFY,
-    PRIV_DATASTORE_PRUNE, PRIV_DATASTORE_READ, PRIV_DATASTORE_VERIFY, UPID, UPID_SCHEMA,
-    VERIFICATION_OUTDATED_AFTER_SCHEMA,
+    BACKUP_TYPE_SCHEMA, DATASTORE_SCHEMA, GROUP_TEMPLATE_NAME_SCHEMA,
+    IGNORE_VERIFIED_BACKUPS_SCHEMA, MAX_NAMESPACE_DEPTH, NS_MAX_DEPTH_SCHEMA, PRIV_DATASTORE_AUDIT,
+    PRIV_DATASTORE_BACKUP, PRIV_DATASTORE_MODIFY, PRIV_DATASTORE_PRUNE, PRIV_DATASTORE_READ,
+    PRIV_DATASTORE_VERIFY, PRIV_SYS_MODIFY, UPID, UPID_SCHEMA, VERIFICATION_OUTDATED_AFTER_SCHEMA,
 };
 use pbs_client::pxar::{create_tar, create_zip};
 use pbs_config::CachedUserInfo;
@@ -59,19 +60,31 @@ use pbs_datastore::{
     check_backup_owner, task_tracking, BackupDir, BackupGroup, DataStore, LocalChunkReader,
     StoreProgress, CATALOG_NAME,
 };
-use pbs_tools::json::required_string_param;
+use pbs_tools::json::{required_integer_param, required_string_param};
 use proxmox_rest_server::{formatter, WorkerTask};
 
 use crate::api2::backup::optional_ns_param;
 use crate::api2::node::rrd::create_value_from_rrd;
 use crate::backup::{
-    check_ns_privs_full, verify_all_backups, verify_backup_dir, verify_backup_group, verify_filter,
+    check_ns_privs, check_ns_privs_full,
+    chunk_xref::{generate_xref_report, list_xref_reports, xref_report_path},
+    seed::{check_seed_dir, export_seed, import_seed, seed_history},
+    verify_all_backups, verify_backup_dir, verify_backup_group, verify_filter,
+    verify_report::generate_verify_report,
     ListAccessibleBackupGroups, NS_PRIVS_OK,
 };
 
-use crate::server::jobstate::{compute_schedule_status, Job, JobState};
+use crate::server::jobstate::{
+    compute_schedule_status, limit_job_history, read_single_job_history, Job, JobState,
+};
+use crate::server::restore_accounting::accounted_download;
+use crate::server::restore_link::{
+    claim_restore_link, consume_after_download, create_restore_link_ticket,
+    verify_restore_link_ticket, RestoreLinkScope, MAX_RESTORE_LINK_LIFETIME,
+};
 
 const GROUP_NOTES_FILE_NAME: &str = "notes";
+const GROUP_TEMPLATE_FILE_NAME: &str = "template.json";
 
 fn get_group_note_path(
     store: &DataStore,
@@ -108,6 +121,18 @@ fn check_privs_and_load_store(
     Ok(datastore)
 }
 
+// archived snapshots are only a stub, their files are read from the copy on the archive datastore
+fn resolve_archived_snapshot(
+    datastore: Arc<DataStore>,
+    backup_dir: BackupDir,
+    auth_id: &Authid,
+) -> Result<(Arc<DataStore>, BackupDir), Error> {
+    match crate::server::archive_job::lookup_archived_snapshot(&backup_dir, auth_id)? {
+        Some(archived) => Ok(archived),
+        None => Ok((datastore, backup_dir)),
+    }
+}
+
 fn read_backup_index(
     backup_dir: &BackupDir,
 ) -> Result<(BackupManifest, Vec<BackupContent>), Error> {
@@ -506,6 +531,7 @@ unsafe fn list_snapshots_blocking(
             time: info.backup_dir.backup_time(),
         };
         let protected = info.backup_dir.is_protected();
+        let retained_until = info.retained_until;
 
         match get_all_snapshot_files(&info) {
             Ok((manifest, files)) => {
@@ -534,6 +560,7 @@ unsafe fn list_snapshots_blocking(
                     };
 
                 let size = Some(files.iter().map(|x| x.size.unwrap_or(0)).sum());
+                let archived = manifest.archived_to().map(String::from);
 
                 SnapshotListItem {
                     backup,
@@ -544,6 +571,8 @@ unsafe fn list_snapshots_blocking(
                     size,
                     owner,
                     protected,
+                    retained_until,
+                    archived,
                 }
             }
             Err(err) => {
@@ -567,6 +596,8 @@ unsafe fn list_snapshots_blocking(
                     size: None,
                     owner,
                     protected,
+                    retained_until,
+                    archived: None,
                 }
             }
         }
@@ -996,6 +1027,8 @@ pub fn prune(
         backup_time: i64,
         keep: bool,
         protected: bool,
+        #[serde(rename = "retained-until", skip_serializing_if = "Option::is_none")]
+        retained_until: Option<i64>,
         #[serde(skip_serializing_if = "Option::is_none")]
         ns: Option<BackupNamespace>,
     }
@@ -1020,6 +1053,7 @@ pub fn prune(
                 backup_time: backup_dir.backup_time(),
                 keep,
                 protected: mark.protected(),
+                retained_until: info.retained_until,
                 ns: None,
             };
             let prune_ns = backup_dir.backup_ns();
@@ -1068,6 +1102,7 @@ pub fn prune(
                 backup_time,
                 keep,
                 protected: mark.protected(),
+                retained_until: info.retained_until,
                 ns: None,
             });
 
@@ -1211,6 +1246,261 @@ pub fn start_garbage_collection(
     Ok(json!(upid_str))
 }
 
+#[api(
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+        },
+    },
+    returns: {
+        schema: UPID_SCHEMA,
+    },
+    access: {
+        permission: &Permission::Privilege(&["datastore", "{store}"], PRIV_DATASTORE_MODIFY, false),
+    },
+)]
+/// Move old snapshots to the archive datastore.
+pub fn start_archive(
+    store: String,
+    _info: &ApiMethod,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Value, Error> {
+    let datastore = DataStore::lookup_datastore(&store, Some(Operation::Write))?;
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+
+    let job =
+        Job::new("archive", &store).map_err(|_| format_err!("archive job already running"))?;
+
+    let to_stdout = rpcenv.env_type() == RpcEnvironmentType::CLI;
+
+    let upid_str = crate::server::do_archive_job(job, datastore, &auth_id, None, to_stdout)
+        .map_err(|err| format_err!("unable to start archive job on datastore {store} - {err}"))?;
+
+    Ok(json!(upid_str))
+}
+
+#[api(
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+        },
+    },
+    returns: {
+        schema: UPID_SCHEMA,
+    },
+    access: {
+        permission: &Permission::Privilege(&["datastore", "{store}"], PRIV_DATASTORE_VERIFY, false),
+    },
+)]
+/// Check all chunks and generate a report mapping them to their referencing indexes.
+pub fn start_chunk_xref(
+    store: String,
+    _info: &ApiMethod,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Value, Error> {
+    let datastore = DataStore::lookup_datastore(&store, Some(Operation::Read))?;
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+
+    let to_stdout = rpcenv.env_type() == RpcEnvironmentType::CLI;
+
+    let upid_str = WorkerTask::new_thread(
+        "chunk-xref",
+        Some(store),
+        auth_id.to_string(),
+        to_stdout,
+        move |worker| {
+            let name = generate_xref_report(datastore, &*worker)?;
+            task_log!(worker, "stored report {name}");
+            Ok(())
+        },
+    )?;
+
+    Ok(json!(upid_str))
+}
+
+#[api(
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+        },
+    },
+    returns: {
+        description: "List of stored chunk cross-reference reports, oldest first.",
+        type: Array,
+        items: {
+            type: ChunkXrefReportInfo,
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&["datastore", "{store}"], PRIV_DATASTORE_AUDIT, false),
+    },
+)]
+/// List the stored chunk cross-reference reports.
+pub fn list_chunk_xref_reports(store: String) -> Result<Vec<ChunkXrefReportInfo>, Error> {
+    list_xref_reports(&store)
+}
+
+// seeds are read and written with the privileges of the daemon, so the path is restricted to
+// users which may manage the disks of the node anyway
+fn seed_path_param(path: String, auth_id: &Authid) -> Result<PathBuf, Error> {
+    let user_info = CachedUserInfo::new()?;
+    user_info.check_privs(auth_id, &["system", "disks"], PRIV_SYS_MODIFY, false)?;
+
+    let path = PathBuf::from(path);
+    if !path.is_absolute() {
+        param_bail!("path", "seed directory has to be an absolute path");
+    }
+    if let Err(err) = check_seed_dir(&path) {
+        param_bail!("path", err);
+    }
+    Ok(path)
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+            ns: {
+                type: BackupNamespace,
+                optional: true,
+            },
+            path: {
+                type: String,
+                description: "Directory to write the seed to, usually on a removable disk.",
+            },
+        },
+    },
+    returns: {
+        schema: UPID_SCHEMA,
+    },
+    access: {
+        description: "Requires Datastore.Modify on the datastore and Sys.Modify on '/system/disks'.",
+        permission: &Permission::Privilege(&["datastore", "{store}"], PRIV_DATASTORE_MODIFY, false),
+    },
+)]
+/// Export all snapshots in and below a namespace as seed for the initial sync of a remote site.
+pub fn start_seed_export(
+    store: String,
+    ns: Option<BackupNamespace>,
+    path: String,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Value, Error> {
+    let datastore = DataStore::lookup_datastore(&store, Some(Operation::Read))?;
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let path = seed_path_param(path, &auth_id)?;
+
+    let to_stdout = rpcenv.env_type() == RpcEnvironmentType::CLI;
+
+    let upid_str = WorkerTask::new_thread(
+        "seed-export",
+        Some(store),
+        auth_id.to_string(),
+        to_stdout,
+        move |worker| export_seed(datastore, ns.unwrap_or_default(), &path, &auth_id, &*worker),
+    )?;
+
+    Ok(json!(upid_str))
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+            ns: {
+                type: BackupNamespace,
+                optional: true,
+            },
+            path: {
+                type: String,
+                description: "Directory containing the seed.",
+            },
+            owner: {
+                type: Authid,
+                optional: true,
+                description: "Owner of the imported backup groups, should be the owner of the \
+                    sync job (default: the importing user).",
+            },
+        },
+    },
+    returns: {
+        schema: UPID_SCHEMA,
+    },
+    access: {
+        description: "Requires Datastore.Modify on the datastore and Sys.Modify on '/system/disks'.",
+        permission: &Permission::Privilege(&["datastore", "{store}"], PRIV_DATASTORE_MODIFY, false),
+    },
+)]
+/// Import a seed written by a seed export, skipping snapshots which already exist.
+pub fn start_seed_import(
+    store: String,
+    ns: Option<BackupNamespace>,
+    path: String,
+    owner: Option<Authid>,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Value, Error> {
+    let datastore = DataStore::lookup_datastore(&store, Some(Operation::Write))?;
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let path = seed_path_param(path, &auth_id)?;
+    let owner = owner.unwrap_or_else(|| auth_id.clone());
+
+    let to_stdout = rpcenv.env_type() == RpcEnvironmentType::CLI;
+
+    let upid_str = WorkerTask::new_thread(
+        "seed-import",
+        Some(store),
+        auth_id.to_string(),
+        to_stdout,
+        move |worker| {
+            import_seed(
+                datastore,
+                ns.unwrap_or_default(),
+                &path,
+                &owner,
+                &auth_id,
+                &*worker,
+            )
+        },
+    )?;
+
+    Ok(json!(upid_str))
+}
+
+#[api(
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+        },
+    },
+    returns: {
+        description: "Seed exports and imports of the datastore, oldest first.",
+        type: Array,
+        items: {
+            type: SeedHistoryEntry,
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&["datastore", "{store}"], PRIV_DATASTORE_AUDIT, false),
+    },
+)]
+/// List the seed exports and imports of a datastore.
+pub fn get_seed_history(store: String) -> Result<Vec<SeedHistoryEntry>, Error> {
+    seed_history(&store)
+}
+
 #[api(
     input: {
         properties: {
@@ -1396,7 +1686,9 @@ pub fn download_file(
             file_name
         );
 
+        let name = format!("{backup_dir}/{file_name}");
         let backup_dir = datastore.backup_dir(backup_ns, backup_dir)?;
+        let (datastore, backup_dir) = resolve_archived_snapshot(datastore, backup_dir, &auth_id)?;
 
         let mut path = datastore.base_path();
         path.push(backup_dir.relative_path());
@@ -1413,7 +1705,7 @@ pub fn download_file(
                     eprintln!("error during streaming of '{:?}' - {}", &path, err);
                     err
                 });
-        let body = Body::wrap_stream(payload);
+        let body = accounted_download(Body::wrap_stream(payload), auth_id, store, name);
 
         // fixme: set other headers ?
         Ok(Response::builder()
@@ -1426,17 +1718,124 @@ pub fn download_file(
 }
 
 #[sortable]
-pub const API_METHOD_DOWNLOAD_FILE_DECODED: ApiMethod = ApiMethod::new(
-    &ApiHandler::AsyncHttp(&download_file_decoded),
+pub const API_METHOD_DOWNLOAD_CHUNK_XREF_REPORT: ApiMethod = ApiMethod::new(
+    &ApiHandler::AsyncHttp(&download_chunk_xref_report),
     &ObjectSchema::new(
-        "Download single decoded file from backup snapshot. Only works if it's not encrypted.",
+        "Download a zstd compressed chunk cross-reference report.",
+        &sorted!([
+            ("store", false, &DATASTORE_SCHEMA),
+            (
+                "name",
+                false,
+                &StringSchema::new("File name of the report.")
+                    .max_length(64)
+                    .schema()
+            ),
+        ]),
+    ),
+)
+.access(
+    None,
+    &Permission::Privilege(&["datastore", "{store}"], PRIV_DATASTORE_AUDIT, false),
+);
+
+pub fn download_chunk_xref_report(
+    _parts: Parts,
+    _req_body: Body,
+    param: Value,
+    _info: &ApiMethod,
+    _rpcenv: Box<dyn RpcEnvironment>,
+) -> ApiResponseFuture {
+    async move {
+        let store = required_string_param(&param, "store")?;
+        let name = required_string_param(&param, "name")?;
+
+        let path = xref_report_path(store, name)?;
+
+        let file = tokio::fs::File::open(&path)
+            .await
+            .map_err(|err| http_err!(BAD_REQUEST, "File open failed: {}", err))?;
+
+        let payload =
+            tokio_util::codec::FramedRead::new(file, tokio_util::codec::BytesCodec::new())
+                .map_ok(|bytes| bytes.freeze());
+        let body = Body::wrap_stream(payload);
+
+        Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "application/zstd")
+            .body(body)
+            .unwrap())
+    }
+    .boxed()
+}
+
+/// Blocking writer sending its data through a channel, used to stream snapshot exports
+struct BlockingChannelWriter {
+    sender: tokio::sync::mpsc::Sender<Result<hyper::body::Bytes, Error>>,
+    buffer: Vec<u8>,
+}
+
+impl BlockingChannelWriter {
+    const BUFFER_SIZE: usize = 1024 * 1024;
+
+    fn send_buffer(&mut self) -> std::io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let data = std::mem::replace(&mut self.buffer, Vec::with_capacity(Self::BUFFER_SIZE));
+        self.sender
+            .blocking_send(Ok(data.into()))
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::BrokenPipe))
+    }
+}
+
+impl std::io::Write for BlockingChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= Self::BUFFER_SIZE {
+            self.send_buffer()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.send_buffer()
+    }
+}
+
+/// Blocking reader receiving its data through a channel, used to import snapshot exports
+struct BlockingChannelReader {
+    receiver: tokio::sync::mpsc::Receiver<Result<hyper::body::Bytes, Error>>,
+    buffer: hyper::body::Bytes,
+}
+
+impl std::io::Read for BlockingChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.buffer.is_empty() {
+            match self.receiver.blocking_recv() {
+                Some(Ok(data)) => self.buffer = data,
+                Some(Err(err)) => return Err(std::io::Error::new(std::io::ErrorKind::Other, err)),
+                None => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.buffer.len());
+        buf[..len].copy_from_slice(&self.buffer.split_to(len));
+        Ok(len)
+    }
+}
+
+#[sortable]
+pub const API_METHOD_EXPORT_SNAPSHOT: ApiMethod = ApiMethod::new(
+    &ApiHandler::AsyncHttp(&export_snapshot),
+    &ObjectSchema::new(
+        "Download a snapshot including all referenced chunks as snapshot export archive.",
         &sorted!([
             ("store", false, &DATASTORE_SCHEMA),
             ("ns", true, &BACKUP_NAMESPACE_SCHEMA),
             ("backup-type", false, &BACKUP_TYPE_SCHEMA),
             ("backup-id", false, &BACKUP_ID_SCHEMA),
             ("backup-time", false, &BACKUP_TIME_SCHEMA),
-            ("file-name", false, &BACKUP_ARCHIVE_NAME_SCHEMA),
         ]),
     ),
 )
@@ -1448,7 +1847,7 @@ pub const API_METHOD_DOWNLOAD_FILE_DECODED: ApiMethod = ApiMethod::new(
     &Permission::Anybody,
 );
 
-pub fn download_file_decoded(
+pub fn export_snapshot(
     _parts: Parts,
     _req_body: Body,
     param: Value,
@@ -1460,7 +1859,153 @@ pub fn download_file_decoded(
         let store = required_string_param(&param, "store")?;
         let backup_ns = optional_ns_param(&param)?;
 
-        let backup_dir_api: pbs_api_types::BackupDir = Deserialize::deserialize(&param)?;
+        let backup_dir: pbs_api_types::BackupDir = Deserialize::deserialize(&param)?;
+        let datastore = check_privs_and_load_store(
+            store,
+            &backup_ns,
+            &auth_id,
+            PRIV_DATASTORE_READ,
+            PRIV_DATASTORE_BACKUP,
+            Some(Operation::Read),
+            &backup_dir.group,
+        )?;
+
+        println!(
+            "Export {} from {}",
+            backup_dir,
+            print_store_and_ns(store, &backup_ns),
+        );
+
+        let name = backup_dir.to_string();
+        let (sender, receiver) = tokio::sync::mpsc::channel(16);
+        let writer = BlockingChannelWriter {
+            sender: sender.clone(),
+            buffer: Vec::with_capacity(BlockingChannelWriter::BUFFER_SIZE),
+        };
+
+        tokio::task::spawn_blocking(move || {
+            if let Err(err) = pbs_datastore::snapshot_export::export_snapshot(
+                datastore, backup_ns, backup_dir, writer,
+            ) {
+                eprintln!("snapshot export failed - {err}");
+                let _ = sender.blocking_send(Err(err));
+            }
+        });
+
+        let body = Body::wrap_stream(ReceiverStream::new(receiver));
+        let body = accounted_download(body, auth_id, store, name);
+
+        Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "application/octet-stream")
+            .body(body)
+            .unwrap())
+    }
+    .boxed()
+}
+
+#[sortable]
+pub const API_METHOD_IMPORT_SNAPSHOT: ApiMethod = ApiMethod::new(
+    &ApiHandler::AsyncHttp(&import_snapshot),
+    &ObjectSchema::new(
+        "Upload a snapshot export archive and import the contained snapshot.",
+        &sorted!([
+            ("store", false, &DATASTORE_SCHEMA),
+            ("ns", true, &BACKUP_NAMESPACE_SCHEMA),
+        ]),
+    ),
+)
+.access(
+    Some(
+        "Requires on /datastore/{store}[/{namespace}] DATASTORE_BACKUP. The group of the \
+        imported snapshot must not exist yet or be owned by the importing user.",
+    ),
+    &Permission::Anybody,
+);
+
+pub fn import_snapshot(
+    _parts: Parts,
+    mut req_body: Body,
+    param: Value,
+    _info: &ApiMethod,
+    rpcenv: Box<dyn RpcEnvironment>,
+) -> ApiResponseFuture {
+    async move {
+        let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+        let store = required_string_param(&param, "store")?;
+        let backup_ns = optional_ns_param(&param)?;
+
+        check_ns_privs(store, &backup_ns, &auth_id, PRIV_DATASTORE_BACKUP)?;
+
+        let datastore = DataStore::lookup_datastore(store, Some(Operation::Write))?;
+
+        let (sender, receiver) = tokio::sync::mpsc::channel(16);
+        let reader = BlockingChannelReader {
+            receiver,
+            buffer: hyper::body::Bytes::new(),
+        };
+
+        let ns = backup_ns.clone();
+        let import = tokio::task::spawn_blocking(move || {
+            pbs_datastore::snapshot_export::import_snapshot(&datastore, &ns, &auth_id, reader)
+        });
+
+        while let Some(data) = req_body.next().await {
+            if sender.send(data.map_err(Error::from)).await.is_err() {
+                break; // import failed early, the error is reported below
+            }
+        }
+        drop(sender);
+
+        let snapshot = import.await??;
+
+        println!(
+            "Imported {} into {}",
+            snapshot,
+            print_store_and_ns(store, &backup_ns),
+        );
+
+        Ok(formatter::JSON_FORMATTER.format_data(json!(snapshot), &*rpcenv))
+    }
+    .boxed()
+}
+
+#[sortable]
+pub const API_METHOD_DOWNLOAD_FILE_DECODED: ApiMethod = ApiMethod::new(
+    &ApiHandler::AsyncHttp(&download_file_decoded),
+    &ObjectSchema::new(
+        "Download single decoded file from backup snapshot. Only works if it's not encrypted.",
+        &sorted!([
+            ("store", false, &DATASTORE_SCHEMA),
+            ("ns", true, &BACKUP_NAMESPACE_SCHEMA),
+            ("backup-type", false, &BACKUP_TYPE_SCHEMA),
+            ("backup-id", false, &BACKUP_ID_SCHEMA),
+            ("backup-time", false, &BACKUP_TIME_SCHEMA),
+            ("file-name", false, &BACKUP_ARCHIVE_NAME_SCHEMA),
+        ]),
+    ),
+)
+.access(
+    Some(
+        "Requires on /datastore/{store}[/{namespace}] either DATASTORE_READ for any or \
+        DATASTORE_BACKUP and being the owner of the group",
+    ),
+    &Permission::Anybody,
+);
+
+pub fn download_file_decoded(
+    _parts: Parts,
+    _req_body: Body,
+    param: Value,
+    _info: &ApiMethod,
+    rpcenv: Box<dyn RpcEnvironment>,
+) -> ApiResponseFuture {
+    async move {
+        let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+        let store = required_string_param(&param, "store")?;
+        let backup_ns = optional_ns_param(&param)?;
+
+        let backup_dir_api: pbs_api_types::BackupDir = Deserialize::deserialize(&param)?;
         let datastore = check_privs_and_load_store(
             store,
             &backup_ns,
@@ -1473,6 +2018,7 @@ pub fn download_file_decoded(
 
         let file_name = required_string_param(&param, "file-name")?.to_owned();
         let backup_dir = datastore.backup_dir(backup_ns.clone(), backup_dir_api.clone())?;
+        let (datastore, backup_dir) = resolve_archived_snapshot(datastore, backup_dir, &auth_id)?;
 
         let (manifest, files) = read_backup_index(&backup_dir)?;
         for file in files {
@@ -1548,6 +2094,12 @@ pub fn download_file_decoded(
                 bail!("cannot download '{}' files", extension);
             }
         };
+        let body = accounted_download(
+            body,
+            auth_id,
+            store,
+            format!("{backup_dir_api}/{file_name}"),
+        );
 
         // fixme: set other headers ?
         Ok(Response::builder()
@@ -1684,6 +2236,7 @@ pub async fn catalog(
         )?;
 
         let backup_dir = datastore.backup_dir(ns, backup_dir)?;
+        let (datastore, backup_dir) = resolve_archived_snapshot(datastore, backup_dir, &auth_id)?;
 
         let file_name = CATALOG_NAME;
 
@@ -1790,81 +2343,333 @@ pub fn pxar_file_download(
         )?;
 
         let backup_dir = datastore.backup_dir(ns, backup_dir)?;
+        let (datastore, backup_dir) = resolve_archived_snapshot(datastore, backup_dir, &auth_id)?;
 
         let filepath = required_string_param(&param, "filepath")?.to_owned();
 
         let tar = param["tar"].as_bool().unwrap_or(false);
 
-        let mut components = base64::decode(&filepath)?;
-        if !components.is_empty() && components[0] == b'/' {
-            components.remove(0);
-        }
+        let pxar_file = open_pxar_file(&datastore, &backup_dir, &filepath).await?;
+        let name = pxar_file.name.clone();
+        let body = pxar_file_body(pxar_file, tar).await?;
+        let body = accounted_download(body, auth_id, store, name);
 
-        let mut split = components.splitn(2, |c| *c == b'/');
-        let pxar_name = std::str::from_utf8(split.next().unwrap())?;
-        let file_path = split.next().unwrap_or(b"/");
-        let (manifest, files) = read_backup_index(&backup_dir)?;
-        for file in files {
-            if file.filename == pxar_name && file.crypt_mode == Some(CryptMode::Encrypt) {
-                bail!("cannot decode '{}' - is encrypted", pxar_name);
-            }
+        // fixme: set other headers ?
+        Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "application/octet-stream")
+            .body(body)
+            .unwrap())
+    }
+    .boxed()
+}
+
+type LocalPxarReader = LocalDynamicReadAt<LocalChunkReader>;
+
+/// An entry of a pxar archive in a local snapshot, to be downloaded.
+struct PxarFile {
+    decoder: Accessor<LocalPxarReader>,
+    file: FileEntry<LocalPxarReader>,
+    /// Path of the entry inside the archive
+    path: OsString,
+    /// Name of the download for restore accounting
+    name: String,
+}
+
+/// Look up the entry referenced by a base64 encoded `filepath` of the form `archive.pxar/path`.
+async fn open_pxar_file(
+    datastore: &Arc<DataStore>,
+    backup_dir: &BackupDir,
+    filepath: &str,
+) -> Result<PxarFile, Error> {
+    let mut components = base64::decode(filepath)?;
+    if !components.is_empty() && components[0] == b'/' {
+        components.remove(0);
+    }
+
+    let mut split = components.splitn(2, |c| *c == b'/');
+    let pxar_name = std::str::from_utf8(split.next().unwrap())?;
+    let file_path = split.next().unwrap_or(b"/");
+    let (manifest, files) = read_backup_index(backup_dir)?;
+    for file in files {
+        if file.filename == pxar_name && file.crypt_mode == Some(CryptMode::Encrypt) {
+            bail!("cannot decode '{}' - is encrypted", pxar_name);
         }
+    }
+
+    let name = format!(
+        "{}/{pxar_name}/{}",
+        backup_dir.dir(),
+        String::from_utf8_lossy(file_path).trim_start_matches('/'),
+    );
+
+    let (reader, archive_size) =
+        get_local_pxar_reader(datastore.clone(), &manifest, backup_dir, pxar_name)?;
+
+    let decoder = Accessor::new(reader, archive_size).await?;
+    let root = decoder.open_root().await?;
+    let path = OsStr::from_bytes(file_path).to_os_string();
+    let file = root
+        .lookup(&path)
+        .await?
+        .ok_or_else(|| format_err!("error opening '{:?}'", path))?;
+
+    Ok(PxarFile {
+        decoder,
+        file,
+        path,
+        name,
+    })
+}
 
-        let (reader, archive_size) =
-            get_local_pxar_reader(datastore.clone(), &manifest, &backup_dir, pxar_name)?;
-
-        let decoder = Accessor::new(reader, archive_size).await?;
-        let root = decoder.open_root().await?;
-        let path = OsStr::from_bytes(file_path).to_os_string();
-        let file = root
-            .lookup(&path)
-            .await?
-            .ok_or_else(|| format_err!("error opening '{:?}'", path))?;
-
-        let body = match file.kind() {
-            EntryKind::File { .. } => Body::wrap_stream(
-                AsyncReaderStream::new(file.contents().await?).map_err(move |err| {
-                    eprintln!("error during streaming of file '{:?}' - {}", filepath, err);
+/// Stream the contents of a pxar entry, directories as zip or, if `tar` is set, as .tar.zst.
+async fn pxar_file_body(pxar_file: PxarFile, tar: bool) -> Result<Body, Error> {
+    let PxarFile {
+        decoder,
+        file,
+        path,
+        ..
+    } = pxar_file;
+
+    let body = match file.kind() {
+        EntryKind::File { .. } => Body::wrap_stream(
+            AsyncReaderStream::new(file.contents().await?).map_err(move |err| {
+                eprintln!("error during streaming of file '{:?}' - {}", path, err);
+                err
+            }),
+        ),
+        EntryKind::Hardlink(_) => Body::wrap_stream(
+            AsyncReaderStream::new(decoder.follow_hardlink(&file).await?.contents().await?)
+                .map_err(move |err| {
+                    eprintln!("error during streaming of hardlink '{:?}' - {}", path, err);
                     err
                 }),
+        ),
+        EntryKind::Directory => {
+            let (sender, receiver) = tokio::sync::mpsc::channel::<Result<_, Error>>(100);
+            let channelwriter = AsyncChannelWriter::new(sender, 1024 * 1024);
+            if tar {
+                proxmox_rest_server::spawn_internal_task(create_tar(
+                    channelwriter,
+                    decoder,
+                    path.clone(),
+                ));
+                let zstdstream = ZstdEncoder::new(ReceiverStream::new(receiver))?;
+                Body::wrap_stream(zstdstream.map_err(move |err| {
+                    log::error!("error during streaming of tar.zst '{:?}' - {}", path, err);
+                    err
+                }))
+            } else {
+                proxmox_rest_server::spawn_internal_task(create_zip(
+                    channelwriter,
+                    decoder,
+                    path.clone(),
+                ));
+                Body::wrap_stream(ReceiverStream::new(receiver).map_err(move |err| {
+                    log::error!("error during streaming of zip '{:?}' - {}", path, err);
+                    err
+                }))
+            }
+        }
+        other => bail!("cannot download file of type {:?}", other),
+    };
+    Ok(body)
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            store: { schema: DATASTORE_SCHEMA },
+            ns: {
+                type: BackupNamespace,
+                optional: true,
+            },
+            backup_dir: {
+                type: pbs_api_types::BackupDir,
+                flatten: true,
+            },
+            filepath: {
+                description: "Base64 encoded path",
+                type: String,
+            },
+            "expire-in": {
+                description: "Lifetime of the link in seconds.",
+                type: Integer,
+                minimum: 60,
+                maximum: MAX_RESTORE_LINK_LIFETIME,
+                default: 86400,
+                optional: true,
+            },
+            "one-time": {
+                description: "The link can only be used for a single download.",
+                type: bool,
+                default: true,
+                optional: true,
+            },
+        },
+    },
+    returns: {
+        type: RestoreLink,
+    },
+    access: {
+        permission: &Permission::Anybody,
+        description: "Requires on /datastore/{store}[/{namespace}] either DATASTORE_READ for any \
+            or DATASTORE_BACKUP and being the owner of the group",
+    },
+)]
+/// Create an expiring link for downloading a file or directory of a snapshot without logging in.
+pub fn create_restore_link(
+    store: String,
+    ns: Option<BackupNamespace>,
+    backup_dir: pbs_api_types::BackupDir,
+    filepath: String,
+    expire_in: Option<i64>,
+    one_time: Option<bool>,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<RestoreLink, Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let ns = ns.unwrap_or_default();
+
+    let datastore = check_privs_and_load_store(
+        &store,
+        &ns,
+        &auth_id,
+        PRIV_DATASTORE_READ,
+        PRIV_DATASTORE_BACKUP,
+        Some(Operation::Read),
+        &backup_dir.group,
+    )?;
+
+    // fail early for snapshots which do not exist
+    datastore
+        .backup_dir(ns.clone(), backup_dir.clone())?
+        .load_manifest()?;
+
+    let expire = proxmox_time::epoch_i64() + expire_in.unwrap_or(86400);
+    let one_time = one_time.unwrap_or(true);
+    let scope = RestoreLinkScope {
+        store: &store,
+        ns: &ns,
+        dir: &backup_dir,
+        filepath: &filepath,
+        expire,
+        one_time,
+    };
+    let ticket = create_restore_link_ticket(&auth_id, &scope)?;
+
+    let mut query = url::form_urlencoded::Serializer::new(String::new());
+    if !ns.is_root() {
+        query.append_pair("ns", &ns.to_string());
+    }
+    query
+        .append_pair("backup-type", &backup_dir.group.ty.to_string())
+        .append_pair("backup-id", &backup_dir.group.id)
+        .append_pair("backup-time", &backup_dir.time.to_string())
+        .append_pair("filepath", &filepath)
+        .append_pair("expire", &expire.to_string())
+        .append_pair("one-time", if one_time { "1" } else { "0" })
+        .append_pair("ticket", &ticket);
+
+    Ok(RestoreLink {
+        url: format!(
+            "/api2/json/admin/datastore/{store}/restore-link-download?{}",
+            query.finish()
+        ),
+        expire,
+        one_time,
+    })
+}
+
+#[sortable]
+pub const API_METHOD_RESTORE_LINK_DOWNLOAD: ApiMethod = ApiMethod::new(
+    &ApiHandler::AsyncHttp(&restore_link_download),
+    &ObjectSchema::new(
+        "Download a single file or directory (as zip) of a backup snapshot via a restore link.",
+        &sorted!([
+            ("store", false, &DATASTORE_SCHEMA),
+            ("ns", true, &BACKUP_NAMESPACE_SCHEMA),
+            ("backup-type", false, &BACKUP_TYPE_SCHEMA),
+            ("backup-id", false, &BACKUP_ID_SCHEMA),
+            ("backup-time", false, &BACKUP_TIME_SCHEMA),
+            (
+                "filepath",
+                false,
+                &StringSchema::new("Base64 encoded path").schema()
             ),
-            EntryKind::Hardlink(_) => Body::wrap_stream(
-                AsyncReaderStream::new(decoder.follow_hardlink(&file).await?.contents().await?)
-                    .map_err(move |err| {
-                        eprintln!("error during streaming of hardlink '{:?}' - {}", path, err);
-                        err
-                    }),
+            (
+                "expire",
+                false,
+                &IntegerSchema::new("Expiry of the link (epoch)").schema()
             ),
-            EntryKind::Directory => {
-                let (sender, receiver) = tokio::sync::mpsc::channel::<Result<_, Error>>(100);
-                let channelwriter = AsyncChannelWriter::new(sender, 1024 * 1024);
-                if tar {
-                    proxmox_rest_server::spawn_internal_task(create_tar(
-                        channelwriter,
-                        decoder,
-                        path.clone(),
-                    ));
-                    let zstdstream = ZstdEncoder::new(ReceiverStream::new(receiver))?;
-                    Body::wrap_stream(zstdstream.map_err(move |err| {
-                        log::error!("error during streaming of tar.zst '{:?}' - {}", path, err);
-                        err
-                    }))
-                } else {
-                    proxmox_rest_server::spawn_internal_task(create_zip(
-                        channelwriter,
-                        decoder,
-                        path.clone(),
-                    ));
-                    Body::wrap_stream(ReceiverStream::new(receiver).map_err(move |err| {
-                        log::error!("error during streaming of zip '{:?}' - {}", path, err);
-                        err
-                    }))
-                }
-            }
-            other => bail!("cannot download file of type {:?}", other),
+            (
+                "one-time",
+                false,
+                &BooleanSchema::new("The link can only be used once").schema()
+            ),
+            (
+                "ticket",
+                false,
+                &StringSchema::new("Restore link ticket").schema()
+            ),
+        ]),
+    ),
+)
+.access(
+    Some(
+        "Anybody with a valid restore link. The creator of the link still needs the privileges \
+        to download the file.",
+    ),
+    &Permission::World,
+);
+
+pub fn restore_link_download(
+    _parts: Parts,
+    _req_body: Body,
+    param: Value,
+    _info: &ApiMethod,
+    _rpcenv: Box<dyn RpcEnvironment>,
+) -> ApiResponseFuture {
+    async move {
+        let store = required_string_param(&param, "store")?;
+        let ns = optional_ns_param(&param)?;
+        let backup_dir: pbs_api_types::BackupDir = Deserialize::deserialize(&param)?;
+        let filepath = required_string_param(&param, "filepath")?;
+        let ticket = required_string_param(&param, "ticket")?;
+
+        let scope = RestoreLinkScope {
+            store,
+            ns: &ns,
+            dir: &backup_dir,
+            filepath,
+            expire: required_integer_param(&param, "expire")?,
+            one_time: param["one-time"].as_bool().unwrap_or(true),
         };
+        let auth_id = verify_restore_link_ticket(ticket, &scope)
+            .map_err(|err| http_err!(UNAUTHORIZED, "invalid restore link - {err}"))?;
+
+        let datastore = check_privs_and_load_store(
+            store,
+            &ns,
+            &auth_id,
+            PRIV_DATASTORE_READ,
+            PRIV_DATASTORE_BACKUP,
+            Some(Operation::Read),
+            &backup_dir.group,
+        )?;
+
+        let snapshot = datastore.backup_dir(ns.clone(), backup_dir.clone())?;
+        let (datastore, snapshot) = resolve_archived_snapshot(datastore, snapshot, &auth_id)?;
+        let pxar_file = open_pxar_file(&datastore, &snapshot, filepath).await?;
+
+        let claim = claim_restore_link(ticket, &scope)
+            .map_err(|err| http_err!(UNAUTHORIZED, "invalid restore link - {err}"))?;
+
+        let name = pxar_file.name.clone();
+        let body = pxar_file_body(pxar_file, false).await?;
+        let body = accounted_download(body, auth_id, store, name);
+        let body = consume_after_download(body, claim);
 
-        // fixme: set other headers ?
         Ok(Response::builder()
             .status(StatusCode::OK)
             .header(header::CONTENT_TYPE, "application/octet-stream")
@@ -1922,6 +2727,97 @@ pub fn get_rrd_stats(
     create_value_from_rrd(&format!("datastore/{}", store), &rrd_fields, timeframe, cf)
 }
 
+#[api(
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+            timeframe: {
+                type: RRDTimeFrame,
+                optional: true,
+            },
+        },
+    },
+    returns: {
+        type: DataStoreUsageTrend,
+    },
+    access: {
+        permission: &Permission::Privilege(
+            &["datastore", "{store}"], PRIV_DATASTORE_AUDIT | PRIV_DATASTORE_BACKUP, true),
+    },
+)]
+/// Project the usage of the datastore from its usage history, by default over the last month.
+pub fn get_usage_trend(
+    store: String,
+    timeframe: Option<RRDTimeFrame>,
+) -> Result<DataStoreUsageTrend, Error> {
+    let history =
+        crate::api2::status::usage_history(&store, timeframe.unwrap_or(RRDTimeFrame::Month))?;
+
+    history
+        .and_then(|history| crate::api2::status::usage_trend(&history, proxmox_time::epoch_i64()))
+        .ok_or_else(|| format_err!("not enough usage data for datastore '{store}' yet"))
+}
+
+#[api(
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+            since: {
+                type: Integer,
+                description: "Only list runs started since this UNIX epoch.",
+                optional: true,
+            },
+            limit: {
+                type: Integer,
+                description: "Only list the most recent runs, 0 means no limit.",
+                optional: true,
+                minimum: 0,
+                default: 0,
+            },
+        },
+    },
+    returns: {
+        description: "Garbage collection and verification runs, oldest first.",
+        type: Array,
+        items: { type: JobHistoryEntry },
+    },
+    access: {
+        permission: &Permission::Privilege(&["datastore", "{store}"], PRIV_DATASTORE_AUDIT, true),
+    },
+)]
+/// List the statistics of the recorded garbage collection and verification job runs of the
+/// datastore.
+pub fn get_stats_history(
+    store: String,
+    since: Option<i64>,
+    limit: u64,
+) -> Result<Vec<JobHistoryEntry>, Error> {
+    let mut list = read_single_job_history("garbage_collection", &store)?;
+
+    let (config, _digest) = pbs_config::verify::config()?;
+    let jobs: Vec<VerificationJobConfig> = config.convert_to_typed_array("verification")?;
+    for job in jobs {
+        if job.store != store {
+            continue;
+        }
+        // a job's datastore can be changed, so check each run
+        list.extend(
+            read_single_job_history("verificationjob", &job.id)?
+                .into_iter()
+                .filter(|entry| entry.store.as_deref() == Some(store.as_str())),
+        );
+    }
+
+    list.sort_unstable_by_key(|entry| entry.starttime);
+    limit_job_history(&mut list, since, limit as usize);
+
+    Ok(list)
+}
+
 #[api(
     input: {
         properties: {
@@ -1943,6 +2839,62 @@ pub fn get_active_operations(store: String, _param: Value) -> Result<Value, Erro
     }))
 }
 
+#[api(
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+            ns: {
+                type: BackupNamespace,
+                optional: true,
+            },
+            "max-depth": {
+                schema: NS_MAX_DEPTH_SCHEMA,
+                optional: true,
+            },
+        },
+    },
+    returns: {
+        type: VerifyReport,
+    },
+    access: {
+        permission: &Permission::Anybody,
+        description: "Requires on /datastore/{store}[/{namespace}] either DATASTORE_AUDIT, \
+            DATASTORE_READ, or DATASTORE_BACKUP and being the owner of the group",
+    },
+)]
+/// Get a signed report of the verification state of all snapshots.
+///
+/// Other servers holding a copy of the datastore can use this to skip verifying snapshots this
+/// server already verified successfully.
+pub fn get_verify_report(
+    store: String,
+    ns: Option<BackupNamespace>,
+    max_depth: Option<usize>,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<VerifyReport, Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let ns = ns.unwrap_or_default();
+
+    check_ns_privs_full(
+        &store,
+        &ns,
+        &auth_id,
+        PRIV_DATASTORE_AUDIT | PRIV_DATASTORE_READ,
+        PRIV_DATASTORE_BACKUP,
+    )?;
+
+    let datastore = DataStore::lookup_datastore(&store, Some(Operation::Read))?;
+
+    generate_verify_report(
+        &datastore,
+        ns,
+        max_depth.unwrap_or(MAX_NAMESPACE_DEPTH),
+        &auth_id,
+    )
+}
+
 #[api(
     input: {
         properties: {
@@ -2037,6 +2989,139 @@ pub fn set_group_notes(
     Ok(())
 }
 
+#[api(
+    input: {
+        properties: {
+            store: { schema: DATASTORE_SCHEMA },
+            ns: {
+                type: BackupNamespace,
+                optional: true,
+            },
+            backup_group: {
+                type: pbs_api_types::BackupGroup,
+                flatten: true,
+            },
+        },
+    },
+    returns: {
+        type: GroupTemplate,
+    },
+    access: {
+        permission: &Permission::Anybody,
+        description: "Requires on /datastore/{store}[/{namespace}] either DATASTORE_AUDIT for any \
+            or DATASTORE_BACKUP and being the owner of the group",
+    },
+)]
+/// Get the template a backup group was created from, with the settings applied.
+pub fn get_group_template(
+    store: String,
+    ns: Option<BackupNamespace>,
+    backup_group: pbs_api_types::BackupGroup,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<GroupTemplate, Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let ns = ns.unwrap_or_default();
+
+    let datastore = check_privs_and_load_store(
+        &store,
+        &ns,
+        &auth_id,
+        PRIV_DATASTORE_AUDIT,
+        PRIV_DATASTORE_BACKUP,
+        Some(Operation::Read),
+        &backup_group,
+    )?;
+
+    let mut path = datastore.group_path(&ns, &backup_group);
+    path.push(GROUP_TEMPLATE_FILE_NAME);
+    match file_read_optional_string(path)? {
+        Some(data) => Ok(serde_json::from_str(&data)?),
+        None => http_bail!(NOT_FOUND, "backup group was not created from a template"),
+    }
+}
+
+#[api(
+    input: {
+        properties: {
+            store: { schema: DATASTORE_SCHEMA },
+            ns: {
+                type: BackupNamespace,
+                optional: true,
+            },
+            backup_group: {
+                type: pbs_api_types::BackupGroup,
+                flatten: true,
+            },
+            template: {
+                schema: GROUP_TEMPLATE_NAME_SCHEMA,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Anybody,
+        description: "Requires on /datastore/{store}[/{namespace}] either DATASTORE_MODIFY, \
+            or DATASTORE_BACKUP if the template does not set an owner other than the user",
+    },
+)]
+/// Create a backup group before its first backup, with the owner, notes, tags and retention
+/// hints of a template from the datastore config.
+pub fn create_group_from_template(
+    store: String,
+    ns: Option<BackupNamespace>,
+    backup_group: pbs_api_types::BackupGroup,
+    template: String,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<(), Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let ns = ns.unwrap_or_default();
+
+    let limited = check_ns_privs_full(
+        &store,
+        &ns,
+        &auth_id,
+        PRIV_DATASTORE_MODIFY,
+        PRIV_DATASTORE_BACKUP,
+    )?;
+
+    let (config, _digest) = pbs_config::datastore::config()?;
+    let store_config: DataStoreConfig = config.lookup("datastore", &store)?;
+    let template = store_config.lookup_group_template(&template)?;
+
+    let owner = template.owner.clone().unwrap_or_else(|| auth_id.clone());
+    if limited {
+        check_backup_owner(&owner, &auth_id)?;
+    }
+
+    let datastore = DataStore::lookup_datastore(&store, Some(Operation::Write))?;
+    if !datastore.namespace_exists(&ns) {
+        bail!("namespace '{ns}' does not exist");
+    }
+    if datastore
+        .backup_group(ns.clone(), backup_group.clone())
+        .exists()
+    {
+        bail!("backup group '{backup_group}' already exists");
+    }
+
+    let (_owner, _guard) = datastore.create_locked_backup_group(&ns, &backup_group, &owner)?;
+
+    if let Some(notes) = &template.notes {
+        let note_path = get_group_note_path(&datastore, &ns, &backup_group);
+        replace_file(note_path, notes.as_bytes(), CreateOptions::new(), false)?;
+    }
+
+    let mut path = datastore.group_path(&ns, &backup_group);
+    path.push(GROUP_TEMPLATE_FILE_NAME);
+    replace_file(
+        path,
+        &serde_json::to_vec(&template)?,
+        CreateOptions::new(),
+        false,
+    )?;
+
+    Ok(())
+}
+
 #[api(
     input: {
         properties: {
@@ -2346,11 +3431,22 @@ const DATASTORE_INFO_SUBDIRS: SubdirMap = &[
         "active-operations",
         &Router::new().get(&API_METHOD_GET_ACTIVE_OPERATIONS),
     ),
+    ("archive", &Router::new().post(&API_METHOD_START_ARCHIVE)),
     ("catalog", &Router::new().get(&API_METHOD_CATALOG)),
     (
         "change-owner",
         &Router::new().post(&API_METHOD_SET_BACKUP_OWNER),
     ),
+    (
+        "chunk-xref",
+        &Router::new()
+            .get(&API_METHOD_LIST_CHUNK_XREF_REPORTS)
+            .post(&API_METHOD_START_CHUNK_XREF),
+    ),
+    (
+        "chunk-xref-download",
+        &Router::new().download(&API_METHOD_DOWNLOAD_CHUNK_XREF_REPORT),
+    ),
     (
         "download",
         &Router::new().download(&API_METHOD_DOWNLOAD_FILE),
@@ -2372,6 +3468,12 @@ const DATASTORE_INFO_SUBDIRS: SubdirMap = &[
             .get(&API_METHOD_GET_GROUP_NOTES)
             .put(&API_METHOD_SET_GROUP_NOTES),
     ),
+    (
+        "group-template",
+        &Router::new()
+            .get(&API_METHOD_GET_GROUP_TEMPLATE)
+            .post(&API_METHOD_CREATE_GROUP_FROM_TEMPLATE),
+    ),
     (
         "groups",
         &Router::new()
@@ -2404,7 +3506,35 @@ const DATASTORE_INFO_SUBDIRS: SubdirMap = &[
         "pxar-file-download",
         &Router::new().download(&API_METHOD_PXAR_FILE_DOWNLOAD),
     ),
+    (
+        "restore-link",
+        &Router::new().post(&API_METHOD_CREATE_RESTORE_LINK),
+    ),
+    (
+        "restore-link-download",
+        &Router::new().download(&API_METHOD_RESTORE_LINK_DOWNLOAD),
+    ),
     ("rrd", &Router::new().get(&API_METHOD_GET_RRD_STATS)),
+    (
+        "seed-export",
+        &Router::new().post(&API_METHOD_START_SEED_EXPORT),
+    ),
+    (
+        "seed-history",
+        &Router::new().get(&API_METHOD_GET_SEED_HISTORY),
+    ),
+    (
+        "seed-import",
+        &Router::new().post(&API_METHOD_START_SEED_IMPORT),
+    ),
+    (
+        "snapshot-export",
+        &Router::new().download(&API_METHOD_EXPORT_SNAPSHOT),
+    ),
+    (
+        "snapshot-import",
+        &Router::new().upload(&API_METHOD_IMPORT_SNAPSHOT),
+    ),
     (
         "snapshots",
         &Router::new()
@@ -2416,7 +3546,20 @@ const DATASTORE_INFO_SUBDIRS: SubdirMap = &[
         "upload-backup-log",
         &Router::new().upload(&API_METHOD_UPLOAD_BACKUP_LOG),
     ),
+    (
+        "stats-history",
+        &Router::new().get(&API_METHOD_GET_STATS_HISTORY),
+    ),
+    (
+        "usage-trend",
+        &Router::new().get(&API_METHOD_GET_USAGE_TREND),
+    ),
     ("verify", &Router::new().post(&API_METHOD_VERIFY)),
+    (
+        "verify-report",
+        &Router::new().get(&API_METHOD_GET_VERIFY_REPORT),
+    ),
+    ("warm-reader", &crate::api2::admin::warm_reader::ROUTER),
 ];
 
 const DATASTORE_INFO_ROUTER: Router = Router::new()
@@ -2426,3 +3569,40 @@ const DATASTORE_INFO_ROUTER: Router = Router::new()
 pub const ROUTER: Router = Router::new()
     .get(&API_METHOD_GET_DATASTORE_LIST)
     .match_all("store", &DATASTORE_INFO_ROUTER);
+
+#[sortable]
+const DATASTORE_RESTORE_SUBDIRS: SubdirMap = &[
+    ("catalog", &Router::new().get(&API_METHOD_CATALOG)),
+    (
+        "download",
+        &Router::new().download(&API_METHOD_DOWNLOAD_FILE),
+    ),
+    (
+        "download-decoded",
+        &Router::new().download(&API_METHOD_DOWNLOAD_FILE_DECODED),
+    ),
+    ("files", &Router::new().get(&API_METHOD_LIST_SNAPSHOT_FILES)),
+    ("groups", &Router::new().get(&API_METHOD_LIST_GROUPS)),
+    (
+        "namespace",
+        &Router::new().get(&crate::api2::admin::namespace::API_METHOD_LIST_NAMESPACES),
+    ),
+    (
+        "pxar-file-download",
+        &Router::new().download(&API_METHOD_PXAR_FILE_DOWNLOAD),
+    ),
+    (
+        "restore-link-download",
+        &Router::new().download(&API_METHOD_RESTORE_LINK_DOWNLOAD),
+    ),
+    ("snapshots", &Router::new().get(&API_METHOD_LIST_SNAPSHOTS)),
+];
+
+const DATASTORE_RESTORE_ROUTER: Router = Router::new()
+    .get(&list_subdirs_api_method!(DATASTORE_RESTORE_SUBDIRS))
+    .subdirs(DATASTORE_RESTORE_SUBDIRS);
+
+/// Read-only part of the datastore API, for the restore-only listener of the proxy.
+pub const RESTORE_ROUTER: Router = Router::new()
+    .get(&API_METHOD_GET_DATASTORE_LIST)
+    .match_all("store", &DATASTORE_RESTORE_ROUTER);
@@ -0,0 +1,177 @@
+//! Job run history and statistics
+
+use std::collections::BTreeMap;
+
+use anyhow::Error;
+
+use proxmox_router::{list_subdirs_api_method, Permission, Router, SubdirMap};
+use proxmox_schema::api;
+use proxmox_sortable_macro::sortable;
+
+use pbs_api_types::{JobHistoryEntry, JobHistoryStatistics, TaskStateType, PRIV_SYS_AUDIT};
+
+use crate::server::jobstate::read_job_history;
+
+fn filter_history(
+    job_type: Option<String>,
+    id: Option<String>,
+    since: Option<i64>,
+    until: Option<i64>,
+) -> Result<impl Iterator<Item = JobHistoryEntry>, Error> {
+    let list = read_job_history(job_type.as_deref(), id.as_deref())?;
+
+    Ok(list.into_iter().filter(move |entry| {
+        since.map(|since| entry.starttime >= since).unwrap_or(true)
+            && until.map(|until| entry.starttime <= until).unwrap_or(true)
+    }))
+}
+
+#[api(
+    input: {
+        properties: {
+            "job-type": {
+                type: String,
+                description: "Only list runs of this job type (e.g. 'syncjob').",
+                optional: true,
+            },
+            id: {
+                type: String,
+                description: "Only list runs of the job with this ID.",
+                optional: true,
+            },
+            since: {
+                type: Integer,
+                description: "Only list runs started since this UNIX epoch.",
+                optional: true,
+            },
+            until: {
+                type: Integer,
+                description: "Only list runs started until this UNIX epoch.",
+                optional: true,
+            },
+            limit: {
+                type: Integer,
+                description: "Only list the most recent runs, 0 means no limit.",
+                optional: true,
+                minimum: 0,
+                default: 0,
+            },
+        },
+    },
+    returns: {
+        description: "List of recorded job runs, oldest first.",
+        type: Array,
+        items: { type: JobHistoryEntry },
+    },
+    access: {
+        permission: &Permission::Privilege(&["system", "tasks"], PRIV_SYS_AUDIT, false),
+    },
+)]
+/// List recorded job runs.
+pub fn list_job_history(
+    job_type: Option<String>,
+    id: Option<String>,
+    since: Option<i64>,
+    until: Option<i64>,
+    limit: u64,
+) -> Result<Vec<JobHistoryEntry>, Error> {
+    let mut list: Vec<JobHistoryEntry> = filter_history(job_type, id, since, until)?.collect();
+
+    let limit = limit as usize;
+    if limit > 0 && list.len() > limit {
+        list.drain(..list.len() - limit);
+    }
+
+    Ok(list)
+}
+
+#[api(
+    input: {
+        properties: {
+            "job-type": {
+                type: String,
+                description: "Only include runs of this job type (e.g. 'syncjob').",
+                optional: true,
+            },
+            id: {
+                type: String,
+                description: "Only include runs of the job with this ID.",
+                optional: true,
+            },
+            since: {
+                type: Integer,
+                description: "Only include runs started since this UNIX epoch.",
+                optional: true,
+            },
+            until: {
+                type: Integer,
+                description: "Only include runs started until this UNIX epoch.",
+                optional: true,
+            },
+        },
+    },
+    returns: {
+        description: "Statistics per job.",
+        type: Array,
+        items: { type: JobHistoryStatistics },
+    },
+    access: {
+        permission: &Permission::Privilege(&["system", "tasks"], PRIV_SYS_AUDIT, false),
+    },
+)]
+/// Get success rates and durations of the recorded job runs, per job.
+pub fn job_history_statistics(
+    job_type: Option<String>,
+    id: Option<String>,
+    since: Option<i64>,
+    until: Option<i64>,
+) -> Result<Vec<JobHistoryStatistics>, Error> {
+    let mut map: BTreeMap<(String, String), JobHistoryStatistics> = BTreeMap::new();
+    let mut total_durations: BTreeMap<(String, String), i64> = BTreeMap::new();
+
+    for entry in filter_history(job_type, id, since, until)? {
+        let key = (entry.job_type.clone(), entry.job_id.clone());
+        let stats = map
+            .entry(key.clone())
+            .or_insert_with(|| JobHistoryStatistics {
+                job_type: entry.job_type.clone(),
+                job_id: entry.job_id.clone(),
+                ..Default::default()
+            });
+
+        stats.runs += 1;
+        match entry.status {
+            TaskStateType::OK => stats.ok += 1,
+            TaskStateType::Warning => stats.warnings += 1,
+            TaskStateType::Error | TaskStateType::Unknown => stats.errors += 1,
+        }
+
+        let duration = (entry.endtime - entry.starttime).max(0);
+        stats.max_duration = stats.max_duration.max(duration);
+        *total_durations.entry(key).or_default() += duration;
+
+        stats.bytes += entry.bytes.unwrap_or(0);
+    }
+
+    Ok(map
+        .into_iter()
+        .map(|(key, mut stats)| {
+            let total = total_durations.get(&key).copied().unwrap_or(0);
+            stats.average_duration = total as f64 / stats.runs as f64;
+            stats
+        })
+        .collect())
+}
+
+#[sortable]
+const SUBDIRS: SubdirMap = &sorted!([
+    ("runs", &Router::new().get(&API_METHOD_LIST_JOB_HISTORY)),
+    (
+        "statistics",
+        &Router::new().get(&API_METHOD_JOB_HISTORY_STATISTICS)
+    ),
+]);
+
+pub const ROUTER: Router = Router::new()
+    .get(&list_subdirs_api_method!(SUBDIRS))
+    .subdirs(SUBDIRS);
@@ -4,21 +4,30 @@ use proxmox_router::list_subdirs_api_method;
 use proxmox_router::{Router, SubdirMap};
 use proxmox_sortable_macro::sortable;
 
+pub mod accounting;
+pub mod api_stats;
 pub mod datastore;
 pub mod gc;
+pub mod job_history;
 pub mod metrics;
 pub mod namespace;
 pub mod prune;
+pub mod schedule;
 pub mod sync;
 pub mod traffic_control;
 pub mod verify;
+pub mod warm_reader;
 
 #[sortable]
 const SUBDIRS: SubdirMap = &sorted!([
+    ("accounting", &accounting::ROUTER),
+    ("api-stats", &api_stats::ROUTER),
     ("datastore", &datastore::ROUTER),
+    ("job-history", &job_history::ROUTER),
     ("metrics", &metrics::ROUTER),
     ("prune", &prune::ROUTER),
     ("gc", &gc::ROUTER),
+    ("schedule-preview", &schedule::ROUTER),
     ("sync", &sync::ROUTER),
     ("traffic-control", &traffic_control::ROUTER),
     ("verify", &verify::ROUTER),
@@ -27,3 +36,10 @@ const SUBDIRS: SubdirMap = &sorted!([
 pub const ROUTER: Router = Router::new()
     .get(&list_subdirs_api_method!(SUBDIRS))
     .subdirs(SUBDIRS);
+
+const RESTORE_SUBDIRS: SubdirMap = &[("datastore", &datastore::RESTORE_ROUTER)];
+
+/// Admin API subset for the restore-only listener of the proxy.
+pub const RESTORE_ROUTER: Router = Router::new()
+    .get(&list_subdirs_api_method!(RESTORE_SUBDIRS))
+    .subdirs(RESTORE_SUBDIRS);
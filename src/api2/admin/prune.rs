@@ -18,7 +18,7 @@ use pbs_config::CachedUserInfo;
 
 use crate::server::{
     do_prune_job,
-    jobstate::{compute_schedule_status, Job, JobState},
+    jobstate::{compute_schedule_status, last_run_error_class, Job, JobState},
 };
 
 #[api(
@@ -80,6 +80,8 @@ pub fn list_prune_jobs(
         if job.disable {
             status.next_run = None;
         }
+        status.last_run_error_class =
+            last_run_error_class("prunejob", &job.id, status.last_run_upid.as_deref());
 
         list.push(PruneJobStatus {
             config: job,
@@ -121,7 +123,14 @@ pub fn run_prune_job(
 
     let job = Job::new("prunejob", &id)?;
 
-    let upid_str = do_prune_job(job, prune_job.options, prune_job.store, &auth_id, None)?;
+    let upid_str = do_prune_job(
+        job,
+        prune_job.options,
+        prune_job.store,
+        prune_job.retry,
+        &auth_id,
+        None,
+    )?;
 
     Ok(upid_str)
 }
@@ -0,0 +1,235 @@
+//! Preview of the upcoming runs of scheduled jobs
+
+use std::collections::HashMap;
+
+use anyhow::{bail, Error};
+
+use proxmox_router::{Permission, Router};
+use proxmox_schema::api;
+
+use pbs_api_types::{
+    JobScheduleConflict, JobSchedulePreview, SchedulePreview, DATASTORE_SCHEMA, PRIV_SYS_AUDIT,
+};
+
+use crate::server::jobstate::{last_run_time, read_job_history};
+use crate::server::schedule::{next_runs, scheduled_jobs};
+
+/// Job types putting a heavy load on their datastore, which should not run at the same time.
+const HEAVY_JOB_TYPES: &[&str] = &[
+    "archive",
+    "garbage_collection",
+    "syncjob",
+    "tape-backup-job",
+    "verificationjob",
+];
+
+/// Duration assumed for runs of jobs without any recorded run, in seconds.
+const DEFAULT_ESTIMATED_DURATION: i64 = 3600;
+
+/// Average run duration per job type and ID, from the job history.
+fn average_durations() -> Result<HashMap<(String, String), i64>, Error> {
+    let mut totals: HashMap<(String, String), (i64, i64)> = HashMap::new();
+    for entry in read_job_history(None, None)? {
+        let total = totals.entry((entry.job_type, entry.job_id)).or_default();
+        total.0 += (entry.endtime - entry.starttime).max(0);
+        total.1 += 1;
+    }
+    Ok(totals
+        .into_iter()
+        .map(|(key, (duration, runs))| (key, duration / runs))
+        .collect())
+}
+
+/// Find the runs of heavy jobs on the same datastore which are expected to overlap.
+fn find_conflicts(jobs: &[JobSchedulePreview]) -> Vec<JobScheduleConflict> {
+    let mut runs: HashMap<&str, Vec<(i64, i64, String)>> = HashMap::new();
+    for job in jobs.iter().filter(|job| job.heavy) {
+        let store = job.store.as_str();
+        let label = format!("{}/{}", job.job_type, job.job_id);
+        for start in job.next_runs.iter() {
+            runs.entry(store).or_default().push((
+                *start,
+                start + job.estimated_duration,
+                label.clone(),
+            ));
+        }
+    }
+
+    let mut conflicts = Vec::new();
+    for (store, mut runs) in runs {
+        runs.sort_unstable_by_key(|(start, _, _)| *start);
+        for (i, (first_start, first_end, first)) in runs.iter().enumerate() {
+            for (second_start, _, second) in runs[i + 1..].iter() {
+                if second_start >= first_end {
+                    break;
+                }
+                if first != second {
+                    conflicts.push(JobScheduleConflict {
+                        store: store.to_string(),
+                        first: first.clone(),
+                        first_start: *first_start,
+                        second: second.clone(),
+                        second_start: *second_start,
+                    });
+                }
+            }
+        }
+    }
+    conflicts.sort_unstable_by_key(|conflict| conflict.first_start);
+
+    conflicts
+}
+
+#[api(
+    input: {
+        properties: {
+            count: {
+                type: Integer,
+                description: "Number of upcoming runs to compute per job.",
+                optional: true,
+                minimum: 1,
+                maximum: 100,
+                default: 5,
+            },
+            store: {
+                schema: DATASTORE_SCHEMA,
+                optional: true,
+            },
+            "job-type": {
+                type: String,
+                description: "Type of the job to preview a changed schedule for (e.g. 'syncjob').",
+                optional: true,
+            },
+            id: {
+                type: String,
+                description: "ID of the job to preview a changed schedule for.",
+                optional: true,
+            },
+            schedule: {
+                type: String,
+                description: "Changed schedule to use for the job given by 'job-type' and 'id'.",
+                optional: true,
+            },
+        },
+    },
+    returns: {
+        type: SchedulePreview,
+    },
+    access: {
+        permission: &Permission::Privilege(&["system", "tasks"], PRIV_SYS_AUDIT, false),
+    },
+)]
+/// Compute the upcoming runs of all scheduled jobs and the overlapping runs of heavy jobs on the
+/// same datastore, optionally with a changed schedule for one job.
+pub fn preview_schedules(
+    count: u64,
+    store: Option<String>,
+    job_type: Option<String>,
+    id: Option<String>,
+    schedule: Option<String>,
+) -> Result<SchedulePreview, Error> {
+    let mut errors = Vec::new();
+    let mut jobs = scheduled_jobs(|err| errors.push(err));
+    if let Some(err) = errors.into_iter().next() {
+        return Err(err);
+    }
+
+    match (job_type, id, schedule) {
+        (Some(job_type), Some(id), Some(schedule)) => {
+            proxmox_time::verify_calendar_event(&schedule)?;
+            match jobs
+                .iter_mut()
+                .find(|job| job.job_type == job_type && job.id == id)
+            {
+                Some(job) => job.schedule = schedule,
+                None => bail!("no scheduled job '{id}' of type '{job_type}'"),
+            }
+        }
+        (None, None, None) => {}
+        _ => bail!("'job-type', 'id' and 'schedule' have to be set together"),
+    }
+
+    let durations = average_durations()?;
+    let now = proxmox_time::epoch_i64();
+
+    let mut previews = Vec::new();
+    for job in jobs {
+        if store.as_ref().map_or(false, |store| *store != job.store) {
+            continue;
+        }
+
+        let estimated_duration = durations
+            .get(&(job.job_type.to_string(), job.id.clone()))
+            .copied()
+            .unwrap_or(DEFAULT_ESTIMATED_DURATION);
+
+        let runs = last_run_time(job.job_type, &job.id)
+            .and_then(|last| next_runs(&job.schedule, &job.exclude, last, now, count as usize));
+        let (next_runs, error) = match runs {
+            Ok(runs) => (runs, None),
+            Err(err) => (Vec::new(), Some(err.to_string())),
+        };
+
+        previews.push(JobSchedulePreview {
+            job_type: job.job_type.to_string(),
+            job_id: job.id,
+            store: job.store,
+            schedule: job.schedule,
+            heavy: HEAVY_JOB_TYPES.contains(&job.job_type),
+            next_runs,
+            estimated_duration,
+            error,
+        });
+    }
+
+    let conflicts = find_conflicts(&previews);
+
+    Ok(SchedulePreview {
+        jobs: previews,
+        conflicts,
+    })
+}
+
+pub const ROUTER: Router = Router::new().get(&API_METHOD_PREVIEW_SCHEDULES);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn preview(job_type: &str, id: &str, store: &str, runs: &[i64]) -> JobSchedulePreview {
+        JobSchedulePreview {
+            job_type: job_type.to_string(),
+            job_id: id.to_string(),
+            store: store.to_string(),
+            schedule: "daily".to_string(),
+            heavy: HEAVY_JOB_TYPES.contains(&job_type),
+            next_runs: runs.to_vec(),
+            estimated_duration: 600,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_find_conflicts() {
+        let jobs = vec![
+            preview("garbage_collection", "store1", "store1", &[1000, 90000]),
+            preview("verificationjob", "verify1", "store1", &[1300, 100000]),
+            // not heavy, or on another datastore
+            preview("prunejob", "prune1", "store1", &[1100]),
+            preview("syncjob", "sync2", "store2", &[1000]),
+        ];
+
+        let conflicts = find_conflicts(&jobs);
+        assert_eq!(conflicts.len(), 1);
+        let conflict = &conflicts[0];
+        assert_eq!(conflict.store, "store1");
+        assert_eq!(conflict.first, "garbage_collection/store1");
+        assert_eq!(conflict.first_start, 1000);
+        assert_eq!(conflict.second, "verificationjob/verify1");
+        assert_eq!(conflict.second_start, 1300);
+
+        // runs of the same job never conflict with each other
+        let jobs = vec![preview("syncjob", "sync1", "store1", &[1000, 1200])];
+        assert!(find_conflicts(&jobs).is_empty());
+    }
+}
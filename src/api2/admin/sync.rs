@@ -19,7 +19,7 @@ use crate::{
         config::sync::{check_sync_job_modify_access, check_sync_job_read_access},
         pull::do_sync_job,
     },
-    server::jobstate::{compute_schedule_status, Job, JobState},
+    server::jobstate::{compute_schedule_status, last_run_error_class, Job, JobState},
 };
 
 #[api(
@@ -70,7 +70,9 @@ pub fn list_sync_jobs(
         let last_state = JobState::load("syncjob", &job.id)
             .map_err(|err| format_err!("could not open statefile for {}: {}", &job.id, err))?;
 
-        let status = compute_schedule_status(&last_state, job.schedule.as_deref())?;
+        let mut status = compute_schedule_status(&last_state, job.schedule.as_deref())?;
+        status.last_run_error_class =
+            last_run_error_class("syncjob", &job.id, status.last_run_upid.as_deref());
 
         list.push(SyncJobStatus {
             config: job,
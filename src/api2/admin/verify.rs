@@ -1,6 +1,6 @@
 //! Datastore Verify Job Management
 
-use anyhow::{format_err, Error};
+use anyhow::{bail, format_err, Error};
 use serde_json::Value;
 
 use proxmox_router::{
@@ -11,15 +11,17 @@ use proxmox_schema::api;
 use proxmox_sortable_macro::sortable;
 
 use pbs_api_types::{
-    Authid, VerificationJobConfig, VerificationJobStatus, DATASTORE_SCHEMA, JOB_ID_SCHEMA,
-    PRIV_DATASTORE_AUDIT, PRIV_DATASTORE_VERIFY,
+    Authid, VerificationJobConfig, VerificationJobStatus, VerifyAttestation, VerifyJobResult,
+    DATASTORE_SCHEMA, JOB_ID_SCHEMA, PRIV_DATASTORE_AUDIT, PRIV_DATASTORE_VERIFY, UPID,
 };
 use pbs_config::verify;
 use pbs_config::CachedUserInfo;
 
+use crate::backup::verify_report::list_verify_attestations;
+use crate::backup::verify_result::load_verify_job_result;
 use crate::server::{
     do_verification_job,
-    jobstate::{compute_schedule_status, Job, JobState},
+    jobstate::{compute_schedule_status, last_run_error_class, Job, JobState},
 };
 
 #[api(
@@ -76,7 +78,9 @@ pub fn list_verification_jobs(
         let last_state = JobState::load("verificationjob", &job.id)
             .map_err(|err| format_err!("could not open statefile for {}: {}", &job.id, err))?;
 
-        let status = compute_schedule_status(&last_state, job.schedule.as_deref())?;
+        let mut status = compute_schedule_status(&last_state, job.schedule.as_deref())?;
+        status.last_run_error_class =
+            last_run_error_class("verificationjob", &job.id, status.last_run_upid.as_deref());
 
         list.push(VerificationJobStatus {
             config: job,
@@ -129,9 +133,107 @@ pub fn run_verification_job(
     Ok(upid_str)
 }
 
+#[api(
+    input: {
+        properties: {
+            id: {
+                schema: JOB_ID_SCHEMA,
+            },
+            upid: {
+                schema: UPID::API_SCHEMA,
+                optional: true,
+            },
+        },
+    },
+    returns: { type: VerifyAttestation },
+    access: {
+        permission: &Permission::Anybody,
+        description: "Requires Datastore.Audit or Datastore.Verify on job's datastore.",
+    },
+)]
+/// Get the signed attestation of a verification job run, defaults to the most recent run.
+pub fn get_verification_attestation(
+    id: String,
+    upid: Option<String>,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<VerifyAttestation, Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let user_info = CachedUserInfo::new()?;
+
+    let (config, _digest) = verify::config()?;
+    let verification_job: VerificationJobConfig = config.lookup("verification", &id)?;
+
+    user_info.check_privs(
+        &auth_id,
+        &verification_job.acl_path(),
+        PRIV_DATASTORE_AUDIT | PRIV_DATASTORE_VERIFY,
+        true,
+    )?;
+
+    let list = list_verify_attestations(&id)?;
+    let attestation = match upid {
+        Some(upid) => list
+            .into_iter()
+            .find(|attestation| attestation.upid == upid),
+        None => list.into_iter().last(),
+    };
+
+    match attestation {
+        Some(attestation) => Ok(attestation),
+        None => bail!("no attestation found for verification job '{id}'"),
+    }
+}
+
+#[api(
+    input: {
+        properties: {
+            id: {
+                schema: JOB_ID_SCHEMA,
+            },
+        },
+    },
+    returns: { type: VerifyJobResult },
+    access: {
+        permission: &Permission::Anybody,
+        description: "Requires Datastore.Audit or Datastore.Verify on job's datastore.",
+    },
+)]
+/// Get the per-snapshot results of the last run of a verification job.
+pub fn get_verification_result(
+    id: String,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<VerifyJobResult, Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let user_info = CachedUserInfo::new()?;
+
+    let (config, _digest) = verify::config()?;
+    let verification_job: VerificationJobConfig = config.lookup("verification", &id)?;
+
+    user_info.check_privs(
+        &auth_id,
+        &verification_job.acl_path(),
+        PRIV_DATASTORE_AUDIT | PRIV_DATASTORE_VERIFY,
+        true,
+    )?;
+
+    match load_verify_job_result(&id)? {
+        Some(result) => Ok(result),
+        None => bail!("no result found for verification job '{id}'"),
+    }
+}
+
 #[sortable]
-const VERIFICATION_INFO_SUBDIRS: SubdirMap =
-    &[("run", &Router::new().post(&API_METHOD_RUN_VERIFICATION_JOB))];
+const VERIFICATION_INFO_SUBDIRS: SubdirMap = &sorted!([
+    (
+        "attestation",
+        &Router::new().get(&API_METHOD_GET_VERIFICATION_ATTESTATION)
+    ),
+    (
+        "result",
+        &Router::new().get(&API_METHOD_GET_VERIFICATION_RESULT)
+    ),
+    ("run", &Router::new().post(&API_METHOD_RUN_VERIFICATION_JOB)),
+]);
 
 const VERIFICATION_INFO_ROUTER: Router = Router::new()
     .get(&list_subdirs_api_method!(VERIFICATION_INFO_SUBDIRS))
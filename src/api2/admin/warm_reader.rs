@@ -0,0 +1,158 @@
+//! Warm standby reader sessions of a datastore
+
+use anyhow::Error;
+
+use proxmox_router::{http_bail, Permission, Router, RpcEnvironment};
+use proxmox_schema::api;
+
+use pbs_api_types::{
+    Authid, BackupNamespace, Operation, WarmReaderSessionInfo, DATASTORE_SCHEMA,
+    PRIV_DATASTORE_AUDIT, PRIV_DATASTORE_BACKUP, PRIV_DATASTORE_MODIFY, PRIV_DATASTORE_READ,
+    WARM_READER_TTL_SCHEMA,
+};
+use pbs_config::CachedUserInfo;
+use pbs_datastore::{check_backup_owner, DataStore};
+
+use crate::backup::check_ns_privs_full;
+use crate::server::warm_reader;
+
+#[api(
+    input: {
+        properties: {
+            store: { schema: DATASTORE_SCHEMA },
+        },
+    },
+    returns: {
+        description: "List of warm reader sessions.",
+        type: Array,
+        items: { type: WarmReaderSessionInfo },
+    },
+    access: {
+        permission: &Permission::Anybody,
+        description: "Lists the sessions in namespaces with DATASTORE_AUDIT or DATASTORE_READ, \
+            and the own sessions.",
+    },
+)]
+/// List the warm reader sessions of a datastore.
+pub fn list_warm_reader_sessions(
+    store: String,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Vec<WarmReaderSessionInfo>, Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let user_info = CachedUserInfo::new()?;
+
+    Ok(warm_reader::list_sessions(&store)
+        .into_iter()
+        .filter(|info| {
+            let privs = user_info.lookup_privs(&auth_id, &info.ns.acl_path(&store));
+            info.auth_id == auth_id || privs & (PRIV_DATASTORE_AUDIT | PRIV_DATASTORE_READ) != 0
+        })
+        .collect())
+}
+
+#[api(
+    input: {
+        properties: {
+            store: { schema: DATASTORE_SCHEMA },
+            ns: {
+                type: BackupNamespace,
+                optional: true,
+            },
+            backup_dir: {
+                type: pbs_api_types::BackupDir,
+                flatten: true,
+            },
+            ttl: {
+                schema: WARM_READER_TTL_SCHEMA,
+                optional: true,
+            },
+        },
+    },
+    returns: { type: WarmReaderSessionInfo },
+    access: {
+        permission: &Permission::Anybody,
+        description: "Requires on /datastore/{store}[/{namespace}] either DATASTORE_READ for any \
+            or DATASTORE_BACKUP and being the owner of the group",
+    },
+)]
+/// Open a warm reader session for a snapshot, which keeps its index files in memory, so that
+/// reader sessions for it start faster. If a session for the snapshot exists already, its
+/// lifetime is extended to the new TTL instead.
+pub fn create_warm_reader_session(
+    store: String,
+    ns: Option<BackupNamespace>,
+    backup_dir: pbs_api_types::BackupDir,
+    ttl: Option<i64>,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<WarmReaderSessionInfo, Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let ns = ns.unwrap_or_default();
+
+    let limited = check_ns_privs_full(
+        &store,
+        &ns,
+        &auth_id,
+        PRIV_DATASTORE_READ,
+        PRIV_DATASTORE_BACKUP,
+    )?;
+
+    let datastore = DataStore::lookup_datastore(&store, Some(Operation::Read))?;
+    let backup_dir = datastore.backup_dir(ns, backup_dir)?;
+
+    if limited {
+        let owner = backup_dir.get_owner()?;
+        check_backup_owner(&owner, &auth_id)?;
+    }
+
+    warm_reader::create_session(
+        datastore,
+        backup_dir,
+        auth_id,
+        ttl.unwrap_or(warm_reader::DEFAULT_TTL),
+    )
+}
+
+#[api(
+    input: {
+        properties: {
+            store: { schema: DATASTORE_SCHEMA },
+            id: {
+                type: String,
+                description: "Identifier of the session.",
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Anybody,
+        description: "Requires being the creator of the session or DATASTORE_MODIFY on \
+            /datastore/{store}[/{namespace}]",
+    },
+)]
+/// Close a warm reader session. Reader sessions currently using it are not affected.
+pub fn delete_warm_reader_session(
+    store: String,
+    id: String,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<(), Error> {
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+
+    let session = match warm_reader::get_session(&store, &id) {
+        Some(session) => session,
+        None => http_bail!(NOT_FOUND, "no such warm reader session '{id}'"),
+    };
+
+    if *session.auth_id() != auth_id {
+        check_ns_privs_full(&store, session.ns(), &auth_id, PRIV_DATASTORE_MODIFY, 0)?;
+    }
+
+    warm_reader::remove_session(&id);
+
+    Ok(())
+}
+
+const ITEM_ROUTER: Router = Router::new().delete(&API_METHOD_DELETE_WARM_READER_SESSION);
+
+pub const ROUTER: Router = Router::new()
+    .get(&API_METHOD_LIST_WARM_READER_SESSIONS)
+    .post(&API_METHOD_CREATE_WARM_READER_SESSION)
+    .match_all("id", &ITEM_ROUTER);
@@ -2,6 +2,7 @@ use anyhow::{bail, format_err, Error};
 use nix::dir::Dir;
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 use ::serde::Serialize;
 use serde_json::{json, Value};
@@ -13,10 +14,12 @@ use pbs_api_types::Authid;
 use pbs_datastore::backup_info::{BackupDir, BackupInfo};
 use pbs_datastore::dynamic_index::DynamicIndexWriter;
 use pbs_datastore::fixed_index::FixedIndexWriter;
+use pbs_datastore::manifest::MANIFEST_BLOB_NAME;
 use pbs_datastore::{DataBlob, DataStore};
 use proxmox_rest_server::{formatter::*, WorkerTask};
 
 use crate::backup::verify_backup_dir_with_lock;
+use crate::traffic_control_cache::SharedRateLimit;
 
 use hyper::{Body, Response};
 
@@ -113,6 +116,12 @@ pub struct BackupEnvironment {
     pub datastore: Arc<DataStore>,
     pub backup_dir: BackupDir,
     pub last_backup: Option<BackupInfo>,
+    /// Throttles chunk uploads while restores on the datastore have priority
+    pub upload_limiter: Option<SharedRateLimit>,
+    /// Minimum retention period of the new snapshot in days, enforced by the datastore
+    pub min_retention_days: Option<u64>,
+    /// Mark the new snapshot as protected when finishing
+    pub protect_new: bool,
     state: Arc<Mutex<SharedBackupState>>,
 }
 
@@ -145,10 +154,23 @@ impl BackupEnvironment {
             formatter: JSON_FORMATTER,
             backup_dir,
             last_backup: None,
+            upload_limiter: None,
+            min_retention_days: None,
+            protect_new: false,
             state: Arc::new(Mutex::new(state)),
         }
     }
 
+    /// Delay the upload of `size` bytes if restores on the datastore have priority.
+    pub async fn throttle_upload(&self, size: u64) {
+        if let Some(limiter) = &self.upload_limiter {
+            let delay = limiter.register_traffic(Instant::now(), size);
+            if !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+
     /// Register a Chunk with associated length.
     ///
     /// We do not fully trust clients, so a client may only use registered
@@ -575,7 +597,19 @@ impl BackupEnvironment {
         let blob = DataBlob::load_from_reader(&mut &data[..])?;
 
         let raw_data = blob.raw_data();
-        replace_file(&path, raw_data, CreateOptions::new(), false)?;
+        let dedup = match self.datastore.blob_dedup_min_size() {
+            // manifests are unique to their snapshot and get updated
+            Some(min_size) => blob_len as u64 >= min_size && file_name != MANIFEST_BLOB_NAME,
+            None => false,
+        };
+        if dedup {
+            let reused = self.datastore.insert_dedup_blob(raw_data, &path)?;
+            if reused {
+                self.log(format!("reused deduplicated blob for {file_name}"));
+            }
+        } else {
+            replace_file(&path, raw_data, CreateOptions::new(), false)?;
+        }
 
         self.log(format!(
             "add blob {:?} ({} bytes, comp: {})",
@@ -605,6 +639,12 @@ impl BackupEnvironment {
             bail!("backup does not contain valid files (file count == 0)");
         }
 
+        let (manifest, _) = self
+            .backup_dir
+            .load_manifest()
+            .map_err(|err| format_err!("unable to load manifest blob - {err}"))?;
+        self.datastore.check_crypt_policy(&manifest)?;
+
         // check for valid manifest and store stats
         let stats = serde_json::to_value(state.backup_stat)?;
         self.backup_dir
@@ -625,6 +665,17 @@ impl BackupEnvironment {
 
         self.datastore.try_ensure_sync_level()?;
 
+        // set last, a failed backup must still be removable. The snapshot directory is locked by
+        // this session, so no update_protection() here.
+        if self.protect_new {
+            std::fs::File::create(self.backup_dir.protected_file())
+                .map_err(|err| format_err!("could not create protection file - {err}"))?;
+        }
+        if let Some(days) = self.min_retention_days {
+            let until = proxmox_time::epoch_i64() + (days as i64) * 86400;
+            self.backup_dir.set_retained_until(until)?;
+        }
+
         // marks the backup as successful
         state.finished = true;
 
@@ -17,9 +17,10 @@ use proxmox_schema::*;
 use proxmox_sortable_macro::sortable;
 
 use pbs_api_types::{
-    Authid, BackupNamespace, BackupType, Operation, SnapshotVerifyState, VerifyState,
-    BACKUP_ARCHIVE_NAME_SCHEMA, BACKUP_ID_SCHEMA, BACKUP_NAMESPACE_SCHEMA, BACKUP_TIME_SCHEMA,
-    BACKUP_TYPE_SCHEMA, CHUNK_DIGEST_SCHEMA, DATASTORE_SCHEMA, PRIV_DATASTORE_BACKUP,
+    Authid, BackupNamespace, BackupPriority, BackupType, DataStoreConfig, Operation,
+    SnapshotVerifyState, VerifyState, BACKUP_ARCHIVE_NAME_SCHEMA, BACKUP_ID_SCHEMA,
+    BACKUP_NAMESPACE_SCHEMA, BACKUP_TIME_SCHEMA, BACKUP_TYPE_SCHEMA, CHUNK_DIGEST_SCHEMA,
+    DATASTORE_SCHEMA, PRIV_DATASTORE_BACKUP, PRIV_DATASTORE_MODIFY,
 };
 use pbs_config::CachedUserInfo;
 use pbs_datastore::index::IndexFile;
@@ -29,6 +30,11 @@ use pbs_tools::json::{required_array_param, required_integer_param, required_str
 use proxmox_rest_server::{H2Service, WorkerTask};
 use proxmox_sys::fs::lock_dir_noblock_shared;
 
+use crate::server::auth_last_used::record_use;
+use crate::server::datastore_window::{admit_session, Admission, DatastoreAccess};
+use crate::server::space_watermark::check_backup_space;
+use crate::traffic_control_cache::restore_priority_limiters;
+
 mod environment;
 use environment::*;
 
@@ -50,11 +56,13 @@ pub const API_METHOD_UPGRADE_BACKUP: ApiMethod = ApiMethod::new(
             ("backup-time", false, &BACKUP_TIME_SCHEMA),
             ("debug", true, &BooleanSchema::new("Enable verbose debug logging.").schema()),
             ("benchmark", true, &BooleanSchema::new("Job is a benchmark (do not keep data).").schema()),
+            ("priority", true, &BackupPriority::API_SCHEMA),
         ]),
     )
 ).access(
     // Note: parameter 'store' is no uri parameter, so we need to test inside function body
-    Some("Requires on /datastore/{store}[/{namespace}] DATASTORE_BACKUP and being the owner of the group"),
+    Some("Requires on /datastore/{store}[/{namespace}] DATASTORE_BACKUP and being the owner of the group, \
+        a 'high' priority also DATASTORE_MODIFY"),
     &Permission::Anybody
 );
 
@@ -76,8 +84,13 @@ fn upgrade_to_backup_protocol(
     async move {
         let debug = param["debug"].as_bool().unwrap_or(false);
         let benchmark = param["benchmark"].as_bool().unwrap_or(false);
+        let priority: BackupPriority = match param.get("priority") {
+            Some(priority) => serde_json::from_value(priority.clone())?,
+            None => BackupPriority::default(),
+        };
 
         let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+        record_use(&auth_id, rpcenv.get_client_ip().map(|addr| addr.ip()));
 
         let store = required_string_param(&param, "store")?.to_owned();
         let backup_ns = optional_ns_param(&param)?;
@@ -94,8 +107,40 @@ fn upgrade_to_backup_protocol(
             )
             .map_err(|err| http_err!(FORBIDDEN, "{err}"))?;
 
+        // high priority sessions get past the session limit, reserve that for admins
+        if priority == BackupPriority::High {
+            user_info
+                .check_privs(
+                    &auth_id,
+                    &backup_ns.acl_path(&store),
+                    PRIV_DATASTORE_MODIFY,
+                    false,
+                )
+                .map_err(|err| {
+                    http_err!(FORBIDDEN, "high backup priority not allowed - {err}")
+                })?;
+        }
+
+        let session = match admit_session(&store, DatastoreAccess::Backup, priority).await? {
+            Admission::Admitted(session) => session,
+            Admission::Refused(response) => return Ok(response),
+        };
+
         let datastore = DataStore::lookup_datastore(&store, Some(Operation::Write))?;
 
+        let (config, _digest) = pbs_config::datastore::config()?;
+        let store_config: DataStoreConfig = config.lookup("datastore", &store)?;
+        check_backup_space(&store_config, &datastore).await?;
+
+        // high priority backups are not slowed down in favor of restores
+        let upload_limiter = match priority {
+            BackupPriority::High => None,
+            _ => restore_priority_limiters(&store)?.map(|(_, limiter)| limiter),
+        };
+
+        let min_retention_days = store_config.min_retention_days;
+        let protect_new = store_config.protect_new_backups.unwrap_or(false);
+
         let protocols = parts
             .headers
             .get("UPGRADE")
@@ -213,6 +258,9 @@ fn upgrade_to_backup_protocol(
 
                 env.debug = debug;
                 env.last_backup = last_backup;
+                env.upload_limiter = upload_limiter;
+                env.min_retention_days = min_retention_days;
+                env.protect_new = protect_new;
 
                 let origin = match rpcenv.get_client_ip().map(|addr| addr.ip()) {
                     Some(ip) => format!(" from {ip}"),
@@ -221,6 +269,9 @@ fn upgrade_to_backup_protocol(
                 env.log(format!(
                     "starting new {worker_type} on datastore '{store}'{origin}: {path:?}",
                 ));
+                if priority != BackupPriority::Normal {
+                    env.log(format!("backup priority: {priority}"));
+                }
 
                 let service =
                     H2Service::new(env.clone(), worker.clone(), &BACKUP_API_ROUTER, debug);
@@ -263,7 +314,8 @@ fn upgrade_to_backup_protocol(
                 let mut abort_future = abort_future.map(|_| Err(format_err!("task aborted")));
 
                 async move {
-                    // keep flock until task ends
+                    // keep flock and session slot until task ends
+                    let _session = session;
                     let _group_guard = _group_guard;
                     let snap_guard = snap_guard;
                     let _last_guard = _last_guard;
@@ -316,14 +368,23 @@ fn upgrade_to_backup_protocol(
             },
         )?;
 
-        let response = Response::builder()
+        let mut response = Response::builder()
             .status(StatusCode::SWITCHING_PROTOCOLS)
             .header(CONNECTION, HeaderValue::from_static("upgrade"))
             .header(
                 UPGRADE,
                 HeaderValue::from_static(PROXMOX_BACKUP_PROTOCOL_ID_V1!()),
-            )
-            .body(Body::empty())?;
+            );
+
+        // let clients know that they cannot remove the new snapshot right away
+        if let Some(days) = min_retention_days {
+            response = response.header("proxmox-backup-min-retention-days", days);
+        }
+        if protect_new {
+            response = response.header("proxmox-backup-protected", "1");
+        }
+
+        let response = response.body(Body::empty())?;
 
         Ok(response)
     }
@@ -332,6 +393,10 @@ fn upgrade_to_backup_protocol(
 
 const BACKUP_API_SUBDIRS: SubdirMap = &[
     ("blob", &Router::new().upload(&API_METHOD_UPLOAD_BLOB)),
+    (
+        "digest_salt",
+        &Router::new().get(&API_METHOD_GET_DIGEST_SALT),
+    ),
     (
         "dynamic_chunk",
         &Router::new().upload(&API_METHOD_UPLOAD_DYNAMIC_CHUNK),
@@ -809,6 +874,21 @@ fn get_previous_backup_time(
     Ok(json!(backup_time))
 }
 
+pub const API_METHOD_GET_DIGEST_SALT: ApiMethod = ApiMethod::new(
+    &ApiHandler::Sync(&get_digest_salt),
+    &ObjectSchema::new("Get the digest salt of the datastore.", &[]),
+);
+
+fn get_digest_salt(
+    _param: Value,
+    _info: &ApiMethod,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Value, Error> {
+    let env: &BackupEnvironment = rpcenv.as_ref();
+
+    Ok(json!(env.datastore.digest_salt().map(hex::encode)))
+}
+
 #[sortable]
 pub const API_METHOD_DOWNLOAD_PREVIOUS: ApiMethod = ApiMethod::new(
     &ApiHandler::AsyncHttp(&download_previous),
@@ -13,8 +13,11 @@ use proxmox_router::{ApiHandler, ApiMethod, ApiResponseFuture, RpcEnvironment};
 use proxmox_schema::*;
 use proxmox_sortable_macro::sortable;
 
-use pbs_api_types::{BACKUP_ARCHIVE_NAME_SCHEMA, CHUNK_DIGEST_SCHEMA};
+use pbs_api_types::{
+    BACKUP_ARCHIVE_NAME_SCHEMA, CHUNK_DIGEST_SCHEMA, MAX_BLOB_SIZE_LIMIT, MAX_CHUNK_SIZE_LIMIT,
+};
 use pbs_datastore::file_formats::{DataBlobHeader, EncryptedDataBlobHeader};
+use pbs_datastore::manifest::MANIFEST_BLOB_NAME;
 use pbs_datastore::{DataBlob, DataStore};
 use pbs_tools::json::{required_integer_param, required_string_param};
 
@@ -77,7 +80,11 @@ impl Future for UploadChunk {
                             let mut chunk = DataBlob::from_raw(raw_data)?;
 
                             proxmox_async::runtime::block_in_place(|| {
-                                chunk.verify_unencrypted(this.size as usize, &this.digest)?;
+                                chunk.verify_unencrypted_salted(
+                                    this.size as usize,
+                                    &this.digest,
+                                    this.store.digest_salt(),
+                                )?;
 
                                 // always comput CRC at server side
                                 chunk.set_crc(chunk.compute_crc());
@@ -106,6 +113,26 @@ impl Future for UploadChunk {
     }
 }
 
+/// Check an announced chunk against the `max-chunk-size` of the datastore, before receiving it.
+fn check_chunk_size(env: &BackupEnvironment, size: u32, encoded_size: u32) -> Result<(), Error> {
+    let max_size = env.datastore.max_chunk_size();
+    if size as usize > max_size {
+        bail!(
+            "chunk of {size} bytes exceeds the maximum chunk size of {max_size} bytes of \
+            datastore '{}' (tuning option 'max-chunk-size')",
+            env.datastore.name(),
+        );
+    }
+    if encoded_size as usize > max_size + std::mem::size_of::<EncryptedDataBlobHeader>() {
+        bail!(
+            "encoded chunk of {encoded_size} bytes exceeds the maximum chunk size of {max_size} \
+            bytes of datastore '{}' (tuning option 'max-chunk-size')",
+            env.datastore.name(),
+        );
+    }
+    Ok(())
+}
+
 #[sortable]
 pub const API_METHOD_UPLOAD_FIXED_CHUNK: ApiMethod = ApiMethod::new(
     &ApiHandler::AsyncHttp(&upload_fixed_chunk),
@@ -126,7 +153,7 @@ pub const API_METHOD_UPLOAD_FIXED_CHUNK: ApiMethod = ApiMethod::new(
                 false,
                 &IntegerSchema::new("Chunk size.")
                     .minimum(1)
-                    .maximum(1024 * 1024 * 16)
+                    .maximum((MAX_CHUNK_SIZE_LIMIT * 1024 * 1024) as isize)
                     .schema()
             ),
             (
@@ -135,7 +162,7 @@ pub const API_METHOD_UPLOAD_FIXED_CHUNK: ApiMethod = ApiMethod::new(
                 &IntegerSchema::new("Encoded chunk size.")
                     .minimum((std::mem::size_of::<DataBlobHeader>() as isize) + 1)
                     .maximum(
-                        1024 * 1024 * 16
+                        (MAX_CHUNK_SIZE_LIMIT * 1024 * 1024) as isize
                             + (std::mem::size_of::<EncryptedDataBlobHeader>() as isize)
                     )
                     .schema()
@@ -161,6 +188,11 @@ fn upload_fixed_chunk(
 
         let env: &BackupEnvironment = rpcenv.as_ref();
 
+        check_chunk_size(env, size, encoded_size)?;
+
+        // delay before receiving the chunk, so the client is slowed down as well
+        env.throttle_upload(encoded_size as u64).await;
+
         let (digest, size, compressed_size, is_duplicate) =
             UploadChunk::new(req_body, env.datastore.clone(), digest, size, encoded_size).await?;
 
@@ -195,7 +227,7 @@ pub const API_METHOD_UPLOAD_DYNAMIC_CHUNK: ApiMethod = ApiMethod::new(
                 false,
                 &IntegerSchema::new("Chunk size.")
                     .minimum(1)
-                    .maximum(1024 * 1024 * 16)
+                    .maximum((MAX_CHUNK_SIZE_LIMIT * 1024 * 1024) as isize)
                     .schema()
             ),
             (
@@ -204,7 +236,7 @@ pub const API_METHOD_UPLOAD_DYNAMIC_CHUNK: ApiMethod = ApiMethod::new(
                 &IntegerSchema::new("Encoded chunk size.")
                     .minimum((std::mem::size_of::<DataBlobHeader>() as isize) + 1)
                     .maximum(
-                        1024 * 1024 * 16
+                        (MAX_CHUNK_SIZE_LIMIT * 1024 * 1024) as isize
                             + (std::mem::size_of::<EncryptedDataBlobHeader>() as isize)
                     )
                     .schema()
@@ -230,6 +262,11 @@ fn upload_dynamic_chunk(
 
         let env: &BackupEnvironment = rpcenv.as_ref();
 
+        check_chunk_size(env, size, encoded_size)?;
+
+        // delay before receiving the chunk, so the client is slowed down as well
+        env.throttle_upload(encoded_size as u64).await;
+
         let (digest, size, compressed_size, is_duplicate) =
             UploadChunk::new(req_body, env.datastore.clone(), digest, size, encoded_size).await?;
 
@@ -292,7 +329,7 @@ pub const API_METHOD_UPLOAD_BLOB: ApiMethod = ApiMethod::new(
                 &IntegerSchema::new("Encoded blob size.")
                     .minimum(std::mem::size_of::<DataBlobHeader>() as isize)
                     .maximum(
-                        1024 * 1024 * 16
+                        (MAX_BLOB_SIZE_LIMIT * 1024 * 1024) as isize
                             + (std::mem::size_of::<EncryptedDataBlobHeader>() as isize)
                     )
                     .schema()
@@ -318,9 +355,27 @@ fn upload_blob(
             bail!("wrong blob file extension: '{}'", file_name);
         }
 
+        let (max_size, option) = if file_name == MANIFEST_BLOB_NAME {
+            (env.datastore.max_manifest_size(), "max-manifest-size")
+        } else {
+            (env.datastore.max_blob_size(), "max-blob-size")
+        };
+        if encoded_size > max_size + std::mem::size_of::<EncryptedDataBlobHeader>() {
+            bail!(
+                "blob '{file_name}' of {encoded_size} bytes exceeds the maximum size of \
+                {max_size} bytes of datastore '{}' (tuning option '{option}')",
+                env.datastore.name(),
+            );
+        }
+
         let data = req_body
             .map_err(Error::from)
             .try_fold(Vec::new(), |mut acc, chunk| {
+                if acc.len() + chunk.len() > encoded_size {
+                    return future::err(format_err!(
+                        "uploaded blob is larger than announced ({encoded_size} bytes)"
+                    ));
+                }
                 acc.extend_from_slice(&chunk);
                 future::ok::<_, Error>(acc)
             })
@@ -152,6 +152,10 @@ pub enum DeletableProperty {
     SyncAttributes,
     /// User classes
     UserClasses,
+    /// Login notification
+    LoginNotify,
+    /// Namespace provisioning
+    NamespaceProvision,
 }
 
 #[api(
@@ -244,6 +248,12 @@ pub async fn update_ad_realm(
                 DeletableProperty::UserClasses => {
                     config.user_classes = None;
                 }
+                DeletableProperty::LoginNotify => {
+                    config.login_notify = None;
+                }
+                DeletableProperty::NamespaceProvision => {
+                    config.namespace_provision = None;
+                }
             }
         }
     }
@@ -301,6 +311,14 @@ pub async fn update_ad_realm(
         config.user_classes = Some(user_classes);
     }
 
+    if let Some(login_notify) = update.login_notify {
+        config.login_notify = Some(login_notify);
+    }
+
+    if let Some(namespace_provision) = update.namespace_provision {
+        config.namespace_provision = Some(namespace_provision);
+    }
+
     let mut ldap_config = if password.is_some() {
         AdAuthenticator::api_type_to_config_with_password(&config, password.clone())?
     } else {
@@ -157,6 +157,14 @@ pub enum DeletableProperty {
     Prompt,
     /// Delete the acr_values property
     AcrValues,
+    /// Delete the login-notify property
+    LoginNotify,
+    /// Delete the namespace-provision property
+    NamespaceProvision,
+    /// Delete the roles-claim property
+    RolesClaim,
+    /// Delete the role-mapping property
+    RoleMapping,
 }
 
 #[api(
@@ -229,6 +237,18 @@ pub fn update_openid_realm(
                 DeletableProperty::AcrValues => {
                     config.acr_values = None;
                 }
+                DeletableProperty::LoginNotify => {
+                    config.login_notify = None;
+                }
+                DeletableProperty::NamespaceProvision => {
+                    config.namespace_provision = None;
+                }
+                DeletableProperty::RolesClaim => {
+                    config.roles_claim = None;
+                }
+                DeletableProperty::RoleMapping => {
+                    config.role_mapping = None;
+                }
             }
         }
     }
@@ -264,6 +284,18 @@ pub fn update_openid_realm(
     if update.acr_values.is_some() {
         config.acr_values = update.acr_values;
     }
+    if update.login_notify.is_some() {
+        config.login_notify = update.login_notify;
+    }
+    if update.namespace_provision.is_some() {
+        config.namespace_provision = update.namespace_provision;
+    }
+    if update.roles_claim.is_some() {
+        config.roles_claim = update.roles_claim;
+    }
+    if update.role_mapping.is_some() {
+        config.role_mapping = update.role_mapping;
+    }
 
     domains.set_data(&realm, "openid", &config)?;
 
@@ -1,3 +1,4 @@
+use std::collections::HashSet;
 use std::path::PathBuf;
 
 use ::serde::{Deserialize, Serialize};
@@ -58,12 +59,15 @@ pub fn list_datastores(
     rpcenv["digest"] = hex::encode(digest).into();
 
     let list: Vec<DataStoreConfig> = config.convert_to_typed_array("datastore")?;
-    let filter_by_privs = |store: &DataStoreConfig| {
+    let filter_by_privs = |mut store: DataStoreConfig| {
         let user_privs = user_info.lookup_privs(&auth_id, &["datastore", &store.name]);
-        (user_privs & PRIV_DATASTORE_AUDIT) != 0
+        if (user_privs & PRIV_DATASTORE_MODIFY) == 0 {
+            store.digest_salt = None;
+        }
+        ((user_privs & PRIV_DATASTORE_AUDIT) != 0).then_some(store)
     };
 
-    Ok(list.into_iter().filter(filter_by_privs).collect())
+    Ok(list.into_iter().filter_map(filter_by_privs).collect())
 }
 
 pub(crate) fn do_create_datastore(
@@ -95,6 +99,26 @@ pub(crate) fn do_create_datastore(
     jobstate::create_state_file("garbage_collection", &datastore.name)
 }
 
+/// Check that a cold tier directory exists and is outside of the datastore.
+fn check_cold_tier_path(config: &DataStoreConfig, path: String) -> Result<String, Error> {
+    let cold_path = std::path::Path::new(&path);
+    if !cold_path.is_absolute() {
+        param_bail!("cold-tier-path", "expected an absolute path");
+    }
+    if !cold_path.is_dir() {
+        param_bail!("cold-tier-path", "directory {path:?} does not exist");
+    }
+    if cold_path.starts_with(&config.path)
+        || std::path::Path::new(&config.path).starts_with(cold_path)
+    {
+        param_bail!(
+            "cold-tier-path",
+            "cold tier must not overlap with the datastore path"
+        );
+    }
+    Ok(path)
+}
+
 #[api(
     protected: true,
     input: {
@@ -122,6 +146,11 @@ pub fn create_datastore(
         param_bail!("name", "datastore '{}' already exists.", config.name);
     }
 
+    let mut config = config;
+    if let Some(path) = config.cold_tier_path.take() {
+        config.cold_tier_path = Some(check_cold_tier_path(&config, path)?);
+    }
+
     let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
     let to_stdout = rpcenv.env_type() == RpcEnvironmentType::CLI;
 
@@ -135,11 +164,13 @@ pub fn create_datastore(
             comment: None,
             disable: false,
             schedule: schedule.clone(),
+            schedule_exclude: None,
             options: PruneJobOptions {
                 keep: config.keep.clone(),
                 max_depth: None,
                 ns: None,
             },
+            retry: None,
         }
     });
 
@@ -187,9 +218,16 @@ pub fn read_datastore(
 ) -> Result<DataStoreConfig, Error> {
     let (config, digest) = pbs_config::datastore::config()?;
 
-    let store_config = config.lookup("datastore", &name)?;
+    let mut store_config: DataStoreConfig = config.lookup("datastore", &name)?;
     rpcenv["digest"] = hex::encode(digest).into();
 
+    // the salt is only revealed to admins and, through the backup protocol, to writers
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let user_privs = CachedUserInfo::new()?.lookup_privs(&auth_id, &["datastore", &name]);
+    if (user_privs & PRIV_DATASTORE_MODIFY) == 0 {
+        store_config.digest_salt = None;
+    }
+
     Ok(store_config)
 }
 
@@ -228,6 +266,52 @@ pub enum DeletableProperty {
     Tuning,
     /// Delete the maintenance-mode property
     MaintenanceMode,
+    /// Delete the backup-window property
+    BackupWindow,
+    /// Delete the restore-window property
+    RestoreWindow,
+    /// Delete the maintenance-window property
+    MaintenanceWindow,
+    /// Delete the backup-window-action property
+    BackupWindowAction,
+    /// Delete the restore-window-action property
+    RestoreWindowAction,
+    /// Delete the max-backup-sessions property
+    MaxBackupSessions,
+    /// Delete the max-reader-sessions property
+    MaxReaderSessions,
+    /// Delete the archive-store property
+    ArchiveStore,
+    /// Delete the archive-after property
+    ArchiveAfter,
+    /// Delete the archive-schedule property
+    ArchiveSchedule,
+    /// Delete the cold-tier-after property
+    ColdTierAfter,
+    /// Delete the restore-priority-ratio property
+    RestorePriorityRatio,
+    /// Delete the low-space-watermark property
+    LowSpaceWatermark,
+    /// Delete the critical-space-watermark property
+    CriticalSpaceWatermark,
+    /// Delete the critical-space-deny-backup property
+    CriticalSpaceDenyBackup,
+    /// Delete the crypt-policy property
+    CryptPolicy,
+    /// Delete the min-retention-days property
+    MinRetentionDays,
+    /// Delete the protect-new-backups property
+    ProtectNewBackups,
+    /// Delete the group-template property
+    GroupTemplate,
+    /// Delete the read-through-remote property
+    ReadThroughRemote,
+    /// Delete the read-through-store property
+    ReadThroughStore,
+    /// Delete the read-through-cache-size property
+    ReadThroughCacheSize,
+    /// Delete the warm-up property
+    WarmUp,
 }
 
 #[api(
@@ -265,6 +349,7 @@ pub fn update_datastore(
     name: String,
     delete: Option<Vec<DeletableProperty>>,
     digest: Option<String>,
+    rpcenv: &mut dyn RpcEnvironment,
 ) -> Result<(), Error> {
     let _lock = pbs_config::datastore::lock_config()?;
 
@@ -277,6 +362,9 @@ pub fn update_datastore(
     }
 
     let mut data: DataStoreConfig = config.lookup("datastore", &name)?;
+    let was_offline = data
+        .get_maintenance_mode()
+        .map_or(false, |mode| mode.is_offline());
 
     if let Some(delete) = delete {
         for delete_prop in delete {
@@ -326,6 +414,75 @@ pub fn update_datastore(
                 DeletableProperty::MaintenanceMode => {
                     data.set_maintenance_mode(None)?;
                 }
+                DeletableProperty::BackupWindow => {
+                    data.backup_window = None;
+                }
+                DeletableProperty::RestoreWindow => {
+                    data.restore_window = None;
+                }
+                DeletableProperty::MaintenanceWindow => {
+                    data.maintenance_window = None;
+                }
+                DeletableProperty::BackupWindowAction => {
+                    data.backup_window_action = None;
+                }
+                DeletableProperty::RestoreWindowAction => {
+                    data.restore_window_action = None;
+                }
+                DeletableProperty::MaxBackupSessions => {
+                    data.max_backup_sessions = None;
+                }
+                DeletableProperty::MaxReaderSessions => {
+                    data.max_reader_sessions = None;
+                }
+                DeletableProperty::ArchiveStore => {
+                    data.archive_store = None;
+                }
+                DeletableProperty::ArchiveAfter => {
+                    data.archive_after = None;
+                }
+                DeletableProperty::ArchiveSchedule => {
+                    data.archive_schedule = None;
+                }
+                DeletableProperty::ColdTierAfter => {
+                    data.cold_tier_after = None;
+                }
+                DeletableProperty::RestorePriorityRatio => {
+                    data.restore_priority_ratio = None;
+                }
+                DeletableProperty::LowSpaceWatermark => {
+                    data.low_space_watermark = None;
+                }
+                DeletableProperty::CriticalSpaceWatermark => {
+                    data.critical_space_watermark = None;
+                }
+                DeletableProperty::CriticalSpaceDenyBackup => {
+                    data.critical_space_deny_backup = None;
+                }
+                DeletableProperty::CryptPolicy => {
+                    data.crypt_policy = None;
+                }
+                DeletableProperty::MinRetentionDays => {
+                    data.min_retention_days = None;
+                }
+                DeletableProperty::ProtectNewBackups => {
+                    data.protect_new_backups = None;
+                }
+                DeletableProperty::GroupTemplate => {
+                    data.group_template = None;
+                }
+                DeletableProperty::ReadThroughRemote => {
+                    data.read_through_remote = None;
+                }
+                DeletableProperty::ReadThroughStore => {
+                    data.read_through_store = None;
+                }
+                DeletableProperty::ReadThroughCacheSize => {
+                    data.read_through_cache_size = None;
+                }
+                DeletableProperty::WarmUp => {
+                    data.warm_up = None;
+                }
             }
         }
     }
@@ -398,6 +555,119 @@ pub fn update_datastore(
         data.tuning = update.tuning;
     }
 
+    if update.backup_window.is_some() {
+        data.backup_window = update.backup_window;
+    }
+    if update.restore_window.is_some() {
+        data.restore_window = update.restore_window;
+    }
+    if update.maintenance_window.is_some() {
+        data.maintenance_window = update.maintenance_window;
+    }
+    if update.backup_window_action.is_some() {
+        data.backup_window_action = update.backup_window_action;
+    }
+    if update.restore_window_action.is_some() {
+        data.restore_window_action = update.restore_window_action;
+    }
+    if update.max_backup_sessions.is_some() {
+        data.max_backup_sessions = update.max_backup_sessions;
+    }
+    if update.max_reader_sessions.is_some() {
+        data.max_reader_sessions = update.max_reader_sessions;
+    }
+    if let Some(archive_store) = update.archive_store {
+        if archive_store == name {
+            param_bail!("archive-store", "a datastore cannot be its own archive");
+        }
+        if config.sections.get(&archive_store).is_none() {
+            param_bail!(
+                "archive-store",
+                "datastore '{archive_store}' does not exist"
+            );
+        }
+        data.archive_store = Some(archive_store);
+    }
+    if update.archive_after.is_some() {
+        data.archive_after = update.archive_after;
+    }
+    if update.archive_schedule.is_some() {
+        data.archive_schedule = update.archive_schedule;
+    }
+    if let Some(path) = update.cold_tier_path {
+        if data.cold_tier_path.is_some() {
+            param_bail!(
+                "cold-tier-path",
+                "the cold tier of a datastore cannot be changed once set"
+            );
+        }
+        data.cold_tier_path = Some(check_cold_tier_path(&data, path)?);
+    }
+    if update.cold_tier_after.is_some() {
+        data.cold_tier_after = update.cold_tier_after;
+    }
+    if update.restore_priority_ratio.is_some() {
+        data.restore_priority_ratio = update.restore_priority_ratio;
+    }
+    if update.low_space_watermark.is_some() {
+        data.low_space_watermark = update.low_space_watermark;
+    }
+    if update.critical_space_watermark.is_some() {
+        data.critical_space_watermark = update.critical_space_watermark;
+    }
+    if update.critical_space_deny_backup.is_some() {
+        data.critical_space_deny_backup = update.critical_space_deny_backup;
+    }
+    if update.crypt_policy.is_some() {
+        data.crypt_policy = update.crypt_policy;
+    }
+    if update.min_retention_days.is_some() {
+        data.min_retention_days = update.min_retention_days;
+    }
+    if update.protect_new_backups.is_some() {
+        data.protect_new_backups = update.protect_new_backups;
+    }
+    if update.group_template.is_some() {
+        data.group_template = update.group_template;
+
+        let mut names = HashSet::new();
+        for template in data.group_templates()? {
+            if !names.insert(template.name.clone()) {
+                param_bail!(
+                    "group-template",
+                    "duplicate backup group template '{}'",
+                    template.name
+                );
+            }
+        }
+    }
+
+    if update.read_through_remote.is_some() {
+        data.read_through_remote = update.read_through_remote;
+    }
+    if update.read_through_store.is_some() {
+        data.read_through_store = update.read_through_store;
+    }
+    if update.read_through_cache_size.is_some() {
+        data.read_through_cache_size = update.read_through_cache_size;
+    }
+    if update.warm_up.is_some() {
+        data.warm_up = update.warm_up;
+    }
+
+    if let Some(remote) = &data.read_through_remote {
+        let (remote_config, _digest) = pbs_config::remote::config()?;
+        if remote_config.sections.get(remote).is_none() {
+            param_bail!("read-through-remote", "no such remote '{}'", remote);
+        }
+        if data.read_through_store.is_none() {
+            param_bail!(
+                "read-through-store",
+                "a read-through remote requires the datastore on the remote"
+            );
+        }
+    }
+
     let mut maintenance_mode_changed = false;
     if update.maintenance_mode.is_some() {
         maintenance_mode_changed = data.maintenance_mode != update.maintenance_mode;
@@ -411,10 +681,24 @@ pub fn update_datastore(
         data.set_maintenance_mode(maintenance_mode)?;
     }
 
+    let comes_online = was_offline
+        && !data
+            .get_maintenance_mode()
+            .map_or(false, |mode| mode.is_offline());
+    let warm_up = comes_online && data.warm_up.unwrap_or(false);
+
     config.set_data(&name, "datastore", &data)?;
 
     pbs_config::datastore::save_config(&config)?;
 
+    if warm_up {
+        let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+        let to_stdout = rpcenv.env_type() == RpcEnvironmentType::CLI;
+        if let Err(err) = crate::server::do_warm_up_job(&name, &auth_id, to_stdout) {
+            log::warn!("could not start warm-up of datastore '{name}' - {err}");
+        }
+    }
+
     // we want to reset the statefiles, to avoid an immediate action in some cases
     // (e.g. going from monthly to weekly in the second week of the month)
     if gc_schedule_changed {
@@ -0,0 +1,299 @@
+use ::serde::{Deserialize, Serialize};
+use anyhow::{bail, Error};
+use hex::FromHex;
+use serde_json::Value;
+
+use proxmox_router::{http_bail, ApiMethod, Permission, Router, RpcEnvironment};
+use proxmox_schema::{api, param_bail};
+use proxmox_section_config::SectionConfigData;
+
+use pbs_api_types::{
+    HolidayCalendarConfig, HolidayCalendarConfigUpdater, PruneJobConfig, ScheduleExclusion,
+    SyncJobConfig, TapeBackupJobConfig, VerificationJobConfig, HOLIDAY_CALENDAR_ID_SCHEMA,
+    PRIV_SYS_AUDIT, PRIV_SYS_MODIFY, PROXMOX_CONFIG_DIGEST_SCHEMA,
+};
+
+#[api(
+    input: {
+        properties: {},
+    },
+    returns: {
+        description: "The list of configured holiday calendars (with config digest).",
+        type: Array,
+        items: { type: HolidayCalendarConfig },
+    },
+    access: {
+        permission: &Permission::Privilege(&[], PRIV_SYS_AUDIT, false),
+    },
+)]
+/// List holiday calendars
+pub fn list_holiday_calendars(
+    _param: Value,
+    _info: &ApiMethod,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Vec<HolidayCalendarConfig>, Error> {
+    let (config, digest) = pbs_config::holiday::config()?;
+
+    let list: Vec<HolidayCalendarConfig> = config.convert_to_typed_array("calendar")?;
+
+    rpcenv["digest"] = hex::encode(digest).into();
+
+    Ok(list)
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            config: {
+                type: HolidayCalendarConfig,
+                flatten: true,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&[], PRIV_SYS_MODIFY, false),
+    },
+)]
+/// Create new holiday calendar.
+pub fn create_holiday_calendar(config: HolidayCalendarConfig) -> Result<(), Error> {
+    let _lock = pbs_config::holiday::lock_config()?;
+
+    let (mut section_config, _digest) = pbs_config::holiday::config()?;
+
+    if section_config.sections.get(&config.name).is_some() {
+        param_bail!("name", "holiday calendar '{}' already exists.", config.name);
+    }
+
+    section_config.set_data(&config.name, "calendar", &config)?;
+    crate::server::schedule::check_schedule_exclusions_with(&config.exclude, &section_config)?;
+
+    pbs_config::holiday::save_config(&section_config)?;
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            name: {
+                schema: HOLIDAY_CALENDAR_ID_SCHEMA,
+            },
+        },
+    },
+    returns: { type: HolidayCalendarConfig },
+    access: {
+        permission: &Permission::Privilege(&[], PRIV_SYS_AUDIT, false),
+    }
+)]
+/// Read holiday calendar configuration data.
+pub fn read_holiday_calendar(
+    name: String,
+    _info: &ApiMethod,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<HolidayCalendarConfig, Error> {
+    let (config, digest) = pbs_config::holiday::config()?;
+    let data: HolidayCalendarConfig = config.lookup("calendar", &name)?;
+    rpcenv["digest"] = hex::encode(digest).into();
+    Ok(data)
+}
+
+#[api()]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Deletable property name
+pub enum DeletableProperty {
+    /// Delete the comment property.
+    Comment,
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            name: {
+                schema: HOLIDAY_CALENDAR_ID_SCHEMA,
+            },
+            update: {
+                type: HolidayCalendarConfigUpdater,
+                flatten: true,
+            },
+            delete: {
+                description: "List of properties to delete.",
+                type: Array,
+                optional: true,
+                items: {
+                    type: DeletableProperty,
+                }
+            },
+            digest: {
+                optional: true,
+                schema: PROXMOX_CONFIG_DIGEST_SCHEMA,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&[], PRIV_SYS_MODIFY, false),
+    },
+)]
+/// Update holiday calendar configuration.
+pub fn update_holiday_calendar(
+    name: String,
+    update: HolidayCalendarConfigUpdater,
+    delete: Option<Vec<DeletableProperty>>,
+    digest: Option<String>,
+) -> Result<(), Error> {
+    let _lock = pbs_config::holiday::lock_config()?;
+
+    let (mut config, expected_digest) = pbs_config::holiday::config()?;
+
+    if let Some(ref digest) = digest {
+        let digest = <[u8; 32]>::from_hex(digest)?;
+        crate::tools::detect_modified_configuration_file(&digest, &expected_digest)?;
+    }
+
+    let mut data: HolidayCalendarConfig = config.lookup("calendar", &name)?;
+
+    if let Some(delete) = delete {
+        for delete_prop in delete {
+            match delete_prop {
+                DeletableProperty::Comment => {
+                    data.comment = None;
+                }
+            }
+        }
+    }
+
+    if let Some(comment) = update.comment {
+        let comment = comment.trim().to_string();
+        if comment.is_empty() {
+            data.comment = None;
+        } else {
+            data.comment = Some(comment);
+        }
+    }
+
+    if let Some(exclude) = update.exclude {
+        data.exclude = exclude;
+    }
+
+    config.set_data(&name, "calendar", &data)?;
+    crate::server::schedule::check_schedule_exclusions_with(&data.exclude, &config)?;
+
+    pbs_config::holiday::save_config(&config)?;
+
+    Ok(())
+}
+
+fn references_calendar(exclude: Option<&[ScheduleExclusion]>, name: &str) -> bool {
+    exclude
+        .unwrap_or_default()
+        .iter()
+        .any(|exclusion| matches!(exclusion, ScheduleExclusion::Calendar(other) if other == name))
+}
+
+// returns the holiday calendars and jobs referencing the holiday calendar `name`
+fn holiday_calendar_references(
+    name: &str,
+    calendars: &SectionConfigData,
+) -> Result<Vec<String>, Error> {
+    let mut references = Vec::new();
+
+    let list: Vec<HolidayCalendarConfig> = calendars.convert_to_typed_array("calendar")?;
+    for calendar in list {
+        if calendar.name != name && references_calendar(Some(calendar.exclude.as_slice()), name) {
+            references.push(format!("holiday calendar '{}'", calendar.name));
+        }
+    }
+
+    let (config, _digest) = pbs_config::sync::config()?;
+    let list: Vec<SyncJobConfig> = config.convert_to_typed_array("sync")?;
+    for job in list {
+        if references_calendar(job.schedule_exclude.as_deref(), name) {
+            references.push(format!("sync job '{}'", job.id));
+        }
+    }
+
+    let (config, _digest) = pbs_config::verify::config()?;
+    let list: Vec<VerificationJobConfig> = config.convert_to_typed_array("verification")?;
+    for job in list {
+        if references_calendar(job.schedule_exclude.as_deref(), name) {
+            references.push(format!("verification job '{}'", job.id));
+        }
+    }
+
+    let (config, _digest) = pbs_config::prune::config()?;
+    let list: Vec<PruneJobConfig> = config.convert_to_typed_array("prune")?;
+    for job in list {
+        if references_calendar(job.schedule_exclude.as_deref(), name) {
+            references.push(format!("prune job '{}'", job.id));
+        }
+    }
+
+    let (config, _digest) = pbs_config::tape_job::config()?;
+    let list: Vec<TapeBackupJobConfig> = config.convert_to_typed_array("backup")?;
+    for job in list {
+        if references_calendar(job.schedule_exclude.as_deref(), name) {
+            references.push(format!("tape backup job '{}'", job.id));
+        }
+    }
+
+    Ok(references)
+}
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            name: {
+                schema: HOLIDAY_CALENDAR_ID_SCHEMA,
+            },
+            digest: {
+                optional: true,
+                schema: PROXMOX_CONFIG_DIGEST_SCHEMA,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&[], PRIV_SYS_MODIFY, false),
+    },
+)]
+/// Remove a holiday calendar from the configuration file.
+pub fn delete_holiday_calendar(name: String, digest: Option<String>) -> Result<(), Error> {
+    let _lock = pbs_config::holiday::lock_config()?;
+
+    let (mut config, expected_digest) = pbs_config::holiday::config()?;
+
+    if let Some(ref digest) = digest {
+        let digest = <[u8; 32]>::from_hex(digest)?;
+        crate::tools::detect_modified_configuration_file(&digest, &expected_digest)?;
+    }
+
+    match config.sections.get(&name) {
+        Some(_) => {
+            let references = holiday_calendar_references(&name, &config)?;
+            if !references.is_empty() {
+                bail!(
+                    "holiday calendar '{name}' is still in use by {}",
+                    references.join(", ")
+                );
+            }
+            config.sections.remove(&name);
+        }
+        None => http_bail!(NOT_FOUND, "holiday calendar '{}' does not exist.", name),
+    }
+
+    pbs_config::holiday::save_config(&config)?;
+
+    Ok(())
+}
+
+const ITEM_ROUTER: Router = Router::new()
+    .get(&API_METHOD_READ_HOLIDAY_CALENDAR)
+    .put(&API_METHOD_UPDATE_HOLIDAY_CALENDAR)
+    .delete(&API_METHOD_DELETE_HOLIDAY_CALENDAR);
+
+pub const ROUTER: Router = Router::new()
+    .get(&API_METHOD_LIST_HOLIDAY_CALENDARS)
+    .post(&API_METHOD_CREATE_HOLIDAY_CALENDAR)
+    .match_all("name", &ITEM_ROUTER);
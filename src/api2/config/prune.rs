@@ -64,6 +64,8 @@ pub fn do_create_prune_job(
 ) -> Result<(), Error> {
     let _lock = prune::lock_config()?;
 
+    crate::server::schedule::check_schedule_exclusions(config.schedule_exclude.as_deref())?;
+
     let (mut section_config, _digest) = prune::config()?;
 
     if section_config.sections.get(&config.id).is_some() {
@@ -152,6 +154,8 @@ pub fn read_prune_job(
 pub enum DeletableProperty {
     /// Delete the comment.
     Comment,
+    /// Delete the schedule exclusions.
+    ScheduleExclude,
     /// Unset the disable flag.
     Disable,
     /// Reset the namespace to the root namespace.
@@ -170,6 +174,8 @@ pub enum DeletableProperty {
     KeepMonthly,
     /// Delete number of yearly backups to keep.
     KeepYearly,
+    /// Delete the retry policy.
+    Retry,
 }
 
 #[api(
@@ -234,6 +240,9 @@ pub fn update_prune_job(
                 DeletableProperty::Comment => {
                     data.comment = None;
                 }
+                DeletableProperty::ScheduleExclude => {
+                    data.schedule_exclude = None;
+                }
                 DeletableProperty::Disable => {
                     data.disable = false;
                 }
@@ -261,6 +270,9 @@ pub fn update_prune_job(
                 DeletableProperty::KeepYearly => {
                     data.options.keep.keep_yearly = None;
                 }
+                DeletableProperty::Retry => {
+                    data.retry = None;
+                }
             }
         }
     }
@@ -287,6 +299,10 @@ pub fn update_prune_job(
         data.schedule = schedule;
     }
 
+    if update.schedule_exclude.is_some() {
+        data.schedule_exclude = update.schedule_exclude;
+    }
+
     if let Some(max_depth) = update.options.max_depth {
         if max_depth <= pbs_api_types::MAX_NAMESPACE_DEPTH {
             data.options.max_depth = Some(max_depth);
@@ -299,6 +315,9 @@ pub fn update_prune_job(
     if let Some(value) = update.comment {
         data.comment = Some(value);
     }
+    if let Some(value) = update.retry {
+        data.retry = Some(value);
+    }
     if let Some(value) = update.options.keep.keep_last {
         data.options.keep.keep_last = Some(value);
     }
@@ -318,6 +337,8 @@ pub fn update_prune_job(
         data.options.keep.keep_yearly = Some(value);
     }
 
+    crate::server::schedule::check_schedule_exclusions(data.schedule_exclude.as_deref())?;
+
     config.set_data(&id, "prune", &data)?;
 
     prune::save_config(&config)?;
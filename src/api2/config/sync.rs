@@ -8,8 +8,8 @@ use proxmox_schema::{api, param_bail};
 
 use pbs_api_types::{
     Authid, SyncJobConfig, SyncJobConfigUpdater, JOB_ID_SCHEMA, PRIV_DATASTORE_AUDIT,
-    PRIV_DATASTORE_BACKUP, PRIV_DATASTORE_MODIFY, PRIV_DATASTORE_PRUNE, PRIV_REMOTE_AUDIT,
-    PRIV_REMOTE_READ, PROXMOX_CONFIG_DIGEST_SCHEMA,
+    PRIV_DATASTORE_BACKUP, PRIV_DATASTORE_MODIFY, PRIV_DATASTORE_PRUNE, PRIV_DATASTORE_VERIFY,
+    PRIV_REMOTE_AUDIT, PRIV_REMOTE_READ, PROXMOX_CONFIG_DIGEST_SCHEMA,
 };
 use pbs_config::sync;
 
@@ -54,6 +54,12 @@ pub fn check_sync_job_modify_access(
         }
     }
 
+    if let Some(true) = job.verify_new {
+        if ns_anchor_privs & PRIV_DATASTORE_VERIFY == 0 {
+            return false;
+        }
+    }
+
     let correct_owner = match job.owner {
         Some(ref owner) => {
             owner == auth_id
@@ -152,6 +158,8 @@ pub fn create_sync_job(
         }
     }
 
+    crate::server::schedule::check_schedule_exclusions(config.schedule_exclude.as_deref())?;
+
     let (mut section_config, _digest) = sync::config()?;
 
     if section_config.sections.get(&config.id).is_some() {
@@ -211,6 +219,8 @@ pub enum DeletableProperty {
     Comment,
     /// Delete the job schedule.
     Schedule,
+    /// Delete the schedule exclusions.
+    ScheduleExclude,
     /// Delete the remove-vanished flag.
     RemoveVanished,
     /// Delete the group_filter property.
@@ -231,6 +241,10 @@ pub enum DeletableProperty {
     MaxDepth,
     /// Delete the transfer_last property,
     TransferLast,
+    /// Delete the verify_new property,
+    VerifyNew,
+    /// Delete the retry policy.
+    Retry,
 }
 
 #[api(
@@ -301,6 +315,9 @@ pub fn update_sync_job(
                 DeletableProperty::Schedule => {
                     data.schedule = None;
                 }
+                DeletableProperty::ScheduleExclude => {
+                    data.schedule_exclude = None;
+                }
                 DeletableProperty::RemoveVanished => {
                     data.remove_vanished = None;
                 }
@@ -331,6 +348,12 @@ pub fn update_sync_job(
                 DeletableProperty::TransferLast => {
                     data.transfer_last = None;
                 }
+                DeletableProperty::VerifyNew => {
+                    data.verify_new = None;
+                }
+                DeletableProperty::Retry => {
+                    data.retry = None;
+                }
             }
         }
     }
@@ -368,6 +391,12 @@ pub fn update_sync_job(
     if let Some(transfer_last) = update.transfer_last {
         data.transfer_last = Some(transfer_last);
     }
+    if let Some(verify_new) = update.verify_new {
+        data.verify_new = Some(verify_new);
+    }
+    if let Some(retry) = update.retry {
+        data.retry = Some(retry);
+    }
 
     if update.limit.rate_in.is_some() {
         data.limit.rate_in = update.limit.rate_in;
@@ -389,6 +418,9 @@ pub fn update_sync_job(
     if update.schedule.is_some() {
         data.schedule = update.schedule;
     }
+    if update.schedule_exclude.is_some() {
+        data.schedule_exclude = update.schedule_exclude;
+    }
     if update.remove_vanished.is_some() {
         data.remove_vanished = update.remove_vanished;
     }
@@ -409,6 +441,8 @@ pub fn update_sync_job(
         bail!("permission check failed");
     }
 
+    crate::server::schedule::check_schedule_exclusions(data.schedule_exclude.as_deref())?;
+
     config.set_data(&id, "sync", &data)?;
 
     sync::save_config(&config)?;
@@ -531,8 +565,11 @@ acl:1:/remote/remote1/remotestore1:write@pbs:RemoteSyncOperator
         max_depth: None,
         group_filter: None,
         schedule: None,
+        schedule_exclude: None,
         limit: pbs_api_types::RateLimitConfig::default(), // no limit
         transfer_last: None,
+        verify_new: None,
+        retry: None,
     };
 
     // should work without ACLs
@@ -662,5 +699,27 @@ acl:1:/remote/remote1/remotestore1:write@pbs:RemoteSyncOperator
         &job
     ));
 
+    // verifying new snapshots requires Datastore.Verify
+    job.verify_new = Some(true);
+    assert!(check_sync_job_modify_access(
+        &user_info,
+        &write_auth_id,
+        &job
+    ));
+    job.store = "localstore1".to_string();
+    job.owner = Some(write_auth_id.clone());
+    job.remove_vanished = None;
+    assert!(!check_sync_job_modify_access(
+        &user_info,
+        &write_auth_id,
+        &job
+    ));
+    job.verify_new = None;
+    assert!(check_sync_job_modify_access(
+        &user_info,
+        &write_auth_id,
+        &job
+    ));
+
     Ok(())
 }
@@ -73,6 +73,8 @@ pub fn create_tape_backup_job(
 ) -> Result<(), Error> {
     let _lock = pbs_config::tape_job::lock()?;
 
+    crate::server::schedule::check_schedule_exclusions(job.schedule_exclude.as_deref())?;
+
     let (mut config, _digest) = pbs_config::tape_job::config()?;
 
     if config.sections.get(&job.id).is_some() {
@@ -124,6 +126,8 @@ pub enum DeletableProperty {
     Comment,
     /// Delete the job schedule.
     Schedule,
+    /// Delete the schedule exclusions.
+    ScheduleExclude,
     /// Delete the eject-media property
     EjectMedia,
     /// Delete the export-media-set property
@@ -140,6 +144,8 @@ pub enum DeletableProperty {
     MaxDepth,
     /// Delete the 'ns' property
     Ns,
+    /// Delete the 'retry' property
+    Retry,
 }
 
 #[api(
@@ -210,6 +216,9 @@ pub fn update_tape_backup_job(
                 DeletableProperty::Schedule => {
                     data.schedule = None;
                 }
+                DeletableProperty::ScheduleExclude => {
+                    data.schedule_exclude = None;
+                }
                 DeletableProperty::Comment => {
                     data.comment = None;
                 }
@@ -222,6 +231,9 @@ pub fn update_tape_backup_job(
                 DeletableProperty::Ns => {
                     data.setup.ns = None;
                 }
+                DeletableProperty::Retry => {
+                    data.retry = None;
+                }
             }
         }
     }
@@ -265,6 +277,12 @@ pub fn update_tape_backup_job(
     if update.schedule.is_some() {
         data.schedule = update.schedule;
     }
+    if update.schedule_exclude.is_some() {
+        data.schedule_exclude = update.schedule_exclude;
+    }
+    if update.retry.is_some() {
+        data.retry = update.retry;
+    }
 
     if let Some(comment) = update.comment {
         let comment = comment.trim();
@@ -275,6 +293,8 @@ pub fn update_tape_backup_job(
         }
     }
 
+    crate::server::schedule::check_schedule_exclusions(data.schedule_exclude.as_deref())?;
+
     config.set_data(&id, "backup", &data)?;
 
     pbs_config::tape_job::save_config(&config)?;
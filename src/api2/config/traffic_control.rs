@@ -1,10 +1,11 @@
 use ::serde::{Deserialize, Serialize};
-use anyhow::Error;
+use anyhow::{bail, Error};
 use hex::FromHex;
 use serde_json::Value;
 
 use proxmox_router::{http_bail, ApiMethod, Permission, Router, RpcEnvironment};
 use proxmox_schema::{api, param_bail};
+use proxmox_section_config::SectionConfigData;
 
 use pbs_api_types::{
     TrafficControlRule, TrafficControlRuleUpdater, PRIV_SYS_AUDIT, PRIV_SYS_MODIFY,
@@ -39,6 +40,31 @@ pub fn list_traffic_controls(
     Ok(list)
 }
 
+/// Maximum depth of nested traffic control rules
+pub const MAX_TRAFFIC_CONTROL_DEPTH: usize = 8;
+
+/// Check that `parent` exists and that making it the parent of `name` does not create a loop.
+fn check_parent(config: &SectionConfigData, name: &str, parent: &str) -> Result<(), Error> {
+    let mut current = parent.to_string();
+    for _ in 0..MAX_TRAFFIC_CONTROL_DEPTH {
+        if current == name {
+            param_bail!("parent", "rule '{name}' cannot be its own ancestor");
+        }
+        let rule: TrafficControlRule = match config.lookup("rule", &current) {
+            Ok(rule) => rule,
+            Err(_) => param_bail!("parent", "traffic control rule '{current}' does not exist"),
+        };
+        match rule.parent {
+            Some(parent) => current = parent,
+            None => return Ok(()),
+        }
+    }
+    param_bail!(
+        "parent",
+        "traffic control rules nested deeper than {MAX_TRAFFIC_CONTROL_DEPTH} levels"
+    );
+}
+
 #[api(
     protected: true,
     input: {
@@ -67,6 +93,10 @@ pub fn create_traffic_control(config: TrafficControlRule) -> Result<(), Error> {
         );
     }
 
+    if let Some(parent) = &config.parent {
+        check_parent(&section_config, &config.name, parent)?;
+    }
+
     section_config.set_data(&config.name, "rule", &config)?;
 
     pbs_config::traffic_control::save_config(&section_config)?;
@@ -116,6 +146,8 @@ pub enum DeletableProperty {
     Comment,
     /// Delete the timeframe property
     Timeframe,
+    /// Delete the parent property
+    Parent,
 }
 
 // fixme: use  TrafficControlUpdater
@@ -187,6 +219,9 @@ pub fn update_traffic_control(
                 DeletableProperty::Timeframe => {
                     data.timeframe = None;
                 }
+                DeletableProperty::Parent => {
+                    data.parent = None;
+                }
             }
         }
     }
@@ -222,6 +257,10 @@ pub fn update_traffic_control(
     if update.timeframe.is_some() {
         data.timeframe = update.timeframe;
     }
+    if let Some(parent) = update.parent {
+        check_parent(&config, &name, &parent)?;
+        data.parent = Some(parent);
+    }
 
     config.set_data(&name, "rule", &data)?;
 
@@ -258,6 +297,17 @@ pub fn delete_traffic_control(name: String, digest: Option<String>) -> Result<()
         crate::tools::detect_modified_configuration_file(&digest, &expected_digest)?;
     }
 
+    let rules: Vec<TrafficControlRule> = config.convert_to_typed_array("rule")?;
+    if let Some(child) = rules
+        .iter()
+        .find(|rule| rule.parent.as_deref() == Some(name.as_str()))
+    {
+        bail!(
+            "traffic control rule '{name}' is the parent of rule '{}'",
+            child.name
+        );
+    }
+
     match config.sections.get(&name) {
         Some(_) => {
             config.sections.remove(&name);
@@ -1,5 +1,5 @@
 use ::serde::{Deserialize, Serialize};
-use anyhow::Error;
+use anyhow::{format_err, Error};
 use hex::FromHex;
 use serde_json::Value;
 
@@ -7,13 +7,53 @@ use proxmox_router::{http_bail, Permission, Router, RpcEnvironment};
 use proxmox_schema::{api, param_bail};
 
 use pbs_api_types::{
-    Authid, VerificationJobConfig, VerificationJobConfigUpdater, JOB_ID_SCHEMA,
-    PRIV_DATASTORE_AUDIT, PRIV_DATASTORE_VERIFY, PROXMOX_CONFIG_DIGEST_SCHEMA,
+    Authid, Remote, VerificationJobConfig, VerificationJobConfigUpdater, JOB_ID_SCHEMA,
+    PRIV_DATASTORE_AUDIT, PRIV_DATASTORE_VERIFY, PRIV_REMOTE_AUDIT, PRIV_SYS_MODIFY,
+    PROXMOX_CONFIG_DIGEST_SCHEMA,
 };
 use pbs_config::verify;
 
 use pbs_config::CachedUserInfo;
 
+// the proxy sends requests to the webhook, so only allow who may configure notification endpoints
+fn check_attestation_webhook(
+    user_info: &CachedUserInfo,
+    auth_id: &Authid,
+    url: &str,
+) -> Result<(), Error> {
+    user_info.check_privs(
+        auth_id,
+        &["system", "notifications"],
+        PRIV_SYS_MODIFY,
+        false,
+    )?;
+
+    let uri: http::Uri = url
+        .parse()
+        .map_err(|err| format_err!("invalid attestation webhook URL '{url}' - {err}"))?;
+    match uri.scheme_str() {
+        Some("http" | "https") if uri.host().is_some() => Ok(()),
+        _ => param_bail!(
+            "attestation-webhook",
+            "invalid attestation webhook URL '{url}', expected an http or https URL"
+        ),
+    }
+}
+
+// verify reports of a replica are checked against the certificate fingerprint of its remote
+fn check_replica_remote(replica: &str) -> Result<(), Error> {
+    let (remote_config, _digest) = pbs_config::remote::config()?;
+    let remote: Remote = remote_config.lookup("remote", replica)?;
+    if remote.config.fingerprint.is_none() {
+        param_bail!(
+            "replica",
+            "remote '{replica}' has no fingerprint configured, which is required to check its \
+            verify reports"
+        );
+    }
+    Ok(())
+}
+
 #[api(
     input: {
         properties: {},
@@ -68,7 +108,8 @@ pub fn list_verification_jobs(
     },
     access: {
         permission: &Permission::Anybody,
-        description: "Requires Datastore.Verify on job's datastore.",
+        description: "Requires Datastore.Verify on job's datastore. Setting an attestation \
+            webhook requires Sys.Modify on '/system/notifications'.",
     },
 )]
 /// Create a new verification job.
@@ -80,9 +121,18 @@ pub fn create_verification_job(
     let user_info = CachedUserInfo::new()?;
 
     user_info.check_privs(&auth_id, &config.acl_path(), PRIV_DATASTORE_VERIFY, false)?;
+    if let Some(replica) = &config.replica {
+        user_info.check_privs(&auth_id, &["remote", replica], PRIV_REMOTE_AUDIT, false)?;
+        check_replica_remote(replica)?;
+    }
+    if let Some(url) = &config.attestation_webhook {
+        check_attestation_webhook(&user_info, &auth_id, url)?;
+    }
 
     let _lock = verify::lock_config()?;
 
+    crate::server::schedule::check_schedule_exclusions(config.schedule_exclude.as_deref())?;
+
     let (mut section_config, _digest) = verify::config()?;
 
     if section_config.sections.get(&config.id).is_some() {
@@ -143,12 +193,30 @@ pub enum DeletableProperty {
     Comment,
     /// Delete the job schedule.
     Schedule,
+    /// Delete the schedule exclusions.
+    ScheduleExclude,
     /// Delete outdated after property.
     OutdatedAfter,
     /// Delete namespace property, defaulting to root namespace then.
     Ns,
     /// Delete max-depth property, defaulting to full recursion again
     MaxDepth,
+    /// Delete replica property, verifying all snapshots locally again
+    Replica,
+    /// Delete replica-store property, defaulting to the local datastore name
+    ReplicaStore,
+    /// Delete replica-ns property, defaulting to the local namespace
+    ReplicaNs,
+    /// Delete attestation property, no longer storing attestations
+    Attestation,
+    /// Delete attestation-webhook property
+    AttestationWebhook,
+    /// Delete junit-report property, no longer writing JUnit XML reports
+    JunitReport,
+    /// Delete exclude property, verifying all groups again
+    Exclude,
+    /// Delete priority property, verifying all groups in their default order
+    Priority,
 }
 
 #[api(
@@ -178,7 +246,8 @@ pub enum DeletableProperty {
     },
     access: {
         permission: &Permission::Anybody,
-        description: "Requires Datastore.Verify on job's datastore.",
+        description: "Requires Datastore.Verify on job's datastore. Setting an attestation \
+            webhook requires Sys.Modify on '/system/notifications'.",
     },
 )]
 /// Update verification job config.
@@ -223,12 +292,39 @@ pub fn update_verification_job(
                 DeletableProperty::Schedule => {
                     data.schedule = None;
                 }
+                DeletableProperty::ScheduleExclude => {
+                    data.schedule_exclude = None;
+                }
                 DeletableProperty::Ns => {
                     data.ns = None;
                 }
                 DeletableProperty::MaxDepth => {
                     data.max_depth = None;
                 }
+                DeletableProperty::Replica => {
+                    data.replica = None;
+                }
+                DeletableProperty::ReplicaStore => {
+                    data.replica_store = None;
+                }
+                DeletableProperty::ReplicaNs => {
+                    data.replica_ns = None;
+                }
+                DeletableProperty::Attestation => {
+                    data.attestation = None;
+                }
+                DeletableProperty::AttestationWebhook => {
+                    data.attestation_webhook = None;
+                }
+                DeletableProperty::JunitReport => {
+                    data.junit_report = None;
+                }
+                DeletableProperty::Exclude => {
+                    data.exclude = None;
+                }
+                DeletableProperty::Priority => {
+                    data.priority = None;
+                }
             }
         }
     }
@@ -256,6 +352,9 @@ pub fn update_verification_job(
     if update.schedule.is_some() {
         data.schedule = update.schedule;
     }
+    if update.schedule_exclude.is_some() {
+        data.schedule_exclude = update.schedule_exclude;
+    }
     if let Some(ns) = update.ns {
         if !ns.is_root() {
             data.ns = Some(ns);
@@ -267,9 +366,39 @@ pub fn update_verification_job(
         }
     }
 
+    if let Some(replica) = update.replica {
+        user_info.check_privs(&auth_id, &["remote", &replica], PRIV_REMOTE_AUDIT, false)?;
+        check_replica_remote(&replica)?;
+        data.replica = Some(replica);
+    }
+    if update.replica_store.is_some() {
+        data.replica_store = update.replica_store;
+    }
+    if let Some(ns) = update.replica_ns {
+        data.replica_ns = if ns.is_root() { None } else { Some(ns) };
+    }
+    if update.attestation.is_some() {
+        data.attestation = update.attestation;
+    }
+    if let Some(url) = update.attestation_webhook {
+        check_attestation_webhook(&user_info, &auth_id, &url)?;
+        data.attestation_webhook = Some(url);
+    }
+    if update.junit_report.is_some() {
+        data.junit_report = update.junit_report;
+    }
+    if update.exclude.is_some() {
+        data.exclude = update.exclude;
+    }
+    if update.priority.is_some() {
+        data.priority = update.priority;
+    }
+
     // check new store and NS
     user_info.check_privs(&auth_id, &data.acl_path(), PRIV_DATASTORE_VERIFY, true)?;
 
+    crate::server::schedule::check_schedule_exclusions(data.schedule_exclude.as_deref())?;
+
     config.set_data(&id, "verification", &data)?;
 
     verify::save_config(&config)?;
@@ -330,6 +459,8 @@ pub fn delete_verification_job(
     verify::save_config(&config)?;
 
     crate::server::jobstate::remove_state_file("verificationjob", &id)?;
+    crate::backup::verify_report::remove_verify_attestations(&id)?;
+    crate::backup::verify_result::remove_verify_job_result(&id)?;
 
     Ok(())
 }
@@ -27,3 +27,15 @@ pub async fn create_download_response(path: PathBuf) -> Result<Response<Body>, E
         .body(body)
         .unwrap())
 }
+
+/// Create a `503 Service Unavailable` response telling the client when to retry.
+///
+/// Used to turn away new sessions while the server or datastore cannot accept them, clients
+/// are expected to honor the `Retry-After` header (in seconds).
+pub fn service_unavailable_response(msg: String, retry_after: i64) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::SERVICE_UNAVAILABLE)
+        .header(header::RETRY_AFTER, retry_after.max(1))
+        .body(Body::from(msg))
+        .unwrap()
+}
@@ -36,3 +36,20 @@ const SUBDIRS: SubdirMap = &sorted!([
 pub const ROUTER: Router = Router::new()
     .get(&list_subdirs_api_method!(SUBDIRS))
     .subdirs(SUBDIRS);
+
+#[sortable]
+const RESTORE_SUBDIRS: SubdirMap = &sorted!([
+    ("access", &access::RESTORE_ROUTER),
+    ("admin", &admin::RESTORE_ROUTER),
+    ("ping", &ping::ROUTER),
+    ("reader", &reader::ROUTER),
+    ("version", &version::ROUTER),
+]);
+
+/// The API served by the restore-only listener of the proxy.
+///
+/// It allows logging in, browsing and restoring backups, but no backups, configuration or
+/// administration.
+pub const RESTORE_ROUTER: Router = Router::new()
+    .get(&list_subdirs_api_method!(RESTORE_SUBDIRS))
+    .subdirs(RESTORE_SUBDIRS);
@@ -519,6 +519,13 @@ pub fn renew_acme_cert(force: bool, rpcenv: &mut dyn RpcEnvironment) -> Result<S
     spawn_certificate_worker("acme-renew-cert", force, rpcenv)
 }
 
+/// Returns the expiry time of the proxy certificate.
+pub fn cert_expiry() -> Result<i64, Error> {
+    let cert = pem_to_cert_info(get_certificate_pem()?.as_bytes())?;
+    cert.not_after_unix()
+        .map_err(|err| format_err!("Failed to get certificate expiration date: {}", err))
+}
+
 /// Check whether the current certificate expires within the next 30 days.
 pub fn cert_expires_soon() -> Result<bool, Error> {
     let cert = pem_to_cert_info(get_certificate_pem()?.as_bytes())?;
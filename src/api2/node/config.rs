@@ -1,18 +1,24 @@
 use ::serde::{Deserialize, Serialize};
-use anyhow::Error;
+use anyhow::{bail, Error};
 use hex::FromHex;
 
-use proxmox_router::{Permission, Router, RpcEnvironment};
+use proxmox_router::{Permission, Router, RpcEnvironment, SubdirMap};
 use proxmox_schema::api;
 
-use pbs_api_types::{NODE_SCHEMA, PRIV_SYS_AUDIT, PRIV_SYS_MODIFY};
+use pbs_api_types::{ConfigGeneration, NODE_SCHEMA, PRIV_SYS_AUDIT, PRIV_SYS_MODIFY};
 
 use crate::api2::node::apt::update_apt_proxy_config;
 use crate::config::node::{NodeConfig, NodeConfigUpdater};
 
+const SUBDIRS: SubdirMap = &[(
+    "generations",
+    &Router::new().get(&API_METHOD_GET_CONFIG_GENERATIONS),
+)];
+
 pub const ROUTER: Router = Router::new()
     .get(&API_METHOD_GET_NODE_CONFIG)
-    .put(&API_METHOD_UPDATE_NODE_CONFIG);
+    .put(&API_METHOD_UPDATE_NODE_CONFIG)
+    .subdirs(SUBDIRS);
 
 #[api(
     input: {
@@ -34,6 +40,28 @@ pub fn get_node_config(rpcenv: &mut dyn RpcEnvironment) -> Result<NodeConfig, Er
     Ok(config)
 }
 
+#[api(
+    input: {
+        properties: {
+            node: { schema: NODE_SCHEMA },
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&["system"], PRIV_SYS_AUDIT, false),
+    },
+    returns: {
+        description: "Generations of the configuration files.",
+        type: Array,
+        items: { type: ConfigGeneration },
+    },
+)]
+/// Get the generations of the configuration files and the ones loaded by the proxy.
+///
+/// Generations increase on every change, configurations are reloaded within seconds.
+pub fn get_config_generations() -> Result<Vec<ConfigGeneration>, Error> {
+    crate::server::config_watch::config_generations()
+}
+
 #[api()]
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
@@ -55,6 +83,14 @@ pub enum DeletableProperty {
     HttpProxy,
     /// Delete the email-from property.
     EmailFrom,
+    /// Delete the email-reply-to property.
+    EmailReplyTo,
+    /// Delete the email-footer property.
+    EmailFooter,
+    /// Delete the email-subject-tag property.
+    EmailSubjectTag,
+    /// Delete the email-subject-nodename property.
+    EmailSubjectNodename,
     /// Delete the ciphers-tls-1.3 property.
     #[serde(rename = "ciphers-tls-1.3")]
     CiphersTls1_3,
@@ -67,6 +103,28 @@ pub enum DeletableProperty {
     Description,
     /// Delete the task-log-max-days property
     TaskLogMaxDays,
+    /// Delete the port property.
+    Port,
+    /// Delete the restore-port property.
+    RestorePort,
+    /// Delete the login-notify-pam property.
+    LoginNotifyPam,
+    /// Delete the login-notify-pbs property.
+    LoginNotifyPbs,
+    /// Delete the local-api-users property.
+    LocalApiUsers,
+    /// Delete the max-warm-reader-sessions property.
+    MaxWarmReaderSessions,
+    /// Delete the status-page-path property.
+    StatusPagePath,
+    /// Delete the status-page-rate-limit property.
+    StatusPageRateLimit,
+    /// Delete the login-max-failures property.
+    LoginMaxFailures,
+    /// Delete the login-failure-window property.
+    LoginFailureWindow,
+    /// Delete the login-lockout-time property.
+    LoginLockoutTime,
 }
 
 #[api(
@@ -140,6 +198,18 @@ pub fn update_node_config(
                 DeletableProperty::EmailFrom => {
                     config.email_from = None;
                 }
+                DeletableProperty::EmailReplyTo => {
+                    config.email_reply_to = None;
+                }
+                DeletableProperty::EmailFooter => {
+                    config.email_footer = None;
+                }
+                DeletableProperty::EmailSubjectTag => {
+                    config.email_subject_tag = None;
+                }
+                DeletableProperty::EmailSubjectNodename => {
+                    config.email_subject_nodename = None;
+                }
                 DeletableProperty::CiphersTls1_3 => {
                     config.ciphers_tls_1_3 = None;
                 }
@@ -155,6 +225,39 @@ pub fn update_node_config(
                 DeletableProperty::TaskLogMaxDays => {
                     config.task_log_max_days = None;
                 }
+                DeletableProperty::Port => {
+                    config.port = None;
+                }
+                DeletableProperty::RestorePort => {
+                    config.restore_port = None;
+                }
+                DeletableProperty::LoginNotifyPam => {
+                    config.login_notify_pam = None;
+                }
+                DeletableProperty::LoginNotifyPbs => {
+                    config.login_notify_pbs = None;
+                }
+                DeletableProperty::LocalApiUsers => {
+                    config.local_api_users = None;
+                }
+                DeletableProperty::MaxWarmReaderSessions => {
+                    config.max_warm_reader_sessions = None;
+                }
+                DeletableProperty::StatusPagePath => {
+                    config.status_page_path = None;
+                }
+                DeletableProperty::StatusPageRateLimit => {
+                    config.status_page_rate_limit = None;
+                }
+                DeletableProperty::LoginMaxFailures => {
+                    config.login_max_failures = None;
+                }
+                DeletableProperty::LoginFailureWindow => {
+                    config.login_failure_window = None;
+                }
+                DeletableProperty::LoginLockoutTime => {
+                    config.login_lockout_time = None;
+                }
             }
         }
     }
@@ -183,6 +286,18 @@ pub fn update_node_config(
     if update.email_from.is_some() {
         config.email_from = update.email_from;
     }
+    if update.email_reply_to.is_some() {
+        config.email_reply_to = update.email_reply_to;
+    }
+    if update.email_footer.is_some() {
+        config.email_footer = update.email_footer;
+    }
+    if update.email_subject_tag.is_some() {
+        config.email_subject_tag = update.email_subject_tag;
+    }
+    if update.email_subject_nodename.is_some() {
+        config.email_subject_nodename = update.email_subject_nodename;
+    }
     if update.ciphers_tls_1_3.is_some() {
         config.ciphers_tls_1_3 = update.ciphers_tls_1_3;
     }
@@ -198,6 +313,46 @@ pub fn update_node_config(
     if update.task_log_max_days.is_some() {
         config.task_log_max_days = update.task_log_max_days;
     }
+    if update.port.is_some() {
+        config.port = update.port;
+    }
+    if update.restore_port.is_some() {
+        config.restore_port = update.restore_port;
+    }
+    if update.login_notify_pam.is_some() {
+        config.login_notify_pam = update.login_notify_pam;
+    }
+    if update.login_notify_pbs.is_some() {
+        config.login_notify_pbs = update.login_notify_pbs;
+    }
+    if update.local_api_users.is_some() {
+        config.local_api_users = update.local_api_users;
+    }
+    if update.max_warm_reader_sessions.is_some() {
+        config.max_warm_reader_sessions = update.max_warm_reader_sessions;
+    }
+    if update.status_page_path.is_some() {
+        config.status_page_path = update.status_page_path;
+    }
+    if update.status_page_rate_limit.is_some() {
+        config.status_page_rate_limit = update.status_page_rate_limit;
+    }
+    if update.login_max_failures.is_some() {
+        config.login_max_failures = update.login_max_failures;
+    }
+    if update.login_failure_window.is_some() {
+        config.login_failure_window = update.login_failure_window;
+    }
+    if update.login_lockout_time.is_some() {
+        config.login_lockout_time = update.login_lockout_time;
+    }
+
+    let proxy_port = config
+        .port
+        .unwrap_or(crate::config::node::DEFAULT_PROXY_PORT);
+    if config.restore_port == Some(proxy_port) {
+        bail!("the restore port must differ from the port of the proxy");
+    }
 
     crate::config::node::save_config(&config)?;
 
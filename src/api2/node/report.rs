@@ -1,12 +1,19 @@
 use anyhow::Error;
+use futures::FutureExt;
+use http::request::Parts;
+use http::{header, Response, StatusCode};
+use hyper::Body;
 use serde_json::{json, Value};
 
-use proxmox_router::{ApiMethod, Permission, Router, RpcEnvironment};
-use proxmox_schema::api;
+use proxmox_router::{
+    ApiHandler, ApiMethod, ApiResponseFuture, Permission, Router, RpcEnvironment, SubdirMap,
+};
+use proxmox_schema::{api, ObjectSchema};
+use proxmox_sortable_macro::sortable;
 
 use pbs_api_types::{NODE_SCHEMA, PRIV_SYS_AUDIT};
 
-use crate::server::generate_report;
+use crate::server::{generate_report, generate_report_bundle};
 
 #[api(
     input: {
@@ -33,4 +40,51 @@ fn get_report(
     Ok(json!(generate_report()))
 }
 
-pub const ROUTER: Router = Router::new().get(&API_METHOD_GET_REPORT);
+#[sortable]
+pub const API_METHOD_DOWNLOAD_REPORT_BUNDLE: ApiMethod = ApiMethod::new(
+    &ApiHandler::AsyncHttp(&download_report_bundle),
+    &ObjectSchema::new(
+        "Download a support bundle (.tar.zst) containing the report and recent failed task logs.",
+        &sorted!([("node", false, &NODE_SCHEMA)]),
+    ),
+)
+.access(
+    None,
+    &Permission::Privilege(&["system", "status"], PRIV_SYS_AUDIT, false),
+);
+
+fn download_report_bundle(
+    _parts: Parts,
+    _req_body: Body,
+    _param: Value,
+    _info: &ApiMethod,
+    _rpcenv: Box<dyn RpcEnvironment>,
+) -> ApiResponseFuture {
+    async move {
+        let bundle = tokio::task::spawn_blocking(generate_report_bundle).await??;
+
+        let file_name = format!(
+            "pbs-report-{}-{}.tar.zst",
+            proxmox_sys::nodename(),
+            proxmox_time::epoch_i64(),
+        );
+
+        Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "application/zstd")
+            .header(
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{file_name}\""),
+            )
+            .body(Body::from(bundle))
+            .unwrap())
+    }
+    .boxed()
+}
+
+const SUBDIRS: SubdirMap = &[(
+    "bundle",
+    &Router::new().get(&API_METHOD_DOWNLOAD_REPORT_BUNDLE),
+)];
+
+pub const ROUTER: Router = Router::new().get(&API_METHOD_GET_REPORT).subdirs(SUBDIRS);
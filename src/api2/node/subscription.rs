@@ -36,6 +36,42 @@ fn apt_auth_file_opts() -> CreateOptions {
     CreateOptions::new().perm(mode).owner(nix::unistd::ROOT)
 }
 
+/// Offline subscriptions are never checked against the shop server, mark them as expired once
+/// their due date passed.
+pub fn check_offline_expiry(info: &mut SubscriptionInfo) {
+    if !info.is_signed() || info.status != SubscriptionStatus::Active {
+        return;
+    }
+    let due_date = match info.nextduedate.as_deref() {
+        Some(due_date) if !due_date.is_empty() => due_date,
+        _ => return,
+    };
+    match proxmox_time::parse_rfc3339(&format!("{due_date}T23:59:59Z")) {
+        Ok(due) if due < proxmox_time::epoch_i64() => {
+            info.status = SubscriptionStatus::Expired;
+            info.message = Some(format!("offline subscription expired on {due_date}"));
+        }
+        Ok(_) => (),
+        Err(_) => {
+            info.status = SubscriptionStatus::Invalid;
+            info.message = Some(format!("invalid due date '{due_date}'"));
+        }
+    }
+}
+
+// reads the subscription, offline subscriptions are marked as expired past their due date
+fn read_subscription_info() -> Result<Option<SubscriptionInfo>, Error> {
+    let mut info = proxmox_subscription::files::read_subscription(
+        PROXMOX_BACKUP_SUBSCRIPTION_FN,
+        &[proxmox_subscription::files::DEFAULT_SIGNING_KEY],
+    )
+    .map_err(|err| format_err!("could not read subscription status: {err}"))?;
+    if let Some(info) = info.as_mut() {
+        check_offline_expiry(info);
+    }
+    Ok(info)
+}
+
 fn check_and_write_subscription(key: String, server_id: String) -> Result<(), Error> {
     let proxy_config = if let Ok((node_config, _digest)) = node::config() {
         node_config.http_proxy()
@@ -93,16 +129,29 @@ fn check_and_write_subscription(key: String, server_id: String) -> Result<(), Er
 )]
 /// Check and update subscription status.
 pub fn check_subscription(force: bool) -> Result<(), Error> {
-    let mut info = match proxmox_subscription::files::read_subscription(
-        PROXMOX_BACKUP_SUBSCRIPTION_FN,
-        &[proxmox_subscription::files::DEFAULT_SIGNING_KEY],
-    ) {
-        Err(err) => bail!("could not read subscription status: {}", err),
-        Ok(Some(info)) => info,
-        Ok(None) => return Ok(()),
+    let mut info = match read_subscription_info()? {
+        Some(info) => info,
+        None => return Ok(()),
     };
 
     let server_id = proxmox_subscription::get_hardware_address()?;
+
+    if info.is_signed() && info.status != SubscriptionStatus::Active {
+        // an expired offline subscription no longer grants access to the enterprise repository
+        proxmox_subscription::files::update_apt_auth(
+            APT_AUTH_FN,
+            apt_auth_file_opts(),
+            APT_AUTH_URL,
+            None,
+            None,
+        )?;
+        bail!(
+            "offline subscription is not active ({}) - {}",
+            info.status,
+            info.message.as_deref().unwrap_or("no reason given")
+        );
+    }
+
     let key = if let Some(key) = info.key.as_ref() {
         // always update apt auth if we have a key to ensure user can access enterprise repo
         proxmox_subscription::files::update_apt_auth(
@@ -118,6 +167,10 @@ pub fn check_subscription(force: bool) -> Result<(), Error> {
     };
 
     if info.is_signed() {
+        if !force {
+            // nothing to check online, the expiry was checked above
+            return Ok(());
+        }
         bail!("Updating offline key not possible - please remove and re-add subscription key to switch to online key.");
     }
 
@@ -150,13 +203,9 @@ pub fn get_subscription(
     _param: Value,
     rpcenv: &mut dyn RpcEnvironment,
 ) -> Result<SubscriptionInfo, Error> {
-    let info = match proxmox_subscription::files::read_subscription(
-        PROXMOX_BACKUP_SUBSCRIPTION_FN,
-        &[proxmox_subscription::files::DEFAULT_SIGNING_KEY],
-    ) {
-        Err(err) => bail!("could not read subscription status: {}", err),
-        Ok(Some(info)) => info,
-        Ok(None) => SubscriptionInfo {
+    let info = match read_subscription_info()? {
+        Some(info) => info,
+        None => SubscriptionInfo {
             status: SubscriptionStatus::NotFound,
             message: Some("There is no subscription key".into()),
             serverid: Some(proxmox_subscription::get_hardware_address()?),
@@ -239,3 +288,44 @@ pub const ROUTER: Router = Router::new()
     .put(&API_METHOD_SET_SUBSCRIPTION)
     .delete(&API_METHOD_DELETE_SUBSCRIPTION)
     .get(&API_METHOD_GET_SUBSCRIPTION);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn offline_info(due_date: &str) -> SubscriptionInfo {
+        SubscriptionInfo {
+            status: SubscriptionStatus::Active,
+            nextduedate: Some(due_date.to_string()),
+            signature: Some("signature".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_check_offline_expiry() {
+        let mut info = offline_info("2000-01-01");
+        check_offline_expiry(&mut info);
+        assert_eq!(info.status, SubscriptionStatus::Expired);
+
+        let mut info = offline_info("9999-12-31");
+        check_offline_expiry(&mut info);
+        assert_eq!(info.status, SubscriptionStatus::Active);
+
+        let mut info = offline_info("soon");
+        check_offline_expiry(&mut info);
+        assert_eq!(info.status, SubscriptionStatus::Invalid);
+
+        // online subscriptions are checked against the shop server instead
+        let mut info = offline_info("2000-01-01");
+        info.signature = None;
+        check_offline_expiry(&mut info);
+        assert_eq!(info.status, SubscriptionStatus::Active);
+
+        // already invalid, for example because of a wrong signature
+        let mut info = offline_info("2000-01-01");
+        info.status = SubscriptionStatus::Invalid;
+        check_offline_expiry(&mut info);
+        assert_eq!(info.status, SubscriptionStatus::Invalid);
+    }
+}
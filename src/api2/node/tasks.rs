@@ -2,7 +2,7 @@ use std::fs::File;
 use std::io::{BufRead, BufReader};
 
 use anyhow::{bail, Error};
-use futures::FutureExt;
+use futures::{FutureExt, StreamExt};
 use http::request::Parts;
 use http::{header, Response, StatusCode};
 use hyper::Body;
@@ -23,9 +23,10 @@ use pbs_api_types::{
 };
 
 use crate::api2::pull::check_pull_privs;
+use crate::server::task_log::{compressed_log_path, read_task_status, CompressedTaskLog};
 
 use pbs_config::CachedUserInfo;
-use proxmox_rest_server::{upid_log_path, upid_read_status, TaskListInfoIterator, TaskState};
+use proxmox_rest_server::{upid_log_path, TaskListInfoIterator, TaskState};
 
 pub const START_PARAM_SCHEMA: Schema =
     IntegerSchema::new("Start at this line when reading the tasklog")
@@ -288,7 +289,7 @@ async fn get_task_status(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Resul
     if proxmox_rest_server::worker_is_active(&upid).await? {
         result["status"] = Value::from("running");
     } else {
-        let exitstatus = upid_read_status(&upid).unwrap_or(TaskState::Unknown { endtime: 0 });
+        let exitstatus = read_task_status(&upid).unwrap_or(TaskState::Unknown { endtime: 0 });
         result["status"] = Value::from("stopped");
         result["exitstatus"] = Value::from(exitstatus.to_string());
     };
@@ -348,20 +349,59 @@ fn read_task_log(
                 upid.worker_type,
                 proxmox_time::epoch_to_rfc3339_utc(upid.starttime)?
             );
-            let stream = AsyncReaderStream::new(tokio::fs::File::open(path).await?);
+            let body = match tokio::fs::File::open(&path).await {
+                Ok(file) => Body::wrap_stream(AsyncReaderStream::new(file)),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                    let log = CompressedTaskLog::open(&compressed_log_path(&path))?;
+                    let stream = futures::stream::iter(0..log.frame_count()).map(move |frame| {
+                        proxmox_async::runtime::block_in_place(|| log.read_frame(frame))
+                    });
+                    Body::wrap_stream(stream)
+                }
+                Err(err) => return Err(err.into()),
+            };
 
             return Ok(Response::builder()
                 .status(StatusCode::OK)
                 .header(header::CONTENT_TYPE, "text/plain")
                 .header(header::CONTENT_DISPOSITION, &header_disp)
-                .body(Body::wrap_stream(stream))
+                .body(body)
                 .unwrap());
         }
         let start = param["start"].as_u64().unwrap_or(0);
         let mut limit = param["limit"].as_u64().unwrap_or(50);
         let test_status = param["test-status"].as_bool().unwrap_or(false);
 
-        let file = File::open(path)?;
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                // only the frames containing the requested lines are decompressed
+                let log = CompressedTaskLog::open(&compressed_log_path(&path))?;
+                let limit = if limit == 0 { None } else { Some(limit) };
+                let lines: Vec<Value> =
+                    proxmox_async::runtime::block_in_place(|| log.read_lines(start, limit))?
+                        .into_iter()
+                        .map(|(n, line)| json!({ "n": n, "t": line }))
+                        .collect();
+
+                let mut json = json!({
+                    "data": lines,
+                    "total": log.total_lines(),
+                    "success": 1,
+                });
+                if test_status {
+                    let active = proxmox_rest_server::worker_is_active(&upid).await?;
+                    json["active"] = Value::from(active);
+                }
+
+                return Ok(Response::builder()
+                    .status(StatusCode::OK)
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(json.to_string()))
+                    .unwrap());
+            }
+            Err(err) => return Err(err.into()),
+        };
 
         let mut count: u64 = 0;
         let mut lines: Vec<Value> = vec![];
@@ -16,8 +16,9 @@ use pbs_config::CachedUserInfo;
 use proxmox_human_byte::HumanByte;
 use proxmox_rest_server::WorkerTask;
 
-use crate::server::jobstate::Job;
-use crate::server::pull::{pull_store, PullParameters};
+use crate::server::job_error::{classify_error, parse_retry_policy, run_with_retry_async};
+use crate::server::jobstate::{Job, JobRunStatistics};
+use crate::server::pull::{pull_store, verify_new_snapshots, PullParameters};
 
 pub fn check_pull_privs(
     auth_id: &Authid,
@@ -114,6 +115,8 @@ pub fn do_sync_job(
         bail!("can't sync to same datastore");
     }
 
+    let retry = parse_retry_policy(sync_job.retry.as_deref())?;
+
     let upid_str = WorkerTask::spawn(
         &worker_type,
         Some(job_id.clone()),
@@ -126,8 +129,6 @@ pub fn do_sync_job(
             let sync_job2 = sync_job.clone();
 
             let worker_future = async move {
-                let pull_params = PullParameters::try_from(&sync_job)?;
-
                 task_log!(worker, "Starting datastore sync job '{}'", job_id);
                 if let Some(event_str) = schedule {
                     task_log!(worker, "task triggered by schedule '{}'", event_str);
@@ -143,7 +144,13 @@ pub fn do_sync_job(
                     sync_job.remote_store,
                 );
 
-                let pull_stats = pull_store(&worker, pull_params).await?;
+                let pull_stats = {
+                    let (worker, sync_job) = (&worker, &sync_job);
+                    run_with_retry_async(worker, &retry, || async move {
+                        pull_store(worker, PullParameters::try_from(sync_job)?).await
+                    })
+                    .await?
+                };
 
                 if pull_stats.bytes != 0 {
                     let amount = HumanByte::from(pull_stats.bytes);
@@ -159,7 +166,7 @@ pub fn do_sync_job(
                     task_log!(worker, "Summary: sync job found no new data to pull");
                 }
 
-                if let Some(removed) = pull_stats.removed {
+                if let Some(removed) = &pull_stats.removed {
                     task_log!(
                         worker,
                         "Summary: removed vanished: snapshots: {}, groups: {}, namespaces: {}",
@@ -169,9 +176,22 @@ pub fn do_sync_job(
                     );
                 }
 
+                let verified = if sync_job.verify_new.unwrap_or(false) {
+                    let verified = proxmox_async::runtime::block_in_place(|| {
+                        verify_new_snapshots(worker.clone(), &pull_stats.new_snapshots)
+                    })?;
+                    task_log!(
+                        worker,
+                        "Summary: verified {verified} newly synced snapshots"
+                    );
+                    Some(verified)
+                } else {
+                    None
+                };
+
                 task_log!(worker, "sync job '{}' end", &job_id);
 
-                Ok(())
+                Ok((pull_stats, verified))
             };
 
             let mut abort_future = worker2
@@ -183,16 +203,37 @@ pub fn do_sync_job(
                 abort = abort_future => abort,
             };
 
+            let (result, statistics, verified) = match result {
+                Ok((pull_stats, verified)) => (
+                    Ok(()),
+                    JobRunStatistics {
+                        bytes: Some(pull_stats.bytes as u64),
+                        snapshots: Some(pull_stats.snapshot_count as u64),
+                        ..Default::default()
+                    },
+                    verified,
+                ),
+                Err(err) => {
+                    let error_class = classify_error(&err);
+                    task_log!(worker2, "error class: {error_class}");
+                    let statistics = JobRunStatistics {
+                        error_class: Some(error_class),
+                        ..Default::default()
+                    };
+                    (Err(err), statistics, None)
+                }
+            };
+
             let status = worker2.create_state(&result);
 
-            match job.finish(status) {
+            match job.finish_with_statistics(status, statistics) {
                 Ok(_) => {}
                 Err(err) => {
                     eprintln!("could not finish job state: {}", err);
                 }
             }
 
-            if let Err(err) = crate::server::send_sync_status(&sync_job2, &result) {
+            if let Err(err) = crate::server::send_sync_status(&sync_job2, &result, verified) {
                 eprintln!("send sync notification failed: {err}");
             }
 
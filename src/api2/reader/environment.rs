@@ -1,16 +1,22 @@
 use std::collections::HashSet;
 use std::sync::{Arc, RwLock};
+use std::time::Instant;
 
 use serde_json::{json, Value};
 
 use proxmox_router::{RpcEnvironment, RpcEnvironmentType};
 
-use pbs_api_types::Authid;
+use pbs_api_types::{Authid, RestoreAccountingKind};
 use pbs_datastore::backup_info::BackupDir;
 use pbs_datastore::DataStore;
 use proxmox_rest_server::formatter::*;
 use proxmox_rest_server::WorkerTask;
 
+use crate::server::read_through::ReadThroughRemote;
+use crate::server::restore_accounting::RestoreAccounting;
+use crate::server::warm_reader::WarmReaderSession;
+use crate::traffic_control_cache::SharedRateLimit;
+
 /// `RpcEnvironmet` implementation for backup reader service
 #[derive(Clone)]
 pub struct ReaderEnvironment {
@@ -22,6 +28,14 @@ pub struct ReaderEnvironment {
     pub worker: Arc<WorkerTask>,
     pub datastore: Arc<DataStore>,
     pub backup_dir: BackupDir,
+    /// Counts restore traffic, so that backups to the datastore can be throttled
+    pub restore_traffic: Option<SharedRateLimit>,
+    /// Accounts the bytes sent in this session to the auth-id
+    pub accounting: Arc<RestoreAccounting>,
+    /// Remote missing chunks are fetched from, if the snapshot is cached from it
+    pub read_through: Option<Arc<ReadThroughRemote>>,
+    /// Warm session the manifest and indexes are served from
+    pub warm_session: Option<Arc<WarmReaderSession>>,
     allowed_chunks: Arc<RwLock<HashSet<[u8; 32]>>>,
 }
 
@@ -33,6 +47,12 @@ impl ReaderEnvironment {
         datastore: Arc<DataStore>,
         backup_dir: BackupDir,
     ) -> Self {
+        let accounting = RestoreAccounting::new(
+            auth_id.clone(),
+            datastore.name(),
+            RestoreAccountingKind::Reader,
+            backup_dir.dir().to_string(),
+        );
         Self {
             result_attributes: json!({}),
             env_type,
@@ -42,6 +62,10 @@ impl ReaderEnvironment {
             debug: false,
             formatter: JSON_FORMATTER,
             backup_dir,
+            restore_traffic: None,
+            accounting,
+            read_through: None,
+            warm_session: None,
             allowed_chunks: Arc::new(RwLock::new(HashSet::new())),
         }
     }
@@ -56,6 +80,13 @@ impl ReaderEnvironment {
         }
     }
 
+    pub fn register_restore_traffic(&self, size: u64) {
+        self.accounting.add(size);
+        if let Some(counter) = &self.restore_traffic {
+            counter.register_traffic(Instant::now(), size);
+        }
+    }
+
     pub fn register_chunk(&self, digest: [u8; 32]) {
         let mut allowed_chunks = self.allowed_chunks.write().unwrap();
         allowed_chunks.insert(digest);
@@ -1,5 +1,7 @@
 //! Backup reader/restore protocol (HTTP2 upgrade)
 
+use std::sync::Arc;
+
 use anyhow::{bail, format_err, Error};
 use futures::*;
 use hex::FromHex;
@@ -7,7 +9,7 @@ use hyper::header::{self, HeaderValue, CONNECTION, UPGRADE};
 use hyper::http::request::Parts;
 use hyper::{Body, Request, Response, StatusCode};
 use serde::Deserialize;
-use serde_json::Value;
+use serde_json::{json, Value};
 
 use proxmox_router::{
     http_err, list_subdirs_api_method, ApiHandler, ApiMethod, ApiResponseFuture, Permission,
@@ -17,9 +19,9 @@ use proxmox_schema::{BooleanSchema, ObjectSchema};
 use proxmox_sortable_macro::sortable;
 
 use pbs_api_types::{
-    Authid, Operation, BACKUP_ARCHIVE_NAME_SCHEMA, BACKUP_ID_SCHEMA, BACKUP_NAMESPACE_SCHEMA,
-    BACKUP_TIME_SCHEMA, BACKUP_TYPE_SCHEMA, CHUNK_DIGEST_SCHEMA, DATASTORE_SCHEMA,
-    PRIV_DATASTORE_BACKUP, PRIV_DATASTORE_READ,
+    Authid, BackupPriority, Operation, BACKUP_ARCHIVE_NAME_SCHEMA, BACKUP_ID_SCHEMA,
+    BACKUP_NAMESPACE_SCHEMA, BACKUP_TIME_SCHEMA, BACKUP_TYPE_SCHEMA, CHUNK_DIGEST_SCHEMA,
+    DATASTORE_SCHEMA, PRIV_DATASTORE_BACKUP, PRIV_DATASTORE_READ,
 };
 use pbs_config::CachedUserInfo;
 use pbs_datastore::index::IndexFile;
@@ -31,6 +33,10 @@ use proxmox_sys::fs::lock_dir_noblock_shared;
 
 use crate::api2::backup::optional_ns_param;
 use crate::api2::helpers;
+use crate::server::auth_last_used::record_use;
+use crate::server::datastore_window::{admit_session, Admission, DatastoreAccess};
+use crate::server::{archive_job, read_through, warm_reader};
+use crate::traffic_control_cache::restore_priority_limiters;
 
 mod environment;
 use environment::*;
@@ -77,6 +83,8 @@ fn upgrade_to_backup_reader_protocol(
         let debug = param["debug"].as_bool().unwrap_or(false);
 
         let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+        record_use(&auth_id, rpcenv.get_client_ip().map(|addr| addr.ip()));
+
         let store = required_string_param(&param, "store")?.to_owned();
         let backup_ns = optional_ns_param(&param)?;
 
@@ -92,8 +100,16 @@ fn upgrade_to_backup_reader_protocol(
             bail!("no permissions on /{}", acl_path.join("/"));
         }
 
+        let session =
+            match admit_session(&store, DatastoreAccess::Restore, BackupPriority::Normal).await? {
+                Admission::Admitted(session) => session,
+                Admission::Refused(response) => return Ok(response),
+            };
+
         let datastore = DataStore::lookup_datastore(&store, Some(Operation::Read))?;
 
+        let restore_traffic = restore_priority_limiters(&store)?.map(|(counter, _)| counter);
+
         let backup_dir = pbs_api_types::BackupDir::deserialize(&param)?;
 
         let protocols = parts
@@ -116,6 +132,40 @@ fn upgrade_to_backup_reader_protocol(
         let env_type = rpcenv.env_type();
 
         let backup_dir = datastore.backup_dir(backup_ns, backup_dir)?;
+
+        // snapshots missing locally are fetched from the read-through remote, if configured
+        let mut read_through = None;
+        if let Some(config) = read_through::read_through_config(&store)? {
+            let snapshot_path = backup_dir.full_path();
+            let missing = !snapshot_path.exists();
+            if missing || read_through::is_cached_snapshot(&snapshot_path) {
+                if !priv_read {
+                    bail!(
+                        "snapshot {} is cached from the read-through remote, reading it requires \
+                        the Datastore.Read privilege",
+                        backup_dir.dir()
+                    );
+                }
+                let mut remote =
+                    read_through::connect(&config, backup_dir.backup_ns(), backup_dir.dir())
+                        .await?;
+                if missing {
+                    read_through::fetch_snapshot(
+                        &remote,
+                        &datastore,
+                        backup_dir.backup_ns(),
+                        backup_dir.dir(),
+                    )
+                    .await?;
+                }
+                proxmox_async::runtime::block_in_place(|| {
+                    remote.load_chunk_sizes(&datastore, &backup_dir)?;
+                    read_through::record_read(&store, &backup_dir)
+                })?;
+                read_through = Some((Arc::new(remote), config, missing));
+            }
+        }
+
         if !priv_read {
             let owner = backup_dir.get_owner()?;
             let correct_owner = owner == auth_id
@@ -135,6 +185,43 @@ fn upgrade_to_backup_reader_protocol(
             "locked by another operation",
         )?;
 
+        if let Some((_, config, true)) = &read_through {
+            // the snapshot is locked now, so it is not evicted itself
+            if let Err(err) = proxmox_async::runtime::block_in_place(|| {
+                read_through::enforce_cache_size(&datastore, config)
+            }) {
+                log::warn!("unable to enforce read-through cache size of '{store}' - {err}");
+            }
+        }
+
+        // archived snapshots are only a stub, read them from their archive datastore instead
+        let archived = archive_job::lookup_archived_snapshot(&backup_dir, &auth_id)?;
+
+        // a warm session keeps the indexes loaded, they are checked against the current manifest
+        // (warm sessions are never created for archived snapshots)
+        let warm_session = match archived {
+            Some(_) => None,
+            None => warm_reader::use_session(&store, &backup_dir),
+        };
+
+        let (datastore, backup_dir, _archive_guard) = match archived {
+            Some((archive, archived_dir)) => {
+                let archive_guard = lock_dir_noblock_shared(
+                    &archived_dir.full_path(),
+                    "snapshot",
+                    "locked by another operation",
+                )
+                .map_err(|err| {
+                    format_err!(
+                        "cannot open archived snapshot on '{}' - {err}",
+                        archive.name()
+                    )
+                })?;
+                (archive, archived_dir, Some(archive_guard))
+            }
+            None => (datastore, backup_dir, None),
+        };
+
         let path = datastore.base_path();
 
         //let files = BackupInfo::list_files(&path, &backup_dir)?;
@@ -154,7 +241,9 @@ fn upgrade_to_backup_reader_protocol(
             auth_id.to_string(),
             true,
             move |worker| async move {
+                let _session = session;
                 let _guard = _guard;
+                let _archive_guard = _archive_guard;
 
                 let mut env = ReaderEnvironment::new(
                     env_type,
@@ -165,11 +254,17 @@ fn upgrade_to_backup_reader_protocol(
                 );
 
                 env.debug = debug;
+                env.restore_traffic = restore_traffic;
+                env.read_through = read_through.map(|(remote, _, _)| remote);
+                env.warm_session = warm_session;
 
                 env.log(format!(
                     "starting new backup reader datastore '{}': {:?}",
                     store, path
                 ));
+                if env.warm_session.is_some() {
+                    env.log("using warm reader session");
+                }
 
                 let service =
                     H2Service::new(env.clone(), worker.clone(), &READER_API_ROUTER, debug);
@@ -223,6 +318,10 @@ fn upgrade_to_backup_reader_protocol(
 
 const READER_API_SUBDIRS: SubdirMap = &[
     ("chunk", &Router::new().download(&API_METHOD_DOWNLOAD_CHUNK)),
+    (
+        "digest_salt",
+        &Router::new().get(&API_METHOD_GET_DIGEST_SALT),
+    ),
     (
         "download",
         &Router::new().download(&API_METHOD_DOWNLOAD_FILE),
@@ -234,6 +333,21 @@ pub const READER_API_ROUTER: Router = Router::new()
     .get(&list_subdirs_api_method!(READER_API_SUBDIRS))
     .subdirs(READER_API_SUBDIRS);
 
+pub const API_METHOD_GET_DIGEST_SALT: ApiMethod = ApiMethod::new(
+    &ApiHandler::Sync(&get_digest_salt),
+    &ObjectSchema::new("Get the digest salt of the datastore.", &[]),
+);
+
+fn get_digest_salt(
+    _param: Value,
+    _info: &ApiMethod,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Value, Error> {
+    let env: &ReaderEnvironment = rpcenv.as_ref();
+
+    Ok(json!(env.datastore.digest_salt().map(hex::encode)))
+}
+
 #[sortable]
 pub const API_METHOD_DOWNLOAD_FILE: ApiMethod = ApiMethod::new(
     &ApiHandler::AsyncHttp(&download_file),
@@ -261,6 +375,23 @@ fn download_file(
 
         env.log(format!("download {:?}", path.clone()));
 
+        if let Some(file) = env
+            .warm_session
+            .as_ref()
+            .and_then(|session| session.file(&file_name))
+        {
+            for digest in &file.digests {
+                env.register_chunk(*digest);
+            }
+            env.accounting.add(file.data.len() as u64);
+
+            return Ok(Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, "application/octet-stream")
+                .body(Body::from(file.data.clone()))
+                .unwrap());
+        }
+
         let index: Option<Box<dyn IndexFile + Send>> = match archive_type(&file_name)? {
             ArchiveType::FixedIndex => {
                 let index = env.datastore.open_fixed_reader(&path)?;
@@ -285,6 +416,10 @@ fn download_file(
             }
         }
 
+        if let Ok(metadata) = std::fs::metadata(&path) {
+            env.accounting.add(metadata.len());
+        }
+
         helpers::create_download_response(path).await
     }
     .boxed()
@@ -324,15 +459,37 @@ fn download_chunk(
             ));
         }
 
-        let (path, _) = env.datastore.chunk_path(&digest);
+        let path = env.datastore.chunk_read_path(&digest);
         let path2 = path.clone();
 
         env.debug(format!("download chunk {:?}", path));
 
-        let data =
-            proxmox_async::runtime::block_in_place(|| std::fs::read(path)).map_err(move |err| {
-                http_err!(BAD_REQUEST, "reading file {:?} failed: {}", path2, err)
-            })?;
+        let data = match proxmox_async::runtime::block_in_place(|| std::fs::read(path)) {
+            Ok(data) => data,
+            Err(err)
+                if err.kind() == std::io::ErrorKind::NotFound && env.read_through.is_some() =>
+            {
+                env.debug(format!(
+                    "fetch chunk {} from read-through remote",
+                    digest_str
+                ));
+                let remote = env.read_through.as_ref().unwrap();
+                remote
+                    .fetch_chunk(&env.datastore, &digest)
+                    .await
+                    .map_err(|err| http_err!(BAD_REQUEST, "{}", err))?
+            }
+            Err(err) => {
+                return Err(http_err!(
+                    BAD_REQUEST,
+                    "reading file {:?} failed: {}",
+                    path2,
+                    err
+                ))
+            }
+        };
+
+        env.register_restore_traffic(data.len() as u64);
 
         let body = Body::from(data);
 
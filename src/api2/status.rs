@@ -8,18 +8,120 @@ use proxmox_router::{ApiMethod, Permission, Router, RpcEnvironment, SubdirMap};
 use proxmox_schema::api;
 
 use pbs_api_types::{
-    Authid, DataStoreStatusListItem, Operation, RRDMode, RRDTimeFrame, PRIV_DATASTORE_AUDIT,
-    PRIV_DATASTORE_BACKUP,
+    Authid, DataStoreStatusListItem, DataStoreUsageTrend, Operation, RRDMode, RRDTimeFrame,
+    PRIV_DATASTORE_AUDIT, PRIV_DATASTORE_BACKUP,
 };
 
 use pbs_config::CachedUserInfo;
 use pbs_datastore::DataStore;
 
 use crate::rrd_cache::extract_rrd_data;
-use crate::tools::statistics::linear_regression;
+use crate::tools::statistics::{linear_regression, slope_standard_error};
 
 use crate::backup::can_access_any_namespace;
 
+/// Minimum number of data points for usage estimates
+const MIN_DATA_POINTS: usize = 7;
+
+/// Usage history of a datastore, as fraction of the storage size.
+pub(crate) struct UsageHistory {
+    pub start: u64,
+    pub resolution: u64,
+    /// One entry per RRD slot, `None` if there is no data for the slot.
+    pub history: Vec<Option<f64>>,
+}
+
+impl UsageHistory {
+    /// Returns the times and usages of all slots with data.
+    pub fn data_points(&self) -> (Vec<u64>, Vec<f64>) {
+        self.history
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, usage)| {
+                Some((self.start + (idx as u64) * self.resolution, (*usage)?))
+            })
+            .unzip()
+    }
+}
+
+/// Read the usage history of a datastore from the RRD data.
+pub(crate) fn usage_history(
+    store: &str,
+    timeframe: RRDTimeFrame,
+) -> Result<Option<UsageHistory>, Error> {
+    let rrd_dir = format!("datastore/{}", store);
+
+    let get_rrd = |what: &str| extract_rrd_data(&rrd_dir, what, timeframe, RRDMode::Average);
+
+    let total_res = get_rrd("total")?;
+    let used_res = get_rrd("used")?;
+    let avail_res = get_rrd("available")?;
+
+    let ((total_entry, used), avail) = match total_res.zip(used_res).zip(avail_res) {
+        Some(data) => data,
+        None => return Ok(None),
+    };
+
+    let mut history = Vec::new();
+
+    for (idx, used) in used.data.iter().enumerate() {
+        let used = match used {
+            Some(used) => used,
+            _ => {
+                history.push(None);
+                continue;
+            }
+        };
+
+        let total = if let Some(avail) = avail.get(idx) {
+            avail + used
+        } else if let Some(total) = total_entry.get(idx) {
+            total
+        } else {
+            history.push(None);
+            continue;
+        };
+
+        history.push(Some(used / total));
+    }
+
+    Ok(Some(UsageHistory {
+        start: total_entry.start,
+        resolution: total_entry.resolution,
+        history,
+    }))
+}
+
+/// Project the usage of a datastore from its history.
+///
+/// Returns `None` for datastores with not enough data.
+pub(crate) fn usage_trend(history: &UsageHistory, now: i64) -> Option<DataStoreUsageTrend> {
+    let (time_list, usage_list) = history.data_points();
+    if usage_list.len() < MIN_DATA_POINTS {
+        return None;
+    }
+
+    let (a, b) = linear_regression(&time_list, &usage_list)?;
+    let usage = (a + b * (now as f64)).clamp(0.0, 1.0);
+    let remaining = 1.0 - usage;
+
+    const DAY: f64 = 24.0 * 60.0 * 60.0;
+    let growth_per_day = b * DAY;
+    // approximately 95% of the growth rates are within this margin
+    let margin = slope_standard_error(&time_list, &usage_list, a, b).unwrap_or(0.0) * 1.96 * DAY;
+
+    let days_until = |growth: f64| (growth > 0.0).then(|| remaining / growth);
+
+    Some(DataStoreUsageTrend {
+        usage,
+        growth_per_day,
+        days_until_full: days_until(growth_per_day),
+        days_until_full_min: days_until(growth_per_day + margin),
+        days_until_full_max: days_until(growth_per_day - margin),
+        data_points: usage_list.len() as u64,
+    })
+}
+
 #[api(
     returns: {
         description: "Lists the Status of the Datastores.",
@@ -77,58 +179,25 @@ pub async fn datastore_status(
             estimated_full_date: None,
             error: None,
             gc_status: Some(datastore.last_gc_status()),
+            usage_trend: None,
         };
 
-        let rrd_dir = format!("datastore/{}", store);
-
-        let get_rrd =
-            |what: &str| extract_rrd_data(&rrd_dir, what, RRDTimeFrame::Month, RRDMode::Average);
-
-        let total_res = get_rrd("total")?;
-        let used_res = get_rrd("used")?;
-        let avail_res = get_rrd("available")?;
-
-        if let Some(((total_entry, used), avail)) = total_res.zip(used_res).zip(avail_res) {
-            let mut usage_list: Vec<f64> = Vec::new();
-            let mut time_list: Vec<u64> = Vec::new();
-            let mut history = Vec::new();
-
-            for (idx, used) in used.data.iter().enumerate() {
-                let used = match used {
-                    Some(used) => used,
-                    _ => {
-                        history.push(None);
-                        continue;
-                    }
-                };
-
-                let total = if let Some(avail) = avail.get(idx) {
-                    avail + used
-                } else if let Some(total) = total_entry.get(idx) {
-                    total
-                } else {
-                    history.push(None);
-                    continue;
-                };
-
-                let usage = used / total;
-                time_list.push(total_entry.start + (idx as u64) * total_entry.resolution);
-                usage_list.push(usage);
-                history.push(Some(usage));
-            }
-
-            entry.history_start = Some(total_entry.start);
-            entry.history_delta = Some(total_entry.resolution);
-            entry.history = Some(history);
+        if let Some(history) = usage_history(store, RRDTimeFrame::Month)? {
+            let (time_list, usage_list) = history.data_points();
 
             // we skip the calculation for datastores with not enough data
-            if usage_list.len() >= 7 {
+            if usage_list.len() >= MIN_DATA_POINTS {
                 entry.estimated_full_date = match linear_regression(&time_list, &usage_list) {
                     Some((a, b)) if b != 0.0 => Some(((1.0 - a) / b).floor() as i64),
                     Some((_, b)) if b == 0.0 => Some(0), // infinite estimate, set to past for gui to detect
                     _ => None,
                 };
             }
+            entry.usage_trend = usage_trend(&history, proxmox_time::epoch_i64());
+
+            entry.history_start = Some(history.start);
+            entry.history_delta = Some(history.resolution);
+            entry.history = Some(history.history);
         }
 
         list.push(entry);
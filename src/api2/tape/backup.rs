@@ -22,7 +22,10 @@ use proxmox_rest_server::WorkerTask;
 use crate::tape::TapeNotificationMode;
 use crate::{
     server::{
-        jobstate::{compute_schedule_status, Job, JobState},
+        job_error::{classify_error, parse_retry_policy, run_with_retry},
+        jobstate::{
+            compute_schedule_status, last_run_error_class, Job, JobRunStatistics, JobState,
+        },
         TapeBackupJobSummary,
     },
     tape::{
@@ -99,7 +102,9 @@ pub fn list_tape_backup_jobs(
         let last_state = JobState::load("tape-backup-job", &job.id)
             .map_err(|err| format_err!("could not open statefile for {}: {}", &job.id, err))?;
 
-        let status = compute_schedule_status(&last_state, job.schedule.as_deref())?;
+        let mut status = compute_schedule_status(&last_state, job.schedule.as_deref())?;
+        status.last_run_error_class =
+            last_run_error_class("tape-backup-job", &job.id, status.last_run_upid.as_deref());
 
         let next_run = status.next_run.unwrap_or(current_time);
 
@@ -135,10 +140,13 @@ pub fn list_tape_backup_jobs(
 pub fn do_tape_backup_job(
     mut job: Job,
     setup: TapeBackupJobSetup,
+    retry: Option<String>,
     auth_id: &Authid,
     schedule: Option<String>,
     to_stdout: bool,
 ) -> Result<String, Error> {
+    let retry = parse_retry_policy(retry.as_deref())?;
+
     let job_id = format!(
         "{}:{}:{}:{}",
         setup.store,
@@ -196,17 +204,29 @@ pub fn do_tape_backup_job(
                     task_log!(worker, "task triggered by schedule '{}'", event_str);
                 }
 
-                backup_worker(
-                    &worker,
-                    datastore,
-                    &pool_config,
-                    &setup,
-                    &mut summary,
-                    false,
-                )
+                run_with_retry(&worker, &retry, || {
+                    summary = Default::default();
+                    backup_worker(
+                        &worker,
+                        datastore.clone(),
+                        &pool_config,
+                        &setup,
+                        &mut summary,
+                        false,
+                    )
+                })
             });
 
             let status = worker.create_state(&job_result);
+            let error_class = job_result.as_ref().err().map(classify_error);
+            if let Some(error_class) = error_class {
+                task_log!(worker, "error class: {error_class}");
+            }
+            let statistics = JobRunStatistics {
+                snapshots: Some(summary.snapshot_list.len() as u64),
+                error_class,
+                ..Default::default()
+            };
 
             if let Err(err) = crate::server::send_tape_backup_status(
                 Some(job.jobname()),
@@ -217,7 +237,7 @@ pub fn do_tape_backup_job(
                 eprintln!("send tape backup notification failed: {err}");
             }
 
-            if let Err(err) = job.finish(status) {
+            if let Err(err) = job.finish_with_statistics(status, statistics) {
                 eprintln!("could not finish job state for {}: {}", job.jobtype(), err);
             }
 
@@ -265,7 +285,14 @@ pub fn run_tape_backup_job(id: String, rpcenv: &mut dyn RpcEnvironment) -> Resul
 
     let to_stdout = rpcenv.env_type() == RpcEnvironmentType::CLI;
 
-    let upid_str = do_tape_backup_job(job, backup_job.setup, &auth_id, None, to_stdout)?;
+    let upid_str = do_tape_backup_job(
+        job,
+        backup_job.setup,
+        backup_job.retry,
+        &auth_id,
+        None,
+        to_stdout,
+    )?;
 
     Ok(upid_str)
 }
@@ -619,7 +646,7 @@ fn backup_snapshot(
         worker.check_abort()?;
 
         let (leom, _bytes) =
-            pool_writer.append_chunk_archive(worker, &mut chunk_iter, datastore.name())?;
+            pool_writer.append_chunk_archive(worker, &mut chunk_iter, &datastore)?;
 
         if leom {
             pool_writer.set_media_status_full(&uuid)?;
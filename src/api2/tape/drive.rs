@@ -16,9 +16,10 @@ use proxmox_sys::{task_log, task_warn};
 use proxmox_uuid::Uuid;
 
 use pbs_api_types::{
-    Authid, DriveListEntry, LabelUuidMap, Lp17VolumeStatistics, LtoDriveAndMediaStatus,
-    LtoTapeDrive, MamAttribute, MediaIdFlat, TapeDensity, CHANGER_NAME_SCHEMA, DRIVE_NAME_SCHEMA,
-    MEDIA_LABEL_SCHEMA, MEDIA_POOL_NAME_SCHEMA, UPID_SCHEMA,
+    Authid, DriveListEntry, DriveOperationProgress, LabelUuidMap, Lp17VolumeStatistics,
+    LtoDriveAndMediaStatus, LtoTapeDrive, MamAttribute, MediaIdFlat, TapeDensity,
+    CHANGER_NAME_SCHEMA, DRIVE_NAME_SCHEMA, MEDIA_LABEL_SCHEMA, MEDIA_POOL_NAME_SCHEMA,
+    UPID_SCHEMA,
 };
 
 use pbs_api_types::{PRIV_TAPE_AUDIT, PRIV_TAPE_READ, PRIV_TAPE_WRITE};
@@ -36,8 +37,9 @@ use crate::{
     tape::{
         changer::update_changer_online_status,
         drive::{
-            get_tape_device_state, lock_tape_device, media_changer, open_drive,
-            required_media_changer, set_tape_device_state, LtoTapeHandle, TapeDriver,
+            get_tape_device_progress, get_tape_device_state, lock_tape_device, media_changer,
+            open_drive, required_media_changer, set_tape_device_state, DriveProgressReporter,
+            LtoTapeHandle, TapeDriver,
         },
         encryption_keys::insert_key,
         file_formats::{MediaLabel, MediaSetLabel},
@@ -1263,6 +1265,31 @@ pub async fn status(drive: String) -> Result<LtoDriveAndMediaStatus, Error> {
     .await
 }
 
+#[api(
+    input: {
+        properties: {
+            drive: {
+                schema: DRIVE_NAME_SCHEMA,
+            },
+        },
+    },
+    returns: {
+        type: DriveOperationProgress,
+        optional: true,
+    },
+    access: {
+        permission: &Permission::Privilege(&["tape", "device", "{drive}"], PRIV_TAPE_AUDIT, false),
+    },
+)]
+/// Get the progress of the task currently using the drive
+///
+/// This does not access the drive, so it also works while the drive is in use. Returns
+/// nothing if no task reported progress.
+pub fn drive_progress(drive: String) -> Result<Option<DriveOperationProgress>, Error> {
+    let (config, _digest) = pbs_config::drive::config()?;
+    get_tape_device_progress(&config, &drive)
+}
+
 #[api(
     input: {
         properties: {
@@ -1312,6 +1339,7 @@ pub fn catalog_media(
         "catalog-media",
         Some(drive.clone()),
         move |worker, config| {
+            let mut progress = DriveProgressReporter::new(&drive, worker.upid().to_string());
             let mut drive = open_drive(&config, &drive)?;
 
             drive.rewind()?;
@@ -1398,6 +1426,7 @@ pub fn catalog_media(
                 &mut checked_chunks,
                 verbose,
                 &auth_id,
+                &mut progress,
             )?;
 
             Ok(())
@@ -1512,6 +1541,7 @@ pub const SUBDIRS: SubdirMap = &sorted!([
         "volume-statistics",
         &Router::new().get(&API_METHOD_VOLUME_STATISTICS)
     ),
+    ("progress", &Router::new().get(&API_METHOD_DRIVE_PROGRESS)),
     ("read-label", &Router::new().get(&API_METHOD_READ_LABEL)),
     ("restore-key", &Router::new().post(&API_METHOD_RESTORE_KEY)),
     ("rewind", &Router::new().post(&API_METHOD_REWIND)),
@@ -5,6 +5,7 @@ use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use anyhow::{bail, format_err, Error};
+use hex::FromHex;
 use serde_json::Value;
 
 use proxmox_human_byte::HumanByte;
@@ -38,7 +39,10 @@ use crate::backup::check_ns_modification_privs;
 use crate::tape::TapeNotificationMode;
 use crate::{
     tape::{
-        drive::{lock_tape_device, request_and_load_media, set_tape_device_state, TapeDriver},
+        drive::{
+            lock_tape_device, request_and_load_media, set_tape_device_state, DriveProgressReporter,
+            TapeDriver,
+        },
         file_formats::{
             CatalogArchiveHeader, ChunkArchiveDecoder, ChunkArchiveHeader, SnapshotArchiveHeader,
             PROXMOX_BACKUP_CATALOG_ARCHIVE_MAGIC_1_0, PROXMOX_BACKUP_CATALOG_ARCHIVE_MAGIC_1_1,
@@ -53,32 +57,51 @@ use crate::{
     tools::parallel_handler::ParallelHandler,
 };
 
+struct NamespaceMapping {
+    target: BackupNamespace,
+    max_depth: usize,
+    owner: Option<Authid>,
+}
+
+impl NamespaceMapping {
+    fn map(
+        &self,
+        prefix: &BackupNamespace,
+        source_ns: &BackupNamespace,
+    ) -> Option<BackupNamespace> {
+        // filter out prefixes which are too long
+        if prefix.depth() > source_ns.depth() || source_ns.depth() - prefix.depth() > self.max_depth
+        {
+            return None;
+        }
+        source_ns.map_prefix(prefix, &self.target).ok()
+    }
+}
+
 struct NamespaceMap {
-    map: HashMap<String, HashMap<BackupNamespace, (BackupNamespace, usize)>>,
+    map: HashMap<String, HashMap<BackupNamespace, NamespaceMapping>>,
 }
 
-impl TryFrom<Vec<String>> for NamespaceMap {
+impl TryFrom<Vec<TapeRestoreNamespace>> for NamespaceMap {
     type Error = Error;
 
-    fn try_from(mappings: Vec<String>) -> Result<Self, Error> {
+    fn try_from(mappings: Vec<TapeRestoreNamespace>) -> Result<Self, Error> {
         let mut map = HashMap::new();
 
-        let mappings = mappings.into_iter().map(|s| {
-            let value = TapeRestoreNamespace::API_SCHEMA.parse_property_string(&s)?;
-            let value: TapeRestoreNamespace = serde_json::from_value(value)?;
-            Ok::<_, Error>(value)
-        });
-
         for mapping in mappings {
-            let mapping = mapping?;
             let source = mapping.source.unwrap_or_default();
             let target = mapping.target.unwrap_or_default();
             let max_depth = mapping.max_depth.unwrap_or(MAX_NAMESPACE_DEPTH);
 
-            let ns_map: &mut HashMap<BackupNamespace, (BackupNamespace, usize)> =
+            let ns_map: &mut HashMap<BackupNamespace, NamespaceMapping> =
                 map.entry(mapping.store).or_default();
 
-            if ns_map.insert(source, (target, max_depth)).is_some() {
+            let mapping = NamespaceMapping {
+                target,
+                max_depth,
+                owner: mapping.owner,
+            };
+            if ns_map.insert(source, mapping).is_some() {
                 bail!("duplicate mapping found");
             }
         }
@@ -91,8 +114,8 @@ impl NamespaceMap {
     fn used_namespaces(&self, datastore: &str) -> HashSet<BackupNamespace> {
         let mut set = HashSet::new();
         if let Some(mapping) = self.map.get(datastore) {
-            for (ns, _) in mapping.values() {
-                set.insert(ns.clone());
+            for mapping in mapping.values() {
+                set.insert(mapping.target.clone());
             }
         }
 
@@ -103,19 +126,42 @@ impl NamespaceMap {
         if let Some(mapping) = self.map.get(source_ds) {
             return mapping
                 .iter()
-                .filter_map(|(ns, (target_ns, max_depth))| {
-                    // filter out prefixes which are too long
-                    if ns.depth() > source_ns.depth() || source_ns.depth() - ns.depth() > *max_depth
-                    {
-                        return None;
-                    }
-                    source_ns.map_prefix(ns, target_ns).ok()
-                })
+                .filter_map(|(ns, mapping)| mapping.map(ns, source_ns))
                 .collect();
         }
 
         vec![]
     }
+
+    /// Returns the owner override of the mapping from `source_ns` to `target_ns`, if any.
+    fn get_owner(
+        &self,
+        source_ds: &str,
+        source_ns: &BackupNamespace,
+        target_ns: &BackupNamespace,
+    ) -> Option<&Authid> {
+        self.map
+            .get(source_ds)?
+            .iter()
+            .find_map(|(ns, mapping)| match mapping.map(ns, source_ns) {
+                Some(ns) if ns == *target_ns => mapping.owner.as_ref(),
+                _ => None,
+            })
+    }
+
+    /// Returns the source datastore, target namespace and owner of the mappings with an owner
+    /// override.
+    fn owner_overrides(&self) -> Vec<(&str, &BackupNamespace, &Authid)> {
+        let mut list = Vec::new();
+        for (store, mapping) in self.map.iter() {
+            for mapping in mapping.values() {
+                if let Some(owner) = &mapping.owner {
+                    list.push((store.as_str(), &mapping.target, owner));
+                }
+            }
+        }
+        list
+    }
 }
 
 pub struct DataStoreMap {
@@ -162,7 +208,31 @@ impl TryFrom<String> for DataStoreMap {
 impl DataStoreMap {
     fn add_namespaces_maps(&mut self, mappings: Vec<String>) -> Result<bool, Error> {
         let count = mappings.len();
-        let ns_map = NamespaceMap::try_from(mappings)?;
+
+        let mut parsed = Vec::with_capacity(count);
+        for mapping in mappings {
+            let value = TapeRestoreNamespace::API_SCHEMA.parse_property_string(&mapping)?;
+            let mapping: TapeRestoreNamespace = serde_json::from_value(value)?;
+
+            // a mapping can also select the target datastore of its source datastore
+            if let Some(target_store) = &mapping.target_store {
+                match self.map.get(&mapping.store) {
+                    Some(datastore) if datastore.name() != target_store => bail!(
+                        "conflicting target datastores for source datastore '{}'",
+                        mapping.store
+                    ),
+                    Some(_) => (),
+                    None => {
+                        let datastore =
+                            DataStore::lookup_datastore(target_store, Some(Operation::Write))?;
+                        self.map.insert(mapping.store.clone(), datastore);
+                    }
+                }
+            }
+            parsed.push(mapping);
+        }
+
+        let ns_map = NamespaceMap::try_from(parsed)?;
         self.ns_map = Some(ns_map);
         Ok(count > 0)
     }
@@ -203,6 +273,19 @@ impl DataStoreMap {
             .map(|store| (store, self.target_ns(source_datastore, source_ns)))
     }
 
+    /// Returns the owner for restoring from a source datastore/ns to `target_ns`, if the
+    /// namespace mapping overrides it.
+    fn target_owner(
+        &self,
+        datastore: &str,
+        ns: &BackupNamespace,
+        target_ns: &BackupNamespace,
+    ) -> Option<&Authid> {
+        self.ns_map
+            .as_ref()
+            .and_then(|mapping| mapping.get_owner(datastore, ns, target_ns))
+    }
+
     /// Returns true if there's both a datastore and namespace mapping from a source datastore/ns
     fn has_full_mapping(&self, datastore: &str, ns: &BackupNamespace) -> bool {
         self.target_store(datastore).is_some() && self.target_ns(datastore, ns).is_some()
@@ -366,6 +449,19 @@ pub fn restore(
             }
         }
     }
+    if let Some(ns_map) = &store_map.ns_map {
+        for (source, ns, mapping_owner) in ns_map.owner_overrides() {
+            if let Some(target) = store_map.target_store(source) {
+                check_datastore_privs(
+                    &user_info,
+                    target.name(),
+                    ns,
+                    &auth_id,
+                    Some(mapping_owner),
+                )?;
+            }
+        }
+    }
     user_info.check_privs(&auth_id, &["tape", "drive", &drive], PRIV_TAPE_READ, false)?;
 
     let media_set_uuid = media_set.parse()?;
@@ -512,6 +608,7 @@ fn restore_full_worker(
     }
 
     let mut checked_chunks_map = HashMap::new();
+    let mut progress = DriveProgressReporter::new(drive_name, worker.upid().to_string());
 
     for media_id in media_id_list.iter() {
         request_and_restore_media(
@@ -524,6 +621,7 @@ fn restore_full_worker(
             restore_owner,
             notification_mode,
             auth_id,
+            &mut progress,
         )?;
     }
 
@@ -562,9 +660,14 @@ fn check_snapshot_restorable(
         }
     };
 
+    let source_ns = ns;
     let mut have_some_permissions = false;
     let mut can_restore_some = false;
     for ns in namespaces {
+        let restore_owner = store_map
+            .target_owner(store, source_ns, &ns)
+            .unwrap_or(restore_owner);
+
         // only simple check, ns creation comes later
         if let Err(err) = check_datastore_privs(
             user_info,
@@ -647,6 +750,7 @@ fn restore_list_worker(
     let mut datastore_locks = Vec::new();
     let mut snapshot_file_hash: BTreeMap<Uuid, Vec<u64>> = BTreeMap::new();
     let mut skipped = Vec::new();
+    let mut progress = DriveProgressReporter::new(drive_name, worker.upid().to_string());
 
     let res = proxmox_lang::try_block!({
         // phase 0
@@ -794,6 +898,7 @@ fn restore_list_worker(
                 &info,
                 &media_set_uuid,
                 &mut datastore_chunk_map,
+                &mut progress,
             )
             .map_err(|err| format_err!("could not restore snapshots to tmpdir: {}", err))?;
             tmp_paths.extend(tmp_path);
@@ -831,14 +936,21 @@ fn restore_list_worker(
 
         for (media_uuid, file_chunk_map) in media_file_chunk_map.iter_mut() {
             let media_id = inventory.lookup_media(media_uuid).unwrap();
-            let (mut drive, _info) = request_and_load_media(
+            let (mut drive, info) = request_and_load_media(
                 &worker,
                 &drive_config,
                 drive_name,
                 &media_id.label,
                 notification_mode,
             )?;
-            restore_file_chunk_map(worker.clone(), &mut drive, &store_map, file_chunk_map)?;
+            progress.set_media(&info.label.label_text);
+            restore_file_chunk_map(
+                worker.clone(),
+                &mut drive,
+                &store_map,
+                file_chunk_map,
+                &mut progress,
+            )?;
         }
 
         task_log!(
@@ -855,6 +967,9 @@ fn restore_list_worker(
                 })?;
 
                 for ns in target_ns.unwrap_or_else(|| vec![source_ns.clone()]) {
+                    let restore_owner = store_map
+                        .target_owner(&source_datastore, &source_ns, &ns)
+                        .unwrap_or(restore_owner);
                     if let Err(err) = proxmox_lang::try_block!({
                         check_and_create_namespaces(
                             &user_info,
@@ -1011,6 +1126,7 @@ fn restore_snapshots_to_tmpdir(
     media_id: &MediaId,
     media_set_uuid: &Uuid,
     chunks_list: &mut HashMap<String, HashSet<[u8; 32]>>,
+    progress: &mut DriveProgressReporter,
 ) -> Result<Vec<PathBuf>, Error> {
     let mut tmp_paths = Vec::new();
     match media_id.media_set_label {
@@ -1033,6 +1149,8 @@ fn restore_snapshots_to_tmpdir(
         }
     }
 
+    progress.set_media(&media_id.label.label_text);
+
     for file_num in file_list {
         let current_file_number = drive.current_file_number()?;
         if current_file_number != *file_num {
@@ -1044,7 +1162,8 @@ fn restore_snapshots_to_tmpdir(
             let current_file_number = drive.current_file_number()?;
             task_log!(worker, "now at file {}", current_file_number);
         }
-        let mut reader = drive.read_next_file()?;
+        progress.update(drive.as_mut());
+        let mut reader = progress.count_reads(drive.read_next_file()?);
 
         let header: MediaContentHeader = unsafe { reader.read_le_value()? };
         if header.magic != PROXMOX_BACKUP_CONTENT_HEADER_MAGIC_1_0 {
@@ -1125,6 +1244,7 @@ fn restore_file_chunk_map(
     drive: &mut Box<dyn TapeDriver>,
     store_map: &DataStoreMap,
     file_chunk_map: &mut BTreeMap<u64, HashSet<[u8; 32]>>,
+    progress: &mut DriveProgressReporter,
 ) -> Result<(), Error> {
     for (nr, chunk_map) in file_chunk_map.iter_mut() {
         let current_file_number = drive.current_file_number()?;
@@ -1134,7 +1254,8 @@ fn restore_file_chunk_map(
             let current_file_number = drive.current_file_number()?;
             task_log!(worker, "now at file {}", current_file_number);
         }
-        let mut reader = drive.read_next_file()?;
+        progress.update(drive.as_mut());
+        let mut reader = progress.count_reads(drive.read_next_file()?);
         let header: MediaContentHeader = unsafe { reader.read_le_value()? };
         if header.magic != PROXMOX_BACKUP_CONTENT_HEADER_MAGIC_1_0 {
             bail!("file is missing the MediaContentHeader");
@@ -1147,7 +1268,7 @@ fn restore_file_chunk_map(
                 let archive_header: ChunkArchiveHeader = serde_json::from_slice(&header_data)
                     .map_err(|err| format_err!("unable to parse chunk archive header - {err}"))?;
 
-                let source_datastore = archive_header.store;
+                let source_datastore = archive_header.store.clone();
 
                 task_log!(
                     worker,
@@ -1157,6 +1278,7 @@ fn restore_file_chunk_map(
                 let datastore = store_map.target_store(&source_datastore).ok_or_else(|| {
                     format_err!("unexpected chunk archive for store: {source_datastore}")
                 })?;
+                check_chunk_archive_digest_salt(&datastore, &archive_header)?;
 
                 let count = restore_partial_chunk_archive(
                     worker.clone(),
@@ -1173,6 +1295,25 @@ fn restore_file_chunk_map(
     Ok(())
 }
 
+/// Chunks written from a datastore with another digest salt would fail verification one by one,
+/// so refuse the whole chunk archive up front.
+fn check_chunk_archive_digest_salt(
+    datastore: &DataStore,
+    archive_header: &ChunkArchiveHeader,
+) -> Result<(), Error> {
+    let fingerprint = archive_header
+        .digest_salt_fingerprint
+        .as_deref()
+        .map(<[u8; 32]>::from_hex)
+        .transpose()
+        .map_err(|err| format_err!("invalid digest salt fingerprint in chunk archive - {err}"))?;
+
+    datastore.check_digest_salt(
+        &format!("chunk archive of datastore '{}'", archive_header.store),
+        fingerprint,
+    )
+}
+
 fn restore_partial_chunk_archive<'a>(
     worker: Arc<WorkerTask>,
     reader: Box<dyn 'a + TapeRead>,
@@ -1195,7 +1336,8 @@ fn restore_partial_chunk_archive<'a>(
                 bytes2.fetch_add(chunk.raw_size(), std::sync::atomic::Ordering::SeqCst);
                 chunk.verify_crc()?;
                 if chunk.crypt_mode()? == CryptMode::None {
-                    chunk.decode(None, Some(&digest))?; // verify digest
+                    // verify digest
+                    chunk.decode_salted(None, Some(&digest), datastore.digest_salt())?;
                 }
 
                 datastore.insert_chunk(&chunk, &digest)?;
@@ -1246,6 +1388,7 @@ pub fn request_and_restore_media(
     restore_owner: &Authid,
     notification_mode: &TapeNotificationMode,
     auth_id: &Authid,
+    progress: &mut DriveProgressReporter,
 ) -> Result<(), Error> {
     let media_set_uuid = match media_id.media_set_label {
         None => bail!("restore_media: no media set - internal error"),
@@ -1288,12 +1431,14 @@ pub fn request_and_restore_media(
         checked_chunks_map,
         false,
         auth_id,
+        progress,
     )
 }
 
 /// Restore complete media content and catalog
 ///
 /// Only create the catalog if target is None.
+#[allow(clippy::too_many_arguments)]
 pub fn restore_media(
     worker: Arc<WorkerTask>,
     drive: &mut Box<dyn TapeDriver>,
@@ -1302,11 +1447,15 @@ pub fn restore_media(
     checked_chunks_map: &mut HashMap<String, HashSet<[u8; 32]>>,
     verbose: bool,
     auth_id: &Authid,
+    progress: &mut DriveProgressReporter,
 ) -> Result<(), Error> {
     let mut catalog = MediaCatalog::create_temporary_database(TAPE_STATUS_DIR, media_id, false)?;
 
+    progress.set_media(&media_id.label.label_text);
+
     loop {
         let current_file_number = drive.current_file_number()?;
+        progress.update(drive.as_mut());
         let reader = match drive.read_next_file() {
             Err(BlockReadError::EndOfFile) => {
                 task_log!(
@@ -1323,7 +1472,7 @@ pub fn restore_media(
             Err(BlockReadError::Error(err)) => {
                 return Err(err.into());
             }
-            Ok(reader) => reader,
+            Ok(reader) => progress.count_reads(reader),
         };
 
         restore_archive(
@@ -1471,7 +1620,7 @@ fn restore_archive<'a>(
             let archive_header: ChunkArchiveHeader = serde_json::from_slice(&header_data)
                 .map_err(|err| format_err!("unable to parse chunk archive header - {}", err))?;
 
-            let source_datastore = archive_header.store;
+            let source_datastore = archive_header.store.clone();
 
             task_log!(
                 worker,
@@ -1495,6 +1644,7 @@ fn restore_archive<'a>(
                     .or_default();
 
                 let chunks = if let Some(datastore) = datastore {
+                    check_chunk_archive_digest_salt(&datastore, &archive_header)?;
                     restore_chunk_archive(
                         worker.clone(),
                         reader,
@@ -1619,7 +1769,8 @@ fn restore_chunk_archive<'a>(
                 // println!("verify and write {}", hex::encode(&digest));
                 chunk.verify_crc()?;
                 if chunk.crypt_mode()? == CryptMode::None {
-                    chunk.decode(None, Some(&digest))?; // verify digest
+                    // verify digest
+                    chunk.decode_salted(None, Some(&digest), datastore.digest_salt())?;
                 }
 
                 datastore.insert_chunk(&chunk, &digest)?;
@@ -26,8 +26,10 @@ use pbs_api_types::{
 use pbs_buildcfg::configdir;
 
 use crate::auth_helpers;
+use crate::tools::login_attempts::{LoginAttempts, LoginLimits};
 
 pub const TERM_PREFIX: &str = "PBSTERM";
+pub const RESTORE_LINK_PREFIX: &str = "PBSRESTORE";
 
 struct PbsAuthenticator;
 
@@ -339,6 +341,84 @@ pub(crate) fn lookup_authenticator(
     }
 }
 
+/// Count a failed login of `userid` from `address`, logging lockouts.
+pub(crate) fn record_login_failure(
+    attempts: &LoginAttempts,
+    userid: &Userid,
+    address: Option<IpAddr>,
+) {
+    let limits = LoginLimits::from_node_config();
+    match attempts.record_failure(userid, address, &limits) {
+        Ok(true) => match address {
+            Some(address) => {
+                log::warn!("too many failed logins for '{userid}' from {address}, locking out")
+            }
+            None => log::warn!("too many failed logins for '{userid}', locking out"),
+        },
+        Ok(false) => (),
+        Err(err) => log::warn!("unable to count failed login for '{userid}' - {err}"),
+    }
+}
+
+/// Wraps the authenticator of a realm used for logins, counting failed logins.
+struct LoginAuthenticator {
+    realm: String,
+    inner: Box<dyn Authenticator + Send + Sync>,
+}
+
+impl Authenticator for LoginAuthenticator {
+    fn authenticate_user<'a>(
+        &'a self,
+        username: &'a UsernameRef,
+        password: &'a str,
+        client_ip: Option<&'a IpAddr>,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'a>> {
+        Box::pin(async move {
+            let userid = Userid::try_from(format!("{}@{}", username.as_str(), self.realm));
+            let address = client_ip.copied();
+            let attempts = match LoginAttempts::new() {
+                Ok(attempts) => Some(attempts),
+                Err(err) => {
+                    log::error!("unable to access failed login counters - {err}");
+                    None
+                }
+            };
+
+            if let (Ok(userid), Some(attempts)) = (&userid, attempts) {
+                if attempts.locked_until(userid, address).is_some() {
+                    bail!("too many failed logins, try again later");
+                }
+            }
+
+            let result = self
+                .inner
+                .authenticate_user(username, password, client_ip)
+                .await;
+
+            // counters are reset once the login is completed, including the second factor
+            if let (Ok(userid), Some(attempts)) = (&userid, attempts) {
+                if result.is_err() {
+                    record_login_failure(attempts, userid, address);
+                }
+            }
+            result
+        })
+    }
+
+    fn store_password(
+        &self,
+        username: &UsernameRef,
+        password: &str,
+        client_ip: Option<&IpAddr>,
+    ) -> Result<(), Error> {
+        self.inner.store_password(username, password, client_ip)
+    }
+
+    fn remove_password(&self, username: &UsernameRef) -> Result<(), Error> {
+        self.inner.remove_password(username)
+    }
+}
+
 /// Authenticate users
 pub(crate) fn authenticate_user<'a>(
     userid: &'a Userid,
@@ -392,7 +472,11 @@ struct PbsAuthContext {
 
 impl proxmox_auth_api::api::AuthContext for PbsAuthContext {
     fn lookup_realm(&self, realm: &RealmRef) -> Option<Box<dyn Authenticator + Send + Sync>> {
-        lookup_authenticator(realm).ok()
+        let inner = lookup_authenticator(realm).ok()?;
+        Some(Box::new(LoginAuthenticator {
+            realm: realm.as_str().to_string(),
+            inner,
+        }))
     }
 
     /// Get the current authentication keyring.
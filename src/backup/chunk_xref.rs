@@ -0,0 +1,287 @@
+//! Chunk store cross-reference reports
+//!
+//! A report maps every chunk of a datastore to the indexes referencing it, and lists chunks which
+//! are unreferenced, missing or corrupt. Reports are stored zstd compressed in JSON lines format:
+//! a header line with the list of all indexes, one line per chunk referencing the indexes by
+//! their position in that list, and a final summary line.
+
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{bail, format_err, Error};
+use serde_json::json;
+
+use proxmox_sys::fs::{create_path, CreateOptions};
+use proxmox_sys::{task_log, task_warn, WorkerTaskContext};
+
+use pbs_api_types::{ChunkXrefReportInfo, CryptMode};
+use pbs_buildcfg::PROXMOX_BACKUP_STATE_DIR_M;
+use pbs_datastore::DataStore;
+
+const XREF_BASEDIR: &str = concat!(PROXMOX_BACKUP_STATE_DIR_M!(), "/chunk-xref");
+
+/// Number of reports kept per datastore.
+const XREF_KEEP: usize = 5;
+
+const XREF_PREFIX: &str = "chunk-xref-";
+const XREF_SUFFIX: &str = ".jsonl.zst";
+
+fn xref_dir(store: &str) -> PathBuf {
+    let mut path = PathBuf::from(XREF_BASEDIR);
+    path.push(store);
+    path
+}
+
+fn report_time(name: &str) -> Option<i64> {
+    name.strip_prefix(XREF_PREFIX)?
+        .strip_suffix(XREF_SUFFIX)?
+        .parse()
+        .ok()
+}
+
+/// Returns the path of a stored report, checking the name to not allow escaping the directory.
+pub fn xref_report_path(store: &str, name: &str) -> Result<PathBuf, Error> {
+    if report_time(name).is_none() {
+        bail!("invalid report name '{name}'");
+    }
+    Ok(xref_dir(store).join(name))
+}
+
+/// List the stored reports of a datastore, oldest first.
+pub fn list_xref_reports(store: &str) -> Result<Vec<ChunkXrefReportInfo>, Error> {
+    let mut list = Vec::new();
+
+    let entries = match std::fs::read_dir(xref_dir(store)) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(list),
+        Err(err) => bail!("unable to read chunk cross-reference reports of '{store}' - {err}"),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        let name = match entry.file_name().into_string() {
+            Ok(name) => name,
+            Err(_) => continue,
+        };
+        if let Some(time) = report_time(&name) {
+            list.push(ChunkXrefReportInfo {
+                name,
+                time,
+                size: entry.metadata()?.len(),
+            });
+        }
+    }
+
+    list.sort_unstable_by_key(|info| info.time);
+
+    Ok(list)
+}
+
+/// Load and check a chunk, returns its raw size.
+fn check_chunk(datastore: &DataStore, digest: &[u8; 32]) -> Result<u64, Error> {
+    let chunk = datastore.load_chunk(digest)?;
+    chunk.verify_crc()?;
+    if chunk.crypt_mode()? == CryptMode::None {
+        // verifies the digest
+        chunk.decode_salted(None, Some(digest), datastore.digest_salt())?;
+    }
+    Ok(chunk.raw_size())
+}
+
+/// Generate a cross-reference report of the datastore, returns the name of the stored report.
+///
+/// Every chunk is read and checked, so this takes about as long as verifying all snapshots.
+pub fn generate_xref_report(
+    datastore: Arc<DataStore>,
+    worker: &dyn WorkerTaskContext,
+) -> Result<String, Error> {
+    let store = datastore.name().to_string();
+    let base_path = datastore.base_path();
+
+    task_log!(worker, "collecting indexes of datastore {store}");
+
+    let mut indexes = Vec::new();
+    let mut references: HashMap<[u8; 32], Vec<u32>> = HashMap::new();
+
+    for path in datastore.list_images()? {
+        worker.check_abort()?;
+        worker.fail_on_shutdown()?;
+
+        let index = match datastore.open_index(&path) {
+            Ok(index) => index,
+            Err(err) => {
+                task_warn!(worker, "unable to open index {path:?} - {err}");
+                continue;
+            }
+        };
+
+        let position = indexes.len() as u32;
+        for pos in 0..index.index_count() {
+            let digest = index.index_digest(pos).unwrap();
+            let list = references.entry(*digest).or_default();
+            if list.last() != Some(&position) {
+                list.push(position);
+            }
+        }
+
+        let relative = path.strip_prefix(&base_path).unwrap_or(&path);
+        indexes.push(relative.to_string_lossy().into_owned());
+    }
+
+    task_log!(
+        worker,
+        "found {} indexes referencing {} chunks",
+        indexes.len(),
+        references.len()
+    );
+
+    let backup_user = pbs_config::backup_user()?;
+    let opts = CreateOptions::new()
+        .owner(backup_user.uid)
+        .group(backup_user.gid);
+    let dir = xref_dir(&store);
+    create_path(&dir, Some(opts.clone()), Some(opts))?;
+
+    let time = proxmox_time::epoch_i64();
+    let name = format!("{XREF_PREFIX}{time}{XREF_SUFFIX}");
+    let path = dir.join(&name);
+    let mut tmp_path = path.clone();
+    tmp_path.set_extension("tmp");
+
+    let file = std::fs::File::create(&tmp_path)
+        .map_err(|err| format_err!("unable to create {tmp_path:?} - {err}"))?;
+    let mut writer = zstd::stream::write::Encoder::new(file, 0)?;
+
+    let mut write_line = |value: serde_json::Value| -> Result<(), Error> {
+        serde_json::to_writer(&mut writer, &value)?;
+        writer.write_all(b"\n")?;
+        Ok(())
+    };
+
+    write_line(json!({
+        "store": store,
+        "time": time,
+        "indexes": indexes,
+    }))?;
+
+    let mut counts: HashMap<&str, u64> = HashMap::new();
+    let mut referenced_bytes = 0;
+    let mut unreferenced_bytes = 0;
+    // renamed by an earlier verification, reported once after all chunks were seen
+    let mut bad_chunks = HashSet::new();
+
+    let result = proxmox_lang::try_block!({
+        let mut last_percentage = 0;
+        for (entry, percentage, bad) in datastore.get_chunk_iterator()? {
+            worker.check_abort()?;
+            worker.fail_on_shutdown()?;
+
+            if percentage != last_percentage {
+                task_log!(worker, "processed {percentage}% of the chunk store");
+                last_percentage = percentage;
+            }
+
+            let entry = entry?;
+            let file_name = match entry.file_name().to_str() {
+                Ok(name) => name,
+                Err(_) => continue,
+            };
+            let digest_str = match bad {
+                // renamed by an earlier verification, e.g. "<digest>.0.bad"
+                true => file_name.split('.').next().unwrap_or(file_name),
+                false => file_name,
+            };
+            let mut digest = [0u8; 32];
+            if hex::decode_to_slice(digest_str, &mut digest).is_err() {
+                continue;
+            }
+
+            if bad {
+                bad_chunks.insert(digest);
+                continue;
+            }
+
+            let chunk_indexes = references.remove(&digest);
+            let (state, size) = match check_chunk(&datastore, &digest) {
+                Ok(size) => {
+                    if chunk_indexes.is_some() {
+                        referenced_bytes += size;
+                        ("ok", Some(size))
+                    } else {
+                        unreferenced_bytes += size;
+                        ("unreferenced", Some(size))
+                    }
+                }
+                Err(err) => {
+                    task_warn!(worker, "chunk {digest_str} is corrupt - {err}");
+                    ("corrupt", None)
+                }
+            };
+
+            *counts.entry(state).or_default() += 1;
+            write_line(json!({
+                "digest": digest_str,
+                "state": state,
+                "size": size,
+                "indexes": chunk_indexes.unwrap_or_default(),
+            }))?;
+        }
+
+        // bad chunks which were not rewritten since, a rewritten chunk was reported above
+        for digest in bad_chunks {
+            let (path, digest_str) = datastore.chunk_path(&digest);
+            if path.exists() {
+                continue;
+            }
+            *counts.entry("corrupt").or_default() += 1;
+            write_line(json!({
+                "digest": digest_str,
+                "state": "corrupt",
+                "size": None::<u64>,
+                "indexes": references.remove(&digest).unwrap_or_default(),
+            }))?;
+        }
+
+        // whatever was not found in the chunk store is missing
+        for (digest, chunk_indexes) in references.drain() {
+            *counts.entry("missing").or_default() += 1;
+            write_line(json!({
+                "digest": hex::encode(digest),
+                "state": "missing",
+                "indexes": chunk_indexes,
+            }))?;
+        }
+
+        write_line(json!({
+            "summary": counts,
+            "referenced-bytes": referenced_bytes,
+            "unreferenced-bytes": unreferenced_bytes,
+        }))?;
+
+        Ok(())
+    });
+
+    let result = result.and_then(|()| {
+        writer.finish()?.sync_all()?;
+        std::fs::rename(&tmp_path, &path)
+            .map_err(|err| format_err!("unable to rename {tmp_path:?} - {err}"))
+    });
+    if let Err(err) = result {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(err);
+    }
+
+    for (state, count) in &counts {
+        task_log!(worker, "{state} chunks: {count}");
+    }
+
+    let mut list = list_xref_reports(&store)?;
+    while list.len() > XREF_KEEP {
+        let oldest = list.remove(0);
+        let _ = std::fs::remove_file(dir.join(oldest.name));
+    }
+
+    Ok(name)
+}
@@ -8,3 +8,11 @@ pub use verify::*;
 
 mod hierarchy;
 pub use hierarchy::*;
+
+pub mod verify_report;
+
+pub mod verify_result;
+
+pub mod chunk_xref;
+
+pub mod seed;
@@ -0,0 +1,451 @@
+//! Seeding a datastore from removable disks
+//!
+//! For the initial sync of a large datastore to a remote site, its snapshots can be exported to
+//! a directory (usually a mounted removable disk), carried over and imported into the
+//! destination datastore. The sync job run afterwards then only transfers what changed since
+//! the export.
+//!
+//! A seed directory contains one snapshot export archive per snapshot in `snapshots/`, each
+//! leaving out the chunks already contained in the previous ones, the chunk inventory
+//! [`SEED_INVENTORY_NAME`] listing every chunk of the seed, and the seed manifest
+//! [`SEED_MANIFEST_NAME`], which is written last and so marks a complete seed.
+//!
+//! Imported snapshots are marked by [`SEEDED_MARKER_FILE_NAME`], the next sync verifies them
+//! against the remote and removes the mark. Every export and import is recorded in the seed
+//! history of the datastore.
+//!
+//! Seed directories have to be given by their canonical path, so they cannot be reached via a
+//! symlink. Exports only go into empty directories and never follow or replace existing files,
+//! imports only read regular files.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{bail, format_err, Error};
+use serde::{Deserialize, Serialize};
+
+use proxmox_sys::fs::{create_path, file_read_optional_string, replace_file, CreateOptions};
+use proxmox_sys::{task_log, task_warn, WorkerTaskContext};
+
+use pbs_api_types::{print_ns_and_snapshot, Authid, BackupNamespace, SeedAction, SeedHistoryEntry};
+use pbs_buildcfg::PROXMOX_BACKUP_STATE_DIR_M;
+use pbs_config::open_backup_lockfile;
+use pbs_datastore::snapshot_export::{export_snapshot_incremental, import_snapshot};
+use pbs_datastore::{BackupInfo, DataStore};
+
+/// Name of the seed manifest in a seed directory.
+pub const SEED_MANIFEST_NAME: &str = "seed.json";
+/// Name of the chunk inventory in a seed directory.
+pub const SEED_INVENTORY_NAME: &str = "chunks.list";
+/// Marks a snapshot as imported from a seed and not yet verified by a sync.
+pub const SEEDED_MARKER_FILE_NAME: &str = ".seeded";
+
+const SEED_SNAPSHOT_DIR: &str = "snapshots";
+const SEED_HISTORY_DIR: &str = concat!(PROXMOX_BACKUP_STATE_DIR_M!(), "/seed");
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct SeedSnapshot {
+    /// Namespace relative to the exported namespace
+    ns: BackupNamespace,
+    snapshot: String,
+    /// File name of the export archive in the snapshot directory
+    archive: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct SeedManifest {
+    seed_id: String,
+    store: String,
+    time: i64,
+    snapshots: Vec<SeedSnapshot>,
+    chunks: u64,
+    /// SHA-256 of the chunk inventory, hex encoded
+    inventory_csum: String,
+}
+
+fn history_path(store: &str) -> PathBuf {
+    PathBuf::from(SEED_HISTORY_DIR).join(format!("{store}.json"))
+}
+
+/// Returns the seed exports and imports of a datastore, oldest first.
+pub fn seed_history(store: &str) -> Result<Vec<SeedHistoryEntry>, Error> {
+    match file_read_optional_string(history_path(store))? {
+        Some(content) => serde_json::from_str(&content)
+            .map_err(|err| format_err!("unable to parse seed history of '{store}' - {err}")),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn record_history(store: &str, entry: SeedHistoryEntry) -> Result<(), Error> {
+    let backup_user = pbs_config::backup_user()?;
+    let options = CreateOptions::new()
+        .owner(backup_user.uid)
+        .group(backup_user.gid);
+    create_path(SEED_HISTORY_DIR, None, Some(options.clone()))?;
+
+    let lock_path = PathBuf::from(SEED_HISTORY_DIR).join(format!(".{store}.lck"));
+    let _lock = open_backup_lockfile(lock_path, None, true)?;
+
+    let mut history = seed_history(store)?;
+    history.push(entry);
+
+    replace_file(
+        history_path(store),
+        serde_json::to_string_pretty(&history)?.as_bytes(),
+        options.perm(nix::sys::stat::Mode::from_bits_truncate(0o0640)),
+        true,
+    )
+}
+
+/// Check that `path` is a directory given by its canonical path, so that neither it nor one of
+/// its parents is a symlink.
+pub fn check_seed_dir(path: &Path) -> Result<(), Error> {
+    let canonical = path
+        .canonicalize()
+        .map_err(|err| format_err!("unable to access seed directory {path:?} - {err}"))?;
+    if canonical != path {
+        bail!("seed directory {path:?} has to be given by its canonical path {canonical:?}");
+    }
+    if !path.is_dir() {
+        bail!("seed directory {path:?} is not a directory");
+    }
+    Ok(())
+}
+
+// never follows a symlink and never touches an existing file
+fn create_seed_file(path: &Path) -> Result<File, Error> {
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)
+        .map_err(|err| format_err!("unable to create {path:?} - {err}"))
+}
+
+// only opens regular files, without following symlinks
+fn open_seed_file(path: &Path) -> Result<File, Error> {
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)
+        .map_err(|err| format_err!("unable to open {path:?} - {err}"))?;
+    if !file.metadata()?.is_file() {
+        bail!("{path:?} is not a regular file");
+    }
+    Ok(file)
+}
+
+fn read_seed_file(path: &Path) -> Result<Vec<u8>, Error> {
+    let mut data = Vec::new();
+    open_seed_file(path)?.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+fn list_snapshots(
+    datastore: &Arc<DataStore>,
+    ns: &BackupNamespace,
+) -> Result<Vec<(BackupNamespace, BackupInfo)>, Error> {
+    let mut list = Vec::new();
+    for ns in datastore.recursive_iter_backup_ns_ok(ns.clone(), None)? {
+        for group in datastore.iter_backup_groups_ok(ns.clone())? {
+            let mut snapshots = group.list_backups()?;
+            BackupInfo::sort_list(&mut snapshots, true);
+            for info in snapshots.into_iter().filter(|info| info.is_finished()) {
+                list.push((ns.clone(), info));
+            }
+        }
+    }
+    Ok(list)
+}
+
+/// Export all snapshots in and below `ns` into the seed directory `path`.
+pub fn export_seed(
+    datastore: Arc<DataStore>,
+    ns: BackupNamespace,
+    path: &Path,
+    auth_id: &Authid,
+    worker: &dyn WorkerTaskContext,
+) -> Result<(), Error> {
+    check_seed_dir(path)?;
+    if path.read_dir()?.next().is_some() {
+        bail!("seed directory {path:?} is not empty");
+    }
+
+    let store = datastore.name().to_string();
+    let time = proxmox_time::epoch_i64();
+    let seed_id = format!("{store}:{time:08X}");
+
+    task_log!(worker, "exporting seed {seed_id} to {path:?}");
+
+    let snapshot_dir = path.join(SEED_SNAPSHOT_DIR);
+    std::fs::create_dir(&snapshot_dir)
+        .map_err(|err| format_err!("unable to create {snapshot_dir:?} - {err}"))?;
+
+    let mut exported = HashSet::new();
+    let mut snapshots = Vec::new();
+
+    for (snapshot_ns, info) in list_snapshots(&datastore, &ns)? {
+        worker.check_abort()?;
+        worker.fail_on_shutdown()?;
+
+        let dir = info.backup_dir.dir().clone();
+        match info.backup_dir.load_manifest() {
+            Ok((manifest, _)) if manifest.archived_to().is_some() => {
+                task_warn!(
+                    worker,
+                    "skipping archived snapshot {}",
+                    print_ns_and_snapshot(&snapshot_ns, &dir)
+                );
+                continue;
+            }
+            _ => {}
+        }
+
+        let archive = format!("{:06}.pbsx", snapshots.len());
+        let file = create_seed_file(&snapshot_dir.join(&archive))?;
+        let mut writer = BufWriter::new(file);
+        let chunks = export_snapshot_incremental(
+            datastore.clone(),
+            snapshot_ns.clone(),
+            dir.clone(),
+            &mut writer,
+            &mut exported,
+        )
+        .map_err(|err| {
+            format_err!(
+                "export of {} failed - {err}",
+                print_ns_and_snapshot(&snapshot_ns, &dir)
+            )
+        })?;
+        writer.into_inner()?.sync_all()?;
+
+        task_log!(
+            worker,
+            "exported {} ({chunks} new chunks)",
+            print_ns_and_snapshot(&snapshot_ns, &dir)
+        );
+
+        snapshots.push(SeedSnapshot {
+            ns: snapshot_ns.map_prefix(&ns, &BackupNamespace::root())?,
+            snapshot: dir.to_string(),
+            archive,
+        });
+    }
+
+    let mut inventory: Vec<String> = exported.iter().map(hex::encode).collect();
+    inventory.sort_unstable();
+    let mut inventory = inventory.join("\n");
+    inventory.push('\n');
+    let mut file = create_seed_file(&path.join(SEED_INVENTORY_NAME))?;
+    file.write_all(inventory.as_bytes())?;
+    file.sync_all()?;
+
+    let manifest = SeedManifest {
+        seed_id: seed_id.clone(),
+        store: store.clone(),
+        time,
+        chunks: exported.len() as u64,
+        inventory_csum: hex::encode(openssl::sha::sha256(inventory.as_bytes())),
+        snapshots,
+    };
+    let mut file = create_seed_file(&path.join(SEED_MANIFEST_NAME))?;
+    file.write_all(serde_json::to_string_pretty(&manifest)?.as_bytes())?;
+    file.sync_all()?;
+
+    task_log!(
+        worker,
+        "exported {} snapshots with {} chunks",
+        manifest.snapshots.len(),
+        manifest.chunks
+    );
+
+    record_history(
+        &store,
+        SeedHistoryEntry {
+            action: SeedAction::Export,
+            seed_id,
+            time,
+            path: path.to_string_lossy().into_owned(),
+            source_store: store.clone(),
+            auth_id: auth_id.clone(),
+            snapshots: manifest.snapshots.len() as u64,
+            skipped: 0,
+            chunks: manifest.chunks,
+        },
+    )
+}
+
+fn read_inventory(path: &Path, manifest: &SeedManifest) -> Result<Vec<[u8; 32]>, Error> {
+    let inventory = read_seed_file(&path.join(SEED_INVENTORY_NAME))
+        .map_err(|err| format_err!("unable to read chunk inventory - {err}"))?;
+    if hex::encode(openssl::sha::sha256(&inventory)) != manifest.inventory_csum {
+        bail!("chunk inventory does not match the seed manifest");
+    }
+
+    let mut digests = Vec::new();
+    for line in std::str::from_utf8(&inventory)?.lines() {
+        if line.is_empty() {
+            continue;
+        }
+        let mut digest = [0u8; 32];
+        hex::decode_to_slice(line, &mut digest)
+            .map_err(|err| format_err!("invalid chunk inventory entry '{line}' - {err}"))?;
+        digests.push(digest);
+    }
+    if digests.len() as u64 != manifest.chunks {
+        bail!(
+            "chunk inventory lists {} chunks, the seed manifest {}",
+            digests.len(),
+            manifest.chunks
+        );
+    }
+
+    Ok(digests)
+}
+
+/// Import the seed in directory `path` into `ns`, the snapshots are owned by `owner`.
+///
+/// Snapshots which already exist are skipped, so an interrupted import can be repeated. All
+/// chunks are checked against their digest and the chunk inventory.
+pub fn import_seed(
+    datastore: Arc<DataStore>,
+    ns: BackupNamespace,
+    path: &Path,
+    owner: &Authid,
+    auth_id: &Authid,
+    worker: &dyn WorkerTaskContext,
+) -> Result<(), Error> {
+    check_seed_dir(path)?;
+    let manifest_path = path.join(SEED_MANIFEST_NAME);
+    if manifest_path.symlink_metadata().is_err() {
+        bail!("{path:?} does not contain a complete seed");
+    }
+    let manifest: SeedManifest = serde_json::from_slice(&read_seed_file(&manifest_path)?)
+        .map_err(|err| format_err!("unable to parse seed manifest - {err}"))?;
+    let inventory = read_inventory(path, &manifest)?;
+    let snapshot_dir = path.join(SEED_SNAPSHOT_DIR);
+    if !snapshot_dir.symlink_metadata()?.is_dir() {
+        bail!("{snapshot_dir:?} is not a directory");
+    }
+
+    task_log!(
+        worker,
+        "importing seed {} of datastore '{}' ({} snapshots, {} chunks)",
+        manifest.seed_id,
+        manifest.store,
+        manifest.snapshots.len(),
+        manifest.chunks
+    );
+
+    let mut imported = 0;
+    let mut skipped = 0;
+
+    for entry in manifest.snapshots.iter() {
+        worker.check_abort()?;
+        worker.fail_on_shutdown()?;
+
+        let target_ns = entry.ns.map_prefix(&BackupNamespace::root(), &ns)?;
+        let dir: pbs_api_types::BackupDir = entry.snapshot.parse()?;
+        let snapshot_str = print_ns_and_snapshot(&target_ns, &dir);
+
+        if datastore.snapshot_path(&target_ns, &dir).exists() {
+            task_log!(worker, "skipping existing snapshot {snapshot_str}");
+            skipped += 1;
+            continue;
+        }
+
+        if entry.archive.contains('/') {
+            bail!("invalid archive name '{}' in seed manifest", entry.archive);
+        }
+        datastore.create_namespace_recursive(&target_ns)?;
+
+        let file = open_seed_file(&snapshot_dir.join(&entry.archive))?;
+        let snapshot = import_snapshot(&datastore, &target_ns, owner, BufReader::new(file))
+            .map_err(|err| format_err!("import of {snapshot_str} failed - {err}"))?;
+        if snapshot != dir {
+            bail!("archive '{}' contains {snapshot}, not {dir}", entry.archive);
+        }
+
+        let marker = datastore
+            .snapshot_path(&target_ns, &dir)
+            .join(SEEDED_MARKER_FILE_NAME);
+        std::fs::write(marker, &manifest.seed_id)?;
+
+        task_log!(worker, "imported {snapshot_str}");
+        imported += 1;
+    }
+
+    let mut missing = 0;
+    for digest in inventory.iter() {
+        if !datastore.cond_touch_chunk(digest, false)? {
+            task_warn!(worker, "chunk {} missing after import", hex::encode(digest));
+            missing += 1;
+        }
+    }
+    if missing > 0 {
+        bail!("{missing} chunks of the seed are missing after import");
+    }
+
+    task_log!(
+        worker,
+        "imported {imported} snapshots, skipped {skipped} existing ones"
+    );
+
+    record_history(
+        datastore.name(),
+        SeedHistoryEntry {
+            action: SeedAction::Import,
+            seed_id: manifest.seed_id,
+            time: proxmox_time::epoch_i64(),
+            path: path.to_string_lossy().into_owned(),
+            source_store: manifest.store,
+            auth_id: auth_id.clone(),
+            snapshots: imported,
+            skipped,
+            chunks: manifest.chunks,
+        },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_seed_paths() -> Result<(), Error> {
+        let base = std::env::temp_dir().join(format!("pbs-seed-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        std::fs::create_dir_all(base.join("seed"))?;
+        let base = base.canonicalize()?;
+        let seed = base.join("seed");
+
+        check_seed_dir(&seed)?;
+        assert!(check_seed_dir(&base.join("seed/../seed")).is_err());
+        assert!(check_seed_dir(&base.join("missing")).is_err());
+
+        std::os::unix::fs::symlink(&seed, base.join("link"))?;
+        assert!(check_seed_dir(&base.join("link")).is_err());
+
+        // existing files and symlinks are never written to
+        create_seed_file(&seed.join(SEED_MANIFEST_NAME))?.write_all(b"{}")?;
+        assert!(create_seed_file(&seed.join(SEED_MANIFEST_NAME)).is_err());
+        std::fs::write(base.join("target"), b"data")?;
+        std::os::unix::fs::symlink(base.join("target"), seed.join(SEED_INVENTORY_NAME))?;
+        assert!(create_seed_file(&seed.join(SEED_INVENTORY_NAME)).is_err());
+        assert_eq!(std::fs::read(base.join("target"))?, b"data");
+
+        // only regular files are read
+        assert_eq!(read_seed_file(&seed.join(SEED_MANIFEST_NAME))?, b"{}");
+        assert!(read_seed_file(&seed.join(SEED_INVENTORY_NAME)).is_err());
+        assert!(open_seed_file(&base.join("seed")).is_err());
+
+        let _ = std::fs::remove_dir_all(&base);
+        Ok(())
+    }
+}
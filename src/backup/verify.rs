@@ -10,7 +10,8 @@ use proxmox_sys::{task_log, WorkerTaskContext};
 
 use pbs_api_types::{
     print_ns_and_snapshot, print_store_and_ns, Authid, BackupNamespace, BackupType, CryptMode,
-    SnapshotVerifyState, VerifyState, PRIV_DATASTORE_BACKUP, PRIV_DATASTORE_VERIFY, UPID,
+    SnapshotVerifyOutcome, SnapshotVerifyResult, SnapshotVerifyState, VerifyGroupSelector,
+    VerifyState, PRIV_DATASTORE_BACKUP, PRIV_DATASTORE_VERIFY, UPID,
 };
 use pbs_datastore::backup_info::{BackupDir, BackupGroup, BackupInfo};
 use pbs_datastore::index::IndexFile;
@@ -21,6 +22,7 @@ use proxmox_sys::fs::lock_dir_noblock_shared;
 use crate::tools::parallel_handler::ParallelHandler;
 
 use crate::backup::hierarchy::ListAccessibleBackupGroups;
+use crate::backup::verify_report::ReplicaVerifyState;
 
 /// A VerifyWorker encapsulates a task worker, datastore and information about which chunks have
 /// already been verified or detected as corrupt.
@@ -29,6 +31,10 @@ pub struct VerifyWorker {
     datastore: Arc<DataStore>,
     verified_chunks: Arc<Mutex<HashSet<[u8; 32]>>>,
     corrupt_chunks: Arc<Mutex<HashSet<[u8; 32]>>>,
+    replica: Option<ReplicaVerifyState>,
+    exclude: Vec<VerifyGroupSelector>,
+    priority: Vec<VerifyGroupSelector>,
+    results: Mutex<Vec<SnapshotVerifyResult>>,
 }
 
 impl VerifyWorker {
@@ -41,8 +47,63 @@ impl VerifyWorker {
             verified_chunks: Arc::new(Mutex::new(HashSet::with_capacity(16 * 1024))),
             // start with 64 chunks since we assume there are few corrupt ones
             corrupt_chunks: Arc::new(Mutex::new(HashSet::with_capacity(64))),
+            replica: None,
+            exclude: Vec::new(),
+            priority: Vec::new(),
+            results: Mutex::new(Vec::new()),
         }
     }
+
+    /// Trust successful verifications of a replica instead of reading snapshots locally.
+    pub fn with_replica(mut self, replica: ReplicaVerifyState) -> Self {
+        self.replica = Some(replica);
+        self
+    }
+
+    /// Skip the groups selected by `exclude` when verifying all backups.
+    pub fn with_exclude(mut self, exclude: Vec<VerifyGroupSelector>) -> Self {
+        self.exclude = exclude;
+        self
+    }
+
+    /// Verify the groups selected by `priority` first, in the order of the list, when verifying
+    /// all backups.
+    pub fn with_priority(mut self, priority: Vec<VerifyGroupSelector>) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    fn is_excluded(&self, group: &BackupGroup) -> bool {
+        self.exclude
+            .iter()
+            .any(|selector| selector.matches(group.backup_ns(), group.group()))
+    }
+
+    fn priority_of(&self, group: &BackupGroup) -> usize {
+        priority_position(&self.priority, group.backup_ns(), group.group())
+    }
+
+    fn record_result(
+        &self,
+        backup_dir: &BackupDir,
+        outcome: SnapshotVerifyOutcome,
+        reason: Option<&str>,
+        errors: Vec<String>,
+        start: Instant,
+    ) {
+        self.results.lock().unwrap().push(SnapshotVerifyResult {
+            snapshot: print_ns_and_snapshot(backup_dir.backup_ns(), backup_dir.as_ref()),
+            outcome,
+            reason: reason.map(String::from),
+            errors,
+            duration: start.elapsed().as_secs_f64(),
+        });
+    }
+
+    /// Returns the results of the snapshots visited so far, in order of verification.
+    pub fn take_results(&self) -> Vec<SnapshotVerifyResult> {
+        std::mem::take(&mut *self.results.lock().unwrap())
+    }
 }
 
 fn verify_blob(backup_dir: &BackupDir, info: &FileInfo) -> Result<(), Error> {
@@ -75,6 +136,7 @@ fn rename_corrupted_chunk(
     worker: &dyn WorkerTaskContext,
 ) {
     let (path, digest_str) = datastore.chunk_path(digest);
+    let read_path = datastore.chunk_read_path(digest);
 
     let mut counter = 0;
     let mut new_path = path.clone();
@@ -87,6 +149,24 @@ fn rename_corrupted_chunk(
         }
     }
 
+    if read_path != path {
+        // the chunk was offloaded, keep the corrupt copy next to its stub so it is handled like
+        // any other bad chunk, and drop the stub so the chunk counts as missing
+        let result = std::fs::copy(&read_path, &new_path)
+            .and_then(|_| std::fs::remove_file(&read_path))
+            .and_then(|_| std::fs::remove_file(&path));
+        match result {
+            Ok(()) => task_log!(worker, "corrupted cold chunk moved to {:?}", &new_path),
+            Err(err) => task_log!(
+                worker,
+                "could not move corrupted cold chunk {:?} - {}",
+                &read_path,
+                err
+            ),
+        }
+        return;
+    }
+
     match std::fs::rename(&path, &new_path) {
         Ok(_) => {
             task_log!(worker, "corrupted chunk renamed to {:?}", &new_path);
@@ -147,7 +227,9 @@ fn verify_index_chunks(
                 errors2.fetch_add(1, Ordering::SeqCst);
             }
 
-            if let Err(err) = chunk.verify_unencrypted(size as usize, &digest) {
+            if let Err(err) =
+                chunk.verify_unencrypted_salted(size as usize, &digest, datastore2.digest_salt())
+            {
                 corrupt_chunks2.lock().unwrap().insert(digest);
                 task_log!(worker2, "{}", err);
                 errors2.fetch_add(1, Ordering::SeqCst);
@@ -335,6 +417,13 @@ pub fn verify_backup_dir(
             verify_worker.datastore.name(),
             backup_dir.dir(),
         );
+        verify_worker.record_result(
+            backup_dir,
+            SnapshotVerifyOutcome::Skipped,
+            Some("snapshot does not exist (anymore)"),
+            Vec::new(),
+            Instant::now(),
+        );
         return Ok(true);
     }
 
@@ -355,6 +444,13 @@ pub fn verify_backup_dir(
                 backup_dir.dir(),
                 err,
             );
+            verify_worker.record_result(
+                backup_dir,
+                SnapshotVerifyOutcome::Skipped,
+                Some(&format!("could not acquire snapshot lock: {err}")),
+                Vec::new(),
+                Instant::now(),
+            );
             Ok(true)
         }
     }
@@ -368,6 +464,7 @@ pub fn verify_backup_dir_with_lock(
     filter: Option<&dyn Fn(&BackupManifest) -> bool>,
     _snap_lock: Dir,
 ) -> Result<bool, Error> {
+    let start = Instant::now();
     let manifest = match backup_dir.load_manifest() {
         Ok((manifest, _)) => manifest,
         Err(err) => {
@@ -378,10 +475,35 @@ pub fn verify_backup_dir_with_lock(
                 backup_dir.dir(),
                 err,
             );
+            verify_worker.record_result(
+                backup_dir,
+                SnapshotVerifyOutcome::Failed,
+                None,
+                vec![format!("manifest load error: {err}")],
+                start,
+            );
             return Ok(false);
         }
     };
 
+    if let Some(archive_store) = manifest.archived_to() {
+        task_log!(
+            verify_worker.worker,
+            "SKIPPED: verify {}:{} (archived to '{}')",
+            verify_worker.datastore.name(),
+            backup_dir.dir(),
+            archive_store,
+        );
+        verify_worker.record_result(
+            backup_dir,
+            SnapshotVerifyOutcome::Skipped,
+            Some(&format!("archived to '{archive_store}'")),
+            Vec::new(),
+            start,
+        );
+        return Ok(true);
+    }
+
     if let Some(filter) = filter {
         if !filter(&manifest) {
             task_log!(
@@ -390,6 +512,43 @@ pub fn verify_backup_dir_with_lock(
                 verify_worker.datastore.name(),
                 backup_dir.dir(),
             );
+            verify_worker.record_result(
+                backup_dir,
+                SnapshotVerifyOutcome::Skipped,
+                Some("recently verified"),
+                Vec::new(),
+                start,
+            );
+            return Ok(true);
+        }
+    }
+
+    if let Some(replica) = &verify_worker.replica {
+        if replica.confirms(backup_dir, &manifest) {
+            task_log!(
+                verify_worker.worker,
+                "verify {}:{} - confirmed by replica '{}'",
+                verify_worker.datastore.name(),
+                backup_dir.dir(),
+                replica.remote(),
+            );
+            let verify_state = SnapshotVerifyState {
+                state: VerifyState::Ok,
+                upid,
+            };
+            let verify_state = serde_json::to_value(verify_state)?;
+            backup_dir
+                .update_manifest(|manifest| {
+                    manifest.unprotected["verify_state"] = verify_state;
+                })
+                .map_err(|err| format_err!("unable to update manifest blob - {}", err))?;
+            verify_worker.record_result(
+                backup_dir,
+                SnapshotVerifyOutcome::Ok,
+                Some(&format!("confirmed by replica '{}'", replica.remote())),
+                Vec::new(),
+                start,
+            );
             return Ok(true);
         }
     }
@@ -401,7 +560,7 @@ pub fn verify_backup_dir_with_lock(
         backup_dir.dir()
     );
 
-    let mut error_count = 0;
+    let mut errors = Vec::new();
 
     let mut verify_result = VerifyState::Ok;
     for info in manifest.files() {
@@ -426,7 +585,7 @@ pub fn verify_backup_dir_with_lock(
                 info.filename,
                 err,
             );
-            error_count += 1;
+            errors.push(format!("{}: {err}", info.filename));
             verify_result = VerifyState::Failed;
         }
     }
@@ -442,7 +601,15 @@ pub fn verify_backup_dir_with_lock(
         })
         .map_err(|err| format_err!("unable to update manifest blob - {}", err))?;
 
-    Ok(error_count == 0)
+    let success = errors.is_empty();
+    let outcome = if success {
+        SnapshotVerifyOutcome::Ok
+    } else {
+        SnapshotVerifyOutcome::Failed
+    };
+    verify_worker.record_result(backup_dir, outcome, None, errors, start);
+
+    Ok(success)
 }
 
 /// Verify all backups inside a backup group
@@ -500,6 +667,18 @@ pub fn verify_backup_group(
     Ok(errors)
 }
 
+// position of the first entry of `priority` selecting the group, unlisted groups come last
+fn priority_position(
+    priority: &[VerifyGroupSelector],
+    ns: &BackupNamespace,
+    group: &pbs_api_types::BackupGroup,
+) -> usize {
+    priority
+        .iter()
+        .position(|selector| selector.matches(ns, group))
+        .unwrap_or(priority.len())
+}
+
 /// Verify all (owned) backups inside a datastore
 ///
 /// Errors are logged to the worker log.
@@ -568,10 +747,29 @@ pub fn verify_all_backups(
         }
     };
 
-    list.sort_unstable_by(|a, b| a.group().cmp(b.group()));
+    task_log!(worker, "found {} groups", list.len());
+
+    let found_count = list.len();
+    list.retain(|group| !verify_worker.is_excluded(group));
+    if found_count > list.len() {
+        task_log!(worker, "excluded {} groups", found_count - list.len());
+    }
+
+    list.sort_unstable_by(|a, b| {
+        verify_worker
+            .priority_of(a)
+            .cmp(&verify_worker.priority_of(b))
+            .then_with(|| a.group().cmp(b.group()))
+    });
 
     let group_count = list.len();
-    task_log!(worker, "found {} groups", group_count);
+    if !verify_worker.priority.is_empty() {
+        let prioritized = list
+            .iter()
+            .filter(|group| verify_worker.priority_of(group) < verify_worker.priority.len())
+            .count();
+        task_log!(worker, "verifying {prioritized} prioritized groups first");
+    }
 
     let mut progress = StoreProgress::new(group_count as u64);
 
@@ -614,3 +812,58 @@ pub fn verify_filter(
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_priority_order() {
+        let priority: Vec<VerifyGroupSelector> = ["group:vm/300", "ns:important", "type:ct"]
+            .iter()
+            .map(|selector| selector.parse().unwrap())
+            .collect();
+
+        let root = BackupNamespace::root();
+        let important = BackupNamespace::new("important").unwrap();
+        let group = |ty, id| pbs_api_types::BackupGroup::new(ty, id);
+
+        let mut list = vec![
+            (root.clone(), group(BackupType::Vm, "100")),
+            (root.clone(), group(BackupType::Ct, "200")),
+            (important.clone(), group(BackupType::Vm, "101")),
+            (root.clone(), group(BackupType::Host, "backup")),
+            (important.clone(), group(BackupType::Ct, "201")),
+            (root.clone(), group(BackupType::Vm, "300")),
+        ];
+
+        // like verify_all_backups, unlisted groups keep their regular order
+        list.sort_by(|(a_ns, a), (b_ns, b)| {
+            priority_position(&priority, a_ns, a)
+                .cmp(&priority_position(&priority, b_ns, b))
+                .then_with(|| a.cmp(b))
+        });
+
+        let order: Vec<String> = list
+            .iter()
+            .map(|(ns, group)| format!("{ns}:{group}"))
+            .collect();
+        assert_eq!(
+            order,
+            [
+                ":vm/300",
+                // first match wins, so ct/201 is prioritized by its namespace
+                "important:ct/201",
+                "important:vm/101",
+                ":ct/200",
+                ":host/backup",
+                ":vm/100",
+            ]
+        );
+
+        assert_eq!(
+            priority_position(&[], &root, &group(BackupType::Vm, "100")),
+            0
+        );
+    }
+}
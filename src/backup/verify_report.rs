@@ -0,0 +1,475 @@
+//! Signed verify reports, allowing to skip local verification of snapshots already verified on
+//! a trusted replica, and signed attestations of the snapshots verified by a verification job.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{bail, format_err, Error};
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, PKeyRef, Private};
+use openssl::sign::{Signer, Verifier};
+use serde::Serialize;
+
+use proxmox_sys::fs::{create_path, replace_file, CreateOptions};
+
+use pbs_api_types::{
+    print_ns_and_snapshot, Authid, BackupNamespace, SnapshotVerifyState, VerifyAttestation,
+    VerifyReport, VerifyReportEntry, VerifyState, PRIV_DATASTORE_AUDIT, PRIV_DATASTORE_READ,
+};
+use pbs_buildcfg::{configdir, PROXMOX_BACKUP_STATE_DIR_M};
+use pbs_datastore::backup_info::BackupDir;
+use pbs_datastore::manifest::BackupManifest;
+use pbs_datastore::DataStore;
+use pbs_tools::cert::CertInfo;
+
+use crate::backup::ListAccessibleBackupGroups;
+
+const ATTESTATION_BASEDIR: &str = concat!(PROXMOX_BACKUP_STATE_DIR_M!(), "/verify-attestations");
+
+/// Number of attestations kept per verification job.
+const ATTESTATION_KEEP: usize = 100;
+
+fn signed_data<T: Serialize>(document: &T) -> Result<Vec<u8>, Error> {
+    let mut data = serde_json::to_value(document)?;
+    data.as_object_mut().unwrap().remove("signature"); // exclude
+    proxmox_serde::json::to_canonical_json(&data)
+}
+
+fn sign_document_with_key<T: Serialize>(
+    document: &T,
+    key: &PKeyRef<Private>,
+) -> Result<String, Error> {
+    let mut signer = Signer::new(MessageDigest::sha256(), key)?;
+    let signature = signer.sign_oneshot_to_vec(&signed_data(document)?)?;
+    Ok(hex::encode(signature))
+}
+
+/// Sign a document with the key of this node's certificate, returns the hex encoded signature.
+fn sign_document<T: Serialize>(document: &T) -> Result<String, Error> {
+    let key = proxmox_sys::fs::file_get_contents(configdir!("/proxy.key"))?;
+    let key = PKey::private_key_from_pem(&key)?;
+    sign_document_with_key(document, &key)
+}
+
+// the certificate contained in a document is only trusted if it matches the expected fingerprint
+fn check_document_signature<T: Serialize>(
+    document: &T,
+    certificate: &str,
+    signature: Option<&str>,
+    fingerprint: &str,
+) -> Result<(), Error> {
+    let signature = match signature {
+        Some(signature) => hex::decode(signature)?,
+        None => bail!("document is not signed"),
+    };
+
+    let cert = CertInfo::from_pem(certificate.as_bytes())?;
+    let actual = cert.fingerprint()?;
+    if !actual.eq_ignore_ascii_case(fingerprint) {
+        bail!("certificate fingerprint mismatch ({actual} != {fingerprint})");
+    }
+
+    let key = cert.public_key()?;
+    let mut verifier = Verifier::new(MessageDigest::sha256(), &key)?;
+    if !verifier.verify_oneshot(&signature, &signed_data(document)?)? {
+        bail!("signature is invalid");
+    }
+
+    Ok(())
+}
+
+fn report_entry(backup_dir: &BackupDir, manifest: &BackupManifest) -> Option<VerifyReportEntry> {
+    let verify_state = manifest.unprotected["verify_state"].clone();
+    let verify_state: SnapshotVerifyState = serde_json::from_value(verify_state).ok()?;
+
+    let ns = backup_dir.backup_ns();
+    Some(VerifyReportEntry {
+        ns: (!ns.is_root()).then(|| ns.clone()),
+        backup: backup_dir.dir().clone(),
+        content_digest: hex::encode(manifest.content_digest()),
+        state: verify_state.state,
+        verified: verify_state.upid.starttime,
+    })
+}
+
+/// Generate a verify report for all snapshots below `ns` visible to `auth_id`.
+///
+/// Only snapshots with a verification state are included. The report is signed with the key of
+/// this node's certificate.
+pub fn generate_verify_report(
+    datastore: &Arc<DataStore>,
+    ns: BackupNamespace,
+    max_depth: usize,
+    auth_id: &Authid,
+) -> Result<VerifyReport, Error> {
+    let mut snapshots = Vec::new();
+
+    let groups = ListAccessibleBackupGroups::new_with_privs(
+        datastore,
+        ns,
+        max_depth,
+        Some(PRIV_DATASTORE_AUDIT | PRIV_DATASTORE_READ),
+        None,
+        Some(auth_id),
+    )?;
+
+    for group in groups {
+        for info in group?.list_backups()? {
+            let manifest = match info.backup_dir.load_manifest() {
+                Ok((manifest, _)) => manifest,
+                Err(_) => continue,
+            };
+            // skip snapshots which were never verified
+            if let Some(entry) = report_entry(&info.backup_dir, &manifest) {
+                snapshots.push(entry);
+            }
+        }
+    }
+
+    let mut report = VerifyReport {
+        node: proxmox_sys::nodename().to_string(),
+        store: datastore.name().to_string(),
+        generated: proxmox_time::epoch_i64(),
+        snapshots,
+        certificate: proxmox_sys::fs::file_read_string(configdir!("/proxy.pem"))?,
+        signature: None,
+    };
+
+    report.signature = Some(sign_document(&report)?);
+
+    Ok(report)
+}
+
+/// Check the signature of a verify report.
+///
+/// The certificate contained in the report must match `fingerprint`, the configured fingerprint
+/// of the replica remote.
+pub fn check_verify_report(report: &VerifyReport, fingerprint: &str) -> Result<(), Error> {
+    check_document_signature(
+        report,
+        &report.certificate,
+        report.signature.as_deref(),
+        fingerprint,
+    )
+    .map_err(|err| format_err!("verify report - {err}"))
+}
+
+/// Generate a signed attestation for the snapshots verified by the task `upid`.
+///
+/// Snapshots are recognized by the task recorded in their verification state, so this must be
+/// called after the verification finished.
+pub fn generate_verify_attestation(
+    datastore: &Arc<DataStore>,
+    ns: BackupNamespace,
+    max_depth: usize,
+    job: &str,
+    upid: &str,
+) -> Result<VerifyAttestation, Error> {
+    let mut snapshots = Vec::new();
+
+    let groups =
+        ListAccessibleBackupGroups::new_with_privs(datastore, ns, max_depth, None, None, None)?;
+
+    for group in groups {
+        for info in group?.list_backups()? {
+            let manifest = match info.backup_dir.load_manifest() {
+                Ok((manifest, _)) => manifest,
+                Err(_) => continue,
+            };
+            let verified_by_task =
+                manifest.unprotected["verify_state"]["upid"].as_str() == Some(upid);
+            if !verified_by_task {
+                continue;
+            }
+            if let Some(entry) = report_entry(&info.backup_dir, &manifest) {
+                snapshots.push(entry);
+            }
+        }
+    }
+
+    let mut attestation = VerifyAttestation {
+        node: proxmox_sys::nodename().to_string(),
+        store: datastore.name().to_string(),
+        job: job.to_string(),
+        upid: upid.to_string(),
+        generated: proxmox_time::epoch_i64(),
+        snapshots,
+        certificate: proxmox_sys::fs::file_read_string(configdir!("/proxy.pem"))?,
+        signature: None,
+    };
+
+    attestation.signature = Some(sign_document(&attestation)?);
+
+    Ok(attestation)
+}
+
+fn attestation_dir(job: &str) -> PathBuf {
+    let mut path = PathBuf::from(ATTESTATION_BASEDIR);
+    path.push(job);
+    path
+}
+
+fn attestation_file_name(upid: &str) -> String {
+    // UPIDs contain colons, name the file after a digest of the UPID instead
+    let digest = openssl::sha::sha256(upid.as_bytes());
+    hex::encode(&digest[..16]) + ".json"
+}
+
+/// Store an attestation of a verification job, only the newest attestations are kept.
+pub fn store_verify_attestation(attestation: &VerifyAttestation) -> Result<(), Error> {
+    let backup_user = pbs_config::backup_user()?;
+    let opts = CreateOptions::new()
+        .owner(backup_user.uid)
+        .group(backup_user.gid);
+
+    let dir = attestation_dir(&attestation.job);
+    create_path(&dir, Some(opts.clone()), Some(opts.clone()))?;
+
+    let data = serde_json::to_string_pretty(attestation)?;
+    replace_file(
+        dir.join(attestation_file_name(&attestation.upid)),
+        data.as_bytes(),
+        opts.perm(nix::sys::stat::Mode::from_bits_truncate(0o0640)),
+        false,
+    )?;
+
+    let mut list = list_verify_attestations(&attestation.job)?;
+    while list.len() > ATTESTATION_KEEP {
+        let oldest = list.remove(0);
+        let _ = std::fs::remove_file(dir.join(attestation_file_name(&oldest.upid)));
+    }
+
+    Ok(())
+}
+
+/// List the stored attestations of a verification job, oldest first.
+pub fn list_verify_attestations(job: &str) -> Result<Vec<VerifyAttestation>, Error> {
+    let mut list = Vec::new();
+
+    let entries = match std::fs::read_dir(attestation_dir(job)) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(list),
+        Err(err) => bail!("unable to read attestations of job '{job}' - {err}"),
+    };
+
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension() != Some("json".as_ref()) {
+            continue;
+        }
+        let data = proxmox_sys::fs::file_get_contents(&path)?;
+        match serde_json::from_slice::<VerifyAttestation>(&data) {
+            Ok(attestation) => list.push(attestation),
+            Err(err) => log::warn!("skipping invalid attestation {path:?} - {err}"),
+        }
+    }
+
+    list.sort_unstable_by_key(|attestation| attestation.generated);
+
+    Ok(list)
+}
+
+/// Remove all stored attestations of a verification job.
+pub fn remove_verify_attestations(job: &str) -> Result<(), Error> {
+    match std::fs::remove_dir_all(attestation_dir(job)) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+            bail!("unable to remove attestations of job '{job}' - {err}")
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Snapshots confirmed as good by a replica, mapped to their local namespace.
+pub struct ReplicaVerifyState {
+    remote: String,
+    // keyed by the printed namespace and snapshot path
+    snapshots: HashMap<String, [u8; 32]>,
+}
+
+impl ReplicaVerifyState {
+    /// Collect successful verifications from a (checked) report.
+    ///
+    /// Snapshots below `remote_ns` on the replica are mapped to `local_ns`. Verifications older
+    /// than `outdated_after` days are ignored.
+    pub fn from_report(
+        remote: &str,
+        report: VerifyReport,
+        remote_ns: &BackupNamespace,
+        local_ns: &BackupNamespace,
+        outdated_after: Option<i64>,
+    ) -> Result<Self, Error> {
+        let now = proxmox_time::epoch_i64();
+        let mut snapshots = HashMap::new();
+
+        for entry in report.snapshots {
+            if entry.state != VerifyState::Ok {
+                continue;
+            }
+            if let Some(max_age) = outdated_after {
+                if (now - entry.verified) / 86400 > max_age {
+                    continue;
+                }
+            }
+
+            let ns = entry.ns.unwrap_or_default();
+            let ns = match ns.map_prefix(remote_ns, local_ns) {
+                Ok(ns) => ns,
+                Err(_) => continue, // outside of the mapped namespace
+            };
+
+            let mut digest = [0u8; 32];
+            hex::decode_to_slice(&entry.content_digest, &mut digest)
+                .map_err(|err| format_err!("invalid content digest in verify report - {err}"))?;
+
+            snapshots.insert(print_ns_and_snapshot(&ns, &entry.backup), digest);
+        }
+
+        Ok(Self {
+            remote: remote.to_string(),
+            snapshots,
+        })
+    }
+
+    /// Name of the replica remote.
+    pub fn remote(&self) -> &str {
+        &self.remote
+    }
+
+    /// Number of snapshots confirmed by the replica.
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    /// Returns whether the replica verified an identical copy of the snapshot.
+    pub fn confirms(&self, backup_dir: &BackupDir, manifest: &BackupManifest) -> bool {
+        let key = print_ns_and_snapshot(backup_dir.backup_ns(), backup_dir.dir());
+        match self.snapshots.get(&key) {
+            Some(digest) => *digest == manifest.content_digest(),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::nid::Nid;
+    use openssl::x509::{X509NameBuilder, X509};
+
+    fn test_certificate() -> Result<(PKey<Private>, String), Error> {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+        let key = PKey::from_ec_key(EcKey::generate(&group)?)?;
+
+        let mut name = X509NameBuilder::new()?;
+        name.append_entry_by_text("CN", "replica")?;
+        let name = name.build();
+
+        let mut cert = X509::builder()?;
+        cert.set_version(2)?;
+        cert.set_subject_name(&name)?;
+        cert.set_issuer_name(&name)?;
+        cert.set_pubkey(&key)?;
+        cert.set_not_before(&*openssl::asn1::Asn1Time::days_from_now(0)?)?;
+        cert.set_not_after(&*openssl::asn1::Asn1Time::days_from_now(1)?)?;
+        cert.sign(&key, MessageDigest::sha256())?;
+        let cert = String::from_utf8(cert.build().to_pem()?)?;
+
+        Ok((key, cert))
+    }
+
+    fn signed_report(key: &PKey<Private>, certificate: &str) -> Result<VerifyReport, Error> {
+        let mut report = VerifyReport {
+            node: "replica".to_string(),
+            store: "store1".to_string(),
+            generated: 1_700_000_000,
+            snapshots: vec![VerifyReportEntry {
+                ns: None,
+                backup: "vm/100/2023-11-14T22:13:20Z".parse()?,
+                content_digest: hex::encode([1u8; 32]),
+                state: VerifyState::Ok,
+                verified: 1_700_000_000,
+            }],
+            certificate: certificate.to_string(),
+            signature: None,
+        };
+        report.signature = Some(sign_document_with_key(&report, key)?);
+        Ok(report)
+    }
+
+    #[test]
+    fn test_attestation_signature() -> Result<(), Error> {
+        let (key, cert) = test_certificate()?;
+        let fingerprint = CertInfo::from_pem(cert.as_bytes())?.fingerprint()?;
+
+        let report = signed_report(&key, &cert)?;
+        let mut attestation = VerifyAttestation {
+            node: report.node,
+            store: report.store,
+            job: "verify-store1".to_string(),
+            upid: "UPID:node:00000001:00000002:00000003:65540000:verificationjob:store1\\x3averify\\x2dstore1:root@pam:".to_string(),
+            generated: report.generated,
+            snapshots: report.snapshots,
+            certificate: cert.clone(),
+            signature: None,
+        };
+        attestation.signature = Some(sign_document_with_key(&attestation, &key)?);
+        check_document_signature(
+            &attestation,
+            &attestation.certificate,
+            attestation.signature.as_deref(),
+            &fingerprint,
+        )?;
+
+        // the signature covers the job and task, not just the snapshot list
+        let mut moved = attestation.clone();
+        moved.job = "other".to_string();
+        assert!(check_document_signature(
+            &moved,
+            &moved.certificate,
+            moved.signature.as_deref(),
+            &fingerprint,
+        )
+        .is_err());
+
+        // file names are stable and do not contain the colons of the UPID
+        let name = attestation_file_name(&attestation.upid);
+        assert_eq!(name, attestation_file_name(&attestation.upid));
+        assert!(name.ends_with(".json") && !name.contains(':'));
+        assert_ne!(name, attestation_file_name("UPID:other"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_verify_report() -> Result<(), Error> {
+        let (key, cert) = test_certificate()?;
+        let fingerprint = CertInfo::from_pem(cert.as_bytes())?.fingerprint()?;
+
+        let report = signed_report(&key, &cert)?;
+        check_verify_report(&report, &fingerprint)?;
+        check_verify_report(&report, &fingerprint.to_uppercase())?;
+
+        // a report signed by some other certificate is not trusted, even if its signature is
+        // valid for the certificate it contains
+        let (other_key, other_cert) = test_certificate()?;
+        let other = signed_report(&other_key, &other_cert)?;
+        assert!(check_verify_report(&other, &fingerprint).is_err());
+
+        let mut tampered = report.clone();
+        tampered.snapshots[0].state = VerifyState::Failed;
+        assert!(check_verify_report(&tampered, &fingerprint).is_err());
+
+        let mut unsigned = report;
+        unsigned.signature = None;
+        assert!(check_verify_report(&unsigned, &fingerprint).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,205 @@
+//! Results of verification job runs
+//!
+//! The result of the last run of each verification job is stored as JSON, listing every snapshot
+//! with its outcome and the errors found. With the job's `junit-report` option, the result is
+//! additionally written as JUnit XML file, for test-reporting tools to pick up.
+
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use anyhow::{bail, Error};
+
+use proxmox_sys::fs::{create_path, file_read_optional_string, replace_file, CreateOptions};
+
+use pbs_api_types::{SnapshotVerifyOutcome, VerifyJobResult};
+use pbs_buildcfg::PROXMOX_BACKUP_STATE_DIR_M;
+
+const VERIFY_RESULT_DIR: &str = concat!(PROXMOX_BACKUP_STATE_DIR_M!(), "/verify-results");
+
+fn result_path(job: &str, extension: &str) -> PathBuf {
+    let mut path = PathBuf::from(VERIFY_RESULT_DIR);
+    path.push(format!("{job}.{extension}"));
+    path
+}
+
+/// Store the result of a verification job run, replacing the one of the previous run.
+pub fn store_verify_job_result(result: &VerifyJobResult, junit: bool) -> Result<(), Error> {
+    let backup_user = pbs_config::backup_user()?;
+    let opts = CreateOptions::new()
+        .owner(backup_user.uid)
+        .group(backup_user.gid);
+
+    create_path(VERIFY_RESULT_DIR, Some(opts.clone()), Some(opts.clone()))?;
+
+    let opts = opts.perm(nix::sys::stat::Mode::from_bits_truncate(0o0640));
+    let data = serde_json::to_string_pretty(result)?;
+    replace_file(
+        result_path(&result.job, "json"),
+        data.as_bytes(),
+        opts.clone(),
+        false,
+    )?;
+
+    let junit_path = result_path(&result.job, "xml");
+    if junit {
+        let data = verify_job_result_to_junit(result);
+        replace_file(junit_path, data.as_bytes(), opts, false)?;
+    } else {
+        let _ = std::fs::remove_file(junit_path);
+    }
+
+    Ok(())
+}
+
+/// Load the result of the last run of a verification job, if any.
+pub fn load_verify_job_result(job: &str) -> Result<Option<VerifyJobResult>, Error> {
+    match file_read_optional_string(result_path(job, "json"))? {
+        Some(data) => Ok(Some(serde_json::from_str(&data)?)),
+        None => Ok(None),
+    }
+}
+
+/// Remove the stored results of a verification job.
+pub fn remove_verify_job_result(job: &str) -> Result<(), Error> {
+    for extension in ["json", "xml"] {
+        match std::fs::remove_file(result_path(job, extension)) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                bail!("unable to remove verify result of job '{job}' - {err}")
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // not allowed in XML 1.0
+            c if c.is_control() && !matches!(c, '\n' | '\r' | '\t') => escaped.push(' '),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Format a verification job result as JUnit XML, with one test case per snapshot.
+pub fn verify_job_result_to_junit(result: &VerifyJobResult) -> String {
+    let count = |outcome| {
+        result
+            .snapshots
+            .iter()
+            .filter(|snapshot| snapshot.outcome == outcome)
+            .count()
+    };
+    let timestamp = proxmox_time::epoch_to_rfc3339_utc(result.starttime).unwrap_or_default();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites>\n");
+    let _ = writeln!(
+        xml,
+        "  <testsuite name=\"{}\" hostname=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"0\" \
+        skipped=\"{}\" timestamp=\"{}\" time=\"{}\">",
+        xml_escape(&format!("verify/{}", result.job)),
+        xml_escape(&proxmox_sys::nodename()),
+        result.snapshots.len(),
+        count(SnapshotVerifyOutcome::Failed),
+        count(SnapshotVerifyOutcome::Skipped),
+        xml_escape(&timestamp),
+        result.endtime - result.starttime,
+    );
+    let _ = writeln!(
+        xml,
+        "    <properties>\n      <property name=\"upid\" value=\"{}\"/>\n    </properties>",
+        xml_escape(&result.upid),
+    );
+
+    for snapshot in result.snapshots.iter() {
+        let _ = write!(
+            xml,
+            "    <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\"",
+            xml_escape(&result.store),
+            xml_escape(&snapshot.snapshot),
+            snapshot.duration,
+        );
+        let reason = snapshot.reason.as_deref().unwrap_or_default();
+        match snapshot.outcome {
+            SnapshotVerifyOutcome::Ok => xml.push_str("/>\n"),
+            SnapshotVerifyOutcome::Skipped => {
+                let _ = writeln!(
+                    xml,
+                    ">\n      <skipped message=\"{}\"/>\n    </testcase>",
+                    xml_escape(reason),
+                );
+            }
+            SnapshotVerifyOutcome::Failed => {
+                let message = snapshot
+                    .errors
+                    .first()
+                    .map(String::as_str)
+                    .unwrap_or(reason);
+                let _ = writeln!(
+                    xml,
+                    ">\n      <failure message=\"{}\">{}</failure>\n    </testcase>",
+                    xml_escape(message),
+                    xml_escape(&snapshot.errors.join("\n")),
+                );
+            }
+        }
+    }
+
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use pbs_api_types::SnapshotVerifyResult;
+
+    #[test]
+    fn test_xml_escape() {
+        assert_eq!(xml_escape("a<b>&\"c'"), "a&lt;b&gt;&amp;&quot;c&apos;");
+        assert_eq!(xml_escape("line\nnext\x07"), "line\nnext ");
+    }
+
+    #[test]
+    fn test_junit_counts() {
+        let snapshot = |name: &str, outcome| SnapshotVerifyResult {
+            snapshot: name.to_string(),
+            outcome,
+            reason: None,
+            errors: Vec::new(),
+            duration: 0.5,
+        };
+        let mut failed = snapshot("vm/101/2023-01-01T00:00:00Z", SnapshotVerifyOutcome::Failed);
+        failed.errors.push("chunk <abc> missing".to_string());
+
+        let result = VerifyJobResult {
+            store: "store1".to_string(),
+            job: "v-1".to_string(),
+            upid: "UPID:test".to_string(),
+            starttime: 0,
+            endtime: 10,
+            snapshots: vec![
+                snapshot("vm/100/2023-01-01T00:00:00Z", SnapshotVerifyOutcome::Ok),
+                failed,
+                snapshot(
+                    "vm/102/2023-01-01T00:00:00Z",
+                    SnapshotVerifyOutcome::Skipped,
+                ),
+            ],
+        };
+
+        let xml = verify_job_result_to_junit(&result);
+        assert!(xml.contains("tests=\"3\" failures=\"1\" errors=\"0\" skipped=\"1\""));
+        assert!(xml.contains("<failure message=\"chunk &lt;abc&gt; missing\">"));
+        assert!(xml.contains("name=\"vm/102/2023-01-01T00:00:00Z\" time=\"0.500\">"));
+    }
+}
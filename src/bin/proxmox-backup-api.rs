@@ -156,19 +156,49 @@ async fn run() -> Result<(), Error> {
     });
 
     start_notification_worker();
+    start_auth_last_used_worker();
+    start_local_api_forwarding()?;
 
     server.await?;
     log::info!("server shutting down, waiting for active workers to complete");
     proxmox_rest_server::last_worker_future().await?;
+    proxmox_backup::server::auth_last_used::flush_pending().await;
 
     log::info!("done - exit server");
 
     Ok(())
 }
 
+// protected calls made via the local API socket of the proxy
+fn start_local_api_forwarding() -> Result<(), Error> {
+    use proxmox_backup::server::local_api;
+
+    let listener = local_api::bind_socket(local_api::LOCAL_API_FORWARD_SOCKET_FN, 0o600)?;
+    let backup_user = pbs_config::backup_user()?;
+    nix::unistd::chown(
+        local_api::LOCAL_API_FORWARD_SOCKET_FN,
+        Some(backup_user.uid),
+        Some(backup_user.gid),
+    )?;
+
+    let task = futures::future::select(
+        Box::pin(local_api::serve_forwarded_api(listener)),
+        proxmox_rest_server::shutdown_future(),
+    );
+    tokio::spawn(task);
+    Ok(())
+}
+
 fn start_notification_worker() {
     let abort_future = proxmox_rest_server::shutdown_future();
     let future = Box::pin(proxmox_backup::server::notifications::notification_worker());
     let task = futures::future::select(future, abort_future);
     tokio::spawn(task);
 }
+
+fn start_auth_last_used_worker() {
+    let abort_future = proxmox_rest_server::shutdown_future();
+    let future = Box::pin(proxmox_backup::server::auth_last_used::flush_worker());
+    let task = futures::future::select(future, abort_future);
+    tokio::spawn(task);
+}
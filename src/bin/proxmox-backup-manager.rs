@@ -442,11 +442,62 @@ async fn verify(store: String, mut param: Value) -> Result<Value, Error> {
     Ok(Value::Null)
 }
 
-#[api()]
+#[api(
+   input: {
+        properties: {
+            "store": {
+                schema: DATASTORE_SCHEMA,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+   }
+)]
+/// Check all chunks and generate a chunk cross-reference report
+async fn chunk_xref(store: String, mut param: Value) -> Result<Value, Error> {
+    let output_format = extract_output_format(&mut param);
+
+    let client = connect_to_localhost()?;
+
+    let path = format!("api2/json/admin/datastore/{}/chunk-xref", store);
+
+    let result = client.post(&path, None).await?;
+
+    view_task_result(&client, result, &output_format).await?;
+
+    Ok(Value::Null)
+}
+
+#[api(
+    input: {
+        properties: {
+            output: {
+                type: String,
+                optional: true,
+                description: "Write a support bundle (.tar.zst) containing the report and the \
+                    logs of recent failed tasks to this file instead of printing the report.",
+            },
+        }
+    }
+)]
 /// System report
-async fn report() -> Result<Value, Error> {
-    let report = proxmox_backup::server::generate_report();
-    io::stdout().write_all(report.as_bytes())?;
+async fn report(output: Option<String>) -> Result<Value, Error> {
+    match output {
+        Some(output) => {
+            let bundle = proxmox_backup::server::generate_report_bundle()?;
+            let options =
+                CreateOptions::new().perm(nix::sys::stat::Mode::from_bits_truncate(0o600));
+            proxmox_sys::fs::replace_file(&output, &bundle, options, false)
+                .map_err(|err| format_err!("unable to write support bundle {output:?} - {err}"))?;
+            println!("wrote support bundle to {output:?}");
+        }
+        None => {
+            let report = proxmox_backup::server::generate_report();
+            io::stdout().write_all(report.as_bytes())?;
+        }
+    }
     Ok(Value::Null)
 }
 
@@ -491,7 +542,6 @@ async fn get_versions(verbose: bool, param: Value) -> Result<Value, Error> {
 }
 
 async fn run() -> Result<(), Error> {
-    init_cli_logger("PBS_LOG", "info");
     proxmox_backup::server::notifications::init()?;
 
     let cmd_def = CliCommandMap::new()
@@ -499,6 +549,7 @@ async fn run() -> Result<(), Error> {
         .insert("datastore", datastore_commands())
         .insert("disk", disk_commands())
         .insert("dns", dns_commands())
+        .insert("holiday-calendar", holiday_calendar_commands())
         .insert("ldap", ldap_commands())
         .insert("ad", ad_commands())
         .insert("network", network_commands())
@@ -533,6 +584,12 @@ async fn run() -> Result<(), Error> {
                 .arg_param(&["store"])
                 .completion_cb("store", pbs_config::datastore::complete_datastore_name),
         )
+        .insert(
+            "chunk-xref",
+            CliCommand::new(&API_METHOD_CHUNK_XREF)
+                .arg_param(&["store"])
+                .completion_cb("store", pbs_config::datastore::complete_datastore_name),
+        )
         .insert("report", CliCommand::new(&API_METHOD_REPORT))
         .insert("versions", CliCommand::new(&API_METHOD_GET_VERSIONS));
 
@@ -571,6 +628,9 @@ async fn run() -> Result<(), Error> {
 
 fn main() -> Result<(), Error> {
     proxmox_backup::tools::setup_safe_path_env();
+    init_cli_logger("PBS_LOG", "info");
+    // before the runtime starts any threads, setlocale is not thread safe
+    pbs_tools::format::setup_locale();
 
     proxmox_async::runtime::main(run())
 }
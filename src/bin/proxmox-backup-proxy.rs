@@ -1,4 +1,5 @@
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 
 use anyhow::{bail, format_err, Context, Error};
@@ -32,8 +33,14 @@ use proxmox_backup::rrd_cache::{
 };
 use proxmox_backup::{
     server::{
+        api_stats::{api_stats, ApiStatsMakeService},
         auth::check_pbs_auth,
+        datastore_window::{datastore_window_closed_for, window_closed_for, DatastoreAccess},
         jobstate::{self, Job},
+        local_api,
+        schedule::ScheduledJobConfig,
+        space_watermark::{self, SpaceLevel},
+        status_page::{StatusPage, StatusPageMakeService},
     },
     tools::disks::BlockDevStat,
     traffic_control_cache::{SharedRateLimit, TRAFFIC_CONTROL_CACHE},
@@ -43,8 +50,8 @@ use pbs_buildcfg::configdir;
 use proxmox_time::CalendarEvent;
 
 use pbs_api_types::{
-    Authid, DataStoreConfig, Operation, PruneJobConfig, SyncJobConfig, TapeBackupJobConfig,
-    VerificationJobConfig,
+    ApiStatsSortBy, Authid, DataStoreConfig, Operation, PruneJobConfig, ScheduleExclusion,
+    SyncJobConfig, TapeBackupJobConfig, VerificationJobConfig,
 };
 
 use proxmox_rest_server::daemon;
@@ -60,6 +67,7 @@ use proxmox_backup::api2::pull::do_sync_job;
 use proxmox_backup::api2::tape::backup::do_tape_backup_job;
 use proxmox_backup::server::do_prune_job;
 use proxmox_backup::server::do_verification_job;
+use proxmox_backup::server::restore_accounting::rrd_update_restore_accounting;
 
 fn main() -> Result<(), Error> {
     pbs_tools::setup_libc_malloc_opts();
@@ -253,8 +261,51 @@ async fn run() -> Result<(), Error> {
             &mut command_sock,
         )?;
 
-    let rest_server = RestServer::new(config);
+    let (node_config, _) = proxmox_backup::config::node::config()?;
+
+    let status_page = node_config
+        .status_page_path
+        .clone()
+        .map(|path| StatusPage::new(path, node_config.status_page_rate_limit.unwrap_or(60)));
+    let rest_server = StatusPageMakeService::new(
+        ApiStatsMakeService::new(RestServer::new(config), &proxmox_backup::api2::ROUTER),
+        status_page,
+    );
     let redirector = Redirector::new();
+
+    let port = node_config
+        .port
+        .unwrap_or(proxmox_backup::config::node::DEFAULT_PROXY_PORT);
+
+    // reduced API for restores, so it can be exposed to networks without access to the full API
+    let restore_server = match node_config.restore_port {
+        Some(restore_port) => {
+            let mut restore_command_sock =
+                proxmox_rest_server::CommandSocket::new(restore_ctrl_sock(), backup_user.gid);
+            let config = ApiConfig::new(pbs_buildcfg::JS_DIR, RpcEnvironmentType::PUBLIC)
+                .auth_handler_func(|h, m| Box::pin(check_pbs_auth(h, m)))
+                .default_api2_handler(&proxmox_backup::api2::RESTORE_ROUTER)
+                .enable_access_log(
+                    pbs_buildcfg::API_ACCESS_LOG_FN,
+                    Some(dir_opts.clone()),
+                    Some(file_opts.clone()),
+                    &mut restore_command_sock,
+                )?
+                .enable_auth_log(
+                    pbs_buildcfg::API_AUTH_LOG_FN,
+                    Some(dir_opts.clone()),
+                    Some(file_opts.clone()),
+                    &mut restore_command_sock,
+                )?;
+            let rest_server = RestServer::new(config);
+            Some((
+                restore_port,
+                ApiStatsMakeService::new(rest_server, &proxmox_backup::api2::RESTORE_ROUTER),
+                restore_command_sock,
+            ))
+        }
+        None => None,
+    };
     proxmox_rest_server::init_worker_tasks(
         pbs_buildcfg::PROXMOX_BACKUP_LOG_DIR_M!().into(),
         file_opts.clone(),
@@ -266,9 +317,15 @@ async fn run() -> Result<(), Error> {
     let acceptor = make_tls_acceptor()?;
     let acceptor = Arc::new(Mutex::new(acceptor));
 
+    let restore_acceptor = match restore_server {
+        Some(_) => Some(Arc::new(Mutex::new(make_restore_tls_acceptor()?))),
+        None => None,
+    };
+
     // to renew the acceptor we just add a command-socket handler
     command_sock.register_command("reload-certificate".to_string(), {
         let acceptor = Arc::clone(&acceptor);
+        let restore_acceptor = restore_acceptor.clone();
         move |_value| -> Result<_, Error> {
             log::info!("reloading certificate");
             match make_tls_acceptor() {
@@ -278,6 +335,12 @@ async fn run() -> Result<(), Error> {
                     *guard = new_acceptor;
                 }
             }
+            if let Some(restore_acceptor) = &restore_acceptor {
+                match make_restore_tls_acceptor() {
+                    Err(err) => log::error!("error reloading restore listener certificate: {err}"),
+                    Ok(new_acceptor) => *restore_acceptor.lock().unwrap() = new_acceptor,
+                }
+            }
             Ok(Value::Null)
         }
     })?;
@@ -306,7 +369,7 @@ async fn run() -> Result<(), Error> {
         .tcp_keepalive_time(PROXMOX_BACKUP_TCP_KEEPALIVE_TIME);
 
     let server = daemon::create_daemon(
-        ([0, 0, 0, 0, 0, 0, 0, 0], 8007).into(),
+        ([0, 0, 0, 0, 0, 0, 0, 0], port).into(),
         move |listener| {
             let (secure_connections, insecure_connections) =
                 connections.accept_tls_optional(listener, acceptor);
@@ -360,6 +423,43 @@ async fn run() -> Result<(), Error> {
         bail!("unable to start daemon - {err}");
     }
 
+    if let (
+        Some((restore_port, restore_rest_server, restore_command_sock)),
+        Some(restore_acceptor),
+    ) = (restore_server, restore_acceptor)
+    {
+        restore_command_sock.spawn()?;
+        RESTORE_LISTENER_STARTED.store(true, Ordering::SeqCst);
+
+        // not handed over on reload like the main listener, so allow the new process to bind
+        // the port while the old one is still finishing its connections
+        let listener = bind_reuse_port(restore_port)
+            .map_err(|err| format_err!("unable to bind restore port {restore_port} - {err}"))?;
+        let restore_connections = proxmox_rest_server::connection::AcceptBuilder::new()
+            .debug(debug)
+            .rate_limiter_lookup(Arc::new(lookup_rate_limiter))
+            .tcp_keepalive_time(PROXMOX_BACKUP_TCP_KEEPALIVE_TIME)
+            .accept_tls(listener, restore_acceptor);
+
+        let restore_server = hyper::Server::builder(restore_connections)
+            .serve(restore_rest_server)
+            .with_graceful_shutdown(proxmox_rest_server::shutdown_future());
+        tokio::spawn(async move {
+            if let Err(err) = restore_server.await {
+                log::error!("restore listener failed - {err}");
+            }
+        });
+        log::info!("restore listener started on port {restore_port}");
+    }
+
+    // always listening, the uid mapping is read for every connection
+    let listener = local_api::bind_socket(local_api::LOCAL_API_SOCKET_FN, 0o666)?;
+    let task = futures::future::select(
+        Box::pin(local_api::serve_local_api(listener)),
+        proxmox_rest_server::shutdown_future(),
+    );
+    tokio::spawn(task.map(|_| ()));
+
     // stop gap for https://github.com/tokio-rs/tokio/issues/4730 where the thread holding the
     // IO-driver may block progress completely if it starts polling its own tasks (blocks).
     // So, trigger a notify to parked threads, as we're immediately ready the woken up thread will
@@ -374,19 +474,58 @@ async fn run() -> Result<(), Error> {
     start_task_scheduler();
     start_stat_generator();
     start_traffic_control_updater();
+    start_auth_last_used_worker();
+    server::config_watch::start_config_watcher();
 
     server.await?;
     log::info!("server shutting down, waiting for active workers to complete");
     proxmox_rest_server::last_worker_future().await?;
+    proxmox_backup::server::auth_last_used::flush_pending().await;
     log::info!("done - exit server");
 
     Ok(())
 }
 
+/// Set once the restore listener runs, whose log files need to be reopened as well on rotation.
+static RESTORE_LISTENER_STARTED: AtomicBool = AtomicBool::new(false);
+
+// the restore listener writes to the same access and auth logs, but needs its own control socket
+// for reopening them, as a command can only be registered once per socket
+fn restore_ctrl_sock() -> String {
+    format!(
+        "\0{}/restore-control-{}.sock",
+        pbs_buildcfg::PROXMOX_BACKUP_RUN_DIR,
+        std::process::id()
+    )
+}
+
+// reopen the log files of the restore listener, if it runs
+async fn reopen_restore_listener_logfiles(command: &str) -> Result<(), Error> {
+    if RESTORE_LISTENER_STARTED.load(Ordering::SeqCst) {
+        proxmox_rest_server::send_raw_command(restore_ctrl_sock(), command)
+            .await
+            .map_err(|err| format_err!("reopen command failed, restore listener: {err}"))?;
+    }
+    Ok(())
+}
+
 fn make_tls_acceptor() -> Result<SslAcceptor, Error> {
-    let key_path = configdir!("/proxy.key");
-    let cert_path = configdir!("/proxy.pem");
+    build_tls_acceptor(configdir!("/proxy.key"), configdir!("/proxy.pem"))
+}
+
+/// The restore listener uses its own certificate if there is one, and the proxy's otherwise.
+fn make_restore_tls_acceptor() -> Result<SslAcceptor, Error> {
+    let key_path = configdir!("/proxy-restore.key");
+    let cert_path = configdir!("/proxy-restore.pem");
 
+    if Path::new(key_path).exists() && Path::new(cert_path).exists() {
+        build_tls_acceptor(key_path, cert_path)
+    } else {
+        make_tls_acceptor()
+    }
+}
+
+fn build_tls_acceptor(key_path: &str, cert_path: &str) -> Result<SslAcceptor, Error> {
     let (config, _) = proxmox_backup::config::node::config()?;
     let ciphers_tls_1_3 = config.ciphers_tls_1_3;
     let ciphers_tls_1_2 = config.ciphers_tls_1_2;
@@ -405,6 +544,27 @@ fn make_tls_acceptor() -> Result<SslAcceptor, Error> {
     acceptor.build()
 }
 
+fn bind_reuse_port(port: u16) -> Result<tokio::net::TcpListener, Error> {
+    use nix::sys::socket::{self, sockopt, AddressFamily, SockFlag, SockType, SockaddrIn6};
+    use std::os::unix::io::FromRawFd;
+
+    let fd = socket::socket(
+        AddressFamily::Inet6,
+        SockType::Stream,
+        SockFlag::SOCK_CLOEXEC | SockFlag::SOCK_NONBLOCK,
+        None,
+    )?;
+    let listener = unsafe { std::net::TcpListener::from_raw_fd(fd) };
+
+    socket::setsockopt(fd, sockopt::ReuseAddr, &true)?;
+    socket::setsockopt(fd, sockopt::ReusePort, &true)?;
+    let addr = std::net::SocketAddrV6::new(std::net::Ipv6Addr::UNSPECIFIED, port, 0, 0);
+    socket::bind(fd, &SockaddrIn6::from(addr))?;
+    socket::listen(fd, 4096)?;
+
+    Ok(tokio::net::TcpListener::from_std(listener)?)
+}
+
 fn start_stat_generator() {
     let abort_future = proxmox_rest_server::shutdown_future();
     let future = Box::pin(run_stat_generator());
@@ -412,6 +572,13 @@ fn start_stat_generator() {
     tokio::spawn(task.map(|_| ()));
 }
 
+fn start_auth_last_used_worker() {
+    let abort_future = proxmox_rest_server::shutdown_future();
+    let future = Box::pin(proxmox_backup::server::auth_last_used::flush_worker());
+    let task = futures::future::select(future, abort_future);
+    tokio::spawn(task.map(|_| ()));
+}
+
 fn start_task_scheduler() {
     let abort_future = proxmox_rest_server::shutdown_future();
     let future = Box::pin(run_task_scheduler());
@@ -459,17 +626,113 @@ async fn run_task_scheduler() {
 }
 
 async fn schedule_tasks() -> Result<(), Error> {
-    schedule_datastore_garbage_collection().await;
-    schedule_datastore_prune_jobs().await;
-    schedule_datastore_sync_jobs().await;
-    schedule_datastore_verify_jobs().await;
-    schedule_tape_backup_jobs().await;
+    schedule_datastore_space_watermarks().await;
+
+    let jobs = server::schedule::scheduled_jobs(|err| eprintln!("{err}"));
+    for job in jobs {
+        match job.config {
+            ScheduledJobConfig::GarbageCollection(store_config) => {
+                schedule_datastore_garbage_collection(store_config, job.schedule)
+            }
+            ScheduledJobConfig::Archive(_) => schedule_datastore_archive_job(job.id, job.schedule),
+            ScheduledJobConfig::Prune(job_config) => schedule_datastore_prune_job(job_config),
+            ScheduledJobConfig::Sync(job_config) => {
+                schedule_datastore_sync_job(job_config, job.schedule)
+            }
+            ScheduledJobConfig::Verification(job_config) => {
+                schedule_datastore_verify_job(job_config, job.schedule)
+            }
+            ScheduledJobConfig::TapeBackup(job_config) => {
+                schedule_tape_backup_job(job_config, job.schedule)
+            }
+        }
+    }
+
     schedule_task_log_rotate().await;
 
     Ok(())
 }
 
-async fn schedule_datastore_garbage_collection() {
+fn schedule_datastore_garbage_collection(store_config: DataStoreConfig, event_str: String) {
+    let store = store_config.name.clone();
+
+    let event: CalendarEvent = match event_str.parse() {
+        Ok(event) => event,
+        Err(err) => {
+            eprintln!("unable to parse schedule '{event_str}' - {err}");
+            return;
+        }
+    };
+
+    {
+        // limit datastore scope due to Op::Lookup
+        let datastore = match DataStore::lookup_datastore(&store, Some(Operation::Lookup)) {
+            Ok(datastore) => datastore,
+            Err(err) => {
+                eprintln!("lookup_datastore failed - {err}");
+                return;
+            }
+        };
+
+        if datastore.garbage_collection_running() {
+            return;
+        }
+    }
+
+    let worker_type = "garbage_collection";
+
+    let last = match jobstate::last_run_time(worker_type, &store) {
+        Ok(time) => time,
+        Err(err) => {
+            eprintln!("could not get last run time of {worker_type} {store}: {err}");
+            return;
+        }
+    };
+
+    let next = match event.compute_next_event(last) {
+        Ok(Some(next)) => next,
+        Ok(None) => return,
+        Err(err) => {
+            eprintln!("compute_next_event for '{event_str}' failed - {err}");
+            return;
+        }
+    };
+
+    let now = proxmox_time::epoch_i64();
+
+    if next > now {
+        return;
+    }
+
+    match window_closed_for(&store_config, DatastoreAccess::Maintenance, now) {
+        Ok(None) => {}
+        Ok(Some(_)) => return, // wait for the maintenance window to open
+        Err(err) => eprintln!("could not check maintenance window of {store}: {err}"),
+    }
+
+    let job = match Job::new(worker_type, &store) {
+        Ok(job) => job,
+        Err(_) => return, // could not get lock
+    };
+
+    let datastore = match DataStore::lookup_datastore(&store, Some(Operation::Write)) {
+        Ok(datastore) => datastore,
+        Err(err) => {
+            log::warn!("skipping scheduled GC on {store}, could look it up - {err}");
+            return;
+        }
+    };
+
+    let auth_id = Authid::root_auth_id();
+
+    if let Err(err) =
+        crate::server::do_garbage_collection_job(job, datastore, auth_id, Some(event_str), false)
+    {
+        eprintln!("unable to start garbage collection job on datastore {store} - {err}");
+    }
+}
+
+async fn schedule_datastore_space_watermarks() {
     let config = match pbs_config::datastore::config() {
         Err(err) => {
             eprintln!("unable to read datastore config - {err}");
@@ -487,60 +750,44 @@ async fn schedule_datastore_garbage_collection() {
             }
         };
 
-        let event_str = match store_config.gc_schedule {
-            Some(event_str) => event_str,
-            None => continue,
-        };
-
-        let event: CalendarEvent = match event_str.parse() {
-            Ok(event) => event,
-            Err(err) => {
-                eprintln!("unable to parse schedule '{event_str}' - {err}");
-                continue;
-            }
-        };
-
+        if store_config.low_space_watermark.is_none()
+            && store_config.critical_space_watermark.is_none()
         {
+            continue;
+        }
+
+        let usage = {
             // limit datastore scope due to Op::Lookup
             let datastore = match DataStore::lookup_datastore(&store, Some(Operation::Lookup)) {
                 Ok(datastore) => datastore,
+                Err(_) => continue, // e.g. in maintenance mode
+            };
+            match space_watermark::datastore_usage(&datastore).await {
+                Ok(usage) => usage,
                 Err(err) => {
-                    eprintln!("lookup_datastore failed - {err}");
+                    eprintln!("could not get usage of datastore {store} - {err}");
                     continue;
                 }
-            };
-
-            if datastore.garbage_collection_running() {
-                continue;
-            }
-        }
-
-        let worker_type = "garbage_collection";
-
-        let last = match jobstate::last_run_time(worker_type, &store) {
-            Ok(time) => time,
-            Err(err) => {
-                eprintln!("could not get last run time of {worker_type} {store}: {err}");
-                continue;
             }
         };
 
-        let next = match event.compute_next_event(last) {
-            Ok(Some(next)) => next,
-            Ok(None) => continue,
+        let level = space_watermark::space_level(&store_config, usage);
+        let now = proxmox_time::epoch_i64();
+        match space_watermark::watermark_run_due(&store, level, now) {
+            Ok(true) => {}
+            Ok(false) => continue,
             Err(err) => {
-                eprintln!("compute_next_event for '{event_str}' failed - {err}");
+                eprintln!("could not check space watermark state of {store} - {err}");
                 continue;
             }
-        };
-
-        let now = proxmox_time::epoch_i64();
+        }
 
-        if next > now {
+        // running full is worse than maintenance outside of its window
+        if level == SpaceLevel::Low && maintenance_window_closed(&store) {
             continue;
         }
 
-        let job = match Job::new(worker_type, &store) {
+        let job = match Job::new(space_watermark::SPACE_WATERMARK_WORKER_TYPE, &store) {
             Ok(job) => job,
             Err(_) => continue, // could not get lock
         };
@@ -548,179 +795,131 @@ async fn schedule_datastore_garbage_collection() {
         let datastore = match DataStore::lookup_datastore(&store, Some(Operation::Write)) {
             Ok(datastore) => datastore,
             Err(err) => {
-                log::warn!("skipping scheduled GC on {store}, could look it up - {err}");
+                log::warn!("skipping space watermark job on {store}, could look it up - {err}");
                 continue;
             }
         };
 
         let auth_id = Authid::root_auth_id();
 
-        if let Err(err) = crate::server::do_garbage_collection_job(
-            job,
-            datastore,
-            auth_id,
-            Some(event_str),
-            false,
-        ) {
-            eprintln!("unable to start garbage collection job on datastore {store} - {err}");
+        if let Err(err) =
+            space_watermark::do_space_watermark_job(job, datastore, usage, level, auth_id)
+        {
+            eprintln!("unable to start space watermark job on datastore {store} - {err}");
         }
     }
 }
 
-async fn schedule_datastore_prune_jobs() {
-    let config = match pbs_config::prune::config() {
-        Err(err) => {
-            eprintln!("unable to read prune job config - {err}");
-            return;
-        }
-        Ok((config, _digest)) => config,
-    };
-    for (job_id, (_, job_config)) in config.sections {
-        let job_config: PruneJobConfig = match serde_json::from_value(job_config) {
-            Ok(c) => c,
-            Err(err) => {
-                eprintln!("prune job config from_value failed - {err}");
-                continue;
-            }
-        };
-
-        if job_config.disable {
-            continue;
-        }
-
-        if !job_config.options.keeps_something() {
-            continue; // no 'keep' values set, keep all
-        }
-
-        let worker_type = "prunejob";
-        let auth_id = Authid::root_auth_id().clone();
-        if check_schedule(worker_type, &job_config.schedule, &job_id) {
-            let job = match Job::new(worker_type, &job_id) {
-                Ok(job) => job,
-                Err(_) => continue, // could not get lock
-            };
-            if let Err(err) = do_prune_job(
-                job,
-                job_config.options,
-                job_config.store,
-                &auth_id,
-                Some(job_config.schedule),
-            ) {
-                eprintln!("unable to start datastore prune job {job_id} - {err}");
-            }
-        };
+fn schedule_datastore_archive_job(store: String, event_str: String) {
+    let worker_type = "archive";
+    if !check_schedule(worker_type, &event_str, &store) || maintenance_window_closed(&store) {
+        return;
     }
-}
 
-async fn schedule_datastore_sync_jobs() {
-    let config = match pbs_config::sync::config() {
+    let job = match Job::new(worker_type, &store) {
+        Ok(job) => job,
+        Err(_) => return, // could not get lock
+    };
+
+    let datastore = match DataStore::lookup_datastore(&store, Some(Operation::Write)) {
+        Ok(datastore) => datastore,
         Err(err) => {
-            eprintln!("unable to read sync job config - {err}");
+            log::warn!("skipping scheduled archive job on {store}, could look it up - {err}");
             return;
         }
-        Ok((config, _digest)) => config,
     };
 
-    for (job_id, (_, job_config)) in config.sections {
-        let job_config: SyncJobConfig = match serde_json::from_value(job_config) {
-            Ok(c) => c,
-            Err(err) => {
-                eprintln!("sync job config from_value failed - {err}");
-                continue;
-            }
-        };
-
-        let event_str = match job_config.schedule {
-            Some(ref event_str) => event_str.clone(),
-            None => continue,
-        };
-
-        let worker_type = "syncjob";
-        if check_schedule(worker_type, &event_str, &job_id) {
-            let job = match Job::new(worker_type, &job_id) {
-                Ok(job) => job,
-                Err(_) => continue, // could not get lock
-            };
+    let auth_id = Authid::root_auth_id();
 
-            let auth_id = Authid::root_auth_id().clone();
-            if let Err(err) = do_sync_job(job, job_config, &auth_id, Some(event_str), false) {
-                eprintln!("unable to start datastore sync job {job_id} - {err}");
-            }
-        };
+    if let Err(err) = crate::server::do_archive_job(job, datastore, auth_id, Some(event_str), false)
+    {
+        eprintln!("unable to start archive job on datastore {store} - {err}");
     }
 }
 
-async fn schedule_datastore_verify_jobs() {
-    let config = match pbs_config::verify::config() {
-        Err(err) => {
-            eprintln!("unable to read verification job config - {err}");
-            return;
+fn schedule_datastore_prune_job(job_config: PruneJobConfig) {
+    let job_id = job_config.id.clone();
+    let worker_type = "prunejob";
+    let auth_id = Authid::root_auth_id().clone();
+    if check_schedule(worker_type, &job_config.schedule, &job_id)
+        && !skip_excluded_run(worker_type, &job_id, job_config.schedule_exclude.as_deref())
+        && !maintenance_window_closed(&job_config.store)
+    {
+        let job = match Job::new(worker_type, &job_id) {
+            Ok(job) => job,
+            Err(_) => return, // could not get lock
+        };
+        if let Err(err) = do_prune_job(
+            job,
+            job_config.options,
+            job_config.store,
+            job_config.retry,
+            &auth_id,
+            Some(job_config.schedule),
+        ) {
+            eprintln!("unable to start datastore prune job {job_id} - {err}");
         }
-        Ok((config, _digest)) => config,
     };
-    for (job_id, (_, job_config)) in config.sections {
-        let job_config: VerificationJobConfig = match serde_json::from_value(job_config) {
-            Ok(c) => c,
-            Err(err) => {
-                eprintln!("verification job config from_value failed - {err}");
-                continue;
-            }
-        };
-        let event_str = match job_config.schedule {
-            Some(ref event_str) => event_str.clone(),
-            None => continue,
+}
+
+fn schedule_datastore_sync_job(job_config: SyncJobConfig, event_str: String) {
+    let job_id = job_config.id.clone();
+    let worker_type = "syncjob";
+    if check_schedule(worker_type, &event_str, &job_id)
+        && !skip_excluded_run(worker_type, &job_id, job_config.schedule_exclude.as_deref())
+    {
+        let job = match Job::new(worker_type, &job_id) {
+            Ok(job) => job,
+            Err(_) => return, // could not get lock
         };
 
-        let worker_type = "verificationjob";
         let auth_id = Authid::root_auth_id().clone();
-        if check_schedule(worker_type, &event_str, &job_id) {
-            let job = match Job::new(worker_type, &job_id) {
-                Ok(job) => job,
-                Err(_) => continue, // could not get lock
-            };
-            if let Err(err) = do_verification_job(job, job_config, &auth_id, Some(event_str), false)
-            {
-                eprintln!("unable to start datastore verification job {job_id} - {err}");
-            }
-        };
-    }
+        if let Err(err) = do_sync_job(job, job_config, &auth_id, Some(event_str), false) {
+            eprintln!("unable to start datastore sync job {job_id} - {err}");
+        }
+    };
 }
 
-async fn schedule_tape_backup_jobs() {
-    let config = match pbs_config::tape_job::config() {
-        Err(err) => {
-            eprintln!("unable to read tape job config - {err}");
-            return;
+fn schedule_datastore_verify_job(job_config: VerificationJobConfig, event_str: String) {
+    let job_id = job_config.id.clone();
+    let worker_type = "verificationjob";
+    let auth_id = Authid::root_auth_id().clone();
+    if check_schedule(worker_type, &event_str, &job_id)
+        && !skip_excluded_run(worker_type, &job_id, job_config.schedule_exclude.as_deref())
+        && !maintenance_window_closed(&job_config.store)
+    {
+        let job = match Job::new(worker_type, &job_id) {
+            Ok(job) => job,
+            Err(_) => return, // could not get lock
+        };
+        if let Err(err) = do_verification_job(job, job_config, &auth_id, Some(event_str), false) {
+            eprintln!("unable to start datastore verification job {job_id} - {err}");
         }
-        Ok((config, _digest)) => config,
     };
-    for (job_id, (_, job_config)) in config.sections {
-        let job_config: TapeBackupJobConfig = match serde_json::from_value(job_config) {
-            Ok(c) => c,
-            Err(err) => {
-                eprintln!("tape backup job config from_value failed - {err}");
-                continue;
-            }
-        };
-        let event_str = match job_config.schedule {
-            Some(ref event_str) => event_str.clone(),
-            None => continue,
-        };
+}
 
-        let worker_type = "tape-backup-job";
-        let auth_id = Authid::root_auth_id().clone();
-        if check_schedule(worker_type, &event_str, &job_id) {
-            let job = match Job::new(worker_type, &job_id) {
-                Ok(job) => job,
-                Err(_) => continue, // could not get lock
-            };
-            if let Err(err) =
-                do_tape_backup_job(job, job_config.setup, &auth_id, Some(event_str), false)
-            {
-                eprintln!("unable to start tape backup job {job_id} - {err}");
-            }
+fn schedule_tape_backup_job(job_config: TapeBackupJobConfig, event_str: String) {
+    let job_id = job_config.id.clone();
+    let worker_type = "tape-backup-job";
+    let auth_id = Authid::root_auth_id().clone();
+    if check_schedule(worker_type, &event_str, &job_id)
+        && !skip_excluded_run(worker_type, &job_id, job_config.schedule_exclude.as_deref())
+    {
+        let job = match Job::new(worker_type, &job_id) {
+            Ok(job) => job,
+            Err(_) => return, // could not get lock
         };
-    }
+        if let Err(err) = do_tape_backup_job(
+            job,
+            job_config.setup,
+            job_config.retry,
+            &auth_id,
+            Some(event_str),
+            false,
+        ) {
+            eprintln!("unable to start tape backup job {job_id} - {err}");
+        }
+    };
 }
 
 async fn schedule_task_log_rotate() {
@@ -802,7 +1001,7 @@ async fn schedule_task_log_rotate() {
                     pbs_buildcfg::API_AUTH_LOG_FN,
                     true,
                     Some(max_files),
-                    Some(options),
+                    Some(options.clone()),
                 )?;
 
                 if logrotate.rotate(max_size)? {
@@ -813,6 +1012,20 @@ async fn schedule_task_log_rotate() {
                     task_log!(worker, "API authentication log was not rotated");
                 }
 
+                // opened for every entry, so no need to tell the daemons
+                let mut logrotate = LogRotate::new(
+                    pbs_buildcfg::RESTORE_ACCOUNTING_LOG_FN,
+                    true,
+                    Some(max_files),
+                    Some(options.clone()),
+                )?;
+
+                if logrotate.rotate(max_size)? {
+                    task_log!(worker, "restore accounting log was rotated");
+                } else {
+                    task_log!(worker, "restore accounting log was not rotated");
+                }
+
                 if has_rotated {
                     task_log!(worker, "cleaning up old task logs");
                     if let Err(err) = cleanup_old_tasks(&worker, true) {
@@ -820,6 +1033,12 @@ async fn schedule_task_log_rotate() {
                     }
                 }
 
+                task_log!(worker, "compressing logs of finished tasks");
+                let options = options.perm(nix::sys::stat::Mode::from_bits_truncate(0o0640));
+                if let Err(err) = server::task_log::compress_finished_task_logs(&worker, options) {
+                    task_warn!(worker, "could not compress task logs: {err}");
+                }
+
                 Ok(())
             });
 
@@ -837,6 +1056,8 @@ async fn schedule_task_log_rotate() {
 }
 
 async fn command_reopen_access_logfiles() -> Result<(), Error> {
+    reopen_restore_listener_logfiles("{\"command\":\"api-access-log-reopen\"}\n").await?;
+
     // only care about the most recent daemon instance for each, proxy & api, as other older ones
     // should not respond to new requests anyway, but only finish their current one and then exit.
     let sock = proxmox_rest_server::our_ctrl_sock();
@@ -859,6 +1080,8 @@ async fn command_reopen_access_logfiles() -> Result<(), Error> {
 }
 
 async fn command_reopen_auth_logfiles() -> Result<(), Error> {
+    reopen_restore_listener_logfiles("{\"command\":\"api-auth-log-reopen\"}\n").await?;
+
     // only care about the most recent daemon instance for each, proxy & api, as other older ones
     // should not respond to new requests anyway, but only finish their current one and then exit.
     let sock = proxmox_rest_server::our_ctrl_sock();
@@ -882,6 +1105,9 @@ async fn run_stat_generator() {
     loop {
         let delay_target = Instant::now() + Duration::from_secs(10);
 
+        // release the snapshot locks of expired sessions even if nobody lists them
+        server::warm_reader::remove_expired_sessions();
+
         let stats_future = tokio::task::spawn_blocking(|| {
             let hoststats = collect_host_stats_sync();
             let (hostdisk, datastores) = collect_disk_stats_sync();
@@ -900,6 +1126,7 @@ async fn run_stat_generator() {
             let stats = Arc::clone(&stats);
             move || {
                 rrd_update_host_stats_sync(&stats.0, &stats.1, &stats.2);
+                rrd_update_restore_accounting();
                 rrd_sync_journal();
             }
         });
@@ -984,6 +1211,27 @@ async fn send_data_to_metric_servers(
         ));
     }
 
+    for stats in api_stats(ApiStatsSortBy::Count) {
+        values.push(Arc::new(
+            MetricsData::new(
+                "api",
+                ctime,
+                json!({
+                    "count": stats.count,
+                    "errors": stats.errors,
+                    "average": stats.average,
+                    "p95": stats.p95,
+                    "max": stats.max,
+                    "bytes-in": stats.bytes_in,
+                    "bytes-out": stats.bytes_out,
+                }),
+            )?
+            .tag("object", "host")
+            .tag("host", nodename)
+            .tag("endpoint", stats.endpoint),
+        ));
+    }
+
     // we must have a concrete functions, because the inferred lifetime from a
     // closure is not general enough for the tokio::spawn call we are in here...
     fn map_fn(item: &(proxmox_metrics::Metrics, String)) -> &proxmox_metrics::Metrics {
@@ -1251,6 +1499,47 @@ fn check_schedule(worker_type: &str, event_str: &str, id: &str) -> bool {
     next <= now
 }
 
+/// Checks whether a due run falls on an excluded day.
+///
+/// If so, the run is recorded as skipped by updating the last run time, so that the job is
+/// considered again at its next scheduled time instead of immediately once the day has passed.
+fn skip_excluded_run(worker_type: &str, id: &str, exclude: Option<&[ScheduleExclusion]>) -> bool {
+    let exclude = match exclude {
+        Some(exclude) if !exclude.is_empty() => exclude,
+        _ => return false,
+    };
+
+    let now = proxmox_time::epoch_i64();
+    match server::schedule::is_excluded(exclude, now) {
+        Ok(false) => false,
+        Ok(true) => {
+            if let Err(err) = jobstate::update_job_last_run_time(worker_type, id) {
+                eprintln!("could not update last run time of {worker_type} {id}: {err}");
+            }
+            true
+        }
+        Err(err) => {
+            log::error!(
+                "could not evaluate schedule exclusions of {worker_type} {id}, running it anyway: {err}"
+            );
+            false
+        }
+    }
+}
+
+/// Checks whether the maintenance window of a datastore is currently closed.
+///
+/// Due jobs are not started then, but picked up again once the window opens.
+fn maintenance_window_closed(store: &str) -> bool {
+    match datastore_window_closed_for(store, DatastoreAccess::Maintenance) {
+        Ok(closed) => closed.is_some(),
+        Err(err) => {
+            eprintln!("could not check maintenance window of {store}: {err}");
+            false
+        }
+    }
+}
+
 fn gather_disk_stats(disk_manager: Arc<DiskManage>, path: &Path, name: &str) -> DiskStat {
     let usage = match proxmox_sys::fs::fs_info(path) {
         Ok(status) => Some(status),
@@ -1305,9 +1594,13 @@ async fn run_traffic_control_updater() {
 
         {
             let mut cache = TRAFFIC_CONTROL_CACHE.lock().unwrap();
+            // apply changed rules without waiting for the next connection
+            cache.reload(proxmox_time::epoch_i64());
             cache.compute_current_rates();
         }
 
+        server::config_watch::refresh_datastore_cache();
+
         tokio::time::sleep_until(tokio::time::Instant::from_std(delay_target)).await;
     }
 }
@@ -59,6 +59,10 @@ async fn do_update(rpcenv: &mut dyn RpcEnvironment) -> Result<(), Error> {
         log::error!("error checking certificates: {err}");
     }
 
+    if let Err(err) = proxmox_backup::server::do_health_check(rpcenv).await {
+        log::error!("error running health check: {err}");
+    }
+
     // TODO: cleanup tasks like in PVE?
 
     Ok(())
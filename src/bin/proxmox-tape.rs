@@ -774,6 +774,63 @@ async fn status(mut param: Value) -> Result<(), Error> {
     Ok(())
 }
 
+#[api(
+    input: {
+        properties: {
+            drive: {
+                schema: DRIVE_NAME_SCHEMA,
+                optional: true,
+            },
+             "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+             },
+        },
+    },
+)]
+/// Show the progress of the task using the drive
+///
+/// This does not access the drive, so it also works while a backup or restore uses it.
+async fn progress(mut param: Value) -> Result<(), Error> {
+    let output_format = extract_output_format(&mut param);
+
+    let (config, _digest) = pbs_config::drive::config()?;
+
+    let drive = extract_drive_name(&mut param, &config)?;
+
+    let client = connect_to_localhost()?;
+
+    let path = format!("api2/json/tape/drive/{}/progress", drive);
+    let mut result = client.get(&path, None).await?;
+    let mut data = result["data"].take();
+
+    if data.is_null() && output_format == "text" {
+        println!("no task reported progress on drive '{drive}'");
+        return Ok(());
+    }
+
+    let info = &api2::tape::drive::API_METHOD_DRIVE_PROGRESS;
+
+    let render_ratio = |value: &Value, _record: &Value| match value.as_u64() {
+        Some(ratio) => Ok(format!("{}%", ratio)),
+        None => Ok(String::new()),
+    };
+
+    let options = default_table_format_options()
+        .column(ColumnConfig::new("upid"))
+        .column(ColumnConfig::new("media"))
+        .column(ColumnConfig::new("file-number"))
+        .column(ColumnConfig::new("block-number"))
+        .column(ColumnConfig::new("bytes-written").renderer(render_bytes_human_readable))
+        .column(ColumnConfig::new("bytes-read").renderer(render_bytes_human_readable))
+        .column(ColumnConfig::new("compression-ratio").renderer(render_ratio))
+        .column(ColumnConfig::new("updated").renderer(render_epoch));
+
+    format_and_print_result_full(&mut data, &info.returns, &output_format, &options);
+
+    Ok(())
+}
+
 #[api(
     input: {
         properties: {
@@ -998,6 +1055,7 @@ async fn catalog_media(mut param: Value) -> Result<(), Error> {
 
 fn main() {
     init_cli_logger("PBS_LOG", "info");
+    pbs_tools::format::setup_locale();
 
     let cmd_def = CliCommandMap::new()
         .insert(
@@ -1035,6 +1093,10 @@ fn main() {
             "status",
             CliCommand::new(&API_METHOD_STATUS).completion_cb("drive", complete_drive_name),
         )
+        .insert(
+            "progress",
+            CliCommand::new(&API_METHOD_PROGRESS).completion_cb("drive", complete_drive_name),
+        )
         .insert(
             "eod",
             CliCommand::new(&API_METHOD_MOVE_TO_EOM).completion_cb("drive", complete_drive_name),
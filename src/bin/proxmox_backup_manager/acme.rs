@@ -7,6 +7,8 @@ use proxmox_router::{cli::*, ApiHandler, RpcEnvironment};
 use proxmox_schema::api;
 use proxmox_sys::fs::file_get_contents;
 
+use pbs_tools::format::{tr, tr_format};
+
 use proxmox_backup::acme::AcmeClient;
 use proxmox_backup::api2;
 use proxmox_backup::api2::types::AcmeAccountName;
@@ -106,12 +108,12 @@ async fn register_account(
     let (directory_url, custom_directory) = match directory {
         Some(directory) => (directory, true),
         None => {
-            println!("Directory endpoints:");
+            println!("{}", tr("Directory endpoints:"));
             for (i, dir) in KNOWN_ACME_DIRECTORIES.iter().enumerate() {
                 println!("{}) {}", i, dir.url);
             }
 
-            println!("{}) Custom", KNOWN_ACME_DIRECTORIES.len());
+            println!("{}) {}", KNOWN_ACME_DIRECTORIES.len(), tr("Custom"));
             let mut attempt = 0;
             loop {
                 print!("Enter selection: ");
@@ -131,7 +133,7 @@ async fn register_account(
                         std::io::stdin().read_line(&mut input)?;
                         break (input.trim().to_owned(), true);
                     }
-                    _ => eprintln!("Invalid selection."),
+                    _ => eprintln!("{}", tr("Invalid selection.")),
                 }
 
                 attempt += 1;
@@ -149,14 +151,14 @@ async fn register_account(
     let mut client = AcmeClient::new(directory_url.clone());
     let directory = client.directory().await?;
     let tos_agreed = if let Some(tos_url) = directory.terms_of_service_url() {
-        println!("Terms of Service: {}", tos_url);
+        println!("{}", tr_format("Terms of Service: {}", &[&tos_url]));
         print!("Do you agree to the above terms? [y|N]: ");
         std::io::stdout().flush()?;
         let mut input = String::new();
         std::io::stdin().read_line(&mut input)?;
         input.trim().eq_ignore_ascii_case("y")
     } else {
-        println!("No Terms of Service found, proceeding.");
+        println!("{}", tr("No Terms of Service found, proceeding."));
         true
     };
 
@@ -168,11 +170,14 @@ async fn register_account(
         std::io::stdin().read_line(&mut input)?;
         eab_enabled = input.trim().eq_ignore_ascii_case("y");
     } else if eab_enabled {
-        println!("The CA requires external account binding.");
+        println!("{}", tr("The CA requires external account binding."));
     }
 
     let eab_creds = if eab_enabled {
-        println!("You should have received a key id and a key from your CA.");
+        println!(
+            "{}",
+            tr("You should have received a key id and a key from your CA.")
+        );
 
         print!("Enter EAB key id: ");
         std::io::stdout().flush()?;
@@ -201,7 +206,13 @@ async fn register_account(
     )
     .await?;
 
-    println!("Registration successful, account URL: {}", account.location);
+    println!(
+        "{}",
+        tr_format(
+            "Registration successful, account URL: {}",
+            &[&account.location]
+        )
+    );
 
     Ok(())
 }
@@ -417,7 +428,10 @@ pub fn plugin_cli() -> CommandLineInterface {
 async fn order_acme_cert(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<(), Error> {
     if !param["force"].as_bool().unwrap_or(false) && !api2::node::certificates::cert_expires_soon()?
     {
-        println!("Certificate does not expire within the next 30 days, not renewing.");
+        println!(
+            "{}",
+            tr("Certificate does not expire within the next 30 days, not renewing.")
+        );
         return Ok(());
     }
 
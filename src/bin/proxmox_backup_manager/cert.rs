@@ -3,6 +3,7 @@ use anyhow::{bail, Error};
 use proxmox_router::cli::*;
 use proxmox_schema::api;
 
+use pbs_tools::format::{tr, tr_format};
 use proxmox_backup::auth_helpers::*;
 use proxmox_backup::config;
 
@@ -11,7 +12,7 @@ use proxmox_backup::config;
 fn cert_info() -> Result<(), Error> {
     let cert = proxmox_backup::cert_info()?;
 
-    println!("Subject: {}", cert.subject_name()?);
+    println!("{}", tr_format("Subject: {}", &[&cert.subject_name()?]));
 
     if let Some(san) = cert.subject_alt_names() {
         for name in san.iter() {
@@ -27,19 +28,20 @@ fn cert_info() -> Result<(), Error> {
         }
     }
 
-    println!("Issuer: {}", cert.issuer_name()?);
-    println!("Validity:");
-    println!("    Not Before: {}", cert.not_before());
-    println!("    Not After : {}", cert.not_after());
+    println!("{}", tr_format("Issuer: {}", &[&cert.issuer_name()?]));
+    println!("{}", tr("Validity:"));
+    println!("    {}", tr_format("Not Before: {}", &[&cert.not_before()]));
+    println!("    {}", tr_format("Not After : {}", &[&cert.not_after()]));
 
-    println!("Fingerprint (sha256): {}", cert.fingerprint()?);
-
-    let pubkey = cert.public_key()?;
     println!(
-        "Public key type: {}",
-        openssl::nid::Nid::from_raw(pubkey.id().as_raw()).long_name()?
+        "{}",
+        tr_format("Fingerprint (sha256): {}", &[&cert.fingerprint()?])
     );
-    println!("Public key bits: {}", pubkey.bits());
+
+    let pubkey = cert.public_key()?;
+    let key_type = openssl::nid::Nid::from_raw(pubkey.id().as_raw()).long_name()?;
+    println!("{}", tr_format("Public key type: {}", &[&key_type]));
+    println!("{}", tr_format("Public key bits: {}", &[&pubkey.bits()]));
 
     Ok(())
 }
@@ -4,7 +4,9 @@ use serde_json::Value;
 use proxmox_router::{cli::*, ApiHandler, RpcEnvironment};
 use proxmox_schema::api;
 
-use pbs_api_types::{DataStoreConfig, DATASTORE_SCHEMA, PROXMOX_CONFIG_DIGEST_SCHEMA};
+use pbs_api_types::{
+    Authid, BackupNamespace, DataStoreConfig, DATASTORE_SCHEMA, PROXMOX_CONFIG_DIGEST_SCHEMA,
+};
 use pbs_client::view_task_result;
 
 use proxmox_backup::api2;
@@ -139,6 +141,122 @@ async fn delete_datastore(mut param: Value, rpcenv: &mut dyn RpcEnvironment) ->
     Ok(())
 }
 
+#[api(
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+            ns: {
+                type: BackupNamespace,
+                optional: true,
+            },
+            path: {
+                type: String,
+                description: "Directory to write the seed to, usually on a removable disk.",
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        },
+    },
+)]
+/// Export the snapshots of a datastore as seed for the initial sync of a remote site.
+async fn seed_export(store: String, mut param: Value) -> Result<Value, Error> {
+    let output_format = extract_output_format(&mut param);
+
+    let client = connect_to_localhost()?;
+
+    let path = format!("api2/json/admin/datastore/{store}/seed-export");
+    param.as_object_mut().unwrap().remove("store");
+
+    let result = client.post(&path, Some(param)).await?;
+
+    view_task_result(&client, result, &output_format).await?;
+
+    Ok(Value::Null)
+}
+
+#[api(
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+            ns: {
+                type: BackupNamespace,
+                optional: true,
+            },
+            path: {
+                type: String,
+                description: "Directory containing the seed.",
+            },
+            owner: {
+                type: Authid,
+                optional: true,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        },
+    },
+)]
+/// Import a seed into a datastore.
+async fn seed_import(store: String, mut param: Value) -> Result<Value, Error> {
+    let output_format = extract_output_format(&mut param);
+
+    let client = connect_to_localhost()?;
+
+    let path = format!("api2/json/admin/datastore/{store}/seed-import");
+    param.as_object_mut().unwrap().remove("store");
+
+    let result = client.post(&path, Some(param)).await?;
+
+    view_task_result(&client, result, &output_format).await?;
+
+    Ok(Value::Null)
+}
+
+#[api(
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        },
+    },
+)]
+/// List the seed exports and imports of a datastore.
+fn seed_history(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value, Error> {
+    let output_format = get_output_format(&param);
+
+    let info = &api2::admin::datastore::API_METHOD_GET_SEED_HISTORY;
+    let mut data = match info.handler {
+        ApiHandler::Sync(handler) => (handler)(param, info, rpcenv)?,
+        _ => unreachable!(),
+    };
+
+    let options = default_table_format_options()
+        .column(ColumnConfig::new("time").renderer(pbs_tools::format::render_epoch))
+        .column(ColumnConfig::new("action"))
+        .column(ColumnConfig::new("seed-id"))
+        .column(ColumnConfig::new("path"))
+        .column(ColumnConfig::new("auth-id"))
+        .column(ColumnConfig::new("snapshots"))
+        .column(ColumnConfig::new("skipped"))
+        .column(ColumnConfig::new("chunks"));
+
+    format_and_print_result_full(&mut data, &info.returns, &output_format, &options);
+
+    Ok(Value::Null)
+}
+
 pub fn datastore_commands() -> CommandLineInterface {
     let cmd_def = CliCommandMap::new()
         .insert("list", CliCommand::new(&API_METHOD_LIST_DATASTORES))
@@ -171,6 +289,27 @@ pub fn datastore_commands() -> CommandLineInterface {
             CliCommand::new(&API_METHOD_DELETE_DATASTORE)
                 .arg_param(&["name"])
                 .completion_cb("name", pbs_config::datastore::complete_datastore_name),
+        )
+        .insert(
+            "seed-export",
+            CliCommand::new(&API_METHOD_SEED_EXPORT)
+                .arg_param(&["store", "path"])
+                .completion_cb("store", pbs_config::datastore::complete_datastore_name)
+                .completion_cb("path", complete_file_name),
+        )
+        .insert(
+            "seed-import",
+            CliCommand::new(&API_METHOD_SEED_IMPORT)
+                .arg_param(&["store", "path"])
+                .completion_cb("store", pbs_config::datastore::complete_datastore_name)
+                .completion_cb("path", complete_file_name)
+                .completion_cb("owner", pbs_config::user::complete_authid),
+        )
+        .insert(
+            "seed-history",
+            CliCommand::new(&API_METHOD_SEED_HISTORY)
+                .arg_param(&["store"])
+                .completion_cb("store", pbs_config::datastore::complete_datastore_name),
         );
 
     cmd_def.into()
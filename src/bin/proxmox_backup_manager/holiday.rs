@@ -0,0 +1,98 @@
+use anyhow::Error;
+use serde_json::Value;
+
+use proxmox_router::{cli::*, ApiHandler, RpcEnvironment};
+use proxmox_schema::api;
+
+use pbs_api_types::HOLIDAY_CALENDAR_ID_SCHEMA;
+
+use proxmox_backup::api2;
+
+#[api(
+    input: {
+        properties: {
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    }
+)]
+/// List configured holiday calendars.
+fn list_holiday_calendars(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value, Error> {
+    let output_format = get_output_format(&param);
+
+    let info = &api2::config::holiday::API_METHOD_LIST_HOLIDAY_CALENDARS;
+    let mut data = match info.handler {
+        ApiHandler::Sync(handler) => (handler)(param, info, rpcenv)?,
+        _ => unreachable!(),
+    };
+
+    let options = default_table_format_options()
+        .column(ColumnConfig::new("name"))
+        .column(ColumnConfig::new("exclude"))
+        .column(ColumnConfig::new("comment"));
+
+    format_and_print_result_full(&mut data, &info.returns, &output_format, &options);
+
+    Ok(Value::Null)
+}
+
+#[api(
+    input: {
+        properties: {
+            name: {
+                schema: HOLIDAY_CALENDAR_ID_SCHEMA,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    }
+)]
+/// Show holiday calendar configuration
+fn show_holiday_calendar(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value, Error> {
+    let output_format = get_output_format(&param);
+
+    let info = &api2::config::holiday::API_METHOD_READ_HOLIDAY_CALENDAR;
+    let mut data = match info.handler {
+        ApiHandler::Sync(handler) => (handler)(param, info, rpcenv)?,
+        _ => unreachable!(),
+    };
+
+    let options = default_table_format_options();
+    format_and_print_result_full(&mut data, &info.returns, &output_format, &options);
+
+    Ok(Value::Null)
+}
+
+pub fn holiday_calendar_commands() -> CommandLineInterface {
+    let cmd_def = CliCommandMap::new()
+        .insert("list", CliCommand::new(&API_METHOD_LIST_HOLIDAY_CALENDARS))
+        .insert(
+            "show",
+            CliCommand::new(&API_METHOD_SHOW_HOLIDAY_CALENDAR)
+                .arg_param(&["name"])
+                .completion_cb("name", pbs_config::holiday::complete_holiday_calendar_name),
+        )
+        .insert(
+            "create",
+            CliCommand::new(&api2::config::holiday::API_METHOD_CREATE_HOLIDAY_CALENDAR)
+                .arg_param(&["name"]),
+        )
+        .insert(
+            "update",
+            CliCommand::new(&api2::config::holiday::API_METHOD_UPDATE_HOLIDAY_CALENDAR)
+                .arg_param(&["name"])
+                .completion_cb("name", pbs_config::holiday::complete_holiday_calendar_name),
+        )
+        .insert(
+            "remove",
+            CliCommand::new(&api2::config::holiday::API_METHOD_DELETE_HOLIDAY_CALENDAR)
+                .arg_param(&["name"])
+                .completion_cb("name", pbs_config::holiday::complete_holiday_calendar_name),
+        );
+
+    cmd_def.into()
+}
@@ -10,6 +10,8 @@ mod datastore;
 pub use datastore::*;
 mod dns;
 pub use dns::*;
+mod holiday;
+pub use holiday::*;
 mod ldap;
 pub use ldap::*;
 mod network;
@@ -246,7 +246,9 @@ pub(crate) fn update_to_prune_jobs_config() -> Result<(), Error> {
             disable: false,
             comment: None,
             schedule,
+            schedule_exclude: None,
             options,
+            retry: None,
         };
 
         let prune_config = serde_json::to_value(prune_config)?;
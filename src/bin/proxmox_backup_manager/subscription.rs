@@ -1,13 +1,94 @@
-use anyhow::{bail, Error};
+use anyhow::{bail, format_err, Error};
+use openssl::hash::MessageDigest;
+use openssl::sign::{Signer, Verifier};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 use proxmox_router::{cli::*, ApiHandler, RpcEnvironment};
 use proxmox_schema::api;
-use proxmox_subscription::{ProductType, SubscriptionInfo};
+use proxmox_subscription::{ProductType, SubscriptionInfo, SubscriptionStatus};
+use proxmox_sys::fs::{file_get_contents, file_read_optional_string, replace_file, CreateOptions};
 
-use proxmox_backup::api2::{self, node::subscription::subscription_file_opts};
+use proxmox_backup::api2::{
+    self,
+    node::subscription::{check_offline_expiry, subscription_file_opts},
+};
 
-use pbs_buildcfg::PROXMOX_BACKUP_SUBSCRIPTION_FN;
+use pbs_api_types::SUBSCRIPTION_KEY_SCHEMA;
+use pbs_buildcfg::{configdir, PROXMOX_BACKUP_SUBSCRIPTION_FN};
+use pbs_tools::format::{tr, tr_format};
+
+/// Pending offline activation request, only the newest one can be completed.
+const OFFLINE_REQUEST_FN: &str = configdir!("/subscription-offline-request.json");
+
+/// Maximum age of an offline activation request, in seconds.
+const OFFLINE_REQUEST_MAX_AGE: i64 = 30 * 24 * 3600;
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Request to activate a subscription key for a server without internet access, signed with the
+/// key of the server's certificate.
+struct OfflineActivationRequest {
+    product: String,
+    key: String,
+    serverid: String,
+    nodename: String,
+    created: i64,
+    /// Random value which has to be returned in the response.
+    challenge: String,
+    /// Certificate of the requesting node (PEM).
+    certificate: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signature: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Response to an offline activation request.
+struct OfflineActivationResponse {
+    challenge: String,
+    /// base64-encoded signed subscription info
+    subscription: String,
+    /// Signature over the challenge and the subscription info, made with the offline signing
+    /// key (base64)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signature: Option<String>,
+}
+
+// The subscription info has its own signature, but the challenge is not part of it. The whole
+// response is signed as well, so that a subscription info cannot be reused for another request.
+fn response_signed_data(response: &OfflineActivationResponse) -> Result<Vec<u8>, Error> {
+    let mut data = serde_json::to_value(response)?;
+    data.as_object_mut().unwrap().remove("signature"); // exclude
+    proxmox_serde::json::to_canonical_json(&data)
+}
+
+fn verify_signature(data: &[u8], signature: &[u8], key_pem: &[u8]) -> Result<bool, Error> {
+    let key = openssl::pkey::PKey::public_key_from_pem(key_pem)?;
+    let mut verifier = Verifier::new(MessageDigest::sha512(), &key)?;
+    Ok(verifier.verify_oneshot(signature, data)?)
+}
+
+fn check_response_signature(
+    response: &OfflineActivationResponse,
+    keys: &[&str],
+) -> Result<(), Error> {
+    let signature = match response.signature.as_deref() {
+        Some(signature) => base64::decode(signature)?,
+        None => bail!("response is not signed"),
+    };
+    let data = response_signed_data(response)?;
+    for path in keys {
+        let key = match file_get_contents(path) {
+            Ok(key) => key,
+            Err(_) => continue,
+        };
+        if verify_signature(&data, &signature, &key)? {
+            return Ok(());
+        }
+    }
+    bail!("response signature is invalid")
+}
 
 #[api(
     input: {
@@ -47,6 +128,16 @@ fn get(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value, Error> {
 )]
 /// (Internal use only!) Set a signed subscription info blob as offline key
 pub fn set_offline_subscription_key(data: String) -> Result<(), Error> {
+    let info = decode_offline_subscription(&data)?;
+    proxmox_subscription::files::write_subscription(
+        PROXMOX_BACKUP_SUBSCRIPTION_FN,
+        subscription_file_opts()?,
+        &info,
+    )?;
+    Ok(())
+}
+
+fn decode_offline_subscription(data: &str) -> Result<SubscriptionInfo, Error> {
     let mut info: SubscriptionInfo = serde_json::from_slice(&base64::decode(data)?)?;
     if !info.is_signed() {
         bail!("Offline subscription key must be signed!");
@@ -60,11 +151,136 @@ pub fn set_offline_subscription_key(data: String) -> Result<(), Error> {
     info.check_signature(&[proxmox_subscription::files::DEFAULT_SIGNING_KEY]);
     info.check_age(false);
     info.check_server_id();
+    Ok(info)
+}
+
+fn sign_request(request: &OfflineActivationRequest) -> Result<String, Error> {
+    let mut data = serde_json::to_value(request)?;
+    data.as_object_mut().unwrap().remove("signature"); // exclude
+    let data = proxmox_serde::json::to_canonical_json(&data)?;
+
+    let key = file_get_contents(configdir!("/proxy.key"))?;
+    let key = openssl::pkey::PKey::private_key_from_pem(&key)?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+    Ok(hex::encode(signer.sign_oneshot_to_vec(&data)?))
+}
+
+#[api(
+    input: {
+        properties: {
+            key: {
+                schema: SUBSCRIPTION_KEY_SCHEMA,
+            },
+            output: {
+                type: String,
+                description: "Write the request to this file instead of standard output.",
+                optional: true,
+            },
+        }
+    }
+)]
+/// Create a signed request to activate a subscription key on a server without internet access.
+///
+/// Submit the request through the shop from a system with internet access, and import the
+/// response file with 'offline-import'.
+fn offline_request(key: String, output: Option<String>) -> Result<(), Error> {
+    let mut challenge = [0u8; 16];
+    openssl::rand::rand_bytes(&mut challenge)?;
+
+    let mut request = OfflineActivationRequest {
+        product: ProductType::Pbs.to_string(),
+        key,
+        serverid: proxmox_subscription::get_hardware_address()?,
+        nodename: proxmox_sys::nodename().to_string(),
+        created: proxmox_time::epoch_i64(),
+        challenge: hex::encode(challenge),
+        certificate: proxmox_sys::fs::file_read_string(configdir!("/proxy.pem"))?,
+        signature: None,
+    };
+    request.signature = Some(sign_request(&request)?);
+
+    let data = serde_json::to_string_pretty(&request)?;
+    replace_file(
+        OFFLINE_REQUEST_FN,
+        data.as_bytes(),
+        CreateOptions::new()
+            .perm(nix::sys::stat::Mode::from_bits_truncate(0o0600))
+            .owner(nix::unistd::ROOT),
+        true,
+    )?;
+
+    match output {
+        Some(output) => {
+            replace_file(&output, data.as_bytes(), CreateOptions::new(), false)?;
+            eprintln!("wrote offline activation request to '{output}'");
+        }
+        None => println!("{data}"),
+    }
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            file: {
+                type: String,
+                description: "Response file to the last offline activation request.",
+            },
+        }
+    }
+)]
+/// Import the response to an offline activation request and activate the subscription.
+fn offline_import(file: String) -> Result<(), Error> {
+    let request: OfflineActivationRequest = match file_read_optional_string(OFFLINE_REQUEST_FN)? {
+        Some(data) => serde_json::from_str(&data)?,
+        None => bail!("no pending offline activation request, create one with 'offline-request'"),
+    };
+    if proxmox_time::epoch_i64() - request.created > OFFLINE_REQUEST_MAX_AGE {
+        bail!("offline activation request is too old, please create a new one");
+    }
+
+    let response: OfflineActivationResponse =
+        serde_json::from_str(&proxmox_sys::fs::file_read_string(&file)?)
+            .map_err(|err| format_err!("unable to parse response file '{file}' - {err}"))?;
+    if response.challenge != request.challenge {
+        bail!("response does not belong to the last offline activation request");
+    }
+    check_response_signature(
+        &response,
+        &[proxmox_subscription::files::DEFAULT_SIGNING_KEY],
+    )?;
+
+    let mut info = decode_offline_subscription(&response.subscription)?;
+    if info.key.as_deref() != Some(request.key.as_str()) {
+        bail!("response is for another subscription key");
+    }
+    check_offline_expiry(&mut info);
+    if info.status != SubscriptionStatus::Active {
+        bail!(
+            "subscription is not active ({}) - {}",
+            info.status,
+            info.message.as_deref().unwrap_or("no reason given")
+        );
+    }
+
     proxmox_subscription::files::write_subscription(
         PROXMOX_BACKUP_SUBSCRIPTION_FN,
         subscription_file_opts()?,
         &info,
     )?;
+    let _ = std::fs::remove_file(OFFLINE_REQUEST_FN);
+
+    match info.nextduedate.as_deref() {
+        Some(due_date) if !due_date.is_empty() => {
+            println!(
+                "{}",
+                tr_format("subscription activated, valid until {}", &[&due_date])
+            )
+        }
+        _ => println!("{}", tr("subscription activated")),
+    }
+
     Ok(())
 }
 
@@ -81,6 +297,16 @@ pub fn subscription_commands() -> CommandLineInterface {
             "set-offline-key",
             CliCommand::new(&API_METHOD_SET_OFFLINE_SUBSCRIPTION_KEY).arg_param(&["data"]),
         )
+        .insert(
+            "offline-request",
+            CliCommand::new(&API_METHOD_OFFLINE_REQUEST).arg_param(&["key"]),
+        )
+        .insert(
+            "offline-import",
+            CliCommand::new(&API_METHOD_OFFLINE_IMPORT)
+                .arg_param(&["file"])
+                .completion_cb("file", complete_file_name),
+        )
         .insert(
             "update",
             CliCommand::new(&api2::node::subscription::API_METHOD_CHECK_SUBSCRIPTION)
@@ -94,3 +320,40 @@ pub fn subscription_commands() -> CommandLineInterface {
 
     cmd_def.into()
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::nid::Nid;
+    use openssl::pkey::PKey;
+
+    #[test]
+    fn test_response_signature() -> Result<(), Error> {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+        let key = PKey::from_ec_key(EcKey::generate(&group)?)?;
+        let public_key = key.public_key_to_pem()?;
+
+        let mut response = OfflineActivationResponse {
+            challenge: "00112233445566778899aabbccddeeff".to_string(),
+            subscription: base64::encode(b"{}"),
+            signature: None,
+        };
+        let data = response_signed_data(&response)?;
+        let mut signer = Signer::new(MessageDigest::sha512(), &key)?;
+        let signature = signer.sign_oneshot_to_vec(&data)?;
+
+        // the signature is not part of the signed data
+        response.signature = Some(base64::encode(&signature));
+        assert_eq!(response_signed_data(&response)?, data);
+        assert!(verify_signature(&data, &signature, &public_key)?);
+
+        // a subscription info moved to the response of another request
+        response.challenge = "ffeeddccbbaa99887766554433221100".to_string();
+        let data = response_signed_data(&response)?;
+        assert!(!verify_signature(&data, &signature, &public_key)?);
+
+        Ok(())
+    }
+}
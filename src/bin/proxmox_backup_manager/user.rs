@@ -7,6 +7,7 @@ use proxmox_router::{cli::*, ApiHandler, RpcEnvironment};
 use proxmox_schema::api;
 
 use pbs_api_types::{Authid, Userid, ACL_PATH_SCHEMA};
+use pbs_tools::format::{tr, tr_format};
 
 use proxmox_backup::api2;
 
@@ -29,6 +30,15 @@ fn render_expire(value: &Value, _record: &Value) -> Result<String, Error> {
     Ok(text)
 }
 
+fn render_last_used(value: &Value, _record: &Value) -> Result<String, Error> {
+    match value.as_i64() {
+        Some(epoch) => {
+            Ok(proxmox_time::strftime_local("%c", epoch).unwrap_or_else(|_| epoch.to_string()))
+        }
+        None => Ok(String::from("never")),
+    }
+}
+
 #[api(
     input: {
         properties: {
@@ -58,6 +68,7 @@ fn list_users(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value, Er
         .column(ColumnConfig::new("firstname"))
         .column(ColumnConfig::new("lastname"))
         .column(ColumnConfig::new("email"))
+        .column(ColumnConfig::new("last-used").renderer(render_last_used))
         .column(ColumnConfig::new("comment"));
 
     format_and_print_result_full(&mut data, &info.returns, &output_format, &options);
@@ -94,6 +105,49 @@ fn list_tokens(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value, E
             ColumnConfig::new("enable").renderer(pbs_tools::format::render_bool_with_default_true),
         )
         .column(ColumnConfig::new("expire").renderer(render_expire))
+        .column(ColumnConfig::new("last-used").renderer(render_last_used))
+        .column(ColumnConfig::new("last-used-ip"))
+        .column(ColumnConfig::new("comment"));
+
+    format_and_print_result_full(&mut data, &info.returns, &output_format, &options);
+
+    Ok(Value::Null)
+}
+
+#[api(
+    input: {
+        properties: {
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+            days: {
+                type: u64,
+                description: "Report tokens not used for this many days.",
+                optional: true,
+                default: 90,
+                minimum: 1,
+            },
+        }
+    }
+)]
+/// List API tokens of all users which were not used recently.
+fn list_unused_tokens(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value, Error> {
+    let output_format = get_output_format(&param);
+
+    let info = &api2::access::user::API_METHOD_LIST_UNUSED_TOKENS;
+    let mut data = match info.handler {
+        ApiHandler::Sync(handler) => (handler)(param, info, rpcenv)?,
+        _ => unreachable!(),
+    };
+
+    let options = default_table_format_options()
+        .column(ColumnConfig::new("tokenid"))
+        .column(
+            ColumnConfig::new("enable").renderer(pbs_tools::format::render_bool_with_default_true),
+        )
+        .column(ColumnConfig::new("last-used").renderer(render_last_used))
+        .column(ColumnConfig::new("last-used-ip"))
         .column(ColumnConfig::new("comment"));
 
     format_and_print_result_full(&mut data, &info.returns, &output_format, &options);
@@ -129,12 +183,15 @@ fn list_permissions(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Val
     };
 
     if output_format == "text" {
-        println!("Privileges with (*) have the propagate flag set\n");
+        println!(
+            "{}\n",
+            tr("Privileges with (*) have the propagate flag set")
+        );
         let data: HashMap<String, HashMap<String, bool>> = serde_json::from_value(data)?;
         let mut paths: Vec<String> = data.keys().cloned().collect();
         paths.sort_unstable();
         for path in paths {
-            println!("Path: {}", path);
+            println!("{}", tr_format("Path: {}", &[&path]));
             let priv_map = data.get(&path).unwrap();
             let mut privs: Vec<String> = priv_map.keys().cloned().collect();
             if privs.is_empty() {
@@ -217,12 +274,23 @@ pub fn user_commands() -> CommandLineInterface {
                 .arg_param(&["userid"])
                 .completion_cb("userid", pbs_config::user::complete_userid),
         )
+        .insert(
+            "unused-tokens",
+            CliCommand::new(&API_METHOD_LIST_UNUSED_TOKENS),
+        )
         .insert(
             "generate-token",
             CliCommand::new(&api2::access::user::API_METHOD_GENERATE_TOKEN)
                 .arg_param(&["userid", "token-name"])
                 .completion_cb("userid", pbs_config::user::complete_userid),
         )
+        .insert(
+            "update-token",
+            CliCommand::new(&api2::access::user::API_METHOD_UPDATE_TOKEN)
+                .arg_param(&["userid", "token-name"])
+                .completion_cb("userid", pbs_config::user::complete_userid)
+                .completion_cb("token-name", pbs_config::user::complete_token_name),
+        )
         .insert(
             "delete-token",
             CliCommand::new(&api2::access::user::API_METHOD_DELETE_TOKEN)
@@ -231,6 +299,12 @@ pub fn user_commands() -> CommandLineInterface {
                 .completion_cb("token-name", pbs_config::user::complete_token_name),
         )
         .insert("tfa", tfa_commands())
+        .insert(
+            "unlock-login",
+            CliCommand::new(&api2::access::user::API_METHOD_UNLOCK_LOGIN)
+                .arg_param(&["userid"])
+                .completion_cb("userid", pbs_config::user::complete_userid),
+        )
         .insert(
             "permissions",
             CliCommand::new(&API_METHOD_LIST_PERMISSIONS)
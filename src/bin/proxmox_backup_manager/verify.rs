@@ -4,9 +4,16 @@ use serde_json::Value;
 use proxmox_router::{cli::*, ApiHandler, RpcEnvironment};
 use proxmox_schema::api;
 
-use pbs_api_types::JOB_ID_SCHEMA;
+use pbs_api_types::{SnapshotVerifyResult, VerifyJobResult, JOB_ID_SCHEMA, UPID};
 
 use proxmox_backup::api2;
+use proxmox_backup::backup::verify_result::verify_job_result_to_junit;
+
+const SNAPSHOT_RESULTS_SCHEMA: &proxmox_schema::Schema = &proxmox_schema::ArraySchema::new(
+    "Per-snapshot verification results",
+    &SnapshotVerifyResult::API_SCHEMA,
+)
+.schema();
 
 #[api(
     input: {
@@ -70,6 +77,110 @@ fn show_verification_job(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Resul
     Ok(Value::Null)
 }
 
+#[api(
+    input: {
+        properties: {
+            id: {
+                schema: JOB_ID_SCHEMA,
+            },
+            upid: {
+                schema: UPID::API_SCHEMA,
+                optional: true,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    }
+)]
+/// Show the signed attestation of a verification job run
+fn show_verification_attestation(
+    param: Value,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Value, Error> {
+    let output_format = get_output_format(&param);
+
+    let info = &api2::admin::verify::API_METHOD_GET_VERIFICATION_ATTESTATION;
+    let mut data = match info.handler {
+        ApiHandler::Sync(handler) => (handler)(param, info, rpcenv)?,
+        _ => unreachable!(),
+    };
+
+    // the attestation is meant to be handed on, so print it in full by default
+    let output_format = if output_format == "text" {
+        "json-pretty".to_string()
+    } else {
+        output_format
+    };
+    format_and_print_result(&data.take(), &output_format);
+
+    Ok(Value::Null)
+}
+
+#[api(
+    input: {
+        properties: {
+            id: {
+                schema: JOB_ID_SCHEMA,
+            },
+            junit: {
+                type: bool,
+                description: "Print the result as JUnit XML.",
+                optional: true,
+                default: false,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        }
+    }
+)]
+/// Show the per-snapshot results of the last run of a verification job
+fn show_verification_result(
+    junit: bool,
+    param: Value,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Value, Error> {
+    let output_format = get_output_format(&param);
+
+    let info = &api2::admin::verify::API_METHOD_GET_VERIFICATION_RESULT;
+    let mut data = match info.handler {
+        ApiHandler::Sync(handler) => (handler)(param, info, rpcenv)?,
+        _ => unreachable!(),
+    };
+
+    if junit {
+        let result: VerifyJobResult = serde_json::from_value(data)?;
+        print!("{}", verify_job_result_to_junit(&result));
+        return Ok(Value::Null);
+    }
+
+    if output_format != "text" {
+        format_and_print_result(&data, &output_format);
+        return Ok(Value::Null);
+    }
+
+    let options = default_table_format_options()
+        .column(ColumnConfig::new("snapshot"))
+        .column(ColumnConfig::new("outcome"))
+        .column(ColumnConfig::new("reason"))
+        .column(ColumnConfig::new("errors"))
+        .column(ColumnConfig::new("duration"));
+    format_and_print_result_full(
+        &mut data["snapshots"],
+        &proxmox_schema::ReturnType {
+            optional: false,
+            schema: SNAPSHOT_RESULTS_SCHEMA,
+        },
+        &output_format,
+        &options,
+    );
+
+    Ok(Value::Null)
+}
+
 #[api(
     input: {
         properties: {
@@ -120,6 +231,18 @@ pub fn verify_job_commands() -> CommandLineInterface {
                 .arg_param(&["id"])
                 .completion_cb("id", pbs_config::verify::complete_verification_job_id),
         )
+        .insert(
+            "attestation",
+            CliCommand::new(&API_METHOD_SHOW_VERIFICATION_ATTESTATION)
+                .arg_param(&["id"])
+                .completion_cb("id", pbs_config::verify::complete_verification_job_id),
+        )
+        .insert(
+            "result",
+            CliCommand::new(&API_METHOD_SHOW_VERIFICATION_RESULT)
+                .arg_param(&["id"])
+                .completion_cb("id", pbs_config::verify::complete_verification_job_id),
+        )
         .insert(
             "remove",
             CliCommand::new(&api2::config::verify::API_METHOD_DELETE_VERIFICATION_JOB)
@@ -7,7 +7,7 @@ use proxmox_auth_api::ticket::Ticket;
 
 use crate::auth::private_auth_keyring;
 
-/// Connect to localhost:8007 as root@pam
+/// Connect to the proxy on localhost as root@pam
 ///
 /// This automatically creates a ticket if run as 'root' user.
 pub fn connect_to_localhost() -> Result<pbs_client::HttpClient, Error> {
@@ -20,5 +20,7 @@ pub fn connect_to_localhost() -> Result<pbs_client::HttpClient, Error> {
         HttpClientOptions::new_interactive(None, None)
     };
 
-    HttpClient::new("localhost", 8007, Authid::root_auth_id(), options)
+    let port = crate::config::node::proxy_port()?;
+
+    HttpClient::new("localhost", port, Authid::root_auth_id(), options)
 }
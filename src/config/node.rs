@@ -1,16 +1,16 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
-use anyhow::{bail, Error};
+use anyhow::{bail, format_err, Error};
 use openssl::ssl::{SslAcceptor, SslMethod};
 use serde::{Deserialize, Serialize};
 
-use proxmox_schema::{api, ApiStringFormat, ApiType, Updater};
+use proxmox_schema::{api, ApiStringFormat, ApiType, Schema, StringSchema, Updater};
 
 use proxmox_http::ProxyConfig;
 
 use pbs_api_types::{
-    EMAIL_SCHEMA, MULTI_LINE_COMMENT_SCHEMA, OPENSSL_CIPHERS_TLS_1_2_SCHEMA,
-    OPENSSL_CIPHERS_TLS_1_3_SCHEMA,
+    Authid, LoginNotify, EMAIL_SCHEMA, MULTI_LINE_COMMENT_SCHEMA, OPENSSL_CIPHERS_TLS_1_2_SCHEMA,
+    OPENSSL_CIPHERS_TLS_1_3_SCHEMA, SINGLE_LINE_COMMENT_SCHEMA,
 };
 
 use pbs_buildcfg::configdir;
@@ -24,11 +24,76 @@ use crate::api2::types::{
 const CONF_FILE: &str = configdir!("/node.cfg");
 const LOCK_FILE: &str = configdir!("/.node.lck");
 
+pub const LOCAL_API_USERS_SCHEMA: Schema = StringSchema::new(
+    "Comma separated list of '<uid>:<auth-id>' pairs, connections of local processes running \
+    with the uid to the local API socket are authenticated as the auth-id.",
+)
+.format(&ApiStringFormat::VerifyFn(verify_local_api_users))
+.schema();
+
+pub const STATUS_PAGE_PATH_SCHEMA: Schema = StringSchema::new(
+    "Path on which the proxy serves an unauthenticated status page for uptime monitors.",
+)
+.format(&ApiStringFormat::VerifyFn(verify_status_page_path))
+.min_length(2)
+.max_length(128)
+.schema();
+
+fn verify_status_page_path(value: &str) -> Result<(), Error> {
+    if !value.starts_with('/') {
+        bail!("status page path must start with '/'");
+    }
+    if !value
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '-' | '_' | '.'))
+    {
+        bail!("status page path contains invalid characters");
+    }
+    if value == "/api2" || value.starts_with("/api2/") {
+        bail!("status page path must not be below '/api2'");
+    }
+    Ok(())
+}
+
+fn verify_local_api_users(value: &str) -> Result<(), Error> {
+    parse_local_api_users(value).map(|_| ())
+}
+
+fn parse_local_api_users(value: &str) -> Result<HashMap<u32, Authid>, Error> {
+    let mut users = HashMap::new();
+    for entry in value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        let (uid, auth_id) = entry
+            .split_once(':')
+            .ok_or_else(|| format_err!("expected '<uid>:<auth-id>', got '{entry}'"))?;
+        let uid: u32 = uid
+            .parse()
+            .map_err(|_| format_err!("invalid uid '{uid}' in '{entry}'"))?;
+        let auth_id: Authid = auth_id.parse()?;
+        if users.insert(uid, auth_id).is_some() {
+            bail!("duplicate uid {uid} in local API users");
+        }
+    }
+    Ok(users)
+}
+
 pub fn lock() -> Result<BackupLockGuard, Error> {
     open_backup_lockfile(LOCK_FILE, None, true)
 }
 
 /// Read the Node Config.
+/// Port the proxy listens on if none is configured.
+pub const DEFAULT_PROXY_PORT: u16 = 8007;
+
+/// The port the proxy listens on.
+pub fn proxy_port() -> Result<u16, Error> {
+    let (config, _digest) = config()?;
+    Ok(config.port.unwrap_or(DEFAULT_PROXY_PORT))
+}
+
 pub fn config() -> Result<(NodeConfig, [u8; 32]), Error> {
     let content = proxmox_sys::fs::file_read_optional_string(CONF_FILE)?.unwrap_or_default();
 
@@ -159,6 +224,23 @@ pub enum Translation {
             schema: EMAIL_SCHEMA,
             optional: true,
         },
+        "email-reply-to": {
+            schema: EMAIL_SCHEMA,
+            optional: true,
+        },
+        "email-footer": {
+            schema: MULTI_LINE_COMMENT_SCHEMA,
+            optional: true,
+        },
+        "email-subject-tag": {
+            schema: SINGLE_LINE_COMMENT_SCHEMA,
+            optional: true,
+        },
+        "email-subject-nodename": {
+            type: Boolean,
+            optional: true,
+            default: false,
+        },
         "ciphers-tls-1.3": {
             schema: OPENSSL_CIPHERS_TLS_1_3_SCHEMA,
             optional: true,
@@ -174,10 +256,74 @@ pub enum Translation {
         "description" : {
             optional: true,
             schema: MULTI_LINE_COMMENT_SCHEMA,
-        }
+        },
+        port: {
+            type: Integer,
+            minimum: 1,
+            maximum: 65535,
+            default: 8007,
+            optional: true,
+        },
+        "restore-port": {
+            type: Integer,
+            minimum: 1,
+            maximum: 65535,
+            optional: true,
+        },
+        "login-notify-pam": {
+            type: LoginNotify,
+            optional: true,
+        },
+        "login-notify-pbs": {
+            type: LoginNotify,
+            optional: true,
+        },
+        "local-api-users": {
+            schema: LOCAL_API_USERS_SCHEMA,
+            optional: true,
+        },
+        "max-warm-reader-sessions": {
+            type: Integer,
+            minimum: 0,
+            maximum: 1024,
+            default: 16,
+            optional: true,
+        },
+        "status-page-path": {
+            schema: STATUS_PAGE_PATH_SCHEMA,
+            optional: true,
+        },
+        "status-page-rate-limit": {
+            type: Integer,
+            minimum: 1,
+            maximum: 3600,
+            default: 60,
+            optional: true,
+        },
+        "login-max-failures": {
+            type: Integer,
+            minimum: 1,
+            maximum: 1000,
+            default: 5,
+            optional: true,
+        },
+        "login-failure-window": {
+            type: Integer,
+            minimum: 1,
+            maximum: 86400,
+            default: 300,
+            optional: true,
+        },
+        "login-lockout-time": {
+            type: Integer,
+            minimum: 0,
+            maximum: 86400,
+            default: 60,
+            optional: true,
+        },
     },
 )]
-#[derive(Deserialize, Serialize, Updater)]
+#[derive(Default, Deserialize, Serialize, Updater)]
 #[serde(rename_all = "kebab-case")]
 /// Node specific configuration.
 pub struct NodeConfig {
@@ -206,6 +352,22 @@ pub struct NodeConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub email_from: Option<String>,
 
+    /// Reply-To address of notification mails sent directly to users and datastore owners.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email_reply_to: Option<String>,
+
+    /// Text appended to the body of all notifications sent by this node.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email_footer: Option<String>,
+
+    /// Site identifier prepended to the subject of all notifications.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email_subject_tag: Option<String>,
+
+    /// Prepend the node name to the subject of all notifications.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email_subject_nodename: Option<bool>,
+
     /// List of TLS ciphers for TLS 1.3 that will be used by the proxy. (Proxy has to be restarted for changes to take effect)
     #[serde(skip_serializing_if = "Option::is_none", rename = "ciphers-tls-1.3")]
     pub ciphers_tls_1_3: Option<String>,
@@ -225,6 +387,55 @@ pub struct NodeConfig {
     /// Maximum days to keep Task logs
     #[serde(skip_serializing_if = "Option::is_none")]
     pub task_log_max_days: Option<usize>,
+
+    /// Port the proxy listens on. (Proxy has to be restarted for changes to take effect)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+
+    /// Port of an additional listener of the proxy, which only serves the API needed for
+    /// restores. (Proxy has to be restarted for changes to take effect)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub restore_port: Option<u16>,
+
+    /// Notify about logins from previously unseen addresses in the built-in 'pam' realm
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub login_notify_pam: Option<LoginNotify>,
+
+    /// Notify about logins from previously unseen addresses in the built-in 'pbs' realm
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub login_notify_pbs: Option<LoginNotify>,
+
+    /// Auth-ids requests on the local API socket are authenticated as, by uid of the client
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub local_api_users: Option<String>,
+
+    /// Maximum number of concurrent warm standby reader sessions, 0 disables them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_warm_reader_sessions: Option<usize>,
+
+    /// Serve an unauthenticated status page on this path. (Proxy has to be restarted for changes
+    /// to take effect)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status_page_path: Option<String>,
+
+    /// Maximum number of status page requests per minute and client address, excess requests
+    /// are rejected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status_page_rate_limit: Option<u32>,
+
+    /// Number of failed logins of a user from one address after which further logins from
+    /// there are refused.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub login_max_failures: Option<u32>,
+
+    /// Time span in seconds in which failed logins are counted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub login_failure_window: Option<i64>,
+
+    /// Time in seconds for which logins are refused after too many failures, 0 disables the
+    /// lockout.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub login_lockout_time: Option<i64>,
 }
 
 impl NodeConfig {
@@ -259,6 +470,32 @@ impl NodeConfig {
         }
     }
 
+    /// Returns the tag to prepend to notification subjects, if any.
+    ///
+    /// Combines the configured site identifier and, if enabled, the node name.
+    pub fn email_subject_tag(&self) -> Option<String> {
+        let mut tags = Vec::new();
+        if let Some(tag) = &self.email_subject_tag {
+            tags.push(tag.clone());
+        }
+        if self.email_subject_nodename.unwrap_or(false) {
+            tags.push(proxmox_sys::nodename().to_string());
+        }
+        if tags.is_empty() {
+            None
+        } else {
+            Some(tags.join(" "))
+        }
+    }
+
+    /// Returns the auth-ids connections to the local API socket are authenticated as, by uid.
+    pub fn local_api_users(&self) -> Result<HashMap<u32, Authid>, Error> {
+        match self.local_api_users.as_deref() {
+            Some(users) => parse_local_api_users(users),
+            None => Ok(HashMap::new()),
+        }
+    }
+
     /// Sets the HTTP proxy configuration
     pub fn set_http_proxy(&mut self, http_proxy: Option<String>) {
         self.http_proxy = http_proxy;
@@ -324,3 +561,23 @@ impl<'a> Iterator for AcmeDomainIter<'a> {
         ))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_local_api_users() -> Result<(), Error> {
+        let users = parse_local_api_users("0:root@pam, 1000:scripts@pbs!local")?;
+        assert_eq!(users.len(), 2);
+        assert_eq!(users[&0], "root@pam".parse::<Authid>()?);
+        assert_eq!(users[&1000], "scripts@pbs!local".parse::<Authid>()?);
+
+        assert!(parse_local_api_users("")?.is_empty());
+        assert!(parse_local_api_users("0:root@pam,0:admin@pbs").is_err());
+        assert!(parse_local_api_users("root:root@pam").is_err());
+        assert!(parse_local_api_users("0").is_err());
+        assert!(parse_local_api_users("0:root").is_err());
+        Ok(())
+    }
+}
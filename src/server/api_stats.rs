@@ -0,0 +1,292 @@
+//! Latency and throughput statistics of the API endpoints
+//!
+//! The proxy wraps its REST server in [`ApiStatsMakeService`], which times every API call from
+//! receiving the request until the response starts and accounts it to the endpoint, identified
+//! by the HTTP method and the path template of its router (`GET /admin/datastore/{store}/status`).
+//! Body sizes are only known for bodies with a `Content-Length`, so streamed uploads and
+//! downloads are only counted as calls.
+//!
+//! Statistics are kept in memory since the start of the proxy and sent to the metric servers.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use hyper::service::Service;
+use hyper::{header, Body, Method, Request, Response};
+use lazy_static::lazy_static;
+
+use proxmox_router::{Router, SubRoute};
+
+use pbs_api_types::{ApiEndpointStats, ApiStatsSortBy, API_LATENCY_BUCKETS_MS};
+
+struct EndpointStats {
+    count: u64,
+    errors: u64,
+    total: Duration,
+    max: Duration,
+    bytes_in: u64,
+    bytes_out: u64,
+    histogram: Vec<u64>,
+}
+
+impl EndpointStats {
+    fn new() -> Self {
+        Self {
+            count: 0,
+            errors: 0,
+            total: Duration::ZERO,
+            max: Duration::ZERO,
+            bytes_in: 0,
+            bytes_out: 0,
+            histogram: vec![0; API_LATENCY_BUCKETS_MS.len() + 1],
+        }
+    }
+
+    fn to_api_stats(&self, endpoint: &str) -> ApiEndpointStats {
+        // the first bucket reaching 95% of the calls
+        let threshold = (self.count * 95 + 99) / 100;
+        let mut calls = 0;
+        let mut p95 = self.max.as_millis() as u64;
+        for (pos, count) in self.histogram.iter().enumerate() {
+            calls += count;
+            if calls >= threshold {
+                if let Some(bound) = API_LATENCY_BUCKETS_MS.get(pos) {
+                    p95 = p95.min(*bound);
+                }
+                break;
+            }
+        }
+
+        ApiEndpointStats {
+            endpoint: endpoint.to_string(),
+            count: self.count,
+            errors: self.errors,
+            average: self.total.as_secs_f64() * 1000.0 / self.count.max(1) as f64,
+            p95,
+            max: self.max.as_millis() as u64,
+            bytes_in: self.bytes_in,
+            bytes_out: self.bytes_out,
+            histogram: self.histogram.clone(),
+        }
+    }
+}
+
+lazy_static! {
+    static ref API_STATS: Mutex<HashMap<String, EndpointStats>> = Mutex::new(HashMap::new());
+}
+
+/// Returns the path template of an API call, `None` for requests not matching an API endpoint.
+pub fn endpoint_template(router: &'static Router, method: &Method, path: &str) -> Option<String> {
+    let mut components = path.split('/').filter(|component| !component.is_empty());
+    if components.next()? != "api2" {
+        return None;
+    }
+    components.next()?; // output format
+
+    let mut template = String::new();
+    let mut router = router;
+    for component in components {
+        router = match &router.subroute {
+            Some(SubRoute::Map(dirmap)) => {
+                let (name, router) = dirmap.iter().find(|(name, _)| *name == component)?;
+                template.push('/');
+                template.push_str(name);
+                router
+            }
+            Some(SubRoute::MatchAll { router, param_name }) => {
+                template.push_str("/{");
+                template.push_str(param_name);
+                template.push('}');
+                router
+            }
+            None => return None,
+        };
+    }
+    if template.is_empty() {
+        template.push('/');
+    }
+
+    Some(format!("{method} {template}"))
+}
+
+/// Account a finished API call to `endpoint`.
+pub fn record_call(
+    endpoint: String,
+    duration: Duration,
+    failed: bool,
+    bytes_in: u64,
+    bytes_out: u64,
+) {
+    let millis = duration.as_millis() as u64;
+    let bucket = API_LATENCY_BUCKETS_MS
+        .iter()
+        .position(|bound| millis <= *bound)
+        .unwrap_or(API_LATENCY_BUCKETS_MS.len());
+
+    let mut stats = API_STATS.lock().unwrap();
+    let entry = stats.entry(endpoint).or_insert_with(EndpointStats::new);
+    entry.count += 1;
+    if failed {
+        entry.errors += 1;
+    }
+    entry.total += duration;
+    entry.max = entry.max.max(duration);
+    entry.bytes_in += bytes_in;
+    entry.bytes_out += bytes_out;
+    entry.histogram[bucket] += 1;
+}
+
+/// Returns the statistics of all endpoints called since the start of the proxy, ordered by
+/// `sort_by`.
+pub fn api_stats(sort_by: ApiStatsSortBy) -> Vec<ApiEndpointStats> {
+    let mut list: Vec<ApiEndpointStats> = API_STATS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(endpoint, stats)| stats.to_api_stats(endpoint))
+        .collect();
+
+    match sort_by {
+        ApiStatsSortBy::Average => list.sort_by(|a, b| b.average.total_cmp(&a.average)),
+        ApiStatsSortBy::P95 => list.sort_by(|a, b| b.p95.cmp(&a.p95)),
+        ApiStatsSortBy::Max => list.sort_by(|a, b| b.max.cmp(&a.max)),
+        ApiStatsSortBy::Count => list.sort_by(|a, b| b.count.cmp(&a.count)),
+    }
+
+    list
+}
+
+fn content_length(headers: &hyper::HeaderMap) -> u64 {
+    headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Wraps the per-connection services of a REST server with [`ApiStatsService`].
+#[derive(Clone)]
+pub struct ApiStatsMakeService<S> {
+    inner: S,
+    router: &'static Router,
+}
+
+impl<S> ApiStatsMakeService<S> {
+    /// Collect statistics for the API calls to `inner`, which serves `router`.
+    pub fn new(inner: S, router: &'static Router) -> Self {
+        Self { inner, router }
+    }
+}
+
+impl<T, S> Service<T> for ApiStatsMakeService<S>
+where
+    S: Service<T>,
+    S::Future: Send + 'static,
+{
+    type Response = ApiStatsService<S::Response>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, conn: T) -> Self::Future {
+        let router = self.router;
+        let future = self.inner.call(conn);
+        Box::pin(async move {
+            let inner = future.await?;
+            Ok(ApiStatsService { inner, router })
+        })
+    }
+}
+
+/// Times the API calls of a single connection.
+pub struct ApiStatsService<S> {
+    inner: S,
+    router: &'static Router,
+}
+
+impl<S> Service<Request<Body>> for ApiStatsService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let start = Instant::now();
+        let endpoint = endpoint_template(self.router, req.method(), req.uri().path());
+        let bytes_in = content_length(req.headers());
+
+        let future = self.inner.call(req);
+        Box::pin(async move {
+            let result = future.await;
+            if let Some(endpoint) = endpoint {
+                let (failed, bytes_out) = match &result {
+                    Ok(response) => (
+                        response.status().is_server_error(),
+                        content_length(response.headers()),
+                    ),
+                    Err(_) => (true, 0),
+                };
+                record_call(endpoint, start.elapsed(), failed, bytes_in, bytes_out);
+            }
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use proxmox_router::SubdirMap;
+
+    const STATUS_ROUTER: Router = Router::new();
+    const STORE_SUBDIRS: SubdirMap = &[("status", &STATUS_ROUTER)];
+    const STORE_ROUTER: Router = Router::new().subdirs(STORE_SUBDIRS);
+    const DATASTORE_ROUTER: Router = Router::new().match_all("store", &STORE_ROUTER);
+    const ADMIN_SUBDIRS: SubdirMap = &[("datastore", &DATASTORE_ROUTER)];
+    const ADMIN_ROUTER: Router = Router::new().subdirs(ADMIN_SUBDIRS);
+    const SUBDIRS: SubdirMap = &[("admin", &ADMIN_ROUTER)];
+    const ROUTER: Router = Router::new().subdirs(SUBDIRS);
+
+    #[test]
+    fn test_endpoint_template() {
+        assert_eq!(
+            endpoint_template(
+                &ROUTER,
+                &Method::GET,
+                "/api2/json/admin/datastore/store1/status"
+            ),
+            Some("GET /admin/datastore/{store}/status".to_string()),
+        );
+        assert_eq!(
+            endpoint_template(&ROUTER, &Method::POST, "/api2/extjs/admin/datastore/"),
+            Some("POST /admin/datastore".to_string()),
+        );
+        assert_eq!(
+            endpoint_template(&ROUTER, &Method::GET, "/api2/json"),
+            Some("GET /".to_string()),
+        );
+        assert_eq!(
+            endpoint_template(&ROUTER, &Method::GET, "/api2/json/admin/unknown"),
+            None,
+        );
+        assert_eq!(
+            endpoint_template(&ROUTER, &Method::GET, "/js/proxmox.js"),
+            None
+        );
+    }
+}
@@ -0,0 +1,353 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{bail, format_err, Error};
+
+use proxmox_sys::fs::lock_dir_noblock;
+use proxmox_sys::{task_log, task_warn, WorkerTaskContext};
+
+use pbs_api_types::{
+    Authid, BackupNamespace, Operation, MAX_NAMESPACE_DEPTH, PRIV_DATASTORE_BACKUP,
+    PRIV_DATASTORE_READ,
+};
+use pbs_datastore::backup_info::{BackupDir, BackupInfo};
+use pbs_datastore::manifest::{
+    archive_type, ArchiveType, CLIENT_LOG_BLOB_NAME, MANIFEST_BLOB_NAME,
+};
+use pbs_datastore::{check_backup_owner, DataStore};
+use proxmox_rest_server::WorkerTask;
+
+use crate::backup::{check_ns_privs_full, ListAccessibleBackupGroups};
+use crate::server::jobstate::Job;
+
+/// Create `ns` with all its parents on `datastore`, if missing.
+fn copy_file(source: &Path, target: &Path) -> Result<(), Error> {
+    let mut tmp_path = target.to_owned();
+    tmp_path.set_extension("tmp");
+    std::fs::copy(source, &tmp_path)
+        .map_err(|err| format_err!("copying {source:?} to {tmp_path:?} failed - {err}"))?;
+    std::fs::rename(&tmp_path, target)
+        .map_err(|err| format_err!("renaming {tmp_path:?} to {target:?} failed - {err}"))?;
+    Ok(())
+}
+
+/// Copy a snapshot including all referenced chunks to the archive datastore.
+///
+/// The manifest is copied last, so an interrupted copy is not visible as a finished snapshot.
+fn copy_snapshot(snapshot: &BackupDir, archive: &Arc<DataStore>) -> Result<(), Error> {
+    let source = snapshot.datastore();
+    let ns = snapshot.backup_ns();
+    let (manifest, _) = snapshot.load_manifest()?;
+
+    archive.create_namespace_recursive(ns)?;
+
+    let owner = snapshot.get_owner()?;
+    let (_owner, _group_guard) =
+        archive.create_locked_backup_group(ns, snapshot.as_ref(), &owner)?;
+    let (_relative_path, is_new, _snapshot_guard) =
+        archive.create_locked_backup_dir(ns, snapshot.dir())?;
+    let target = archive.backup_dir(ns.clone(), snapshot.dir().clone())?;
+
+    if !is_new {
+        match target.load_manifest() {
+            Ok((existing, _)) if existing.content_digest() == manifest.content_digest() => {
+                return Ok(()); // already copied by an earlier, interrupted run
+            }
+            Ok(_) => bail!("a different snapshot with the same name exists on the archive"),
+            Err(_) => (), // incomplete copy, overwrite
+        }
+    }
+
+    for item in manifest.files() {
+        let mut source_path = snapshot.full_path();
+        source_path.push(&item.filename);
+        let mut target_path = target.full_path();
+        target_path.push(&item.filename);
+
+        match archive_type(&item.filename)? {
+            ArchiveType::FixedIndex | ArchiveType::DynamicIndex => {
+                let index = source.open_index(&source_path)?;
+                for pos in 0..index.index_count() {
+                    let digest = index.index_digest(pos).unwrap();
+                    if archive.cond_touch_chunk(digest, false)? {
+                        continue;
+                    }
+                    let chunk = source.load_chunk(digest)?;
+                    archive.insert_chunk(&chunk, digest)?;
+                }
+            }
+            ArchiveType::Blob => (),
+        }
+
+        copy_file(&source_path, &target_path)?;
+    }
+
+    let mut source_path = snapshot.full_path();
+    source_path.push(CLIENT_LOG_BLOB_NAME);
+    if source_path.exists() {
+        let mut target_path = target.full_path();
+        target_path.push(CLIENT_LOG_BLOB_NAME);
+        copy_file(&source_path, &target_path)?;
+    }
+
+    let mut source_path = snapshot.full_path();
+    source_path.push(MANIFEST_BLOB_NAME);
+    let mut target_path = target.full_path();
+    target_path.push(MANIFEST_BLOB_NAME);
+    copy_file(&source_path, &target_path)?;
+
+    Ok(())
+}
+
+/// Move a single snapshot to the archive datastore, leaving a stub behind.
+fn archive_snapshot(info: &BackupInfo, archive: &Arc<DataStore>) -> Result<(), Error> {
+    let snapshot = &info.backup_dir;
+
+    // exclusive lock, readers must not see the snapshot while its archives are removed
+    let _guard = lock_dir_noblock(
+        &snapshot.full_path(),
+        "snapshot",
+        "possibly running or in use",
+    )?;
+
+    copy_snapshot(snapshot, archive)?;
+    snapshot.convert_to_archive_stub(archive.name())
+}
+
+/// Look up the copy of an archived snapshot on its archive datastore.
+///
+/// Returns `None` if the snapshot is not a stub. Reading the copy requires the same privileges
+/// on the archive datastore as on the original one: Datastore.Read on the namespace, or
+/// Datastore.Backup and owning the group there.
+pub fn lookup_archived_snapshot(
+    snapshot: &BackupDir,
+    auth_id: &Authid,
+) -> Result<Option<(Arc<DataStore>, BackupDir)>, Error> {
+    let archive_store = match snapshot.load_manifest() {
+        Ok((manifest, _)) => match manifest.archived_to() {
+            Some(archive_store) => archive_store.to_string(),
+            None => return Ok(None),
+        },
+        Err(_) => return Ok(None),
+    };
+
+    let ns = snapshot.backup_ns();
+    let limited = check_ns_privs_full(
+        &archive_store,
+        ns,
+        auth_id,
+        PRIV_DATASTORE_READ,
+        PRIV_DATASTORE_BACKUP,
+    )?;
+
+    let archive = DataStore::lookup_datastore(&archive_store, Some(Operation::Read))?;
+    if limited {
+        let owner = archive.get_owner(ns, snapshot.as_ref())?;
+        check_backup_owner(&owner, auth_id)?;
+    }
+
+    let archived = archive.backup_dir(ns.clone(), snapshot.dir().clone())?;
+    if !archived.full_path().exists() {
+        bail!(
+            "archived snapshot {} is missing on '{archive_store}'",
+            snapshot.dir()
+        );
+    }
+
+    Ok(Some((archive, archived)))
+}
+
+/// Returns the snapshots of a group which are due to be archived.
+///
+/// The newest snapshot of a group is never archived, as it is the base for incremental backups.
+fn archive_candidates(mut list: Vec<BackupInfo>, cutoff: i64) -> Vec<BackupInfo> {
+    BackupInfo::sort_list(&mut list, false); // newest first
+    list.into_iter()
+        .skip(1)
+        .filter(|info| info.backup_dir.backup_time() < cutoff)
+        .filter(|info| info.is_finished() && !info.protected && info.retained_until.is_none())
+        .filter(|info| match info.backup_dir.load_manifest() {
+            Ok((manifest, _)) => manifest.archived_to().is_none(),
+            Err(_) => false,
+        })
+        .collect()
+}
+
+/// Runs an archive job, moving old snapshots of a datastore to its archive datastore.
+pub fn do_archive_job(
+    mut job: Job,
+    datastore: Arc<DataStore>,
+    auth_id: &Authid,
+    schedule: Option<String>,
+    to_stdout: bool,
+) -> Result<String, Error> {
+    let store = datastore.name().to_string();
+    let (config, _digest) = pbs_config::datastore::config()?;
+    let store_config: pbs_api_types::DataStoreConfig = config.lookup("datastore", &store)?;
+
+    let (archive_store, archive_after) =
+        match (store_config.archive_store, store_config.archive_after) {
+            (Some(archive_store), Some(archive_after)) => (archive_store, archive_after),
+            _ => bail!("datastore '{store}' has no archive datastore and age configured"),
+        };
+    if archive_store == store {
+        bail!("datastore '{store}' cannot be its own archive");
+    }
+    let archive = DataStore::lookup_datastore(&archive_store, Some(Operation::Write))?;
+
+    let worker_type = job.jobtype().to_string();
+    let upid_str = WorkerTask::new_thread(
+        &worker_type,
+        Some(store.clone()),
+        auth_id.to_string(),
+        to_stdout,
+        move |worker| {
+            job.start(&worker.upid().to_string())?;
+
+            task_log!(
+                worker,
+                "moving snapshots older than {archive_after} days from {store} to {archive_store}"
+            );
+            if let Some(event_str) = schedule {
+                task_log!(worker, "task triggered by schedule '{event_str}'");
+            }
+
+            let cutoff = proxmox_time::epoch_i64() - (archive_after as i64) * 86400;
+            let mut archived = 0;
+            let mut failed = 0;
+
+            let result = proxmox_lang::try_block!({
+                let groups = ListAccessibleBackupGroups::new_with_privs(
+                    &datastore,
+                    BackupNamespace::root(),
+                    MAX_NAMESPACE_DEPTH,
+                    None,
+                    None,
+                    None,
+                )?;
+
+                for group in groups {
+                    let group = group?;
+                    for info in archive_candidates(group.list_backups()?, cutoff) {
+                        worker.check_abort()?;
+                        let snapshot = &info.backup_dir;
+                        match archive_snapshot(&info, &archive) {
+                            Ok(()) => {
+                                task_log!(worker, "archived snapshot {snapshot:?}");
+                                archived += 1;
+                            }
+                            Err(err) => {
+                                task_warn!(
+                                    worker,
+                                    "failed to archive snapshot {snapshot:?} - {err}"
+                                );
+                                failed += 1;
+                            }
+                        }
+                    }
+                }
+
+                task_log!(worker, "archived {archived} snapshots");
+                if failed > 0 {
+                    bail!("failed to archive {failed} snapshots");
+                }
+
+                Ok(())
+            });
+
+            let status = worker.create_state(&result);
+
+            if let Err(err) = job.finish(status) {
+                eprintln!("could not finish job state for {}: {err}", job.jobtype());
+            }
+
+            result
+        },
+    )?;
+
+    Ok(upid_str)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use pbs_api_types::{CryptMode, DatastoreFSyncLevel};
+    use pbs_datastore::data_blob::DataBlob;
+    use pbs_datastore::manifest::BackupManifest;
+    use pbs_datastore::ChunkStore;
+
+    fn test_datastore(name: &str) -> Result<(Arc<DataStore>, std::path::PathBuf), Error> {
+        let path =
+            std::env::temp_dir().join(format!("pbs-archive-test-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        let user = nix::unistd::User::from_uid(nix::unistd::Uid::current())?.unwrap();
+        ChunkStore::create(
+            name,
+            &path,
+            user.uid,
+            user.gid,
+            None,
+            DatastoreFSyncLevel::None,
+        )?;
+        // only this test uses the datastore, so there are no other process lockers on it
+        let datastore = unsafe { DataStore::open_path(name, &path, None)? };
+        Ok((datastore, path))
+    }
+
+    fn write_blob(snapshot: &BackupDir, name: &str, data: &[u8]) -> Result<DataBlob, Error> {
+        let blob = DataBlob::encode(data, None, true)?;
+        std::fs::write(snapshot.full_path().join(name), blob.raw_data())?;
+        Ok(blob)
+    }
+
+    #[test]
+    fn test_copy_snapshot() -> Result<(), Error> {
+        let (source, source_path) = test_datastore("source")?;
+        let (archive, archive_path) = test_datastore("archive")?;
+
+        let ns = BackupNamespace::new("a/b")?;
+        assert_eq!(source.create_namespace_recursive(&ns)?.len(), 2);
+        assert!(source.create_namespace_recursive(&ns)?.is_empty());
+
+        let dir: pbs_api_types::BackupDir = "host/test/2024-01-01T00:00:00Z".parse()?;
+        let owner: Authid = "test@pbs".parse()?;
+        source.create_locked_backup_group(&ns, dir.as_ref(), &owner)?;
+        source.create_locked_backup_dir(&ns, &dir)?;
+        let snapshot = source.backup_dir(ns.clone(), dir.clone())?;
+
+        let blob = write_blob(&snapshot, "data.blob", b"archived data")?;
+        let mut manifest = BackupManifest::new(dir.clone());
+        manifest.add_file(
+            "data.blob".into(),
+            blob.raw_size(),
+            openssl::sha::sha256(blob.raw_data()),
+            CryptMode::None,
+        )?;
+        write_blob(
+            &snapshot,
+            MANIFEST_BLOB_NAME,
+            manifest.to_string(None)?.as_bytes(),
+        )?;
+
+        copy_snapshot(&snapshot, &archive)?;
+
+        // the namespace is created on the archive, too
+        let copy = archive.backup_dir(ns.clone(), dir.clone())?;
+        let (copied_manifest, _) = copy.load_manifest()?;
+        assert_eq!(copied_manifest.content_digest(), manifest.content_digest());
+        assert_eq!(
+            std::fs::read(copy.full_path().join("data.blob"))?,
+            blob.raw_data()
+        );
+        assert_eq!(archive.get_owner(&ns, dir.as_ref())?, owner);
+
+        // an interrupted run is simply repeated
+        copy_snapshot(&snapshot, &archive)?;
+
+        let _ = std::fs::remove_dir_all(source_path);
+        let _ = std::fs::remove_dir_all(archive_path);
+
+        Ok(())
+    }
+}
@@ -1,13 +1,63 @@
+use anyhow::format_err;
+
 use proxmox_rest_server::AuthError;
 use proxmox_router::UserInformation;
 
+use pbs_api_types::Authid;
 use pbs_config::CachedUserInfo;
 
+use super::auth_last_used::record_use;
+
 pub async fn check_pbs_auth(
     headers: &http::HeaderMap,
     method: &hyper::Method,
 ) -> Result<(String, Box<dyn UserInformation + Sync + Send>), AuthError> {
     let user_info = CachedUserInfo::new()?;
-    proxmox_auth_api::api::http_check_auth(headers, method)
-        .map(move |name| (name, Box::new(user_info) as _))
+    let name = proxmox_auth_api::api::http_check_auth(headers, method)?;
+    if let Ok(auth_id) = name.parse::<Authid>() {
+        record_use(&auth_id, None);
+    }
+    Ok((name, Box::new(user_info) as _))
+}
+
+/// Authenticate a request on the local API socket as the auth-id mapped to the uid of the
+/// connected process.
+pub async fn check_local_socket_auth(
+    auth_id: Authid,
+) -> Result<(String, Box<dyn UserInformation + Sync + Send>), AuthError> {
+    let user_info = CachedUserInfo::new()?;
+    if !user_info.is_active_auth_id(&auth_id) {
+        return Err(AuthError::Generic(format_err!(
+            "user account or token '{auth_id}' disabled or expired"
+        )));
+    }
+    record_use(&auth_id, None);
+    Ok((auth_id.to_string(), Box::new(user_info) as _))
+}
+
+/// Authenticate a protected call forwarded by the proxy from the local API socket, as the
+/// auth-id the proxy passed on.
+pub async fn check_forwarded_auth(
+    headers: &http::HeaderMap,
+) -> Result<(String, Box<dyn UserInformation + Sync + Send>), AuthError> {
+    let auth_id = headers
+        .get(crate::server::local_api::LOCAL_AUTH_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .ok_or(AuthError::NoData)?
+        .parse::<Authid>()
+        .map_err(AuthError::Generic)?;
+
+    // limits what the unprivileged proxy can pass on to the configured local API users
+    let (node_config, _) = crate::config::node::config()?;
+    if !node_config
+        .local_api_users()?
+        .values()
+        .any(|user| *user == auth_id)
+    {
+        return Err(AuthError::Generic(format_err!(
+            "'{auth_id}' is not a local API user"
+        )));
+    }
+
+    check_local_socket_auth(auth_id).await
 }
@@ -0,0 +1,139 @@
+//! Last successful use of users and API tokens
+//!
+//! Every authenticated request records a use, so uses are only collected in memory. The daemons
+//! write them to the state file every [`FLUSH_INTERVAL`] seconds with [`flush_worker`], and once
+//! more on shutdown. Both daemons merge their uses into the same file, keeping the newest one.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{format_err, Error};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+use proxmox_sys::fs::{file_read_optional_string, replace_file, CreateOptions};
+
+use pbs_api_types::Authid;
+use pbs_buildcfg::PROXMOX_BACKUP_STATE_DIR_M;
+use pbs_config::open_backup_lockfile;
+
+const LAST_USED_FN: &str = concat!(PROXMOX_BACKUP_STATE_DIR_M!(), "/auth-last-used.json");
+const LAST_USED_LOCK_FN: &str = concat!(PROXMOX_BACKUP_STATE_DIR_M!(), "/.auth-last-used.lck");
+
+/// Interval between two writes of the state file, in seconds
+pub const FLUSH_INTERVAL: u64 = 60;
+
+#[derive(Serialize, Deserialize, Clone)]
+/// Last successful use of a user or API token.
+pub struct LastUse {
+    /// Time of the use (epoch)
+    pub time: i64,
+    /// Source address of the last use it was known of
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip: Option<String>,
+}
+
+impl LastUse {
+    fn merge(&mut self, other: &LastUse) {
+        if other.time >= self.time {
+            self.time = other.time;
+            if other.ip.is_some() {
+                self.ip = other.ip.clone();
+            }
+        } else if self.ip.is_none() {
+            self.ip = other.ip.clone();
+        }
+    }
+}
+
+lazy_static! {
+    static ref PENDING: Mutex<HashMap<String, LastUse>> = Mutex::new(HashMap::new());
+}
+
+/// Record a successful use of `auth_id`, with the source address if known.
+pub fn record_use(auth_id: &Authid, ip: Option<IpAddr>) {
+    let new = LastUse {
+        time: proxmox_time::epoch_i64(),
+        ip: ip.map(|ip| ip.to_canonical().to_string()),
+    };
+
+    PENDING
+        .lock()
+        .unwrap()
+        .entry(auth_id.to_string())
+        .and_modify(|last| last.merge(&new))
+        .or_insert(new);
+}
+
+fn read_file() -> Result<HashMap<String, LastUse>, Error> {
+    match file_read_optional_string(LAST_USED_FN)? {
+        Some(content) => serde_json::from_str(&content)
+            .map_err(|err| format_err!("unable to parse {LAST_USED_FN:?} - {err}")),
+        None => Ok(HashMap::new()),
+    }
+}
+
+/// Write the pending uses of this process to the state file.
+pub fn flush() -> Result<(), Error> {
+    let uses = std::mem::take(&mut *PENDING.lock().unwrap());
+    if uses.is_empty() {
+        return Ok(());
+    }
+
+    let _lock = open_backup_lockfile(LAST_USED_LOCK_FN, None, true)?;
+
+    let mut data = read_file().unwrap_or_else(|err| {
+        log::warn!("{err}, starting over");
+        HashMap::new()
+    });
+    for (auth_id, last) in uses {
+        data.entry(auth_id)
+            .and_modify(|stored| stored.merge(&last))
+            .or_insert(last);
+    }
+
+    let backup_user = pbs_config::backup_user()?;
+    let options = CreateOptions::new()
+        .perm(nix::sys::stat::Mode::from_bits_truncate(0o0640))
+        .owner(backup_user.uid)
+        .group(backup_user.gid);
+    replace_file(
+        LAST_USED_FN,
+        serde_json::to_string(&data)?.as_bytes(),
+        options,
+        false,
+    )?;
+
+    Ok(())
+}
+
+/// Write the pending uses in a blocking task, logging errors.
+pub async fn flush_pending() {
+    match tokio::task::spawn_blocking(flush).await {
+        Ok(Ok(())) => (),
+        Ok(Err(err)) => log::error!("unable to write last use of users and tokens - {err}"),
+        Err(err) => log::error!("writing last use of users and tokens failed - {err}"),
+    }
+}
+
+/// Write the pending uses every [`FLUSH_INTERVAL`] seconds.
+pub async fn flush_worker() {
+    loop {
+        tokio::time::sleep(Duration::from_secs(FLUSH_INTERVAL)).await;
+        flush_pending().await;
+    }
+}
+
+/// Returns the last uses of all users and API tokens, including the uses not yet written by this
+/// process. Uses recorded by the other daemon might be up to [`FLUSH_INTERVAL`] seconds old.
+pub fn last_uses() -> Result<HashMap<String, LastUse>, Error> {
+    let mut data = read_file()?;
+    for (auth_id, last) in PENDING.lock().unwrap().iter() {
+        data.entry(auth_id.clone())
+            .and_modify(|stored| stored.merge(last))
+            .or_insert_with(|| last.clone());
+    }
+    Ok(data)
+}
@@ -0,0 +1,132 @@
+//! Live reload of configuration files
+//!
+//! Saving a configuration through the API increases its generation in the
+//! [ConfigVersionCache], which the daemons check to reload what they keep cached. The proxy
+//! additionally watches the configuration directory with inotify, so manual edits increase the
+//! generations too, and refreshes its datastore cache and traffic control rules every second
+//! when a generation changed.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::Error;
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
+
+use pbs_api_types::ConfigGeneration;
+use pbs_config::{CachedUserInfo, ConfigVersionCache};
+use pbs_datastore::DataStore;
+
+use crate::traffic_control_cache::TRAFFIC_CONTROL_CACHE;
+
+// configuration files and the generation their changes increase
+const WATCHED_CONFIGS: &[(&str, fn(&ConfigVersionCache))] = &[
+    (
+        "acl.cfg",
+        ConfigVersionCache::increase_user_cache_generation,
+    ),
+    ("datastore.cfg", |cache| {
+        cache.increase_datastore_generation();
+    }),
+    ("remote.cfg", ConfigVersionCache::increase_remote_generation),
+    (
+        "token.shadow",
+        ConfigVersionCache::increase_user_cache_generation,
+    ),
+    (
+        "traffic-control.cfg",
+        ConfigVersionCache::increase_traffic_control_generation,
+    ),
+    (
+        "user.cfg",
+        ConfigVersionCache::increase_user_cache_generation,
+    ),
+];
+
+static LOADED_DATASTORE_GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+fn watch_config_dir() -> Result<(), Error> {
+    let version_cache = ConfigVersionCache::new()?;
+
+    let inotify = Inotify::init(InitFlags::IN_CLOEXEC)?;
+    // the config helpers replace files by renaming a temporary file over them
+    inotify.add_watch(
+        pbs_buildcfg::CONFIGDIR,
+        AddWatchFlags::IN_CLOSE_WRITE | AddWatchFlags::IN_MOVED_TO | AddWatchFlags::IN_DELETE,
+    )?;
+
+    loop {
+        for event in inotify.read_events()? {
+            let name = match event.name.as_ref().and_then(|name| name.to_str()) {
+                Some(name) => name,
+                None => continue,
+            };
+            if let Some((_, increase)) = WATCHED_CONFIGS.iter().find(|(file, _)| *file == name) {
+                log::debug!("configuration file '{name}' changed");
+                increase(&version_cache);
+            }
+        }
+    }
+}
+
+/// Start watching the configuration directory for changes in a separate thread.
+pub fn start_config_watcher() {
+    let result = std::thread::Builder::new()
+        .name("config-watch".to_string())
+        .spawn(|| {
+            if let Err(err) = watch_config_dir() {
+                log::error!("watching the configuration directory failed - {err}");
+            }
+        });
+    if let Err(err) = result {
+        log::error!("unable to start configuration watcher - {err}");
+    }
+}
+
+/// Drop cached datastores that were removed, if the datastore configuration changed.
+pub fn refresh_datastore_cache() {
+    let generation = match ConfigVersionCache::new() {
+        Ok(cache) => cache.datastore_generation(),
+        Err(err) => {
+            log::error!("unable to read the datastore configuration generation - {err}");
+            return;
+        }
+    };
+    if LOADED_DATASTORE_GENERATION.swap(generation, Ordering::AcqRel) == generation {
+        return;
+    }
+    // datastores are looked up by reading the configuration, only removals need handling
+    if let Err(err) = DataStore::remove_unused_datastores() {
+        log::error!("could not refresh datastores: {err}");
+    }
+}
+
+/// Returns the current generations of the configuration files, and the ones in use.
+pub fn config_generations() -> Result<Vec<ConfigGeneration>, Error> {
+    let version_cache = ConfigVersionCache::new()?;
+    let traffic_control_loaded = TRAFFIC_CONTROL_CACHE.lock().unwrap().loaded_generation();
+
+    let generation = |config: &str, generation: usize, loaded: Option<usize>| ConfigGeneration {
+        config: config.to_string(),
+        generation: generation as u64,
+        loaded: loaded.map(|loaded| loaded as u64),
+    };
+
+    Ok(vec![
+        generation(
+            "datastore.cfg",
+            version_cache.datastore_generation(),
+            Some(LOADED_DATASTORE_GENERATION.load(Ordering::Acquire)),
+        ),
+        // remotes are read on every use
+        generation("remote.cfg", version_cache.remote_generation(), None),
+        generation(
+            "traffic-control.cfg",
+            version_cache.traffic_control_generation(),
+            Some(traffic_control_loaded),
+        ),
+        generation(
+            "user.cfg",
+            version_cache.user_cache_generation(),
+            Some(CachedUserInfo::loaded_generation()),
+        ),
+    ])
+}
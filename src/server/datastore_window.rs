@@ -0,0 +1,446 @@
+//! Admission control for datastores: access windows and concurrent session limits
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::Error;
+use hyper::{Body, Response};
+use lazy_static::lazy_static;
+
+use proxmox_time::{parse_daily_duration, DailyDuration, TmEditor};
+
+use pbs_api_types::{BackupPriority, DataStoreConfig, DatastoreWindowAction};
+
+use crate::api2::helpers::service_unavailable_response;
+
+/// How far ahead to look for the next opening of a window.
+const MAX_LOOKAHEAD: i64 = 7 * 24 * 3600;
+
+/// Interval in which queued sessions re-check their window.
+const QUEUE_RECHECK_INTERVAL: u64 = 60;
+
+/// Maximum time (in seconds) a queued session waits for its window to open.
+const MAX_QUEUE_WAIT: i64 = 3600;
+
+/// Backoff (in seconds) per session above the limit, and its maximum.
+const BUSY_RETRY_BASE: i64 = 30;
+const BUSY_RETRY_MAX: i64 = 300;
+/// Backoff (in seconds) of high priority sessions, independent of the number of sessions.
+const BUSY_RETRY_HIGH_PRIORITY: i64 = 10;
+
+/// The kind of datastore access restricted by a window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DatastoreAccess {
+    Backup,
+    Restore,
+    Maintenance,
+}
+
+impl DatastoreAccess {
+    fn as_str(self) -> &'static str {
+        match self {
+            DatastoreAccess::Backup => "backup",
+            DatastoreAccess::Restore => "restore",
+            DatastoreAccess::Maintenance => "maintenance",
+        }
+    }
+
+    fn window(self, config: &DataStoreConfig) -> Option<&[String]> {
+        match self {
+            DatastoreAccess::Backup => config.backup_window.as_deref(),
+            DatastoreAccess::Restore => config.restore_window.as_deref(),
+            DatastoreAccess::Maintenance => config.maintenance_window.as_deref(),
+        }
+    }
+
+    fn action(self, config: &DataStoreConfig) -> DatastoreWindowAction {
+        match self {
+            DatastoreAccess::Backup => config.backup_window_action.unwrap_or_default(),
+            DatastoreAccess::Restore => config.restore_window_action.unwrap_or_default(),
+            // maintenance jobs are scheduled, so they simply wait for the window
+            DatastoreAccess::Maintenance => DatastoreWindowAction::Queue,
+        }
+    }
+}
+
+fn window_match(window: &[DailyDuration], time: i64) -> Result<bool, Error> {
+    let tm = TmEditor::with_epoch(time, false)?;
+    Ok(window
+        .iter()
+        .any(|duration| duration.time_match_with_tm_editor(&tm)))
+}
+
+/// Checks whether `access` is allowed on the datastore at `now`.
+///
+/// Returns `None` if the window is open (or none is configured), otherwise the number of
+/// seconds until it opens again, capped to one week.
+pub fn window_closed_for(
+    config: &DataStoreConfig,
+    access: DatastoreAccess,
+    now: i64,
+) -> Result<Option<i64>, Error> {
+    let window = match access.window(config) {
+        Some(window) if !window.is_empty() => window,
+        _ => return Ok(None),
+    };
+
+    let window = window
+        .iter()
+        .map(|duration| parse_daily_duration(duration))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if window_match(&window, now)? {
+        return Ok(None);
+    }
+
+    // daily durations have minute resolution, so checking each minute start is sufficient
+    let mut time = now - now.rem_euclid(60) + 60;
+    while time - now < MAX_LOOKAHEAD {
+        if window_match(&window, time)? {
+            return Ok(Some(time - now));
+        }
+        time += 60;
+    }
+
+    Ok(Some(MAX_LOOKAHEAD))
+}
+
+/// Like [`window_closed_for`], but looks up the datastore configuration by name.
+pub fn datastore_window_closed_for(
+    store: &str,
+    access: DatastoreAccess,
+) -> Result<Option<i64>, Error> {
+    let (config, _digest) = pbs_config::datastore::config()?;
+    let config: DataStoreConfig = config.lookup("datastore", store)?;
+    window_closed_for(&config, access, proxmox_time::epoch_i64())
+}
+
+fn refuse_response(store: &str, access: DatastoreAccess, wait: i64) -> Response<Body> {
+    let msg = format!(
+        "datastore '{store}' does not accept {} sessions at this time, \
+        the window opens in {wait} seconds",
+        access.as_str(),
+    );
+    service_unavailable_response(msg, wait)
+}
+
+/// Admitted sessions and refused ones that have yet to retry, of one datastore and kind of
+/// access.
+#[derive(Default)]
+struct SessionState {
+    /// Number of admitted sessions that are still running.
+    active: i64,
+    /// Times until which refused sessions were asked to wait.
+    retry_at: Vec<i64>,
+}
+
+lazy_static! {
+    /// Session state of this process, by datastore and kind of access.
+    ///
+    /// Admission checks and counts a session under this lock, so concurrent sessions cannot all
+    /// pass the limit check before any of them is counted.
+    static ref SESSIONS: Mutex<HashMap<(String, DatastoreAccess), SessionState>> =
+        Mutex::new(HashMap::new());
+}
+
+/// A session admitted by [`admit_session`], counted towards the session limit of its datastore
+/// until dropped.
+pub struct SessionGuard {
+    store: String,
+    access: DatastoreAccess,
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        let mut sessions = SESSIONS.lock().unwrap();
+        if let Some(state) = sessions.get_mut(&(self.store.clone(), self.access)) {
+            state.active -= 1;
+        }
+    }
+}
+
+/// The outcome of [`admit_session`].
+pub enum Admission {
+    /// The session may start, keep the guard for as long as it runs.
+    Admitted(SessionGuard),
+    /// The session must not start, send the response to the client instead.
+    Refused(Response<Body>),
+}
+
+/// Checks the concurrent session limit of the datastore for `access`, with `active` sessions
+/// already running.
+///
+/// Returns the number of seconds the client should wait before retrying if the limit is
+/// reached. The backoff grows with the number of sessions above the limit, including the
+/// refused ones that have not retried yet, to spread out clients that all start at the same
+/// time. The returned retry time is recorded in `state`.
+///
+/// High priority sessions may use one slot above the limit and retry quickly, low priority ones
+/// leave the last slot free and retry with twice the backoff.
+fn session_limit_backoff(
+    config: &DataStoreConfig,
+    access: DatastoreAccess,
+    priority: BackupPriority,
+    active: i64,
+    state: &mut SessionState,
+    now: i64,
+) -> Option<i64> {
+    let max = match access {
+        DatastoreAccess::Backup => config.max_backup_sessions,
+        DatastoreAccess::Restore => config.max_reader_sessions,
+        DatastoreAccess::Maintenance => None,
+    };
+    let max = max? as i64;
+
+    state.retry_at.retain(|retry_at| *retry_at > now);
+
+    let limit = match priority {
+        BackupPriority::Low if max > 1 => max - 1,
+        BackupPriority::Low | BackupPriority::Normal => max,
+        BackupPriority::High => max + 1,
+    };
+    if active < limit {
+        return None;
+    }
+
+    let excess = active - limit + state.retry_at.len() as i64 + 1;
+    let backoff = match priority {
+        BackupPriority::Low => (2 * BUSY_RETRY_BASE * excess).min(2 * BUSY_RETRY_MAX),
+        BackupPriority::Normal => (BUSY_RETRY_BASE * excess).min(BUSY_RETRY_MAX),
+        BackupPriority::High => BUSY_RETRY_HIGH_PRIORITY,
+    };
+    state.retry_at.push(now + backoff);
+
+    Some(backoff)
+}
+
+/// Admission control for new client sessions.
+///
+/// If the relevant window of the datastore is closed, this either refuses the session with a
+/// `503 Service Unavailable` response with a `Retry-After` header, or waits until the window
+/// opens, depending on the configured action. Sessions are only queued if the window opens
+/// within an hour, otherwise they are refused, too.
+///
+/// If the datastore is at its concurrent session limit, the session is refused with a `503`
+/// response with a server-assigned backoff, so that clients retry later instead of failing. The
+/// limit and backoff depend on the `priority` of the session.
+///
+/// Sessions are counted by this process, so sessions still running in an old proxy process after
+/// a reload do not count towards the limit.
+pub async fn admit_session(
+    store: &str,
+    access: DatastoreAccess,
+    priority: BackupPriority,
+) -> Result<Admission, Error> {
+    let queued_since = proxmox_time::epoch_i64();
+
+    let config = loop {
+        let (config, _digest) = pbs_config::datastore::config()?;
+        let config: DataStoreConfig = config.lookup("datastore", store)?;
+
+        let now = proxmox_time::epoch_i64();
+        let wait = match window_closed_for(&config, access, now)? {
+            Some(wait) => wait,
+            None => break config,
+        };
+
+        match access.action(&config) {
+            DatastoreWindowAction::Refuse => {
+                return Ok(Admission::Refused(refuse_response(store, access, wait)));
+            }
+            DatastoreWindowAction::Queue if now - queued_since + wait > MAX_QUEUE_WAIT => {
+                return Ok(Admission::Refused(refuse_response(store, access, wait)));
+            }
+            DatastoreWindowAction::Queue => {
+                // re-check periodically, the configuration might change while we wait
+                let wait = (wait as u64).min(QUEUE_RECHECK_INTERVAL);
+                tokio::time::sleep(Duration::from_secs(wait)).await;
+            }
+        }
+    };
+
+    let mut sessions = SESSIONS.lock().unwrap();
+    let state = sessions.entry((store.to_string(), access)).or_default();
+
+    let now = proxmox_time::epoch_i64();
+    let active = state.active;
+    if let Some(retry_after) = session_limit_backoff(&config, access, priority, active, state, now)
+    {
+        let msg = format!(
+            "datastore '{store}' is busy, too many concurrent {} sessions - retry in \
+            {retry_after} seconds",
+            access.as_str(),
+        );
+        return Ok(Admission::Refused(service_unavailable_response(
+            msg,
+            retry_after,
+        )));
+    }
+
+    state.active += 1;
+
+    Ok(Admission::Admitted(SessionGuard {
+        store: store.to_string(),
+        access,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // 2024-01-01 (a monday) 00:00 local time
+    fn monday() -> i64 {
+        let mut tm = proxmox_time::localtime(0).unwrap();
+        tm.tm_year = 124;
+        tm.tm_mon = 0;
+        tm.tm_mday = 1;
+        tm.tm_hour = 0;
+        tm.tm_min = 0;
+        tm.tm_sec = 0;
+        tm.tm_isdst = -1;
+        proxmox_time::timelocal(&mut tm).unwrap()
+    }
+
+    fn window_config(window: &[&str]) -> DataStoreConfig {
+        let mut config = DataStoreConfig::new("test".to_string(), "/tmp/test".to_string());
+        config.backup_window = Some(window.iter().map(|w| w.to_string()).collect());
+        config
+    }
+
+    #[test]
+    fn test_window_closed_for() -> Result<(), Error> {
+        let monday = monday();
+
+        let config = DataStoreConfig::new("test".to_string(), "/tmp/test".to_string());
+        assert_eq!(
+            window_closed_for(&config, DatastoreAccess::Backup, monday)?,
+            None
+        );
+
+        let config = window_config(&["18:00-23:59"]);
+        assert_eq!(
+            window_closed_for(&config, DatastoreAccess::Backup, monday + 19 * 3600)?,
+            None
+        );
+        assert_eq!(
+            window_closed_for(&config, DatastoreAccess::Backup, monday + 17 * 3600)?,
+            Some(3600)
+        );
+        assert_eq!(
+            window_closed_for(&config, DatastoreAccess::Backup, monday + 17 * 3600 + 30)?,
+            Some(3570)
+        );
+        // other kinds of access are not restricted by the backup window
+        assert_eq!(
+            window_closed_for(&config, DatastoreAccess::Restore, monday + 17 * 3600)?,
+            None
+        );
+
+        // the window on saturday opens five days later
+        let config = window_config(&["sat 1:00-2:00"]);
+        assert_eq!(
+            window_closed_for(&config, DatastoreAccess::Backup, monday)?,
+            Some(5 * 24 * 3600 + 3600)
+        );
+
+        // multiple timeframes, the earliest opening counts
+        let config = window_config(&["sat 1:00-2:00", "tue 3:00-4:00"]);
+        assert_eq!(
+            window_closed_for(&config, DatastoreAccess::Backup, monday)?,
+            Some(24 * 3600 + 3 * 3600)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_session_limit_backoff() {
+        let mut config = DataStoreConfig::new("test".to_string(), "/tmp/test".to_string());
+        let mut state = SessionState::default();
+        let now = 1_700_000_000;
+
+        let backoff = |config: &DataStoreConfig, priority, active, state: &mut SessionState| {
+            session_limit_backoff(
+                config,
+                DatastoreAccess::Backup,
+                priority,
+                active,
+                state,
+                now,
+            )
+        };
+
+        // no limit configured
+        assert_eq!(
+            backoff(&config, BackupPriority::Normal, 100, &mut state),
+            None
+        );
+
+        config.max_backup_sessions = Some(2);
+        assert_eq!(
+            backoff(&config, BackupPriority::Normal, 1, &mut state),
+            None
+        );
+        assert_eq!(
+            backoff(&config, BackupPriority::Low, 1, &mut state),
+            Some(60)
+        );
+        assert_eq!(backoff(&config, BackupPriority::High, 2, &mut state), None);
+
+        // refused clients that did not retry yet make the backoff of the next ones grow
+        let mut state = SessionState::default();
+        assert_eq!(
+            backoff(&config, BackupPriority::Normal, 2, &mut state),
+            Some(30)
+        );
+        assert_eq!(
+            backoff(&config, BackupPriority::Normal, 2, &mut state),
+            Some(60)
+        );
+        assert_eq!(
+            backoff(&config, BackupPriority::Normal, 2, &mut state),
+            Some(90)
+        );
+        assert_eq!(
+            backoff(&config, BackupPriority::High, 3, &mut state),
+            Some(10)
+        );
+        for _ in 0..20 {
+            backoff(&config, BackupPriority::Normal, 2, &mut state);
+        }
+        assert_eq!(
+            backoff(&config, BackupPriority::Normal, 2, &mut state),
+            Some(BUSY_RETRY_MAX)
+        );
+
+        // reader sessions have their own limit
+        let mut state = SessionState::default();
+        let restore = |config: &DataStoreConfig, active, state: &mut SessionState| {
+            let priority = BackupPriority::Normal;
+            session_limit_backoff(
+                config,
+                DatastoreAccess::Restore,
+                priority,
+                active,
+                state,
+                now,
+            )
+        };
+        assert_eq!(restore(&config, 10, &mut state), None);
+        config.max_reader_sessions = Some(4);
+        assert_eq!(restore(&config, 3, &mut state), None);
+        assert_eq!(restore(&config, 4, &mut state), Some(30));
+
+        // retry times that passed are forgotten
+        let mut state = SessionState {
+            active: 0,
+            retry_at: vec![now - 10, now],
+        };
+        assert_eq!(
+            backoff(&config, BackupPriority::Normal, 2, &mut state),
+            Some(30)
+        );
+    }
+}
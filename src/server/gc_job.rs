@@ -3,11 +3,20 @@ use std::sync::Arc;
 
 use proxmox_sys::task_log;
 
-use pbs_api_types::Authid;
+use pbs_api_types::{Authid, GarbageCollectionStatus, GcRunStatistics};
 use pbs_datastore::DataStore;
 use proxmox_rest_server::WorkerTask;
 
-use crate::server::{jobstate::Job, send_gc_status};
+use crate::server::{
+    jobstate::{Job, JobRunStatistics},
+    send_gc_status,
+};
+
+fn used_space(datastore: &DataStore) -> Option<u64> {
+    proxmox_sys::fs::fs_info(&datastore.base_path())
+        .ok()
+        .map(|info| info.used)
+}
 
 /// Runs a garbage collection job.
 pub fn do_garbage_collection_job(
@@ -33,15 +42,23 @@ pub fn do_garbage_collection_job(
                 task_log!(worker, "task triggered by schedule '{event_str}'");
             }
 
+            let used_before = used_space(&datastore);
+
             let result = datastore.garbage_collection(&*worker, worker.upid());
 
             let status = worker.create_state(&result);
 
-            if let Err(err) = job.finish(status) {
+            let gc_status = datastore.last_gc_status();
+            let statistics = JobRunStatistics {
+                store: Some(store.clone()),
+                gc: gc_run_statistics(&gc_status, &worker, used_before, used_space(&datastore)),
+                ..Default::default()
+            };
+
+            if let Err(err) = job.finish_with_statistics(status, statistics) {
                 eprintln!("could not finish job state for {}: {err}", job.jobtype());
             }
 
-            let gc_status = datastore.last_gc_status();
             if let Err(err) = send_gc_status(&store, &gc_status, &result) {
                 eprintln!("send gc notification failed: {err}");
             }
@@ -52,3 +69,25 @@ pub fn do_garbage_collection_job(
 
     Ok(upid_str)
 }
+
+// the status is only updated by successful runs, failed ones have no statistics
+fn gc_run_statistics(
+    gc_status: &GarbageCollectionStatus,
+    worker: &WorkerTask,
+    used_before: Option<u64>,
+    used_after: Option<u64>,
+) -> Option<GcRunStatistics> {
+    if gc_status.upid.as_deref() != Some(&worker.upid().to_string()) {
+        return None;
+    }
+
+    Some(GcRunStatistics {
+        removed_bytes: gc_status.removed_bytes,
+        removed_chunks: gc_status.removed_chunks,
+        pending_bytes: gc_status.pending_bytes,
+        pending_chunks: gc_status.pending_chunks,
+        disk_bytes: gc_status.disk_bytes,
+        used_before,
+        used_after,
+    })
+}
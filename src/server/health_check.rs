@@ -0,0 +1,176 @@
+//! Daily health check
+//!
+//! Collects conditions which need the attention of an administrator before they become a problem,
+//! like expiring certificates or datastores running full, and sends a single notification listing
+//! all of them.
+
+use anyhow::Error;
+use serde_json::Value;
+
+use proxmox_router::RpcEnvironment;
+use proxmox_subscription::SubscriptionStatus;
+
+use pbs_api_types::ApiToken;
+
+use crate::api2;
+
+/// Warn if the proxy certificate expires within this many days
+const CERT_EXPIRY_DAYS: i64 = 30;
+/// Warn about tape encryption keys older than this many days
+const TAPE_KEY_AGE_DAYS: i64 = 365;
+/// Warn if an API token expires within this many days
+const TOKEN_EXPIRY_DAYS: i64 = 14;
+/// Warn if a datastore is estimated to be full within this many days
+const DATASTORE_FULL_DAYS: i64 = 30;
+
+const DAY: i64 = 24 * 60 * 60;
+
+fn format_time(epoch: i64) -> String {
+    proxmox_time::strftime_local("%F", epoch).unwrap_or_else(|_| epoch.to_string())
+}
+
+fn check_certificate(now: i64, warnings: &mut Vec<String>) -> Result<(), Error> {
+    let expiry = api2::node::certificates::cert_expiry()?;
+    if expiry < now {
+        warnings.push(format!(
+            "the proxy certificate expired on {}",
+            format_time(expiry)
+        ));
+    } else if expiry < now + CERT_EXPIRY_DAYS * DAY {
+        warnings.push(format!(
+            "the proxy certificate expires on {}",
+            format_time(expiry)
+        ));
+    }
+    Ok(())
+}
+
+fn check_tape_keys(now: i64, warnings: &mut Vec<String>) -> Result<(), Error> {
+    let (key_map, _digest) = crate::tape::encryption_keys::load_key_configs()?;
+    for (fingerprint, key_config) in key_map {
+        if key_config.created < now - TAPE_KEY_AGE_DAYS * DAY {
+            warnings.push(format!(
+                "tape encryption key {fingerprint} was created on {}, consider rotating it",
+                format_time(key_config.created),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn check_tokens(now: i64, warnings: &mut Vec<String>) -> Result<(), Error> {
+    let (config, _digest) = pbs_config::user::config()?;
+    let tokens: Vec<ApiToken> = config.convert_to_typed_array("token")?;
+    for token in tokens {
+        if token.enable == Some(false) {
+            continue;
+        }
+        match token.expire {
+            Some(expire) if expire > 0 && expire < now => {
+                warnings.push(format!(
+                    "API token {} expired on {}",
+                    token.tokenid,
+                    format_time(expire)
+                ));
+            }
+            Some(expire) if expire > 0 && expire < now + TOKEN_EXPIRY_DAYS * DAY => {
+                warnings.push(format!(
+                    "API token {} expires on {}",
+                    token.tokenid,
+                    format_time(expire)
+                ));
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+fn check_subscription(
+    rpcenv: &mut dyn RpcEnvironment,
+    warnings: &mut Vec<String>,
+) -> Result<(), Error> {
+    let info = api2::node::subscription::get_subscription(Value::Null, rpcenv)?;
+    match info.status {
+        SubscriptionStatus::Active | SubscriptionStatus::NotFound => (),
+        status => {
+            let mut message = format!("the subscription is {status}");
+            if let Some(reason) = info.message {
+                message.push_str(&format!(" - {reason}"));
+            }
+            warnings.push(message);
+        }
+    }
+    Ok(())
+}
+
+async fn check_datastores(
+    now: i64,
+    rpcenv: &mut dyn RpcEnvironment,
+    warnings: &mut Vec<String>,
+) -> Result<(), Error> {
+    let list = api2::status::datastore_status(
+        Value::Null,
+        &api2::status::API_METHOD_DATASTORE_STATUS,
+        rpcenv,
+    )
+    .await?;
+
+    for entry in list {
+        if let Some(error) = entry.error {
+            warnings.push(format!(
+                "datastore {} is not available - {error}",
+                entry.store
+            ));
+            continue;
+        }
+        // an estimate in the past means the usage is not growing
+        match entry.estimated_full_date {
+            Some(full) if full > now && full < now + DATASTORE_FULL_DAYS * DAY => {
+                warnings.push(format!(
+                    "datastore {} is estimated to be full on {}",
+                    entry.store,
+                    format_time(full)
+                ));
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// Run all health checks, sending a notification if any of them produced a warning.
+///
+/// A check which fails is reported as a warning too, so that a broken check does not go
+/// unnoticed.
+pub async fn do_health_check(rpcenv: &mut dyn RpcEnvironment) -> Result<(), Error> {
+    let now = proxmox_time::epoch_i64();
+    let mut warnings = Vec::new();
+
+    if let Err(err) = check_certificate(now, &mut warnings) {
+        warnings.push(format!("could not check the proxy certificate - {err}"));
+    }
+    if let Err(err) = check_tape_keys(now, &mut warnings) {
+        warnings.push(format!("could not check the tape encryption keys - {err}"));
+    }
+    if let Err(err) = check_tokens(now, &mut warnings) {
+        warnings.push(format!("could not check the API tokens - {err}"));
+    }
+    if let Err(err) = check_subscription(rpcenv, &mut warnings) {
+        warnings.push(format!("could not check the subscription - {err}"));
+    }
+    if let Err(err) = check_datastores(now, rpcenv, &mut warnings).await {
+        warnings.push(format!("could not check the datastore usage - {err}"));
+    }
+
+    if warnings.is_empty() {
+        log::info!("health check found no problems");
+        return Ok(());
+    }
+
+    for warning in &warnings {
+        log::warn!("health check: {warning}");
+    }
+
+    super::send_health_check_warnings(&warnings)
+}
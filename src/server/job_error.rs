@@ -0,0 +1,233 @@
+//! Classification of job errors and automatic retries
+//!
+//! Failed sync, verify, prune and tape backup jobs record a [`JobErrorClass`] in their job
+//! history, which is also exposed in the job status and the error notifications. Jobs with a
+//! retry policy are run again if they failed with a transient network error, waiting between
+//! the attempts with an exponential backoff.
+
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use anyhow::Error;
+
+use proxmox_router::HttpError;
+use proxmox_schema::ApiType;
+use proxmox_sys::task_warn;
+
+use pbs_api_types::{JobErrorClass, JobRetryPolicy};
+use pbs_client::retry::{backoff_delay, is_transient_error};
+use proxmox_rest_server::WorkerTask;
+
+/// Upper bound for the delay between two attempts, in seconds
+const MAX_RETRY_DELAY: u64 = 3600;
+
+fn classify_io_error(err: &std::io::Error) -> Option<JobErrorClass> {
+    match err.raw_os_error().map(nix::errno::Errno::from_i32) {
+        Some(nix::errno::Errno::ENOSPC | nix::errno::Errno::EDQUOT) => {
+            Some(JobErrorClass::StorageFull)
+        }
+        _ if err.kind() == std::io::ErrorKind::PermissionDenied => Some(JobErrorClass::Permission),
+        _ => None,
+    }
+}
+
+// only used for errors which are not transient, those are detected by their type
+fn classify_message(msg: &str) -> JobErrorClass {
+    let msg = msg.to_lowercase();
+    let contains_any = |patterns: &[&str]| patterns.iter().any(|p| msg.contains(p));
+
+    if contains_any(&["wrong digest", "checksum", "corrupt"]) {
+        JobErrorClass::DataCorruption
+    } else if contains_any(&["no space left", "quota exceeded"]) {
+        JobErrorClass::StorageFull
+    } else if contains_any(&["permission", "not allowed", "authentication failed"]) {
+        JobErrorClass::Permission
+    } else {
+        JobErrorClass::Other
+    }
+}
+
+/// Classify an error by its causes, falling back to looking at the error message.
+pub fn classify_error(err: &Error) -> JobErrorClass {
+    for cause in err.chain() {
+        if let Some(HttpError { code, .. }) = cause.downcast_ref::<HttpError>() {
+            if matches!(code.as_u16(), 401 | 403) {
+                return JobErrorClass::Permission;
+            }
+        }
+        if is_transient_error(cause) {
+            return JobErrorClass::TransientNetwork;
+        }
+        let class = if let Some(err) = cause.downcast_ref::<std::io::Error>() {
+            classify_io_error(err)
+        } else if let Some(errno) = cause.downcast_ref::<nix::errno::Errno>() {
+            classify_io_error(&std::io::Error::from(*errno))
+        } else {
+            None
+        };
+        if let Some(class) = class {
+            return class;
+        }
+    }
+
+    classify_message(&format!("{err:#}"))
+}
+
+/// Parse the retry policy property string of a job config.
+pub fn parse_retry_policy(retry: Option<&str>) -> Result<JobRetryPolicy, Error> {
+    Ok(serde_json::from_value(
+        JobRetryPolicy::API_SCHEMA.parse_property_string(retry.unwrap_or(""))?,
+    )?)
+}
+
+// the delay before the retry number `attempt`, starting at 1
+fn retry_delay(policy: &JobRetryPolicy, attempt: u64) -> Duration {
+    backoff_delay(
+        Duration::from_secs(policy.delay.unwrap_or(60)),
+        Duration::from_secs(MAX_RETRY_DELAY),
+        attempt.try_into().unwrap_or(u32::MAX),
+    )
+}
+
+// returns the delay if the error should be retried, logging the failed attempt
+fn check_retry(
+    worker: &WorkerTask,
+    policy: &JobRetryPolicy,
+    attempt: u64,
+    err: &Error,
+) -> Option<Duration> {
+    if attempt > policy.count.unwrap_or(0) {
+        return None;
+    }
+    let class = classify_error(err);
+    if class != JobErrorClass::TransientNetwork {
+        return None;
+    }
+    let delay = retry_delay(policy, attempt);
+    task_warn!(worker, "attempt failed ({class}): {err}");
+    task_warn!(
+        worker,
+        "retrying in {} seconds ({attempt}/{})",
+        delay.as_secs(),
+        policy.count.unwrap_or(0)
+    );
+    Some(delay)
+}
+
+/// Run `job`, retrying it according to `policy` if it fails with a transient error.
+///
+/// Waiting for the next attempt is interrupted if the worker gets aborted.
+pub fn run_with_retry<T, F>(
+    worker: &WorkerTask,
+    policy: &JobRetryPolicy,
+    mut job: F,
+) -> Result<T, Error>
+where
+    F: FnMut() -> Result<T, Error>,
+{
+    let mut attempt = 1;
+    loop {
+        let err = match job() {
+            Ok(result) => return Ok(result),
+            Err(err) => err,
+        };
+        let delay = match check_retry(worker, policy, attempt, &err) {
+            Some(delay) => delay,
+            None => return Err(err),
+        };
+        let until = Instant::now() + delay;
+        while Instant::now() < until {
+            worker.check_abort()?;
+            std::thread::sleep(Duration::from_secs(1).min(until - Instant::now()));
+        }
+        attempt += 1;
+    }
+}
+
+/// Async variant of [`run_with_retry`].
+///
+/// Aborting the worker is left to the caller, which usually selects on its abort future.
+pub async fn run_with_retry_async<T, F, R>(
+    worker: &WorkerTask,
+    policy: &JobRetryPolicy,
+    mut job: F,
+) -> Result<T, Error>
+where
+    F: FnMut() -> R,
+    R: Future<Output = Result<T, Error>>,
+{
+    let mut attempt = 1;
+    loop {
+        let err = match job().await {
+            Ok(result) => return Ok(result),
+            Err(err) => err,
+        };
+        let delay = match check_retry(worker, policy, attempt, &err) {
+            Some(delay) => delay,
+            None => return Err(err),
+        };
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use anyhow::format_err;
+
+    #[test]
+    fn test_classify_error() {
+        let err = Error::from(std::io::Error::from(std::io::ErrorKind::ConnectionReset));
+        assert_eq!(classify_error(&err), JobErrorClass::TransientNetwork);
+
+        let err = Error::from(nix::errno::Errno::ENOSPC).context("unable to write chunk");
+        assert_eq!(classify_error(&err), JobErrorClass::StorageFull);
+
+        let err = Error::from(HttpError::new(
+            http::StatusCode::FORBIDDEN,
+            "permission check failed".to_string(),
+        ));
+        assert_eq!(classify_error(&err), JobErrorClass::Permission);
+
+        let err = Error::from(HttpError::new(
+            http::StatusCode::SERVICE_UNAVAILABLE,
+            "unavailable".to_string(),
+        ));
+        assert_eq!(classify_error(&err), JobErrorClass::TransientNetwork);
+
+        let err = format_err!("detected chunk with wrong digest.");
+        assert_eq!(classify_error(&err), JobErrorClass::DataCorruption);
+
+        let err = format_err!("no such datastore 'foo'");
+        assert_eq!(classify_error(&err), JobErrorClass::Other);
+
+        // e.g. a tape drive not responding, retrying the whole job won't help
+        let err = Error::from(nix::errno::Errno::ETIMEDOUT).context("write filemark failed");
+        assert_eq!(classify_error(&err), JobErrorClass::Other);
+
+        let err = format_err!("connection timed out");
+        assert_eq!(classify_error(&err), JobErrorClass::Other);
+    }
+
+    #[test]
+    fn test_retry_policy() -> Result<(), Error> {
+        let policy = parse_retry_policy(None)?;
+        assert_eq!(policy.count, None);
+
+        let policy = parse_retry_policy(Some("count=3,delay=10"))?;
+        assert_eq!(policy.count, Some(3));
+        assert_eq!(retry_delay(&policy, 1), Duration::from_secs(10));
+        assert_eq!(retry_delay(&policy, 3), Duration::from_secs(40));
+
+        let policy = parse_retry_policy(Some("count=10,delay=3600"))?;
+        assert_eq!(
+            retry_delay(&policy, 10),
+            Duration::from_secs(MAX_RETRY_DELAY)
+        );
+
+        assert!(parse_retry_policy(Some("count=11")).is_err());
+        Ok(())
+    }
+}
@@ -11,6 +11,9 @@
 //! 'Job' which handles locking and writing to a file
 //! 'JobState' which is the actual state
 //!
+//! Additionally, every finished run is appended to a compact per-job history, which is kept
+//! independently of the task log archive (see [`read_job_history`]).
+//!
 //! an example usage would be
 //! ```no_run
 //! # use anyhow::{bail, Error};
@@ -46,11 +49,16 @@ use proxmox_sys::fs::{create_path, file_read_optional_string, replace_file, Crea
 
 use proxmox_time::CalendarEvent;
 
-use pbs_api_types::{JobScheduleStatus, UPID};
+use pbs_api_types::{
+    GcRunStatistics, JobErrorClass, JobHistoryEntry, JobScheduleStatus, TaskStateType,
+    VerifyRunStatistics, UPID,
+};
 use pbs_buildcfg::PROXMOX_BACKUP_STATE_DIR_M;
 use pbs_config::{open_backup_lockfile, BackupLockGuard};
 
-use proxmox_rest_server::{upid_read_status, worker_is_active_local, TaskState};
+use proxmox_rest_server::{worker_is_active_local, TaskState};
+
+use crate::server::task_log::read_task_status;
 
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
@@ -77,8 +85,28 @@ pub struct Job {
     _lock: BackupLockGuard,
 }
 
+/// Additional statistics of a job run, recorded in the job history
+#[derive(Default)]
+pub struct JobRunStatistics {
+    /// Amount of transferred bytes
+    pub bytes: Option<u64>,
+    /// Number of processed snapshots
+    pub snapshots: Option<u64>,
+    /// Datastore the job ran on
+    pub store: Option<String>,
+    /// Statistics of garbage collection runs
+    pub gc: Option<GcRunStatistics>,
+    /// Statistics of verification runs
+    pub verify: Option<VerifyRunStatistics>,
+    /// Classification of the error a failed run ended with
+    pub error_class: Option<JobErrorClass>,
+}
+
 const JOB_STATE_BASEDIR: &str = concat!(PROXMOX_BACKUP_STATE_DIR_M!(), "/jobstates");
 
+/// Maximum number of runs kept in the history of a single job
+const JOB_HISTORY_MAX_ENTRIES: usize = 500;
+
 /// Create jobstate stat dir with correct permission
 pub fn create_jobstate_dir() -> Result<(), Error> {
     let backup_user = pbs_config::backup_user()?;
@@ -99,6 +127,12 @@ fn get_path(jobtype: &str, jobname: &str) -> PathBuf {
     path
 }
 
+fn get_history_path(jobtype: &str, jobname: &str) -> PathBuf {
+    let mut path = get_path(jobtype, jobname);
+    path.set_extension("history");
+    path
+}
+
 fn get_lock<P>(path: P) -> Result<BackupLockGuard, Error>
 where
     P: AsRef<Path>,
@@ -117,6 +151,12 @@ pub fn remove_state_file(jobtype: &str, jobname: &str) -> Result<(), Error> {
             bail!("cannot remove statefile for {jobtype} - {jobname}: {err}");
         }
     }
+    path.set_extension("history");
+    if let Err(err) = std::fs::remove_file(&path) {
+        if err.kind() != std::io::ErrorKind::NotFound {
+            bail!("cannot remove history for {jobtype} - {jobname}: {err}");
+        }
+    }
     path.set_extension("lck");
     if let Err(err) = std::fs::remove_file(&path) {
         if err.kind() != std::io::ErrorKind::NotFound {
@@ -198,7 +238,7 @@ impl JobState {
                         .map_err(|err| format_err!("error parsing upid: {err}"))?;
 
                     if !worker_is_active_local(&parsed) {
-                        let state = upid_read_status(&parsed).unwrap_or(TaskState::Unknown {
+                        let state = read_task_status(&parsed).unwrap_or(TaskState::Unknown {
                             endtime: parsed.starttime,
                         });
 
@@ -259,6 +299,16 @@ impl Job {
     /// Finish the job and update the statefile accordingly with the given taskstate
     /// Fails if the job was not yet started
     pub fn finish(&mut self, state: TaskState) -> Result<(), Error> {
+        self.finish_with_statistics(state, JobRunStatistics::default())
+    }
+
+    /// Like [`finish`](Self::finish), but additionally records the given statistics in the job
+    /// history.
+    pub fn finish_with_statistics(
+        &mut self,
+        state: TaskState,
+        statistics: JobRunStatistics,
+    ) -> Result<(), Error> {
         let upid = match &self.state {
             JobState::Created { .. } => bail!("cannot finish when not started"),
             JobState::Started { upid } => upid,
@@ -266,6 +316,14 @@ impl Job {
         }
         .to_string();
 
+        if let Err(err) = self.append_history(&upid, &state, statistics) {
+            log::error!(
+                "could not update job history for {} - {}: {err}",
+                self.jobtype,
+                self.jobname
+            );
+        }
+
         self.state = JobState::Finished {
             upid,
             state,
@@ -283,21 +341,168 @@ impl Job {
         &self.jobname
     }
 
-    fn write_state(&mut self) -> Result<(), Error> {
-        let serialized = serde_json::to_string(&self.state)?;
-        let path = get_path(&self.jobtype, &self.jobname);
+    fn append_history(
+        &self,
+        upid: &str,
+        state: &TaskState,
+        statistics: JobRunStatistics,
+    ) -> Result<(), Error> {
+        let parsed_upid: UPID = upid.parse()?;
+        let status = match state {
+            TaskState::OK { .. } => TaskStateType::OK,
+            TaskState::Warning { .. } => TaskStateType::Warning,
+            TaskState::Error { .. } => TaskStateType::Error,
+            TaskState::Unknown { .. } => TaskStateType::Unknown,
+        };
+
+        let entry = JobHistoryEntry {
+            job_type: self.jobtype.clone(),
+            job_id: self.jobname.clone(),
+            upid: upid.to_string(),
+            starttime: parsed_upid.starttime,
+            endtime: state.endtime(),
+            status,
+            state: state.to_string(),
+            bytes: statistics.bytes,
+            snapshots: statistics.snapshots,
+            store: statistics.store,
+            gc: statistics.gc,
+            verify: statistics.verify,
+            error_class: statistics.error_class,
+        };
+
+        let path = get_history_path(&self.jobtype, &self.jobname);
+        let content = file_read_optional_string(&path)?.unwrap_or_default();
+
+        let mut lines: Vec<&str> = content.lines().collect();
+        if lines.len() >= JOB_HISTORY_MAX_ENTRIES {
+            lines.drain(..=lines.len() - JOB_HISTORY_MAX_ENTRIES);
+        }
+        let new_entry = serde_json::to_string(&entry)?;
+        lines.push(&new_entry);
 
+        let mut data = lines.join("\n");
+        data.push('\n');
+
+        replace_file(path, data.as_bytes(), Self::file_create_options()?, false)
+    }
+
+    fn file_create_options() -> Result<CreateOptions, Error> {
         let backup_user = pbs_config::backup_user()?;
         let mode = nix::sys::stat::Mode::from_bits_truncate(0o0644);
         // set the correct owner/group/permissions while saving file
         // owner(rw) = backup, group(r)= backup
-        let options = CreateOptions::new()
+        Ok(CreateOptions::new()
             .perm(mode)
             .owner(backup_user.uid)
-            .group(backup_user.gid);
+            .group(backup_user.gid))
+    }
+
+    fn write_state(&mut self) -> Result<(), Error> {
+        let serialized = serde_json::to_string(&self.state)?;
+        let path = get_path(&self.jobtype, &self.jobname);
+
+        replace_file(
+            path,
+            serialized.as_bytes(),
+            Self::file_create_options()?,
+            false,
+        )
+    }
+}
+
+// entries of a history file, skipping lines which cannot be parsed
+fn parse_job_history(content: &str) -> impl Iterator<Item = JobHistoryEntry> + '_ {
+    content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+}
+
+/// Reads the recorded runs of a single job, oldest first.
+pub fn read_single_job_history(
+    jobtype: &str,
+    jobname: &str,
+) -> Result<Vec<JobHistoryEntry>, Error> {
+    Ok(
+        match file_read_optional_string(get_history_path(jobtype, jobname))? {
+            Some(content) => parse_job_history(&content).collect(),
+            None => Vec::new(),
+        },
+    )
+}
+
+/// Reads the recorded runs of all jobs, optionally filtered by job type and job id.
+///
+/// The history files are not locked, entries which cannot be parsed are skipped. The result is
+/// sorted by start time, oldest first.
+pub fn read_job_history(
+    jobtype: Option<&str>,
+    jobname: Option<&str>,
+) -> Result<Vec<JobHistoryEntry>, Error> {
+    if let (Some(jobtype), Some(jobname)) = (jobtype, jobname) {
+        return read_single_job_history(jobtype, jobname);
+    }
+
+    let mut list = Vec::new();
 
-        replace_file(path, serialized.as_bytes(), options, false)
+    let read_dir = match std::fs::read_dir(JOB_STATE_BASEDIR) {
+        Ok(read_dir) => read_dir,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(list),
+        Err(err) => bail!("unable to read job state dir - {err}"),
+    };
+
+    for entry in read_dir {
+        let path = entry?.path();
+        if path.extension() != Some(std::ffi::OsStr::new("history")) {
+            continue;
+        }
+
+        let content = match file_read_optional_string(&path)? {
+            Some(content) => content,
+            None => continue, // removed in the meantime
+        };
+
+        for entry in parse_job_history(&content) {
+            if jobtype.map(|ty| ty != entry.job_type).unwrap_or(false) {
+                break; // all entries of a file belong to the same job
+            }
+            if jobname.map(|name| name != entry.job_id).unwrap_or(false) {
+                break;
+            }
+            list.push(entry);
+        }
     }
+
+    list.sort_unstable_by_key(|entry| entry.starttime);
+
+    Ok(list)
+}
+
+/// Only keep the runs of a history started since `since`, and at most the `limit` most recent
+/// ones, 0 meaning no limit.
+pub fn limit_job_history(list: &mut Vec<JobHistoryEntry>, since: Option<i64>, limit: usize) {
+    if let Some(since) = since {
+        list.retain(|entry| entry.starttime >= since);
+    }
+    if limit > 0 && list.len() > limit {
+        list.drain(..list.len() - limit);
+    }
+}
+
+/// Returns the error class recorded in the job history for the run with `upid`, if it failed.
+pub fn last_run_error_class(
+    jobtype: &str,
+    jobname: &str,
+    upid: Option<&str>,
+) -> Option<JobErrorClass> {
+    let upid = upid?;
+    let path = get_history_path(jobtype, jobname);
+    let content = file_read_optional_string(path).ok()??;
+    let entry: JobHistoryEntry = serde_json::from_str(content.lines().last()?).ok()?;
+    if entry.upid != upid {
+        return None;
+    }
+    entry.error_class
 }
 
 pub fn compute_schedule_status(
@@ -341,3 +546,55 @@ pub fn compute_schedule_status(
 
     Ok(status)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn history_line(starttime: i64) -> String {
+        format!(
+            concat!(
+                r#"{{"job-type":"garbage_collection","job-id":"store1","#,
+                r#""upid":"UPID:test:{starttime}","starttime":{starttime},"#,
+                r#""endtime":{starttime},"status":"ok","state":"OK"}}"#
+            ),
+            starttime = starttime
+        )
+    }
+
+    #[test]
+    fn test_parse_job_history() {
+        let content = format!(
+            "{}\nnot json\n{{\"job-type\":\"incomplete\"}}\n\n{}\n",
+            history_line(10),
+            history_line(20),
+        );
+        let starttimes: Vec<i64> = parse_job_history(&content)
+            .map(|entry| entry.starttime)
+            .collect();
+        assert_eq!(starttimes, [10, 20]);
+    }
+
+    #[test]
+    fn test_limit_job_history() {
+        let content: String = [10, 20, 30, 40]
+            .iter()
+            .map(|starttime| history_line(*starttime) + "\n")
+            .collect();
+        let history: Vec<JobHistoryEntry> = parse_job_history(&content).collect();
+        let starttimes = |since, limit| {
+            let mut list = history.clone();
+            limit_job_history(&mut list, since, limit);
+            list.iter()
+                .map(|entry| entry.starttime)
+                .collect::<Vec<i64>>()
+        };
+
+        assert_eq!(starttimes(None, 0), [10, 20, 30, 40]);
+        assert_eq!(starttimes(None, 2), [30, 40]);
+        assert_eq!(starttimes(None, 10), [10, 20, 30, 40]);
+        assert_eq!(starttimes(Some(20), 0), [20, 30, 40]);
+        assert_eq!(starttimes(Some(20), 1), [40]);
+        assert_eq!(starttimes(Some(50), 1), Vec::<i64>::new());
+    }
+}
@@ -0,0 +1,204 @@
+//! API access for local processes via unix sockets
+//!
+//! The proxy serves the API on [`LOCAL_API_SOCKET_FN`], authenticating every connection as the
+//! auth-id the `local-api-users` node option maps the uid of the connected process to.
+//! Connections from other uids are closed.
+//!
+//! Protected API calls have to be handled by the privileged daemon. Such requests cannot be
+//! forwarded the usual way, as they carry no ticket or token. Instead, the proxy passes them to
+//! the forwarding socket of the privileged daemon, adding the authenticated auth-id as header.
+//! That socket only accepts connections from the backup user, so the header can be trusted.
+
+use std::collections::HashMap;
+use std::os::unix::fs::PermissionsExt;
+
+use anyhow::{bail, format_err, Error};
+use futures::*;
+use hyper::header::HeaderValue;
+use hyper::service::Service;
+use hyper::{Body, Method, Request, Response};
+
+use proxmox_rest_server::{normalize_path_with_components, ApiConfig, RestServer};
+use proxmox_router::RpcEnvironmentType;
+
+use pbs_api_types::Authid;
+
+use crate::server::auth::{check_forwarded_auth, check_local_socket_auth};
+
+pub use pbs_buildcfg::PROXMOX_BACKUP_LOCAL_API_SOCKET_FN as LOCAL_API_SOCKET_FN;
+
+/// Socket of the privileged daemon for protected calls made via the local API socket
+pub const LOCAL_API_FORWARD_SOCKET_FN: &str = concat!(
+    pbs_buildcfg::PROXMOX_BACKUP_RUN_DIR_M!(),
+    "/api-forward.sock"
+);
+
+/// Header holding the auth-id of requests on the forwarding socket
+pub const LOCAL_AUTH_ID_HEADER: &str = "proxmox-backup-local-auth-id";
+
+/// Bind a unix socket at `path` with the permissions `mode`.
+///
+/// Replaces the socket of a previous instance, which keeps serving its open connections.
+pub fn bind_socket(path: &str, mode: u32) -> Result<tokio::net::UnixListener, Error> {
+    match std::fs::remove_file(path) {
+        Ok(()) => (),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+        Err(err) => bail!("unable to remove old socket {path:?} - {err}"),
+    }
+    let listener = tokio::net::UnixListener::bind(path)
+        .map_err(|err| format_err!("unable to bind socket {path:?} - {err}"))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+
+    Ok(listener)
+}
+
+/// Returns whether the API call `path` must be handled by the privileged daemon.
+fn is_protected_request(method: &Method, path: &str) -> bool {
+    let components = match normalize_path_with_components(path) {
+        Ok((_path, components)) => components,
+        Err(_) => return false,
+    };
+    // skip the 'api2' and format components
+    if components.len() < 2 || components[0] != "api2" {
+        return false;
+    }
+    crate::api2::ROUTER
+        .find_method(&components[2..], method.clone(), &mut HashMap::new())
+        .map_or(false, |method| method.protected)
+}
+
+async fn accept_loop<F, R>(listener: tokio::net::UnixListener, name: &'static str, serve: F)
+where
+    F: Fn(tokio::net::UnixStream) -> R,
+    R: Future<Output = Result<(), Error>> + Send + 'static,
+{
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _addr)) => stream,
+            Err(err) => {
+                log::error!("error accepting {name} connection - {err}");
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+        let connection = serve(stream);
+        tokio::spawn(async move {
+            if let Err(err) = connection.await {
+                log::warn!("{name} connection failed - {err}");
+            }
+        });
+    }
+}
+
+/// Serve the API for local processes, used by the proxy.
+pub async fn serve_local_api(listener: tokio::net::UnixListener) {
+    accept_loop(listener, "local API", serve_local_api_connection).await
+}
+
+/// Serve the API on a local socket connection, authenticated as the auth-id configured for the
+/// uid of the connected process.
+async fn serve_local_api_connection(stream: tokio::net::UnixStream) -> Result<(), Error> {
+    let uid = stream.peer_cred()?.uid();
+    let (node_config, _) = crate::config::node::config()?;
+    let auth_id = match node_config.local_api_users()?.remove(&uid) {
+        Some(auth_id) => auth_id,
+        None => bail!("no auth-id configured for uid {uid}"),
+    };
+
+    // the peer is only known per connection, so every connection gets its own server
+    let check_auth_id = auth_id.clone();
+    let config = ApiConfig::new(pbs_buildcfg::JS_DIR, RpcEnvironmentType::PUBLIC)
+        .auth_handler_func(move |_, _| Box::pin(check_local_socket_auth(check_auth_id.clone())))
+        .default_api2_handler(&crate::api2::ROUTER);
+    let mut api_service = RestServer::new(config).call(&stream).await?;
+
+    let service = hyper::service::service_fn(move |req: Request<Body>| {
+        if is_protected_request(req.method(), req.uri().path()) {
+            forward_protected_request(req, auth_id.clone()).boxed()
+        } else {
+            api_service.call(req)
+        }
+    });
+
+    hyper::server::conn::Http::new()
+        .serve_connection(stream, service)
+        .with_upgrades()
+        .await?;
+
+    Ok(())
+}
+
+/// Pass a protected call made via the local API socket on to the privileged daemon.
+async fn forward_protected_request(
+    mut req: Request<Body>,
+    auth_id: Authid,
+) -> Result<Response<Body>, Error> {
+    let stream = tokio::net::UnixStream::connect(LOCAL_API_FORWARD_SOCKET_FN)
+        .await
+        .map_err(|err| format_err!("unable to connect to the privileged daemon - {err}"))?;
+    let (mut sender, connection) = hyper::client::conn::handshake(stream).await?;
+    tokio::spawn(async move {
+        if let Err(err) = connection.await {
+            log::warn!("forwarding local API request failed - {err}");
+        }
+    });
+
+    // replaces a header sent by the client
+    req.headers_mut().insert(
+        LOCAL_AUTH_ID_HEADER,
+        HeaderValue::from_str(&auth_id.to_string())?,
+    );
+
+    Ok(sender.send_request(req).await?)
+}
+
+/// Serve protected calls forwarded by the proxy, used by the privileged daemon.
+pub async fn serve_forwarded_api(listener: tokio::net::UnixListener) {
+    accept_loop(listener, "forwarded local API", serve_forwarded_connection).await
+}
+
+async fn serve_forwarded_connection(stream: tokio::net::UnixStream) -> Result<(), Error> {
+    // only the proxy may pass on the auth-id
+    let uid = stream.peer_cred()?.uid();
+    if uid != pbs_config::backup_user()?.uid.as_raw() {
+        bail!("connection from uid {uid} refused");
+    }
+
+    let config = ApiConfig::new(pbs_buildcfg::JS_DIR, RpcEnvironmentType::PRIVILEGED)
+        .auth_handler_func(|headers, _method| Box::pin(check_forwarded_auth(headers)))
+        .default_api2_handler(&crate::api2::ROUTER);
+    let service = RestServer::new(config).call(&stream).await?;
+
+    hyper::server::conn::Http::new()
+        .serve_connection(stream, service)
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_protected_request() {
+        assert!(is_protected_request(
+            &Method::PUT,
+            "/api2/json/nodes/localhost/dns"
+        ));
+        assert!(!is_protected_request(
+            &Method::GET,
+            "/api2/json/nodes/localhost/dns"
+        ));
+        assert!(is_protected_request(
+            &Method::PUT,
+            "/api2/extjs/nodes/localhost/dns"
+        ));
+        assert!(!is_protected_request(&Method::GET, "/api2/json/version"));
+        assert!(!is_protected_request(&Method::PUT, "/nodes/localhost/dns"));
+        assert!(!is_protected_request(
+            &Method::GET,
+            "/api2/json/no/such/path"
+        ));
+    }
+}
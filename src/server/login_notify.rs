@@ -0,0 +1,196 @@
+//! Detection of logins from previously unseen devices
+//!
+//! The source addresses and user agents of the last logins of every user are kept in a small
+//! state file. If a realm has `login-notify` set, a login from an address and user agent
+//! combination not in this history triggers a notification to the user and/or the notification
+//! system. The first recorded login of a user never notifies, otherwise enabling the option would
+//! notify about every user at once.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use anyhow::{format_err, Error};
+use serde::{Deserialize, Serialize};
+
+use proxmox_sys::fs::{file_read_optional_string, replace_file, CreateOptions};
+
+use pbs_api_types::{
+    AdRealmConfig, LdapRealmConfig, LoginNotify, OpenIdRealmConfig, RealmRef, Userid,
+};
+use pbs_buildcfg::PROXMOX_BACKUP_STATE_DIR_M;
+use pbs_config::open_backup_lockfile;
+
+const LOGIN_HISTORY_FN: &str = concat!(PROXMOX_BACKUP_STATE_DIR_M!(), "/login-history.json");
+const LOGIN_HISTORY_LOCK_FN: &str = concat!(PROXMOX_BACKUP_STATE_DIR_M!(), "/.login-history.lck");
+
+/// Number of devices remembered per user, the least recently used one is dropped first.
+pub const MAX_SEEN_DEVICES: usize = 16;
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct SeenDevice {
+    ip: String,
+    /// Not recorded by older versions, such an entry matches any user agent once.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user_agent: Option<String>,
+    first_seen: i64,
+    last_seen: i64,
+}
+
+/// Returns the login notification setting of `realm`, `None` if disabled.
+pub fn login_notify_mode(realm: &RealmRef) -> Result<Option<LoginNotify>, Error> {
+    match realm.as_str() {
+        "pam" => Ok(crate::config::node::config()?.0.login_notify_pam),
+        "pbs" => Ok(crate::config::node::config()?.0.login_notify_pbs),
+        realm => {
+            let (domains, _digest) = pbs_config::domains::config()?;
+            if let Ok(config) = domains.lookup::<OpenIdRealmConfig>("openid", realm) {
+                Ok(config.login_notify)
+            } else if let Ok(config) = domains.lookup::<LdapRealmConfig>("ldap", realm) {
+                Ok(config.login_notify)
+            } else if let Ok(config) = domains.lookup::<AdRealmConfig>("ad", realm) {
+                Ok(config.login_notify)
+            } else {
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Record a completed login of `userid`, notifying about it if the device is new.
+///
+/// Runs in a blocking task, the login itself is not delayed.
+pub fn check_login(userid: &Userid, ip: Option<IpAddr>, user_agent: Option<String>) {
+    let ip = match ip {
+        Some(ip) => ip.to_canonical(),
+        None => return,
+    };
+    let userid = userid.clone();
+
+    tokio::task::spawn_blocking(move || {
+        if let Err(err) = do_check_login(&userid, ip, user_agent.as_deref()) {
+            log::error!("login notification for '{userid}' failed - {err}");
+        }
+    });
+}
+
+fn do_check_login(userid: &Userid, ip: IpAddr, user_agent: Option<&str>) -> Result<(), Error> {
+    let notify = match login_notify_mode(userid.realm())? {
+        Some(notify) => notify,
+        None => return Ok(()),
+    };
+
+    let ip = ip.to_string();
+    if !record_login(userid, &ip, user_agent)? {
+        return Ok(());
+    }
+
+    log::info!("login of '{userid}' from previously unseen device {ip} ({user_agent:?})");
+    crate::server::send_login_notification(userid, &ip, user_agent, notify)
+}
+
+/// Adds the device to the logins seen of a user, returns true if it is new to a known user.
+fn record_device(seen: &mut Vec<SeenDevice>, ip: &str, user_agent: Option<&str>, now: i64) -> bool {
+    let known_user = !seen.is_empty();
+
+    let entry = seen
+        .iter_mut()
+        .filter(|entry| entry.ip == ip)
+        .find(|entry| entry.user_agent.is_none() || entry.user_agent.as_deref() == user_agent);
+
+    let is_new = match entry {
+        Some(entry) => {
+            if entry.user_agent.is_none() {
+                entry.user_agent = user_agent.map(str::to_string);
+            }
+            entry.last_seen = now;
+            false
+        }
+        None => {
+            seen.push(SeenDevice {
+                ip: ip.to_string(),
+                user_agent: user_agent.map(str::to_string),
+                first_seen: now,
+                last_seen: now,
+            });
+            true
+        }
+    };
+
+    seen.sort_unstable_by_key(|entry| std::cmp::Reverse(entry.last_seen));
+    seen.truncate(MAX_SEEN_DEVICES);
+
+    known_user && is_new
+}
+
+/// Adds the device to the login history of `userid`, returns true if it is new to a known user.
+fn record_login(userid: &Userid, ip: &str, user_agent: Option<&str>) -> Result<bool, Error> {
+    let _lock = open_backup_lockfile(LOGIN_HISTORY_LOCK_FN, None, true)?;
+
+    let mut history: HashMap<String, Vec<SeenDevice>> =
+        match file_read_optional_string(LOGIN_HISTORY_FN)? {
+            Some(content) => serde_json::from_str(&content).unwrap_or_else(|err| {
+                log::warn!("unable to parse {LOGIN_HISTORY_FN:?}, starting over - {err}");
+                HashMap::new()
+            }),
+            None => HashMap::new(),
+        };
+
+    let seen = history.entry(userid.to_string()).or_default();
+    let is_new = record_device(seen, ip, user_agent, proxmox_time::epoch_i64());
+
+    let backup_user = pbs_config::backup_user()?;
+    let options = CreateOptions::new()
+        .perm(nix::sys::stat::Mode::from_bits_truncate(0o0640))
+        .owner(backup_user.uid)
+        .group(backup_user.gid);
+    replace_file(
+        LOGIN_HISTORY_FN,
+        serde_json::to_string(&history)?.as_bytes(),
+        options,
+        false,
+    )
+    .map_err(|err| format_err!("unable to write {LOGIN_HISTORY_FN:?} - {err}"))?;
+
+    Ok(is_new)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record_device() {
+        let mut seen = Vec::new();
+        let firefox = Some("Mozilla/5.0 Firefox/120.0");
+        let client = Some("proxmox-backup-client/3.1");
+
+        // the first login of a user never counts as new
+        assert!(!record_device(&mut seen, "192.0.2.1", firefox, 1));
+        assert!(!record_device(&mut seen, "192.0.2.1", firefox, 2));
+
+        // a new address or a new user agent on a known address are new devices
+        assert!(record_device(&mut seen, "192.0.2.2", firefox, 3));
+        assert!(record_device(&mut seen, "192.0.2.1", client, 4));
+        assert!(!record_device(&mut seen, "192.0.2.1", client, 5));
+        assert_eq!(seen.len(), 3);
+
+        // entries of older versions without user agent take the first one seen
+        let mut seen = vec![SeenDevice {
+            ip: "192.0.2.1".to_string(),
+            user_agent: None,
+            first_seen: 1,
+            last_seen: 1,
+        }];
+        assert!(!record_device(&mut seen, "192.0.2.1", firefox, 2));
+        assert_eq!(seen[0].user_agent.as_deref(), firefox);
+        assert!(record_device(&mut seen, "192.0.2.1", client, 3));
+
+        // the least recently used devices are dropped
+        for i in 0..(MAX_SEEN_DEVICES as i64 + 4) {
+            record_device(&mut seen, &format!("198.51.100.{i}"), firefox, 10 + i);
+        }
+        assert_eq!(seen.len(), MAX_SEEN_DEVICES);
+        assert_eq!(seen[0].ip, format!("198.51.100.{}", MAX_SEEN_DEVICES + 3));
+    }
+}
@@ -13,6 +13,12 @@ use pbs_buildcfg;
 
 pub mod jobstate;
 
+pub mod job_error;
+
+pub mod schedule;
+
+pub mod datastore_window;
+
 mod verify_job;
 pub use verify_job::*;
 
@@ -22,6 +28,18 @@ pub use prune_job::*;
 mod gc_job;
 pub use gc_job::*;
 
+mod archive_job;
+pub use archive_job::*;
+
+mod warm_up_job;
+pub use warm_up_job::*;
+
+pub mod space_watermark;
+
+pub mod restore_accounting;
+
+pub mod restore_link;
+
 mod realm_sync_job;
 pub use realm_sync_job::*;
 
@@ -31,8 +49,31 @@ pub use notifications::*;
 mod report;
 pub use report::*;
 
+mod health_check;
+pub use health_check::*;
+
 pub mod auth;
 
+pub mod auth_last_used;
+
+pub mod local_api;
+
+pub mod api_stats;
+
+pub mod status_page;
+
+pub mod config_watch;
+
+pub mod task_log;
+
+pub mod login_notify;
+
+pub mod namespace_provision;
+
+pub mod read_through;
+
+pub mod warm_reader;
+
 pub(crate) mod pull;
 
 pub(crate) async fn reload_proxy_certificate() -> Result<(), Error> {
@@ -0,0 +1,219 @@
+//! Provisioning of personal namespaces for users of a realm
+//!
+//! Realms with `namespace-provision` set get a namespace created from the configured path
+//! template on the first completed login of one of their users. The user is granted the
+//! configured role on it, so self-service users can start backing up right away.
+//!
+//! Provisioned namespaces are recorded in a state file, so a namespace removed by an
+//! administrator is not created again on the next login.
+
+use std::collections::HashMap;
+
+use anyhow::{format_err, Error};
+use serde::{Deserialize, Serialize};
+
+use proxmox_schema::ApiType;
+use proxmox_sys::fs::{file_read_optional_string, replace_file, CreateOptions};
+
+use pbs_api_types::{
+    AdRealmConfig, Authid, BackupNamespace, LdapRealmConfig, NamespaceProvision, OpenIdRealmConfig,
+    Operation, RealmRef, Userid,
+};
+use pbs_buildcfg::PROXMOX_BACKUP_STATE_DIR_M;
+use pbs_config::open_backup_lockfile;
+use pbs_datastore::DataStore;
+
+const PROVISIONED_FN: &str = concat!(PROXMOX_BACKUP_STATE_DIR_M!(), "/namespace-provisioned.json");
+const PROVISIONED_LOCK_FN: &str =
+    concat!(PROXMOX_BACKUP_STATE_DIR_M!(), "/.namespace-provisioned.lck");
+
+/// Role granted on a provisioned namespace if the realm does not configure one.
+pub const DEFAULT_PROVISION_ROLE: &str = "DatastoreBackup";
+
+/// Returns the namespace provisioning settings of `realm`, if any.
+pub fn realm_namespace_provision(realm: &RealmRef) -> Result<Option<NamespaceProvision>, Error> {
+    let (domains, _digest) = pbs_config::domains::config()?;
+
+    let provision =
+        if let Ok(config) = domains.lookup::<OpenIdRealmConfig>("openid", realm.as_str()) {
+            config.namespace_provision
+        } else if let Ok(config) = domains.lookup::<LdapRealmConfig>("ldap", realm.as_str()) {
+            config.namespace_provision
+        } else if let Ok(config) = domains.lookup::<AdRealmConfig>("ad", realm.as_str()) {
+            config.namespace_provision
+        } else {
+            None
+        };
+
+    match provision {
+        Some(provision) => {
+            let value = NamespaceProvision::API_SCHEMA.parse_property_string(&provision)?;
+            Ok(Some(serde_json::from_value(value)?))
+        }
+        None => Ok(None),
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct ProvisionedNamespace {
+    store: String,
+    ns: String,
+    time: i64,
+}
+
+/// Expand the namespace path template of a realm for `userid`.
+fn expand_template(template: &str, userid: &Userid) -> Result<BackupNamespace, Error> {
+    let path = template
+        .replace("{user}", userid.name().as_str())
+        .replace("{realm}", userid.realm().as_str());
+    BackupNamespace::new(&path)
+        .map_err(|err| format_err!("invalid namespace '{path}' for '{userid}' - {err}"))
+}
+
+/// Returns whether `ns` on `store` was already provisioned for the user with `history`.
+fn is_provisioned(history: &[ProvisionedNamespace], store: &str, ns: &BackupNamespace) -> bool {
+    let ns = ns.to_string();
+    history
+        .iter()
+        .any(|entry| entry.store == store && entry.ns == ns)
+}
+
+fn read_provisioned() -> Result<HashMap<String, Vec<ProvisionedNamespace>>, Error> {
+    Ok(match file_read_optional_string(PROVISIONED_FN)? {
+        Some(content) => serde_json::from_str(&content)
+            .map_err(|err| format_err!("unable to parse {PROVISIONED_FN:?} - {err}"))?,
+        None => HashMap::new(),
+    })
+}
+
+fn write_provisioned(
+    provisioned: &HashMap<String, Vec<ProvisionedNamespace>>,
+) -> Result<(), Error> {
+    let backup_user = pbs_config::backup_user()?;
+    let options = CreateOptions::new()
+        .perm(nix::sys::stat::Mode::from_bits_truncate(0o0640))
+        .owner(backup_user.uid)
+        .group(backup_user.gid);
+    replace_file(
+        PROVISIONED_FN,
+        serde_json::to_string(provisioned)?.as_bytes(),
+        options,
+        false,
+    )
+    .map_err(|err| format_err!("unable to write {PROVISIONED_FN:?} - {err}"))
+}
+
+/// Create the personal namespace of `userid` on its first completed login if its realm is
+/// configured to do so, then grant the configured role on it.
+///
+/// A namespace that already exists is only recorded as provisioned, without changing its
+/// permissions.
+pub fn provision_user_namespace(userid: &Userid) -> Result<(), Error> {
+    let provision = match realm_namespace_provision(userid.realm())? {
+        Some(provision) => provision,
+        None => return Ok(()),
+    };
+
+    let ns = expand_template(&provision.ns, userid)?;
+
+    let _lock = open_backup_lockfile(PROVISIONED_LOCK_FN, None, true)?;
+    let mut provisioned = read_provisioned()?;
+    if let Some(history) = provisioned.get(userid.as_str()) {
+        if is_provisioned(history, &provision.store, &ns) {
+            return Ok(());
+        }
+    }
+
+    let datastore = DataStore::lookup_datastore(&provision.store, Some(Operation::Write))?;
+    if !datastore.namespace_exists(&ns) {
+        create_namespace(&datastore, &provision, userid, &ns)?;
+    }
+
+    provisioned
+        .entry(userid.to_string())
+        .or_default()
+        .push(ProvisionedNamespace {
+            store: provision.store.clone(),
+            ns: ns.to_string(),
+            time: proxmox_time::epoch_i64(),
+        });
+    write_provisioned(&provisioned)
+}
+
+fn create_namespace(
+    datastore: &DataStore,
+    provision: &NamespaceProvision,
+    userid: &Userid,
+    ns: &BackupNamespace,
+) -> Result<(), Error> {
+    // logins are handled by the privileged daemon, the namespaces must belong to the backup user
+    let backup_user = pbs_config::backup_user()?;
+    for created in datastore.create_namespace_recursive(ns)? {
+        nix::unistd::chown(
+            &datastore.namespace_path(&created),
+            Some(backup_user.uid),
+            Some(backup_user.gid),
+        )?;
+    }
+
+    let role = provision.role.as_deref().unwrap_or(DEFAULT_PROVISION_ROLE);
+    let acl_path = format!("/{}", ns.acl_path(&provision.store).join("/"));
+
+    let _lock = pbs_config::acl::lock_config()?;
+    let (mut tree, _digest) = pbs_config::acl::config()?;
+    tree.insert_user_role(&acl_path, &Authid::from(userid.clone()), role, true);
+    pbs_config::acl::save_config(&tree)?;
+
+    log::info!(
+        "provisioned namespace '{ns}' on datastore '{}' for '{userid}' with role {role}",
+        provision.store
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_expand_template() -> Result<(), Error> {
+        let userid: Userid = "jane@sso".parse()?;
+        assert_eq!(
+            expand_template("users/{user}", &userid)?,
+            BackupNamespace::new("users/jane")?
+        );
+        assert_eq!(
+            expand_template("{realm}/{user}", &userid)?,
+            BackupNamespace::new("sso/jane")?
+        );
+
+        // namespace components are limited to 32 characters
+        let userid: Userid = "jane.doe.with.a.very.long.user.name@sso".parse()?;
+        assert!(expand_template("users/{user}", &userid).is_err());
+
+        assert!(expand_template("a/b/c/d/e/f/g/{user}", &userid).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_provisioned() -> Result<(), Error> {
+        let ns = BackupNamespace::new("users/jane")?;
+        let history = vec![ProvisionedNamespace {
+            store: "store1".to_string(),
+            ns: ns.to_string(),
+            time: 0,
+        }];
+
+        assert!(!is_provisioned(&[], "store1", &ns));
+        assert!(is_provisioned(&history, "store1", &ns));
+        assert!(!is_provisioned(&history, "store2", &ns));
+        assert!(!is_provisioned(
+            &history,
+            "store1",
+            &BackupNamespace::new("users/john")?
+        ));
+        Ok(())
+    }
+}
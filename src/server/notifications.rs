@@ -1,22 +1,28 @@
 use std::collections::HashMap;
+use std::io::Write;
 use std::path::Path;
+use std::process::{Command, Stdio};
 use std::time::{Duration, Instant};
 
-use anyhow::Error;
+use anyhow::{bail, format_err, Error};
 use const_format::concatcp;
 use nix::unistd::Uid;
-use serde_json::json;
+use serde_json::{json, Value};
 
 use proxmox_notify::context::pbs::PBS_CONTEXT;
 use proxmox_schema::ApiType;
 use proxmox_sys::fs::{create_path, CreateOptions};
 
+use crate::config::node::NodeConfig;
+use crate::server::job_error::classify_error;
 use crate::tape::TapeNotificationMode;
 use pbs_api_types::{
-    APTUpdateInfo, DataStoreConfig, DatastoreNotify, GarbageCollectionStatus, NotificationMode,
-    Notify, SyncJobConfig, TapeBackupJobSetup, User, Userid, VerificationJobConfig,
+    APTUpdateInfo, DataStoreConfig, DatastoreNotify, GarbageCollectionStatus, JobErrorClass,
+    LoginNotify, NotificationMode, Notify, SyncJobConfig, TapeBackupJobSetup, User, Userid,
+    VerificationJobConfig,
 };
 use proxmox_notify::endpoints::sendmail::{SendmailConfig, SendmailEndpoint};
+use proxmox_notify::renderer::{render_template, TemplateType};
 use proxmox_notify::{Endpoint, Notification, Severity};
 
 const SPOOL_DIR: &str = concatcp!(pbs_buildcfg::PROXMOX_BACKUP_STATE_DIR, "/notifications");
@@ -98,6 +104,32 @@ pub async fn notification_worker() {
     }
 }
 
+// add the node wide footer and subject tag to the template data
+fn add_node_template_data(node_config: &NodeConfig, data: &mut Value) {
+    if let Some(tag) = node_config.email_subject_tag() {
+        data["subject-tag"] = tag.into();
+    }
+    if let Some(footer) = &node_config.email_footer {
+        data["footer"] = footer.as_str().into();
+    }
+}
+
+/// Create a notification from a template, adding the node wide footer and subject tag to the
+/// template data.
+fn notification_from_template(
+    severity: Severity,
+    template: &str,
+    mut data: Value,
+    metadata: HashMap<String, String>,
+) -> Notification {
+    match crate::config::node::config() {
+        Ok((node_config, _digest)) => add_node_template_data(&node_config, &mut data),
+        Err(err) => log::error!("could not read node config for notification - {err}"),
+    }
+
+    Notification::from_template(severity, template, data, metadata)
+}
+
 fn send_notification(notification: Notification) -> Result<(), Error> {
     if nix::unistd::ROOT == Uid::current() {
         let config = pbs_config::notifications::config()?;
@@ -117,7 +149,82 @@ fn send_notification(notification: Notification) -> Result<(), Error> {
     Ok(())
 }
 
-fn send_sendmail_legacy_notification(notification: Notification, email: &str) -> Result<(), Error> {
+// format a plain text mail, encoding the subject if it is not plain ASCII
+fn format_mail(
+    mailto: &str,
+    subject: &str,
+    body: &str,
+    mailfrom: &str,
+    reply_to: &str,
+    date: &str,
+) -> String {
+    let subject = if subject.is_ascii() {
+        subject.to_string()
+    } else {
+        format!("=?utf-8?B?{}?=", base64::encode(subject))
+    };
+    let author = format!("Proxmox Backup Server - {}", proxmox_sys::nodename());
+
+    let mut mail = String::new();
+    mail.push_str(&format!("From: {author} <{mailfrom}>\n"));
+    mail.push_str(&format!("To: {mailto}\n"));
+    mail.push_str(&format!("Reply-To: {reply_to}\n"));
+    mail.push_str(&format!("Subject: {subject}\n"));
+    mail.push_str(&format!("Date: {date}\n"));
+    mail.push_str("Auto-Submitted: auto-generated;\n");
+    mail.push_str("MIME-Version: 1.0\n");
+    mail.push_str("Content-Type: text/plain; charset=\"UTF-8\"\n");
+    mail.push_str("Content-Transfer-Encoding: 8bit\n\n");
+    mail.push_str(body);
+    mail
+}
+
+// the sendmail endpoint cannot set additional headers, so mails with a Reply-To are composed and
+// passed to sendmail here
+fn send_mail_with_reply_to(
+    node_config: &NodeConfig,
+    template: &str,
+    data: &Value,
+    email: &str,
+    reply_to: &str,
+) -> Result<(), Error> {
+    let mut data = data.clone();
+    add_node_template_data(node_config, &mut data);
+
+    let subject = render_template(TemplateType::Subject, template, &data)?;
+    let body = render_template(TemplateType::PlaintextBody, template, &data)?;
+    let mailfrom = node_config.email_from.as_deref().unwrap_or("root");
+    let date = proxmox_time::strftime_local("%a, %d %b %Y %T %z", proxmox_time::epoch_i64())?;
+    let mail = format_mail(email, subject.trim(), &body, mailfrom, reply_to, &date);
+
+    let mut sendmail = Command::new("/usr/sbin/sendmail")
+        .args(["-B", "8BITMIME", "-f", mailfrom, "--", email])
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|err| format_err!("could not spawn sendmail - {err}"))?;
+    if let Some(mut stdin) = sendmail.stdin.take() {
+        stdin.write_all(mail.as_bytes())?;
+    }
+    let status = sendmail.wait()?;
+    if !status.success() {
+        bail!("sendmail failed - {status}");
+    }
+
+    Ok(())
+}
+
+fn send_sendmail_legacy_notification(
+    notification: Notification,
+    template: &str,
+    data: &Value,
+    email: &str,
+) -> Result<(), Error> {
+    if let Ok((node_config, _digest)) = crate::config::node::config() {
+        if let Some(reply_to) = node_config.email_reply_to.as_deref() {
+            return send_mail_with_reply_to(&node_config, template, data, email, reply_to);
+        }
+    }
+
     let endpoint = SendmailEndpoint {
         config: SendmailConfig {
             mailto: vec![email.into()],
@@ -177,7 +284,7 @@ pub fn send_gc_status(
         ("type".into(), "gc".into()),
     ]);
 
-    let notification = Notification::from_template(severity, template, data, metadata);
+    let notification = notification_from_template(severity, template, data.clone(), metadata);
 
     let (email, notify, mode) = lookup_datastore_notify_settings(datastore);
     match mode {
@@ -189,7 +296,7 @@ pub fn send_gc_status(
             }
 
             if let Some(email) = email {
-                send_sendmail_legacy_notification(notification, &email)?;
+                send_sendmail_legacy_notification(notification, template, &data, &email)?;
             }
         }
         NotificationMode::NotificationSystem => {
@@ -200,6 +307,13 @@ pub fn send_gc_status(
     Ok(())
 }
 
+// allows matching on the error class of failed jobs
+fn insert_error_class(data: &Value, metadata: &mut HashMap<String, String>) {
+    if let Some(class) = data["error-class"].as_str() {
+        metadata.insert("error-class".into(), class.into());
+    }
+}
+
 pub fn send_verify_status(
     job: VerificationJobConfig,
     result: &Result<Vec<String>, Error>,
@@ -215,6 +329,7 @@ pub fn send_verify_status(
         Ok(errors) if errors.is_empty() => ("verify-ok", Severity::Info),
         Ok(errors) => {
             data["errors"] = json!(errors);
+            data["error-class"] = JobErrorClass::DataCorruption.to_string().into();
             ("verify-err", Severity::Error)
         }
         Err(_) => {
@@ -223,14 +338,15 @@ pub fn send_verify_status(
         }
     };
 
-    let metadata = HashMap::from([
+    let mut metadata = HashMap::from([
         ("job-id".into(), job.id.clone()),
         ("datastore".into(), job.store.clone()),
         ("hostname".into(), proxmox_sys::nodename().into()),
         ("type".into(), "verify".into()),
     ]);
+    insert_error_class(&data, &mut metadata);
 
-    let notification = Notification::from_template(severity, template, data, metadata);
+    let notification = notification_from_template(severity, template, data.clone(), metadata);
 
     let (email, notify, mode) = lookup_datastore_notify_settings(&job.store);
     match mode {
@@ -242,7 +358,7 @@ pub fn send_verify_status(
             }
 
             if let Some(email) = email {
-                send_sendmail_legacy_notification(notification, &email)?;
+                send_sendmail_legacy_notification(notification, template, &data, &email)?;
             }
         }
         NotificationMode::NotificationSystem => {
@@ -270,18 +386,20 @@ pub fn send_prune_status(
         Ok(()) => ("prune-ok", Severity::Info),
         Err(err) => {
             data["error"] = err.to_string().into();
+            data["error-class"] = classify_error(err).to_string().into();
             ("prune-err", Severity::Error)
         }
     };
 
-    let metadata = HashMap::from([
+    let mut metadata = HashMap::from([
         ("job-id".into(), jobname.to_string()),
         ("datastore".into(), store.into()),
         ("hostname".into(), proxmox_sys::nodename().into()),
         ("type".into(), "prune".into()),
     ]);
+    insert_error_class(&data, &mut metadata);
 
-    let notification = Notification::from_template(severity, template, data, metadata);
+    let notification = notification_from_template(severity, template, data.clone(), metadata);
 
     let (email, notify, mode) = lookup_datastore_notify_settings(store);
     match mode {
@@ -293,7 +411,7 @@ pub fn send_prune_status(
             }
 
             if let Some(email) = email {
-                send_sendmail_legacy_notification(notification, &email)?;
+                send_sendmail_legacy_notification(notification, template, &data, &email)?;
             }
         }
         NotificationMode::NotificationSystem => {
@@ -304,30 +422,37 @@ pub fn send_prune_status(
     Ok(())
 }
 
-pub fn send_sync_status(job: &SyncJobConfig, result: &Result<(), Error>) -> Result<(), Error> {
+pub fn send_sync_status(
+    job: &SyncJobConfig,
+    result: &Result<(), Error>,
+    verified: Option<usize>,
+) -> Result<(), Error> {
     let (fqdn, port) = get_server_url();
     let mut data = json!({
         "job": job,
         "fqdn": fqdn,
         "port": port,
+        "verified": verified,
     });
 
     let (template, severity) = match result {
         Ok(()) => ("sync-ok", Severity::Info),
         Err(err) => {
             data["error"] = err.to_string().into();
+            data["error-class"] = classify_error(err).to_string().into();
             ("sync-err", Severity::Error)
         }
     };
 
-    let metadata = HashMap::from([
+    let mut metadata = HashMap::from([
         ("job-id".into(), job.id.clone()),
         ("datastore".into(), job.store.clone()),
         ("hostname".into(), proxmox_sys::nodename().into()),
         ("type".into(), "sync".into()),
     ]);
+    insert_error_class(&data, &mut metadata);
 
-    let notification = Notification::from_template(severity, template, data, metadata);
+    let notification = notification_from_template(severity, template, data.clone(), metadata);
 
     let (email, notify, mode) = lookup_datastore_notify_settings(&job.store);
     match mode {
@@ -339,7 +464,7 @@ pub fn send_sync_status(job: &SyncJobConfig, result: &Result<(), Error>) -> Resu
             }
 
             if let Some(email) = email {
-                send_sendmail_legacy_notification(notification, &email)?;
+                send_sendmail_legacy_notification(notification, template, &data, &email)?;
             }
         }
         NotificationMode::NotificationSystem => {
@@ -372,6 +497,7 @@ pub fn send_tape_backup_status(
         Ok(()) => ("tape-backup-ok", Severity::Info),
         Err(err) => {
             data["error"] = err.to_string().into();
+            data["error-class"] = classify_error(err).to_string().into();
             ("tape-backup-err", Severity::Error)
         }
     };
@@ -386,8 +512,9 @@ pub fn send_tape_backup_status(
     if let Some(id) = id {
         metadata.insert("job-id".into(), id.into());
     }
+    insert_error_class(&data, &mut metadata);
 
-    let notification = Notification::from_template(severity, template, data, metadata);
+    let notification = notification_from_template(severity, template, data.clone(), metadata);
 
     let mode = TapeNotificationMode::from(job);
 
@@ -396,7 +523,7 @@ pub fn send_tape_backup_status(
             let email = lookup_user_email(notify_user);
 
             if let Some(email) = email {
-                send_sendmail_legacy_notification(notification, &email)?;
+                send_sendmail_legacy_notification(notification, template, &data, &email)?;
             }
         }
         TapeNotificationMode::NotificationSystem => {
@@ -429,14 +556,16 @@ pub fn send_load_media_notification(
         ("hostname".into(), proxmox_sys::nodename().into()),
         ("type".into(), "tape-load".into()),
     ]);
-    let notification = Notification::from_template(Severity::Notice, "tape-load", data, metadata);
+    let template = "tape-load";
+    let notification =
+        notification_from_template(Severity::Notice, template, data.clone(), metadata);
 
     match mode {
         TapeNotificationMode::LegacySendmail { notify_user } => {
             let email = lookup_user_email(notify_user);
 
             if let Some(email) = email {
-                send_sendmail_legacy_notification(notification, &email)?;
+                send_sendmail_legacy_notification(notification, template, &data, &email)?;
             }
         }
         TapeNotificationMode::NotificationSystem => {
@@ -448,8 +577,6 @@ pub fn send_load_media_notification(
 }
 
 fn get_server_url() -> (String, usize) {
-    // user will surely request that they can change this
-
     let nodename = proxmox_sys::nodename();
     let mut fqdn = nodename.to_owned();
 
@@ -460,7 +587,8 @@ fn get_server_url() -> (String, usize) {
         }
     }
 
-    let port = 8007;
+    let port = crate::config::node::proxy_port().unwrap_or(crate::config::node::DEFAULT_PROXY_PORT)
+        as usize;
 
     (fqdn, port)
 }
@@ -482,7 +610,7 @@ pub fn send_updates_available(updates: &[&APTUpdateInfo]) -> Result<(), Error> {
     ]);
 
     let notification =
-        Notification::from_template(Severity::Info, "package-updates", data, metadata);
+        notification_from_template(Severity::Info, "package-updates", data, metadata);
 
     send_notification(notification)?;
     Ok(())
@@ -508,12 +636,80 @@ pub fn send_certificate_renewal_mail(result: &Result<(), Error>) -> Result<(), E
         ("type".into(), "acme".into()),
     ]);
 
-    let notification = Notification::from_template(Severity::Info, "acme-err", data, metadata);
+    let notification = notification_from_template(Severity::Info, "acme-err", data, metadata);
 
     send_notification(notification)?;
     Ok(())
 }
 
+/// Send the warnings found by the daily health check.
+pub fn send_health_check_warnings(warnings: &[String]) -> Result<(), Error> {
+    let (fqdn, port) = get_server_url();
+    let hostname = proxmox_sys::nodename().to_string();
+
+    let data = json!({
+        "fqdn": fqdn,
+        "hostname": &hostname,
+        "port": port,
+        "warnings": warnings,
+    });
+
+    let metadata = HashMap::from([
+        ("hostname".into(), hostname),
+        ("type".into(), "health-check".into()),
+    ]);
+
+    let notification =
+        notification_from_template(Severity::Warning, "health-check", data, metadata);
+
+    send_notification(notification)?;
+    Ok(())
+}
+
+/// Notify about a login of `userid` from a previously unseen address.
+pub fn send_login_notification(
+    userid: &Userid,
+    ip: &str,
+    user_agent: Option<&str>,
+    notify: LoginNotify,
+) -> Result<(), Error> {
+    let (fqdn, port) = get_server_url();
+    let hostname = proxmox_sys::nodename().to_string();
+
+    let data = json!({
+        "fqdn": fqdn,
+        "hostname": &hostname,
+        "port": port,
+        "userid": userid,
+        "ip": ip,
+        "user-agent": user_agent.unwrap_or("unknown"),
+        "time": proxmox_time::epoch_to_rfc3339(proxmox_time::epoch_i64())?,
+    });
+
+    let metadata = HashMap::from([
+        ("hostname".into(), hostname),
+        ("type".into(), "login".into()),
+        ("user".into(), userid.to_string()),
+    ]);
+
+    let notification =
+        || notification_from_template(Severity::Notice, "login", data.clone(), metadata.clone());
+
+    if matches!(notify, LoginNotify::User | LoginNotify::All) {
+        match lookup_user_email(userid) {
+            Some(email) => {
+                send_sendmail_legacy_notification(notification(), "login", &data, &email)?
+            }
+            None => log::info!("not notifying '{userid}' about new login - no email address"),
+        }
+    }
+    if matches!(notify, LoginNotify::Admin | LoginNotify::All) {
+        send_notification(notification())?;
+    }
+
+    Ok(())
+}
+
 /// Lookup users email address
 pub fn lookup_user_email(userid: &Userid) -> Option<String> {
     if let Ok(user_config) = pbs_config::user::cached_config() {
@@ -564,3 +760,47 @@ pub fn lookup_datastore_notify_settings(
 
     (email, notify, notification_mode)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format_mail() {
+        let mail = format_mail(
+            "admin@example.com",
+            "Sync failed",
+            "body\n",
+            "root",
+            "support@example.com",
+            "Thu, 15 Oct 2026 10:00:00 +0000",
+        );
+        let (headers, body) = mail.split_once("\n\n").unwrap();
+        assert_eq!(body, "body\n");
+        assert!(headers.contains("\nTo: admin@example.com\n"));
+        assert!(headers.contains("\nReply-To: support@example.com\n"));
+        assert!(headers.contains("\nSubject: Sync failed\n"));
+        assert!(headers.ends_with("Content-Transfer-Encoding: 8bit"));
+
+        let mail = format_mail("a@example.com", "Grüße", "", "root", "b@example.com", "");
+        assert!(mail.contains("\nSubject: =?utf-8?B?R3LDvMOfZQ==?=\n"));
+    }
+
+    #[test]
+    fn test_node_template_data() {
+        let node_config = NodeConfig {
+            email_subject_tag: Some("site-a".to_string()),
+            email_footer: Some("footer text".to_string()),
+            ..Default::default()
+        };
+        let mut data = json!({ "fqdn": "pbs.example.com" });
+        add_node_template_data(&node_config, &mut data);
+        assert_eq!(data["subject-tag"], "site-a");
+        assert_eq!(data["footer"], "footer text");
+
+        let mut data = json!({});
+        add_node_template_data(&NodeConfig::default(), &mut data);
+        assert!(data.get("subject-tag").is_none());
+        assert!(data.get("footer").is_none());
+    }
+}
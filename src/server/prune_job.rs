@@ -13,7 +13,8 @@ use pbs_datastore::DataStore;
 use proxmox_rest_server::WorkerTask;
 
 use crate::backup::ListAccessibleBackupGroups;
-use crate::server::jobstate::Job;
+use crate::server::job_error::{classify_error, parse_retry_policy, run_with_retry};
+use crate::server::jobstate::{Job, JobRunStatistics};
 
 pub fn prune_datastore(
     worker: Arc<WorkerTask>,
@@ -129,9 +130,11 @@ pub fn do_prune_job(
     mut job: Job,
     prune_options: PruneJobOptions,
     store: String,
+    retry: Option<String>,
     auth_id: &Authid,
     schedule: Option<String>,
 ) -> Result<String, Error> {
+    let retry = parse_retry_policy(retry.as_deref())?;
     let datastore = DataStore::lookup_datastore(&store, Some(Operation::Write))?;
 
     let worker_type = job.jobtype().to_string();
@@ -156,11 +159,28 @@ pub fn do_prune_job(
                 task_log!(worker, "task triggered by schedule '{event_str}'");
             }
 
-            let result = prune_datastore(worker.clone(), auth_id, prune_options, datastore, false);
+            let result = run_with_retry(&worker, &retry, || {
+                prune_datastore(
+                    worker.clone(),
+                    auth_id.clone(),
+                    prune_options.clone(),
+                    datastore.clone(),
+                    false,
+                )
+            });
 
             let status = worker.create_state(&result);
+            let error_class = result.as_ref().err().map(classify_error);
+            if let Some(error_class) = error_class {
+                task_log!(worker, "error class: {error_class}");
+            }
+            let statistics = JobRunStatistics {
+                store: Some(store.clone()),
+                error_class,
+                ..Default::default()
+            };
 
-            if let Err(err) = job.finish(status) {
+            if let Err(err) = job.finish_with_statistics(status, statistics) {
                 eprintln!("could not finish job state for {}: {err}", job.jobtype());
             }
 
@@ -16,13 +16,13 @@ use proxmox_sys::{task_log, task_warn};
 use serde_json::json;
 
 use pbs_api_types::{
-    print_store_and_ns, Authid, BackupDir, BackupGroup, BackupNamespace, CryptMode, GroupFilter,
-    GroupListItem, Operation, RateLimitConfig, Remote, SnapshotListItem, MAX_NAMESPACE_DEPTH,
-    PRIV_DATASTORE_AUDIT, PRIV_DATASTORE_BACKUP, PRIV_DATASTORE_READ,
+    print_ns_and_snapshot, print_store_and_ns, Authid, BackupDir, BackupGroup, BackupNamespace,
+    CryptMode, GroupFilter, GroupListItem, Operation, RateLimitConfig, Remote, SnapshotListItem,
+    MAX_NAMESPACE_DEPTH, PRIV_DATASTORE_AUDIT, PRIV_DATASTORE_BACKUP, PRIV_DATASTORE_READ,
 };
 use pbs_client::{BackupReader, BackupRepository, HttpClient, RemoteChunkReader};
 use pbs_config::CachedUserInfo;
-use pbs_datastore::data_blob::DataBlob;
+use pbs_datastore::data_blob::{digest_salt_fingerprint, DataBlob};
 use pbs_datastore::dynamic_index::DynamicIndexReader;
 use pbs_datastore::fixed_index::FixedIndexReader;
 use pbs_datastore::index::IndexFile;
@@ -35,7 +35,11 @@ use pbs_datastore::{
 };
 use pbs_tools::sha::sha256;
 
-use crate::backup::{check_ns_modification_privs, check_ns_privs, ListAccessibleBackupGroups};
+use crate::backup::seed::SEEDED_MARKER_FILE_NAME;
+use crate::backup::{
+    check_ns_modification_privs, check_ns_privs, verify_backup_dir, ListAccessibleBackupGroups,
+    VerifyWorker,
+};
 use crate::tools::parallel_handler::ParallelHandler;
 
 struct RemoteReader {
@@ -84,8 +88,11 @@ impl RemovedVanishedStats {
 pub(crate) struct PullStats {
     pub(crate) chunk_count: usize,
     pub(crate) bytes: usize,
+    pub(crate) snapshot_count: usize,
     pub(crate) elapsed: Duration,
     pub(crate) removed: Option<RemovedVanishedStats>,
+    /// Snapshots which did not exist locally before
+    pub(crate) new_snapshots: Vec<pbs_datastore::BackupDir>,
 }
 
 impl From<RemovedVanishedStats> for PullStats {
@@ -101,7 +108,9 @@ impl PullStats {
     fn add(&mut self, rhs: PullStats) {
         self.chunk_count += rhs.chunk_count;
         self.bytes += rhs.bytes;
+        self.snapshot_count += rhs.snapshot_count;
         self.elapsed += rhs.elapsed;
+        self.new_snapshots.extend(rhs.new_snapshots);
 
         if let Some(rhs_removed) = rhs.removed {
             if let Some(ref mut removed) = self.removed {
@@ -396,6 +405,9 @@ trait PullReader: Send + Sync {
     ) -> Result<(), Error>;
 
     fn skip_chunk_sync(&self, target_store_name: &str) -> bool;
+
+    /// Returns the digest salt of the source datastore.
+    fn digest_salt(&self) -> Option<&[u8; 32]>;
 }
 
 #[async_trait::async_trait]
@@ -478,6 +490,10 @@ impl PullReader for RemoteReader {
     fn skip_chunk_sync(&self, _target_store_name: &str) -> bool {
         false
     }
+
+    fn digest_salt(&self) -> Option<&[u8; 32]> {
+        self.backup_reader.digest_salt()
+    }
 }
 
 #[async_trait::async_trait]
@@ -520,6 +536,10 @@ impl PullReader for LocalReader {
     fn skip_chunk_sync(&self, target_store_name: &str) -> bool {
         self.datastore.name() == target_store_name
     }
+
+    fn digest_salt(&self) -> Option<&[u8; 32]> {
+        self.datastore.digest_salt()
+    }
 }
 
 /// Parameters for a pull operation.
@@ -561,6 +581,11 @@ impl PullParameters {
         };
         let remove_vanished = remove_vanished.unwrap_or(false);
 
+        let target = PullTarget {
+            store: DataStore::lookup_datastore(store, Some(Operation::Write))?,
+            ns,
+        };
+
         let source: Arc<dyn PullSource> = if let Some(remote) = remote {
             let (remote_config, _digest) = pbs_config::remote::config()?;
             let remote: Remote = remote_config.lookup("remote", remote)?;
@@ -578,15 +603,18 @@ impl PullParameters {
                 client,
             })
         } else {
+            let source_store = DataStore::lookup_datastore(remote_store, Some(Operation::Read))?;
+            // remote datastores are only checked once a reader session is started, as their
+            // digest salt is not known before
+            target.store.check_digest_salt(
+                &format!("datastore '{remote_store}'"),
+                source_store.digest_salt_fingerprint(),
+            )?;
             Arc::new(LocalSource {
-                store: DataStore::lookup_datastore(remote_store, Some(Operation::Read))?,
+                store: source_store,
                 ns: remote_ns,
             })
         };
-        let target = PullTarget {
-            store: DataStore::lookup_datastore(store, Some(Operation::Write))?,
-            ns,
-        };
 
         let group_filter = group_filter.unwrap_or_default();
 
@@ -634,7 +662,7 @@ async fn pull_index_chunks<I: IndexFile>(
         4,
         move |(chunk, digest, size): (DataBlob, [u8; 32], u64)| {
             // println!("verify and write {}", hex::encode(&digest));
-            chunk.verify_unencrypted(size as usize, &digest)?;
+            chunk.verify_unencrypted_salted(size as usize, &digest, target2.digest_salt())?;
             target2.insert_chunk(&chunk, &digest)?;
             Ok(())
         },
@@ -701,6 +729,7 @@ async fn pull_index_chunks<I: IndexFile>(
         bytes,
         elapsed,
         removed: None,
+        new_snapshots: Vec::new(),
     })
 }
 
@@ -842,6 +871,13 @@ async fn pull_snapshot<'a>(
         return Ok(pull_stats);
     }
 
+    // snapshots imported from a seed are verified once against the source
+    let seeded_marker = snapshot.full_path().join(SEEDED_MARKER_FILE_NAME);
+    let seeded = seeded_marker.exists();
+    if seeded {
+        task_log!(worker, "verifying snapshot imported from seed");
+    }
+
     if manifest_name.exists() {
         let manifest_blob = proxmox_lang::try_block!({
             let mut manifest_file = std::fs::File::open(&manifest_name).map_err(|err| {
@@ -855,7 +891,7 @@ async fn pull_snapshot<'a>(
             format_err!("unable to read local manifest {manifest_name:?} - {err}")
         })?;
 
-        if manifest_blob.raw_data() == tmp_manifest_blob.raw_data() {
+        if manifest_blob.raw_data() == tmp_manifest_blob.raw_data() && !seeded {
             if !client_log_name.exists() {
                 reader
                     .try_download_client_log(&client_log_name, worker)
@@ -868,6 +904,7 @@ async fn pull_snapshot<'a>(
     }
 
     let manifest = BackupManifest::try_from(tmp_manifest_blob)?;
+    snapshot.datastore().check_crypt_policy(&manifest)?;
 
     for item in manifest.files() {
         let mut path = snapshot.full_path();
@@ -879,7 +916,8 @@ async fn pull_snapshot<'a>(
                     let index = DynamicIndexReader::open(&path)?;
                     let (csum, size) = index.compute_csum();
                     match manifest.verify_file(&item.filename, &csum, size) {
-                        Ok(_) => continue,
+                        Ok(_) if !seeded || has_all_chunks(snapshot, &index, worker)? => continue,
+                        Ok(_) => {}
                         Err(err) => {
                             task_log!(worker, "detected changed file {:?} - {}", path, err);
                         }
@@ -889,7 +927,8 @@ async fn pull_snapshot<'a>(
                     let index = FixedIndexReader::open(&path)?;
                     let (csum, size) = index.compute_csum();
                     match manifest.verify_file(&item.filename, &csum, size) {
-                        Ok(_) => continue,
+                        Ok(_) if !seeded || has_all_chunks(snapshot, &index, worker)? => continue,
+                        Ok(_) => {}
                         Err(err) => {
                             task_log!(worker, "detected changed file {:?} - {}", path, err);
                         }
@@ -932,9 +971,35 @@ async fn pull_snapshot<'a>(
         .cleanup_unreferenced_files(&manifest)
         .map_err(|err| format_err!("failed to cleanup unreferenced files - {err}"))?;
 
+    if seeded {
+        std::fs::remove_file(&seeded_marker)
+            .map_err(|err| format_err!("unable to remove seed marker - {err}"))?;
+        task_log!(worker, "snapshot imported from seed verified");
+    }
+
     Ok(pull_stats)
 }
 
+/// Check that all chunks of a local index exist, used to verify snapshots imported from a seed.
+fn has_all_chunks(
+    snapshot: &pbs_datastore::BackupDir,
+    index: &dyn IndexFile,
+    worker: &WorkerTask,
+) -> Result<bool, Error> {
+    for pos in 0..index.index_count() {
+        let digest = index.index_digest(pos).unwrap();
+        if !snapshot.datastore().cond_touch_chunk(digest, false)? {
+            task_log!(
+                worker,
+                "chunk {} of seeded snapshot missing, pulling archive again",
+                hex::encode(digest)
+            );
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
 /// Pulls a `snapshot`, removing newly created ones on error, but keeping existing ones in any case.
 ///
 /// The `reader` is configured to read from the source backup directory, while the
@@ -945,6 +1010,11 @@ async fn pull_snapshot_from<'a>(
     snapshot: &'a pbs_datastore::BackupDir,
     downloaded_chunks: Arc<Mutex<HashSet<[u8; 32]>>>,
 ) -> Result<PullStats, Error> {
+    snapshot.datastore().check_digest_salt(
+        "source datastore",
+        reader.digest_salt().map(digest_salt_fingerprint),
+    )?;
+
     let (_path, is_new, _snap_lock) = snapshot
         .datastore()
         .create_locked_backup_dir(snapshot.backup_ns(), snapshot.as_ref())?;
@@ -963,8 +1033,10 @@ async fn pull_snapshot_from<'a>(
                 }
                 return Err(err);
             }
-            Ok(pull_stats) => {
+            Ok(mut pull_stats) => {
                 task_log!(worker, "sync snapshot {} done", snapshot.dir());
+                pull_stats.snapshot_count += 1;
+                pull_stats.new_snapshots.push(snapshot.clone());
                 pull_stats
             }
         }
@@ -1109,7 +1181,14 @@ async fn pull_group(
         .enumerate()
         .filter(|&(pos, ref dir)| {
             source_snapshots.insert(dir.time);
-            if last_sync_time > dir.time {
+            // snapshots imported from a seed still need to be verified
+            let seeded = params
+                .target
+                .store
+                .snapshot_path(&target_ns, dir)
+                .join(SEEDED_MARKER_FILE_NAME)
+                .exists();
+            if last_sync_time > dir.time && !seeded {
                 already_synced_skip_info.update(dir.time);
                 return false;
             } else if already_synced_skip_info.count > 0 {
@@ -1584,3 +1663,50 @@ pub(crate) async fn pull_ns(
 
     Ok((progress, pull_stats, errors))
 }
+
+/// Verify the snapshots newly pulled by a sync job.
+///
+/// Returns the number of verified snapshots.
+pub(crate) fn verify_new_snapshots(
+    worker: Arc<WorkerTask>,
+    snapshots: &[pbs_datastore::BackupDir],
+) -> Result<usize, Error> {
+    let datastore = match snapshots.first() {
+        Some(snapshot) => Arc::clone(snapshot.datastore()),
+        None => return Ok(0),
+    };
+
+    task_log!(
+        worker,
+        "verifying {} newly synced snapshots",
+        snapshots.len()
+    );
+
+    let upid = worker.upid().clone();
+    let verify_worker = VerifyWorker::new(worker.clone(), datastore);
+
+    let mut failed = Vec::new();
+    for snapshot in snapshots {
+        worker.check_abort()?;
+        if !verify_backup_dir(&verify_worker, snapshot, upid.clone(), None)? {
+            failed.push(print_ns_and_snapshot(
+                snapshot.backup_ns(),
+                snapshot.as_ref(),
+            ));
+        }
+    }
+
+    if !failed.is_empty() {
+        task_log!(worker, "Failed to verify the following snapshots:");
+        for dir in failed.iter() {
+            task_log!(worker, "\t{dir}");
+        }
+        bail!(
+            "verification of {} of {} newly synced snapshots failed",
+            failed.len(),
+            snapshots.len(),
+        );
+    }
+
+    Ok(snapshots.len())
+}
@@ -0,0 +1,430 @@
+//! Read-through caching of snapshots from a remote
+//!
+//! Datastores with `read-through-remote` set act as a caching read proxy for a datastore on
+//! another Proxmox Backup Server. A reader session for a snapshot not present locally fetches its
+//! manifest and indices from the remote and stores them in the local datastore, chunks are only
+//! fetched from the remote when the client reads them and are inserted into the local chunk
+//! store. Repeated restores from the same snapshot are then served locally.
+//!
+//! Cached snapshots are marked by [`READ_THROUGH_MARKER_FILE_NAME`]. Their size and the time of
+//! their last read are recorded in a state file, so that the cache size can be enforced without
+//! walking the datastore. When the logical size of all cached snapshots exceeds
+//! `read-through-cache-size`, the least recently read ones are removed, garbage collection then
+//! frees their chunks.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{bail, format_err, Error};
+use serde::{Deserialize, Serialize};
+
+use proxmox_sys::fs::{file_read_optional_string, replace_file, CreateOptions};
+
+use pbs_api_types::{
+    print_ns_and_snapshot, Authid, BackupDir, BackupNamespace, DataStoreConfig, Remote,
+};
+use pbs_buildcfg::PROXMOX_BACKUP_STATE_DIR_M;
+use pbs_client::{BackupReader, HttpClient};
+use pbs_config::open_backup_lockfile;
+use pbs_datastore::data_blob::DataBlob;
+use pbs_datastore::index::IndexFile;
+use pbs_datastore::manifest::{archive_type, ArchiveType, BackupManifest, MANIFEST_BLOB_NAME};
+use pbs_datastore::DataStore;
+
+/// Marks a snapshot as fetched from the read-through remote.
+pub const READ_THROUGH_MARKER_FILE_NAME: &str = ".read-through";
+
+const CACHE_STATE_FN: &str = concat!(PROXMOX_BACKUP_STATE_DIR_M!(), "/read-through-cache.json");
+const CACHE_STATE_LOCK_FN: &str =
+    concat!(PROXMOX_BACKUP_STATE_DIR_M!(), "/.read-through-cache.lck");
+
+/// Connection to the datastore on the read-through remote, for a single snapshot.
+pub struct ReadThroughRemote {
+    reader: Arc<BackupReader>,
+    // keeps the connection alive
+    _client: HttpClient,
+    // sizes of the chunks referenced by the indexes of the snapshot
+    chunk_sizes: HashMap<[u8; 32], u64>,
+}
+
+impl ReadThroughRemote {
+    /// Load the chunk sizes from the indexes of the locally stored snapshot, needed to verify
+    /// fetched chunks.
+    pub fn load_chunk_sizes(
+        &mut self,
+        datastore: &DataStore,
+        backup_dir: &pbs_datastore::BackupDir,
+    ) -> Result<(), Error> {
+        let (manifest, _) = backup_dir.load_manifest()?;
+        for file in manifest.files() {
+            let path = backup_dir.full_path().join(&file.filename);
+            let index: Box<dyn IndexFile> = match archive_type(&file.filename)? {
+                ArchiveType::FixedIndex => Box::new(datastore.open_fixed_reader(&path)?),
+                ArchiveType::DynamicIndex => Box::new(datastore.open_dynamic_reader(&path)?),
+                ArchiveType::Blob => continue,
+            };
+            for pos in 0..index.index_count() {
+                if let Some(info) = index.chunk_info(pos) {
+                    self.chunk_sizes.insert(info.digest, info.size());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Fetch the chunk `digest` from the remote, verify it and insert it into the local chunk
+    /// store.
+    ///
+    /// Returns the raw chunk data.
+    pub async fn fetch_chunk(
+        &self,
+        datastore: &DataStore,
+        digest: &[u8; 32],
+    ) -> Result<Vec<u8>, Error> {
+        let size = match self.chunk_sizes.get(digest) {
+            Some(size) => *size,
+            None => bail!("chunk {} is not part of the snapshot", hex::encode(digest)),
+        };
+
+        let mut raw = Vec::with_capacity(4 * 1024 * 1024);
+        self.reader
+            .download_chunk(digest, &mut raw)
+            .await
+            .map_err(|err| format_err!("fetching chunk from remote failed - {err}"))?;
+
+        proxmox_async::runtime::block_in_place(|| {
+            let chunk = verify_fetched_chunk(&raw, digest, size, datastore.digest_salt())?;
+            datastore.insert_chunk(&chunk, digest)
+        })?;
+
+        Ok(raw)
+    }
+}
+
+// like a sync, only insert chunks from the remote after checking them
+fn verify_fetched_chunk(
+    raw: &[u8],
+    digest: &[u8; 32],
+    size: u64,
+    salt: Option<&[u8; 32]>,
+) -> Result<DataBlob, Error> {
+    // verifies the CRC
+    let chunk = DataBlob::load_from_reader(&mut &raw[..])?;
+    chunk
+        .verify_unencrypted_salted(size as usize, digest, salt)
+        .map_err(|err| {
+            format_err!(
+                "chunk {} from remote failed verification - {err}",
+                hex::encode(digest)
+            )
+        })?;
+    Ok(chunk)
+}
+
+/// Returns the datastore configuration if it has a read-through remote.
+pub fn read_through_config(store: &str) -> Result<Option<DataStoreConfig>, Error> {
+    let (config, _digest) = pbs_config::datastore::config()?;
+    let store_config: DataStoreConfig = config.lookup("datastore", store)?;
+    if store_config.read_through_remote.is_some() && store_config.read_through_store.is_some() {
+        Ok(Some(store_config))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Returns true if the snapshot at `path` was fetched from the read-through remote.
+pub fn is_cached_snapshot(path: &Path) -> bool {
+    path.join(READ_THROUGH_MARKER_FILE_NAME).exists()
+}
+
+/// Connect to the snapshot on the read-through remote of `config`.
+pub async fn connect(
+    config: &DataStoreConfig,
+    ns: &BackupNamespace,
+    dir: &BackupDir,
+) -> Result<ReadThroughRemote, Error> {
+    let (remote, remote_store) = match (&config.read_through_remote, &config.read_through_store) {
+        (Some(remote), Some(store)) => (remote, store),
+        _ => bail!("datastore '{}' has no read-through remote", config.name),
+    };
+
+    let (remote_config, _digest) = pbs_config::remote::config()?;
+    let remote: Remote = remote_config.lookup("remote", remote)?;
+    let client = crate::api2::config::remote::remote_client_config(&remote, None)?;
+
+    let reader = BackupReader::start(&client, None, remote_store, ns, dir, false)
+        .await
+        .map_err(|err| {
+            format_err!(
+                "unable to open {} on remote '{}' - {err}",
+                print_ns_and_snapshot(ns, dir),
+                remote.name
+            )
+        })?;
+
+    Ok(ReadThroughRemote {
+        reader,
+        _client: client,
+        chunk_sizes: HashMap::new(),
+    })
+}
+
+/// Fetch the manifest and all index and blob files of a snapshot from the remote into a new
+/// local snapshot, owned by root.
+pub async fn fetch_snapshot(
+    remote: &ReadThroughRemote,
+    datastore: &Arc<DataStore>,
+    ns: &BackupNamespace,
+    dir: &BackupDir,
+) -> Result<(), Error> {
+    let (manifest, raw_manifest) = remote.reader.download_manifest().await?;
+
+    datastore.create_namespace_recursive(ns)?;
+    let (_owner, _group_guard) =
+        datastore.create_locked_backup_group(ns, &dir.group, Authid::root_auth_id())?;
+    let (relative_path, is_new, _snapshot_guard) = datastore.create_locked_backup_dir(ns, dir)?;
+    if !is_new {
+        bail!("snapshot {dir} was created concurrently");
+    }
+    let path = datastore.base_path().join(relative_path);
+
+    let result = async {
+        for file in manifest.files() {
+            let target = path.join(&file.filename);
+            let mut tmp_path = target.clone();
+            tmp_path.set_extension("tmp");
+
+            let tmp_file = std::fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&tmp_path)?;
+            remote
+                .reader
+                .download(&file.filename, tmp_file)
+                .await
+                .map_err(|err| format_err!("fetching '{}' failed - {err}", file.filename))?;
+            std::fs::rename(&tmp_path, &target)?;
+        }
+
+        mark_cached_snapshot(&path)?;
+
+        let mut manifest_path = path.join(MANIFEST_BLOB_NAME);
+        manifest_path.set_extension("tmp");
+        std::fs::write(&manifest_path, raw_manifest)?;
+        std::fs::rename(&manifest_path, path.join(MANIFEST_BLOB_NAME))?;
+
+        Ok::<_, Error>(())
+    }
+    .await;
+
+    if let Err(err) = result {
+        let _ = std::fs::remove_dir_all(&path);
+        bail!(
+            "fetching {} from read-through remote failed - {err}",
+            print_ns_and_snapshot(ns, dir)
+        );
+    }
+
+    Ok(())
+}
+
+// the marker distinguishes cached snapshots from the ones backed up to this datastore
+fn mark_cached_snapshot(path: &Path) -> Result<(), Error> {
+    let backup_user = pbs_config::backup_user()?;
+    let options = CreateOptions::new()
+        .perm(nix::sys::stat::Mode::from_bits_truncate(0o0640))
+        .owner(backup_user.uid)
+        .group(backup_user.gid);
+    replace_file(
+        path.join(READ_THROUGH_MARKER_FILE_NAME),
+        proxmox_time::epoch_i64().to_string().as_bytes(),
+        options,
+        false,
+    )
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct CachedSnapshot {
+    ns: String,
+    snapshot: String,
+    size: u64,
+    last_read: i64,
+}
+
+fn read_cache_state() -> Result<HashMap<String, Vec<CachedSnapshot>>, Error> {
+    Ok(match file_read_optional_string(CACHE_STATE_FN)? {
+        Some(content) => serde_json::from_str(&content)
+            .map_err(|err| format_err!("unable to parse {CACHE_STATE_FN:?} - {err}"))?,
+        None => HashMap::new(),
+    })
+}
+
+fn write_cache_state(state: &HashMap<String, Vec<CachedSnapshot>>) -> Result<(), Error> {
+    let backup_user = pbs_config::backup_user()?;
+    let options = CreateOptions::new()
+        .perm(nix::sys::stat::Mode::from_bits_truncate(0o0640))
+        .owner(backup_user.uid)
+        .group(backup_user.gid);
+    replace_file(
+        CACHE_STATE_FN,
+        serde_json::to_string(state)?.as_bytes(),
+        options,
+        false,
+    )
+    .map_err(|err| format_err!("unable to write {CACHE_STATE_FN:?} - {err}"))
+}
+
+/// Record a read of the cached snapshot `backup_dir`.
+pub fn record_read(store: &str, backup_dir: &pbs_datastore::BackupDir) -> Result<(), Error> {
+    let ns = backup_dir.backup_ns().to_string();
+    let snapshot = backup_dir.dir().to_string();
+    let now = proxmox_time::epoch_i64();
+
+    let _lock = open_backup_lockfile(CACHE_STATE_LOCK_FN, None, true)?;
+    let mut state = read_cache_state()?;
+    let cached = state.entry(store.to_string()).or_default();
+
+    match cached
+        .iter_mut()
+        .find(|entry| entry.ns == ns && entry.snapshot == snapshot)
+    {
+        Some(entry) => entry.last_read = now,
+        None => {
+            let (manifest, _) = backup_dir.load_manifest()?;
+            cached.push(CachedSnapshot {
+                ns,
+                snapshot,
+                size: manifest_size(&manifest),
+                last_read: now,
+            });
+        }
+    }
+
+    write_cache_state(&state)
+}
+
+/// Returns the cached snapshots to remove so that the rest fits into `limit` bytes, least
+/// recently read first.
+fn select_evictions(cached: &[CachedSnapshot], limit: u64) -> Vec<CachedSnapshot> {
+    let mut total: u64 = cached.iter().map(|entry| entry.size).sum();
+    let mut by_last_read: Vec<&CachedSnapshot> = cached.iter().collect();
+    by_last_read.sort_by_key(|entry| entry.last_read);
+
+    let mut evict = Vec::new();
+    for entry in by_last_read {
+        if total <= limit {
+            break;
+        }
+        total -= entry.size;
+        evict.push(entry.clone());
+    }
+    evict
+}
+
+/// Remove the least recently read cached snapshots until their logical size fits into the
+/// configured cache size. Snapshots in use are skipped.
+///
+/// Only needed after fetching a snapshot, since reading cached ones does not grow the cache.
+pub fn enforce_cache_size(
+    datastore: &Arc<DataStore>,
+    config: &DataStoreConfig,
+) -> Result<(), Error> {
+    let limit = config.read_through_cache_size.unwrap_or(100) * 1024 * 1024 * 1024;
+
+    let _lock = open_backup_lockfile(CACHE_STATE_LOCK_FN, None, true)?;
+    let mut state = read_cache_state()?;
+    let cached = match state.get_mut(datastore.name()) {
+        Some(cached) => cached,
+        None => return Ok(()),
+    };
+
+    for entry in select_evictions(cached, limit) {
+        let ns = BackupNamespace::new(&entry.ns)?;
+        let dir: BackupDir = entry.snapshot.parse()?;
+        let path = datastore.backup_dir(ns.clone(), dir.clone())?.full_path();
+
+        // removed otherwise, for example by a prune job
+        if path.exists() {
+            if let Err(err) = datastore.remove_backup_dir(&ns, &dir, false) {
+                log::warn!(
+                    "unable to remove cached snapshot {} - {err}",
+                    print_ns_and_snapshot(&ns, &dir)
+                );
+                continue;
+            }
+            log::info!(
+                "removed cached snapshot {} from datastore '{}'",
+                print_ns_and_snapshot(&ns, &dir),
+                datastore.name()
+            );
+        }
+        cached.retain(|cached| cached != &entry);
+    }
+
+    write_cache_state(&state)
+}
+
+fn manifest_size(manifest: &BackupManifest) -> u64 {
+    manifest.files().iter().map(|file| file.size).sum()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use pbs_datastore::data_blob::DataChunkBuilder;
+
+    fn cached(snapshot: &str, size: u64, last_read: i64) -> CachedSnapshot {
+        CachedSnapshot {
+            ns: String::new(),
+            snapshot: snapshot.to_string(),
+            size,
+            last_read,
+        }
+    }
+
+    #[test]
+    fn test_select_evictions() {
+        let entries = vec![
+            cached("vm/100/2023-01-01T00:00:00Z", 40, 300),
+            cached("vm/100/2023-01-02T00:00:00Z", 40, 100),
+            cached("vm/101/2023-01-01T00:00:00Z", 40, 200),
+        ];
+
+        assert!(select_evictions(&entries, 120).is_empty());
+        assert_eq!(select_evictions(&entries, 80), vec![entries[1].clone()]);
+        assert_eq!(
+            select_evictions(&entries, 50),
+            vec![entries[1].clone(), entries[2].clone()]
+        );
+        assert_eq!(select_evictions(&entries, 0).len(), 3);
+    }
+
+    #[test]
+    fn test_verify_fetched_chunk() -> Result<(), Error> {
+        let data = vec![0xaa; 4096];
+        let salt = [1u8; 32];
+        let (chunk, digest) = DataChunkBuilder::new(&data)
+            .digest_salt(Some(&salt))
+            .build()?;
+        let raw = chunk.raw_data();
+
+        verify_fetched_chunk(raw, &digest, 4096, Some(&salt))?;
+
+        // wrong size, digest or salt
+        assert!(verify_fetched_chunk(raw, &digest, 4095, Some(&salt)).is_err());
+        assert!(verify_fetched_chunk(raw, &[0u8; 32], 4096, Some(&salt)).is_err());
+        assert!(verify_fetched_chunk(raw, &digest, 4096, None).is_err());
+
+        // corrupted data fails the CRC check
+        let mut corrupted = raw.to_vec();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 1;
+        assert!(verify_fetched_chunk(&corrupted, &digest, 4096, Some(&salt)).is_err());
+
+        Ok(())
+    }
+}
@@ -2,6 +2,20 @@ use std::fmt::Write;
 use std::path::Path;
 use std::process::Command;
 
+use anyhow::Error;
+
+use proxmox_rest_server::{TaskListInfoIterator, TaskState};
+
+use pbs_api_types::{DataStoreConfig, UPID};
+
+use crate::tools::disks::DiskUsageQuery;
+
+/// Configuration properties whose values must never end up in a report.
+const REDACTED_PROPERTIES: &[&str] = &["password", "client-key", "secret", "token"];
+
+/// Maximal number of failed tasks listed in the report (and included in the bundle).
+const MAX_FAILED_TASKS: usize = 20;
+
 fn get_top_processes() -> String {
     let (exe, args) = ("top", vec!["-b", "-c", "-w512", "-n", "1", "-o", "TIME"]);
     let output = Command::new(exe).args(&args).output();
@@ -35,6 +49,7 @@ fn files() -> Vec<(&'static str, Vec<&'static str>)> {
             vec![
                 "/etc/proxmox-backup/user.cfg",
                 "/etc/proxmox-backup/acl.cfg",
+                "/etc/proxmox-backup/domains.cfg",
             ],
         ),
         ("Remotes", vec!["/etc/proxmox-backup/remote.cfg"]),
@@ -58,6 +73,7 @@ fn files() -> Vec<(&'static str, Vec<&'static str>)> {
             vec![
                 "/etc/proxmox-backup/node.cfg",
                 "/etc/proxmox-backup/traffic-control.cfg",
+                "/etc/proxmox-backup/metricserver.cfg",
             ],
         ),
     ]
@@ -86,6 +102,19 @@ fn commands() -> Vec<(&'static str, Vec<&'static str>)> {
         ("zpool", vec!["status"]),
         ("zfs", vec!["list"]),
         ("arcstat", vec![]),
+        (
+            "journalctl",
+            vec![
+                "-b",
+                "-p",
+                "warning",
+                "-n",
+                "500",
+                "--no-pager",
+                "-o",
+                "short-iso",
+            ],
+        ),
     ]
 }
 
@@ -106,14 +135,125 @@ fn function_calls() -> Vec<FunctionMapping> {
             }
             format!("```\n{}\n```", list.join(", "))
         }),
+        ("Datastore Status", get_datastore_status),
+        ("Failed Tasks", || {
+            let tasks = match recent_failed_tasks() {
+                Ok(tasks) => tasks,
+                Err(err) => return format!("could not read task list - {err}"),
+            };
+            let list: Vec<String> = tasks
+                .iter()
+                .map(|(upid, status)| format!("{upid}: {status}"))
+                .collect();
+            format!("```\n{}\n```", list.join("\n"))
+        }),
+        ("Disks & SMART", get_disk_smart_status),
         ("System Load & Uptime", get_top_processes),
     ]
 }
 
+fn get_datastore_status() -> String {
+    let config = match pbs_config::datastore::config() {
+        Ok((config, _digest)) => config,
+        _ => return String::from("could not read datastore config"),
+    };
+
+    let mut out = String::new();
+    for store in config.sections.keys() {
+        let store_config: DataStoreConfig = match config.lookup("datastore", store) {
+            Ok(store_config) => store_config,
+            Err(err) => {
+                let _ = writeln!(out, "{store}: could not parse config - {err}");
+                continue;
+            }
+        };
+        let maintenance = store_config
+            .get_maintenance_mode()
+            .map(|mode| format!(" (maintenance: {})", mode.ty))
+            .unwrap_or_default();
+        match proxmox_sys::fs::fs_info(&store_config.path) {
+            Ok(info) => {
+                let _ = writeln!(
+                    out,
+                    "{store}{maintenance}: total {} used {} avail {}",
+                    info.total, info.used, info.available,
+                );
+            }
+            Err(err) => {
+                let _ = writeln!(out, "{store}{maintenance}: fs info failed - {err}");
+            }
+        }
+    }
+    format!("```\n{}\n```", out.trim_end())
+}
+
+fn get_disk_smart_status() -> String {
+    let disks = match DiskUsageQuery::new().smart(true).query() {
+        Ok(disks) => disks,
+        Err(err) => return format!("could not query disks - {err}"),
+    };
+
+    let mut names: Vec<&String> = disks.keys().collect();
+    names.sort();
+
+    let mut out = String::new();
+    for name in names {
+        let info = &disks[name];
+        let wearout = info
+            .wearout
+            .map(|wearout| format!(" wearout {wearout:.0}%"))
+            .unwrap_or_default();
+        let _ = writeln!(
+            out,
+            "{name}: {} {:?} status {:?}{wearout}",
+            info.model.as_deref().unwrap_or("unknown model"),
+            info.disk_type,
+            info.status,
+        );
+    }
+    format!("```\n{}\n```", out.trim_end())
+}
+
+/// Returns the UPID and status of the most recent failed tasks.
+fn recent_failed_tasks() -> Result<Vec<(String, String)>, Error> {
+    let mut list = Vec::new();
+    for info in TaskListInfoIterator::new(false)? {
+        let info = match info {
+            Ok(info) => info,
+            Err(_) => break,
+        };
+        if let Some(state @ TaskState::Error { .. }) = &info.state {
+            list.push((info.upid_str, state.to_string()));
+            if list.len() >= MAX_FAILED_TASKS {
+                break;
+            }
+        }
+    }
+    Ok(list)
+}
+
+/// Replace the values of sensitive config properties, keeping the structure intact.
+fn redact_config(content: &str) -> String {
+    content
+        .lines()
+        .map(|line| {
+            let trimmed = line.trim_start();
+            let key = trimmed.split_whitespace().next().unwrap_or("");
+            if REDACTED_PROPERTIES.contains(&key) {
+                let indent = &line[..line.len() - trimmed.len()];
+                format!("{indent}{key} <redacted>")
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
 fn get_file_content(file: impl AsRef<Path>) -> String {
     use proxmox_sys::fs::file_read_optional_string;
     let content = match file_read_optional_string(&file) {
-        Ok(Some(content)) => content,
+        Ok(Some(content)) => redact_config(&content),
         Ok(None) => String::from("# file does not exist"),
         Err(err) => err.to_string(),
     };
@@ -221,3 +361,39 @@ pub fn generate_report() -> String {
         "## FILES\n\n{file_contents}\n## COMMANDS\n\n{command_outputs}\n## FUNCTIONS\n\n{function_outputs}\n"
     )
 }
+
+/// Generate a support bundle.
+///
+/// The bundle is a zstd compressed tar archive containing the report as `report.md` and the logs
+/// of the most recent failed tasks below `tasks/`.
+pub fn generate_report_bundle() -> Result<Vec<u8>, Error> {
+    let encoder = zstd::stream::write::Encoder::new(Vec::new(), 0)?;
+    let mut archive = tar::Builder::new(encoder);
+
+    let mtime = proxmox_time::epoch_i64() as u64;
+    let mut append = |name: &str, data: &[u8]| -> Result<(), Error> {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o640);
+        header.set_mtime(mtime);
+        header.set_cksum();
+        archive.append_data(&mut header, name, data)?;
+        Ok(())
+    };
+
+    append("report.md", generate_report().as_bytes())?;
+
+    for (upid_str, _status) in recent_failed_tasks()? {
+        let upid: UPID = match upid_str.parse() {
+            Ok(upid) => upid,
+            Err(_) => continue,
+        };
+        let log = match super::task_log::read_task_log(&upid) {
+            Ok(log) => log,
+            Err(err) => format!("could not read task log - {err}").into_bytes(),
+        };
+        append(&format!("tasks/{upid_str}.log"), &log)?;
+    }
+
+    Ok(archive.into_inner()?.finish()?)
+}
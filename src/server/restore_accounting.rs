@@ -0,0 +1,133 @@
+//! Accounting of restore traffic per auth-id
+//!
+//! Reader sessions and file downloads account the bytes they send to the auth-id that requested
+//! them. Finished transfers are appended to the restore accounting log, the running totals per
+//! auth-id are fed into the `accounting/<auth-id>/restore` RRD series by the proxy.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Error;
+use futures::TryStreamExt;
+use hyper::Body;
+use lazy_static::lazy_static;
+
+use proxmox_sys::logrotate::LogRotate;
+
+use pbs_api_types::{Authid, RestoreAccountingEntry, RestoreAccountingKind};
+use pbs_buildcfg::RESTORE_ACCOUNTING_LOG_FN;
+
+use crate::rrd_cache::rrd_update_derive;
+
+lazy_static! {
+    /// Bytes sent per auth-id since the proxy started
+    static ref RESTORE_TOTALS: Mutex<HashMap<Authid, u64>> = Mutex::new(HashMap::new());
+}
+
+/// Accounts the traffic of one transfer, writes the log entry when dropped.
+pub struct RestoreAccounting {
+    auth_id: Authid,
+    store: String,
+    kind: RestoreAccountingKind,
+    name: String,
+    start: i64,
+    bytes: AtomicU64,
+}
+
+impl RestoreAccounting {
+    pub fn new(
+        auth_id: Authid,
+        store: &str,
+        kind: RestoreAccountingKind,
+        name: String,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            auth_id,
+            store: store.to_string(),
+            kind,
+            name,
+            start: proxmox_time::epoch_i64(),
+            bytes: AtomicU64::new(0),
+        })
+    }
+
+    /// Account `bytes` sent to the client.
+    pub fn add(&self, bytes: u64) {
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+        let mut totals = RESTORE_TOTALS.lock().unwrap();
+        *totals.entry(self.auth_id.clone()).or_insert(0) += bytes;
+    }
+}
+
+impl Drop for RestoreAccounting {
+    fn drop(&mut self) {
+        let now = proxmox_time::epoch_i64();
+        let entry = RestoreAccountingEntry {
+            time: now,
+            auth_id: self.auth_id.clone(),
+            store: std::mem::take(&mut self.store),
+            kind: self.kind,
+            name: std::mem::take(&mut self.name),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            duration: now - self.start,
+        };
+        if let Err(err) = append_log_entry(&entry) {
+            log::error!("unable to write restore accounting log - {err}");
+        }
+    }
+}
+
+fn append_log_entry(entry: &RestoreAccountingEntry) -> Result<(), Error> {
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(RESTORE_ACCOUNTING_LOG_FN)?;
+    // a single write, so concurrent transfers do not interleave their lines
+    file.write_all(line.as_bytes())?;
+
+    Ok(())
+}
+
+/// Update the restore traffic RRD series of all auth-ids with traffic since the proxy started.
+pub fn rrd_update_restore_accounting() {
+    let totals = RESTORE_TOTALS.lock().unwrap().clone();
+    for (auth_id, bytes) in totals {
+        rrd_update_derive(&format!("accounting/{auth_id}/restore"), bytes as f64);
+    }
+}
+
+/// Read the restore accounting log including all rotated files, newest entries first.
+pub fn read_restore_accounting_log<F>(mut callback: F) -> Result<(), Error>
+where
+    F: FnMut(RestoreAccountingEntry) -> bool,
+{
+    let logrotate = LogRotate::new(RESTORE_ACCOUNTING_LOG_FN, true, None, None)?;
+
+    for file in logrotate.files() {
+        let mut entries = Vec::new();
+        for line in BufReader::new(file).lines() {
+            match serde_json::from_str(&line?) {
+                Ok(entry) => entries.push(entry),
+                Err(err) => log::warn!("skipping invalid restore accounting entry - {err}"),
+            }
+        }
+        for entry in entries.into_iter().rev() {
+            if !callback(entry) {
+                return Ok(());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Wrap the body of a file download, accounting all data sent to `auth_id`.
+pub fn accounted_download(body: Body, auth_id: Authid, store: &str, name: String) -> Body {
+    let accounting = RestoreAccounting::new(auth_id, store, RestoreAccountingKind::Download, name);
+    Body::wrap_stream(body.inspect_ok(move |data| accounting.add(data.len() as u64)))
+}
@@ -0,0 +1,347 @@
+//! Expiring links for downloading a file or directory of a snapshot without logging in
+//!
+//! A link carries a ticket with the [`RESTORE_LINK_PREFIX`], signed with the auth key like
+//! regular tickets. The ticket contains the auth-id of the user who created the link, its
+//! signature covers the referenced snapshot and path as well as the expiry, so a link cannot be
+//! reused for another file or beyond its lifetime. The privileges of the creator are checked
+//! again on every download, so revoking them also invalidates the links they created.
+//!
+//! One-time links are claimed while a download is running, and only used up once it completed
+//! successfully. Used links are remembered in a state file until they expire and rejected
+//! afterwards.
+
+use std::collections::{HashMap, HashSet};
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+
+use anyhow::{bail, format_err, Error};
+use futures::{ready, Stream};
+use hyper::body::Bytes;
+use hyper::Body;
+use once_cell::sync::Lazy;
+use openssl::sha::sha256;
+
+use proxmox_auth_api::ticket::Ticket;
+use proxmox_auth_api::Keyring;
+use proxmox_sys::fs::{file_read_optional_string, replace_file, CreateOptions};
+
+use pbs_api_types::{Authid, BackupDir, BackupNamespace};
+use pbs_buildcfg::PROXMOX_BACKUP_STATE_DIR_M;
+use pbs_config::open_backup_lockfile;
+
+use crate::auth::{private_auth_keyring, public_auth_keyring, RESTORE_LINK_PREFIX};
+
+const USED_LINKS_FN: &str = concat!(PROXMOX_BACKUP_STATE_DIR_M!(), "/restore-links-used.json");
+const USED_LINKS_LOCK_FN: &str = concat!(PROXMOX_BACKUP_STATE_DIR_M!(), "/.restore-links-used.lck");
+
+/// Maximum lifetime of a restore link, in seconds (7 days)
+pub const MAX_RESTORE_LINK_LIFETIME: i64 = 7 * 24 * 3600;
+
+/// Allowed clock skew between creation and verification of a link, in seconds
+const CLOCK_SKEW: i64 = 300;
+
+// one-time links with a download in progress
+static CLAIMED_LINKS: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// The file or directory of a snapshot a restore link grants access to.
+pub struct RestoreLinkScope<'a> {
+    pub store: &'a str,
+    pub ns: &'a BackupNamespace,
+    pub dir: &'a BackupDir,
+    /// Base64 encoded path, as used by the `pxar-file-download` API
+    pub filepath: &'a str,
+    pub expire: i64,
+    pub one_time: bool,
+}
+
+impl RestoreLinkScope<'_> {
+    fn aad(&self) -> String {
+        crate::tools::ticket::restore_link_aad(
+            self.store,
+            self.ns,
+            self.dir,
+            self.filepath,
+            self.expire,
+            self.one_time,
+        )
+    }
+}
+
+/// Create a restore link ticket for `scope` on behalf of `auth_id`.
+pub fn create_restore_link_ticket(
+    auth_id: &Authid,
+    scope: &RestoreLinkScope,
+) -> Result<String, Error> {
+    create_ticket(private_auth_keyring(), auth_id, scope)
+}
+
+fn create_ticket(
+    keyring: &Keyring,
+    auth_id: &Authid,
+    scope: &RestoreLinkScope,
+) -> Result<String, Error> {
+    let now = proxmox_time::epoch_i64();
+    if scope.expire <= now {
+        bail!("restore link expiry is in the past");
+    }
+    if scope.expire - now > MAX_RESTORE_LINK_LIFETIME {
+        bail!("restore link lifetime exceeds the maximum of {MAX_RESTORE_LINK_LIFETIME} seconds");
+    }
+
+    Ticket::new(RESTORE_LINK_PREFIX, auth_id)?.sign(keyring, Some(&scope.aad()))
+}
+
+/// Verify a restore link ticket for `scope`, returning the auth-id of the link's creator.
+pub fn verify_restore_link_ticket(ticket: &str, scope: &RestoreLinkScope) -> Result<Authid, Error> {
+    verify_ticket(public_auth_keyring(), ticket, scope)
+}
+
+fn verify_ticket(
+    keyring: &Keyring,
+    ticket: &str,
+    scope: &RestoreLinkScope,
+) -> Result<Authid, Error> {
+    if proxmox_time::epoch_i64() > scope.expire {
+        bail!("restore link expired");
+    }
+
+    Ticket::<Authid>::parse(ticket)?.verify_with_time_check(
+        keyring,
+        RESTORE_LINK_PREFIX,
+        Some(&scope.aad()),
+        -CLOCK_SKEW..=MAX_RESTORE_LINK_LIFETIME,
+    )
+}
+
+/// A one-time link with a download in progress, released again when dropped.
+pub struct RestoreLinkClaim {
+    key: String,
+    expire: i64,
+}
+
+impl RestoreLinkClaim {
+    fn new(key: String, expire: i64, used: &HashMap<String, i64>) -> Result<Self, Error> {
+        if used.contains_key(&key) {
+            bail!("restore link was already used");
+        }
+        if !CLAIMED_LINKS.lock().unwrap().insert(key.clone()) {
+            bail!("restore link is already in use");
+        }
+        Ok(Self { key, expire })
+    }
+
+    /// Use up the link, so it is rejected from now on.
+    fn consume(self) -> Result<(), Error> {
+        mark_used(&self.key, self.expire)
+    }
+}
+
+impl Drop for RestoreLinkClaim {
+    fn drop(&mut self) {
+        CLAIMED_LINKS.lock().unwrap().remove(&self.key);
+    }
+}
+
+/// Claim a verified one-time link for a download, failing if it was used before or another
+/// download with it is in progress. Returns `None` for links which may be used multiple times.
+pub fn claim_restore_link(
+    ticket: &str,
+    scope: &RestoreLinkScope,
+) -> Result<Option<RestoreLinkClaim>, Error> {
+    if !scope.one_time {
+        return Ok(None);
+    }
+    let key = hex::encode(sha256(ticket.as_bytes()));
+    RestoreLinkClaim::new(key, scope.expire, &read_used_links()?).map(Some)
+}
+
+/// Wrap the body of a restore link download, using up the `claim` only once `body` was streamed
+/// completely, so aborted or failed downloads can be retried.
+pub fn consume_after_download(body: Body, claim: Option<RestoreLinkClaim>) -> Body {
+    match claim {
+        Some(claim) => Body::wrap_stream(ConsumeOnCompletion {
+            inner: body,
+            claim: Some(claim),
+        }),
+        None => body,
+    }
+}
+
+struct ConsumeOnCompletion<S> {
+    inner: S,
+    claim: Option<RestoreLinkClaim>,
+}
+
+impl<S> Stream for ConsumeOnCompletion<S>
+where
+    S: Stream<Item = Result<Bytes, hyper::Error>> + Unpin,
+{
+    type Item = Result<Bytes, hyper::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let item = ready!(Pin::new(&mut self.inner).poll_next(cx));
+        match &item {
+            // releases the claim, the link can be used again
+            Some(Err(_)) => self.claim = None,
+            None => {
+                if let Some(claim) = self.claim.take() {
+                    if let Err(err) = claim.consume() {
+                        log::error!("unable to mark restore link as used - {err}");
+                    }
+                }
+            }
+            Some(Ok(_)) => (),
+        }
+        Poll::Ready(item)
+    }
+}
+
+fn read_used_links() -> Result<HashMap<String, i64>, Error> {
+    match file_read_optional_string(USED_LINKS_FN)? {
+        Some(content) => serde_json::from_str(&content)
+            .map_err(|err| format_err!("unable to parse {USED_LINKS_FN:?} - {err}")),
+        None => Ok(HashMap::new()),
+    }
+}
+
+// remember the link until it expires, so the state file does not grow forever
+fn insert_used(
+    used: &mut HashMap<String, i64>,
+    key: &str,
+    expire: i64,
+    now: i64,
+) -> Result<(), Error> {
+    used.retain(|_, expire| *expire >= now);
+
+    if used.insert(key.to_string(), expire).is_some() {
+        bail!("restore link was already used");
+    }
+    Ok(())
+}
+
+fn mark_used(key: &str, expire: i64) -> Result<(), Error> {
+    let _lock = open_backup_lockfile(USED_LINKS_LOCK_FN, None, true)?;
+
+    let mut used = read_used_links()?;
+    insert_used(&mut used, key, expire, proxmox_time::epoch_i64())?;
+
+    let backup_user = pbs_config::backup_user()?;
+    let options = CreateOptions::new()
+        .perm(nix::sys::stat::Mode::from_bits_truncate(0o0640))
+        .owner(backup_user.uid)
+        .group(backup_user.gid);
+    replace_file(
+        USED_LINKS_FN,
+        serde_json::to_string(&used)?.as_bytes(),
+        options,
+        false,
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn scope<'a>(
+        ns: &'a BackupNamespace,
+        dir: &'a BackupDir,
+        filepath: &'a str,
+        expire: i64,
+    ) -> RestoreLinkScope<'a> {
+        RestoreLinkScope {
+            store: "store1",
+            ns,
+            dir,
+            filepath,
+            expire,
+            one_time: true,
+        }
+    }
+
+    #[test]
+    fn test_ticket_roundtrip() -> Result<(), Error> {
+        let keyring = Keyring::with_private_key(proxmox_auth_api::PrivateKey::generate_ec()?);
+        let auth_id: Authid = "alice@pbs".parse()?;
+        let ns = BackupNamespace::root();
+        let dir: BackupDir = "host/elsa/2023-01-01T00:00:00Z".parse()?;
+        let expire = proxmox_time::epoch_i64() + 3600;
+
+        let link = scope(&ns, &dir, "cm9vdC5weGFyLmRpZHgvZXRj", expire);
+        let ticket = create_ticket(&keyring, &auth_id, &link)?;
+        assert_eq!(verify_ticket(&keyring, &ticket, &link)?, auth_id);
+
+        // the signature covers the whole scope
+        let other_file = scope(&ns, &dir, "cm9vdC5weGFyLmRpZHgvaG9tZQ==", expire);
+        assert!(verify_ticket(&keyring, &ticket, &other_file).is_err());
+
+        let other_dir: BackupDir = "host/elsa/2023-01-02T00:00:00Z".parse()?;
+        let other_snapshot = scope(&ns, &other_dir, "cm9vdC5weGFyLmRpZHgvZXRj", expire);
+        assert!(verify_ticket(&keyring, &ticket, &other_snapshot).is_err());
+
+        let extended = scope(&ns, &dir, "cm9vdC5weGFyLmRpZHgvZXRj", expire + 3600);
+        assert!(verify_ticket(&keyring, &ticket, &extended).is_err());
+
+        let mut reusable = scope(&ns, &dir, "cm9vdC5weGFyLmRpZHgvZXRj", expire);
+        reusable.one_time = false;
+        assert!(verify_ticket(&keyring, &ticket, &reusable).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expiry() -> Result<(), Error> {
+        let keyring = Keyring::with_private_key(proxmox_auth_api::PrivateKey::generate_ec()?);
+        let auth_id: Authid = "alice@pbs".parse()?;
+        let ns = BackupNamespace::root();
+        let dir: BackupDir = "host/elsa/2023-01-01T00:00:00Z".parse()?;
+        let now = proxmox_time::epoch_i64();
+
+        let expired = scope(&ns, &dir, "cm9vdC5weGFyLmRpZHgvZXRj", now - 1);
+        assert!(create_ticket(&keyring, &auth_id, &expired).is_err());
+
+        let too_long = scope(
+            &ns,
+            &dir,
+            "cm9vdC5weGFyLmRpZHgvZXRj",
+            now + MAX_RESTORE_LINK_LIFETIME + 60,
+        );
+        assert!(create_ticket(&keyring, &auth_id, &too_long).is_err());
+
+        // a valid ticket for an expired scope is rejected
+        let link = scope(&ns, &dir, "cm9vdC5weGFyLmRpZHgvZXRj", now + 60);
+        let ticket = create_ticket(&keyring, &auth_id, &link)?;
+        let expired = scope(&ns, &dir, "cm9vdC5weGFyLmRpZHgvZXRj", now - 1);
+        assert!(verify_ticket(&keyring, &ticket, &expired).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_one_time_reuse() -> Result<(), Error> {
+        let now = proxmox_time::epoch_i64();
+        let mut used = HashMap::new();
+
+        // concurrent downloads with the same link are rejected
+        let claim = RestoreLinkClaim::new("link".to_string(), now + 60, &used)?;
+        assert!(RestoreLinkClaim::new("link".to_string(), now + 60, &used).is_err());
+
+        // an aborted download releases the link again
+        drop(claim);
+        let claim = RestoreLinkClaim::new("link".to_string(), now + 60, &used)?;
+
+        // a completed download uses it up
+        insert_used(&mut used, &claim.key, claim.expire, now)?;
+        drop(claim);
+        assert!(RestoreLinkClaim::new("link".to_string(), now + 60, &used).is_err());
+        assert!(insert_used(&mut used, "link", now + 60, now).is_err());
+
+        // expired links are forgotten
+        insert_used(&mut used, "other", now + 60, now + 120)?;
+        assert!(!used.contains_key("link"));
+
+        Ok(())
+    }
+}
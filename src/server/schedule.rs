@@ -0,0 +1,597 @@
+//! Evaluation of job schedules, including schedule exclusions and holiday calendars
+
+use std::collections::HashSet;
+
+use anyhow::{bail, format_err, Error};
+use serde::de::DeserializeOwned;
+
+use proxmox_section_config::SectionConfigData;
+use proxmox_time::CalendarEvent;
+
+use pbs_api_types::{
+    DataStoreConfig, HolidayCalendarConfig, PruneJobConfig, ScheduleExclusion, SyncJobConfig,
+    TapeBackupJobConfig, VerificationJobConfig,
+};
+
+/// Maximum nesting depth of holiday calendars referencing each other.
+const MAX_CALENDAR_DEPTH: usize = 8;
+
+/// Maximum number of calendar events evaluated when computing upcoming runs.
+const MAX_PREVIEW_EVENTS: usize = 10_000;
+
+/// Local start and end (exclusive) of the day containing `time`.
+fn local_day_bounds(time: i64) -> Result<(i64, i64), Error> {
+    let mut tm = proxmox_time::localtime(time)?;
+    tm.tm_hour = 0;
+    tm.tm_min = 0;
+    tm.tm_sec = 0;
+    tm.tm_isdst = -1;
+    let start = proxmox_time::timelocal(&mut tm)?;
+
+    let mut tm = proxmox_time::localtime(time)?;
+    tm.tm_mday += 1;
+    tm.tm_hour = 0;
+    tm.tm_min = 0;
+    tm.tm_sec = 0;
+    tm.tm_isdst = -1;
+    let end = proxmox_time::timelocal(&mut tm)?;
+
+    Ok((start, end))
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        _ if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 => 29,
+        _ => 28,
+    }
+}
+
+fn exclusion_matches(
+    exclusion: &ScheduleExclusion,
+    time: i64,
+    calendars: &SectionConfigData,
+    visited: &mut HashSet<String>,
+) -> Result<bool, Error> {
+    match exclusion {
+        ScheduleExclusion::Calendar(name) => {
+            if visited.len() >= MAX_CALENDAR_DEPTH || !visited.insert(name.clone()) {
+                bail!("holiday calendar '{name}' is referenced recursively");
+            }
+            let calendar: HolidayCalendarConfig = match calendars.lookup("calendar", name) {
+                Ok(calendar) => calendar,
+                Err(err) => bail!("unable to read holiday calendar '{name}' - {err}"),
+            };
+            let mut matches = false;
+            for exclusion in calendar.exclude.iter() {
+                if exclusion_matches(exclusion, time, calendars, visited)? {
+                    matches = true;
+                    break;
+                }
+            }
+            visited.remove(name);
+            Ok(matches)
+        }
+        ScheduleExclusion::Dates(from, to) => {
+            let today = proxmox_time::strftime_local("%F", time)?;
+            Ok(from.as_str() <= today.as_str() && today.as_str() <= to.as_str())
+        }
+        ScheduleExclusion::LastWeekday(weekday) => {
+            let tm = proxmox_time::localtime(time)?;
+            if tm.tm_wday != *weekday as i32 {
+                return Ok(false);
+            }
+            let days = days_in_month(tm.tm_year + 1900, tm.tm_mon as u32 + 1);
+            Ok(tm.tm_mday as u32 + 7 > days)
+        }
+        ScheduleExclusion::Event(event) => {
+            let event: CalendarEvent = event.parse()?;
+            let (start, end) = local_day_bounds(time)?;
+            Ok(match event.compute_next_event(start - 1)? {
+                Some(next) => next < end,
+                None => false,
+            })
+        }
+    }
+}
+
+/// Check whether the (local) day containing `time` is excluded by any of the given exclusions.
+pub fn is_excluded(exclusions: &[ScheduleExclusion], time: i64) -> Result<bool, Error> {
+    if exclusions.is_empty() {
+        return Ok(false);
+    }
+
+    let (calendars, _digest) = pbs_config::holiday::config()?;
+    is_excluded_by(exclusions, time, &calendars)
+}
+
+fn is_excluded_by(
+    exclusions: &[ScheduleExclusion],
+    time: i64,
+    calendars: &SectionConfigData,
+) -> Result<bool, Error> {
+    let mut visited = HashSet::new();
+
+    for exclusion in exclusions {
+        if exclusion_matches(exclusion, time, calendars, &mut visited)? {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+fn check_exclusion(
+    exclusion: &ScheduleExclusion,
+    calendars: &SectionConfigData,
+    visited: &mut HashSet<String>,
+) -> Result<(), Error> {
+    if let ScheduleExclusion::Calendar(name) = exclusion {
+        if visited.len() >= MAX_CALENDAR_DEPTH || !visited.insert(name.clone()) {
+            bail!("holiday calendar '{name}' is referenced recursively");
+        }
+        let calendar: HolidayCalendarConfig = match calendars.lookup("calendar", name) {
+            Ok(calendar) => calendar,
+            Err(_) => bail!("holiday calendar '{name}' does not exist"),
+        };
+        for exclusion in calendar.exclude.iter() {
+            check_exclusion(exclusion, calendars, visited)?;
+        }
+        visited.remove(name);
+    }
+    Ok(())
+}
+
+/// Check that all holiday calendars referenced by `exclusions` exist in `calendars` and do not
+/// reference each other recursively.
+pub fn check_schedule_exclusions_with(
+    exclusions: &[ScheduleExclusion],
+    calendars: &SectionConfigData,
+) -> Result<(), Error> {
+    let mut visited = HashSet::new();
+    for exclusion in exclusions {
+        check_exclusion(exclusion, calendars, &mut visited)?;
+    }
+    Ok(())
+}
+
+/// Check the holiday calendar references of a job's schedule exclusions against the current
+/// holiday calendar configuration.
+pub fn check_schedule_exclusions(exclusions: Option<&[ScheduleExclusion]>) -> Result<(), Error> {
+    let exclusions = exclusions.unwrap_or_default();
+    if !exclusions
+        .iter()
+        .any(|exclusion| matches!(exclusion, ScheduleExclusion::Calendar(_)))
+    {
+        return Ok(());
+    }
+
+    let (calendars, _digest) = pbs_config::holiday::config()?;
+    check_schedule_exclusions_with(exclusions, &calendars)
+}
+
+/// Compute the start times of the next `count` runs of a job, the way the scheduler would start
+/// them: the first run follows `last` (the last run time of the job), a run that is already due
+/// starts at `now`, and runs on excluded days are skipped.
+pub fn next_runs(
+    schedule: &str,
+    exclusions: &[ScheduleExclusion],
+    last: i64,
+    now: i64,
+    count: usize,
+) -> Result<Vec<i64>, Error> {
+    let event: CalendarEvent = schedule.parse()?;
+
+    let calendars = if exclusions.is_empty() {
+        SectionConfigData::new()
+    } else {
+        pbs_config::holiday::config()?.0
+    };
+
+    let mut runs = Vec::with_capacity(count);
+    let mut last = last;
+
+    // exclusions could skip every run, so limit the number of evaluated events
+    for _ in 0..MAX_PREVIEW_EVENTS {
+        if runs.len() >= count {
+            break;
+        }
+        let next = match event.compute_next_event(last)? {
+            Some(next) => next.max(now),
+            None => break,
+        };
+        if !is_excluded_by(exclusions, next, &calendars)? {
+            runs.push(next);
+        }
+        last = next;
+    }
+
+    Ok(runs)
+}
+
+/// Configuration of a job started by the scheduler.
+pub enum ScheduledJobConfig {
+    GarbageCollection(DataStoreConfig),
+    Archive(DataStoreConfig),
+    Prune(PruneJobConfig),
+    Sync(SyncJobConfig),
+    Verification(VerificationJobConfig),
+    TapeBackup(TapeBackupJobConfig),
+}
+
+/// A job with a schedule, as started by the scheduler of the proxy.
+pub struct ScheduledJob {
+    /// Worker type of the job, e.g. 'syncjob'
+    pub job_type: &'static str,
+    pub id: String,
+    /// Datastore the job runs on
+    pub store: String,
+    pub schedule: String,
+    pub exclude: Vec<ScheduleExclusion>,
+    pub config: ScheduledJobConfig,
+}
+
+// the sections of type `section_type`, reporting the ones which cannot be parsed
+fn typed_sections<T: DeserializeOwned>(
+    config: &SectionConfigData,
+    section_type: &str,
+    on_error: &mut dyn FnMut(Error),
+) -> Vec<T> {
+    let mut list = Vec::new();
+    for (id, (ty, data)) in config.sections.iter() {
+        if ty != section_type {
+            continue;
+        }
+        match serde_json::from_value(data.clone()) {
+            Ok(config) => list.push(config),
+            Err(err) => on_error(format_err!(
+                "{section_type} config '{id}' is invalid - {err}"
+            )),
+        }
+    }
+    list
+}
+
+fn add_datastore_jobs(
+    jobs: &mut Vec<ScheduledJob>,
+    config: &SectionConfigData,
+    on_error: &mut dyn FnMut(Error),
+) {
+    for store in typed_sections::<DataStoreConfig>(config, "datastore", on_error) {
+        if let Some(schedule) = store.gc_schedule.clone() {
+            jobs.push(ScheduledJob {
+                job_type: "garbage_collection",
+                id: store.name.clone(),
+                store: store.name.clone(),
+                schedule,
+                exclude: Vec::new(),
+                config: ScheduledJobConfig::GarbageCollection(store.clone()),
+            });
+        }
+        if store.archive_store.is_none() || store.archive_after.is_none() {
+            continue;
+        }
+        if let Some(schedule) = store.archive_schedule.clone() {
+            jobs.push(ScheduledJob {
+                job_type: "archive",
+                id: store.name.clone(),
+                store: store.name.clone(),
+                schedule,
+                exclude: Vec::new(),
+                config: ScheduledJobConfig::Archive(store),
+            });
+        }
+    }
+}
+
+fn add_prune_jobs(
+    jobs: &mut Vec<ScheduledJob>,
+    config: &SectionConfigData,
+    on_error: &mut dyn FnMut(Error),
+) {
+    for job in typed_sections::<PruneJobConfig>(config, "prune", on_error) {
+        // without 'keep' values everything is kept
+        if job.disable || !job.options.keeps_something() {
+            continue;
+        }
+        jobs.push(ScheduledJob {
+            job_type: "prunejob",
+            id: job.id.clone(),
+            store: job.store.clone(),
+            schedule: job.schedule.clone(),
+            exclude: job.schedule_exclude.clone().unwrap_or_default(),
+            config: ScheduledJobConfig::Prune(job),
+        });
+    }
+}
+
+fn add_sync_jobs(
+    jobs: &mut Vec<ScheduledJob>,
+    config: &SectionConfigData,
+    on_error: &mut dyn FnMut(Error),
+) {
+    for job in typed_sections::<SyncJobConfig>(config, "sync", on_error) {
+        if let Some(schedule) = job.schedule.clone() {
+            jobs.push(ScheduledJob {
+                job_type: "syncjob",
+                id: job.id.clone(),
+                store: job.store.clone(),
+                schedule,
+                exclude: job.schedule_exclude.clone().unwrap_or_default(),
+                config: ScheduledJobConfig::Sync(job),
+            });
+        }
+    }
+}
+
+fn add_verification_jobs(
+    jobs: &mut Vec<ScheduledJob>,
+    config: &SectionConfigData,
+    on_error: &mut dyn FnMut(Error),
+) {
+    for job in typed_sections::<VerificationJobConfig>(config, "verification", on_error) {
+        if let Some(schedule) = job.schedule.clone() {
+            jobs.push(ScheduledJob {
+                job_type: "verificationjob",
+                id: job.id.clone(),
+                store: job.store.clone(),
+                schedule,
+                exclude: job.schedule_exclude.clone().unwrap_or_default(),
+                config: ScheduledJobConfig::Verification(job),
+            });
+        }
+    }
+}
+
+fn add_tape_backup_jobs(
+    jobs: &mut Vec<ScheduledJob>,
+    config: &SectionConfigData,
+    on_error: &mut dyn FnMut(Error),
+) {
+    for job in typed_sections::<TapeBackupJobConfig>(config, "backup", on_error) {
+        if let Some(schedule) = job.schedule.clone() {
+            jobs.push(ScheduledJob {
+                job_type: "tape-backup-job",
+                id: job.id.clone(),
+                store: job.setup.store.clone(),
+                schedule,
+                exclude: job.schedule_exclude.clone().unwrap_or_default(),
+                config: ScheduledJobConfig::TapeBackup(job),
+            });
+        }
+    }
+}
+
+/// Collect all jobs with a schedule from the job configs.
+///
+/// Configs which cannot be read and invalid job entries are passed to `on_error` and skipped, so
+/// the other jobs can still be scheduled.
+pub fn scheduled_jobs(mut on_error: impl FnMut(Error)) -> Vec<ScheduledJob> {
+    let mut jobs = Vec::new();
+
+    match pbs_config::datastore::config() {
+        Ok((config, _digest)) => add_datastore_jobs(&mut jobs, &config, &mut on_error),
+        Err(err) => on_error(format_err!("unable to read datastore config - {err}")),
+    }
+    match pbs_config::prune::config() {
+        Ok((config, _digest)) => add_prune_jobs(&mut jobs, &config, &mut on_error),
+        Err(err) => on_error(format_err!("unable to read prune job config - {err}")),
+    }
+    match pbs_config::sync::config() {
+        Ok((config, _digest)) => add_sync_jobs(&mut jobs, &config, &mut on_error),
+        Err(err) => on_error(format_err!("unable to read sync job config - {err}")),
+    }
+    match pbs_config::verify::config() {
+        Ok((config, _digest)) => add_verification_jobs(&mut jobs, &config, &mut on_error),
+        Err(err) => on_error(format_err!(
+            "unable to read verification job config - {err}"
+        )),
+    }
+    match pbs_config::tape_job::config() {
+        Ok((config, _digest)) => add_tape_backup_jobs(&mut jobs, &config, &mut on_error),
+        Err(err) => on_error(format_err!("unable to read tape job config - {err}")),
+    }
+
+    jobs
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn local_time(year: i32, month: i32, day: i32, hour: i32) -> i64 {
+        let mut tm = proxmox_time::localtime(0).unwrap();
+        tm.tm_year = year - 1900;
+        tm.tm_mon = month - 1;
+        tm.tm_mday = day;
+        tm.tm_hour = hour;
+        tm.tm_min = 0;
+        tm.tm_sec = 0;
+        tm.tm_isdst = -1;
+        proxmox_time::timelocal(&mut tm).unwrap()
+    }
+
+    fn calendar(name: &str, exclude: &[&str]) -> HolidayCalendarConfig {
+        HolidayCalendarConfig {
+            name: name.to_string(),
+            comment: None,
+            exclude: exclude.iter().map(|e| e.parse().unwrap()).collect(),
+        }
+    }
+
+    fn matches(exclusion: &str, time: i64, calendars: &SectionConfigData) -> bool {
+        let exclusion: ScheduleExclusion = exclusion.parse().unwrap();
+        exclusion_matches(&exclusion, time, calendars, &mut HashSet::new()).unwrap()
+    }
+
+    #[test]
+    fn test_parse_exclusion() {
+        for valid in [
+            "2024-12-24",
+            "2024-12-24..2024-12-26",
+            "last-fri",
+            "last-Sun",
+            "@holidays",
+        ] {
+            assert!(valid.parse::<ScheduleExclusion>().is_ok(), "{valid}");
+        }
+        for invalid in [
+            "last-sunshine",
+            "last-friday",
+            "last-",
+            "2024-13-01",
+            "2024-02-32",
+            "@",
+            "@foo bar",
+        ] {
+            assert!(invalid.parse::<ScheduleExclusion>().is_err(), "{invalid}");
+        }
+
+        let exclusion: ScheduleExclusion = "last-Fri".parse().unwrap();
+        assert_eq!(exclusion, ScheduleExclusion::LastWeekday(5));
+        assert_eq!(exclusion.to_string(), "last-fri");
+    }
+
+    #[test]
+    fn test_exclusion_matches() {
+        let calendars = SectionConfigData::new();
+
+        // 2024-12-24 is a tuesday
+        let time = local_time(2024, 12, 24, 13);
+        assert!(matches("2024-12-24", time, &calendars));
+        assert!(matches("2024-12-20..2024-12-31", time, &calendars));
+        assert!(!matches("2024-12-25..2024-12-31", time, &calendars));
+        assert!(matches("tue", time, &calendars));
+        assert!(!matches("wed", time, &calendars));
+        assert!(matches("*-12-24 03:00", time, &calendars));
+
+        // 2024-12-27 is the last friday of the month, 2024-12-20 is not
+        assert!(matches("last-fri", local_time(2024, 12, 27, 0), &calendars));
+        assert!(!matches(
+            "last-fri",
+            local_time(2024, 12, 20, 23),
+            &calendars
+        ));
+        // leap year
+        assert!(matches("last-thu", local_time(2024, 2, 29, 12), &calendars));
+    }
+
+    #[test]
+    fn test_calendar_exclusions() {
+        let mut calendars = SectionConfigData::new();
+        let add = |calendars: &mut SectionConfigData, config: HolidayCalendarConfig| {
+            calendars
+                .set_data(&config.name.clone(), "calendar", config)
+                .unwrap();
+        };
+        add(
+            &mut calendars,
+            calendar("christmas", &["2024-12-24..2024-12-26"]),
+        );
+        add(
+            &mut calendars,
+            calendar("holidays", &["@christmas", "2024-01-01"]),
+        );
+        add(&mut calendars, calendar("loop-a", &["@loop-b"]));
+        add(&mut calendars, calendar("loop-b", &["@loop-a"]));
+
+        let holidays: Vec<ScheduleExclusion> = vec!["@holidays".parse().unwrap()];
+        assert!(is_excluded_by(&holidays, local_time(2024, 12, 25, 2), &calendars).unwrap());
+        assert!(is_excluded_by(&holidays, local_time(2024, 1, 1, 2), &calendars).unwrap());
+        assert!(!is_excluded_by(&holidays, local_time(2024, 12, 27, 2), &calendars).unwrap());
+        assert!(check_schedule_exclusions_with(&holidays, &calendars).is_ok());
+
+        let missing: Vec<ScheduleExclusion> = vec!["@missing".parse().unwrap()];
+        assert!(is_excluded_by(&missing, local_time(2024, 1, 1, 2), &calendars).is_err());
+        assert!(check_schedule_exclusions_with(&missing, &calendars).is_err());
+
+        let looping: Vec<ScheduleExclusion> = vec!["@loop-a".parse().unwrap()];
+        assert!(is_excluded_by(&looping, local_time(2024, 1, 1, 2), &calendars).is_err());
+        assert!(check_schedule_exclusions_with(&looping, &calendars).is_err());
+    }
+
+    #[test]
+    fn test_scheduled_jobs() {
+        let mut datastores = SectionConfigData::new();
+        let add = |config: &mut SectionConfigData, ty: &str, id: &str, data: serde_json::Value| {
+            config
+                .sections
+                .insert(id.to_string(), (ty.to_string(), data));
+        };
+        add(
+            &mut datastores,
+            "datastore",
+            "store1",
+            serde_json::json!({
+                "name": "store1",
+                "path": "/store1",
+                "gc-schedule": "daily",
+                "archive-schedule": "weekly",
+            }),
+        );
+        add(
+            &mut datastores,
+            "datastore",
+            "store2",
+            serde_json::json!({ "name": "store2", "path": "/store2" }),
+        );
+
+        let mut prune = SectionConfigData::new();
+        add(
+            &mut prune,
+            "prune",
+            "keep-last",
+            serde_json::json!({
+                "id": "keep-last",
+                "store": "store1",
+                "schedule": "hourly",
+                "keep-last": 3,
+            }),
+        );
+        add(
+            &mut prune,
+            "prune",
+            "keep-all",
+            serde_json::json!({ "id": "keep-all", "store": "store1", "schedule": "hourly" }),
+        );
+        add(
+            &mut prune,
+            "prune",
+            "disabled",
+            serde_json::json!({
+                "id": "disabled",
+                "store": "store1",
+                "schedule": "hourly",
+                "keep-last": 3,
+                "disable": true,
+            }),
+        );
+        add(
+            &mut prune,
+            "prune",
+            "broken",
+            serde_json::json!({ "id": "broken" }),
+        );
+
+        let mut jobs = Vec::new();
+        let mut errors = Vec::new();
+        add_datastore_jobs(&mut jobs, &datastores, &mut |err| errors.push(err));
+        add_prune_jobs(&mut jobs, &prune, &mut |err| errors.push(err));
+
+        let mut list: Vec<(&str, &str, &str)> = jobs
+            .iter()
+            .map(|job| (job.job_type, job.id.as_str(), job.schedule.as_str()))
+            .collect();
+        list.sort_unstable();
+        // archive jobs need an archive store and age, prune jobs something to prune
+        assert_eq!(
+            list,
+            vec![
+                ("garbage_collection", "store1", "daily"),
+                ("prunejob", "keep-last", "hourly"),
+            ]
+        );
+        assert_eq!(errors.len(), 1);
+    }
+}
@@ -0,0 +1,160 @@
+//! Space watermarks: automatic prune and garbage collection for datastores running full
+
+use std::sync::Arc;
+
+use anyhow::{bail, Error};
+
+use proxmox_sys::{task_log, task_warn};
+
+use pbs_api_types::{Authid, DataStoreConfig, PruneJobConfig};
+use pbs_datastore::DataStore;
+use proxmox_rest_server::WorkerTask;
+
+use crate::server::jobstate::{self, Job, JobState};
+use crate::server::prune_datastore;
+
+pub const SPACE_WATERMARK_WORKER_TYPE: &str = "space-watermark";
+
+/// Minimum time between two runs triggered by the low watermark, in seconds
+const LOW_WATERMARK_INTERVAL: i64 = 6 * 3600;
+/// Minimum time between two runs triggered by the critical watermark, in seconds
+const CRITICAL_WATERMARK_INTERVAL: i64 = 3600;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SpaceLevel {
+    Ok,
+    Low,
+    Critical,
+}
+
+/// Returns the percentage of used space of a datastore's file system.
+pub async fn datastore_usage(datastore: &DataStore) -> Result<f64, Error> {
+    let info = crate::tools::fs::fs_info(datastore.base_path()).await?;
+    let total = info.used + info.available;
+    if total == 0 {
+        return Ok(0.0);
+    }
+    Ok((info.used as f64) * 100.0 / (total as f64))
+}
+
+/// Returns the watermark level the usage (in percent) is at.
+pub fn space_level(config: &DataStoreConfig, usage: f64) -> SpaceLevel {
+    match (config.low_space_watermark, config.critical_space_watermark) {
+        (_, Some(critical)) if usage >= critical as f64 => SpaceLevel::Critical,
+        (Some(low), _) if usage >= low as f64 => SpaceLevel::Low,
+        _ => SpaceLevel::Ok,
+    }
+}
+
+/// Refuse new backups if the datastore is above its critical watermark and configured to do so.
+pub async fn check_backup_space(
+    config: &DataStoreConfig,
+    datastore: &DataStore,
+) -> Result<(), Error> {
+    if !config.critical_space_deny_backup.unwrap_or(false) {
+        return Ok(());
+    }
+    let usage = datastore_usage(datastore).await?;
+    if space_level(config, usage) == SpaceLevel::Critical {
+        proxmox_router::http_bail!(
+            INSUFFICIENT_STORAGE,
+            "datastore '{}' is {usage:.1}% full, above its critical watermark - new backups \
+            are refused until space is freed",
+            config.name,
+        );
+    }
+    Ok(())
+}
+
+/// Checks whether a watermark run is due for the datastore at `level`.
+pub fn watermark_run_due(store: &str, level: SpaceLevel, now: i64) -> Result<bool, Error> {
+    let interval = match level {
+        SpaceLevel::Ok => return Ok(false),
+        SpaceLevel::Low => LOW_WATERMARK_INTERVAL,
+        SpaceLevel::Critical => CRITICAL_WATERMARK_INTERVAL,
+    };
+    match JobState::load(SPACE_WATERMARK_WORKER_TYPE, store)? {
+        JobState::Created { .. } => Ok(true), // never ran
+        JobState::Started { .. } => Ok(false),
+        JobState::Finished { .. } => {
+            let last = jobstate::last_run_time(SPACE_WATERMARK_WORKER_TYPE, store)?;
+            Ok(now - last >= interval)
+        }
+    }
+}
+
+/// Run the prune jobs configured for the datastore, followed by a garbage collection.
+///
+/// Protected snapshots are never removed by prune, so they stay untouched.
+pub fn do_space_watermark_job(
+    mut job: Job,
+    datastore: Arc<DataStore>,
+    usage: f64,
+    level: SpaceLevel,
+    auth_id: &Authid,
+) -> Result<String, Error> {
+    let store = datastore.name().to_string();
+    let auth_id = auth_id.clone();
+
+    let (config, _digest) = pbs_config::prune::config()?;
+    let prune_jobs: Vec<PruneJobConfig> = config
+        .convert_to_typed_array::<PruneJobConfig>("prune")?
+        .into_iter()
+        .filter(|job| job.store == store && !job.disable && job.options.keeps_something())
+        .collect();
+
+    let worker_type = job.jobtype().to_string();
+    let upid_str = WorkerTask::new_thread(
+        &worker_type,
+        Some(store.clone()),
+        auth_id.to_string(),
+        false,
+        move |worker| {
+            job.start(&worker.upid().to_string())?;
+
+            let watermark = match level {
+                SpaceLevel::Critical => "critical",
+                _ => "low",
+            };
+            task_log!(
+                worker,
+                "datastore {store} is {usage:.1}% full, above its {watermark} space watermark"
+            );
+
+            let result = proxmox_lang::try_block!({
+                if prune_jobs.is_empty() {
+                    task_log!(worker, "no prune jobs configured for datastore {store}");
+                }
+                for prune_job in prune_jobs {
+                    worker.check_abort()?;
+                    task_log!(worker, "running prune job '{}'", prune_job.id);
+                    if let Err(err) = prune_datastore(
+                        worker.clone(),
+                        auth_id.clone(),
+                        prune_job.options,
+                        datastore.clone(),
+                        false,
+                    ) {
+                        task_warn!(worker, "prune job '{}' failed - {err}", prune_job.id);
+                    }
+                }
+
+                if datastore.garbage_collection_running() {
+                    bail!("garbage collection already running");
+                }
+                task_log!(worker, "starting garbage collection on store {store}");
+                datastore.garbage_collection(&*worker, worker.upid())
+            });
+
+            let status = worker.create_state(&result);
+
+            if let Err(err) = job.finish(status) {
+                eprintln!("could not finish job state for {}: {err}", job.jobtype());
+            }
+
+            result
+        },
+    )?;
+
+    Ok(upid_str)
+}
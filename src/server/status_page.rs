@@ -0,0 +1,347 @@
+//! Unauthenticated status page for external uptime monitors
+//!
+//! If a `status-page-path` is set in the node configuration, the proxy answers `GET` requests to
+//! that path with a minimal JSON document without requiring authentication, so monitors do not
+//! need to hold any credentials. It only reveals that the service is up, its version and one
+//! health flag per datastore, ordered by datastore name but without the names themselves.
+//!
+//! At most `status-page-rate-limit` requests per minute and client address are answered, all
+//! others get a `429 Too Many Requests`.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use anyhow::Error;
+use hyper::service::Service;
+use hyper::{header, Body, Method, Request, Response, StatusCode};
+use serde::Serialize;
+use tokio_openssl::SslStream;
+
+use proxmox_http::RateLimitedStream;
+
+use pbs_api_types::Operation;
+use pbs_datastore::DataStore;
+
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
+// expired rate limit windows are only dropped once this many clients are tracked
+const RATE_LIMIT_CLEANUP_THRESHOLD: usize = 1024;
+
+/// Connections which know the address of their peer.
+pub trait PeerAddress {
+    fn peer_addr(&self) -> Result<SocketAddr, Error>;
+}
+
+impl PeerAddress for tokio::net::TcpStream {
+    fn peer_addr(&self) -> Result<SocketAddr, Error> {
+        Ok(tokio::net::TcpStream::peer_addr(self)?)
+    }
+}
+
+impl<S: PeerAddress> PeerAddress for RateLimitedStream<S> {
+    fn peer_addr(&self) -> Result<SocketAddr, Error> {
+        self.inner().peer_addr()
+    }
+}
+
+impl<S: PeerAddress> PeerAddress for SslStream<S> {
+    fn peer_addr(&self) -> Result<SocketAddr, Error> {
+        self.get_ref().peer_addr()
+    }
+}
+
+impl<S: PeerAddress> PeerAddress for Pin<Box<S>> {
+    fn peer_addr(&self) -> Result<SocketAddr, Error> {
+        self.as_ref().get_ref().peer_addr()
+    }
+}
+
+/// IPv4 clients connect to the dual stack listener with mapped addresses, count them like plain
+/// IPv4 addresses. Connections with an unknown peer all share one rate limit.
+fn client_address(peer: Result<SocketAddr, Error>) -> IpAddr {
+    match peer.map(|addr| addr.ip()) {
+        Ok(IpAddr::V6(addr)) => match addr.to_ipv4_mapped() {
+            Some(addr) => IpAddr::V4(addr),
+            None => IpAddr::V6(addr),
+        },
+        Ok(addr) => addr,
+        Err(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    }
+}
+
+#[derive(Serialize)]
+struct Status {
+    /// `ok` if all datastores are healthy, `degraded` otherwise
+    status: &'static str,
+    version: &'static str,
+    /// Health of the datastores, ordered by their name
+    datastores: Vec<bool>,
+}
+
+/// A datastore is healthy if it is available for reading and its file system can be queried.
+async fn datastore_healthy(store: &str) -> bool {
+    match DataStore::lookup_datastore(store, Some(Operation::Read)) {
+        Ok(datastore) => crate::tools::fs::fs_info(datastore.base_path())
+            .await
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+async fn collect_status() -> Result<Status, Error> {
+    let (config, _digest) = pbs_config::datastore::config()?;
+    let mut stores: Vec<&String> = config.sections.keys().collect();
+    stores.sort();
+
+    let mut datastores = Vec::with_capacity(stores.len());
+    for store in stores {
+        datastores.push(datastore_healthy(store).await);
+    }
+
+    Ok(Status {
+        status: if datastores.iter().all(|healthy| *healthy) {
+            "ok"
+        } else {
+            "degraded"
+        },
+        version: pbs_buildcfg::PROXMOX_PKG_VERSION,
+        datastores,
+    })
+}
+
+fn json_response(status: StatusCode, body: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::CACHE_CONTROL, "no-store")
+        .body(body.into())
+        .unwrap()
+}
+
+async fn status_response() -> Response<Body> {
+    match collect_status().await {
+        Ok(status) => json_response(
+            StatusCode::OK,
+            serde_json::to_string(&status).unwrap_or_default(),
+        ),
+        Err(err) => {
+            // don't leak details to unauthenticated clients
+            log::error!("unable to collect status page data - {err}");
+            json_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                r#"{"status":"error"}"#.to_string(),
+            )
+        }
+    }
+}
+
+/// Configuration and rate limit state of the status page, shared by all connections.
+pub struct StatusPage {
+    path: String,
+    rate_limit: u32,
+    /// Start and request count of the current rate limit window per client address
+    windows: Mutex<HashMap<IpAddr, (Instant, u32)>>,
+}
+
+impl StatusPage {
+    /// Serve the status page on `path`, answering at most `rate_limit` requests per minute and
+    /// client address.
+    pub fn new(path: String, rate_limit: u32) -> Self {
+        Self {
+            path,
+            rate_limit,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn matches(&self, req: &Request<Body>) -> bool {
+        req.uri().path().trim_end_matches('/') == self.path.trim_end_matches('/')
+    }
+
+    fn try_acquire(&self, client: IpAddr) -> bool {
+        let mut windows = self.windows.lock().unwrap();
+        let now = Instant::now();
+
+        if windows.len() >= RATE_LIMIT_CLEANUP_THRESHOLD {
+            windows.retain(|_, window| now.duration_since(window.0) < RATE_LIMIT_WINDOW);
+        }
+
+        let window = windows.entry(client).or_insert((now, 0));
+        if now.duration_since(window.0) >= RATE_LIMIT_WINDOW {
+            *window = (now, 0);
+        }
+        if window.1 >= self.rate_limit {
+            return false;
+        }
+        window.1 += 1;
+        true
+    }
+
+    async fn handle(self: Arc<Self>, method: Method, client: IpAddr) -> Response<Body> {
+        if method != Method::GET && method != Method::HEAD {
+            return Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .header(header::ALLOW, "GET, HEAD")
+                .body(Body::empty())
+                .unwrap();
+        }
+        if !self.try_acquire(client) {
+            return json_response(
+                StatusCode::TOO_MANY_REQUESTS,
+                r#"{"status":"rate-limited"}"#.to_string(),
+            );
+        }
+        let mut response = status_response().await;
+        if method == Method::HEAD {
+            *response.body_mut() = Body::empty();
+        }
+        response
+    }
+}
+
+/// Wraps the per-connection services of a REST server with [`StatusPageService`].
+#[derive(Clone)]
+pub struct StatusPageMakeService<S> {
+    inner: S,
+    page: Option<Arc<StatusPage>>,
+}
+
+impl<S> StatusPageMakeService<S> {
+    /// Answer requests to the status page before passing them on to `inner`, which serves all
+    /// others. Without a `page`, all requests are passed on.
+    pub fn new(inner: S, page: Option<StatusPage>) -> Self {
+        Self {
+            inner,
+            page: page.map(Arc::new),
+        }
+    }
+}
+
+impl<'a, C, S> Service<&'a C> for StatusPageMakeService<S>
+where
+    C: PeerAddress,
+    S: Service<&'a C>,
+    S::Future: Send + 'static,
+{
+    type Response = StatusPageService<S::Response>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, conn: &'a C) -> Self::Future {
+        let page = self.page.clone();
+        let client = client_address(conn.peer_addr());
+        let future = self.inner.call(conn);
+        Box::pin(async move {
+            let inner = future.await?;
+            Ok(StatusPageService {
+                inner,
+                page,
+                client,
+            })
+        })
+    }
+}
+
+/// Answers the status page requests of a single connection.
+pub struct StatusPageService<S> {
+    inner: S,
+    page: Option<Arc<StatusPage>>,
+    client: IpAddr,
+}
+
+impl<S> Service<Request<Body>> for StatusPageService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        match &self.page {
+            Some(page) if page.matches(&req) => {
+                let page = Arc::clone(page);
+                let method = req.method().clone();
+                let client = self.client;
+                Box::pin(async move { Ok(page.handle(method, client).await) })
+            }
+            _ => Box::pin(self.inner.call(req)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rate_limit() {
+        let page = StatusPage::new("/status".to_string(), 2);
+        let client: IpAddr = "192.0.2.1".parse().unwrap();
+        let other: IpAddr = "192.0.2.2".parse().unwrap();
+
+        assert!(page.try_acquire(client));
+        assert!(page.try_acquire(client));
+        assert!(!page.try_acquire(client));
+
+        // other clients are not affected by a flooding one
+        assert!(page.try_acquire(other));
+
+        page.windows.lock().unwrap().get_mut(&client).unwrap().0 -= RATE_LIMIT_WINDOW;
+        assert!(page.try_acquire(client));
+    }
+
+    #[test]
+    fn test_rate_limit_cleanup() {
+        let page = StatusPage::new("/status".to_string(), 1);
+        for i in 0..RATE_LIMIT_CLEANUP_THRESHOLD as u32 {
+            assert!(page.try_acquire(IpAddr::V4(i.into())));
+        }
+        for window in page.windows.lock().unwrap().values_mut() {
+            window.0 -= RATE_LIMIT_WINDOW;
+        }
+
+        let client: IpAddr = "192.0.2.1".parse().unwrap();
+        assert!(page.try_acquire(client));
+        assert_eq!(page.windows.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_client_address() {
+        let mapped: SocketAddr = "[::ffff:192.0.2.1]:1234".parse().unwrap();
+        let plain: SocketAddr = "192.0.2.1:4321".parse().unwrap();
+        assert_eq!(client_address(Ok(mapped)), client_address(Ok(plain)));
+
+        let v6: SocketAddr = "[2001:db8::1]:1234".parse().unwrap();
+        assert_eq!(client_address(Ok(v6)), v6.ip());
+
+        assert_eq!(
+            client_address(Err(anyhow::format_err!("unknown peer"))),
+            IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+        );
+    }
+
+    #[test]
+    fn test_path_match() {
+        let page = StatusPage::new("/status/".to_string(), 1);
+        let req = |uri: &str| Request::get(uri).body(Body::empty()).unwrap();
+        assert!(page.matches(&req("/status")));
+        assert!(page.matches(&req("/status/?foo=bar")));
+        assert!(!page.matches(&req("/status/more")));
+        assert!(!page.matches(&req("/")));
+    }
+}
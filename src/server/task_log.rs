@@ -0,0 +1,435 @@
+//! Compressed storage of finished task logs
+//!
+//! The task log rotation compresses the logs of finished tasks into `<log>.zst`, replacing the
+//! plain file. The log is split into zstd frames of about [`FRAME_SIZE`] bytes, cut at line ends,
+//! and an index of the offset and first line number of every frame is stored in a trailing
+//! skippable frame. So ranges of lines can be read by decompressing only the frames containing
+//! them, while the file stays a valid zstd stream, readable with `zstd -d`.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::fs::{FileExt, MetadataExt};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, format_err, Error};
+
+use proxmox_rest_server::{upid_log_path, upid_read_status, TaskListInfoIterator, TaskState};
+use proxmox_sys::fs::{make_tmp_file, CreateOptions};
+use proxmox_sys::{task_log, task_warn, WorkerTaskContext};
+
+use pbs_api_types::UPID;
+
+/// Uncompressed size of the frames of a compressed task log.
+pub const FRAME_SIZE: usize = 1024 * 1024;
+
+const COMPRESSION_LEVEL: i32 = 3;
+
+// zstd skippable frame magic, the low 4 bits are free to use
+const SKIPPABLE_FRAME_MAGIC: u32 = 0x184D2A5E;
+
+// openssl::sha::sha256(b"Proxmox Backup compressed task log index v1.0")[0..8];
+const INDEX_MAGIC_1_0: [u8; 8] = [29, 255, 3, 170, 252, 155, 245, 70];
+
+// frame count, total lines and magic
+const INDEX_TAIL_SIZE: u64 = 24;
+
+const TASK_LOG_DIR: &str = concat!(pbs_buildcfg::PROXMOX_BACKUP_LOG_DIR_M!(), "/tasks");
+
+/// Returns the path of the compressed version of the task log at `path`.
+pub fn compressed_log_path(path: &Path) -> PathBuf {
+    let mut path = path.as_os_str().to_owned().into_vec();
+    path.extend_from_slice(b".zst");
+    PathBuf::from(std::ffi::OsString::from_vec(path))
+}
+
+struct FrameInfo {
+    offset: u64,
+    // number of lines before the frame
+    first_line: u64,
+}
+
+/// A task log compressed into independent zstd frames.
+pub struct CompressedTaskLog {
+    file: File,
+    frames: Vec<FrameInfo>,
+    data_end: u64,
+    total_lines: u64,
+}
+
+impl CompressedTaskLog {
+    /// Open a compressed task log and read its index.
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let corrupt = || format_err!("compressed task log {path:?} has no valid index");
+
+        if len < INDEX_TAIL_SIZE + 8 {
+            return Err(corrupt());
+        }
+        let mut tail = [0u8; INDEX_TAIL_SIZE as usize];
+        file.read_exact_at(&mut tail, len - INDEX_TAIL_SIZE)?;
+        if tail[16..24] != INDEX_MAGIC_1_0 {
+            return Err(corrupt());
+        }
+        let frame_count = u64::from_le_bytes(tail[0..8].try_into().unwrap());
+        let total_lines = u64::from_le_bytes(tail[8..16].try_into().unwrap());
+
+        let index_size = frame_count
+            .checked_mul(16)
+            .and_then(|size| size.checked_add(INDEX_TAIL_SIZE))
+            .filter(|size| size + 8 <= len)
+            .ok_or_else(corrupt)?;
+        let data_end = len - index_size - 8;
+
+        let mut header = [0u8; 8];
+        file.read_exact_at(&mut header, data_end)?;
+        if u32::from_le_bytes(header[0..4].try_into().unwrap()) != SKIPPABLE_FRAME_MAGIC
+            || u32::from_le_bytes(header[4..8].try_into().unwrap()) as u64 != index_size
+        {
+            return Err(corrupt());
+        }
+
+        let mut index = vec![0u8; (frame_count * 16) as usize];
+        file.read_exact_at(&mut index, data_end + 8)?;
+        let frames = index
+            .chunks_exact(16)
+            .map(|entry| FrameInfo {
+                offset: u64::from_le_bytes(entry[0..8].try_into().unwrap()),
+                first_line: u64::from_le_bytes(entry[8..16].try_into().unwrap()),
+            })
+            .collect();
+
+        Ok(Self {
+            file,
+            frames,
+            data_end,
+            total_lines,
+        })
+    }
+
+    /// The number of lines of the log.
+    pub fn total_lines(&self) -> u64 {
+        self.total_lines
+    }
+
+    /// The number of frames of the log.
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Read and decompress a single frame.
+    pub fn read_frame(&self, frame: usize) -> Result<Vec<u8>, Error> {
+        let start = self.frames[frame].offset;
+        let end = self
+            .frames
+            .get(frame + 1)
+            .map(|next| next.offset)
+            .unwrap_or(self.data_end);
+        if end < start {
+            bail!("compressed task log has an invalid frame index");
+        }
+
+        let mut compressed = vec![0u8; (end - start) as usize];
+        self.file.read_exact_at(&mut compressed, start)?;
+        Ok(zstd::stream::decode_all(&compressed[..])?)
+    }
+
+    /// Read up to `limit` lines, starting at line number `start` (counted from 1).
+    ///
+    /// Returns the line numbers and lines, only the frames containing them are decompressed.
+    pub fn read_lines(&self, start: u64, limit: Option<u64>) -> Result<Vec<(u64, String)>, Error> {
+        let start = start.max(1);
+        let mut lines = Vec::new();
+
+        // the last frame starting before the first requested line
+        let first_frame = self
+            .frames
+            .partition_point(|frame| frame.first_line < start)
+            .saturating_sub(1);
+
+        for frame in first_frame..self.frames.len() {
+            let data = self.read_frame(frame)?;
+            let mut count = self.frames[frame].first_line;
+            for line in data.as_slice().lines() {
+                count += 1;
+                if count < start {
+                    continue;
+                }
+                if limit.map(|limit| lines.len() as u64 >= limit) == Some(true) {
+                    return Ok(lines);
+                }
+                lines.push((count, line?));
+            }
+        }
+
+        Ok(lines)
+    }
+}
+
+/// Compress the task log at `path` into `target`.
+///
+/// The log is compressed frame by frame into a temporary file, which replaces `target` once
+/// complete. The modification time of the log is kept, as it is the end time of the task.
+pub fn compress_task_log(path: &Path, target: &Path, options: CreateOptions) -> Result<(), Error> {
+    let source = File::open(path)?;
+    let metadata = source.metadata()?;
+    let mut reader = BufReader::new(source);
+
+    let (file, tmp_path) = make_tmp_file(target, options)?;
+    let result = write_compressed_log(&mut reader, file, &metadata)
+        .and_then(|()| Ok(std::fs::rename(&tmp_path, target)?));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+fn write_compressed_log<R: BufRead>(
+    reader: &mut R,
+    file: File,
+    metadata: &std::fs::Metadata,
+) -> Result<(), Error> {
+    let mut output = BufWriter::new(file);
+    let mut offset: u64 = 0;
+    let mut index = Vec::new();
+    let mut frame_count: u64 = 0;
+    let mut total_lines: u64 = 0;
+    let mut frame_first_line: u64 = 0;
+    let mut buffer = Vec::with_capacity(FRAME_SIZE + 4096);
+
+    let mut flush = |buffer: &mut Vec<u8>, first_line: u64| -> Result<(), Error> {
+        index.extend_from_slice(&offset.to_le_bytes());
+        index.extend_from_slice(&first_line.to_le_bytes());
+        let frame = zstd::bulk::compress(buffer, COMPRESSION_LEVEL)?;
+        output.write_all(&frame)?;
+        offset += frame.len() as u64;
+        frame_count += 1;
+        buffer.clear();
+        Ok(())
+    };
+
+    loop {
+        let read = reader.read_until(b'\n', &mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        total_lines += 1;
+        if buffer.len() >= FRAME_SIZE {
+            flush(&mut buffer, frame_first_line)?;
+            frame_first_line = total_lines;
+        }
+    }
+    if !buffer.is_empty() {
+        flush(&mut buffer, frame_first_line)?;
+    }
+
+    let index_size = index.len() as u64 + INDEX_TAIL_SIZE;
+    output.write_all(&SKIPPABLE_FRAME_MAGIC.to_le_bytes())?;
+    output.write_all(&(index_size as u32).to_le_bytes())?;
+    output.write_all(&index)?;
+    output.write_all(&frame_count.to_le_bytes())?;
+    output.write_all(&total_lines.to_le_bytes())?;
+    output.write_all(&INDEX_MAGIC_1_0)?;
+
+    let file = output.into_inner().map_err(|err| err.into_error())?;
+    let mtime = libc::timespec {
+        tv_sec: metadata.mtime(),
+        tv_nsec: metadata.mtime_nsec(),
+    };
+    let times = [mtime, mtime];
+    nix::errno::Errno::result(unsafe { libc::futimens(file.as_raw_fd(), &times[0]) })?;
+    file.sync_all()?;
+
+    Ok(())
+}
+
+/// Read the state of a finished task from the end of its log, plain or compressed.
+///
+/// Like [`upid_read_status`], the modification time of the log is used as end time.
+pub fn read_task_status(upid: &UPID) -> Result<TaskState, Error> {
+    let err = match upid_read_status(upid) {
+        Ok(state) => return Ok(state),
+        Err(err) => err,
+    };
+    let path = compressed_log_path(&upid_log_path(upid)?);
+    if !path.exists() {
+        return Err(err);
+    }
+
+    let log = CompressedTaskLog::open(&path)?;
+    let endtime = log.file.metadata()?.mtime();
+    let last_line = match log.frame_count() {
+        0 => None,
+        count => {
+            let data = log.read_frame(count - 1)?;
+            data.as_slice().lines().last().transpose()?
+        }
+    };
+
+    Ok(last_line
+        .and_then(|line| parse_task_result(&line, endtime))
+        .unwrap_or(TaskState::Unknown {
+            endtime: upid.starttime,
+        }))
+}
+
+// parses the closing line of a task log, like 'TASK WARNINGS: 2'
+fn parse_task_result(line: &str, endtime: i64) -> Option<TaskState> {
+    let result = line.strip_prefix("TASK ")?;
+    if result == "OK" {
+        Some(TaskState::OK { endtime })
+    } else if let Some(count) = result.strip_prefix("WARNINGS: ") {
+        Some(TaskState::Warning {
+            count: count.parse().ok()?,
+            endtime,
+        })
+    } else {
+        result
+            .strip_prefix("ERROR: ")
+            .map(|message| TaskState::Error {
+                message: message.to_string(),
+                endtime,
+            })
+    }
+}
+
+/// Read the whole log of a task, plain or compressed.
+pub fn read_task_log(upid: &UPID) -> Result<Vec<u8>, Error> {
+    let path = upid_log_path(upid)?;
+    match std::fs::read(&path) {
+        Ok(data) => Ok(data),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            let log = CompressedTaskLog::open(&compressed_log_path(&path))?;
+            let mut data = Vec::new();
+            for frame in 0..log.frame_count() {
+                data.extend_from_slice(&log.read_frame(frame)?);
+            }
+            Ok(data)
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Compress the logs of all finished tasks, and remove compressed logs of tasks no longer in
+/// the task archive.
+pub fn compress_finished_task_logs(
+    worker: &dyn WorkerTaskContext,
+    options: CreateOptions,
+) -> Result<(), Error> {
+    let mut known = HashSet::new();
+    let mut compressed = 0;
+
+    for info in TaskListInfoIterator::new(false)? {
+        let info = info?;
+        known.insert(info.upid_str.clone());
+        if info.state.is_none() {
+            continue;
+        }
+        worker.check_abort()?;
+
+        let path = upid_log_path(&info.upid)?;
+        if !path.exists() {
+            continue;
+        }
+        let target = compressed_log_path(&path);
+        if let Err(err) = compress_task_log(&path, &target, options.clone()) {
+            task_warn!(worker, "unable to compress task log {path:?} - {err}");
+            let _ = std::fs::remove_file(&target);
+            continue;
+        }
+        if let Err(err) = std::fs::remove_file(&path) {
+            task_warn!(worker, "unable to remove task log {path:?} - {err}");
+        }
+        compressed += 1;
+    }
+    task_log!(worker, "compressed {compressed} task logs");
+
+    for dir in std::fs::read_dir(TASK_LOG_DIR)? {
+        let dir = dir?;
+        if !dir.file_type()?.is_dir() {
+            continue;
+        }
+        for entry in std::fs::read_dir(dir.path())? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let upid_str = match name.to_str().and_then(|name| name.strip_suffix(".zst")) {
+                Some(upid_str) => upid_str,
+                None => continue,
+            };
+            if !known.contains(upid_str) {
+                if let Err(err) = std::fs::remove_file(entry.path()) {
+                    task_warn!(worker, "unable to remove old task log {upid_str} - {err}");
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_compressed_task_log() -> Result<(), Error> {
+        let dir = std::env::temp_dir().join(format!("pbs-task-log-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join("log");
+        let target = compressed_log_path(&path);
+
+        let mut data = String::new();
+        for n in 1..=50_000 {
+            data.push_str(&format!("line {n} {}\n", "x".repeat(n % 100)));
+        }
+        std::fs::write(&path, &data)?;
+
+        compress_task_log(&path, &target, CreateOptions::new())?;
+        let log = CompressedTaskLog::open(&target)?;
+        assert_eq!(log.total_lines(), 50_000);
+        assert!(log.frame_count() > 1);
+
+        let lines = log.read_lines(30_000, Some(3))?;
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].0, 30_000);
+        assert!(lines[0].1.starts_with("line 30000 "));
+        assert_eq!(log.read_lines(0, None)?.len(), 50_000);
+
+        // the whole file is a valid zstd stream
+        let decoded = zstd::stream::decode_all(&std::fs::read(&target)?[..])?;
+        assert_eq!(decoded, data.as_bytes());
+
+        // the end time of the task is kept
+        assert_eq!(
+            std::fs::metadata(&target)?.mtime(),
+            std::fs::metadata(&path)?.mtime()
+        );
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_task_result() {
+        assert!(matches!(
+            parse_task_result("TASK OK", 10),
+            Some(TaskState::OK { endtime: 10 })
+        ));
+        assert!(matches!(
+            parse_task_result("TASK WARNINGS: 3", 10),
+            Some(TaskState::Warning {
+                count: 3,
+                endtime: 10
+            })
+        ));
+        assert!(matches!(
+            parse_task_result("TASK ERROR: no space left on device", 10),
+            Some(TaskState::Error { message, endtime: 10 }) if message == "no space left on device"
+        ));
+        assert!(parse_task_result("TASK WARNINGS: many", 10).is_none());
+        assert!(parse_task_result("some log line", 10).is_none());
+    }
+}
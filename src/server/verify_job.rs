@@ -1,15 +1,132 @@
-use anyhow::{format_err, Error};
+use std::sync::Arc;
 
-use pbs_api_types::{Authid, Operation, VerificationJobConfig};
+use anyhow::{bail, format_err, Error};
+use hyper::{Body, Request};
+use serde_json::json;
+
+use pbs_api_types::{
+    Authid, JobErrorClass, Operation, Remote, SnapshotVerifyOutcome, VerificationJobConfig,
+    VerifyAttestation, VerifyJobResult, VerifyReport, VerifyRunStatistics,
+};
 use pbs_datastore::DataStore;
 use proxmox_rest_server::WorkerTask;
-use proxmox_sys::task_log;
+use proxmox_sys::{task_log, task_warn};
 
 use crate::{
-    backup::{verify_all_backups, verify_filter},
-    server::jobstate::Job,
+    api2::config::remote::remote_client,
+    backup::{
+        verify_all_backups, verify_filter,
+        verify_report::{
+            check_verify_report, generate_verify_attestation, store_verify_attestation,
+            ReplicaVerifyState,
+        },
+        verify_result::store_verify_job_result,
+    },
+    server::{
+        job_error::classify_error,
+        jobstate::{Job, JobRunStatistics},
+    },
 };
 
+/// Fetch and check the verify report of the replica configured for a verification job.
+async fn fetch_replica_state(
+    verification_job: &VerificationJobConfig,
+    replica: &str,
+) -> Result<ReplicaVerifyState, Error> {
+    let (remote_config, _digest) = pbs_config::remote::config()?;
+    let remote: Remote = remote_config.lookup("remote", replica)?;
+
+    let store = verification_job
+        .replica_store
+        .as_deref()
+        .unwrap_or(&verification_job.store);
+    let local_ns = verification_job.ns.clone().unwrap_or_default();
+    let remote_ns = verification_job
+        .replica_ns
+        .clone()
+        .unwrap_or_else(|| local_ns.clone());
+
+    let mut param = json!({});
+    if !remote_ns.is_root() {
+        param["ns"] = serde_json::to_value(&remote_ns)?;
+    }
+    if let Some(max_depth) = verification_job.max_depth {
+        param["max-depth"] = max_depth.into();
+    }
+
+    let client = remote_client(&remote, None).await?;
+    let mut result = client
+        .get(
+            &format!("api2/json/admin/datastore/{store}/verify-report"),
+            Some(param),
+        )
+        .await?;
+    let report: VerifyReport = serde_json::from_value(result["data"].take())?;
+
+    let fingerprint =
+        remote.config.fingerprint.as_deref().ok_or_else(|| {
+            format_err!("replica remote '{replica}' has no fingerprint configured")
+        })?;
+    check_verify_report(&report, fingerprint)?;
+
+    ReplicaVerifyState::from_report(
+        replica,
+        report,
+        &remote_ns,
+        &local_ns,
+        verification_job.outdated_after,
+    )
+}
+
+/// Post a verify attestation to a webhook.
+async fn send_verify_attestation(url: &str, attestation: &VerifyAttestation) -> Result<(), Error> {
+    let (node_config, _digest) = crate::config::node::config()?;
+    let client = crate::tools::pbs_simple_http(node_config.http_proxy());
+
+    let request = Request::post(url)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_vec(attestation)?))?;
+
+    let response = client.request(request).await?;
+    if !response.status().is_success() {
+        bail!("webhook returned status {}", response.status());
+    }
+
+    Ok(())
+}
+
+/// Generate, store and ship the attestation of a finished verification job run.
+fn attest_verification_job(
+    worker: &WorkerTask,
+    datastore: &Arc<DataStore>,
+    verification_job: &VerificationJobConfig,
+) -> Result<(), Error> {
+    let upid = worker.upid().to_string();
+    let attestation = generate_verify_attestation(
+        datastore,
+        verification_job.ns.clone().unwrap_or_default(),
+        verification_job
+            .max_depth
+            .unwrap_or(pbs_api_types::MAX_NAMESPACE_DEPTH),
+        &verification_job.id,
+        &upid,
+    )?;
+    store_verify_attestation(&attestation)?;
+    task_log!(
+        worker,
+        "stored signed attestation for {} verified snapshots",
+        attestation.snapshots.len()
+    );
+
+    if let Some(url) = verification_job.attestation_webhook.as_deref() {
+        proxmox_async::runtime::block_on(send_verify_attestation(url, &attestation))
+            .map_err(|err| format_err!("sending attestation to '{url}' failed - {err}"))?;
+        task_log!(worker, "sent attestation to '{url}'");
+    }
+
+    Ok(())
+}
+
 /// Runs a verification job.
 pub fn do_verification_job(
     mut job: Job,
@@ -19,6 +136,8 @@ pub fn do_verification_job(
     to_stdout: bool,
 ) -> Result<String, Error> {
     let datastore = DataStore::lookup_datastore(&verification_job.store, Some(Operation::Read))?;
+    let attest = verification_job.attestation.unwrap_or(false)
+        || verification_job.attestation_webhook.is_some();
 
     let outdated_after = verification_job.outdated_after;
     let ignore_verified_snapshots = verification_job.ignore_verified.unwrap_or(true);
@@ -44,7 +163,35 @@ pub fn do_verification_job(
                 None => Default::default(),
             };
 
-            let verify_worker = crate::backup::VerifyWorker::new(worker.clone(), datastore);
+            let mut verify_worker =
+                crate::backup::VerifyWorker::new(worker.clone(), Arc::clone(&datastore))
+                    .with_exclude(verification_job.exclude.clone().unwrap_or_default())
+                    .with_priority(verification_job.priority.clone().unwrap_or_default());
+
+            if let Some(replica) = verification_job.replica.as_deref() {
+                task_log!(worker, "fetching verify report from replica '{replica}'");
+                let replica_state = proxmox_async::runtime::block_on(fetch_replica_state(
+                    &verification_job,
+                    replica,
+                ));
+                match replica_state {
+                    Ok(replica_state) => {
+                        task_log!(
+                            worker,
+                            "replica '{replica}' confirms {} snapshots",
+                            replica_state.len()
+                        );
+                        verify_worker = verify_worker.with_replica(replica_state);
+                    }
+                    Err(err) => {
+                        task_warn!(
+                            worker,
+                            "could not use verify report of replica '{replica}', verifying \
+                            all snapshots locally - {err}"
+                        );
+                    }
+                }
+            }
             let result = verify_all_backups(
                 &verify_worker,
                 worker.upid(),
@@ -70,9 +217,57 @@ pub fn do_verification_job(
                 Err(_) => Err(format_err!("verification failed - job aborted")),
             };
 
+            let verify_result = VerifyJobResult {
+                store: verification_job.store.clone(),
+                job: verification_job.id.clone(),
+                upid: worker.upid().to_string(),
+                starttime: worker.upid().starttime,
+                endtime: proxmox_time::epoch_i64(),
+                snapshots: verify_worker.take_results(),
+            };
+            let junit = verification_job.junit_report.unwrap_or(false);
+            if let Err(err) = store_verify_job_result(&verify_result, junit) {
+                task_warn!(worker, "could not store verification result - {err}");
+            }
+
+            if attest {
+                if let Err(err) = attest_verification_job(&worker, &datastore, &verification_job) {
+                    task_warn!(worker, "could not create verify attestation - {err}");
+                }
+            }
+
             let status = worker.create_state(&job_result);
 
-            if let Err(err) = job.finish(status) {
+            // failed snapshots are corrupt, the job itself only fails when aborted
+            let error_class = match &result {
+                Ok(failed_dirs) if failed_dirs.is_empty() => None,
+                Ok(_) => Some(JobErrorClass::DataCorruption),
+                Err(err) => Some(classify_error(err)),
+            };
+            if let Some(error_class) = error_class {
+                task_log!(worker, "error class: {error_class}");
+            }
+
+            let count = |outcome| {
+                verify_result
+                    .snapshots
+                    .iter()
+                    .filter(|snapshot| snapshot.outcome == outcome)
+                    .count() as u64
+            };
+            let statistics = JobRunStatistics {
+                snapshots: Some(verify_result.snapshots.len() as u64),
+                store: Some(verification_job.store.clone()),
+                verify: Some(VerifyRunStatistics {
+                    ok: count(SnapshotVerifyOutcome::Ok),
+                    failed: count(SnapshotVerifyOutcome::Failed),
+                    skipped: count(SnapshotVerifyOutcome::Skipped),
+                }),
+                error_class,
+                ..Default::default()
+            };
+
+            if let Err(err) = job.finish_with_statistics(status, statistics) {
                 eprintln!("could not finish job state for {}: {}", job.jobtype(), err);
             }
 
@@ -0,0 +1,458 @@
+//! Warm standby reader sessions
+//!
+//! A warm session keeps the index files of a snapshot in memory for a limited time, so that the
+//! many reader sessions of a live-restore or of starting many guests from the same snapshot do
+//! not need to open and read them again. Reader sessions for the snapshot of a warm session use
+//! it automatically, and every use extends its lifetime by its TTL. Sessions only exist in the
+//! proxy, which also serves the reader protocol.
+//!
+//! Sessions neither lock the snapshot nor count as operation on the datastore between reader
+//! sessions, so they do not block prunes or maintenance. The manifest is always read from disk,
+//! and the preloaded files are only used while they match the checksums in it.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Error};
+use lazy_static::lazy_static;
+
+use proxmox_sys::fs::lock_dir_noblock_shared;
+
+use pbs_api_types::{Authid, BackupNamespace, WarmReaderSessionInfo};
+use pbs_datastore::index::IndexFile;
+use pbs_datastore::manifest::{archive_type, ArchiveType, BackupManifest};
+use pbs_datastore::{BackupDir, DataStore};
+
+/// Maximum number of warm sessions, if not configured on the node.
+pub const DEFAULT_MAX_WARM_READER_SESSIONS: usize = 16;
+
+/// Lifetime of a session after its creation or last use, if not given.
+pub const DEFAULT_TTL: i64 = 600;
+
+/// A file of the snapshot, read into memory.
+pub struct WarmFile {
+    pub data: Vec<u8>,
+    /// The chunks referenced by an index file.
+    pub digests: Vec<[u8; 32]>,
+    // size and checksum in the manifest when the file was read
+    size: u64,
+    csum: [u8; 32],
+}
+
+/// The preloaded files of a snapshot.
+pub struct WarmReaderSession {
+    id: String,
+    store: String,
+    ns: BackupNamespace,
+    dir: pbs_api_types::BackupDir,
+    auth_id: Authid,
+    files: HashMap<String, WarmFile>,
+    ttl: AtomicI64,
+    ctime: i64,
+    expire: AtomicI64,
+    readers: AtomicU64,
+    downloads: AtomicU64,
+    bytes: AtomicU64,
+}
+
+lazy_static! {
+    static ref SESSIONS: Mutex<HashMap<String, Arc<WarmReaderSession>>> =
+        Mutex::new(HashMap::new());
+}
+
+impl WarmReaderSession {
+    fn new(
+        store: String,
+        ns: BackupNamespace,
+        dir: pbs_api_types::BackupDir,
+        auth_id: Authid,
+        files: HashMap<String, WarmFile>,
+        ttl: i64,
+    ) -> Self {
+        let ctime = proxmox_time::epoch_i64();
+        Self {
+            id: format!("{:x}", proxmox_uuid::Uuid::generate()),
+            store,
+            ns,
+            dir,
+            auth_id,
+            files,
+            ttl: AtomicI64::new(ttl),
+            ctime,
+            expire: AtomicI64::new(ctime + ttl),
+            readers: AtomicU64::new(0),
+            downloads: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+        }
+    }
+
+    fn matches(&self, store: &str, ns: &BackupNamespace, dir: &pbs_api_types::BackupDir) -> bool {
+        self.store == store && &self.ns == ns && &self.dir == dir
+    }
+
+    // whether the preloaded files are still those listed in the manifest
+    fn matches_manifest(&self, manifest: &BackupManifest) -> bool {
+        self.files.iter().all(|(name, file)| {
+            manifest.lookup_file_info(name).map_or(false, |info| {
+                info.size == file.size && info.csum == file.csum
+            })
+        })
+    }
+
+    fn is_expired(&self, now: i64) -> bool {
+        self.expire.load(Ordering::Relaxed) <= now
+    }
+
+    /// The auth-id that created the session.
+    pub fn auth_id(&self) -> &Authid {
+        &self.auth_id
+    }
+
+    /// The namespace of the snapshot.
+    pub fn ns(&self) -> &BackupNamespace {
+        &self.ns
+    }
+
+    /// Returns a preloaded file and counts the download.
+    pub fn file(&self, file_name: &str) -> Option<&WarmFile> {
+        let file = self.files.get(file_name)?;
+        self.downloads.fetch_add(1, Ordering::Relaxed);
+        self.bytes
+            .fetch_add(file.data.len() as u64, Ordering::Relaxed);
+        Some(file)
+    }
+
+    fn keep_alive(&self) {
+        let ttl = self.ttl.load(Ordering::Relaxed);
+        self.expire
+            .store(proxmox_time::epoch_i64() + ttl, Ordering::Relaxed);
+    }
+
+    // used when the session is created again, possibly with another TTL
+    fn refresh(&self, ttl: i64) {
+        self.ttl.store(ttl, Ordering::Relaxed);
+        self.keep_alive();
+    }
+
+    fn info(&self) -> WarmReaderSessionInfo {
+        WarmReaderSessionInfo {
+            id: self.id.clone(),
+            store: self.store.clone(),
+            ns: self.ns.clone(),
+            backup: self.dir.clone(),
+            auth_id: self.auth_id.clone(),
+            ctime: self.ctime,
+            expire: self.expire.load(Ordering::Relaxed),
+            ttl: self.ttl.load(Ordering::Relaxed),
+            files: self.files.len() as u64,
+            preloaded_bytes: self.files.values().map(|file| file.data.len() as u64).sum(),
+            readers: self.readers.load(Ordering::Relaxed),
+            downloads: self.downloads.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+fn max_sessions() -> Result<usize, Error> {
+    let (config, _digest) = crate::config::node::config()?;
+    Ok(config
+        .max_warm_reader_sessions
+        .unwrap_or(DEFAULT_MAX_WARM_READER_SESSIONS))
+}
+
+// reads the files listed in the manifest, the manifest itself is always read fresh
+fn preload_files(
+    datastore: &DataStore,
+    backup_dir: &BackupDir,
+    manifest: &BackupManifest,
+) -> Result<HashMap<String, WarmFile>, Error> {
+    let mut files = HashMap::new();
+    for info in manifest.files() {
+        let path = backup_dir.full_path().join(&info.filename);
+        let data = std::fs::read(&path)?;
+        let digests = match archive_type(&info.filename)? {
+            ArchiveType::FixedIndex | ArchiveType::DynamicIndex => {
+                let index = datastore.open_index(&path)?;
+                (0..index.index_count())
+                    .map(|pos| *index.index_digest(pos).unwrap())
+                    .collect()
+            }
+            ArchiveType::Blob => Vec::new(),
+        };
+        let file = WarmFile {
+            data,
+            digests,
+            size: info.size,
+            csum: info.csum,
+        };
+        files.insert(info.filename.clone(), file);
+    }
+
+    Ok(files)
+}
+
+/// Create a warm session for a snapshot, or extend the lifetime of an existing one to `ttl`.
+///
+/// The snapshot is only locked while its files are read, `datastore` and `backup_dir` are not
+/// kept.
+pub fn create_session(
+    datastore: Arc<DataStore>,
+    backup_dir: BackupDir,
+    auth_id: Authid,
+    ttl: i64,
+) -> Result<WarmReaderSessionInfo, Error> {
+    remove_expired_sessions();
+
+    let store = datastore.name().to_string();
+    if let Some(session) = find_session(&store, backup_dir.backup_ns(), backup_dir.dir()) {
+        session.refresh(ttl);
+        return Ok(session.info());
+    }
+
+    let max = max_sessions()?;
+    if max == 0 {
+        bail!("warm reader sessions are disabled on this node");
+    }
+    if SESSIONS.lock().unwrap().len() >= max {
+        bail!("too many warm reader sessions (max {max})");
+    }
+
+    if !backup_dir.full_path().exists() {
+        bail!("snapshot {} does not exist.", backup_dir.dir());
+    }
+    let files = {
+        let _guard = lock_dir_noblock_shared(
+            &backup_dir.full_path(),
+            "snapshot",
+            "locked by another operation",
+        )?;
+        let (manifest, _) = backup_dir.load_manifest()?;
+        if let Some(archive_store) = manifest.archived_to() {
+            bail!(
+                "snapshot {} is archived to datastore '{archive_store}'",
+                backup_dir.dir()
+            );
+        }
+        preload_files(&datastore, &backup_dir, &manifest)?
+    };
+
+    let session = WarmReaderSession::new(
+        store,
+        backup_dir.backup_ns().clone(),
+        backup_dir.dir().clone(),
+        auth_id,
+        files,
+        ttl,
+    );
+    insert_session(session, max)
+}
+
+// adds the session, unless one for the snapshot was created in the meantime
+fn insert_session(session: WarmReaderSession, max: usize) -> Result<WarmReaderSessionInfo, Error> {
+    let now = proxmox_time::epoch_i64();
+    let mut sessions = SESSIONS.lock().unwrap();
+    if let Some(existing) = sessions.values().find(|existing| {
+        !existing.is_expired(now) && existing.matches(&session.store, &session.ns, &session.dir)
+    }) {
+        existing.refresh(session.ttl.load(Ordering::Relaxed));
+        return Ok(existing.info());
+    }
+    if sessions.len() >= max {
+        bail!("too many warm reader sessions (max {max})");
+    }
+    let info = session.info();
+    sessions.insert(session.id.clone(), Arc::new(session));
+
+    Ok(info)
+}
+
+// returns the unexpired session for a snapshot
+fn find_session(
+    store: &str,
+    ns: &BackupNamespace,
+    dir: &pbs_api_types::BackupDir,
+) -> Option<Arc<WarmReaderSession>> {
+    let now = proxmox_time::epoch_i64();
+    SESSIONS
+        .lock()
+        .unwrap()
+        .values()
+        .find(|session| session.matches(store, ns, dir) && !session.is_expired(now))
+        .cloned()
+}
+
+/// Returns the session for a snapshot for a new reader session, extending its lifetime.
+///
+/// The caller has to hold the snapshot lock. A session whose files no longer match the
+/// manifest, because the snapshot was replaced, is removed.
+pub fn use_session(store: &str, backup_dir: &BackupDir) -> Option<Arc<WarmReaderSession>> {
+    let session = find_session(store, backup_dir.backup_ns(), backup_dir.dir())?;
+    match backup_dir.load_manifest() {
+        Ok((manifest, _)) if session.matches_manifest(&manifest) => (),
+        _ => {
+            remove_session(&session.id);
+            return None;
+        }
+    }
+    session.keep_alive();
+    session.readers.fetch_add(1, Ordering::Relaxed);
+    Some(session)
+}
+
+/// Returns the sessions of a datastore, oldest first.
+pub fn list_sessions(store: &str) -> Vec<WarmReaderSessionInfo> {
+    remove_expired_sessions();
+    let sessions = SESSIONS.lock().unwrap();
+    let mut list: Vec<_> = sessions
+        .values()
+        .filter(|session| session.store == store)
+        .map(|session| session.info())
+        .collect();
+    list.sort_by_key(|info| info.ctime);
+    list
+}
+
+/// Returns the session with `id` on `store`.
+pub fn get_session(store: &str, id: &str) -> Option<Arc<WarmReaderSession>> {
+    SESSIONS
+        .lock()
+        .unwrap()
+        .get(id)
+        .filter(|session| session.store == store)
+        .cloned()
+}
+
+/// Remove a session, readers currently using it keep their reference to the files.
+pub fn remove_session(id: &str) -> bool {
+    SESSIONS.lock().unwrap().remove(id).is_some()
+}
+
+/// Remove the expired sessions, freeing their files.
+pub fn remove_expired_sessions() {
+    let now = proxmox_time::epoch_i64();
+    SESSIONS
+        .lock()
+        .unwrap()
+        .retain(|_, session| !session.is_expired(now));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use pbs_api_types::CryptMode;
+
+    fn snapshot(id: &str) -> pbs_api_types::BackupDir {
+        format!("vm/{id}/2023-01-01T00:00:00Z").parse().unwrap()
+    }
+
+    fn session(store: &str, id: &str, ttl: i64) -> WarmReaderSession {
+        let files = HashMap::from([(
+            "drive-scsi0.img.fidx".to_string(),
+            WarmFile {
+                data: vec![0u8; 16],
+                digests: Vec::new(),
+                size: 16,
+                csum: [1u8; 32],
+            },
+        )]);
+        WarmReaderSession::new(
+            store.to_string(),
+            BackupNamespace::root(),
+            snapshot(id),
+            Authid::root_auth_id().clone(),
+            files,
+            ttl,
+        )
+    }
+
+    #[test]
+    fn test_recreate_refreshes_ttl() -> Result<(), Error> {
+        let store = "warm-test-recreate";
+        let info = insert_session(session(store, "100", 10), 16)?;
+        assert_eq!(info.ttl, 10);
+
+        let now = proxmox_time::epoch_i64();
+        let again = insert_session(session(store, "100", 600), 16)?;
+        assert_eq!(again.id, info.id);
+        assert_eq!(again.ttl, 600);
+        assert!(again.expire >= now + 600);
+        assert_eq!(list_sessions(store).len(), 1);
+
+        // lookups extend the lifetime by the new TTL
+        let found = find_session(store, &BackupNamespace::root(), &snapshot("100")).unwrap();
+        found.keep_alive();
+        assert!(found.info().expire >= now + 600);
+
+        remove_session(&info.id);
+        Ok(())
+    }
+
+    #[test]
+    fn test_expired_sessions() -> Result<(), Error> {
+        let store = "warm-test-expired";
+        let info = insert_session(session(store, "100", 600), 16)?;
+        let found = get_session(store, &info.id).unwrap();
+
+        found.expire.store(0, Ordering::Relaxed);
+        assert!(find_session(store, &BackupNamespace::root(), &snapshot("100")).is_none());
+
+        // an expired session is replaced on creation
+        let new = insert_session(session(store, "100", 600), 16)?;
+        assert_ne!(new.id, info.id);
+
+        remove_expired_sessions();
+        assert!(get_session(store, &info.id).is_none());
+        assert!(get_session(store, &new.id).is_some());
+
+        remove_session(&new.id);
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_sessions() -> Result<(), Error> {
+        let store = "warm-test-max";
+        let info = insert_session(session(store, "100", 600), 16)?;
+
+        // existing sessions can be extended at the limit, new ones are refused
+        assert!(insert_session(session(store, "100", 600), 0).is_ok());
+        assert!(insert_session(session(store, "101", 600), 0).is_err());
+
+        remove_session(&info.id);
+        Ok(())
+    }
+
+    #[test]
+    fn test_matches_manifest() -> Result<(), Error> {
+        let session = session("warm-test-manifest", "100", 600);
+
+        let mut manifest = BackupManifest::new(snapshot("100"));
+        manifest.add_file(
+            "drive-scsi0.img.fidx".to_string(),
+            16,
+            [1u8; 32],
+            CryptMode::None,
+        )?;
+        manifest.add_file(
+            "qemu-server.conf.blob".to_string(),
+            8,
+            [2u8; 32],
+            CryptMode::None,
+        )?;
+        assert!(session.matches_manifest(&manifest));
+
+        // the snapshot was replaced by one with other contents
+        let mut manifest = BackupManifest::new(snapshot("100"));
+        manifest.add_file(
+            "drive-scsi0.img.fidx".to_string(),
+            16,
+            [3u8; 32],
+            CryptMode::None,
+        )?;
+        assert!(!session.matches_manifest(&manifest));
+
+        assert!(!session.matches_manifest(&BackupManifest::new(snapshot("100"))));
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+use anyhow::Error;
+
+use proxmox_sys::{task_log, task_warn, WorkerTaskContext};
+
+use pbs_api_types::{Authid, BackupNamespace, Operation};
+use pbs_datastore::manifest::{archive_type, ArchiveType};
+use pbs_datastore::DataStore;
+use proxmox_rest_server::WorkerTask;
+
+/// Preload the metadata of a datastore into the page cache.
+///
+/// Lists all snapshots, reads their index files and the directories of the chunk store, so the
+/// first backup, listing or garbage collection after the datastore came online does not have to
+/// wait for the cold metadata on slow disks. Only reads are done, the task can be aborted at any
+/// time.
+pub fn do_warm_up_job(store: &str, auth_id: &Authid, to_stdout: bool) -> Result<String, Error> {
+    let datastore = DataStore::lookup_datastore(store, Some(Operation::Read))?;
+
+    let upid_str = WorkerTask::new_thread(
+        "warm-up",
+        Some(store.to_string()),
+        auth_id.to_string(),
+        to_stdout,
+        move |worker| {
+            task_log!(worker, "warming up datastore '{}'", datastore.name());
+
+            let mut snapshots = 0;
+            let mut index_files = 0;
+            for ns in datastore.recursive_iter_backup_ns_ok(BackupNamespace::root(), None)? {
+                for group in datastore.iter_backup_groups_ok(ns)? {
+                    worker.check_abort()?;
+                    for info in group.list_backups()? {
+                        snapshots += 1;
+                        let (manifest, _) = match info.backup_dir.load_manifest() {
+                            Ok(manifest) => manifest,
+                            Err(err) => {
+                                task_warn!(worker, "{} - {err}", info.backup_dir.dir());
+                                continue;
+                            }
+                        };
+                        for file in manifest.files() {
+                            match archive_type(&file.filename)? {
+                                ArchiveType::FixedIndex | ArchiveType::DynamicIndex => {}
+                                ArchiveType::Blob => continue,
+                            }
+                            let path = info.backup_dir.full_path().join(&file.filename);
+                            // reading the index pulls it into the page cache
+                            match std::fs::File::open(&path) {
+                                Ok(mut index) => {
+                                    std::io::copy(&mut index, &mut std::io::sink())?;
+                                    index_files += 1;
+                                }
+                                Err(err) => task_warn!(worker, "{path:?} - {err}"),
+                            }
+                        }
+                    }
+                }
+            }
+            task_log!(
+                worker,
+                "read {index_files} index files of {snapshots} snapshots"
+            );
+
+            let mut last_percentage = 0;
+            let mut chunks: u64 = 0;
+            for (entry, percentage, _bad) in datastore.get_chunk_iterator()? {
+                if entry.is_ok() {
+                    chunks += 1;
+                }
+                if percentage != last_percentage {
+                    worker.check_abort()?;
+                    if percentage % 10 == 0 {
+                        task_log!(worker, "chunk store: {percentage}%");
+                    }
+                    last_percentage = percentage;
+                }
+            }
+            task_log!(worker, "listed {chunks} chunks");
+
+            Ok(())
+        },
+    )?;
+
+    Ok(upid_str)
+}
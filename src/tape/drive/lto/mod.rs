@@ -156,6 +156,10 @@ impl TapeDriver for LtoTapeHandle {
         self.sg_tape.current_file_number()
     }
 
+    fn current_block_number(&mut self) -> Result<Option<u64>, Error> {
+        Ok(Some(self.sg_tape.position()?.logical_object_number))
+    }
+
     fn format_media(&mut self, fast: bool) -> Result<(), Error> {
         self.sg_tape.format_media(fast)
     }
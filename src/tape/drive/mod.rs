@@ -5,7 +5,10 @@ mod virtual_tape;
 mod lto;
 pub use lto::*;
 
+use std::io::Read;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 
 use anyhow::{bail, format_err, Error};
 use nix::fcntl::OFlag;
@@ -22,7 +25,7 @@ use proxmox_section_config::SectionConfigData;
 use proxmox_sys::{task_log, WorkerTaskContext};
 use proxmox_uuid::Uuid;
 
-use pbs_api_types::{Fingerprint, LtoTapeDrive, VirtualTapeDrive};
+use pbs_api_types::{DriveOperationProgress, Fingerprint, LtoTapeDrive, VirtualTapeDrive};
 use pbs_key_config::KeyConfig;
 
 use pbs_tape::{sg_tape::TapeAlertFlags, BlockReadError, MediaContentHeader, TapeRead, TapeWrite};
@@ -65,6 +68,11 @@ pub trait TapeDriver {
     /// Current file number
     fn current_file_number(&mut self) -> Result<u64, Error>;
 
+    /// Current block number, if the drive reports it
+    fn current_block_number(&mut self) -> Result<Option<u64>, Error> {
+        Ok(None)
+    }
+
     /// Completely erase the media
     fn format_media(&mut self, fast: bool) -> Result<(), Error>;
 
@@ -560,21 +568,203 @@ pub fn lock_tape_device(
     })
 }
 
+fn tape_device_state_options() -> Result<CreateOptions, Error> {
+    let backup_user = pbs_config::backup_user()?;
+    let mode = nix::sys::stat::Mode::from_bits_truncate(0o0644);
+    Ok(CreateOptions::new()
+        .perm(mode)
+        .owner(backup_user.uid)
+        .group(backup_user.gid))
+}
+
+fn tape_device_progress_path(drive: &str) -> PathBuf {
+    PathBuf::from(format!("{}/{drive}.progress", crate::tape::DRIVE_STATE_DIR))
+}
+
 /// Writes the given state for the specified drive
 ///
-/// This function does not lock, so make sure the drive is locked
+/// This function does not lock, so make sure the drive is locked. The progress of the previous
+/// operation is removed.
 pub fn set_tape_device_state(drive: &str, state: &str) -> Result<(), Error> {
     let mut path = PathBuf::from(crate::tape::DRIVE_STATE_DIR);
     path.push(drive);
 
-    let backup_user = pbs_config::backup_user()?;
-    let mode = nix::sys::stat::Mode::from_bits_truncate(0o0644);
-    let options = CreateOptions::new()
-        .perm(mode)
-        .owner(backup_user.uid)
-        .group(backup_user.gid);
+    match std::fs::remove_file(tape_device_progress_path(drive)) {
+        Ok(()) => (),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+        Err(err) => bail!("unable to remove progress of drive '{drive}' - {err}"),
+    }
+
+    replace_file(path, state.as_bytes(), tape_device_state_options()?, false)
+}
+
+/// Writes the progress of the operation running on the specified drive
+///
+/// This function does not lock, so make sure the drive is locked
+pub fn set_tape_device_progress(
+    drive: &str,
+    progress: &DriveOperationProgress,
+) -> Result<(), Error> {
+    let data = serde_json::to_vec(progress)?;
+    replace_file(
+        tape_device_progress_path(drive),
+        &data,
+        tape_device_state_options()?,
+        false,
+    )
+}
+
+/// Get the progress of the operation running on the device, if it reported any
+pub fn get_tape_device_progress(
+    config: &SectionConfigData,
+    drive: &str,
+) -> Result<Option<DriveOperationProgress>, Error> {
+    let state = match get_tape_device_state(config, drive)? {
+        Some(state) => state,
+        None => return Ok(None),
+    };
+
+    let data = match file_read_optional_string(tape_device_progress_path(drive))? {
+        Some(data) => data,
+        None => return Ok(None),
+    };
+    let progress: DriveOperationProgress = serde_json::from_str(&data)?;
+
+    // left over by an operation that did not clean up
+    if progress.upid != state {
+        return Ok(None);
+    }
+
+    Ok(Some(progress))
+}
+
+/// Minimum interval between two drive progress updates (seconds)
+const PROGRESS_UPDATE_INTERVAL: i64 = 10;
+
+/// Reports the progress of a task using a drive, see [get_tape_device_progress]
+///
+/// Failures to update the progress are only logged, they do not affect the task.
+pub struct DriveProgressReporter {
+    drive_name: String,
+    upid: String,
+    media: Option<String>,
+    bytes_written: u64,
+    bytes_read: Arc<AtomicU64>,
+    last_update: i64,
+}
+
+impl DriveProgressReporter {
+    pub fn new(drive_name: &str, upid: String) -> Self {
+        Self {
+            drive_name: drive_name.to_string(),
+            upid,
+            media: None,
+            bytes_written: 0,
+            bytes_read: Arc::new(AtomicU64::new(0)),
+            last_update: 0,
+        }
+    }
+
+    /// Set the label of the loaded media
+    pub fn set_media(&mut self, label_text: &str) {
+        self.media = Some(label_text.to_string());
+    }
+
+    /// Account bytes written to the media
+    pub fn add_bytes_written(&mut self, bytes: u64) {
+        self.bytes_written += bytes;
+    }
+
+    /// Bytes read through the readers returned by [Self::count_reads]
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read.load(Ordering::Relaxed)
+    }
+
+    /// Wrap the reader of a file on the media, to account the bytes read from it
+    pub fn count_reads<'a>(&self, reader: Box<dyn TapeRead + 'a>) -> Box<dyn TapeRead + 'a> {
+        Box::new(CountingTapeReader {
+            reader,
+            bytes: Arc::clone(&self.bytes_read),
+        })
+    }
+
+    /// Write the current position on the media, at most every [PROGRESS_UPDATE_INTERVAL]
+    /// seconds
+    pub fn update(&mut self, drive: &mut dyn TapeDriver) {
+        let now = proxmox_time::epoch_i64();
+        if now - self.last_update < PROGRESS_UPDATE_INTERVAL {
+            return;
+        }
+        self.last_update = now;
+
+        let result = self
+            .progress(drive, now)
+            .and_then(|progress| set_tape_device_progress(&self.drive_name, &progress));
+        if let Err(err) = result {
+            log::warn!(
+                "unable to update progress of drive '{}' - {err}",
+                self.drive_name
+            );
+        }
+    }
+
+    fn progress(
+        &self,
+        drive: &mut dyn TapeDriver,
+        now: i64,
+    ) -> Result<DriveOperationProgress, Error> {
+        let file_number = drive.current_file_number()?;
+        let block_number = drive.current_block_number().unwrap_or(None);
+        // only meaningful when writing, and not all drives support that
+        let compression_ratio = match self.bytes_written {
+            0 => None,
+            _ => drive
+                .get_volume_statistics()
+                .ok()
+                .map(|stats| stats.last_load_write_compression_ratio),
+        };
+
+        Ok(DriveOperationProgress {
+            upid: self.upid.clone(),
+            media: self.media.clone(),
+            file_number,
+            block_number,
+            bytes_written: self.bytes_written,
+            bytes_read: self.bytes_read(),
+            compression_ratio,
+            updated: now,
+        })
+    }
+}
+
+// counts the bytes read from a file on the media, including skipped data
+struct CountingTapeReader<'a> {
+    reader: Box<dyn TapeRead + 'a>,
+    bytes: Arc<AtomicU64>,
+}
 
-    replace_file(path, state.as_bytes(), options, false)
+impl Read for CountingTapeReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.reader.read(buf)?;
+        self.bytes.fetch_add(count as u64, Ordering::Relaxed);
+        Ok(count)
+    }
+}
+
+impl TapeRead for CountingTapeReader<'_> {
+    fn is_incomplete(&self) -> Result<bool, std::io::Error> {
+        self.reader.is_incomplete()
+    }
+
+    fn has_end_marker(&self) -> Result<bool, std::io::Error> {
+        self.reader.has_end_marker()
+    }
+
+    fn skip_data(&mut self) -> Result<usize, std::io::Error> {
+        let count = self.reader.skip_data()?;
+        self.bytes.fetch_add(count as u64, Ordering::Relaxed);
+        Ok(count)
+    }
 }
 
 /// Get the device state
@@ -34,10 +34,12 @@ impl<'a> ChunkArchiveWriter<'a> {
     pub fn new(
         mut writer: Box<dyn TapeWrite + 'a>,
         store: &str,
+        digest_salt_fingerprint: Option<[u8; 32]>,
         close_on_leom: bool,
     ) -> Result<(Self, Uuid), Error> {
         let archive_header = ChunkArchiveHeader {
             store: store.to_string(),
+            digest_salt_fingerprint: digest_salt_fingerprint.map(hex::encode),
         };
         let header_data = serde_json::to_string_pretty(&archive_header)?
             .as_bytes()
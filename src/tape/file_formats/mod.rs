@@ -85,6 +85,9 @@ pub fn proxmox_tape_magic_to_text(magic: &[u8; 8]) -> Option<String> {
 pub struct ChunkArchiveHeader {
     // Datastore name
     pub store: String,
+    /// Fingerprint of the digest salt of the datastore, if any (hex)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest_salt_fingerprint: Option<String>,
 }
 
 #[derive(Endian)]
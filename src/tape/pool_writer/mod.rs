@@ -20,7 +20,7 @@ use pbs_tape::{sg_tape::tape_alert_flags_critical, TapeWrite};
 use proxmox_rest_server::WorkerTask;
 
 use crate::tape::{
-    drive::{media_changer, request_and_load_media, TapeDriver},
+    drive::{media_changer, request_and_load_media, DriveProgressReporter, TapeDriver},
     encryption_keys::load_key_configs,
     file_formats::{
         tape_write_catalog, tape_write_snapshot_archive, ChunkArchiveWriter, MediaSetLabel,
@@ -56,6 +56,7 @@ pub struct PoolWriter {
     notification_mode: TapeNotificationMode,
     ns_magic: bool,
     used_tapes: HashSet<Uuid>,
+    progress: DriveProgressReporter,
 }
 
 impl PoolWriter {
@@ -94,6 +95,7 @@ impl PoolWriter {
             notification_mode,
             ns_magic,
             used_tapes: HashSet::new(),
+            progress: DriveProgressReporter::new(drive_name, worker.upid().to_string()),
         })
     }
 
@@ -302,6 +304,8 @@ impl PoolWriter {
 
         drive.assert_encryption_mode(media_set.encryption_key_fingerprint.is_some())?;
 
+        self.progress.set_media(media.label_text());
+
         self.status = Some(PoolWriterState {
             drive,
             media_uuid: media_uuid.clone(),
@@ -503,6 +507,8 @@ impl PoolWriter {
             self.commit()?;
         }
 
+        self.update_progress(bytes_written);
+
         Ok((done, bytes_written))
     }
 
@@ -514,7 +520,7 @@ impl PoolWriter {
         &mut self,
         worker: &WorkerTask,
         chunk_iter: &mut std::iter::Peekable<NewChunksIterator>,
-        store: &str,
+        datastore: &DataStore,
     ) -> Result<(bool, usize), Error> {
         let status = match self.status {
             Some(ref mut status) => status,
@@ -527,8 +533,13 @@ impl PoolWriter {
 
         let start_time = SystemTime::now();
 
-        let (saved_chunks, content_uuid, leom, bytes_written) =
-            write_chunk_archive(worker, writer, chunk_iter, store, MAX_CHUNK_ARCHIVE_SIZE)?;
+        let (saved_chunks, content_uuid, leom, bytes_written) = write_chunk_archive(
+            worker,
+            writer,
+            chunk_iter,
+            datastore,
+            MAX_CHUNK_ARCHIVE_SIZE,
+        )?;
 
         status.bytes_written_after_sync += bytes_written;
 
@@ -555,9 +566,20 @@ impl PoolWriter {
             self.commit()?;
         }
 
+        self.update_progress(bytes_written);
+
         Ok((leom, bytes_written))
     }
 
+    // Report the position on the media and the bytes written, so that the drive progress shows
+    // how far the running task is
+    fn update_progress(&mut self, bytes_written: usize) {
+        self.progress.add_bytes_written(bytes_written as u64);
+        if let Some(ref mut status) = self.status {
+            self.progress.update(status.drive.as_mut());
+        }
+    }
+
     pub fn spawn_chunk_reader_thread(
         &self,
         datastore: Arc<DataStore>,
@@ -581,10 +603,15 @@ fn write_chunk_archive<'a>(
     _worker: &WorkerTask,
     writer: Box<dyn 'a + TapeWrite>,
     chunk_iter: &mut std::iter::Peekable<NewChunksIterator>,
-    store: &str,
+    datastore: &DataStore,
     max_size: usize,
 ) -> Result<(Vec<[u8; 32]>, Uuid, bool, usize), Error> {
-    let (mut writer, content_uuid) = ChunkArchiveWriter::new(writer, store, true)?;
+    let (mut writer, content_uuid) = ChunkArchiveWriter::new(
+        writer,
+        datastore.name(),
+        datastore.digest_salt_fingerprint(),
+        true,
+    )?;
 
     // we want to get the chunk list in correct order
     let mut chunk_list: Vec<[u8; 32]> = Vec::new();
@@ -0,0 +1,102 @@
+// Drive progress tests
+//
+// # cargo test --release tape::test::drive_progress
+
+use std::io::Read;
+
+use anyhow::Error;
+
+use pbs_api_types::DriveOperationProgress;
+use pbs_tape::TapeRead;
+
+use crate::tape::drive::DriveProgressReporter;
+
+struct TestReader {
+    data: std::io::Cursor<Vec<u8>>,
+}
+
+impl Read for TestReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.data.read(buf)
+    }
+}
+
+impl TapeRead for TestReader {
+    fn is_incomplete(&self) -> Result<bool, std::io::Error> {
+        Ok(false)
+    }
+
+    fn has_end_marker(&self) -> Result<bool, std::io::Error> {
+        Ok(true)
+    }
+
+    fn skip_data(&mut self) -> Result<usize, std::io::Error> {
+        let mut rest = Vec::new();
+        self.data.read_to_end(&mut rest)
+    }
+}
+
+fn test_reader(len: usize) -> Box<dyn TapeRead> {
+    Box::new(TestReader {
+        data: std::io::Cursor::new(vec![0u8; len]),
+    })
+}
+
+#[test]
+fn test_count_reads() -> Result<(), Error> {
+    let progress = DriveProgressReporter::new("drive0", "UPID:test".to_string());
+    assert_eq!(progress.bytes_read(), 0);
+
+    let mut reader = progress.count_reads(test_reader(1000));
+    let mut buf = [0u8; 300];
+    reader.read_exact(&mut buf)?;
+    assert_eq!(progress.bytes_read(), 300);
+
+    // skipped data was read from the media too
+    assert_eq!(reader.skip_data()?, 700);
+    assert_eq!(progress.bytes_read(), 1000);
+    assert!(reader.has_end_marker()?);
+
+    // counts accumulate over the files of the task
+    let mut reader = progress.count_reads(test_reader(500));
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    assert_eq!(progress.bytes_read(), 1500);
+
+    Ok(())
+}
+
+#[test]
+fn test_progress_format() -> Result<(), Error> {
+    let progress = DriveOperationProgress {
+        upid: "UPID:test".to_string(),
+        media: None,
+        file_number: 3,
+        block_number: None,
+        bytes_written: 0,
+        bytes_read: 4096,
+        compression_ratio: None,
+        updated: 1000,
+    };
+    let value = serde_json::to_value(&progress)?;
+    assert_eq!(
+        value,
+        serde_json::json!({
+            "upid": "UPID:test",
+            "file-number": 3,
+            "bytes-written": 0,
+            "bytes-read": 4096,
+            "updated": 1000,
+        })
+    );
+
+    // progress written by a backup before reads were counted
+    let progress: DriveOperationProgress = serde_json::from_str(
+        r#"{"upid":"UPID:test","media":"TAPE001L8","file-number":42,"bytes-written":1024,"updated":1000}"#,
+    )?;
+    assert_eq!(progress.media.as_deref(), Some("TAPE001L8"));
+    assert_eq!(progress.bytes_written, 1024);
+    assert_eq!(progress.bytes_read, 0);
+
+    Ok(())
+}
@@ -1,4 +1,5 @@
 mod alloc_writable_media;
 mod compute_media_state;
 mod current_set_usable;
+mod drive_progress;
 mod inventory;
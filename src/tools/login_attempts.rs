@@ -0,0 +1,383 @@
+//! Failed login accounting shared between the daemons
+//!
+//! Failed password and second factor logins are counted per user and client address in shared
+//! memory, so the API daemon and the proxy enforce the same limits, and the counters survive
+//! restarts of either daemon. After `login-max-failures` failed logins within
+//! `login-failure-window` seconds, logins of that user from that address are refused for
+//! `login-lockout-time` seconds, see [`LoginLimits`]. Counting per address keeps an attacker
+//! from locking a user, like `root@pam`, out everywhere.
+//!
+//! The table has a fixed number of entries. Active counters are never replaced, if the table is
+//! full of them, further failures are not counted until entries expire.
+
+use std::mem::MaybeUninit;
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+use anyhow::{bail, Error};
+use nix::sys::stat::Mode;
+use once_cell::sync::OnceCell;
+
+use proxmox_shared_memory::{check_subtype, initialize_subtype};
+use proxmox_shared_memory::{Init, SharedMemory, SharedMutex};
+use proxmox_sys::fs::{create_path, CreateOptions};
+
+use pbs_api_types::Userid;
+
+// openssl::sha::sha256(b"Proxmox Backup LoginAttempts v2.0")[0..8];
+pub const PROXMOX_BACKUP_LOGIN_ATTEMPTS_MAGIC_2_0: [u8; 8] = [20, 70, 43, 43, 186, 139, 57, 238];
+
+const FILE_PATH: &str = pbs_buildcfg::rundir!("/shmem/login-attempts");
+
+/// Default number of failed logins after which a user is locked out.
+pub const DEFAULT_MAX_FAILURES: u32 = 5;
+/// Default time span in seconds in which failed logins are counted.
+pub const DEFAULT_FAILURE_WINDOW: i64 = 300;
+/// Default time in seconds for which logins are refused after too many failures.
+pub const DEFAULT_LOCKOUT_TIME: i64 = 60;
+
+const ENTRIES: usize = 1300;
+const DATA_SIZE: usize = 64 * 1024;
+
+/// Limits for failed logins, configured in the node config.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LoginLimits {
+    /// Number of failed logins after which a user is locked out.
+    pub max_failures: u32,
+    /// Time span in seconds in which failed logins are counted.
+    pub window: i64,
+    /// Time in seconds for which logins are refused after too many failures.
+    pub lockout_time: i64,
+}
+
+impl Default for LoginLimits {
+    fn default() -> Self {
+        Self {
+            max_failures: DEFAULT_MAX_FAILURES,
+            window: DEFAULT_FAILURE_WINDOW,
+            lockout_time: DEFAULT_LOCKOUT_TIME,
+        }
+    }
+}
+
+impl LoginLimits {
+    /// Returns the limits configured in the node config, the defaults if it cannot be read.
+    pub fn from_node_config() -> Self {
+        let defaults = Self::default();
+        match crate::config::node::config() {
+            Ok((config, _digest)) => Self {
+                max_failures: config.login_max_failures.unwrap_or(defaults.max_failures),
+                window: config.login_failure_window.unwrap_or(defaults.window),
+                lockout_time: config.login_lockout_time.unwrap_or(defaults.lockout_time),
+            },
+            Err(err) => {
+                log::error!("unable to read login limits from node config - {err}");
+                defaults
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+#[repr(C)]
+struct Entry {
+    // first 8 bytes of the sha256 of the userid, 0 marks free entries
+    user: u64,
+    // client address, IPv4 addresses mapped to IPv6, all zero if unknown
+    address: [u8; 16],
+    window_start: i64,
+    locked_until: i64,
+    failures: u32,
+    _padding: u32,
+}
+
+impl Entry {
+    fn is_active(&self, now: i64, limits: &LoginLimits) -> bool {
+        self.user != 0
+            && (self.locked_until > now
+                || (self.failures > 0 && self.window_start + limits.window >= now))
+    }
+}
+
+#[repr(C)]
+struct LoginAttemptTable {
+    entries: [Entry; ENTRIES],
+}
+
+impl Init for LoginAttemptTable {
+    fn initialize(this: &mut MaybeUninit<Self>) {
+        this.write(LoginAttemptTable {
+            entries: [Entry::default(); ENTRIES],
+        });
+    }
+}
+
+impl LoginAttemptTable {
+    fn find(&mut self, user: u64, address: &[u8; 16]) -> Option<&mut Entry> {
+        self.entries
+            .iter_mut()
+            .find(|entry| entry.user == user && &entry.address == address)
+    }
+
+    // returns None if the table is full of active entries
+    fn find_or_insert(
+        &mut self,
+        user: u64,
+        address: &[u8; 16],
+        now: i64,
+        limits: &LoginLimits,
+    ) -> Option<&mut Entry> {
+        let pos = match self
+            .entries
+            .iter()
+            .position(|entry| entry.user == user && &entry.address == address)
+        {
+            Some(pos) => pos,
+            None => {
+                let pos = self
+                    .entries
+                    .iter()
+                    .position(|entry| !entry.is_active(now, limits))?;
+                self.entries[pos] = Entry {
+                    user,
+                    address: *address,
+                    window_start: now,
+                    ..Default::default()
+                };
+                pos
+            }
+        };
+        Some(&mut self.entries[pos])
+    }
+}
+
+#[repr(C)]
+struct LoginAttemptsData {
+    magic: [u8; 8],
+    table: SharedMutex<LoginAttemptTable>,
+    padding: [u8; DATA_SIZE - 8 - std::mem::size_of::<SharedMutex<LoginAttemptTable>>()],
+}
+
+impl Init for LoginAttemptsData {
+    fn initialize(this: &mut MaybeUninit<Self>) {
+        unsafe {
+            let me = &mut *this.as_mut_ptr();
+            me.magic = PROXMOX_BACKUP_LOGIN_ATTEMPTS_MAGIC_2_0;
+            initialize_subtype(&mut me.table);
+        }
+    }
+
+    fn check_type_magic(this: &MaybeUninit<Self>) -> Result<(), Error> {
+        unsafe {
+            let me = &*this.as_ptr();
+            if me.magic != PROXMOX_BACKUP_LOGIN_ATTEMPTS_MAGIC_2_0 {
+                bail!("LoginAttemptsData: wrong magic number");
+            }
+            check_subtype(&me.table)?;
+            Ok(())
+        }
+    }
+}
+
+/// Failed login counters in shared memory ([SharedMemory])
+pub struct LoginAttempts {
+    shmem: SharedMemory<LoginAttemptsData>,
+}
+
+static INSTANCE: OnceCell<LoginAttempts> = OnceCell::new();
+
+fn userid_key(userid: &Userid) -> u64 {
+    let digest = openssl::sha::sha256(userid.as_str().as_bytes());
+    let mut key = [0u8; 8];
+    key.copy_from_slice(&digest[0..8]);
+    // never 0, that marks free entries
+    u64::from_le_bytes(key) | 1
+}
+
+fn address_key(address: Option<IpAddr>) -> [u8; 16] {
+    match address {
+        Some(IpAddr::V4(address)) => address.to_ipv6_mapped().octets(),
+        Some(IpAddr::V6(address)) => address.octets(),
+        None => [0u8; 16],
+    }
+}
+
+impl LoginAttempts {
+    /// Returns the singleton instance, mapped from `/run/proxmox-backup/shmem/login-attempts`.
+    pub fn new() -> Result<&'static Self, Error> {
+        INSTANCE.get_or_try_init(Self::open)
+    }
+
+    fn open() -> Result<Self, Error> {
+        let user = pbs_config::backup_user()?;
+
+        let dir_opts = CreateOptions::new()
+            .perm(Mode::from_bits_truncate(0o770))
+            .owner(user.uid)
+            .group(user.gid);
+
+        let path = PathBuf::from(FILE_PATH);
+        create_path(
+            path.parent().unwrap(),
+            Some(dir_opts.clone()),
+            Some(dir_opts),
+        )?;
+
+        let file_opts = CreateOptions::new()
+            .perm(Mode::from_bits_truncate(0o660))
+            .owner(user.uid)
+            .group(user.gid);
+
+        let shmem: SharedMemory<LoginAttemptsData> = SharedMemory::open(&path, file_opts)?;
+
+        Ok(Self { shmem })
+    }
+
+    /// Returns the end of the lockout if logins of `userid` from `address` are currently
+    /// refused.
+    pub fn locked_until(&self, userid: &Userid, address: Option<IpAddr>) -> Option<i64> {
+        let now = proxmox_time::epoch_i64();
+        let mut table = self.shmem.data().table.lock();
+        match table.find(userid_key(userid), &address_key(address)) {
+            Some(entry) if entry.locked_until > now => Some(entry.locked_until),
+            _ => None,
+        }
+    }
+
+    /// Count a failed login of `userid` from `address`. Returns true if this locked the user
+    /// out.
+    ///
+    /// Fails if the table is full of active counters, the failure is not counted then.
+    pub fn record_failure(
+        &self,
+        userid: &Userid,
+        address: Option<IpAddr>,
+        limits: &LoginLimits,
+    ) -> Result<bool, Error> {
+        let now = proxmox_time::epoch_i64();
+        let mut table = self.shmem.data().table.lock();
+        match table.find_or_insert(userid_key(userid), &address_key(address), now, limits) {
+            Some(entry) => Ok(count_failure(entry, now, limits)),
+            None => bail!("failed login table is full"),
+        }
+    }
+
+    /// Reset the failed login counter of `userid` from `address` after a successful login.
+    pub fn record_success(&self, userid: &Userid, address: Option<IpAddr>) {
+        let mut table = self.shmem.data().table.lock();
+        if let Some(entry) = table.find(userid_key(userid), &address_key(address)) {
+            *entry = Entry::default();
+        }
+    }
+
+    /// Reset the failed login counters of `userid` from all addresses, lifting a lockout.
+    ///
+    /// Returns true if the user was locked out.
+    pub fn unlock(&self, userid: &Userid) -> bool {
+        let now = proxmox_time::epoch_i64();
+        let user = userid_key(userid);
+        let mut table = self.shmem.data().table.lock();
+        let mut locked = false;
+        for entry in table.entries.iter_mut().filter(|entry| entry.user == user) {
+            locked |= entry.locked_until > now;
+            *entry = Entry::default();
+        }
+        locked
+    }
+}
+
+// returns true if the failure locked the user out
+fn count_failure(entry: &mut Entry, now: i64, limits: &LoginLimits) -> bool {
+    if entry.window_start + limits.window < now {
+        entry.window_start = now;
+        entry.failures = 0;
+    }
+    entry.failures += 1;
+
+    if entry.failures >= limits.max_failures {
+        entry.locked_until = now + limits.lockout_time;
+        entry.window_start = now;
+        entry.failures = 0;
+        return true;
+    }
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn empty_table() -> Box<LoginAttemptTable> {
+        Box::new(LoginAttemptTable {
+            entries: [Entry::default(); ENTRIES],
+        })
+    }
+
+    #[test]
+    fn test_data_size() {
+        assert_eq!(std::mem::size_of::<LoginAttemptsData>(), DATA_SIZE);
+    }
+
+    #[test]
+    fn test_lockout_per_address() {
+        let limits = LoginLimits {
+            max_failures: 3,
+            window: 100,
+            lockout_time: 60,
+        };
+        let mut table = empty_table();
+        let attacker = address_key(Some("192.0.2.1".parse().unwrap()));
+        let admin = address_key(Some("192.0.2.2".parse().unwrap()));
+
+        for (now, locked) in [(1000, false), (1001, false), (1002, true)] {
+            let entry = table.find_or_insert(3, &attacker, now, &limits).unwrap();
+            assert_eq!(count_failure(entry, now, &limits), locked);
+        }
+        assert_eq!(table.find(3, &attacker).unwrap().locked_until, 1062);
+        assert!(table.find(3, &admin).is_none());
+
+        // failures outside of the window start a new count
+        let entry = table.find_or_insert(3, &admin, 2000, &limits).unwrap();
+        assert!(!count_failure(entry, 2000, &limits));
+        let entry = table.find_or_insert(3, &admin, 2200, &limits).unwrap();
+        assert!(!count_failure(entry, 2200, &limits));
+        assert_eq!(table.find(3, &admin).unwrap().failures, 1);
+    }
+
+    #[test]
+    fn test_active_entries_are_kept() {
+        let limits = LoginLimits::default();
+        let mut table = empty_table();
+        let address = address_key(None);
+
+        let entry = table.find_or_insert(3, &address, 1000, &limits).unwrap();
+        count_failure(entry, 1000, &limits);
+
+        for user in 0..ENTRIES as u64 - 1 {
+            let entry = table
+                .find_or_insert(5 + user * 2, &address, 1001, &limits)
+                .unwrap();
+            count_failure(entry, 1001, &limits);
+        }
+
+        // full of active counters, nothing is replaced
+        assert!(table.find_or_insert(4, &address, 1002, &limits).is_none());
+        assert_eq!(table.find(3, &address).unwrap().failures, 1);
+
+        // once the window passed, entries get reused
+        let now = 1001 + limits.window + 1;
+        assert!(table.find_or_insert(4, &address, now, &limits).is_some());
+    }
+
+    #[test]
+    fn test_address_key() {
+        assert_eq!(
+            address_key(Some("192.0.2.1".parse().unwrap())),
+            address_key(Some("::ffff:192.0.2.1".parse().unwrap()))
+        );
+        assert_ne!(
+            address_key(Some("192.0.2.1".parse().unwrap())),
+            address_key(None)
+        );
+    }
+}
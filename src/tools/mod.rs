@@ -10,6 +10,7 @@ pub mod apt;
 pub mod config;
 pub mod disks;
 pub mod fs;
+pub mod login_attempts;
 
 mod shared_rate_limiter;
 pub use shared_rate_limiter::SharedRateLimiter;
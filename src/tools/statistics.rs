@@ -123,3 +123,49 @@ where
     let alpha = mean_y - beta * mean_x;
     Some((alpha, beta))
 }
+
+/// Returns the standard error of the slope `b` of the linear regression `y = a + bx` with the
+/// factors `(a,b)`, or `None` if there are less than three data points
+/// ```
+/// # use proxmox_backup::tools::statistics::{linear_regression, slope_standard_error};
+///
+/// let x = &[0,1,2,3,4];
+/// let y = &[-4,-2,0,2,4];
+/// let (a,b) = linear_regression(x,y).unwrap();
+/// assert!(slope_standard_error(x,y,a,b).unwrap() < 0.001);
+///
+/// let y = &[-4,-1,0,1,4];
+/// let (a,b) = linear_regression(x,y).unwrap();
+/// assert!((slope_standard_error(x,y,a,b).unwrap() - 0.2309).abs() < 0.001);
+/// ```
+pub fn slope_standard_error<X, Y>(x: &[X], y: &[Y], alpha: f64, beta: f64) -> Option<f64>
+where
+    X: NumAssignRef + ToPrimitive,
+    Y: NumAssignRef + ToPrimitive,
+{
+    let len_x = x.len();
+    let len_y = y.len();
+    if len_x < 3 || len_x != len_y {
+        return None;
+    }
+
+    let mean_x = mean(x)?;
+
+    let mut residuals = 0.0;
+    let mut variance = 0.0;
+
+    for i in 0..len_x {
+        let x = x[i].to_f64()?;
+        let y = y[i].to_f64()?;
+
+        let residual = y - (alpha + beta * x);
+        residuals += residual * residual;
+        variance += (x - mean_x) * (x - mean_x);
+    }
+
+    if variance == 0.0 {
+        return None;
+    }
+
+    Some((residuals / ((len_x - 2) as f64) / variance).sqrt())
+}
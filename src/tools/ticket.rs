@@ -1,5 +1,16 @@
-use pbs_api_types::Userid;
+use pbs_api_types::{BackupDir, BackupNamespace, Userid};
 
 pub fn term_aad(userid: &Userid, path: &str, port: u16) -> String {
     format!("{}{}{}", userid, path, port)
 }
+
+pub fn restore_link_aad(
+    store: &str,
+    ns: &BackupNamespace,
+    dir: &BackupDir,
+    filepath: &str,
+    expire: i64,
+    one_time: bool,
+) -> String {
+    format!("{store}:{ns}:{dir}:{filepath}:{expire}:{one_time}")
+}
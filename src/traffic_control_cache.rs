@@ -1,9 +1,9 @@
 //! Traffic control implementation
 
-use std::collections::HashMap;
+use std::collections::{hash_map, HashMap};
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::sync::{Arc, Mutex};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use anyhow::Error;
 use cidr::IpInet;
@@ -13,7 +13,7 @@ use proxmox_section_config::SectionConfigData;
 
 use proxmox_time::{parse_daily_duration, DailyDuration, TmEditor};
 
-use pbs_api_types::TrafficControlRule;
+use pbs_api_types::{DataStoreConfig, TrafficControlRule};
 
 use pbs_config::ConfigVersionCache;
 
@@ -27,6 +27,20 @@ lazy_static::lazy_static! {
         Arc::new(Mutex::new(TrafficControlCache::new()));
 }
 
+/// Returns the restore priority limiters of a datastore, see
+/// [TrafficControlCache::restore_priority].
+pub fn restore_priority_limiters(
+    store: &str,
+) -> Result<Option<(SharedRateLimit, SharedRateLimit)>, Error> {
+    let (config, _digest) = pbs_config::datastore::config()?;
+    let config: DataStoreConfig = config.lookup("datastore", store)?;
+
+    TRAFFIC_CONTROL_CACHE
+        .lock()
+        .unwrap()
+        .restore_priority(store, config.restore_priority_ratio)
+}
+
 struct ParsedTcRule {
     config: TrafficControlRule,    // original rule config
     networks: Vec<IpInet>,         // parsed networks
@@ -45,6 +59,50 @@ pub struct TrafficStat {
     pub rate_out: u64,
 }
 
+/// Rate limiter of a nested rule, the traffic has to pass the limiter of the rule and the ones
+/// of all its ancestors.
+struct HierarchicalRateLimit {
+    /// The limiter of the rule itself first, followed by the ones of its ancestors
+    limiters: Vec<SharedRateLimit>,
+}
+
+impl ShareableRateLimit for HierarchicalRateLimit {
+    fn update_rate(&self, rate: u64, bucket_size: u64) {
+        self.limiters[0].update_rate(rate, bucket_size);
+    }
+
+    fn traffic(&self) -> u64 {
+        self.limiters[0].traffic()
+    }
+
+    fn register_traffic(&self, current_time: Instant, data_len: u64) -> Duration {
+        self.limiters
+            .iter()
+            .map(|limiter| limiter.register_traffic(current_time, data_len))
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+// maximum number of rules in a chain of parents, guards against loops in the config
+const MAX_RULE_DEPTH: usize = 8;
+
+// rate used while no restore is running, high enough to never delay
+const UNLIMITED_RATE: u64 = 1 << 40;
+
+// backups are never throttled below this rate, so that they cannot stall completely
+const MIN_BACKUP_RATE: u64 = 1_000_000;
+
+/// Restore priority state of a datastore
+struct RestorePriority {
+    /// Maximum backup upload rate in percent of the current restore rate
+    ratio: u64,
+    /// Only used to count restore traffic, never delays
+    restore_traffic: SharedRateLimit,
+    last_restore_traffic: u64,
+    backup_limiter: SharedRateLimit,
+}
+
 /// Cache rules from `/etc/proxmox-backup/traffic-control.cfg`
 /// together with corresponding rate limiter implementation.
 pub struct TrafficControlCache {
@@ -56,6 +114,7 @@ pub struct TrafficControlCache {
     last_traffic_control_generation: usize,
     rules: Vec<ParsedTcRule>,
     limiter_map: HashMap<String, (Option<SharedRateLimit>, Option<SharedRateLimit>)>,
+    restore_priority_map: HashMap<String, RestorePriority>,
     use_utc: bool, // currently only used for testing
 }
 
@@ -125,6 +184,7 @@ impl TrafficControlCache {
             use_shared_memory: true,
             rules: Vec::new(),
             limiter_map: HashMap::new(),
+            restore_priority_map: HashMap::new(),
             last_traffic_control_generation: 0,
             last_update: 0,
             use_utc: false,
@@ -169,6 +229,11 @@ impl TrafficControlCache {
         }
     }
 
+    /// Returns the configuration generation of the loaded rules.
+    pub fn loaded_generation(&self) -> usize {
+        self.last_traffic_control_generation
+    }
+
     fn reload_impl(&mut self) -> Result<(), Error> {
         let (config, _) = pbs_config::traffic_control::config()?;
 
@@ -215,9 +280,76 @@ impl TrafficControlCache {
 
         self.current_rate_map = new_rate_map;
 
+        for priority in self.restore_priority_map.values_mut() {
+            let restore_traffic = priority.restore_traffic.traffic();
+            let traffic_diff = restore_traffic.saturating_sub(priority.last_restore_traffic);
+            priority.last_restore_traffic = restore_traffic;
+
+            let restore_rate = ((traffic_diff as u128) * 1_000_000) / elapsed;
+            let restore_rate: u64 = restore_rate.try_into().unwrap_or(u64::MAX);
+
+            let backup_rate = if restore_rate == 0 {
+                UNLIMITED_RATE
+            } else {
+                (restore_rate.saturating_mul(priority.ratio) / 100).max(MIN_BACKUP_RATE)
+            };
+            priority
+                .backup_limiter
+                .update_rate(backup_rate, backup_rate);
+        }
+
         self.last_rate_compute = Instant::now()
     }
 
+    /// Returns the restore traffic counter and the backup upload limiter of a datastore.
+    ///
+    /// `ratio` is the datastore's `restore-priority-ratio`, nothing is returned if it is not set.
+    /// Restore traffic registered with the counter lowers the rate of the backup limiter to
+    /// `ratio` percent of the restore rate, updated by [Self::compute_current_rates].
+    pub fn restore_priority(
+        &mut self,
+        store: &str,
+        ratio: Option<u64>,
+    ) -> Result<Option<(SharedRateLimit, SharedRateLimit)>, Error> {
+        let ratio = match ratio {
+            Some(ratio) => ratio,
+            None => {
+                self.restore_priority_map.remove(store);
+                return Ok(None);
+            }
+        };
+
+        let priority = match self.restore_priority_map.entry(store.to_string()) {
+            hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            hash_map::Entry::Vacant(entry) => {
+                let restore_traffic = create_limiter(
+                    self.use_shared_memory,
+                    &format!("datastore-{store}.restore"),
+                    UNLIMITED_RATE,
+                    UNLIMITED_RATE,
+                )?;
+                let backup_limiter = create_limiter(
+                    self.use_shared_memory,
+                    &format!("datastore-{store}.backup"),
+                    UNLIMITED_RATE,
+                    UNLIMITED_RATE,
+                )?;
+                entry.insert(RestorePriority {
+                    ratio,
+                    last_restore_traffic: restore_traffic.traffic(),
+                    restore_traffic,
+                    backup_limiter,
+                })
+            }
+        };
+        priority.ratio = ratio;
+
+        Ok(Some((
+            Arc::clone(&priority.restore_traffic),
+            Arc::clone(&priority.backup_limiter),
+        )))
+    }
+
     /// Returns current [TrafficStat] for each configured rule.
     pub fn current_rate_map(&self) -> &HashMap<String, TrafficStat> {
         &self.current_rate_map
@@ -365,18 +497,51 @@ impl TrafficControlCache {
 
         match last_rule_match {
             Some((rule, _)) => {
-                match self.limiter_map.get(&rule.config.name) {
-                    Some((read_limiter, write_limiter)) => (
-                        &rule.config.name,
-                        read_limiter.clone(),
-                        write_limiter.clone(),
-                    ),
-                    None => ("", None, None), // should never happen
-                }
+                let (read_limiter, write_limiter) = self.limiter_chain(rule, &now);
+                (&rule.config.name, read_limiter, write_limiter)
             }
             None => ("", None, None),
         }
     }
+
+    /// Returns the limiters of `rule`, combined with the ones of its parent rules that are
+    /// active at `now`.
+    fn limiter_chain(
+        &self,
+        rule: &ParsedTcRule,
+        now: &TmEditor,
+    ) -> (Option<SharedRateLimit>, Option<SharedRateLimit>) {
+        let mut read_limiters = Vec::new();
+        let mut write_limiters = Vec::new();
+
+        let mut current = Some(rule);
+        for _ in 0..MAX_RULE_DEPTH {
+            let rule = match current {
+                Some(rule) => rule,
+                None => break,
+            };
+            if let Some((read_limiter, write_limiter)) = self.limiter_map.get(&rule.config.name) {
+                read_limiters.extend(read_limiter.clone());
+                write_limiters.extend(write_limiter.clone());
+            }
+
+            current = rule.config.parent.as_ref().and_then(|parent| {
+                self.rules.iter().find(|rule| {
+                    rule.config.name == *parent && timeframe_match(&rule.timeframe, now)
+                })
+            });
+        }
+
+        let combine = |mut limiters: Vec<SharedRateLimit>| -> Option<SharedRateLimit> {
+            match limiters.len() {
+                0 => None,
+                1 => limiters.pop(),
+                _ => Some(Arc::new(HierarchicalRateLimit { limiters })),
+            }
+        };
+
+        (combine(read_limiters), combine(write_limiters))
+    }
 }
 
 #[cfg(test)]
@@ -486,4 +651,45 @@ rule: somewhere
 
         Ok(())
     }
+
+    #[test]
+    fn test_nested_rules() -> Result<(), Error> {
+        let config_data = "
+rule: site
+	network 0.0.0.0/0
+	rate-in 100000000
+	rate-out 100000000
+
+rule: tenant
+	network 192.168.2.0/24
+	rate-out 30000000
+	parent site
+";
+        let config = pbs_config::traffic_control::CONFIG.parse("testconfig", config_data)?;
+
+        let mut cache = TrafficControlCache::new();
+        cache.use_utc = true;
+        cache.use_shared_memory = false; // avoid permission problems in test environment
+
+        cache.update_config(&config)?;
+
+        let tenant = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 2, 1)), 1234);
+        let (rule, read_limiter, write_limiter) = cache.lookup_rate_limiter(tenant, 0);
+        assert_eq!(rule, "tenant");
+
+        // only the site limits incoming traffic, both limit outgoing traffic
+        let read_limiter = read_limiter.unwrap();
+        let write_limiter = write_limiter.unwrap();
+        let (site_read, site_write) = cache.limiter_map.get("site").unwrap();
+
+        let now = Instant::now();
+        read_limiter.register_traffic(now, 500);
+        assert_eq!(site_read.as_ref().unwrap().traffic(), 500);
+
+        write_limiter.register_traffic(now, 1000);
+        assert_eq!(write_limiter.traffic(), 1000);
+        assert_eq!(site_write.as_ref().unwrap().traffic(), 1000);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,355 @@
+//! Golden-file tests of the HTTP API
+//!
+//! Starts `proxmox-backup-api` and `proxmox-backup-proxy` with a temporary datastore, runs real
+//! backups and restores with `proxmox-backup-client` against them and compares the JSON output of
+//! API calls with the files in `tests/golden/`. Volatile values like times, UPIDs and sizes are
+//! replaced by placeholders and lists are sorted before comparing.
+//!
+//! The test runs in private mount and network namespaces, with empty temporary directories
+//! mounted over the configuration, state, log and run directories. So neither the configuration
+//! nor the ports of a Proxmox Backup Server running on the host are touched. Creating the
+//! namespaces needs root, after building the workspace run:
+//!
+//! ```text
+//! cargo build --workspace
+//! cargo test --features integration-tests --test api-golden
+//! ```
+//!
+//! A missing golden file fails the test. Set `UPDATE_GOLDEN=1` to write the golden files after
+//! intended changes of the API, and commit them.
+
+use std::net::TcpStream;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, format_err, Context, Error};
+use serde_json::Value;
+
+const STORE: &str = "golden-test";
+const USER: &str = "golden@pbs";
+const PASSWORD: &str = "golden-test-password";
+// 2023-01-01T00:00:00Z
+const BACKUP_TIME: i64 = 1672531200;
+
+// keys of object members whose values differ between runs
+const VOLATILE_KEYS: &[&str] = &[
+    "avail",
+    "ctime",
+    "endtime",
+    "fingerprint",
+    "last-run-upid",
+    "mtime",
+    "path",
+    "size",
+    "starttime",
+    "total",
+    "upid",
+    "used",
+];
+
+struct Daemon {
+    name: &'static str,
+    child: Child,
+}
+
+impl Daemon {
+    fn start(name: &'static str, command: &mut Command, port: u16) -> Result<Self, Error> {
+        let child = command
+            .spawn()
+            .with_context(|| format!("unable to start {name}"))?;
+        let daemon = Self { name, child };
+
+        let start = Instant::now();
+        while TcpStream::connect(("127.0.0.1", port)).is_err() {
+            if start.elapsed() > Duration::from_secs(30) {
+                bail!("{name} did not start listening on port {port}");
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+
+        Ok(daemon)
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        let pid = nix::unistd::Pid::from_raw(self.child.id() as i32);
+        if let Err(err) = nix::sys::signal::kill(pid, nix::sys::signal::Signal::SIGTERM) {
+            eprintln!("unable to stop {} - {err}", self.name);
+        }
+        let _ = self.child.wait();
+    }
+}
+
+/// Locates the binaries of the workspace, built into the same directory as the daemons.
+fn binary(name: &str) -> Result<PathBuf, Error> {
+    let dir = Path::new(env!("CARGO_BIN_EXE_proxmox-backup-proxy"))
+        .parent()
+        .unwrap()
+        .to_path_buf();
+    let path = dir.join(name);
+    if !path.exists() {
+        bail!("{path:?} missing, build the workspace first");
+    }
+    Ok(path)
+}
+
+fn run(program: &Path, args: &[&str], envs: &[(&str, &str)]) -> Result<String, Error> {
+    let output = Command::new(program)
+        .args(args)
+        .envs(envs.iter().copied())
+        .output()
+        .with_context(|| format!("unable to run {program:?}"))?;
+    if !output.status.success() {
+        bail!(
+            "{program:?} {args:?} failed - {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// Replace the values of volatile keys by a placeholder and sort lists, recursively.
+///
+/// The order of listed groups, snapshots and files depends on the order of directory entries,
+/// so it is not compared.
+fn normalize(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if VOLATILE_KEYS.contains(&key.as_str()) && !value.is_null() {
+                    *value = Value::from("<volatile>");
+                } else {
+                    normalize(value);
+                }
+            }
+        }
+        Value::Array(list) => {
+            list.iter_mut().for_each(normalize);
+            list.sort_by_cached_key(|value| value.to_string());
+        }
+        _ => (),
+    }
+}
+
+/// Compare the normalized JSON `output` with the golden file `name`.
+fn check_golden(name: &str, output: &str) -> Result<(), Error> {
+    let mut value: Value =
+        serde_json::from_str(output).with_context(|| format!("{name}: invalid JSON output"))?;
+    normalize(&mut value);
+    let text = serde_json::to_string_pretty(&value)? + "\n";
+
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{name}.json"));
+
+    if std::env::var("UPDATE_GOLDEN").map_or(false, |value| value == "1") {
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(&path, text)?;
+        eprintln!("{name}: wrote {path:?}");
+        return Ok(());
+    }
+
+    let expected = std::fs::read_to_string(&path).with_context(|| {
+        format!("{name}: unable to read {path:?}, run with UPDATE_GOLDEN=1 to create it")
+    })?;
+    if expected != text {
+        bail!("{name}: output differs from {path:?}:\n{text}");
+    }
+
+    Ok(())
+}
+
+/// Move the test into private mount and network namespaces, with empty directories for the
+/// configuration and state of the daemons. Processes started afterwards inherit them.
+fn isolate(backup_user: &nix::unistd::User) -> Result<(), Error> {
+    use nix::mount::{mount, MsFlags};
+    use nix::sched::{unshare, CloneFlags};
+
+    unshare(CloneFlags::CLONE_NEWNS | CloneFlags::CLONE_NEWNET)
+        .context("unable to create private namespaces")?;
+    // keep the mounts below from propagating to the host
+    mount(
+        None::<&str>,
+        "/",
+        None::<&str>,
+        MsFlags::MS_REC | MsFlags::MS_PRIVATE,
+        None::<&str>,
+    )?;
+
+    let (uid, gid) = (backup_user.uid, backup_user.gid);
+    let dirs = [
+        (pbs_buildcfg::CONFIGDIR, "0700"),
+        (pbs_buildcfg::PROXMOX_BACKUP_RUN_DIR, "0755"),
+        (pbs_buildcfg::PROXMOX_BACKUP_STATE_DIR, "0755"),
+        (pbs_buildcfg::PROXMOX_BACKUP_LOG_DIR, "0755"),
+        (pbs_buildcfg::PROXMOX_BACKUP_CACHE_DIR_M!(), "0755"),
+    ];
+    for (dir, mode) in dirs {
+        std::fs::create_dir_all(dir)?;
+        let options = format!("mode={mode},uid={uid},gid={gid}");
+        mount(
+            Some("tmpfs"),
+            dir,
+            Some("tmpfs"),
+            MsFlags::empty(),
+            Some(options.as_str()),
+        )
+        .with_context(|| format!("unable to mount a temporary directory on {dir}"))?;
+    }
+    // created by the package
+    for dir in ["api", "tasks"] {
+        let path = Path::new(pbs_buildcfg::PROXMOX_BACKUP_LOG_DIR).join(dir);
+        std::fs::create_dir(&path)?;
+        nix::unistd::chown(&path, Some(uid), Some(gid))?;
+    }
+
+    run(Path::new("ip"), &["link", "set", "lo", "up"], &[])?;
+
+    Ok(())
+}
+
+fn create_source(dir: &Path) -> Result<(), Error> {
+    std::fs::create_dir_all(dir.join("sub"))?;
+    std::fs::write(dir.join("file"), "golden file content\n")?;
+    std::fs::write(dir.join("sub/data"), vec![0xa5u8; 4 * 1024 * 1024])?;
+    Ok(())
+}
+
+fn compare_trees(source: &Path, restored: &Path) -> Result<(), Error> {
+    for file in ["file", "sub/data"] {
+        let expected = std::fs::read(source.join(file))?;
+        let actual = std::fs::read(restored.join(file))
+            .map_err(|err| format_err!("restored {file} missing - {err}"))?;
+        if expected != actual {
+            bail!("restored {file} differs from the source");
+        }
+    }
+    Ok(())
+}
+
+fn fingerprint(manager: &Path) -> Result<String, Error> {
+    let info = run(manager, &["cert", "info"], &[])?;
+    info.lines()
+        .find_map(|line| line.strip_prefix("Fingerprint (sha256): "))
+        .map(|fingerprint| fingerprint.trim().to_string())
+        .ok_or_else(|| format_err!("no fingerprint in certificate info"))
+}
+
+#[test]
+fn api_golden() -> Result<(), Error> {
+    if !nix::unistd::Uid::effective().is_root() {
+        bail!("the golden-file API tests need to run as root to create namespaces");
+    }
+
+    let manager = PathBuf::from(env!("CARGO_BIN_EXE_proxmox-backup-manager"));
+    let client = binary("proxmox-backup-client")?;
+    let backup_user = pbs_config::backup_user()?;
+    isolate(&backup_user)?;
+
+    let _api = Daemon::start(
+        "proxmox-backup-api",
+        &mut Command::new(env!("CARGO_BIN_EXE_proxmox-backup-api")),
+        82,
+    )?;
+    let _proxy = Daemon::start(
+        "proxmox-backup-proxy",
+        Command::new(env!("CARGO_BIN_EXE_proxmox-backup-proxy"))
+            .uid(backup_user.uid.as_raw())
+            .gid(backup_user.gid.as_raw()),
+        8007,
+    )?;
+
+    let tmp = std::env::temp_dir().join(format!("pbs-api-golden-{}", std::process::id()));
+    let store_path = tmp.join("store");
+    let source = tmp.join("source");
+    let restored = tmp.join("restored");
+    std::fs::create_dir_all(&tmp)?;
+    nix::unistd::chown(&tmp, Some(backup_user.uid), Some(backup_user.gid))?;
+    create_source(&source)?;
+
+    let store_path_str = store_path.to_str().unwrap();
+    run(
+        &manager,
+        &["datastore", "create", STORE, store_path_str],
+        &[],
+    )?;
+    run(
+        &manager,
+        &["user", "create", USER, "--password", PASSWORD],
+        &[],
+    )?;
+    run(
+        &manager,
+        &[
+            "acl",
+            "update",
+            "/datastore",
+            "DatastoreAdmin",
+            "--auth-id",
+            USER,
+        ],
+        &[],
+    )?;
+
+    let result = (|| {
+        let fingerprint = fingerprint(&manager)?;
+        let repository = format!("{USER}@localhost:{STORE}");
+        let envs = [
+            ("PBS_REPOSITORY", repository.as_str()),
+            ("PBS_PASSWORD", PASSWORD),
+            ("PBS_FINGERPRINT", fingerprint.as_str()),
+        ];
+
+        let spec = format!("root.pxar:{}", source.display());
+        let backup_time = BACKUP_TIME.to_string();
+        for id in ["golden-1", "golden-2"] {
+            run(
+                &client,
+                &[
+                    "backup",
+                    &spec,
+                    "--backup-id",
+                    id,
+                    "--backup-time",
+                    &backup_time,
+                    "--crypt-mode",
+                    "none",
+                ],
+                &envs,
+            )?;
+        }
+
+        let snapshot = "host/golden-1/2023-01-01T00:00:00Z";
+        run(
+            &client,
+            &["restore", snapshot, "root.pxar", restored.to_str().unwrap()],
+            &envs,
+        )?;
+        compare_trees(&source, &restored)?;
+
+        let calls = [
+            ("datastore-list", &manager, vec!["datastore", "list"]),
+            ("client-list", &client, vec!["list"]),
+            ("client-snapshot-list", &client, vec!["snapshot", "list"]),
+            (
+                "client-snapshot-files",
+                &client,
+                vec!["snapshot", "files", snapshot],
+            ),
+        ];
+        for (name, program, mut args) in calls {
+            args.extend(["--output-format", "json"]);
+            check_golden(name, &run(program, &args, &envs)?)?;
+        }
+
+        Ok::<_, Error>(())
+    })();
+
+    // the configuration is discarded with the namespace
+    let _ = std::fs::remove_dir_all(&tmp);
+
+    result
+}
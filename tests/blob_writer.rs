@@ -5,6 +5,7 @@ use std::sync::Arc;
 use anyhow::{bail, Error};
 use lazy_static::lazy_static;
 
+use pbs_datastore::data_blob::DataChunkBuilder;
 use pbs_datastore::{DataBlob, DataBlobReader, DataBlobWriter};
 use pbs_tools::crypt_config::CryptConfig;
 
@@ -103,3 +104,26 @@ fn test_encrypted_compressed_blob_writer() -> Result<(), Error> {
 
     verify_test_blob(blob_writer.finish()?, &TEST_DIGEST_ENC)
 }
+
+#[test]
+fn test_salted_chunk_digest() -> Result<(), Error> {
+    let salt = [2u8; 32];
+    let (chunk, digest) = DataChunkBuilder::new(&TEST_DATA)
+        .digest_salt(Some(&salt))
+        .build()?;
+
+    if digest == *TEST_DIGEST_PLAIN {
+        bail!("salted digest must differ from the plain one");
+    }
+    chunk.verify_unencrypted_salted(TEST_DATA.len(), &digest, Some(&salt))?;
+    if chunk.verify_unencrypted(TEST_DATA.len(), &digest).is_ok() {
+        bail!("salted digest verified without salt");
+    }
+    if chunk
+        .verify_unencrypted_salted(TEST_DATA.len(), &digest, Some(&[3u8; 32]))
+        .is_ok()
+    {
+        bail!("salted digest verified with wrong salt");
+    }
+    Ok(())
+}
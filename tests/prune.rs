@@ -4,7 +4,7 @@ use anyhow::Error;
 
 use pbs_api_types::PruneJobOptions;
 use pbs_datastore::manifest::MANIFEST_BLOB_NAME;
-use pbs_datastore::prune::compute_prune_info;
+use pbs_datastore::prune::{compute_prune_info, PruneMark};
 use pbs_datastore::{BackupDir, BackupInfo};
 
 fn get_prune_list(
@@ -41,6 +41,7 @@ fn create_info(snapshot: &str, partial: bool) -> BackupInfo {
         backup_dir,
         files,
         protected: false,
+        retained_until: None,
     }
 }
 
@@ -75,6 +76,33 @@ fn test_prune_protected() -> Result<(), Error> {
     Ok(())
 }
 
+#[test]
+fn test_prune_retained() -> Result<(), Error> {
+    let mut retained = create_info("host/elsa/2019-11-15T10:59:15Z", false);
+    retained.retained_until = Some(i64::MAX);
+
+    let orig_list = vec![
+        create_info("host/elsa/2019-11-15T09:39:15Z", false),
+        create_info("host/elsa/2019-11-15T10:39:15Z", false),
+        retained,
+    ];
+
+    let mut options = PruneJobOptions::default();
+    options.keep.keep_last = Some(1);
+    let remove_list = get_prune_list(orig_list.clone(), false, &options);
+    let expect: Vec<PathBuf> = vec![PathBuf::from("host/elsa/2019-11-15T09:39:15Z")];
+    assert_eq!(remove_list, expect);
+
+    let prune_info = compute_prune_info(orig_list, &options.keep)?;
+    let (_, mark) = prune_info
+        .iter()
+        .find(|(info, _)| info.retained_until.is_some())
+        .unwrap();
+    assert!(*mark == PruneMark::Retained);
+    assert!(mark.keep() && !mark.protected());
+    Ok(())
+}
+
 #[test]
 fn test_prune_hourly() -> Result<(), Error> {
     let orig_list = vec![
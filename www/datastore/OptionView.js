@@ -218,6 +218,25 @@ Ext.define('PBS.Datastore.Options', {
 		},
 	    },
 	},
+	"warm-up": {
+	    required: true,
+	    header: gettext('Warm-Up'),
+	    defaultValue: false,
+	    renderer: Proxmox.Utils.format_boolean,
+	    editor: {
+		xtype: 'proxmoxWindowEdit',
+		title: gettext('Warm-Up'),
+		width: 350,
+		items: {
+		    xtype: 'proxmoxcheckbox',
+		    name: 'warm-up',
+		    boxLabel: gettext("Preload metadata when leaving offline maintenance"),
+		    defaultValue: false,
+		    deleteDefaultValue: true,
+		    deleteEmpty: true,
+		},
+	    },
+	},
 	"maintenance-mode": {
 	    required: true,
 	    header: gettext('Maintenance mode'),
@@ -271,6 +290,41 @@ Ext.define('PBS.Datastore.Options', {
 			    deleteEmpty: true,
 			    value: '__default__',
 			},
+			{
+			    xtype: 'proxmoxintegerfield',
+			    name: 'max-chunk-size',
+			    fieldLabel: gettext('Max. Chunk Size') + ' (MiB)',
+			    minValue: 1,
+			    maxValue: 64,
+			    emptyText: Proxmox.Utils.defaultText + ' (16)',
+			    deleteEmpty: true,
+			},
+			{
+			    xtype: 'proxmoxintegerfield',
+			    name: 'max-blob-size',
+			    fieldLabel: gettext('Max. Blob Size') + ' (MiB)',
+			    minValue: 1,
+			    maxValue: 128,
+			    emptyText: Proxmox.Utils.defaultText + ' (16)',
+			    deleteEmpty: true,
+			},
+			{
+			    xtype: 'proxmoxintegerfield',
+			    name: 'max-manifest-size',
+			    fieldLabel: gettext('Max. Manifest Size') + ' (MiB)',
+			    minValue: 1,
+			    maxValue: 128,
+			    emptyText: Proxmox.Utils.defaultText + ' (16)',
+			    deleteEmpty: true,
+			},
+			{
+			    xtype: 'proxmoxintegerfield',
+			    name: 'blob-dedup-min-size',
+			    fieldLabel: gettext('Deduplicate Blobs From') + ' (B)',
+			    minValue: 0,
+			    emptyText: gettext('Never'),
+			    deleteEmpty: true,
+			},
 		    ],
 		},
 	    },